@@ -2,10 +2,13 @@
 
 use anyhow::{Context, Result};
 use clap::{Args, Subcommand};
-use gdpi_core::config::{Config, Profile};
-use std::path::PathBuf;
+use gdpi_core::config::{Config, ConfigDiff, Profile};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use tracing::info;
 
+use super::overrides::{load_config, ConfigOverrides};
+
 /// Config command arguments
 #[derive(Args, Debug)]
 pub struct ConfigArgs {
@@ -19,29 +22,77 @@ pub enum ConfigAction {
     /// Show current configuration
     Show {
         /// Config file to show (default: detect)
-        #[arg(short, long)]
-        file: Option<PathBuf>,
+        #[arg(short, long, conflicts_with = "config")]
+        file: Option<String>,
 
-        /// Profile to show
-        #[arg(short, long)]
-        profile: Option<String>,
+        /// Show the configuration `run` would use, after env vars and
+        /// command-line flags are applied
+        #[arg(long)]
+        effective: bool,
+
+        #[command(flatten)]
+        overrides: ConfigOverrides,
     },
 
-    /// Generate a configuration file
+    /// Generate a configuration file, asking a few questions when run
+    /// in a terminal without --profile
     Generate {
         /// Output file path
         #[arg(short, long, default_value = "config.toml")]
         output: PathBuf,
 
-        /// Profile to use as base
-        #[arg(short, long, default_value = "turkey")]
-        profile: String,
+        /// Profile to use as base (skips the questions)
+        #[arg(short, long)]
+        profile: Option<String>,
     },
 
     /// Validate a configuration file
     Validate {
         /// Config file to validate
+        #[arg(short, long)]
         file: PathBuf,
+
+        /// Also list the checks that passed
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Convert a configuration file between TOML, JSON and YAML
+    Convert {
+        /// Config file to read (format detected from extension)
+        from: PathBuf,
+
+        /// Output file (format chosen from extension)
+        to: PathBuf,
+    },
+
+    /// Show the settings that differ between two configuration files, or
+    /// between a file and a profile's defaults
+    Diff {
+        /// First config file (or the only one, compared with --profile)
+        a: PathBuf,
+
+        /// Second config file
+        b: Option<PathBuf>,
+
+        /// Profile to compare against when only one file is given
+        /// (default: the file's own profile, else turkey)
+        #[arg(short, long, conflicts_with = "b")]
+        profile: Option<String>,
+
+        /// Output format (text or json)
+        #[arg(short, long)]
+        format: Option<String>,
+    },
+
+    /// Convert an original goodbyedpi.exe command line to a configuration
+    Import {
+        /// Command line, e.g. "goodbyedpi.exe -9 -e 40 -q --blacklist x.txt"
+        cmdline: String,
+
+        /// Write to this file instead of printing
+        #[arg(short, long)]
+        output: Option<PathBuf>,
     },
 
     /// Show config file locations
@@ -51,25 +102,62 @@ pub enum ConfigAction {
 /// Execute config command
 pub fn execute(args: ConfigArgs) -> Result<()> {
     match args.action {
-        ConfigAction::Show { file, profile } => show_config(file, profile),
-        ConfigAction::Generate { output, profile } => generate_config(output, profile),
-        ConfigAction::Validate { file } => validate_config(file),
+        ConfigAction::Show {
+            file,
+            effective,
+            overrides,
+        } => show_config(file, effective, overrides),
+        ConfigAction::Generate {
+            output,
+            profile: Some(profile),
+        } => generate_config(output, profile),
+        ConfigAction::Generate { output, profile: None } => {
+            if atty::is(atty::Stream::Stdin) {
+                generate_interactive(output)
+            } else {
+                generate_config(output, "turkey".to_string())
+            }
+        }
+        ConfigAction::Validate { file, verbose } => validate_config(file, verbose),
+        ConfigAction::Convert { from, to } => convert_config(from, to),
+        ConfigAction::Diff {
+            a,
+            b: Some(b),
+            format,
+            ..
+        } => diff_configs(a, b, format),
+        ConfigAction::Diff {
+            a,
+            b: None,
+            profile,
+            format,
+        } => diff_with_profile(a, profile, format),
+        ConfigAction::Import { cmdline, output } => import_config(cmdline, output),
         ConfigAction::Paths => show_paths(),
     }
 }
 
-fn show_config(file: Option<PathBuf>, profile: Option<String>) -> Result<()> {
-    let config = if let Some(path) = file {
-        Config::load(&path)
+fn show_config(file: Option<String>, effective: bool, mut overrides: ConfigOverrides) -> Result<()> {
+    if overrides.has_setting_overrides() && !effective {
+        anyhow::bail!("Command-line overrides are only applied with --effective");
+    }
+
+    let config = if effective {
+        if file.is_some() {
+            overrides.config = file;
+        }
+        load_config(&overrides)?
+    } else if let Some(path) = file.or(overrides.config) {
+        Config::load_auto(&path)
             .with_context(|| format!("Failed to load config from {:?}", path))?
-    } else if let Some(profile_name) = profile {
+    } else if let Some(profile_name) = overrides.profile {
         let profile = Profile::from_name(&profile_name)
             .with_context(|| format!("Unknown profile: {}", profile_name))?;
         Config::from_profile(profile)
     } else {
         // Try to find config file
         if let Some(path) = find_config_file() {
-            Config::load(&path)
+            Config::load_auto(&path)
                 .with_context(|| format!("Failed to load config from {:?}", path))?
         } else {
             Config::from_profile(Profile::Turkey)
@@ -87,10 +175,151 @@ fn show_config(file: Option<PathBuf>, profile: Option<String>) -> Result<()> {
 fn generate_config(output: PathBuf, profile_name: String) -> Result<()> {
     let profile = Profile::from_name(&profile_name)
         .with_context(|| format!("Unknown profile: {}", profile_name))?;
-    
-    let config = Config::from_profile(profile);
 
-    let toml_str = toml::to_string_pretty(&config)
+    write_generated(&output, &Config::from_profile(profile), &profile_name)
+}
+
+/// ISPs the wizard offers; they all see the same DPI, so share a profile
+const ISPS: [(&str, Profile); 5] = [
+    ("Türk Telekom", Profile::Turkey),
+    ("Turkcell Superonline", Profile::Turkey),
+    ("Vodafone Net", Profile::Turkey),
+    ("TurkNet", Profile::Turkey),
+    ("Millenicom", Profile::Turkey),
+];
+
+/// Profiles offered when the ISP isn't listed
+const MANUAL_PROFILES: [Profile; 10] = [
+    Profile::Turkey,
+    Profile::Mode9,
+    Profile::Mode1,
+    Profile::Mode2,
+    Profile::Mode3,
+    Profile::Mode4,
+    Profile::Mode5,
+    Profile::Mode6,
+    Profile::Mode7,
+    Profile::Mode8,
+];
+
+/// What the `config generate` wizard was told
+#[derive(Debug)]
+struct WizardAnswers {
+    profile: Profile,
+    /// DNS server to redirect to, `None` to leave DNS alone
+    dns: Option<IpAddr>,
+    block_quic: bool,
+    fake_packets: bool,
+}
+
+impl WizardAnswers {
+    /// The profile's configuration with the answers applied
+    fn to_config(&self) -> Config {
+        let mut config = Config::from_profile(self.profile);
+
+        config.dns.enabled = self.dns.is_some();
+        match self.dns {
+            Some(IpAddr::V4(ip)) => config.dns.ipv4_upstream = Some(ip),
+            Some(IpAddr::V6(ip)) => config.dns.ipv6_upstream = Some(ip),
+            None => {}
+        }
+        config.strategies.block_quic = self.block_quic;
+        config.strategies.quic_block.enabled = self.block_quic;
+        config.strategies.fake_packet.enabled = self.fake_packets;
+
+        config
+    }
+
+    /// One line per question, for the summary after writing the file
+    fn summary(&self) -> Vec<String> {
+        let yes_no = |on: bool| if on { "yes" } else { "no" };
+        vec![
+            format!("Profile: {} ({})", self.profile.name(), self.profile.description()),
+            format!(
+                "DNS redirection: {}",
+                self.dns.map_or_else(|| "no".to_string(), |ip| ip.to_string())
+            ),
+            format!("Block QUIC: {}", yes_no(self.block_quic)),
+            format!("Fake packets: {}", yes_no(self.fake_packets)),
+        ]
+    }
+}
+
+fn generate_interactive(output: PathBuf) -> Result<()> {
+    use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
+
+    let theme = ColorfulTheme::default();
+
+    let mut isps: Vec<&str> = ISPS.iter().map(|(name, _)| *name).collect();
+    isps.push("Other / choose a mode manually");
+    let isp = Select::with_theme(&theme)
+        .with_prompt("Which ISP are you on?")
+        .items(&isps)
+        .default(0)
+        .interact()?;
+    let profile = match ISPS.get(isp) {
+        Some(&(_, profile)) => profile,
+        None => {
+            let modes: Vec<String> = MANUAL_PROFILES
+                .iter()
+                .map(|profile| format!("{:<8} {}", profile.name(), profile.description()))
+                .collect();
+            let mode = Select::with_theme(&theme)
+                .with_prompt("Which mode?")
+                .items(&modes)
+                .default(0)
+                .interact()?;
+            MANUAL_PROFILES[mode]
+        }
+    };
+    let defaults = Config::from_profile(profile);
+
+    let dns = if Confirm::with_theme(&theme)
+        .with_prompt("Enable DNS redirection?")
+        .default(defaults.dns.enabled)
+        .interact()?
+    {
+        let suggested = defaults
+            .dns
+            .ipv4_upstream
+            .map_or_else(|| IpAddr::from([77, 88, 8, 8]), IpAddr::V4);
+        Some(
+            Input::<IpAddr>::with_theme(&theme)
+                .with_prompt("Enter DNS server IP:")
+                .default(suggested)
+                .interact_text()?,
+        )
+    } else {
+        None
+    };
+
+    let block_quic = Confirm::with_theme(&theme)
+        .with_prompt("Block QUIC?")
+        .default(defaults.strategies.block_quic || defaults.strategies.quic_block.enabled)
+        .interact()?;
+    let fake_packets = Confirm::with_theme(&theme)
+        .with_prompt("Enable fake packets?")
+        .default(defaults.strategies.fake_packet.enabled)
+        .interact()?;
+
+    let answers = WizardAnswers {
+        profile,
+        dns,
+        block_quic,
+        fake_packets,
+    };
+    write_generated(&output, &answers.to_config(), profile.name())?;
+
+    println!();
+    for line in answers.summary() {
+        println!("  {}", line);
+    }
+    Ok(())
+}
+
+/// Write `config` to `output` with a header naming the profile it came from
+fn write_generated(output: &Path, config: &Config, profile_name: &str) -> Result<()> {
+    let toml_str = toml::to_string_pretty(config)
         .context("Failed to serialize config")?;
 
     // Add header comment
@@ -102,22 +331,40 @@ fn generate_config(output: PathBuf, profile_name: String) -> Result<()> {
         profile_name, toml_str
     );
 
-    std::fs::write(&output, content)
+    std::fs::write(output, content)
         .with_context(|| format!("Failed to write config to {:?}", output))?;
 
     info!("Generated config file: {:?}", output);
     println!("Configuration file generated: {}", output.display());
-    
+
     Ok(())
 }
 
-fn validate_config(file: PathBuf) -> Result<()> {
-    let config = Config::load(&file)
+fn validate_config(file: PathBuf, verbose: bool) -> Result<()> {
+    let config = Config::load_auto(&file)
         .with_context(|| format!("Failed to load config from {:?}", file))?;
 
-    // Validate
-    config.validate()
-        .context("Configuration validation failed")?;
+    let report = config.validation_report();
+    for check in &report {
+        if check.passed() {
+            if verbose {
+                println!("✓ {} valid", check.name);
+            }
+            continue;
+        }
+        for issue in &check.issues {
+            println!("✗ {}", issue);
+            println!("    fix: {}", issue.suggestion);
+        }
+    }
+
+    let issues: usize = report.iter().map(|check| check.issues.len()).sum();
+    if issues > 0 {
+        anyhow::bail!("{} problem(s) found in {}", issues, file.display());
+    }
+    if verbose {
+        println!();
+    }
 
     println!("✓ Configuration is valid");
     println!("  Profile: {:?}", config.profile);
@@ -128,6 +375,153 @@ fn validate_config(file: PathBuf) -> Result<()> {
     Ok(())
 }
 
+fn convert_config(from: PathBuf, to: PathBuf) -> Result<()> {
+    let config = Config::load_auto(&from)
+        .with_context(|| format!("Failed to load config from {:?}", from))?;
+
+    let extension = to
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+
+    let content = match extension.as_deref() {
+        Some("json") => config.to_json(),
+        Some("yaml" | "yml") => config.to_yaml(),
+        _ => config.to_toml(),
+    }
+    .context("Failed to serialize config")?;
+
+    std::fs::write(&to, content)
+        .with_context(|| format!("Failed to write config to {:?}", to))?;
+
+    info!("Converted {:?} to {:?}", from, to);
+    println!("Configuration converted: {} -> {}", from.display(), to.display());
+
+    Ok(())
+}
+
+fn diff_configs(a: PathBuf, b: PathBuf, format: Option<String>) -> Result<()> {
+    let config_a = Config::load_auto(&a)
+        .with_context(|| format!("Failed to load config from {:?}", a))?;
+    let config_b = Config::load_auto(&b)
+        .with_context(|| format!("Failed to load config from {:?}", b))?;
+
+    let diff = config_a.diff(&config_b).context("Failed to compare configs")?;
+
+    match format.as_deref().unwrap_or("text") {
+        "json" => println!("{}", diff.to_json().context("Failed to serialize diff")?),
+        "text" => {
+            if diff.is_empty() {
+                println!("Configurations are identical");
+            } else {
+                print!("{}", diff);
+            }
+        }
+        other => anyhow::bail!("Unknown diff format: {} (expected text or json)", other),
+    }
+
+    Ok(())
+}
+
+fn diff_with_profile(file: PathBuf, profile: Option<String>, format: Option<String>) -> Result<()> {
+    let config = Config::load_auto(&file)
+        .with_context(|| format!("Failed to load config from {:?}", file))?;
+
+    let profile = match profile {
+        Some(name) => {
+            Profile::from_name(&name).with_context(|| format!("Unknown profile: {}", name))?
+        }
+        None => config.profile.unwrap_or(Profile::Turkey),
+    };
+
+    let diff = Config::from_profile(profile)
+        .diff(&config)
+        .context("Failed to compare configs")?;
+
+    match format.as_deref().unwrap_or("text") {
+        "json" => println!("{}", diff.to_json().context("Failed to serialize diff")?),
+        "text" => {
+            if diff.is_empty() {
+                println!("{} matches the {:?} profile defaults", file.display(), profile);
+            } else {
+                print!("{}", profile_diff_table(&diff));
+            }
+        }
+        other => anyhow::bail!("Unknown diff format: {} (expected text or json)", other),
+    }
+
+    Ok(())
+}
+
+/// One row per changed setting: path, profile value, file value
+fn profile_diff_table(diff: &ConfigDiff) -> String {
+    let show = |value: &Option<serde_json::Value>| {
+        value.as_ref().map_or_else(|| "(unset)".to_string(), ToString::to_string)
+    };
+    let rows: Vec<_> = diff
+        .changes
+        .iter()
+        .map(|change| (change.path.as_str(), show(&change.old), show(&change.new)))
+        .collect();
+
+    let path_width = rows.iter().map(|(path, ..)| path.len()).max().unwrap_or(0).max(5);
+    let old_width = rows.iter().map(|(_, old, _)| old.len()).max().unwrap_or(0).max(7);
+
+    let mut out = format!("{:path_width$}  {:old_width$}  {}\n", "FIELD", "PROFILE", "FILE");
+    for (path, old, new) in rows {
+        out.push_str(&format!("{:path_width$}  {:old_width$}  {}\n", path, old, new));
+    }
+    out
+}
+
+fn import_config(cmdline: String, output: Option<PathBuf>) -> Result<()> {
+    let args = legacy_flags(&cmdline);
+    let import = Config::import_legacy_args(&args)
+        .with_context(|| format!("Failed to import command line: {}", cmdline))?;
+
+    for warning in &import.warnings {
+        eprintln!("⚠ {}", warning);
+    }
+
+    let toml_str = import.config.to_toml().context("Failed to serialize config")?;
+    let content = format!(
+        "# GoodbyeDPI-Turkey Configuration\n\
+         # Imported from: {}\n\n\
+         {}",
+        cmdline.trim(),
+        toml_str
+    );
+
+    match output {
+        Some(path) => {
+            std::fs::write(&path, content)
+                .with_context(|| format!("Failed to write config to {:?}", path))?;
+            println!("Configuration imported: {}", path.display());
+        }
+        None => println!("{}", content),
+    }
+
+    Ok(())
+}
+
+/// Arguments of a batch-file command line, without the program and
+/// anything before it (`start "" goodbyedpi.exe ...`)
+fn legacy_flags(cmdline: &str) -> Vec<&str> {
+    let mut args = Vec::new();
+    let mut rest = cmdline.trim_start();
+    while !rest.is_empty() {
+        let (arg, tail) = match rest.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => rest.split_once(char::is_whitespace).unwrap_or((rest, "")),
+        };
+        args.push(arg);
+        rest = tail.trim_start();
+    }
+
+    let first_flag = args.iter().position(|arg| arg.starts_with('-')).unwrap_or(args.len());
+    args.split_off(first_flag)
+}
+
 fn show_paths() -> Result<()> {
     println!("Configuration file search paths:");
     println!();
@@ -182,3 +576,66 @@ fn find_config_file() -> Option<PathBuf> {
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_flags() {
+        assert_eq!(
+            legacy_flags(r#"start "" goodbyedpi.exe -9 --blacklist "..\russia blacklist.txt" -q"#),
+            ["-9", "--blacklist", r"..\russia blacklist.txt", "-q"]
+        );
+        assert_eq!(legacy_flags("  -5 -e 40 "), ["-5", "-e", "40"]);
+    }
+
+    #[test]
+    fn test_wizard_answers_applied() {
+        let answers = WizardAnswers {
+            profile: Profile::Turkey,
+            dns: Some("1.1.1.1".parse().unwrap()),
+            block_quic: false,
+            fake_packets: false,
+        };
+        let config = answers.to_config();
+        assert!(config.dns.enabled);
+        assert_eq!(config.dns.ipv4_upstream, Some("1.1.1.1".parse().unwrap()));
+        assert!(!config.strategies.block_quic);
+        assert!(!config.strategies.quic_block.enabled);
+        assert!(!config.strategies.fake_packet.enabled);
+        // Everything else is the profile's
+        let profile = Config::from_profile(Profile::Turkey);
+        assert_eq!(
+            config.strategies.fragmentation.https_size,
+            profile.strategies.fragmentation.https_size
+        );
+
+        let no_dns = WizardAnswers { dns: None, ..answers };
+        assert!(!no_dns.to_config().dns.enabled);
+        assert_eq!(no_dns.summary()[1], "DNS redirection: no");
+    }
+
+    #[test]
+    fn test_profile_diff_only_https_size() {
+        let mut config = Config::from_profile(Profile::Turkey);
+        config.strategies.fragmentation.https_size = 7;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, config.to_toml().unwrap()).unwrap();
+
+        let loaded = Config::load_auto(&path).unwrap();
+        let diff = Config::from_profile(Profile::Turkey).diff(&loaded).unwrap();
+        let default_size = Config::from_profile(Profile::Turkey).strategies.fragmentation.https_size;
+
+        assert_eq!(
+            profile_diff_table(&diff),
+            format!(
+                "FIELD                                PROFILE  FILE\n\
+                 strategies.fragmentation.https_size  {:<7}  7\n",
+                default_size
+            )
+        );
+    }
+}
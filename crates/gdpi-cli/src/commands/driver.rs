@@ -26,6 +26,9 @@ pub enum DriverCommands {
     
     /// Check driver status
     Status,
+
+    /// Check installed driver files against the embedded ones
+    Verify,
 }
 
 pub fn run(cmd: DriverCommands) -> Result<()> {
@@ -33,6 +36,7 @@ pub fn run(cmd: DriverCommands) -> Result<()> {
         DriverCommands::Install { force, yes } => install_driver(force, yes),
         DriverCommands::Uninstall { yes } => uninstall_driver(yes),
         DriverCommands::Status => show_status(),
+        DriverCommands::Verify => verify_driver(),
     }
 }
 
@@ -150,6 +154,20 @@ fn uninstall_driver(yes: bool) -> Result<()> {
     Ok(())
 }
 
+fn verify_driver() -> Result<()> {
+    let installer = WinDivertInstaller::new();
+
+    if !installer.is_installed() {
+        println!("✗ WinDivert is not installed");
+        println!("\nTo install, run: goodbyedpi.exe driver install");
+        anyhow::bail!("WinDivert files not found in {:?}", installer.install_dir());
+    }
+
+    installer.verify_checksums()?;
+    println!("✓ WinDivert files match the embedded copies");
+    Ok(())
+}
+
 fn show_status() -> Result<()> {
     let installer = WinDivertInstaller::new();
 
@@ -2,12 +2,15 @@
 //!
 //! Commands for managing whitelist/blacklist domain filters.
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use clap::{Args, Subcommand};
 use colored::Colorize;
 use gdpi_core::filter::{DomainFilter, FilterMode};
+use std::fmt::Write as _;
 use std::path::PathBuf;
 
+use super::run::{RunArgs, Session};
+
 /// Default filter file location
 fn default_filter_path() -> PathBuf {
     let exe_dir = std::env::current_exe()
@@ -85,6 +88,20 @@ pub enum FilterCommands {
         #[arg(short, long)]
         file: Option<PathBuf>,
     },
+
+    /// Run the bypass and report how often each entry matched
+    Stats(FilterStatsArgs),
+}
+
+/// `filter stats` arguments
+#[derive(Args, Debug)]
+pub struct FilterStatsArgs {
+    /// Show only the N most matched entries
+    #[arg(long)]
+    pub top: Option<usize>,
+
+    #[command(flatten)]
+    pub run: RunArgs,
 }
 
 /// Execute filter command
@@ -96,6 +113,7 @@ pub fn execute(args: FilterArgs) -> Result<()> {
         FilterCommands::Mode { mode, file } => set_mode(mode, file),
         FilterCommands::Init { file, mode } => init_filter(file, mode),
         FilterCommands::Check { domain, file } => check_domain(domain, file),
+        FilterCommands::Stats(args) => filter_stats(args),
     }
 }
 
@@ -201,6 +219,7 @@ fn set_mode(mode: String, file: Option<PathBuf>) -> Result<()> {
     let mode_str = match filter_mode {
         FilterMode::Whitelist => "whitelist".green(),
         FilterMode::Blacklist => "blacklist".yellow(),
+        FilterMode::Hybrid => "hybrid".cyan(),
         FilterMode::Disabled => "disabled".dimmed(),
     };
     
@@ -266,6 +285,7 @@ fn init_filter(file: Option<PathBuf>, mode: String) -> Result<()> {
     let mode_str = match filter_mode {
         FilterMode::Whitelist => "whitelist".green(),
         FilterMode::Blacklist => "blacklist".yellow(),
+        FilterMode::Hybrid => "hybrid".cyan(),
         FilterMode::Disabled => "disabled".dimmed(),
     };
     
@@ -305,3 +325,66 @@ fn check_domain(domain: String, file: Option<PathBuf>) -> Result<()> {
     
     Ok(())
 }
+
+/// Run a session and print the filter hit counts once it stops
+fn filter_stats(args: FilterStatsArgs) -> Result<()> {
+    let top = args.top;
+    let session = Session::start(args.run)?;
+    // Context clones share the filter, so its counters outlive the session
+    let ctx = session.handle().ctx;
+    let filter = ctx.filter();
+    if filter.mode() == FilterMode::Disabled {
+        bail!("No domain filter is active; enable [blacklist] in the config or pass --blacklist");
+    }
+
+    println!("Counting filter matches, press Ctrl+C to stop and show the report");
+    session.run()?;
+
+    println!();
+    print!("{}", hit_table(&filter.hit_report(), top));
+    if let Some(whitelist) = filter.whitelist() {
+        println!();
+        println!("{}", "Hybrid whitelist".bold());
+        print!("{}", hit_table(&whitelist.hit_report(), top));
+    }
+    Ok(())
+}
+
+/// Hit counts as a table, most matched first
+fn hit_table(report: &[(String, u64)], top: Option<usize>) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "{:>8}  ENTRY", "HITS");
+    for (entry, hits) in report.iter().take(top.unwrap_or(usize::MAX)) {
+        let _ = writeln!(out, "{:>8}  {}", hits, entry);
+    }
+
+    let unused = report.iter().filter(|(_, hits)| *hits == 0).count();
+    if unused > 0 {
+        let _ = writeln!(out, "{} of {} entries never matched", unused, report.len());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit_table() {
+        let report = vec![
+            ("*.gov.tr".to_string(), 12),
+            ("bank.com".to_string(), 3),
+            ("unused.com".to_string(), 0),
+        ];
+
+        assert_eq!(
+            hit_table(&report, None),
+            "    HITS  ENTRY\n      12  *.gov.tr\n       3  bank.com\n       0  unused.com\n\
+             1 of 3 entries never matched\n"
+        );
+        assert_eq!(
+            hit_table(&report, Some(1)),
+            "    HITS  ENTRY\n      12  *.gov.tr\n1 of 3 entries never matched\n"
+        );
+    }
+}
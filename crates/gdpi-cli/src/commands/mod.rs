@@ -4,8 +4,11 @@ pub mod completions;
 pub mod config;
 pub mod driver;
 pub mod filter;
+pub mod monitor;
+pub mod overrides;
 pub mod run;
 pub mod service;
+pub mod stats;
 pub mod test;
 
 use clap::Subcommand;
@@ -16,6 +19,12 @@ pub enum Command {
     /// Run the DPI bypass (main command)
     Run(run::RunArgs),
 
+    /// Run the DPI bypass with a live statistics view
+    Monitor(monitor::MonitorArgs),
+
+    /// Run the DPI bypass with a live counter line
+    Stats(stats::StatsArgs),
+
     /// Configuration management
     Config(config::ConfigArgs),
 
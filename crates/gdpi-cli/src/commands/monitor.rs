@@ -0,0 +1,171 @@
+//! Monitor command - run the bypass with a live statistics view
+
+use anyhow::{Context, Result};
+use clap::Args;
+use crossterm::cursor::MoveTo;
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::{cursor, execute, queue};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{stdout, Write};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use super::run::{RunArgs, Session, SessionHandle};
+
+/// Monitor command arguments
+#[derive(Args, Debug)]
+pub struct MonitorArgs {
+    /// Refresh interval in milliseconds
+    #[arg(long, default_value_t = 500)]
+    pub interval_ms: u64,
+
+    /// Emit one JSON object per interval instead of a table
+    #[arg(long)]
+    pub json: bool,
+
+    /// Reset counters after every interval (show per-interval totals)
+    #[arg(long)]
+    pub reset: bool,
+
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+/// One refresh worth of statistics
+#[derive(Debug, Default, Serialize)]
+struct Sample {
+    uptime_secs: f64,
+    packets: u64,
+    packets_per_sec: f64,
+    bytes: u64,
+    bytes_per_sec: f64,
+    fake_packets: u64,
+    fragments: u64,
+    connections: usize,
+    errors: u64,
+    strategy_hits: BTreeMap<&'static str, u64>,
+}
+
+/// Execute the monitor command
+pub fn execute(args: MonitorArgs) -> Result<()> {
+    let interval = Duration::from_millis(args.interval_ms.max(50));
+    let session = Session::start(args.run)?;
+    let handle = session.handle();
+    let worker = std::thread::Builder::new()
+        .name("gdpi-session".into())
+        .spawn(move || session.run())
+        .context("Failed to start packet loop")?;
+
+    let started = Instant::now();
+    let mut last = Sample::default();
+    let mut last_tick = started;
+    let mut out = stdout();
+
+    if !args.json {
+        execute!(out, cursor::Hide, Clear(ClearType::All))?;
+    }
+
+    while handle.running.load(Ordering::SeqCst) {
+        std::thread::sleep(interval);
+
+        let now = Instant::now();
+        let sample = sample(&handle, started, &last, now - last_tick, args.reset);
+        last_tick = now;
+
+        if args.json {
+            writeln!(out, "{}", serde_json::to_string(&sample)?)?;
+        } else {
+            queue!(out, MoveTo(0, 0), Clear(ClearType::FromCursorDown))?;
+            write!(out, "{}", render(&sample))?;
+        }
+        out.flush()?;
+
+        if args.reset {
+            handle.stats.reset();
+            handle.ctx.reset_stats();
+            last = Sample::default();
+        } else {
+            last = sample;
+        }
+    }
+
+    if !args.json {
+        execute!(out, cursor::Show)?;
+    }
+
+    worker
+        .join()
+        .map_err(|_| anyhow::anyhow!("Packet loop panicked"))?
+}
+
+fn sample(handle: &SessionHandle, started: Instant, last: &Sample, elapsed: Duration, reset: bool) -> Sample {
+    let pipeline = handle.ctx.get_stats();
+    let packets = handle.stats.total.load(Ordering::Relaxed);
+    let bytes = handle.stats.bytes.load(Ordering::Relaxed);
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    let (prev_packets, prev_bytes) = if reset { (0, 0) } else { (last.packets, last.bytes) };
+
+    Sample {
+        uptime_secs: started.elapsed().as_secs_f64(),
+        packets,
+        packets_per_sec: packets.saturating_sub(prev_packets) as f64 / secs,
+        bytes,
+        bytes_per_sec: bytes.saturating_sub(prev_bytes) as f64 / secs,
+        fake_packets: pipeline.fake_packets_sent,
+        fragments: pipeline.packets_fragmented,
+        connections: handle.ctx.connection_count(),
+        errors: handle.stats.errors.load(Ordering::Relaxed),
+        strategy_hits: pipeline.strategy_hits.into_iter().collect(),
+    }
+}
+
+fn render(sample: &Sample) -> String {
+    let uptime = sample.uptime_secs as u64;
+    let rows = [
+        ("Uptime", format!("{:02}:{:02}:{:02}", uptime / 3600, uptime / 60 % 60, uptime % 60)),
+        ("Packets/s", format!("{:.0}", sample.packets_per_sec)),
+        ("Bytes/s", format!("{:.0}", sample.bytes_per_sec)),
+        ("Packets", sample.packets.to_string()),
+        ("Fake packets", sample.fake_packets.to_string()),
+        ("Fragments", sample.fragments.to_string()),
+        ("Connections", sample.connections.to_string()),
+        ("Errors", sample.errors.to_string()),
+    ];
+
+    let mut table = String::from("GoodbyeDPI monitor (Ctrl+C to stop)\r\n\r\n");
+    for (label, value) in rows {
+        table.push_str(&format!("  {:<14}{:>14}\r\n", label, value));
+    }
+
+    if !sample.strategy_hits.is_empty() {
+        let mut hits: Vec<_> = sample.strategy_hits.iter().collect();
+        hits.sort_by(|a, b| b.1.cmp(a.1));
+
+        table.push_str("\r\n  Strategy hits\r\n");
+        for (name, count) in hits {
+            table.push_str(&format!("  {:<18}{:>10}\r\n", name, count));
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table() {
+        let table = render(&Sample {
+            uptime_secs: 3725.0,
+            packets: 42,
+            strategy_hits: BTreeMap::from([("fragmentation", 3), ("fake_packet", 7)]),
+            ..Sample::default()
+        });
+        assert!(table.contains("01:02:05"));
+        let fake = table.find("fake_packet").unwrap();
+        let fragmentation = table.find("fragmentation").unwrap();
+        assert!(fake < fragmentation, "most hit strategy first");
+        assert!(table.lines().any(|line| line.trim_start().starts_with("Packets ") && line.ends_with(" 42")));
+    }
+}
@@ -0,0 +1,158 @@
+//! Config selection and command-line overrides shared by the commands
+//! that build a configuration (`run`, `config show --effective`)
+
+use anyhow::{Context, Result};
+use clap::Args;
+use gdpi_core::config::{Config, Profile};
+
+/// Profile, config file and per-setting overrides
+#[derive(Args, Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    /// Profile to use (1-9, turkey)
+    #[arg(short = 'p', long)]
+    pub profile: Option<String>,
+
+    /// Configuration file
+    #[arg(short = 'c', long)]
+    pub config: Option<String>,
+
+    /// Alternative DNS server
+    #[arg(long)]
+    pub dns_addr: Option<String>,
+
+    /// Block QUIC (UDP 443)
+    #[arg(long)]
+    pub block_quic: bool,
+
+    /// Use auto-TTL detection
+    #[arg(long)]
+    pub auto_ttl: bool,
+
+    /// Manual TTL value
+    #[arg(long)]
+    pub ttl: Option<u8>,
+
+    /// HTTP fragmentation position
+    #[arg(long)]
+    pub http_frag: Option<u32>,
+
+    /// HTTPS fragmentation position
+    #[arg(long)]
+    pub https_frag: Option<u32>,
+
+    /// Use wrong checksum for fake packets
+    #[arg(long)]
+    pub wrong_chksum: bool,
+
+    /// Use wrong sequence number for fake packets
+    #[arg(long)]
+    pub wrong_seq: bool,
+}
+
+impl ConfigOverrides {
+    /// Whether any per-setting flag (not profile or file) was given
+    pub fn has_setting_overrides(&self) -> bool {
+        self.dns_addr.is_some()
+            || self.block_quic
+            || self.auto_ttl
+            || self.ttl.is_some()
+            || self.http_frag.is_some()
+            || self.https_frag.is_some()
+            || self.wrong_chksum
+            || self.wrong_seq
+    }
+}
+
+/// Build the effective configuration
+///
+/// Priority: CLI flags > GDPI_* env vars > config file > profile > defaults
+pub fn load_config(args: &ConfigOverrides) -> Result<Config> {
+    let mut config = if let Some(ref config_path) = args.config {
+        Config::load_auto(config_path)
+            .with_context(|| format!("Failed to load config from {}", config_path))?
+    } else if let Some(ref profile_name) = args.profile {
+        let profile = Profile::from_name(profile_name)
+            .with_context(|| format!("Unknown profile: {}", profile_name))?;
+        Config::from_profile(profile)
+    } else {
+        // Default: Turkey profile, unless GDPI_CONFIG_FILE/GDPI_PROFILE say otherwise
+        Config::from_env_overlay(Config::from_profile(Profile::Turkey))
+    };
+    config
+        .apply_env_overrides()
+        .context("Invalid GDPI_* environment variable")?;
+
+    apply_cli_flags(&mut config, args)?;
+    Ok(config)
+}
+
+/// Apply the settings given as command-line flags onto `config`
+///
+/// Only flags that were given change anything, so a flag overrides the
+/// loaded value even when it equals the default.
+fn apply_cli_flags(config: &mut Config, args: &ConfigOverrides) -> Result<()> {
+    if let Some(ref dns) = args.dns_addr {
+        config.dns.enabled = true;
+        let ip: std::net::IpAddr = dns.parse()
+            .with_context(|| format!("Invalid DNS address: {}", dns))?;
+        config.dns.set_upstream(ip);
+    }
+
+    let strategies = &mut config.strategies;
+    strategies.block_quic |= args.block_quic;
+    strategies.auto_ttl |= args.auto_ttl;
+    strategies.fake_with_wrong_checksum |= args.wrong_chksum;
+    strategies.fake_with_wrong_seq |= args.wrong_seq;
+
+    if let Some(ttl) = args.ttl {
+        strategies.fake_ttl = Some(ttl);
+    }
+
+    if let Some(pos) = args.http_frag {
+        strategies.http_fragment_position = pos;
+    }
+
+    if let Some(pos) = args.https_frag {
+        strategies.https_fragment_position = pos;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_flags_override_profile() {
+        let overrides = ConfigOverrides {
+            profile: Some("turkey".into()),
+            https_frag: Some(4),
+            block_quic: true,
+            ..Default::default()
+        };
+        assert!(overrides.has_setting_overrides());
+
+        let config = load_config(&overrides).unwrap();
+        assert_eq!(config.strategies.https_fragment_position, 4);
+        assert!(config.strategies.block_quic);
+    }
+
+    #[test]
+    fn test_cli_flag_equal_to_default_overrides_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let mut file = Config::default();
+        file.strategies.https_fragment_position = 4;
+        std::fs::write(&path, toml::to_string(&file).unwrap()).unwrap();
+
+        let default = Config::default().strategies.https_fragment_position;
+        let overrides = ConfigOverrides {
+            config: Some(path.to_string_lossy().into_owned()),
+            https_frag: Some(default),
+            ..Default::default()
+        };
+        let config = load_config(&overrides).unwrap();
+        assert_eq!(config.strategies.https_fragment_position, default);
+    }
+}
@@ -2,23 +2,366 @@
 
 use anyhow::{Context, Result};
 use clap::Args;
-use gdpi_core::config::{Config, Profile};
+use gdpi_core::config::{Config, ConfigWatcher};
+use gdpi_core::filter::FilterMode;
 use gdpi_core::pipeline::{Context as PipelineContext, Pipeline};
 use gdpi_core::strategies::StrategyBuilder;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use gdpi_platform::ipc::{self, StatsServer, StatsSnapshot};
+use gdpi_platform::{PacketCapture, PcapCapture, PcapReplayCapture, PlatformError};
+use ipnetwork::{IpNetwork, Ipv4Network};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 use tracing::{debug, error, info, warn};
 
+use super::overrides::{load_config, ConfigOverrides};
 use crate::args::Args as GlobalArgs;
 
-/// Packet processing statistics
+/// Packet processing statistics, shared with the packet workers and monitor
 #[derive(Default)]
-struct PacketStats {
-    total: u64,
-    modified: u64,
-    errors: u64,
+pub struct PacketStats {
+    /// Packets captured
+    pub total: AtomicU64,
+    /// Bytes captured
+    pub bytes: AtomicU64,
+    /// Packets the pipeline changed
+    pub modified: AtomicU64,
+    /// Pipeline errors
+    pub errors: AtomicU64,
+    /// Receives that filled a whole batch; many of them mean the capture
+    /// queue is backing up and may overflow
+    pub full_batches: AtomicU64,
+    /// Most packets the capture queue holds (0 if unknown)
+    pub queue_max_len: AtomicU64,
+    /// Hostnames the bypass was applied to, newest first
+    recent_domains: Mutex<VecDeque<String>>,
 }
 
+impl PacketStats {
+    /// Reset all counters to zero
+    pub fn reset(&self) {
+        for counter in [&self.total, &self.bytes, &self.modified, &self.errors, &self.full_batches] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.recent_domains.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    /// Remember that the bypass was applied to `host`
+    #[cfg_attr(not(any(windows, all(target_os = "linux", feature = "nfqueue"))), allow(dead_code))]
+    pub fn record_domain(&self, host: &str) {
+        let mut recent = self.recent_domains.lock().unwrap_or_else(PoisonError::into_inner);
+        recent.retain(|seen| seen != host);
+        recent.push_front(host.to_string());
+        recent.truncate(ipc::RECENT_DOMAINS);
+    }
+
+    /// Snapshot for the stats channel, combining these counters with the
+    /// pipeline's
+    pub fn snapshot(&self, ctx: &PipelineContext) -> StatsSnapshot {
+        StatsSnapshot {
+            packets_captured: self.total.load(Ordering::Relaxed),
+            bytes_captured: self.bytes.load(Ordering::Relaxed),
+            packets_modified: self.modified.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            queue_max_len: self.queue_max_len.load(Ordering::Relaxed),
+            full_batches: self.full_batches.load(Ordering::Relaxed),
+            recent_domains: self
+                .recent_domains
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .cloned()
+                .collect(),
+            ..StatsSnapshot::from_stats(&ctx.get_stats())
+        }
+    }
+}
+
+/// A configured bypass session, ready to run
+pub struct Session {
+    config: Config,
+    pipeline: Arc<Pipeline>,
+    ctx: PipelineContext,
+    running: Arc<AtomicBool>,
+    stats: Arc<PacketStats>,
+    watcher: Option<ConfigWatcher>,
+    /// File to reload from on Ctrl+Break (Windows)
+    config_path: Option<String>,
+    queue_num: u16,
+    capture_filter: CaptureFilter,
+}
+
+/// Changes to the WinDivert capture filter from the command line
+#[derive(Debug, Default)]
+struct CaptureFilter {
+    /// Replaces the filter built from the config
+    expr: Option<String>,
+    /// Subnets left out of the capture, in either direction
+    exclude_subnets: Vec<Ipv4Network>,
+    /// Capture with one handle per kind of traffic
+    multi_handle: bool,
+}
+
+#[cfg(windows)]
+impl CaptureFilter {
+    /// The filter to open the driver with
+    fn build(&self, config: &Config) -> Result<String> {
+        use gdpi_platform::windows::{FilterPresets, WinDivertDriver};
+        use gdpi_platform::PacketFilter;
+
+        let filter = match &self.expr {
+            Some(expr) => {
+                WinDivertDriver::validate_filter(expr).context("Invalid --filter-expr")?;
+                expr.clone()
+            }
+            None => default_filter(config),
+        };
+        Ok(FilterPresets::exclude_subnets(&filter, &self.exclude_subnets))
+    }
+}
+
+#[cfg(not(windows))]
+impl CaptureFilter {
+    /// Warn about the options only the WinDivert capture honours
+    fn warn_ignored(&self) {
+        if self.expr.is_some() {
+            warn!("Ignoring --filter-expr, WinDivert filters only apply on Windows");
+        }
+        if !self.exclude_subnets.is_empty() {
+            warn!("Ignoring --exclude-subnet, only supported on Windows");
+        }
+        if self.multi_handle {
+            warn!("Ignoring --multi-handle, only supported on Windows");
+        }
+    }
+}
+
+/// WinDivert handles the packet loop receives from and re-injects through
+#[cfg(windows)]
+enum Capture {
+    /// One handle with the combined filter
+    Single(gdpi_platform::windows::WinDivertDriver),
+    /// One handle per kind of traffic (`--multi-handle`)
+    Multi {
+        driver: gdpi_platform::windows::MultiHandleDriver,
+        batch_size: usize,
+    },
+}
+
+/// Kind of handle a packet came through, `None` for [`Capture::Single`]
+#[cfg(windows)]
+type Lane = Option<gdpi_platform::windows::HandleKind>;
+
+#[cfg(windows)]
+impl Capture {
+    /// Open the handles `config` needs
+    fn open(capture_filter: &CaptureFilter, config: &Config) -> Result<Self> {
+        use gdpi_platform::windows::{DriverOptions, Flags, HandleKind, MultiHandleDriver, WinDivertDriver};
+
+        let batch_size = usize::from(config.performance.batch_size).max(1);
+        if capture_filter.multi_handle {
+            let filters = HandleKind::filters(config, &capture_filter.exclude_subnets);
+            let driver = MultiHandleDriver::open(filters)
+                .context("Failed to open WinDivert - is the driver installed?")?;
+            return Ok(Capture::Multi { driver, batch_size });
+        }
+
+        let filter = capture_filter.build(config)?;
+        info!(filter = filter, "Opening WinDivert handle");
+        let driver = WinDivertDriver::open(&filter, Flags::default())
+            .context("Failed to open WinDivert - is the driver installed?")?
+            .with_options(DriverOptions { batch_size });
+        Ok(Capture::Single(driver))
+    }
+
+    fn batch_size(&self) -> usize {
+        match self {
+            Capture::Single(driver) => driver.batch_size(),
+            Capture::Multi { batch_size, .. } => *batch_size,
+        }
+    }
+
+    /// Receive buffer [`Capture::recv_batch`] needs
+    fn buffer_len(&self) -> usize {
+        match self {
+            Capture::Single(driver) => driver.batch_buffer_len(),
+            // The reader threads bring their own
+            Capture::Multi { .. } => 0,
+        }
+    }
+
+    /// Up to a batch of packets, waiting at most [`RECV_POLL`] for the first
+    fn recv_batch(
+        &self,
+        buffer: &mut [u8],
+    ) -> gdpi_platform::Result<Vec<(Lane, gdpi_platform::CapturedPacket)>> {
+        Ok(match self {
+            Capture::Single(driver) => driver
+                .recv_batch_timeout_shared(buffer, driver.batch_size(), RECV_POLL)?
+                .into_iter()
+                .map(|packet| (None, packet))
+                .collect(),
+            Capture::Multi { driver, batch_size } => driver
+                .recv_batch_timeout(*batch_size, RECV_POLL)?
+                .into_iter()
+                .map(|(kind, packet)| (Some(kind), packet))
+                .collect(),
+        })
+    }
+
+    fn send(&self, lane: Lane, packet: &[u8], addr: &gdpi_platform::PacketAddress) -> gdpi_platform::Result<()> {
+        match self {
+            Capture::Single(driver) => driver.send_shared(packet, addr),
+            Capture::Multi { driver, .. } => driver.send(Self::kind(lane)?, packet, addr),
+        }
+    }
+
+    /// Inject `packets`, returning how many were queued
+    fn send_batch(
+        &self,
+        lane: Lane,
+        packets: &[(bytes::Bytes, gdpi_platform::PacketAddress)],
+    ) -> gdpi_platform::Result<u32> {
+        match self {
+            Capture::Single(driver) => driver.send_batch_shared(packets),
+            Capture::Multi { driver, .. } => driver.send_batch(Self::kind(lane)?, packets),
+        }
+    }
+
+    /// Handle of a packet to send through several handles
+    fn kind(lane: Lane) -> gdpi_platform::Result<gdpi_platform::windows::HandleKind> {
+        lane.ok_or_else(|| PlatformError::HandleError("No handle to send through".into()))
+    }
+
+    /// Reopen the handles with the filters a reloaded `config` needs
+    ///
+    /// The old handles stay open if the new ones can't be opened.
+    fn swap_filter(&mut self, capture_filter: &CaptureFilter, config: &Config) {
+        use gdpi_platform::windows::{HandleKind, MultiHandleDriver};
+        use gdpi_platform::PacketFilter;
+
+        match self {
+            Capture::Single(driver) => {
+                let filter = match capture_filter.build(config) {
+                    Ok(filter) => filter,
+                    Err(e) => {
+                        error!("Keeping current capture filter: {:#}", e);
+                        return;
+                    }
+                };
+                if driver.get_filter() == filter {
+                    return;
+                }
+                match driver.reopen_with_filter(&filter) {
+                    Ok(()) => info!(filter = filter, "Capture filter changed"),
+                    Err(e) => error!("Keeping current capture filter, reopen failed: {}", e),
+                }
+            }
+            Capture::Multi { driver, .. } => {
+                let filters = HandleKind::filters(config, &capture_filter.exclude_subnets);
+                if driver.filters() == filters.as_slice() {
+                    return;
+                }
+                match MultiHandleDriver::open(filters) {
+                    // Dropping the old driver closes its handles
+                    Ok(reopened) => {
+                        *driver = reopened;
+                        info!("Capture filters changed");
+                    }
+                    Err(e) => error!("Keeping current capture filters, reopen failed: {}", e),
+                }
+            }
+        }
+    }
+
+    fn close(self) -> gdpi_platform::Result<()> {
+        use gdpi_platform::PacketCapture;
+
+        match self {
+            Capture::Single(mut driver) => driver.close(),
+            Capture::Multi { mut driver, .. } => {
+                driver.close();
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Shared access to the capture for receiving and sending
+#[cfg(windows)]
+fn read_capture(capture: &std::sync::RwLock<Capture>) -> std::sync::RwLockReadGuard<'_, Capture> {
+    capture.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Capture filter for the strategies and DNS settings in `config`
+#[cfg(windows)]
+fn default_filter(config: &Config) -> String {
+    use gdpi_platform::windows::FilterPresets;
+
+    let mut filter = FilterPresets::goodbyedpi(
+        config.strategies.block_quic,
+        &config.bypass_ports(),
+        config.performance.max_payload_size,
+    );
+
+    // DNS redirection needs queries and the upstream's responses
+    if config.dns.enabled {
+        let upstream_ports: Vec<u16> = [
+            config.dns.ipv4_upstream.map(|_| config.dns.ipv4_port.unwrap_or(53)),
+            config.dns.ipv6_upstream.map(|_| config.dns.ipv6_port.unwrap_or(53)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        if !upstream_ports.is_empty() {
+            filter = format!("{} or {}", filter, FilterPresets::dns_redirect(&upstream_ports));
+        }
+    }
+
+    // Forged resets arrive inbound and are otherwise never captured
+    if config.strategies.passive_dpi.enabled {
+        filter = format!("{} or {}", filter, FilterPresets::rst_inbound());
+    }
+
+    // HTTP is looked for on every port, not just the bypass ports
+    if config.performance.http_all_ports {
+        let max_payload = config.performance.max_payload_size;
+        filter = format!("{} or {}", filter, FilterPresets::tcp_data_outbound(max_payload));
+    }
+
+    filter
+}
+
+/// Read-only view of a running session, for live monitoring
+#[derive(Clone)]
+pub struct SessionHandle {
+    /// Capture-level counters
+    pub stats: Arc<PacketStats>,
+    /// Pipeline context (strategy counters, connection tracking)
+    pub ctx: PipelineContext,
+    /// Cleared when the session shuts down
+    pub running: Arc<AtomicBool>,
+}
+
+/// Capture metadata carried alongside a packet through the worker pool
+#[cfg(windows)]
+struct CaptureJob {
+    /// Original bytes, re-injected if the pipeline fails
+    data: Vec<u8>,
+    /// Address for re-injection
+    address: gdpi_platform::PacketAddress,
+    /// Handle to re-inject through
+    lane: Lane,
+    /// SNI, for logging bypassed blocked domains
+    sni: Option<String>,
+}
+
+/// Longest the WinDivert loop waits for traffic before checking whether
+/// it should stop
+#[cfg(windows)]
+const RECV_POLL: std::time::Duration = std::time::Duration::from_millis(250);
+
 /// Known blocked domains that we want to highlight in logs
 const BLOCKED_DOMAINS: &[&str] = &[
     "discord.com",
@@ -39,53 +382,60 @@ fn is_blocked_domain(host: &str) -> bool {
 /// Run command arguments
 #[derive(Args, Debug)]
 pub struct RunArgs {
-    /// Profile to use (1-9, turkey)
-    #[arg(short = 'p', long)]
-    pub profile: Option<String>,
+    #[command(flatten)]
+    pub overrides: ConfigOverrides,
 
-    /// Configuration file
-    #[arg(short = 'c', long)]
-    pub config: Option<String>,
-
-    /// Blacklist file
+    /// Blacklist file, added to the config's domain filter
     #[arg(short = 'b', long)]
     pub blacklist: Option<String>,
 
-    /// Alternative DNS server
+    /// Dry run (don't actually modify packets)
     #[arg(long)]
-    pub dns_addr: Option<String>,
+    pub dry_run: bool,
 
-    /// Block QUIC (UDP 443)
+    /// Show a live counter line while running
     #[arg(long)]
-    pub block_quic: bool,
+    pub stats: bool,
 
-    /// Use auto-TTL detection
-    #[arg(long)]
-    pub auto_ttl: bool,
+    /// Reload strategies when the config file changes (requires --config)
+    #[arg(long, requires = "config")]
+    pub watch_config: bool,
 
-    /// Manual TTL value
-    #[arg(long)]
-    pub ttl: Option<u8>,
+    /// Netfilter queue to read packets from (Linux, `nfqueue` feature)
+    #[arg(long, default_value_t = 0)]
+    pub queue_num: u16,
 
-    /// HTTP fragmentation position
-    #[arg(long)]
-    pub http_frag: Option<u32>,
+    /// Replay a .pcap/.pcapng file and print what each strategy does
+    #[arg(long, requires = "dry_run")]
+    pub pcap: Option<String>,
 
-    /// HTTPS fragmentation position
-    #[arg(long)]
-    pub https_frag: Option<u32>,
+    /// Run a .pcap/.pcapng file through the bypass instead of live
+    /// traffic (no driver or admin rights needed)
+    #[arg(long, requires = "replay_out", conflicts_with = "pcap")]
+    pub replay: Option<String>,
 
-    /// Use wrong checksum for fake packets
-    #[arg(long)]
-    pub wrong_chksum: bool,
+    /// Where --replay writes the packets that would have been sent
+    #[arg(long, requires = "replay")]
+    pub replay_out: Option<String>,
 
-    /// Use wrong sequence number for fake packets
-    #[arg(long)]
-    pub wrong_seq: bool,
+    /// Local networks for telling outbound from inbound in --pcap and
+    /// --replay (default: private and link-local ranges)
+    #[arg(long, value_delimiter = ',')]
+    pub local_net: Vec<IpNetwork>,
 
-    /// Dry run (don't actually modify packets)
-    #[arg(long)]
-    pub dry_run: bool,
+    /// WinDivert filter to capture with instead of the built-in one
+    #[arg(long, value_name = "FILTER")]
+    pub filter_expr: Option<String>,
+
+    /// Leave traffic to or from this IPv4 subnet alone (repeatable, e.g. a
+    /// VPN's)
+    #[arg(long, value_name = "CIDR")]
+    pub exclude_subnet: Vec<Ipv4Network>,
+
+    /// Capture with one WinDivert handle per kind of traffic, leaving
+    /// packets no strategy needs in the kernel
+    #[arg(long, conflicts_with = "filter_expr")]
+    pub multi_handle: bool,
 }
 
 impl RunArgs {
@@ -97,158 +447,426 @@ impl RunArgs {
         });
 
         Self {
-            profile,
-            config: args.config.clone(),
+            overrides: ConfigOverrides {
+                profile,
+                config: args.config.clone(),
+                dns_addr: args.dns_addr.clone(),
+                block_quic: args.block_quic,
+                auto_ttl: args.auto_ttl,
+                ttl: args.set_ttl,
+                http_frag: args.http_frag,
+                https_frag: args.https_frag,
+                wrong_chksum: args.wrong_chksum,
+                wrong_seq: args.wrong_seq,
+            },
             blacklist: args.blacklist.clone(),
-            dns_addr: args.dns_addr.clone(),
-            block_quic: args.block_quic,
-            auto_ttl: args.auto_ttl,
-            ttl: args.set_ttl,
-            http_frag: args.http_frag,
-            https_frag: args.https_frag,
-            wrong_chksum: args.wrong_chksum,
-            wrong_seq: args.wrong_seq,
             dry_run: false,
+            stats: false,
+            watch_config: false,
+            queue_num: 0,
+            pcap: None,
+            replay: None,
+            replay_out: None,
+            local_net: Vec::new(),
+            filter_expr: None,
+            exclude_subnet: Vec::new(),
+            multi_handle: false,
         }
     }
 }
 
 /// Execute the run command
 pub fn execute(args: RunArgs) -> Result<()> {
-    info!("Starting GoodbyeDPI...");
-
-    // Load configuration
-    let config = load_config(&args)?;
-    info!(profile = ?config.profile, "Loaded configuration");
+    let dry_run = args.dry_run;
+    let stats = args.stats;
+    let pcap = args.pcap.clone();
+    let replay = args.replay.clone().zip(args.replay_out.clone());
+    let local_net = args.local_net.clone();
+    let session = Session::start(args)?;
 
-    // Create pipeline
-    let mut pipeline = Pipeline::new();
-    let strategies = StrategyBuilder::from_config(&config);
-    pipeline.add_strategies(strategies);
-    
-    info!(
-        strategy_count = pipeline.len(),
-        strategies = ?pipeline.strategy_names(),
-        "Initialized pipeline"
-    );
-
-    // Create context
-    let ctx = if let Some(ref blacklist_path) = args.blacklist {
-        let domains = load_blacklist(blacklist_path)?;
-        info!(count = domains.len(), "Loaded blacklist");
-        PipelineContext::with_blacklist(domains)
-    } else {
-        PipelineContext::new()
-    };
+    if let Some((input, output)) = replay {
+        return session.replay(&input, &output, local_net);
+    }
 
-    // Set up signal handler
-    let running = Arc::new(AtomicBool::new(true));
-    let r = running.clone();
-    
-    ctrlc::set_handler(move || {
-        info!("Received interrupt signal, shutting down...");
-        r.store(false, Ordering::SeqCst);
-    }).context("Failed to set signal handler")?;
+    if let Some(path) = pcap {
+        return session.trace_pcap(&path, local_net);
+    }
 
     // Dry run check
-    if args.dry_run {
+    if dry_run {
         warn!("Dry run mode - no packets will be modified");
         info!("Configuration validated successfully");
         return Ok(());
     }
 
-    // Main packet processing loop
-    run_packet_loop(config, pipeline, ctx, running)?;
-
-    // Print final stats
-    info!("GoodbyeDPI stopped");
+    if stats {
+        return super::stats::run_with_stats(session, super::stats::DEFAULT_INTERVAL);
+    }
 
-    Ok(())
+    session.run()
 }
 
-fn load_config(args: &RunArgs) -> Result<Config> {
-    // Priority: config file > profile > defaults
-    if let Some(ref config_path) = args.config {
-        return Config::load(config_path)
-            .with_context(|| format!("Failed to load config from {}", config_path));
-    }
-
-    // Create config from profile or defaults
-    let mut config = if let Some(ref profile_name) = args.profile {
-        let profile = Profile::from_name(profile_name)
-            .with_context(|| format!("Unknown profile: {}", profile_name))?;
-        Config::from_profile(profile)
-    } else {
-        // Default: Turkey profile
-        Config::from_profile(Profile::Turkey)
-    };
+impl Session {
+    /// Load configuration and build the pipeline without capturing yet
+    ///
+    /// Ctrl+C stops the session.
+    pub fn start(args: RunArgs) -> Result<Self> {
+        let running = Arc::new(AtomicBool::new(true));
+        let r = running.clone();
+
+        ctrlc::set_handler(move || {
+            info!("Received interrupt signal, shutting down...");
+            r.store(false, Ordering::SeqCst);
+        }).context("Failed to set signal handler")?;
 
-    // Apply command-line overrides
-    if let Some(ref dns) = args.dns_addr {
-        config.dns.enabled = true;
-        let ip: std::net::IpAddr = dns.parse()
-            .with_context(|| format!("Invalid DNS address: {}", dns))?;
-        config.dns.server = Some(ip);
+        Self::start_with(args, running)
     }
 
-    if args.block_quic {
-        config.strategies.block_quic = true;
+    /// Like [`Session::start`], but stopped by clearing `running` instead
+    /// of Ctrl+C
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn start_with(args: RunArgs, running: Arc<AtomicBool>) -> Result<Self> {
+        info!("Starting GoodbyeDPI...");
+
+        // Load configuration
+        let config = load_config(&args.overrides)?;
+        info!(profile = ?config.profile, "Loaded configuration");
+
+        let capture_filter = CaptureFilter {
+            expr: args.filter_expr,
+            exclude_subnets: args.exclude_subnet,
+            multi_handle: args.multi_handle,
+        };
+        #[cfg(not(windows))]
+        capture_filter.warn_ignored();
+
+        let pipeline = build_pipeline(&config);
+
+        // Create context
+        let ctx = build_context(&config, args.blacklist.as_deref())?;
+
+        // Keep the watcher alive for the lifetime of the packet loop
+        let watcher = match (args.watch_config, args.overrides.config.as_deref()) {
+            (true, Some(path)) => {
+                let pipeline = Arc::clone(&pipeline);
+                let watcher = Config::watch(path, move |config| {
+                    pipeline.reload_config(&config.with_env_overrides())
+                })
+                    .with_context(|| format!("Failed to watch config file {}", path))?;
+                Some(watcher)
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            config,
+            pipeline,
+            ctx,
+            running,
+            stats: Arc::new(PacketStats::default()),
+            watcher,
+            config_path: args.overrides.config,
+            queue_num: args.queue_num,
+            capture_filter,
+        })
     }
 
-    if args.auto_ttl {
-        config.strategies.auto_ttl = true;
+    /// Handle for observing the session while it runs
+    pub fn handle(&self) -> SessionHandle {
+        SessionHandle {
+            stats: Arc::clone(&self.stats),
+            ctx: self.ctx.clone(),
+            running: Arc::clone(&self.running),
+        }
     }
 
-    if let Some(ttl) = args.ttl {
-        config.strategies.fake_ttl = Some(ttl);
+    /// Run a capture file through the pipeline, printing per-packet traces
+    ///
+    /// Nothing is sent; this only shows what the strategies would do.
+    pub fn trace_pcap(&self, path: &str, local_net: Vec<IpNetwork>) -> Result<()> {
+        let mut capture = PcapCapture::open(path)
+            .with_context(|| format!("Failed to open capture file {}", path))?;
+        if !local_net.is_empty() {
+            capture = capture.with_local_networks(local_net);
+        }
+
+        let mut count = 0u64;
+        while self.running.load(Ordering::SeqCst) {
+            let captured = match capture.recv() {
+                Ok(captured) => captured,
+                Err(PlatformError::EndOfCapture) => break,
+                Err(e) => return Err(e).context("Failed to read capture file"),
+            };
+            count += 1;
+
+            let packet = match captured.parse() {
+                Ok(packet) => packet,
+                Err(e) => {
+                    println!("#{} unparsed: {}", count, e);
+                    continue;
+                }
+            };
+
+            let mut line = format!(
+                "#{} {:?} {}:{} -> {}:{} ({} bytes)",
+                count,
+                packet.direction,
+                packet.src_addr,
+                packet.src_port,
+                packet.dst_addr,
+                packet.dst_port,
+                packet.len()
+            );
+            if let Some(sni) = packet.extract_sni() {
+                line.push_str(&format!(" sni={}", sni));
+            }
+            println!("{}", line);
+
+            for step in self.pipeline.process_dry(packet, &self.ctx)? {
+                println!("    {}", step);
+            }
+        }
+
+        println!("{} packets replayed", count);
+        Ok(())
     }
 
-    if let Some(pos) = args.http_frag {
-        config.strategies.http_fragment_position = pos;
+    /// Run a capture file through the pipeline, writing everything that
+    /// would have been sent to `output`
+    pub fn replay(self, input: &str, output: &str, local_net: Vec<IpNetwork>) -> Result<()> {
+        let mut capture = PcapReplayCapture::open(input, output)
+            .with_context(|| format!("Failed to replay {} into {}", input, output))?;
+        if !local_net.is_empty() {
+            capture = capture.with_local_networks(local_net);
+        }
+
+        let mut ctx = self.ctx.clone();
+        while self.running.load(Ordering::SeqCst) {
+            let captured = match capture.recv() {
+                Ok(captured) => captured,
+                Err(PlatformError::EndOfCapture) => break,
+                Err(e) => return Err(e).context("Failed to read capture file"),
+            };
+            self.stats.total.fetch_add(1, Ordering::Relaxed);
+            self.stats.bytes.fetch_add(captured.data.len() as u64, Ordering::Relaxed);
+
+            // Unparseable packets and pipeline errors pass through as-is,
+            // like in the live loop
+            let result = captured
+                .parse()
+                .and_then(|packet| self.pipeline.process(packet, &mut ctx));
+            let batch: Vec<_> = match result {
+                Ok(packets) => {
+                    if packets.len() > 1 {
+                        self.stats.modified.fetch_add(1, Ordering::Relaxed);
+                    }
+                    packets
+                        .into_iter()
+                        .map(|pkt| (pkt.into_bytes(), captured.address.clone()))
+                        .collect()
+                }
+                Err(e) => {
+                    self.stats.errors.fetch_add(1, Ordering::Relaxed);
+                    debug!("Pipeline error: {}", e);
+                    vec![(captured.data.into(), captured.address)]
+                }
+            };
+            capture.send_batch(&batch).context("Failed to write replay output")?;
+        }
+        capture.close()?;
+
+        info!(
+            "Replay finished: {} packets in, {} out, {} modified, {} errors",
+            self.stats.total.load(Ordering::Relaxed),
+            capture.sent(),
+            self.stats.modified.load(Ordering::Relaxed),
+            self.stats.errors.load(Ordering::Relaxed)
+        );
+        Ok(())
     }
 
-    if let Some(pos) = args.https_frag {
-        config.strategies.https_fragment_position = pos;
+    /// Run the packet loop until interrupted
+    pub fn run(self) -> Result<()> {
+        // Keep the watcher alive for the lifetime of the packet loop
+        let _watcher = self.watcher;
+
+        // Live stats for the GUI; the bypass works without them
+        let _stats_server = {
+            let stats = Arc::clone(&self.stats);
+            let ctx = self.ctx.clone();
+            StatsServer::start(move || stats.snapshot(&ctx))
+                .map_err(|e| warn!("Stats channel unavailable: {}", e))
+                .ok()
+        };
+
+        // Main packet processing loop
+        let result = run_packet_loop(
+            self.config,
+            self.pipeline,
+            self.ctx,
+            Arc::clone(&self.running),
+            self.stats,
+            self.config_path,
+            self.queue_num,
+            self.capture_filter,
+        );
+        self.running.store(false, Ordering::SeqCst);
+        result?;
+
+        // Print final stats
+        info!("GoodbyeDPI stopped");
+
+        Ok(())
     }
+}
+
+/// A bypass session on a background thread, for trying one configuration
+/// after another without restarting
+#[cfg_attr(not(windows), allow(dead_code))]
+pub struct BackgroundSession {
+    running: Arc<AtomicBool>,
+    worker: std::thread::JoinHandle<Result<()>>,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+impl BackgroundSession {
+    /// Start capturing with `config` on a new thread
+    pub fn spawn(config: Config) -> Result<Self> {
+        let pipeline = build_pipeline(&config);
+        let ctx = build_context(&config, None)?;
+        let running = Arc::new(AtomicBool::new(true));
 
-    if args.wrong_chksum {
-        config.strategies.fake_with_wrong_checksum = true;
+        let worker = {
+            let running = Arc::clone(&running);
+            std::thread::Builder::new()
+                .name("gdpi-session".into())
+                .spawn(move || {
+                    let stats = Arc::new(PacketStats::default());
+                    run_packet_loop(config, pipeline, ctx, running, stats, None, 0, CaptureFilter::default())
+                })
+                .context("Failed to start packet loop")?
+        };
+
+        Ok(Self { running, worker })
     }
 
-    if args.wrong_seq {
-        config.strategies.fake_with_wrong_seq = true;
+    /// Whether the packet loop is still up (it exits early if the capture
+    /// handle can't be opened)
+    pub fn is_running(&self) -> bool {
+        !self.worker.is_finished()
     }
 
-    Ok(config)
+    /// Stop the packet loop and wait until the capture handle is closed
+    pub fn stop(self) -> Result<()> {
+        self.running.store(false, Ordering::SeqCst);
+        self.worker
+            .join()
+            .map_err(|_| anyhow::anyhow!("Packet loop panicked"))?
+    }
 }
 
-fn load_blacklist(path: &str) -> Result<Vec<String>> {
-    let content = std::fs::read_to_string(path)
-        .with_context(|| format!("Failed to read blacklist file: {}", path))?;
+/// Pipeline with the strategies `config` enables
+fn build_pipeline(config: &Config) -> Arc<Pipeline> {
+    let mut pipeline = new_pipeline(config);
+    pipeline.add_strategies(StrategyBuilder::from_config(config));
+    pipeline.set_overrides(StrategyBuilder::overrides_from_config(config));
+    pipeline.set_max_payload_size(config.performance.max_payload_size);
 
-    let domains: Vec<String> = content
-        .lines()
-        .filter(|line| {
-            let line = line.trim();
-            !line.is_empty() && !line.starts_with('#')
-        })
-        .map(|s| s.trim().to_lowercase())
-        .collect();
+    info!(
+        strategy_count = pipeline.len(),
+        strategies = ?pipeline.strategy_names(),
+        overrides = pipeline.override_count(),
+        "Initialized pipeline"
+    );
+    Arc::new(pipeline)
+}
+
+/// Empty pipeline, exporting metrics if `performance.metrics_addr` is set
+fn new_pipeline(config: &Config) -> Pipeline {
+    let Some(addr) = config.performance.metrics_addr else {
+        return Pipeline::new();
+    };
 
-    Ok(domains)
+    #[cfg(feature = "metrics")]
+    match Pipeline::new_with_metrics_on(addr) {
+        Ok(pipeline) => pipeline,
+        Err(e) => {
+            warn!(%addr, "Metrics endpoint unavailable: {}", e);
+            Pipeline::new()
+        }
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    {
+        warn!(%addr, "Ignoring performance.metrics_addr, built without the metrics feature");
+        Pipeline::new()
+    }
+}
+
+/// Pipeline context for `config`, plus the `--blacklist` file
+///
+/// A `--blacklist` file turns on blacklist mode when the config leaves
+/// filtering disabled.
+fn build_context(config: &Config, blacklist: Option<&str>) -> Result<PipelineContext> {
+    let mut ctx = PipelineContext::from_config(config).context("Failed to load domain filter")?;
+
+    if let Some(path) = blacklist {
+        if ctx.filter().mode() == FilterMode::Disabled {
+            ctx.filter().set_mode(FilterMode::Blacklist);
+            ctx.blacklist_enabled = true;
+        }
+        ctx.filter()
+            .add_file(path)
+            .with_context(|| format!("Failed to read blacklist file: {}", path))?;
+    }
+
+    let filter = ctx.filter();
+    if filter.mode() != FilterMode::Disabled {
+        info!(mode = ?filter.mode(), count = filter.len(), "Loaded domain filter");
+    }
+    Ok(ctx)
+}
+
+/// How often to check the domain filter files for edits (`None` = never)
+#[cfg_attr(not(any(windows, all(target_os = "linux", feature = "nfqueue"))), allow(dead_code))]
+fn filter_reload_interval(config: &Config) -> Option<std::time::Duration> {
+    match config.blacklist.auto_reload_interval {
+        0 => None,
+        secs => Some(std::time::Duration::from_secs(secs)),
+    }
+}
+
+/// Reload the domain filter if its files changed since the last check
+#[cfg_attr(not(any(windows, all(target_os = "linux", feature = "nfqueue"))), allow(dead_code))]
+fn reload_filter(ctx: &PipelineContext) {
+    match ctx.check_filter_reload() {
+        Ok(true) => info!(count = ctx.filter().len(), "Reloaded domain filter"),
+        Ok(false) => {}
+        Err(e) => warn!("Keeping current domain filter, reload failed: {}", e),
+    }
 }
 
 fn run_packet_loop(
     config: Config,
-    pipeline: Pipeline,
-    mut ctx: PipelineContext,
+    pipeline: Arc<Pipeline>,
+    ctx: PipelineContext,
     running: Arc<AtomicBool>,
+    stats: Arc<PacketStats>,
+    #[cfg_attr(not(windows), allow(unused_variables))] config_path: Option<String>,
+    #[cfg_attr(not(all(target_os = "linux", feature = "nfqueue")), allow(unused_variables))] queue_num: u16,
+    #[cfg_attr(not(windows), allow(unused_variables))] capture_filter: CaptureFilter,
 ) -> Result<()> {
+    // Idle TCP flows expire on their own thread, even while no packets arrive
+    let _conntrack_cleanup = ctx
+        .spawn_conntrack_cleanup(&config.performance)
+        .context("Failed to start conntrack cleanup")?;
+
     #[cfg(windows)]
     {
-        use gdpi_platform::windows::{FilterPresets, WinDivertDriver, Flags};
-        use gdpi_platform::PacketCapture;
+        use gdpi_core::pipeline::WorkerPool;
+        use gdpi_platform::windows::ReloadSignal;
         use gdpi_platform::installer::{WinDivertInstaller, interactive_install};
+        use std::sync::RwLock;
 
         let installer = WinDivertInstaller::new();
         
@@ -362,72 +980,173 @@ fn run_packet_loop(
             }
         }
 
-        // Build filter
-        let filter = if config.strategies.block_quic {
-            FilterPresets::turkey_optimized()
-        } else {
-            FilterPresets::goodbyedpi_full()
+        // Written only when a reload swaps the capture filter
+        let capture = Arc::new(RwLock::new(Capture::open(&capture_filter, &config)?));
+
+        if let Capture::Single(driver) = &*read_capture(&capture) {
+            match driver.queue_stats() {
+                Ok(queue) => {
+                    info!(
+                        max_len = queue.max_len,
+                        max_size = queue.max_size,
+                        max_time_ms = queue.max_time_ms,
+                        "WinDivert queue limits"
+                    );
+                    stats.queue_max_len.store(queue.max_len, Ordering::Relaxed);
+                }
+                Err(e) => debug!("Queue limits unavailable: {}", e),
+            }
+        }
+
+        let start_time = std::time::Instant::now();
+
+        // Workers run the pipeline and re-inject; packets of one flow always
+        // go to the same worker so they leave in the order they arrived
+        let pool = {
+            let capture = Arc::clone(&capture);
+            let stats = Arc::clone(&stats);
+            WorkerPool::new(
+                config.performance.worker_threads.into(),
+                Arc::clone(&pipeline),
+                ctx.clone(),
+                move |job: CaptureJob, result| match result {
+                    Ok(output_packets) => {
+                        if output_packets.len() > 1 {
+                            stats.modified.fetch_add(1, Ordering::Relaxed);
+
+                            if let Some(ref host) = job.sni {
+                                stats.record_domain(host);
+
+                                // Log only for known blocked domains
+                                if is_blocked_domain(host) {
+                                    info!("🔓 Bypass: {} → {} packets", host, output_packets.len());
+                                }
+                            }
+                        }
+
+                        let batch: Vec<_> = output_packets
+                            .into_iter()
+                            .map(|pkt| (pkt.into_bytes(), job.address.clone()))
+                            .collect();
+                        match read_capture(&capture).send_batch(job.lane, &batch) {
+                            Ok(queued) if (queued as usize) < batch.len() => {
+                                warn!(queued, total = batch.len(), "Driver took only part of a batch");
+                            }
+                            Ok(_) => {}
+                            Err(e) => error!("Send failed: {}", e),
+                        }
+                    }
+                    Err(e) => {
+                        stats.errors.fetch_add(1, Ordering::Relaxed);
+                        debug!("Pipeline error: {}", e);
+                        let _ = read_capture(&capture).send(job.lane, &job.data, &job.address);
+                    }
+                },
+            )
+            .context("Failed to start packet workers")?
         };
 
-        info!(filter = filter, "Opening WinDivert handle");
+        info!(
+            workers = pool.threads(),
+            batch_size = read_capture(&capture).batch_size(),
+            "Packet capture started - waiting for traffic..."
+        );
+
+        // Expire idle conntrack entries periodically rather than per packet
+        let sweep_interval =
+            std::time::Duration::from_secs(config.performance.conntrack_cleanup_interval.into());
+        let mut last_sweep = start_time;
+        let reload_interval = filter_reload_interval(&config);
+        let mut last_reload = start_time;
+        let mut buffer = vec![0u8; read_capture(&capture).buffer_len()];
 
-        let mut driver = WinDivertDriver::open(&filter, Flags::default())
-            .context("Failed to open WinDivert - is the driver installed?")?;
+        // Ctrl+Break (or the named event) reloads the config file in place,
+        // keeping the driver handle unless the capture filter has to change
+        let reload_signal = match config_path {
+            Some(path) => match ReloadSignal::new() {
+                Ok(signal) => {
+                    info!(path = %path, "Press Ctrl+Break to reload the configuration");
+                    Some((signal, path))
+                }
+                Err(e) => {
+                    warn!("Config reload trigger unavailable: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
 
-        info!("Packet capture started - waiting for traffic...");
+        // Interface of the last DNS query, where DoH answers are injected
+        let mut dns_address = None;
 
-        // Statistics counters
-        let mut stats = PacketStats::default();
-        let start_time = std::time::Instant::now();
-        
         while running.load(Ordering::SeqCst) {
-            match driver.recv() {
-                Ok(captured) => {
-                    stats.total += 1;
-                    
-                    match captured.parse() {
-                        Ok(packet) => {
-                            // Extract SNI for logging blocked domains
-                            let sni = if packet.dst_port == 443 && packet.is_tls_client_hello() {
-                                packet.extract_sni()
-                            } else {
-                                None
-                            };
-                            
-                            // Process through pipeline
-                            match pipeline.process(packet, &mut ctx) {
-                                Ok(output_packets) => {
-                                    let was_modified = output_packets.len() > 1;
-                                    
-                                    if was_modified {
-                                        stats.modified += 1;
-                                        
-                                        // Log only for known blocked domains
-                                        if let Some(ref host) = sni {
-                                            if is_blocked_domain(host) {
-                                                info!("🔓 Bypass: {} → {} packets", host, output_packets.len());
-                                            }
-                                        }
-                                    }
-                                    
-                                    // Send packets
-                                    for pkt in output_packets {
-                                        if let Err(e) = driver.send(pkt.as_bytes(), &captured.address) {
-                                            error!("Send failed: {}", e);
-                                        }
-                                    }
+            if last_sweep.elapsed() >= sweep_interval {
+                let now = std::time::Instant::now();
+                let removed = ctx.sweep_conntrack(now);
+                debug!(removed, "Swept conntrack entries");
+                last_sweep = now;
+            }
+            if reload_interval.is_some_and(|interval| last_reload.elapsed() >= interval) {
+                reload_filter(&ctx);
+                last_reload = std::time::Instant::now();
+            }
+            if let Some((signal, path)) = &reload_signal {
+                if signal.triggered() {
+                    match pipeline.reload_from_file(path) {
+                        Ok(new_config) => {
+                            info!(path = %path, "Configuration reloaded");
+                            capture
+                                .write()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .swap_filter(&capture_filter, &new_config);
+                        }
+                        Err(e) => error!("Keeping current configuration, reload failed: {}", e),
+                    }
+                }
+            }
+
+            let (received, batch_size) = {
+                let capture = read_capture(&capture);
+                (capture.recv_batch(&mut buffer), capture.batch_size())
+            };
+            match received {
+                Ok(batch) => {
+                    if batch_size > 1 && batch.len() == batch_size {
+                        stats.full_batches.fetch_add(1, Ordering::Relaxed);
+                    }
+                    for (lane, captured) in batch {
+                        stats.total.fetch_add(1, Ordering::Relaxed);
+                        stats.bytes.fetch_add(captured.data.len() as u64, Ordering::Relaxed);
+
+                        match captured.parse() {
+                            Ok(packet) => {
+                                // Extract SNI for logging blocked domains
+                                let sni = if ctx.is_https_port(packet.dst_port) && packet.is_tls_client_hello() {
+                                    packet.extract_sni()
+                                } else {
+                                    None
+                                };
+
+                                if packet.is_udp() && packet.dst_port == 53 {
+                                    dns_address = Some((lane, captured.address.clone()));
                                 }
-                                Err(e) => {
-                                    stats.errors += 1;
-                                    debug!("Pipeline error: {}", e);
-                                    let _ = driver.send(&captured.data, &captured.address);
+
+                                let job = CaptureJob {
+                                    data: captured.data,
+                                    address: captured.address,
+                                    lane,
+                                    sni,
+                                };
+                                if let Err(job) = pool.dispatch(packet, job) {
+                                    error!("Packet worker stopped, re-injecting as-is");
+                                    let _ = read_capture(&capture).send(job.lane, &job.data, &job.address);
                                 }
                             }
-                        }
-                        Err(_e) => {
-                            // Re-inject as-is
-                            if let Err(e) = driver.send(&captured.data, &captured.address) {
-                                error!("Failed to re-inject raw packet: {}", e);
+                            Err(_e) => {
+                                // Re-inject as-is
+                                if let Err(e) = read_capture(&capture).send(lane, &captured.data, &captured.address) {
+                                    error!("Failed to re-inject raw packet: {}", e);
+                                }
                             }
                         }
                     }
@@ -436,24 +1155,154 @@ fn run_packet_loop(
                     debug!("Receive error: {}", e);
                 }
             }
+
+            // Answers to queries the pipeline dropped, e.g. from DNS over HTTPS
+            let injections = ctx.take_injections();
+            if !injections.is_empty() {
+                let (lane, address) = dns_address
+                    .clone()
+                    .unwrap_or_else(|| (None, gdpi_platform::PacketAddress::inbound()));
+                let address = gdpi_platform::PacketAddress {
+                    outbound: false,
+                    ..address
+                };
+                let batch: Vec<_> = injections
+                    .into_iter()
+                    .map(|pkt| (pkt.into_bytes(), address.clone()))
+                    .collect();
+                if let Err(e) = read_capture(&capture).send_batch(lane, &batch) {
+                    error!("Failed to inject queued packets: {}", e);
+                }
+            }
         }
 
+        // Let the workers drain their queues before closing the handle
+        pool.join();
+
         // Final stats
         let elapsed = start_time.elapsed();
         info!(
             "Session ended: {} packets processed, {} modified, {} errors in {:.1}s",
-            stats.total,
-            stats.modified, 
-            stats.errors,
+            stats.total.load(Ordering::Relaxed),
+            stats.modified.load(Ordering::Relaxed),
+            stats.errors.load(Ordering::Relaxed),
             elapsed.as_secs_f64()
         );
+        for (name, strategy) in ctx.get_stats().report() {
+            info!(
+                strategy = name,
+                applied = strategy.applied,
+                passed = strategy.passed,
+                replaced = strategy.replaced,
+                dropped = strategy.dropped,
+                injected = strategy.injected,
+                "Strategy stats"
+            );
+        }
+
+        if let Ok(capture) = Arc::try_unwrap(capture) {
+            capture.into_inner().unwrap_or_else(PoisonError::into_inner).close()?;
+        }
+    }
+
+    #[cfg(all(target_os = "linux", feature = "nfqueue"))]
+    {
+        use gdpi_platform::linux::{iptables_rules, NfqueueCapture, RawInjector, INJECT_MARK};
+        use gdpi_platform::PacketCapture;
+
+        let mut ctx = ctx;
+        let mut capture = NfqueueCapture::open(queue_num)
+            .context("Failed to open NFQUEUE - are you running as root?")?;
+        let injector = RawInjector::new(INJECT_MARK).context("Failed to open raw socket for injection")?;
+
+        info!("Packet capture started - route traffic to the queue with:");
+        for rule in iptables_rules(queue_num, &config) {
+            info!("  {}", rule);
+        }
+
+        let start_time = std::time::Instant::now();
+        let sweep_interval =
+            std::time::Duration::from_secs(config.performance.conntrack_cleanup_interval.into());
+        let mut last_sweep = start_time;
+        let reload_interval = filter_reload_interval(&config);
+        let mut last_reload = start_time;
+
+        while running.load(Ordering::SeqCst) {
+            if last_sweep.elapsed() >= sweep_interval {
+                let now = std::time::Instant::now();
+                let removed = ctx.sweep_conntrack(now);
+                debug!(removed, "Swept conntrack entries");
+                last_sweep = now;
+            }
+            if reload_interval.is_some_and(|interval| last_reload.elapsed() >= interval) {
+                reload_filter(&ctx);
+                last_reload = std::time::Instant::now();
+            }
+
+            let captured = match capture.recv() {
+                Ok(captured) => captured,
+                Err(e) => {
+                    debug!("Receive error: {}", e);
+                    continue;
+                }
+            };
+            stats.total.fetch_add(1, Ordering::Relaxed);
+            stats.bytes.fetch_add(captured.data.len() as u64, Ordering::Relaxed);
+
+            // Unparseable packets and pipeline failures pass through unchanged
+            let output = match captured.parse() {
+                Ok(packet) => {
+                    let sni = if packet.is_tls_client_hello() {
+                        packet.extract_sni()
+                    } else {
+                        None
+                    };
+                    match pipeline.process(packet, &mut ctx) {
+                        Ok(output_packets) => {
+                            if output_packets.len() > 1 {
+                                stats.modified.fetch_add(1, Ordering::Relaxed);
+                                if let Some(ref host) = sni {
+                                    stats.record_domain(host);
+                                }
+                            }
+                            output_packets.iter().map(|pkt| pkt.as_bytes().to_vec()).collect()
+                        }
+                        Err(e) => {
+                            stats.errors.fetch_add(1, Ordering::Relaxed);
+                            debug!("Pipeline error: {}", e);
+                            vec![captured.data.clone()]
+                        }
+                    }
+                }
+                Err(_) => vec![captured.data.clone()],
+            };
+
+            if let Err(e) = capture.verdict(&captured.address, &output) {
+                error!("Verdict failed: {}", e);
+            }
+
+            // Answers to queries the pipeline dropped, e.g. from DNS over HTTPS
+            for packet in ctx.take_injections() {
+                if let Err(e) = injector.send(packet.as_bytes()) {
+                    error!("Failed to inject queued packet: {}", e);
+                }
+            }
+        }
+
+        info!(
+            "Session ended: {} packets processed, {} modified, {} errors in {:.1}s",
+            stats.total.load(Ordering::Relaxed),
+            stats.modified.load(Ordering::Relaxed),
+            stats.errors.load(Ordering::Relaxed),
+            start_time.elapsed().as_secs_f64()
+        );
 
-        driver.close()?;
+        capture.close()?;
     }
 
-    #[cfg(not(windows))]
+    #[cfg(not(any(windows, all(target_os = "linux", feature = "nfqueue"))))]
     {
-        warn!("Packet capture is only supported on Windows");
+        warn!("Packet capture is only supported on Windows, or Linux with the nfqueue feature");
         warn!("This build can be used for testing configuration only");
         
         // Just wait for interrupt
@@ -476,10 +1325,13 @@ mod tests {
         let path = temp_dir.path().join("blacklist.txt");
         std::fs::write(&path, content).unwrap();
 
-        let domains = load_blacklist(path.to_str().unwrap()).unwrap();
-        assert_eq!(domains.len(), 3);
-        assert!(domains.contains(&"example.com".to_string()));
-        assert!(domains.contains(&"test.org".to_string()));
-        assert!(domains.contains(&"foo.bar".to_string()));
+        let ctx = build_context(&Config::default(), path.to_str()).unwrap();
+        assert!(ctx.blacklist_enabled);
+        let filter = ctx.filter();
+        assert_eq!(filter.mode(), FilterMode::Blacklist);
+        assert_eq!(filter.len(), 3);
+        assert!(filter.matches("example.com"));
+        assert!(filter.matches("test.org"));
+        assert!(filter.matches("foo.bar"));
     }
 }
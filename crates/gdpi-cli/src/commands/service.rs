@@ -1,7 +1,9 @@
 //! Service command - Windows service management
 
-use anyhow::{Context, Result};
-use clap::{Args, Subcommand};
+use anyhow::Result;
+use clap::{ArgGroup, Args, Subcommand};
+
+use super::run::RunArgs;
 
 /// Service command arguments
 #[derive(Args, Debug)]
@@ -23,7 +25,8 @@ pub enum ServiceAction {
         #[arg(short, long)]
         config: Option<String>,
 
-        /// Start automatically on boot
+        /// Start automatically on boot (default: the config's
+        /// `general.auto_start`)
         #[arg(long)]
         auto_start: bool,
     },
@@ -32,7 +35,21 @@ pub enum ServiceAction {
     Uninstall,
 
     /// Start the service
-    Start,
+    #[command(group(ArgGroup::new("settings").args(["profile", "config"])))]
+    Start {
+        /// Install the service with this profile first, or switch the
+        /// installed service to it
+        #[arg(short, long)]
+        profile: Option<String>,
+
+        /// Like --profile, with a config file
+        #[arg(short, long)]
+        config: Option<String>,
+
+        /// Start automatically on boot (with --profile or --config)
+        #[arg(long, requires = "settings")]
+        auto_start: bool,
+    },
 
     /// Stop the service
     Stop,
@@ -42,11 +59,11 @@ pub enum ServiceAction {
 
     /// Check service status
     Status,
-}
 
-const SERVICE_NAME: &str = "GoodbyeDPI";
-const SERVICE_DISPLAY_NAME: &str = "GoodbyeDPI Turkey";
-const SERVICE_DESCRIPTION: &str = "Deep Packet Inspection bypass service for Turkey";
+    /// Run the bypass as the service (started by the service manager)
+    #[command(hide = true)]
+    Run(RunArgs),
+}
 
 /// Execute service command
 pub fn execute(args: ServiceArgs) -> Result<()> {
@@ -57,16 +74,24 @@ pub fn execute(args: ServiceArgs) -> Result<()> {
                 install_service(&profile, config.as_deref(), auto_start)
             }
             ServiceAction::Uninstall => uninstall_service(),
-            ServiceAction::Start => start_service(),
+            ServiceAction::Start { profile, config, auto_start } => {
+                if profile.is_some() || config.is_some() {
+                    let profile = profile.as_deref().unwrap_or("turkey");
+                    configure_service(profile, config.as_deref(), auto_start)?;
+                }
+                start_service()
+            }
             ServiceAction::Stop => stop_service(),
             ServiceAction::Restart => restart_service(),
             ServiceAction::Status => service_status(),
+            ServiceAction::Run(run_args) => run_as_service(run_args),
         }
     }
 
     #[cfg(not(windows))]
     {
         use colored::Colorize;
+        let _ = args;
         println!("{}", "Service management is only available on Windows.".yellow());
         println!();
         println!("On Linux, you can create a systemd service manually:");
@@ -77,56 +102,91 @@ pub fn execute(args: ServiceArgs) -> Result<()> {
     }
 }
 
+/// Command line the service manager starts the service with
+///
+/// The service has no console, so it logs to `goodbyedpi-service.log`
+/// next to the executable.
+#[cfg(windows)]
+fn launch_arguments(profile: &str, config: Option<&str>) -> Result<Vec<std::ffi::OsString>> {
+    use anyhow::Context;
+
+    let exe_path = std::env::current_exe()
+        .context("Failed to get executable path")?;
+    let log_file = exe_path.with_file_name("goodbyedpi-service.log");
+
+    let mut args = vec![
+        "--log-file".into(),
+        log_file.into_os_string(),
+        "service".into(),
+        "run".into(),
+    ];
+    if let Some(cfg) = config {
+        // Services start in System32, so the path must be absolute
+        let path = std::fs::canonicalize(cfg)
+            .with_context(|| format!("Config file not found: {}", cfg))?;
+        args.push("--config".into());
+        args.push(path.into_os_string());
+    } else {
+        args.push("--profile".into());
+        args.push(profile.into());
+    }
+    Ok(args)
+}
+
 #[cfg(windows)]
 fn install_service(profile: &str, config: Option<&str>, auto_start: bool) -> Result<()> {
+    use super::overrides::{load_config, ConfigOverrides};
+    use anyhow::Context;
     use colored::Colorize;
 
-    println!("Installing {} service...", SERVICE_NAME.cyan());
+    println!("Installing {} service...", gdpi_service::service::SERVICE_NAME.cyan());
+
+    // Check the configuration now rather than when the service starts
+    let loaded = load_config(&ConfigOverrides {
+        profile: Some(profile.to_string()),
+        config: config.map(str::to_string),
+        ..Default::default()
+    })?;
+    let auto_start = auto_start || loaded.general.auto_start;
 
-    // Get current executable path
     let exe_path = std::env::current_exe()
         .context("Failed to get executable path")?;
+    let args = launch_arguments(profile, config)?;
 
-    // Build command line arguments
-    let mut args = vec!["run".to_string()];
-    
-    if let Some(cfg) = config {
-        args.push("--config".to_string());
-        args.push(cfg.to_string());
-    } else {
-        args.push("--profile".to_string());
-        args.push(profile.to_string());
-    }
+    gdpi_service::install_service(&exe_path, &args, auto_start)?;
 
-    // For now, just print what would be done
     println!("  Executable: {}", exe_path.display());
     println!("  Arguments: {:?}", args);
     println!("  Auto-start: {}", auto_start);
-    
-    // Actual service installation would use Windows Service API
-    // sc create GoodbyeDPI binPath= "..." start= auto
-    
     println!();
-    println!("{}", "Service installation would require elevated privileges.".yellow());
-    println!("Run as Administrator to actually install the service.");
-
+    println!("{} Run `goodbyedpi service start` to start it.", "Installed.".green());
     Ok(())
 }
 
+/// Install the service with `profile` (or `config`), or switch the
+/// installed one to it
 #[cfg(windows)]
-fn uninstall_service() -> Result<()> {
-    use colored::Colorize;
+fn configure_service(profile: &str, config: Option<&str>, auto_start: bool) -> Result<()> {
+    use anyhow::Context;
 
-    println!("Uninstalling {} service...", SERVICE_NAME.cyan());
-    
-    // Stop service first
-    let _ = stop_service();
+    let exe_path = std::env::current_exe()
+        .context("Failed to get executable path")?;
+    let args = launch_arguments(profile, config)?;
 
-    // sc delete GoodbyeDPI
-    
-    println!();
-    println!("{}", "Service uninstallation would require elevated privileges.".yellow());
+    if gdpi_service::service_state()?.is_some() {
+        gdpi_service::update_service(&exe_path, &args, auto_start)
+    } else {
+        gdpi_service::install_service(&exe_path, &args, auto_start)
+    }
+}
+
+#[cfg(windows)]
+fn uninstall_service() -> Result<()> {
+    use colored::Colorize;
 
+    println!("Uninstalling {} service...", gdpi_service::service::SERVICE_NAME.cyan());
+    gdpi_service::uninstall_service()?;
+    println!("{}", "Uninstalled.".green());
     Ok(())
 }
 
@@ -134,12 +194,9 @@ fn uninstall_service() -> Result<()> {
 fn start_service() -> Result<()> {
     use colored::Colorize;
 
-    println!("Starting {} service...", SERVICE_NAME.cyan());
-    
-    // net start GoodbyeDPI
-    
-    println!("{}", "Service start would require elevated privileges.".yellow());
-
+    println!("Starting {} service...", gdpi_service::service::SERVICE_NAME.cyan());
+    gdpi_service::start_service()?;
+    println!("{}", "Running.".green());
     Ok(())
 }
 
@@ -147,39 +204,49 @@ fn start_service() -> Result<()> {
 fn stop_service() -> Result<()> {
     use colored::Colorize;
 
-    println!("Stopping {} service...", SERVICE_NAME.cyan());
-    
-    // net stop GoodbyeDPI
-    
-    println!("{}", "Service stop would require elevated privileges.".yellow());
-
+    println!("Stopping {} service...", gdpi_service::service::SERVICE_NAME.cyan());
+    gdpi_service::stop_service()?;
+    println!("{}", "Stopped.".green());
     Ok(())
 }
 
 #[cfg(windows)]
 fn restart_service() -> Result<()> {
     stop_service()?;
-    std::thread::sleep(std::time::Duration::from_secs(2));
     start_service()
 }
 
 #[cfg(windows)]
 fn service_status() -> Result<()> {
     use colored::Colorize;
+    use gdpi_service::service::{SERVICE_DESCRIPTION, SERVICE_DISPLAY_NAME, SERVICE_NAME};
+    use gdpi_service::State;
 
     println!("{} Service Status", SERVICE_NAME.cyan().bold());
     println!();
-
-    // Query service status using sc query
-    // For now, just check if process is running
-    
     println!("  Name: {}", SERVICE_NAME);
     println!("  Display Name: {}", SERVICE_DISPLAY_NAME);
     println!("  Description: {}", SERVICE_DESCRIPTION);
     println!();
-    println!("  Status: {}", "Unknown".yellow());
-    println!();
-    println!("{}", "Full status check requires elevated privileges.".yellow());
 
+    let status = match gdpi_service::service_state()? {
+        None => "Not installed".yellow(),
+        Some(State::Running) => "Running".green(),
+        Some(State::Stopped) => "Stopped".red(),
+        Some(state) => format!("{:?}", state).yellow(),
+    };
+    println!("  Status: {}", status);
     Ok(())
 }
+
+/// Service body: the packet loop, stopped by the service manager instead
+/// of Ctrl+C
+///
+/// The loop checks its stop flag at least every `RECV_POLL`, so a stop
+/// request needs no traffic to wake it.
+#[cfg(windows)]
+fn run_as_service(args: RunArgs) -> Result<()> {
+    use super::run::Session;
+
+    gdpi_service::run_service(move |running| Session::start_with(args, running)?.run())
+}
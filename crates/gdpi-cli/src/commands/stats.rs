@@ -0,0 +1,168 @@
+//! Stats command - run the bypass with a live counter line
+
+use anyhow::{Context, Result};
+use clap::Args;
+use colored::Colorize;
+use crossterm::queue;
+use crossterm::terminal::{self, Clear, ClearType};
+use std::io::{stdout, Write};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use super::run::{RunArgs, Session, SessionHandle};
+
+/// Default refresh interval for `stats` and `run --stats`
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Stats command arguments
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// Refresh interval in milliseconds
+    #[arg(long, default_value_t = DEFAULT_INTERVAL.as_millis() as u64)]
+    pub interval_ms: u64,
+
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+/// Counters shown on the stats line
+#[derive(Debug, Default)]
+struct Counters {
+    packets: u64,
+    modified: u64,
+    fragmented: u64,
+    fake_packets: u64,
+    /// Receives that found more packets waiting than one batch holds
+    full_batches: u64,
+    /// Strategy hits, most applied first
+    hits: Vec<(&'static str, u64)>,
+}
+
+/// Execute the stats command
+pub fn execute(args: StatsArgs) -> Result<()> {
+    let session = Session::start(args.run)?;
+    run_with_stats(session, Duration::from_millis(args.interval_ms.max(50)))
+}
+
+/// Run a session, redrawing the counter line every `interval` until it stops
+pub fn run_with_stats(session: Session, interval: Duration) -> Result<()> {
+    let handle = session.handle();
+    let worker = std::thread::Builder::new()
+        .name("gdpi-session".into())
+        .spawn(move || session.run())
+        .context("Failed to start packet loop")?;
+
+    let mut out = stdout();
+    while handle.running.load(Ordering::SeqCst) {
+        std::thread::sleep(interval);
+
+        let width = terminal::size().map_or(80, |(cols, _)| usize::from(cols));
+        write!(out, "\r{}", render(&counters(&handle), width))?;
+        queue!(out, Clear(ClearType::UntilNewLine))?;
+        out.flush()?;
+    }
+    // Leave the last line in place
+    writeln!(out)?;
+
+    worker
+        .join()
+        .map_err(|_| anyhow::anyhow!("Packet loop panicked"))?
+}
+
+fn counters(handle: &SessionHandle) -> Counters {
+    // Lock-free snapshot of the shared counters; the workers keep running
+    let stats = handle.ctx.get_stats();
+    Counters {
+        packets: handle.stats.total.load(Ordering::Relaxed),
+        modified: handle.stats.modified.load(Ordering::Relaxed),
+        fragmented: stats.packets_fragmented,
+        fake_packets: stats.fake_packets_sent,
+        full_batches: handle.stats.full_batches.load(Ordering::Relaxed),
+        hits: stats
+            .report()
+            .into_iter()
+            .map(|(name, strategy)| (name, strategy.applied))
+            .collect(),
+    }
+}
+
+/// One line of counters, dropping strategy hits that don't fit in `width`
+fn render(counters: &Counters, width: usize) -> String {
+    let mut totals = vec![
+        ("packets", counters.packets),
+        ("modified", counters.modified),
+        ("fragmented", counters.fragmented),
+        ("fakes", counters.fake_packets),
+    ];
+    // Only worth a column once the capture queue starts backing up
+    if counters.full_batches > 0 {
+        totals.push(("full batches", counters.full_batches));
+    }
+
+    let mut line = String::new();
+    let mut len = 0;
+    for (i, (label, value)) in totals.iter().enumerate() {
+        let value = value.to_string();
+        if i > 0 {
+            line.push_str(" │ ");
+            len += 3;
+        }
+        line.push_str(&format!("{} {}", label.dimmed(), value.green().bold()));
+        len += label.len() + 1 + value.len();
+    }
+
+    for (name, hits) in &counters.hits {
+        let hits = hits.to_string();
+        let segment_len = 3 + name.len() + 1 + hits.len();
+        // Keep the cursor off the last column so the line doesn't wrap
+        if len + segment_len >= width {
+            break;
+        }
+        line.push_str(&format!(" │ {} {}", name.cyan(), hits.bold()));
+        len += segment_len;
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_fits_width() {
+        colored::control::set_override(false);
+        let counters = Counters {
+            packets: 120,
+            modified: 7,
+            fragmented: 5,
+            fake_packets: 14,
+            full_batches: 0,
+            hits: vec![("fake_packet", 7), ("fragmentation", 5)],
+        };
+
+        let line = render(&counters, 200);
+        assert_eq!(
+            line,
+            "packets 120 │ modified 7 │ fragmented 5 │ fakes 14 │ fake_packet 7 │ fragmentation 5"
+        );
+
+        // Hits that would wrap are left out
+        let line = render(&counters, 70);
+        assert!(line.ends_with("fake_packet 7"));
+        assert!(line.chars().count() < 70);
+    }
+
+    #[test]
+    fn test_render_full_batches() {
+        colored::control::set_override(false);
+        let counters = Counters {
+            packets: 120,
+            full_batches: 3,
+            ..Counters::default()
+        };
+
+        let line = render(&counters, 200);
+        assert_eq!(line, "packets 120 │ modified 0 │ fragmented 0 │ fakes 0 │ full batches 3");
+    }
+}
@@ -1,10 +1,29 @@
 //! Test command - connectivity testing
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Args, Subcommand};
-use std::net::ToSocketAddrs;
+use gdpi_core::config::{Config, Profile};
+use gdpi_core::packet::ClientHelloBuilder;
+use serde::Serialize;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
+/// Profiles `test tune` tries, in the order they are probed
+const TUNE_PROFILES: [Profile; 10] = [
+    Profile::Mode1,
+    Profile::Mode2,
+    Profile::Mode3,
+    Profile::Mode4,
+    Profile::Mode5,
+    Profile::Mode6,
+    Profile::Mode7,
+    Profile::Mode8,
+    Profile::Mode9,
+    Profile::Turkey,
+];
+
 /// Test command arguments
 #[derive(Args, Debug)]
 pub struct TestArgs {
@@ -12,6 +31,16 @@ pub struct TestArgs {
     pub action: TestAction,
 }
 
+impl TestArgs {
+    /// Whether the results are printed as JSON
+    pub fn json(&self) -> bool {
+        match self.action {
+            TestAction::Url { json, .. } | TestAction::Dns { json, .. } | TestAction::All { json, .. } => json,
+            TestAction::Driver | TestAction::Tune { .. } => false,
+        }
+    }
+}
+
 /// Test subcommands
 #[derive(Subcommand, Debug)]
 pub enum TestAction {
@@ -23,6 +52,14 @@ pub enum TestAction {
         /// Timeout in seconds
         #[arg(short, long, default_value = "10")]
         timeout: u64,
+
+        /// Only open a TCP connection, without a TLS handshake
+        #[arg(long)]
+        tcp_only: bool,
+
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
     },
 
     /// Test DNS resolution
@@ -33,6 +70,10 @@ pub enum TestAction {
         /// DNS server to use (default: system)
         #[arg(short, long)]
         server: Option<String>,
+
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
     },
 
     /// Test all blocked sites
@@ -40,109 +81,340 @@ pub enum TestAction {
         /// Timeout per site in seconds
         #[arg(short, long, default_value = "5")]
         timeout: u64,
+
+        /// Print the results as JSON once every site was probed
+        #[arg(long)]
+        json: bool,
     },
 
     /// Check WinDivert driver status
     Driver,
+
+    /// Try every mode against blocked sites and rank them (Windows)
+    Tune {
+        /// Sites to probe with a TLS handshake
+        #[arg(short, long, value_delimiter = ',', default_value = "discord.com,youtube.com")]
+        domains: Vec<String>,
+
+        /// Handshakes per site and mode
+        #[arg(short, long, default_value = "2")]
+        attempts: u32,
+
+        /// Timeout per handshake in seconds
+        #[arg(short, long, default_value = "5")]
+        timeout: u64,
+
+        /// Time budget per mode in seconds; remaining probes count as failed
+        #[arg(long, default_value = "30")]
+        mode_timeout: u64,
+
+        /// Write the best mode to the config file
+        #[arg(long)]
+        apply: bool,
+
+        /// Config file written by --apply
+        #[arg(short, long, default_value = "config.toml")]
+        output: PathBuf,
+    },
 }
 
 /// Execute test command
 pub fn execute(args: TestArgs) -> Result<()> {
+    let sink = if args.json() { Sink::Json } else { Sink::Text };
     match args.action {
-        TestAction::Url { url, timeout } => test_url(&url, timeout),
-        TestAction::Dns { domain, server } => test_dns(&domain, server),
-        TestAction::All { timeout } => test_all(timeout),
+        TestAction::Url { url, timeout, tcp_only, .. } => test_url(&url, timeout, tcp_only, sink),
+        TestAction::Dns { domain, server, .. } => test_dns(&domain, server, sink),
+        TestAction::All { timeout, .. } => test_all(timeout, sink),
         TestAction::Driver => test_driver(),
+        TestAction::Tune {
+            domains,
+            attempts,
+            timeout,
+            mode_timeout,
+            apply,
+            output,
+        } => tune(
+            &domains,
+            attempts.max(1),
+            Duration::from_secs(timeout),
+            Duration::from_secs(mode_timeout),
+            apply.then_some(output.as_path()),
+        ),
     }
 }
 
-fn test_url(url: &str, timeout_secs: u64) -> Result<()> {
-    use colored::Colorize;
+/// Where test results go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sink {
+    /// Colored text for people
+    Text,
+    /// One JSON document for scripts
+    Json,
+}
 
-    println!("Testing connection to: {}", url.cyan());
-    
+impl Sink {
+    fn emit<T: Serialize>(self, report: &T, render: impl FnOnce(&T) -> String) -> Result<()> {
+        match self {
+            Sink::Text => print!("{}", render(report)),
+            Sink::Json => println!("{}", serde_json::to_string_pretty(report)?),
+        }
+        Ok(())
+    }
+}
+
+/// How probing a site ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Ok,
+    DnsFail,
+    NoAddr,
+    ConnectFail,
+    /// No answer to the SYN or the ClientHello in time
+    Timeout,
+    /// TCP connected, then the connection was reset after the ClientHello
+    TcpOkTlsReset,
+    /// The server answered the ClientHello with something else, e.g. an alert
+    TlsAlert,
+    /// A ServerHello came back
+    TlsOk,
+}
+
+/// How far a probe goes after resolving
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Depth {
+    Resolve,
+    /// Open a TCP connection within the timeout
+    Tcp(Duration),
+    /// Also send a ClientHello and wait for the ServerHello
+    Tls(Duration),
+}
+
+/// Result of resolving and connecting to one site
+#[derive(Debug, Serialize)]
+struct SiteResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'static str>,
+    host: String,
+    addresses: Vec<IpAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolve_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connect_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    handshake_ms: Option<u64>,
+    outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Results of `test all`
+#[derive(Debug, Serialize)]
+struct AllReport {
+    sites: Vec<SiteResult>,
+    passed: usize,
+    failed: usize,
+}
+
+/// Resolve `target`, then go as far as `depth` with its first address
+fn probe(host: &str, target: &str, depth: Depth) -> SiteResult {
     let start = Instant::now();
-    let timeout = Duration::from_secs(timeout_secs);
+    let resolved = target.to_socket_addrs().map(Iterator::collect);
+    probe_resolved(host, resolved, start.elapsed(), depth)
+}
+
+fn probe_resolved(
+    host: &str,
+    resolved: io::Result<Vec<SocketAddr>>,
+    resolve_time: Duration,
+    depth: Depth,
+) -> SiteResult {
+    let mut result = SiteResult {
+        name: None,
+        host: host.to_string(),
+        addresses: Vec::new(),
+        resolve_ms: None,
+        connect_ms: None,
+        handshake_ms: None,
+        outcome: Outcome::Ok,
+        error: None,
+    };
+
+    let addrs = match resolved {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            result.outcome = Outcome::DnsFail;
+            result.error = Some(e.to_string());
+            return result;
+        }
+    };
+    result.resolve_ms = Some(millis(resolve_time));
+    result.addresses = addrs.iter().map(SocketAddr::ip).collect();
+
+    let timeout = match depth {
+        Depth::Resolve => return result,
+        Depth::Tcp(timeout) | Depth::Tls(timeout) => timeout,
+    };
+    let Some(addr) = addrs.first() else {
+        result.outcome = Outcome::NoAddr;
+        return result;
+    };
+    let start = Instant::now();
+    let mut stream = match TcpStream::connect_timeout(addr, timeout) {
+        Ok(stream) => stream,
+        Err(e) => {
+            result.outcome = if is_timeout(&e) { Outcome::Timeout } else { Outcome::ConnectFail };
+            result.error = Some(e.to_string());
+            return result;
+        }
+    };
+    result.connect_ms = Some(millis(start.elapsed()));
+    if depth == Depth::Tcp(timeout) {
+        return result;
+    }
+
+    let sni = host.rsplit_once(':').map_or(host, |(name, _)| name);
+    let start = Instant::now();
+    let answer = server_hello_head(&mut stream, sni, timeout);
+    let (outcome, error) = tls_outcome(answer);
+    if outcome == Outcome::TlsOk {
+        result.handshake_ms = Some(millis(start.elapsed()));
+    }
+    result.outcome = outcome;
+    result.error = error;
+    result
+}
+
+/// Send a ClientHello for `sni` and read the start of the answer
+fn server_hello_head(stream: &mut TcpStream, sni: &str, timeout: Duration) -> io::Result<[u8; 6]> {
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.write_all(&ClientHelloBuilder::new(sni).build())?;
+
+    // Record header plus handshake type
+    let mut head = [0u8; 6];
+    stream.read_exact(&mut head)?;
+    Ok(head)
+}
+
+/// Outcome of a handshake, given how the first answer record began
+fn tls_outcome(answer: io::Result<[u8; 6]>) -> (Outcome, Option<String>) {
+    match answer {
+        Ok(head) if is_server_hello(&head) => (Outcome::TlsOk, None),
+        Ok(_) => (Outcome::TlsAlert, Some("answered without a ServerHello".into())),
+        Err(e) if is_timeout(&e) => (Outcome::Timeout, Some(e.to_string())),
+        Err(e) => (Outcome::TcpOkTlsReset, Some(e.to_string())),
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
 
+fn test_url(url: &str, timeout_secs: u64, tcp_only: bool, sink: Sink) -> Result<()> {
     // Parse URL
     let parsed_url = if url.starts_with("http://") || url.starts_with("https://") {
         url.to_string()
     } else {
         format!("https://{}", url)
     };
-
-    // Simple TCP connection test
     let host_port = extract_host_port(&parsed_url)?;
-    
-    println!("  Resolving {}...", host_port);
-    
-    match host_port.to_socket_addrs() {
-        Ok(addrs) => {
-            let addrs: Vec<_> = addrs.collect();
-            println!("  {} Resolved to {} address(es)", "✓".green(), addrs.len());
-            
-            for addr in &addrs {
-                println!("    {}", addr);
-            }
 
-            // Try to connect
-            println!("  Attempting TCP connection...");
-            
-            match std::net::TcpStream::connect_timeout(&addrs[0], timeout) {
-                Ok(_) => {
-                    let elapsed = start.elapsed();
-                    println!("  {} Connected in {:?}", "✓".green(), elapsed);
-                    println!();
-                    println!("{}", "Connection successful!".green().bold());
-                }
-                Err(e) => {
-                    println!("  {} Connection failed: {}", "✗".red(), e);
-                    println!();
-                    println!("{}", "Connection failed - site may be blocked".red().bold());
-                }
-            }
-        }
-        Err(e) => {
-            println!("  {} DNS resolution failed: {}", "✗".red(), e);
-            println!();
-            println!("{}", "DNS resolution failed - check DNS settings".red().bold());
-        }
+    let timeout = Duration::from_secs(timeout_secs);
+    let depth = if tcp_only { Depth::Tcp(timeout) } else { Depth::Tls(timeout) };
+    let result = probe(&host_port, &host_port, depth);
+    sink.emit(&result, |result| render_url(url, result))
+}
+
+fn render_url(url: &str, result: &SiteResult) -> String {
+    use colored::Colorize;
+
+    let mut out = format!("Testing connection to: {}\n", url.cyan());
+    out.push_str(&format!("  Resolving {}...\n", result.host));
+    if result.outcome == Outcome::DnsFail {
+        out.push_str(&format!(
+            "  {} DNS resolution failed: {}\n\n{}\n",
+            "✗".red(),
+            result.error.as_deref().unwrap_or_default(),
+            "DNS resolution failed - check DNS settings".red().bold()
+        ));
+        return out;
     }
 
-    Ok(())
+    out.push_str(&format!("  {} Resolved to {} address(es)\n", "✓".green(), result.addresses.len()));
+    for addr in &result.addresses {
+        out.push_str(&format!("    {}\n", addr));
+    }
+    out.push_str("  Attempting TCP connection...\n");
+    let Some(connect_ms) = result.connect_ms else {
+        out.push_str(&format!(
+            "  {} Connection failed: {}\n\n{}\n",
+            "✗".red(),
+            result.error.as_deref().unwrap_or("no address"),
+            "Connection failed - site may be blocked".red().bold()
+        ));
+        return out;
+    };
+    out.push_str(&format!("  {} Connected in {}ms\n", "✓".green(), connect_ms));
+    if result.outcome == Outcome::Ok {
+        out.push_str(&format!("\n{}\n", "Connection successful!".green().bold()));
+        return out;
+    }
+
+    out.push_str("  Sending TLS ClientHello...\n");
+    let error = result.error.as_deref().unwrap_or_default();
+    let (step, verdict) = match (result.outcome, result.handshake_ms) {
+        (Outcome::TlsOk, Some(ms)) => (
+            format!("  {} ServerHello in {}ms", "✓".green(), ms),
+            "TLS handshake successful!".green().bold(),
+        ),
+        (Outcome::TcpOkTlsReset, _) => (
+            format!("  {} Connection reset after ClientHello: {}", "✗".red(), error),
+            "Handshake reset - DPI is blocking this site".red().bold(),
+        ),
+        (Outcome::Timeout, _) => (
+            format!("  {} No answer to ClientHello: {}", "✗".red(), error),
+            "Handshake timed out - site may be blocked".red().bold(),
+        ),
+        _ => (
+            format!("  {} Server {}", "!".yellow(), error),
+            "TLS handshake failed".yellow().bold(),
+        ),
+    };
+    out.push_str(&format!("{}\n\n{}\n", step, verdict));
+    out
 }
 
-fn test_dns(domain: &str, _server: Option<String>) -> Result<()> {
+fn test_dns(domain: &str, _server: Option<String>, sink: Sink) -> Result<()> {
+    let result = probe(domain, &format!("{}:80", domain), Depth::Resolve);
+    sink.emit(&result, render_dns)
+}
+
+fn render_dns(result: &SiteResult) -> String {
     use colored::Colorize;
 
-    println!("Testing DNS resolution for: {}", domain.cyan());
-    
-    let start = Instant::now();
-    let lookup = format!("{}:80", domain);
-
-    match lookup.to_socket_addrs() {
-        Ok(addrs) => {
-            let elapsed = start.elapsed();
-            let addrs: Vec<_> = addrs.collect();
-            
-            println!();
-            println!("{} Resolved in {:?}", "✓".green(), elapsed);
-            println!();
-            println!("Addresses:");
-            for addr in &addrs {
-                println!("  {}", addr.ip());
+    let mut out = format!("Testing DNS resolution for: {}\n\n", result.host.cyan());
+    match result.resolve_ms {
+        Some(ms) => {
+            out.push_str(&format!("{} Resolved in {}ms\n\nAddresses:\n", "✓".green(), ms));
+            for addr in &result.addresses {
+                out.push_str(&format!("  {}\n", addr));
             }
         }
-        Err(e) => {
-            println!();
-            println!("{} Resolution failed: {}", "✗".red(), e);
-        }
+        None => out.push_str(&format!(
+            "{} Resolution failed: {}\n",
+            "✗".red(),
+            result.error.as_deref().unwrap_or_default()
+        )),
     }
-
-    Ok(())
+    out
 }
 
-fn test_all(timeout_secs: u64) -> Result<()> {
+fn test_all(timeout_secs: u64, sink: Sink) -> Result<()> {
     use colored::Colorize;
 
     let test_sites = [
@@ -155,58 +427,68 @@ fn test_all(timeout_secs: u64) -> Result<()> {
         ("Medium", "medium.com"),
     ];
 
-    println!("{}", "Testing commonly blocked sites...".cyan().bold());
-    println!();
-
-    let mut success_count = 0;
-    let mut fail_count = 0;
+    if sink == Sink::Text {
+        println!("{}", "Testing commonly blocked sites...".cyan().bold());
+        println!();
+    }
 
+    // Text output shows each site as soon as it was probed
+    let timeout = Duration::from_secs(timeout_secs);
+    let mut sites = Vec::with_capacity(test_sites.len());
     for (name, domain) in test_sites {
-        print!("  {} ({})... ", name, domain);
-
-        let host_port = format!("{}:443", domain);
-        let start = Instant::now();
-        
-        match host_port.to_socket_addrs() {
-            Ok(mut addrs) => {
-                if let Some(addr) = addrs.next() {
-                    let timeout = Duration::from_secs(timeout_secs);
-                    match std::net::TcpStream::connect_timeout(&addr, timeout) {
-                        Ok(_) => {
-                            let elapsed = start.elapsed();
-                            println!("{} ({:?})", "OK".green(), elapsed);
-                            success_count += 1;
-                        }
-                        Err(_) => {
-                            println!("{}", "BLOCKED".red());
-                            fail_count += 1;
-                        }
-                    }
-                } else {
-                    println!("{}", "NO ADDR".yellow());
-                    fail_count += 1;
-                }
-            }
-            Err(_) => {
-                println!("{}", "DNS FAIL".red());
-                fail_count += 1;
-            }
+        if sink == Sink::Text {
+            print!("  {} ({})... ", name, domain);
+            io::stdout().flush()?;
+        }
+        let site = SiteResult {
+            name: Some(name),
+            ..probe(domain, &format!("{}:443", domain), Depth::Tcp(timeout))
+        };
+        if sink == Sink::Text {
+            println!("{}", site_status(&site));
         }
+        sites.push(site);
     }
+    let passed = sites.iter().filter(|site| site.outcome == Outcome::Ok).count();
+    let report = AllReport {
+        failed: sites.len() - passed,
+        passed,
+        sites,
+    };
 
-    println!();
-    println!("Results: {} passed, {} failed", 
-        success_count.to_string().green(),
-        fail_count.to_string().red()
-    );
+    sink.emit(&report, render_all)
+}
 
-    if fail_count > 0 {
-        println!();
-        println!("{}", "Some sites appear to be blocked.".yellow());
-        println!("Run GoodbyeDPI with: goodbyedpi run --turkey");
+/// Status shown after a site's name in `test all`
+fn site_status(site: &SiteResult) -> String {
+    use colored::Colorize;
+
+    match (site.outcome, site.connect_ms) {
+        (Outcome::Ok, Some(ms)) => format!("{} ({}ms)", "OK".green(), ms),
+        (Outcome::Ok, None) => "OK".green().to_string(),
+        (Outcome::DnsFail, _) => "DNS FAIL".red().to_string(),
+        (Outcome::NoAddr, _) => "NO ADDR".yellow().to_string(),
+        (Outcome::ConnectFail | Outcome::TcpOkTlsReset, _) => "BLOCKED".red().to_string(),
+        (Outcome::Timeout, _) => "TIMEOUT".red().to_string(),
+        (Outcome::TlsAlert, _) => "TLS ALERT".yellow().to_string(),
+        (Outcome::TlsOk, _) => "OK".green().to_string(),
     }
+}
 
-    Ok(())
+/// Summary after the per-site lines of `test all`
+fn render_all(report: &AllReport) -> String {
+    use colored::Colorize;
+
+    let mut out = format!(
+        "\nResults: {} passed, {} failed\n",
+        report.passed.to_string().green(),
+        report.failed.to_string().red()
+    );
+    if report.failed > 0 {
+        out.push_str(&format!("\n{}\n", "Some sites appear to be blocked.".yellow()));
+        out.push_str("Run GoodbyeDPI with: goodbyedpi run --turkey\n");
+    }
+    out
 }
 
 fn test_driver() -> Result<()> {
@@ -268,6 +550,216 @@ fn test_driver() -> Result<()> {
     Ok(())
 }
 
+/// Probe results for one mode
+#[derive(Debug)]
+struct ModeResult {
+    profile: Profile,
+    attempts: u32,
+    /// Handshake time of every successful probe
+    latencies: Vec<Duration>,
+}
+
+impl ModeResult {
+    fn success_rate(&self) -> f64 {
+        if self.attempts == 0 {
+            return 0.0;
+        }
+        self.latencies.len() as f64 / f64::from(self.attempts)
+    }
+
+    fn mean_latency(&self) -> Option<Duration> {
+        let count = u32::try_from(self.latencies.len()).ok().filter(|&n| n > 0)?;
+        Some(self.latencies.iter().sum::<Duration>() / count)
+    }
+}
+
+fn tune(
+    domains: &[String],
+    attempts: u32,
+    timeout: Duration,
+    mode_timeout: Duration,
+    apply: Option<&Path>,
+) -> Result<()> {
+    use colored::Colorize;
+
+    if cfg!(not(windows)) {
+        println!("  {} Mode tuning needs WinDivert and only runs on Windows", "!".yellow());
+        return Ok(());
+    }
+
+    println!("{}", "Trying each mode against blocked sites...".cyan().bold());
+    println!("  Sites: {}", domains.join(", "));
+    println!();
+
+    let mut results = Vec::new();
+    for profile in TUNE_PROFILES {
+        print!("  {:<8}... ", profile.name());
+        std::io::stdout().flush()?;
+
+        let result = probe_mode(profile, domains, attempts, timeout, mode_timeout)?;
+        println!("{}/{} handshakes", result.latencies.len(), result.attempts);
+        results.push(result);
+    }
+
+    rank(&mut results);
+    println!();
+    print!("{}", render_table(&results));
+
+    let best = results.first().filter(|result| !result.latencies.is_empty());
+    let Some(best) = best else {
+        println!();
+        println!("{}", "No mode got a TLS handshake through.".red().bold());
+        return Ok(());
+    };
+
+    println!();
+    println!("Best mode: {}", best.profile.name().green().bold());
+    match apply {
+        Some(path) => {
+            apply_profile(best.profile, path)?;
+            println!("Configuration written: {}", path.display());
+        }
+        None => println!("Use it with: goodbyedpi run --profile {}", best.profile.name()),
+    }
+
+    Ok(())
+}
+
+/// Run the bypass with `profile` and probe every domain through it
+#[cfg(windows)]
+fn probe_mode(
+    profile: Profile,
+    domains: &[String],
+    attempts: u32,
+    timeout: Duration,
+    mode_timeout: Duration,
+) -> Result<ModeResult> {
+    use super::run::BackgroundSession;
+
+    let session = BackgroundSession::spawn(Config::from_profile(profile))?;
+    // Give the driver a moment to open before the first handshake
+    std::thread::sleep(Duration::from_millis(300));
+    if !session.is_running() {
+        session.stop()?;
+        anyhow::bail!("Packet loop stopped before probing {}", profile.name());
+    }
+
+    let started = Instant::now();
+    let mut result = ModeResult {
+        profile,
+        attempts: 0,
+        latencies: Vec::new(),
+    };
+    for domain in domains {
+        for _ in 0..attempts {
+            result.attempts += 1;
+            let remaining = mode_timeout.saturating_sub(started.elapsed());
+            if remaining.is_zero() {
+                continue;
+            }
+            if let Ok(latency) = probe_tls(domain, timeout.min(remaining)) {
+                result.latencies.push(latency);
+            }
+        }
+    }
+
+    session
+        .stop()
+        .with_context(|| format!("Packet loop failed in {}", profile.name()))?;
+
+    Ok(result)
+}
+
+#[cfg(not(windows))]
+fn probe_mode(
+    profile: Profile,
+    _domains: &[String],
+    _attempts: u32,
+    _timeout: Duration,
+    _mode_timeout: Duration,
+) -> Result<ModeResult> {
+    Ok(ModeResult {
+        profile,
+        attempts: 0,
+        latencies: Vec::new(),
+    })
+}
+
+/// Time from connecting to `domain`:443 until its ServerHello arrives
+///
+/// A TCP connect alone proves little: DPI lets the SYN through and resets
+/// the connection once it sees the ClientHello's SNI.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn probe_tls(domain: &str, timeout: Duration) -> Result<Duration> {
+    let addr = (domain, 443)
+        .to_socket_addrs()?
+        .next()
+        .with_context(|| format!("No address for {}", domain))?;
+
+    let start = Instant::now();
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    let head = server_hello_head(&mut stream, domain, timeout)?;
+    if !is_server_hello(&head) {
+        anyhow::bail!("{} answered without a ServerHello", domain);
+    }
+    Ok(start.elapsed())
+}
+
+/// Whether a TLS stream starts with a ServerHello record
+fn is_server_hello(head: &[u8]) -> bool {
+    // Handshake record, TLS 1.x, handshake type server_hello
+    head.len() >= 6 && head[0] == 0x16 && head[1] == 0x03 && head[5] == 0x02
+}
+
+/// Most successful first, then fastest
+fn rank(results: &mut [ModeResult]) {
+    results.sort_by(|a, b| {
+        b.success_rate()
+            .total_cmp(&a.success_rate())
+            .then_with(|| a.mean_latency().unwrap_or(Duration::MAX).cmp(&b.mean_latency().unwrap_or(Duration::MAX)))
+    });
+}
+
+fn render_table(results: &[ModeResult]) -> String {
+    let mut out = format!("{:<4}  {:<8}  {:>7}  {:>9}\n", "RANK", "MODE", "SUCCESS", "LATENCY");
+    for (i, result) in results.iter().enumerate() {
+        let latency = result
+            .mean_latency()
+            .map_or_else(|| "-".to_string(), |latency| format!("{}ms", latency.as_millis()));
+        out.push_str(&format!(
+            "{:<4}  {:<8}  {:>6.0}%  {:>9}\n",
+            i + 1,
+            result.profile.name(),
+            result.success_rate() * 100.0,
+            latency
+        ));
+    }
+    out
+}
+
+/// Write `profile` as the configuration at `path`, keeping a backup of
+/// the file it replaces
+fn apply_profile(profile: Profile, path: &Path) -> Result<()> {
+    if path.exists() {
+        let backup = path.with_extension("toml.bak");
+        std::fs::copy(path, &backup)
+            .with_context(|| format!("Failed to back up {:?}", path))?;
+    }
+
+    let mut config = Config::from_profile(profile);
+    config.profile = Some(profile);
+    let toml_str = config.to_toml().context("Failed to serialize config")?;
+    let content = format!(
+        "# GoodbyeDPI-Turkey Configuration\n\
+         # Chosen by `goodbyedpi test tune`: {}\n\n\
+         {}",
+        profile.name(),
+        toml_str
+    );
+
+    std::fs::write(path, content).with_context(|| format!("Failed to write config to {:?}", path))
+}
+
 fn extract_host_port(url: &str) -> Result<String> {
     let url = url.trim_start_matches("https://").trim_start_matches("http://");
     let url = url.split('/').next().unwrap_or(url);
@@ -299,4 +791,113 @@ mod tests {
             "example.com:443"
         );
     }
+
+    #[test]
+    fn test_dns_failure_json() {
+        let resolved = Err(io::Error::new(io::ErrorKind::NotFound, "no such host"));
+        let result = probe_resolved("blocked.invalid", resolved, Duration::ZERO, Depth::Tls(Duration::from_secs(1)));
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["outcome"], "dns_fail");
+        assert_eq!(json["error"], "no such host");
+        assert_eq!(json["addresses"], serde_json::json!([]));
+        assert!(json.get("connect_ms").is_none());
+    }
+
+    #[test]
+    fn test_resolved_without_connect_json() {
+        let addrs = vec!["192.0.2.1:80".parse().unwrap()];
+        let result = probe_resolved("example.com", Ok(addrs), Duration::from_millis(12), Depth::Resolve);
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["outcome"], "ok");
+        assert_eq!(json["addresses"], serde_json::json!(["192.0.2.1"]));
+        assert_eq!(json["resolve_ms"], 12);
+        assert!(json.get("error").is_none());
+    }
+
+    #[test]
+    fn test_no_address_outcome() {
+        let result = probe_resolved("example.com", Ok(Vec::new()), Duration::ZERO, Depth::Tcp(Duration::from_secs(1)));
+        assert_eq!(result.outcome, Outcome::NoAddr);
+        assert_eq!(serde_json::to_value(result.outcome).unwrap(), "no_addr");
+    }
+
+    #[test]
+    fn test_tls_outcome() {
+        let server_hello = [0x16, 0x03, 0x03, 0x00, 0x7a, 0x02];
+        assert_eq!(tls_outcome(Ok(server_hello)), (Outcome::TlsOk, None));
+        assert_eq!(tls_outcome(Ok([0x15, 0x03, 0x03, 0x00, 0x02, 0x02])).0, Outcome::TlsAlert);
+
+        let err = |kind| Err(io::Error::from(kind));
+        assert_eq!(tls_outcome(err(io::ErrorKind::ConnectionReset)).0, Outcome::TcpOkTlsReset);
+        assert_eq!(tls_outcome(err(io::ErrorKind::UnexpectedEof)).0, Outcome::TcpOkTlsReset);
+        assert_eq!(tls_outcome(err(io::ErrorKind::WouldBlock)).0, Outcome::Timeout);
+        assert_eq!(tls_outcome(err(io::ErrorKind::TimedOut)).0, Outcome::Timeout);
+        assert_eq!(serde_json::to_value(Outcome::TcpOkTlsReset).unwrap(), "tcp_ok_tls_reset");
+    }
+
+    #[test]
+    fn test_tls_probe_detects_reset() {
+        use std::net::TcpListener;
+
+        // A server that drops the connection once the ClientHello arrives
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut hello = [0u8; 5];
+            stream.read_exact(&mut hello).unwrap();
+        });
+
+        let result = probe_resolved("localhost", Ok(vec![addr]), Duration::ZERO, Depth::Tls(Duration::from_secs(5)));
+        server.join().unwrap();
+        assert!(result.connect_ms.is_some());
+        assert_eq!(result.outcome, Outcome::TcpOkTlsReset);
+        assert_eq!(result.handshake_ms, None);
+    }
+
+    #[test]
+    fn test_is_server_hello() {
+        assert!(is_server_hello(&[0x16, 0x03, 0x03, 0x00, 0x7a, 0x02]));
+        // Alert record, e.g. handshake_failure
+        assert!(!is_server_hello(&[0x15, 0x03, 0x03, 0x00, 0x02, 0x02]));
+        assert!(!is_server_hello(&[0x16, 0x03]));
+    }
+
+    #[test]
+    fn test_rank_and_table() {
+        colored::control::set_override(false);
+        let ms = Duration::from_millis;
+        let mut results = vec![
+            ModeResult { profile: Profile::Mode1, attempts: 4, latencies: vec![ms(80), ms(120)] },
+            ModeResult { profile: Profile::Mode5, attempts: 4, latencies: Vec::new() },
+            ModeResult { profile: Profile::Mode9, attempts: 4, latencies: vec![ms(90); 4] },
+            ModeResult { profile: Profile::Turkey, attempts: 4, latencies: vec![ms(60); 4] },
+        ];
+
+        rank(&mut results);
+        let order: Vec<_> = results.iter().map(|r| r.profile).collect();
+        assert_eq!(order, [Profile::Turkey, Profile::Mode9, Profile::Mode1, Profile::Mode5]);
+
+        let table = render_table(&results);
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(lines[0], "RANK  MODE      SUCCESS    LATENCY");
+        assert_eq!(lines[1], format!("1     {:<8}     100%       60ms", Profile::Turkey.name()));
+        assert_eq!(lines[3], format!("3     {:<8}      50%      100ms", Profile::Mode1.name()));
+        assert!(lines[4].ends_with("0%          -"));
+    }
+
+    #[test]
+    fn test_apply_profile_keeps_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "# mine\n").unwrap();
+
+        apply_profile(Profile::Mode2, &path).unwrap();
+
+        let config = Config::load_auto(&path).unwrap();
+        assert_eq!(config.profile, Some(Profile::Mode2));
+        assert_eq!(std::fs::read_to_string(dir.path().join("config.toml.bak")).unwrap(), "# mine\n");
+    }
 }
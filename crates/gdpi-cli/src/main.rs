@@ -19,8 +19,10 @@ fn main() -> Result<()> {
     // Initialize logging
     logging::init(&args)?;
 
-    // Print banner
-    print_banner();
+    // Print banner, unless stdout carries JSON
+    if !prints_json(&args) {
+        print_banner();
+    }
 
     // Run the main logic
     let result = run(args);
@@ -37,6 +39,12 @@ fn run(args: Args) -> Result<()> {
         Some(commands::Command::Run(run_args)) => {
             commands::run::execute(run_args)
         }
+        Some(commands::Command::Monitor(monitor_args)) => {
+            commands::monitor::execute(monitor_args)
+        }
+        Some(commands::Command::Stats(stats_args)) => {
+            commands::stats::execute(stats_args)
+        }
         Some(commands::Command::Config(config_args)) => {
             commands::config::execute(config_args)
         }
@@ -63,6 +71,15 @@ fn run(args: Args) -> Result<()> {
     }
 }
 
+/// Whether the command writes JSON that the banner would corrupt
+fn prints_json(args: &Args) -> bool {
+    match &args.command {
+        Some(commands::Command::Test(test_args)) => test_args.json(),
+        Some(commands::Command::Monitor(monitor_args)) => monitor_args.json,
+        _ => false,
+    }
+}
+
 fn print_banner() {
     use colored::Colorize;
 
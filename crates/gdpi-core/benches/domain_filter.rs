@@ -0,0 +1,56 @@
+//! Wildcard lookup with 100k patterns: suffix trie vs. a label walk over a
+//! hash set (the previous implementation)
+//!
+//! Run with `cargo bench -p gdpi-core --bench domain_filter`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use dashmap::DashSet;
+use gdpi_core::filter::{DomainFilter, FilterMode};
+
+const PATTERNS: usize = 100_000;
+
+fn patterns() -> Vec<String> {
+    (0..PATTERNS).map(|i| format!("site{i}.example{}.com", i % 97)).collect()
+}
+
+/// Check every parent of `hostname` against the set
+fn label_walk(set: &DashSet<String>, hostname: &str) -> bool {
+    let mut current = hostname;
+    loop {
+        if set.contains(current) {
+            return true;
+        }
+        match current.find('.') {
+            Some(pos) => current = &current[pos + 1..],
+            None => return false,
+        }
+    }
+}
+
+fn bench_wildcard(c: &mut Criterion) {
+    let patterns = patterns();
+    let filter = DomainFilter::with_domains(
+        FilterMode::Blacklist,
+        patterns.iter().map(|p| format!("*.{p}")).collect(),
+    );
+    let set: DashSet<String> = patterns.iter().cloned().collect();
+
+    let hosts = [
+        ("hit", "cdn.static.site4242.example71.com"),
+        ("miss", "cdn.static.unlisted.example71.com"),
+    ];
+
+    let mut group = c.benchmark_group("wildcard_100k");
+    for (name, host) in hosts {
+        group.bench_with_input(BenchmarkId::new("suffix_trie", name), host, |b, host| {
+            b.iter(|| filter.matches(black_box(host)))
+        });
+        group.bench_with_input(BenchmarkId::new("dashset_walk", name), host, |b, host| {
+            b.iter(|| label_walk(&set, black_box(host)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_wildcard);
+criterion_main!(benches);
@@ -0,0 +1,144 @@
+//! Configuration comparison
+//!
+//! Shows which settings differ between two configurations, e.g. what a
+//! profile change actually modifies.
+
+use super::Config;
+use crate::error::Result;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+/// A single setting that differs between two configurations
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfigChange {
+    /// Dotted path of the setting (e.g. `strategies.fragmentation.http_size`)
+    pub path: String,
+    /// Value in the first configuration (`None` if absent)
+    pub old: Option<Value>,
+    /// Value in the second configuration (`None` if absent)
+    pub new: Option<Value>,
+}
+
+/// Differences between two configurations
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConfigDiff {
+    /// Changed settings, ordered by path
+    pub changes: Vec<ConfigChange>,
+}
+
+impl ConfigDiff {
+    /// Whether the configurations are identical
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Serialize the diff to a JSON string
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            if let Some(old) = &change.old {
+                writeln!(f, "- {}: {}", change.path, old)?;
+            }
+            if let Some(new) = &change.new {
+                writeln!(f, "+ {}: {}", change.path, new)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Compare this configuration with `other`
+    ///
+    /// Sections are compared field by field; lists are compared as a whole.
+    pub fn diff(&self, other: &Config) -> Result<ConfigDiff> {
+        let mut diff = ConfigDiff::default();
+        compare("", &serde_json::to_value(self)?, &serde_json::to_value(other)?, &mut diff.changes);
+        Ok(diff)
+    }
+}
+
+fn compare(path: &str, old: &Value, new: &Value, changes: &mut Vec<ConfigChange>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                let path = join(path, key);
+                match new.get(key) {
+                    Some(new_value) => compare(&path, old_value, new_value, changes),
+                    None => changes.push(ConfigChange {
+                        path,
+                        old: Some(old_value.clone()),
+                        new: None,
+                    }),
+                }
+            }
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    changes.push(ConfigChange {
+                        path: join(path, key),
+                        old: None,
+                        new: Some(new_value.clone()),
+                    });
+                }
+            }
+        }
+        _ if old != new => changes.push(ConfigChange {
+            path: path.to_string(),
+            old: Some(old.clone()),
+            new: Some(new.clone()),
+        }),
+        _ => {}
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_identical() {
+        let config = Config::default();
+        assert!(config.diff(&config.clone()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_diff_changed_fields() {
+        let a = Config::default();
+        let mut b = a.clone();
+        b.strategies.fragmentation.http_size = 4;
+        b.performance.additional_ports = vec![8080];
+
+        let diff = a.diff(&b).unwrap();
+        assert_eq!(diff.changes.len(), 2);
+        assert_eq!(
+            diff.changes[1],
+            ConfigChange {
+                path: "strategies.fragmentation.http_size".to_string(),
+                old: Some(Value::from(2)),
+                new: Some(Value::from(4)),
+            }
+        );
+
+        let text = diff.to_string();
+        assert!(text.contains("- strategies.fragmentation.http_size: 2\n"));
+        assert!(text.contains("+ strategies.fragmentation.http_size: 4\n"));
+        assert!(text.contains("+ performance.additional_ports: [8080]\n"));
+
+        let json: Value = serde_json::from_str(&diff.to_json().unwrap()).unwrap();
+        assert_eq!(json["changes"][1]["new"], 4);
+    }
+}
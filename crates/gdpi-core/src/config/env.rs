@@ -0,0 +1,378 @@
+//! Environment variable configuration overlay
+//!
+//! `GDPI_*` variables sit between the config file and command-line flags
+//! in precedence, which is handy for services and containers.
+
+use super::{Config, Profile};
+use crate::error::{Error, Result};
+use serde_json::Value;
+use std::net::IpAddr;
+use std::str::FromStr;
+use tracing::warn;
+
+/// Config file to load instead of the base configuration
+pub const ENV_CONFIG_FILE: &str = "GDPI_CONFIG_FILE";
+/// Profile to use instead of the base configuration
+pub const ENV_PROFILE: &str = "GDPI_PROFILE";
+/// DNS server (enables DNS redirection)
+pub const ENV_DNS_SERVER: &str = "GDPI_DNS_SERVER";
+/// Fixed TTL for fake packets
+pub const ENV_TTL: &str = "GDPI_TTL";
+/// Block QUIC (`true`/`false`, `1`/`0`, `yes`/`no`, `on`/`off`)
+pub const ENV_BLOCK_QUIC: &str = "GDPI_BLOCK_QUIC";
+/// HTTP fragment size
+pub const ENV_HTTP_FRAG_SIZE: &str = "GDPI_HTTP_FRAG_SIZE";
+/// HTTPS fragment size
+pub const ENV_HTTPS_FRAG_SIZE: &str = "GDPI_HTTPS_FRAG_SIZE";
+/// Log level
+pub const ENV_LOG_LEVEL: &str = "GDPI_LOG_LEVEL";
+
+/// Prefix of every variable read by the overlay
+pub const ENV_PREFIX: &str = "GDPI_";
+
+/// Variables with their own meaning, not mapped to a config path
+const SHORTCUTS: [&str; 8] = [
+    ENV_CONFIG_FILE,
+    ENV_PROFILE,
+    ENV_DNS_SERVER,
+    ENV_TTL,
+    ENV_BLOCK_QUIC,
+    ENV_HTTP_FRAG_SIZE,
+    ENV_HTTPS_FRAG_SIZE,
+    ENV_LOG_LEVEL,
+];
+
+/// `GDPI_*` variables as (name, value) pairs
+type Vars = [(String, String)];
+
+impl Config {
+    /// Overlay `GDPI_*` environment variables on `base`
+    ///
+    /// `GDPI_CONFIG_FILE` (or else `GDPI_PROFILE`) replaces `base` as a
+    /// whole; the remaining variables then override individual settings.
+    /// Values that fail to parse are logged and ignored.
+    pub fn from_env_overlay(base: Config) -> Config {
+        overlay(base, &env_vars())
+    }
+
+    /// Apply the per-setting `GDPI_*` variables, leaving the base source alone
+    ///
+    /// Used when the config file or profile was chosen explicitly on the
+    /// command line, which takes precedence over `GDPI_CONFIG_FILE` and
+    /// `GDPI_PROFILE`. Values that fail to parse are logged and ignored.
+    pub fn with_env_overrides(mut self) -> Config {
+        for e in apply_overrides(&mut self, &env_vars()) {
+            warn!(error = %e, "Ignoring invalid environment variable");
+        }
+        self
+    }
+
+    /// Apply the per-setting `GDPI_*` variables, failing on the first
+    /// value that doesn't parse
+    ///
+    /// Besides the shortcuts above, any setting can be set through its
+    /// serde path in upper case: `strategies.fragmentation.https_size` is
+    /// `GDPI_STRATEGIES_FRAGMENTATION_HTTPS_SIZE`. Lists are comma
+    /// separated. The error names the offending variable.
+    pub fn apply_env_overrides(&mut self) -> Result<()> {
+        match apply_overrides(self, &env_vars()).into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+fn env_vars() -> Vec<(String, String)> {
+    std::env::vars_os()
+        .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+        .filter(|(key, _)| key.starts_with(ENV_PREFIX))
+        .collect()
+}
+
+fn lookup<'a>(vars: &'a Vars, key: &str) -> Option<&'a str> {
+    vars.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+}
+
+fn overlay(base: Config, vars: &Vars) -> Config {
+    let mut config = if let Some(path) = lookup(vars, ENV_CONFIG_FILE) {
+        match Config::load_auto(path) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!(var = ENV_CONFIG_FILE, error = %e, "Ignoring environment variable");
+                base
+            }
+        }
+    } else if let Some(name) = lookup(vars, ENV_PROFILE) {
+        match Profile::from_name(name) {
+            Ok(profile) => Config::from_profile(profile),
+            Err(e) => {
+                warn!(var = ENV_PROFILE, error = %e, "Ignoring environment variable");
+                base
+            }
+        }
+    } else {
+        base
+    };
+
+    for e in apply_overrides(&mut config, vars) {
+        warn!(error = %e, "Ignoring invalid environment variable");
+    }
+    config
+}
+
+/// Apply every override that parses, returning the errors for the rest
+fn apply_overrides(config: &mut Config, vars: &Vars) -> Vec<Error> {
+    let mut errors = Vec::new();
+
+    if let Some(server) = parse_var::<IpAddr>(vars, ENV_DNS_SERVER, &mut errors) {
+        config.dns.enabled = true;
+        config.dns.set_upstream(server);
+    }
+
+    if let Some(ttl) = parse_var::<u8>(vars, ENV_TTL, &mut errors) {
+        config.strategies.fake_ttl = Some(ttl);
+        config.strategies.fake_packet.ttl = Some(ttl);
+    }
+
+    if let Some(value) = lookup(vars, ENV_BLOCK_QUIC) {
+        match parse_bool(value) {
+            Some(block) => {
+                config.strategies.block_quic = block;
+                config.strategies.quic_block.enabled = block;
+            }
+            None => errors.push(invalid(ENV_BLOCK_QUIC, value, "expected a boolean")),
+        }
+    }
+
+    if let Some(size) = parse_var::<u16>(vars, ENV_HTTP_FRAG_SIZE, &mut errors) {
+        config.strategies.fragmentation.http_size = size;
+    }
+
+    if let Some(size) = parse_var::<u16>(vars, ENV_HTTPS_FRAG_SIZE, &mut errors) {
+        config.strategies.fragmentation.https_size = size;
+    }
+
+    if let Some(level) = lookup(vars, ENV_LOG_LEVEL) {
+        config.logging.level = level.trim().to_lowercase();
+    }
+
+    // Sorted so a variable and one nested under it apply in a fixed order
+    let mut paths: Vec<_> = vars
+        .iter()
+        .filter(|(key, _)| !SHORTCUTS.contains(&key.as_str()))
+        .collect();
+    paths.sort();
+    for (key, value) in paths {
+        if let Err(e) = apply_path(config, key, value) {
+            errors.push(e);
+        }
+    }
+
+    errors
+}
+
+/// Set the setting `key` maps to, e.g. `GDPI_DNS_ENABLED` -> `dns.enabled`
+fn apply_path(config: &mut Config, key: &str, value: &str) -> Result<()> {
+    let name = key[ENV_PREFIX.len()..].to_lowercase();
+    let mut tree = serde_json::to_value(&*config)?;
+
+    let Some(slot) = find_slot(&mut tree, &name, 0) else {
+        warn!(var = key, "Ignoring environment variable that matches no setting");
+        return Ok(());
+    };
+    *slot = parse_value(slot, value);
+
+    *config = serde_json::from_value(tree).map_err(|e| invalid(key, value, &e.to_string()))?;
+    Ok(())
+}
+
+/// Walk `node` along the `_`-joined field names in `name`
+///
+/// Keys are matched whole, so `fragmentation_https_size` finds
+/// `fragmentation` then `https_size`. A name that matches nothing inside a
+/// section is taken as a field left out of the serialized form (unset
+/// options, false shortcuts).
+fn find_slot<'a>(node: &'a mut Value, name: &str, depth: usize) -> Option<&'a mut Value> {
+    let map = node.as_object_mut()?;
+
+    if map.contains_key(name) {
+        return map.get_mut(name);
+    }
+
+    let key = map
+        .iter()
+        .filter(|(key, child)| child.is_object() && name.starts_with(&format!("{key}_")))
+        .map(|(key, _)| key.clone())
+        .max_by_key(String::len);
+    match key {
+        Some(key) => {
+            let rest = &name[key.len() + 1..];
+            find_slot(map.get_mut(&key)?, rest, depth + 1)
+        }
+        None if depth > 0 => Some(map.entry(name).or_insert(Value::Null)),
+        None => None,
+    }
+}
+
+/// Convert `value` to the JSON type the setting currently holds
+fn parse_value(current: &Value, value: &str) -> Value {
+    let value = value.trim();
+    match current {
+        Value::Bool(_) => parse_bool(value).map_or_else(|| value.into(), Value::Bool),
+        Value::String(_) => value.into(),
+        Value::Array(items) => {
+            let item = items.first().unwrap_or(&Value::Null);
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|part| !part.is_empty())
+                .map(|part| parse_value(item, part))
+                .collect()
+        }
+        _ => serde_json::from_str::<Value>(value)
+            .ok()
+            .or_else(|| parse_bool(value).map(Value::Bool))
+            .unwrap_or_else(|| value.into()),
+    }
+}
+
+fn parse_var<T: FromStr>(vars: &Vars, key: &str, errors: &mut Vec<Error>) -> Option<T> {
+    let value = lookup(vars, key)?;
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            errors.push(invalid(key, value, "could not be parsed"));
+            None
+        }
+    }
+}
+
+fn invalid(key: &str, value: &str, reason: &str) -> Error {
+    Error::config_value(key, format!("{value:?} {reason}"))
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_overrides() {
+        let config = overlay(
+            Config::default(),
+            &vars(&[
+                (ENV_DNS_SERVER, "1.1.1.1"),
+                (ENV_TTL, "5"),
+                (ENV_BLOCK_QUIC, "off"),
+                (ENV_HTTP_FRAG_SIZE, "3"),
+                (ENV_HTTPS_FRAG_SIZE, "40"),
+                (ENV_LOG_LEVEL, "DEBUG"),
+            ]),
+        );
+
+        assert!(config.dns.enabled);
+        assert_eq!(config.dns.ipv4_upstream, Some("1.1.1.1".parse().unwrap()));
+        assert_eq!(config.dns.ipv6_upstream, None);
+        assert_eq!(config.strategies.fake_packet.ttl, Some(5));
+        assert!(!config.strategies.quic_block.enabled);
+        assert_eq!(config.strategies.fragmentation.http_size, 3);
+        assert_eq!(config.strategies.fragmentation.https_size, 40);
+        assert_eq!(config.logging.level, "debug");
+    }
+
+    #[test]
+    fn test_invalid_values_ignored() {
+        let base = Config::default();
+        let config = overlay(
+            base.clone(),
+            &vars(&[(ENV_TTL, "300"), (ENV_BLOCK_QUIC, "maybe"), (ENV_PROFILE, "nope")]),
+        );
+
+        assert_eq!(config.strategies.fake_packet.ttl, base.strategies.fake_packet.ttl);
+        assert_eq!(config.strategies.quic_block.enabled, base.strategies.quic_block.enabled);
+        assert_eq!(config.profile, base.profile);
+    }
+
+    #[test]
+    fn test_profile_and_config_file() {
+        let config = overlay(Config::default(), &vars(&[(ENV_PROFILE, "turkey")]));
+        let turkey = Config::from_profile(Profile::Turkey);
+        assert_eq!(format!("{config:?}"), format!("{turkey:?}"));
+
+        // Config file wins over profile
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[logging]\nlevel = \"trace\"\n").unwrap();
+        let config = overlay(
+            Config::default(),
+            &vars(&[(ENV_CONFIG_FILE, path.to_str().unwrap()), (ENV_PROFILE, "turkey")]),
+        );
+        assert_eq!(config.logging.level, "trace");
+        assert_ne!(format!("{config:?}"), format!("{turkey:?}"));
+    }
+
+    #[test]
+    fn test_path_overrides() {
+        let mut config = Config::from_profile(Profile::Turkey);
+        let errors = apply_overrides(
+            &mut config,
+            &vars(&[
+                ("GDPI_DNS_ENABLED", "true"),
+                ("GDPI_DNS_IPV4_UPSTREAM", "9.9.9.9"),
+                ("GDPI_STRATEGIES_FRAGMENTATION_HTTPS_SIZE", "7"),
+                ("GDPI_STRATEGIES_FRAGMENTATION_FRAGMENT_POSITIONS", "1, 3"),
+                ("GDPI_STRATEGIES_BLOCK_QUIC", "yes"),
+                ("GDPI_NOT_A_SETTING", "1"),
+            ]),
+        );
+
+        assert!(errors.is_empty(), "{errors:?}");
+        assert!(config.dns.enabled);
+        assert_eq!(config.dns.ipv4_upstream, Some("9.9.9.9".parse().unwrap()));
+        assert_eq!(config.strategies.fragmentation.https_size, 7);
+        assert_eq!(config.strategies.fragmentation.fragment_positions, [1, 3]);
+        assert!(config.strategies.block_quic);
+    }
+
+    #[test]
+    fn test_path_override_error_names_variable() {
+        let mut config = Config::default();
+        let errors = apply_overrides(
+            &mut config,
+            &vars(&[("GDPI_STRATEGIES_FRAGMENTATION_HTTPS_SIZE", "lots")]),
+        );
+
+        assert!(matches!(
+            &errors[..],
+            [Error::ConfigValue { key, .. }] if key == "GDPI_STRATEGIES_FRAGMENTATION_HTTPS_SIZE"
+        ));
+        let default = Config::default();
+        assert_eq!(config.strategies.fragmentation.https_size, default.strategies.fragmentation.https_size);
+    }
+
+    #[test]
+    fn test_apply_env_overrides() {
+        // Settings no other test reads through the environment
+        std::env::set_var("GDPI_PERFORMANCE_CONNTRACK_MAX_ENTRIES", "1234");
+        std::env::set_var("GDPI_DNS_VERBOSE", "on");
+        let mut config = Config::default();
+        let result = config.apply_env_overrides();
+        std::env::remove_var("GDPI_PERFORMANCE_CONNTRACK_MAX_ENTRIES");
+        std::env::remove_var("GDPI_DNS_VERBOSE");
+
+        result.unwrap();
+        assert_eq!(config.performance.conntrack_max_entries, 1234);
+        assert!(config.dns.verbose);
+    }
+}
@@ -0,0 +1,423 @@
+//! Import of original goodbyedpi.exe command lines
+//!
+//! Parses the v1 flag grammar (`-9 -e 40 -q --blacklist x.txt`, short
+//! flags may be clustered as in getopt) into a [`Config`]. A mode flag
+//! (`-1` to `-9`) starts from the matching profile; every other flag is
+//! applied on top in order.
+
+use super::{AutoTtlConfig, Config};
+use crate::error::{Error, Result};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use tracing::warn;
+
+/// Result of importing a legacy command line
+#[derive(Debug)]
+pub struct LegacyImport {
+    /// Equivalent modern configuration
+    pub config: Config,
+    /// Flags that were ignored, with the reason
+    pub warnings: Vec<String>,
+}
+
+/// Short flags that take a value
+const SHORT_WITH_VALUE: &[char] = &['f', 'e', 'k'];
+
+/// Long flags that take a value
+const LONG_WITH_VALUE: &[&str] = &[
+    "port",
+    "ip-id",
+    "dns-addr",
+    "dns-port",
+    "dnsv6-addr",
+    "dnsv6-port",
+    "blacklist",
+    "set-ttl",
+    "min-ttl",
+    "fake-from-hex",
+    "fake-with-sni",
+    "fake-gen",
+    "fake-resend",
+];
+
+impl Config {
+    /// Create configuration from an original goodbyedpi.exe command line
+    ///
+    /// `args` excludes the program name. Flags without a modern
+    /// equivalent are logged and skipped; use [`Config::import_legacy_args`]
+    /// to get them back instead.
+    pub fn from_legacy_args(args: &[&str]) -> Result<Self> {
+        let import = Self::import_legacy_args(args)?;
+        for warning in &import.warnings {
+            warn!("{}", warning);
+        }
+        Ok(import.config)
+    }
+
+    /// Like [`Config::from_legacy_args`], returning the skipped flags
+    pub fn import_legacy_args(args: &[&str]) -> Result<LegacyImport> {
+        let flags = split_flags(args)?;
+
+        let modes: Vec<u8> = flags.iter().filter_map(Flag::mode).collect();
+        let mut config = match modes.as_slice() {
+            [] => bare_config(),
+            [mode] => Self::from_legacy_mode(*mode)?,
+            _ => return Err(Error::config_value("mode", "Only one of -1 to -9 may be given")),
+        };
+
+        let mut warnings = Vec::new();
+        for flag in &flags {
+            if flag.mode().is_none() {
+                if let Some(warning) = apply(&mut config, flag)? {
+                    warnings.push(warning);
+                }
+            }
+        }
+
+        config.validate()?;
+        Ok(LegacyImport { config, warnings })
+    }
+}
+
+/// One flag and its value, as written
+#[derive(Debug)]
+struct Flag<'a> {
+    /// Flag with its dashes, e.g. "-e" or "--set-ttl"
+    name: String,
+    value: Option<&'a str>,
+}
+
+impl Flag<'_> {
+    fn mode(&self) -> Option<u8> {
+        match self.name.as_bytes() {
+            [b'-', digit @ b'1'..=b'9'] => Some(digit - b'0'),
+            _ => None,
+        }
+    }
+
+    fn value(&self) -> Result<&str> {
+        self.value
+            .ok_or_else(|| Error::config_value(self.name.clone(), "Missing value"))
+    }
+
+    fn number<T: FromStr>(&self) -> Result<T> {
+        let value = self.value()?;
+        value.parse().map_err(|_| {
+            Error::config_value(self.name.clone(), format!("Invalid value '{}'", value))
+        })
+    }
+}
+
+/// Split arguments into flags, expanding short clusters such as `-prs`
+fn split_flags<'a>(args: &[&'a str]) -> Result<Vec<Flag<'a>>> {
+    let mut flags = Vec::new();
+    let mut i = 0;
+    // Take the argument after `i` as a value if `accept` allows it
+    let next_value = |i: &mut usize, accept: fn(&str) -> bool| {
+        let value = args.get(*i + 1).copied().filter(|next| accept(next));
+        if value.is_some() {
+            *i += 1;
+        }
+        value
+    };
+    let not_a_flag: fn(&str) -> bool = |next| !next.starts_with('-');
+
+    while i < args.len() {
+        let arg = args[i];
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, attached) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            let value = match attached {
+                Some(value) => Some(value),
+                // Optional values are only taken when they look like one
+                None if name == "auto-ttl" => next_value(&mut i, |v| parse_auto_ttl(v).is_some()),
+                None if name == "max-payload" => next_value(&mut i, |v| v.parse::<u16>().is_ok()),
+                None if LONG_WITH_VALUE.contains(&name) || !is_known_long(name) => {
+                    next_value(&mut i, not_a_flag)
+                }
+                None => None,
+            };
+            flags.push(Flag {
+                name: format!("--{}", name),
+                value,
+            });
+        } else if let Some(short) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            for (pos, c) in short.char_indices() {
+                if SHORT_WITH_VALUE.contains(&c) {
+                    let rest = &short[pos + c.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        next_value(&mut i, not_a_flag)
+                    } else {
+                        Some(rest)
+                    };
+                    flags.push(Flag {
+                        name: format!("-{}", c),
+                        value,
+                    });
+                    break;
+                }
+                flags.push(Flag {
+                    name: format!("-{}", c),
+                    value: None,
+                });
+            }
+        } else {
+            return Err(Error::config_value("args", format!("Unexpected argument '{}'", arg)));
+        }
+        i += 1;
+    }
+
+    Ok(flags)
+}
+
+/// Whether `name` is a long flag without a value that we know about
+fn is_known_long(name: &str) -> bool {
+    matches!(
+        name,
+        "dns-verb"
+            | "allow-no-sni"
+            | "frag-by-sni"
+            | "auto-ttl"
+            | "wrong-chksum"
+            | "wrong-seq"
+            | "native-frag"
+            | "reverse-frag"
+            | "max-payload"
+            | "debug-exit"
+    )
+}
+
+/// Starting point without a mode flag: every strategy off
+fn bare_config() -> Config {
+    let mut config = Config::default();
+    let fragmentation = &mut config.strategies.fragmentation;
+    fragmentation.enabled = false;
+    fragmentation.http_size = 0;
+    fragmentation.https_size = 0;
+    fragmentation.native_split = false;
+    fragmentation.reverse_order = false;
+    fragmentation.http_persistent = false;
+    fragmentation.persistent_nowait = false;
+
+    let fake_packet = &mut config.strategies.fake_packet;
+    fake_packet.enabled = false;
+    fake_packet.wrong_checksum = false;
+    fake_packet.wrong_seq = false;
+
+    config.strategies.quic_block.enabled = false;
+    config.performance.max_payload_size = 0;
+    config
+}
+
+/// `a1-a2-max`, e.g. "1-4-10"
+fn parse_auto_ttl(spec: &str) -> Option<AutoTtlConfig> {
+    let mut parts = spec.split('-').map(|part| part.parse::<u8>().ok());
+    match (parts.next()??, parts.next()??, parts.next()??, parts.next()) {
+        (a1, a2, max, None) => Some(AutoTtlConfig { a1, a2, max }),
+        _ => None,
+    }
+}
+
+/// Apply one flag, returning a warning if it has no modern equivalent
+fn apply(config: &mut Config, flag: &Flag<'_>) -> Result<Option<String>> {
+    let strategies = &mut config.strategies;
+    match flag.name.as_str() {
+        "-p" => strategies.passive_dpi.enabled = true,
+        "-r" => {
+            strategies.header_mangle.enabled = true;
+            strategies.header_mangle.host_replace = true;
+        }
+        "-s" => {
+            strategies.header_mangle.enabled = true;
+            strategies.header_mangle.host_remove_space = true;
+        }
+        "-m" => {
+            strategies.header_mangle.enabled = true;
+            strategies.header_mangle.host_mix_case = true;
+        }
+        "-a" => {
+            strategies.header_mangle.enabled = true;
+            strategies.header_mangle.additional_space = true;
+        }
+        "-f" => {
+            strategies.fragmentation.enabled = true;
+            strategies.fragmentation.http_size = flag.number()?;
+        }
+        "-k" => {
+            strategies.fragmentation.enabled = true;
+            strategies.fragmentation.http_persistent = true;
+            strategies.fragmentation.http_size = flag.number()?;
+        }
+        "-n" => strategies.fragmentation.persistent_nowait = true,
+        "-e" => {
+            strategies.fragmentation.enabled = true;
+            strategies.fragmentation.https_size = flag.number()?;
+        }
+        "-w" => config.performance.http_all_ports = true,
+        "-q" => strategies.quic_block.enabled = true,
+        "--port" => config.performance.additional_ports.push(flag.number()?),
+        "--ip-id" => {
+            strategies.passive_dpi.enabled = true;
+            strategies.passive_dpi.ip_ids.push(flag.number()?);
+        }
+        "--dns-addr" => {
+            config.dns.enabled = true;
+            config.dns.ipv4_upstream = Some(flag.number::<Ipv4Addr>()?);
+        }
+        "--dns-port" => config.dns.ipv4_port = Some(flag.number()?),
+        "--dnsv6-addr" => {
+            config.dns.enabled = true;
+            config.dns.ipv6_upstream = Some(flag.number::<Ipv6Addr>()?);
+        }
+        "--dnsv6-port" => config.dns.ipv6_port = Some(flag.number()?),
+        "--dns-verb" => config.dns.verbose = true,
+        "--blacklist" => {
+            config.blacklist.enabled = true;
+            config.blacklist.mode = "blacklist".to_string();
+            config.blacklist.files.push(flag.value()?.to_string());
+        }
+        "--allow-no-sni" => config.blacklist.allow_no_sni = true,
+        "--frag-by-sni" => strategies.fragmentation.by_sni = true,
+        "--set-ttl" => {
+            strategies.fake_packet.enabled = true;
+            strategies.fake_packet.ttl = Some(flag.number()?);
+        }
+        "--auto-ttl" => {
+            let auto_ttl = match flag.value {
+                Some(spec) => parse_auto_ttl(spec).ok_or_else(|| {
+                    Error::config_value("--auto-ttl", format!("Expected a1-a2-max, got '{}'", spec))
+                })?,
+                None => AutoTtlConfig::default(),
+            };
+            strategies.fake_packet.enabled = true;
+            strategies.fake_packet.auto_ttl = Some(auto_ttl);
+        }
+        "--min-ttl" => strategies.fake_packet.min_ttl_hops = Some(flag.number()?),
+        "--wrong-chksum" => {
+            strategies.fake_packet.enabled = true;
+            strategies.fake_packet.wrong_checksum = true;
+        }
+        "--wrong-seq" => {
+            strategies.fake_packet.enabled = true;
+            strategies.fake_packet.wrong_seq = true;
+        }
+        "--native-frag" => strategies.fragmentation.native_split = true,
+        "--reverse-frag" => {
+            // Reversed fragments are always native segments
+            strategies.fragmentation.native_split = true;
+            strategies.fragmentation.reverse_order = true;
+        }
+        "--max-payload" => {
+            config.performance.max_payload_size = match flag.value {
+                Some(_) => flag.number()?,
+                None => 1200,
+            };
+        }
+        "--fake-from-hex" => strategies.fake_packet.custom_payloads.push(flag.value()?.to_string()),
+        "--fake-with-sni" => strategies.fake_packet.fake_sni_domains.push(flag.value()?.to_string()),
+        "--fake-gen" => strategies.fake_packet.random_count = Some(flag.number()?),
+        "--fake-resend" => strategies.fake_packet.resend_count = flag.number()?,
+        "--debug-exit" => {
+            return Ok(Some("--debug-exit: debugging aid, ignored".to_string()));
+        }
+        name => {
+            let shown = match flag.value {
+                Some(value) => format!("{} {}", name, value),
+                None => name.to_string(),
+            };
+            return Ok(Some(format!("{}: no modern equivalent, ignored", shown)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Profile;
+
+    #[test]
+    fn test_mode9_with_overrides() {
+        let import =
+            Config::import_legacy_args(&["-9", "-e", "40", "-q", "--blacklist", "x.txt"]).unwrap();
+        assert!(import.warnings.is_empty());
+
+        let config = import.config;
+        let mode9 = Profile::Mode9.into_config();
+        assert_eq!(config.strategies.fragmentation.https_size, 40);
+        assert_eq!(
+            config.strategies.fragmentation.http_size,
+            mode9.strategies.fragmentation.http_size
+        );
+        assert_eq!(config.strategies.fake_packet.ttl, mode9.strategies.fake_packet.ttl);
+        assert!(config.strategies.quic_block.enabled);
+        assert!(config.blacklist.enabled);
+        assert_eq!(config.blacklist.mode, "blacklist");
+        assert_eq!(config.blacklist.files, ["x.txt"]);
+    }
+
+    #[test]
+    fn test_mode1_flags_spelled_out() {
+        let config = Config::from_legacy_args(&["-p", "-r", "-s", "-f", "2", "-k", "2", "-n", "-e", "2"]).unwrap();
+        let strategies = &config.strategies;
+        assert!(strategies.passive_dpi.enabled);
+        assert!(strategies.header_mangle.host_replace);
+        assert!(strategies.header_mangle.host_remove_space);
+        assert!(strategies.fragmentation.enabled);
+        assert!(strategies.fragmentation.http_persistent);
+        assert!(strategies.fragmentation.persistent_nowait);
+        assert_eq!(strategies.fragmentation.http_size, 2);
+        assert_eq!(strategies.fragmentation.https_size, 2);
+        assert!(!strategies.fake_packet.enabled);
+        assert!(!strategies.quic_block.enabled);
+
+        // getopt-style clusters and attached values parse the same
+        let clustered = Config::from_legacy_args(&["-prs", "-f2", "-k2", "-n", "-e2"]).unwrap();
+        assert_eq!(
+            clustered.strategies.fragmentation.https_size,
+            strategies.fragmentation.https_size
+        );
+        assert!(clustered.strategies.header_mangle.host_remove_space);
+    }
+
+    #[test]
+    fn test_fake_and_dns_flags() {
+        let import = Config::import_legacy_args(&[
+            "-e1",
+            "--wrong-seq",
+            "--auto-ttl",
+            "1-4-10",
+            "--reverse-frag",
+            "--max-payload",
+            "--dns-addr",
+            "77.88.8.8",
+            "--dns-port=1253",
+            "--debug-exit",
+            "--some-future-flag",
+        ])
+        .unwrap();
+
+        let config = &import.config;
+        let fake_packet = &config.strategies.fake_packet;
+        assert!(fake_packet.enabled && fake_packet.wrong_seq && !fake_packet.wrong_checksum);
+        assert_eq!(fake_packet.auto_ttl.as_ref().map(|t| (t.a1, t.a2, t.max)), Some((1, 4, 10)));
+        assert!(config.strategies.fragmentation.reverse_order);
+        assert!(config.strategies.fragmentation.native_split);
+        assert_eq!(config.performance.max_payload_size, 1200);
+        assert!(config.dns.enabled);
+        assert_eq!(config.dns.ipv4_upstream, Some(Ipv4Addr::new(77, 88, 8, 8)));
+        assert_eq!(config.dns.ipv4_port, Some(1253));
+        assert_eq!(import.warnings.len(), 2);
+        assert!(import.warnings[1].starts_with("--some-future-flag"));
+    }
+
+    #[test]
+    fn test_invalid_command_lines() {
+        assert!(Config::from_legacy_args(&["-1", "-9"]).is_err());
+        assert!(Config::from_legacy_args(&["-e", "forty"]).is_err());
+        assert!(Config::from_legacy_args(&["--set-ttl"]).is_err());
+        assert!(Config::from_legacy_args(&["goodbyedpi.exe"]).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Layered configuration
+//!
+//! Stacks configurations so that each layer only changes the settings it
+//! actually sets: `base.merge(&profile)?.merge(&env)?`.
+
+use super::Config;
+use crate::error::Result;
+use serde_json::Value;
+
+impl Config {
+    /// `self` with every non-default setting of `overlay` applied on top
+    ///
+    /// Sections are merged field by field and lists replace each other
+    /// whole. A setting the overlay leaves at its default keeps the value
+    /// from `self`, so an overlay can't reset a setting to its default.
+    ///
+    /// # Errors
+    /// Returns error if the merged settings don't form a valid config.
+    pub fn merge(&self, overlay: &Config) -> Result<Config> {
+        let mut tree = serde_json::to_value(self)?;
+        let patch = serde_json::to_value(overlay)?;
+        let defaults = serde_json::to_value(Config::default())?;
+        apply(&mut tree, &patch, &defaults);
+
+        Ok(serde_json::from_value(tree)?)
+    }
+}
+
+/// Copy the values of `patch` that differ from `defaults` into `target`
+fn apply(target: &mut Value, patch: &Value, defaults: &Value) {
+    let (Value::Object(target), Value::Object(patch)) = (target, patch) else {
+        return;
+    };
+
+    for (key, value) in patch {
+        let default = defaults.get(key).unwrap_or(&Value::Null);
+        if value == default {
+            continue;
+        }
+        match target.get_mut(key) {
+            Some(current) if value.is_object() && default.is_object() => apply(current, value, default),
+            _ => {
+                target.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Profile;
+
+    #[test]
+    fn test_merge_layers() {
+        let base = Config::from_profile(Profile::Turkey);
+
+        let mut profile = Config::default();
+        profile.strategies.fragmentation.https_size = 7;
+        profile.dns.ipv4_upstream = Some("1.1.1.1".parse().unwrap());
+
+        let mut cli = Config::default();
+        cli.strategies.fake_ttl = Some(4);
+        cli.blacklist.files = vec!["cli.txt".into()];
+
+        let merged = base.merge(&profile).unwrap().merge(&cli).unwrap();
+        assert_eq!(merged.strategies.fragmentation.https_size, 7);
+        assert_eq!(merged.dns.ipv4_upstream, profile.dns.ipv4_upstream);
+        assert_eq!(merged.strategies.fake_ttl, Some(4));
+        assert_eq!(merged.blacklist.files, ["cli.txt"]);
+        // Settings the layers leave at their defaults come from the base
+        assert_eq!(merged.strategies.fragmentation.http_size, base.strategies.fragmentation.http_size);
+        assert_eq!(merged.dns.enabled, base.dns.enabled);
+        assert_eq!(merged.strategies.fake_packet.enabled, base.strategies.fake_packet.enabled);
+    }
+
+    #[test]
+    fn test_merge_default_overlay() {
+        let base = Config::from_profile(Profile::Turkey);
+        assert!(base.merge(&Config::default()).unwrap().diff(&base).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+//! Upgrading configuration files written for older versions
+//!
+//! Each migration rewrites the raw TOML of one version into the layout of
+//! the next; a file runs every migration newer than its `general.version`.
+
+use super::Config;
+use crate::error::Result;
+use std::net::Ipv6Addr;
+use toml::value::Table;
+use toml::Value;
+use tracing::info;
+
+/// Migrations in version order, keyed by the version they upgrade to
+const MIGRATIONS: &[(&str, fn(&mut Value))] = &[("2.0", migrate_2_0)];
+
+impl Config {
+    /// Parse a TOML configuration, upgrading it from older versions first
+    ///
+    /// A file without `general.version` runs every migration; they only
+    /// touch keys that are present, so a current file is unchanged.
+    pub fn migrate(content: &str) -> Result<Self> {
+        let mut value: Value = toml::from_str(content)?;
+        let from = file_version(&value).unwrap_or_else(|| "0".to_string());
+
+        for &(version, migration) in MIGRATIONS {
+            if compare_versions(&from, version).is_lt() {
+                migration(&mut value);
+                info!(from = %from, to = version, "Migrated configuration");
+            }
+        }
+
+        if let Some(&(latest, _)) = MIGRATIONS.last() {
+            if compare_versions(&from, latest).is_lt() {
+                section(&mut value, "general").insert("version".into(), latest.into());
+            }
+        }
+        Ok(value.try_into()?)
+    }
+}
+
+fn file_version(value: &Value) -> Option<String> {
+    match value.get("general")?.get("version")? {
+        Value::String(version) => Some(version.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+/// Compare dotted version numbers, treating missing parts as 0
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let parts = |v: &str| -> Vec<u64> {
+        v.split('.').map(|part| part.trim().parse().unwrap_or(0)).collect()
+    };
+    let (mut a, mut b) = (parts(a), parts(b));
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    a.cmp(&b)
+}
+
+/// The table at `name` in the top-level table, created if missing
+fn section<'a>(value: &'a mut Value, name: &str) -> &'a mut Table {
+    let root = value.as_table_mut().expect("a TOML document is a table");
+    let entry = root.entry(name).or_insert_with(|| Value::Table(Table::new()));
+    if !entry.is_table() {
+        *entry = Value::Table(Table::new());
+    }
+    entry.as_table_mut().expect("just made a table")
+}
+
+/// 1.x -> 2.0: fragment sizes moved into `[strategies.fragmentation]` and
+/// the DNS server into `[dns]`, as the upstream of its address family
+fn migrate_2_0(value: &mut Value) {
+    let strategies = section(value, "strategies");
+    for (old, new) in [("http_frag_position", "http_size"), ("https_frag_position", "https_size")] {
+        let Some(size) = strategies.remove(old) else {
+            continue;
+        };
+        let fragmentation = strategies
+            .entry("fragmentation")
+            .or_insert_with(|| Value::Table(Table::new()));
+        if let Some(fragmentation) = fragmentation.as_table_mut() {
+            fragmentation.entry(new).or_insert(size);
+        }
+    }
+    if strategies.is_empty() {
+        if let Some(root) = value.as_table_mut() {
+            root.remove("strategies");
+        }
+    }
+
+    let server = value.as_table_mut().and_then(|root| root.remove("dns_server"));
+    if let Some(server) = server {
+        let is_ipv6 = server.as_str().is_some_and(|ip| ip.parse::<Ipv6Addr>().is_ok());
+        let key = if is_ipv6 { "ipv6_upstream" } else { "ipv4_upstream" };
+        section(value, "dns").entry(key).or_insert(server);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_1_x() {
+        let config = Config::migrate(
+            r#"
+            dns_server = "77.88.8.8"
+
+            [general]
+            version = "1.2"
+
+            [strategies]
+            http_frag_position = 3
+            https_frag_position = 40
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.general.version, "2.0");
+        assert_eq!(config.strategies.fragmentation.http_size, 3);
+        assert_eq!(config.strategies.fragmentation.https_size, 40);
+        assert_eq!(config.dns.ipv4_upstream, Some("77.88.8.8".parse().unwrap()));
+
+        let config = Config::migrate("dns_server = \"2a02:6b8::feed:ff\"").unwrap();
+        assert_eq!(config.dns.ipv6_upstream, Some("2a02:6b8::feed:ff".parse().unwrap()));
+        assert_eq!(config.dns.ipv4_upstream, None);
+    }
+
+    #[test]
+    fn test_current_config_unchanged() {
+        let mut current = Config::default();
+        current.strategies.fragmentation.https_size = 5;
+        let migrated = Config::migrate(&current.to_toml().unwrap()).unwrap();
+        assert!(current.diff(&migrated).unwrap().is_empty());
+
+        // A newer key wins over the old one it replaces
+        let config = Config::migrate(
+            "[strategies]\nhttp_frag_position = 3\n\n[strategies.fragmentation]\nhttp_size = 7\n",
+        )
+        .unwrap();
+        assert_eq!(config.strategies.fragmentation.http_size, 7);
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert!(compare_versions("1.9", "2.0").is_lt());
+        assert!(compare_versions("2", "2.0").is_eq());
+        assert!(compare_versions("2.0.1", "2.0").is_gt());
+        assert!(compare_versions("0", "2.0").is_lt());
+    }
+}
@@ -3,14 +3,32 @@
 //! Provides a strongly-typed configuration system with TOML support
 //! and profile-based presets for different regions/ISPs.
 
+mod diff;
+mod env;
+mod legacy;
+mod merge;
+mod migrate;
+mod overrides;
+mod ports;
 mod profile;
+mod validate;
+mod watch;
 
+pub use diff::{ConfigChange, ConfigDiff};
+pub use legacy::LegacyImport;
+pub use overrides::OverrideConfig;
+pub use ports::PortStrategyConfig;
 pub use profile::Profile;
+pub use validate::{ValidationCheck, ValidationIssue};
+#[cfg(feature = "doh")]
+pub(crate) use validate::doh_endpoint_ip;
+pub use watch::ConfigWatcher;
 
 use crate::error::{Error, Result};
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::path::Path;
 
 /// Main configuration structure
@@ -38,6 +56,10 @@ pub struct Config {
 
     /// Performance tuning
     pub performance: PerformanceConfig,
+
+    /// Strategy settings for specific domains
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<OverrideConfig>,
 }
 
 impl Default for Config {
@@ -50,18 +72,33 @@ impl Default for Config {
             blacklist: BlacklistConfig::default(),
             logging: LoggingConfig::default(),
             performance: PerformanceConfig::default(),
+            overrides: Vec::new(),
         }
     }
 }
 
 impl Config {
     /// Load configuration from a TOML file
+    ///
+    /// Files written for older versions are upgraded with [`Config::migrate`].
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path = path.as_ref();
         let content = std::fs::read_to_string(path).map_err(|_| Error::ConfigNotFound {
             path: path.display().to_string(),
         })?;
-        Self::from_toml(&content)
+        Self::migrate(&content)
+    }
+
+    /// Watch a configuration file and call `on_change` with each valid revision
+    ///
+    /// Changes that fail to parse or validate are logged and skipped. The
+    /// watch lasts as long as the returned [`ConfigWatcher`] is kept alive.
+    pub fn watch<P, F>(path: P, on_change: F) -> Result<ConfigWatcher>
+    where
+        P: AsRef<Path>,
+        F: Fn(Config) + Send + 'static,
+    {
+        ConfigWatcher::new(path.as_ref(), on_change)
     }
 
     /// Parse configuration from TOML string
@@ -69,6 +106,38 @@ impl Config {
         toml::from_str(content).map_err(Error::from)
     }
 
+    /// Parse configuration from JSON string
+    pub fn from_json(content: &str) -> Result<Self> {
+        serde_json::from_str(content).map_err(Error::from)
+    }
+
+    /// Parse configuration from YAML string
+    pub fn from_yaml(content: &str) -> Result<Self> {
+        serde_yaml::from_str(content).map_err(Error::from)
+    }
+
+    /// Load configuration, choosing the format by file extension
+    ///
+    /// `.json` files are parsed as JSON, `.yaml`/`.yml` as YAML, and
+    /// anything else as TOML.
+    pub fn load_auto<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|_| Error::ConfigNotFound {
+            path: path.display().to_string(),
+        })?;
+
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("json") => Self::from_json(&content),
+            Some("yaml" | "yml") => Self::from_yaml(&content),
+            _ => Self::migrate(&content),
+        }
+    }
+
     /// Create configuration from a preset profile
     pub fn from_profile(profile: Profile) -> Self {
         profile.into_config()
@@ -90,58 +159,20 @@ impl Config {
         }
     }
 
-    /// Validate the configuration
-    pub fn validate(&self) -> Result<()> {
-        // Validate DNS settings
-        if self.dns.enabled {
-            if let Some(port) = self.dns.ipv4_port {
-                if port == 0 {
-                    return Err(Error::InvalidPort { port: port as u32 });
-                }
-            }
-        }
-
-        // Validate fragmentation sizes
-        // Note: http_size or https_size can be 0 to disable fragmentation for that protocol
-        if self.strategies.fragmentation.enabled {
-            let http_size = self.strategies.fragmentation.http_size;
-            let https_size = self.strategies.fragmentation.https_size;
-            
-            // At least one must be non-zero if fragmentation is enabled
-            if http_size == 0 && https_size == 0 {
-                return Err(Error::config_value(
-                    "strategies.fragmentation",
-                    "At least one of http_size or https_size must be non-zero when fragmentation is enabled",
-                ));
-            }
-            if http_size > 65535 {
-                return Err(Error::config_value(
-                    "strategies.fragmentation.http_size",
-                    "Must be between 0 and 65535",
-                ));
-            }
-            if https_size > 65535 {
-                return Err(Error::config_value(
-                    "strategies.fragmentation.https_size",
-                    "Must be between 0 and 65535",
-                ));
-            }
-        }
-
-        // Validate TTL settings
-        if let Some(ttl) = self.strategies.fake_packet.ttl {
-            if ttl == 0 {
-                return Err(Error::InvalidTtl { ttl: ttl as u16 });
-            }
-        }
-
-        Ok(())
-    }
-
     /// Serialize to TOML string
     pub fn to_toml(&self) -> Result<String> {
         toml::to_string_pretty(self).map_err(|e| Error::Config(e.to_string()))
     }
+
+    /// Serialize to JSON string
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(Error::from)
+    }
+
+    /// Serialize to YAML string
+    pub fn to_yaml(&self) -> Result<String> {
+        serde_yaml::to_string(self).map_err(Error::from)
+    }
 }
 
 /// General application settings
@@ -186,6 +217,12 @@ pub struct DnsConfig {
     pub ipv6_upstream: Option<Ipv6Addr>,
     /// IPv6 DNS port
     pub ipv6_port: Option<u16>,
+    /// DNS-over-HTTPS endpoint for UDP queries (e.g. `https://1.1.1.1/dns-query`)
+    ///
+    /// Needs the `doh` feature, and the host must be an IP address. TCP
+    /// queries still go to the upstreams above.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doh_url: Option<String>,
     /// Flush DNS cache on start
     pub flush_cache_on_start: bool,
     /// Verbose DNS logging
@@ -201,12 +238,23 @@ impl Default for DnsConfig {
             ipv4_port: Some(53),
             ipv6_upstream: None,
             ipv6_port: Some(53),
+            doh_url: None,
             flush_cache_on_start: true,
             verbose: false,
         }
     }
 }
 
+impl DnsConfig {
+    /// Redirect queries of `server`'s address family to it
+    pub fn set_upstream(&mut self, server: std::net::IpAddr) {
+        match server {
+            std::net::IpAddr::V4(ip) => self.ipv4_upstream = Some(ip),
+            std::net::IpAddr::V6(ip) => self.ipv6_upstream = Some(ip),
+        }
+    }
+}
+
 /// All strategy configurations
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
@@ -221,6 +269,11 @@ pub struct StrategiesConfig {
     pub quic_block: QuicBlockConfig,
     /// Passive DPI blocking
     pub passive_dpi: PassiveDpiConfig,
+    /// Out-of-order ClientHello segments
+    pub disorder: DisorderConfig,
+    /// How to treat destination ports other than 80/443
+    #[serde(with = "ports::port_map", skip_serializing_if = "HashMap::is_empty")]
+    pub port_overrides: HashMap<u16, PortStrategyConfig>,
 
     // Convenience shortcuts (CLI compatibility)
     /// Block QUIC (shortcut)
@@ -257,6 +310,8 @@ impl Default for StrategiesConfig {
             header_mangle: HeaderMangleConfig::default(),
             quic_block: QuicBlockConfig::default(),
             passive_dpi: PassiveDpiConfig::default(),
+            disorder: DisorderConfig::default(),
+            port_overrides: HashMap::new(),
             block_quic: true,
             auto_ttl: false,
             fake_ttl: None,
@@ -288,6 +343,12 @@ pub struct FragmentationConfig {
     pub http_persistent: bool,
     /// Don't wait for ACK in persistent mode
     pub persistent_nowait: bool,
+    /// Split at these payload offsets (e.g. [1, 2, 3] for four fragments);
+    /// overrides http_size/https_size when non-empty
+    pub fragment_positions: Vec<u16>,
+    /// Send the fragment holding the SNI/Host a second time, with the same
+    /// SEQ, so the server sees a retransmission
+    pub duplicate_first_fragment: bool,
 }
 
 impl Default for FragmentationConfig {
@@ -301,6 +362,8 @@ impl Default for FragmentationConfig {
             by_sni: false,
             http_persistent: true,
             persistent_nowait: true,
+            fragment_positions: Vec::new(),
+            duplicate_first_fragment: false,
         }
     }
 }
@@ -315,6 +378,10 @@ pub struct FakePacketConfig {
     pub wrong_checksum: bool,
     /// Use wrong SEQ/ACK numbers
     pub wrong_seq: bool,
+    /// Added to the SEQ of `wrong_seq` fakes (negative = past, positive = future)
+    pub seq_offset: i64,
+    /// Added to the ACK of `wrong_seq` fakes
+    pub ack_offset: i64,
     /// Fixed TTL value (None = auto)
     pub ttl: Option<u8>,
     /// Auto TTL configuration
@@ -337,6 +404,8 @@ impl Default for FakePacketConfig {
             enabled: true,
             wrong_checksum: true,
             wrong_seq: true,
+            seq_offset: -10000,
+            ack_offset: -66000,
             ttl: None,
             auto_ttl: None,
             min_ttl_hops: None,
@@ -348,6 +417,41 @@ impl Default for FakePacketConfig {
     }
 }
 
+impl FakePacketConfig {
+    /// Decode hex-encoded fake payloads from configuration
+    ///
+    /// Whitespace and `:` separators are allowed (e.g. `"16 03 01"`).
+    pub fn decode_custom_payloads(&self) -> Result<Vec<Bytes>> {
+        self.custom_payloads
+            .iter()
+            .enumerate()
+            .map(|(i, payload)| decode_payload(i, payload))
+            .collect()
+    }
+}
+
+/// Decode the `index`th custom fake payload
+fn decode_payload(index: usize, payload: &str) -> Result<Bytes> {
+    let cleaned: String = payload
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .collect();
+
+    let bytes = hex::decode(&cleaned).map_err(|e| {
+        Error::config_value(
+            format!("strategies.fake_packet.custom_payloads[{index}]"),
+            format!("Invalid hex: {e}"),
+        )
+    })?;
+    if bytes.is_empty() {
+        return Err(Error::config_value(
+            format!("strategies.fake_packet.custom_payloads[{index}]"),
+            "Payload must not be empty",
+        ));
+    }
+    Ok(Bytes::from(bytes))
+}
+
 /// Auto TTL configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AutoTtlConfig {
@@ -403,11 +507,19 @@ impl Default for HeaderMangleConfig {
 pub struct QuicBlockConfig {
     /// Enable QUIC/HTTP3 blocking
     pub enabled: bool,
+    /// Only block QUIC to blacklisted domains (decrypts the Initial to read SNI)
+    pub selective: bool,
+    /// Drop all outbound UDP 443, not just QUIC Initial packets
+    pub drop_all: bool,
 }
 
 impl Default for QuicBlockConfig {
     fn default() -> Self {
-        Self { enabled: true }
+        Self {
+            enabled: true,
+            selective: false,
+            drop_all: false,
+        }
     }
 }
 
@@ -430,6 +542,35 @@ impl Default for PassiveDpiConfig {
     }
 }
 
+/// Disorder strategy configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisorderConfig {
+    /// Enable the disorder strategy
+    pub enabled: bool,
+    /// ClientHello offset to split at
+    pub split_position: u16,
+    /// Send a copy of the first segment with a bad checksum and low TTL
+    /// ahead of the real ones
+    pub fooling: bool,
+    /// Fixed TTL of the fooling copy (None = auto)
+    pub ttl: Option<u8>,
+    /// Auto TTL configuration for the fooling copy
+    pub auto_ttl: Option<AutoTtlConfig>,
+}
+
+impl Default for DisorderConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            split_position: 2,
+            fooling: true,
+            ttl: None,
+            auto_ttl: None,
+        }
+    }
+}
+
 /// Domain filtering configuration (whitelist/blacklist)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
@@ -437,9 +578,11 @@ pub struct BlacklistConfig {
     /// Enable domain filtering
     pub enabled: bool,
     
-    /// Filter mode: "whitelist", "blacklist", or "disabled"
+    /// Filter mode: "whitelist", "blacklist", "hybrid" or "disabled"
     /// - whitelist: Listed domains SKIP bypass (banks, government sites)
     /// - blacklist: ONLY listed domains get bypass applied
+    /// - hybrid: `whitelist_file` entries skip bypass, blacklist entries
+    ///   get it, the rest follow `hybrid_default`
     pub mode: String,
     
     /// Local file path for domain list (auto-reloaded on change)
@@ -455,6 +598,17 @@ pub struct BlacklistConfig {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub files: Vec<String>,
     
+    /// Hybrid mode: domains that skip bypass, checked first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whitelist_file: Option<String>,
+
+    /// Hybrid mode: domains that get bypass, in addition to `file_path`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blacklist_file: Option<String>,
+
+    /// Hybrid mode: apply bypass to domains on neither list
+    pub hybrid_default: bool,
+    
     /// Allow connections without SNI when filtering is enabled
     pub allow_no_sni: bool,
     
@@ -470,6 +624,9 @@ impl Default for BlacklistConfig {
             file_path: None,
             domains: Vec::new(),
             files: Vec::new(),
+            whitelist_file: None,
+            blacklist_file: None,
+            hybrid_default: true,
             allow_no_sni: false,
             auto_reload_interval: 30,
         }
@@ -512,6 +669,8 @@ pub struct PerformanceConfig {
     pub max_payload_size: u16,
     /// Number of worker threads (0 = auto)
     pub worker_threads: u8,
+    /// Packets received/sent per driver call (1 = no batching)
+    pub batch_size: u8,
     /// Connection tracking table max entries
     pub conntrack_max_entries: usize,
     /// Connection tracking cleanup interval (seconds)
@@ -520,6 +679,8 @@ pub struct PerformanceConfig {
     pub http_all_ports: bool,
     /// Additional ports to process
     pub additional_ports: Vec<u16>,
+    /// Address to serve Prometheus metrics on (needs the `metrics` feature)
+    pub metrics_addr: Option<SocketAddr>,
 }
 
 impl Default for PerformanceConfig {
@@ -527,10 +688,12 @@ impl Default for PerformanceConfig {
         Self {
             max_payload_size: 1200,
             worker_threads: 0,
+            batch_size: 32,
             conntrack_max_entries: 10000,
             conntrack_cleanup_interval: 30,
             http_all_ports: false,
             additional_ports: Vec::new(),
+            metrics_addr: None,
         }
     }
 }
@@ -572,6 +735,7 @@ mod tests {
         let config = PerformanceConfig::default();
         assert_eq!(config.max_payload_size, 1200);
         assert_eq!(config.worker_threads, 0);
+        assert_eq!(config.batch_size, 32);
         assert_eq!(config.conntrack_max_entries, 10000);
         assert!(config.additional_ports.is_empty());
     }
@@ -606,6 +770,19 @@ mod tests {
         assert!(config.validate().is_ok());
     }
 
+    #[test]
+    fn test_config_validation_fragment_positions() {
+        let mut config = Config::default();
+        config.strategies.fragmentation.fragment_positions = vec![1, 2, 3];
+        assert!(config.validate().is_ok());
+
+        config.strategies.fragmentation.fragment_positions = vec![2, 2];
+        assert!(config.validate().is_err());
+
+        config.strategies.fragmentation.fragment_positions = vec![0, 4];
+        assert!(config.validate().is_err());
+    }
+
     #[test]
     fn test_config_validation_invalid_ttl() {
         let mut config = Config::default();
@@ -622,6 +799,19 @@ mod tests {
 
     // =========== TOML Serialization Tests ===========
     
+    #[test]
+    fn test_config_validation_custom_payloads() {
+        let mut config = Config::default();
+        config.strategies.fake_packet.custom_payloads =
+            vec!["160301".to_string(), "de:ad:be:ef".to_string()];
+        assert!(config.validate().is_ok());
+        assert_eq!(config.strategies.fake_packet.decode_custom_payloads().unwrap().len(), 2);
+
+        config.strategies.fake_packet.custom_payloads.push("not hex".to_string());
+        let err = config.validate().unwrap_err();
+        assert!(matches!(err, Error::ConfigValue { ref key, .. } if key == "strategies.fake_packet.custom_payloads[2]"));
+    }
+
     #[test]
     fn test_toml_roundtrip() {
         let config = Config::default();
@@ -646,6 +836,69 @@ mod tests {
         assert!(parsed.strategies.quic_block.enabled);
     }
 
+    /// Config with non-default values in most sections
+    fn custom_config() -> Config {
+        let mut config = Config::from_profile(Profile::Turkey);
+        config.dns.enabled = true;
+        config.dns.ipv4_upstream = Some(Ipv4Addr::new(77, 88, 8, 8));
+        config.dns.ipv6_upstream = Some("2a02:6b8::feed:0ff".parse().unwrap());
+        config.strategies.fragmentation.fragment_positions = vec![1, 5, 40];
+        config.strategies.fake_packet.custom_payloads = vec!["160301".to_string()];
+        config.strategies.fake_packet.auto_ttl = Some(AutoTtlConfig::default());
+        config.strategies.quic_block.selective = true;
+        config.logging.level = "debug".to_string();
+        config.performance.additional_ports = vec![8080, 8443];
+        config
+    }
+
+    #[test]
+    fn test_toml_roundtrip_full() {
+        let config = custom_config();
+        let parsed = Config::from_toml(&config.to_toml().unwrap()).unwrap();
+        assert_eq!(format!("{parsed:?}"), format!("{config:?}"));
+    }
+
+    #[test]
+    fn test_json_roundtrip_full() {
+        let config = custom_config();
+        let parsed = Config::from_json(&config.to_json().unwrap()).unwrap();
+        assert_eq!(format!("{parsed:?}"), format!("{config:?}"));
+    }
+
+    #[test]
+    fn test_yaml_roundtrip() {
+        let config = Config::default();
+        let parsed = Config::from_yaml(&config.to_yaml().unwrap()).unwrap();
+        assert_eq!(format!("{parsed:?}"), format!("{config:?}"));
+
+        let config = custom_config();
+        let parsed = Config::from_yaml(&config.to_yaml().unwrap()).unwrap();
+        assert_eq!(format!("{parsed:?}"), format!("{config:?}"));
+    }
+
+    #[test]
+    fn test_load_auto_by_extension() {
+        let config = custom_config();
+        let dir = tempfile::tempdir().unwrap();
+
+        let json = dir.path().join("config.json");
+        std::fs::write(&json, config.to_json().unwrap()).unwrap();
+        let toml = dir.path().join("config.toml");
+        std::fs::write(&toml, config.to_toml().unwrap()).unwrap();
+        let yaml = dir.path().join("config.yml");
+        std::fs::write(&yaml, config.to_yaml().unwrap()).unwrap();
+
+        for path in [&json, &toml, &yaml] {
+            let loaded = Config::load_auto(path).unwrap();
+            assert_eq!(format!("{loaded:?}"), format!("{config:?}"));
+        }
+
+        // JSON is not accepted as TOML
+        let mislabeled = dir.path().join("config.conf");
+        std::fs::write(&mislabeled, config.to_json().unwrap()).unwrap();
+        assert!(Config::load_auto(&mislabeled).is_err());
+    }
+
     #[test]
     fn test_toml_parse_minimal() {
         let toml_content = r#"
@@ -0,0 +1,136 @@
+//! Per-domain strategy overrides
+//!
+//! An `[[overrides]]` entry lists domains and the strategy settings that
+//! differ for them; everything left out is taken from `[strategies]`.
+
+use super::{Config, StrategiesConfig};
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Strategy settings used for a set of domains
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverrideConfig {
+    /// Domains the override applies to, in domain filter syntax
+    pub domains: Vec<String>,
+    /// Partial `[strategies]` table merged over the base strategies
+    pub strategies: Map<String, Value>,
+}
+
+impl OverrideConfig {
+    /// The base strategies with this override's settings merged in
+    ///
+    /// Tables are merged key by key, anything else replaces the base
+    /// value. `key` names the override in errors (e.g. "overrides[0]").
+    pub fn merged_strategies(&self, base: &StrategiesConfig, key: &str) -> Result<StrategiesConfig> {
+        let key = format!("{key}.strategies");
+        let mut tree = serde_json::to_value(base)?;
+        let Some(sections) = tree.as_object_mut() else {
+            return Err(Error::config_value(key, "Strategies are not a table"));
+        };
+
+        for (name, value) in &self.strategies {
+            // Sections left out of the serialized base (false shortcuts)
+            // are still known fields of StrategiesConfig
+            let known = sections.contains_key(name) || SHORTCUTS.contains(&name.as_str());
+            if !known {
+                return Err(Error::config_value(&key, format!("Unknown strategy '{name}'")));
+            }
+            merge(sections.entry(name.as_str()).or_insert(Value::Null), value);
+        }
+
+        serde_json::from_value(tree).map_err(|e| Error::config_value(key, e.to_string()))
+    }
+
+    /// `base` with its strategies replaced by [`merged_strategies`](Self::merged_strategies)
+    pub fn apply_to(&self, base: &Config, key: &str) -> Result<Config> {
+        Ok(Config {
+            strategies: self.merged_strategies(&base.strategies, key)?,
+            ..base.clone()
+        })
+    }
+}
+
+/// Strategy fields skipped when serialized at their default
+const SHORTCUTS: [&str; 6] = [
+    "port_overrides",
+    "block_quic",
+    "auto_ttl",
+    "fake_ttl",
+    "fake_with_wrong_checksum",
+    "fake_with_wrong_seq",
+];
+
+fn merge(target: &mut Value, patch: &Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (name, value) in patch {
+                merge(target.entry(name.as_str()).or_insert(Value::Null), value);
+            }
+        }
+        (target, patch) => *target = patch.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_merge_over_base() {
+        let config = Config::from_toml(
+            r#"
+            [strategies.fragmentation]
+            enabled = true
+            https_size = 2
+
+            [[overrides]]
+            domains = ["*.discord.com"]
+            strategies = { fragmentation = { https_size = 40 }, fake_packet = { enabled = false } }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.overrides.len(), 1);
+        assert_eq!(config.overrides[0].domains, ["*.discord.com"]);
+        let reparsed = Config::from_toml(&config.to_toml().unwrap()).unwrap();
+        assert_eq!(reparsed.overrides[0].strategies, config.overrides[0].strategies);
+
+        let merged = config.overrides[0].apply_to(&config, "overrides[0]").unwrap();
+        assert!(merged.strategies.fragmentation.enabled);
+        assert_eq!(merged.strategies.fragmentation.https_size, 40);
+        assert!(!merged.strategies.fake_packet.enabled);
+        // Untouched settings come from the base
+        assert_eq!(
+            merged.strategies.fragmentation.http_size,
+            config.strategies.fragmentation.http_size
+        );
+    }
+
+    #[test]
+    fn test_override_errors() {
+        let base = StrategiesConfig::default();
+
+        let unknown = OverrideConfig {
+            domains: vec!["discord.com".into()],
+            strategies: serde_json::from_str(r#"{ "fragmentaton": { "enabled": true } }"#).unwrap(),
+        };
+        assert!(matches!(
+            unknown.merged_strategies(&base, "overrides[1]"),
+            Err(Error::ConfigValue { ref key, .. }) if key == "overrides[1].strategies"
+        ));
+
+        let wrong_type = OverrideConfig {
+            domains: vec!["discord.com".into()],
+            strategies: serde_json::from_str(r#"{ "fragmentation": { "https_size": "big" } }"#).unwrap(),
+        };
+        assert!(wrong_type.merged_strategies(&base, "overrides[0]").is_err());
+
+        let shortcut = OverrideConfig {
+            domains: vec!["discord.com".into()],
+            strategies: serde_json::from_str(r#"{ "auto_ttl": true }"#).unwrap(),
+        };
+        assert!(shortcut.merged_strategies(&base, "overrides[0]").unwrap().auto_ttl);
+    }
+}
@@ -0,0 +1,156 @@
+//! Per-port strategy routing
+//!
+//! `[strategies.port_overrides.8443]` tells the strategies how to treat
+//! traffic to a port other than 80/443.
+
+use super::Config;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How the strategies treat traffic to one destination port
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PortStrategyConfig {
+    /// Handle HTTP requests to this port like port 80
+    pub use_http_strategies: bool,
+    /// Handle TLS ClientHellos to this port like port 443
+    pub use_https_strategies: bool,
+    /// Fragment size instead of `http_size`/`https_size`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom_fragment_size: Option<u16>,
+}
+
+impl Default for PortStrategyConfig {
+    fn default() -> Self {
+        Self {
+            use_http_strategies: true,
+            use_https_strategies: true,
+            custom_fragment_size: None,
+        }
+    }
+}
+
+impl Config {
+    /// Port overrides with an entry for every `performance.additional_ports`
+    ///
+    /// Additional ports get both HTTP and HTTPS handling unless
+    /// `strategies.port_overrides` says otherwise.
+    pub fn port_overrides(&self) -> HashMap<u16, PortStrategyConfig> {
+        let mut ports = self.strategies.port_overrides.clone();
+        for &port in &self.performance.additional_ports {
+            ports.entry(port).or_default();
+        }
+        ports
+    }
+
+    /// Destination ports besides 80/443 the strategies act on, sorted
+    pub fn bypass_ports(&self) -> Vec<u16> {
+        let mut ports: Vec<u16> = self.port_overrides().into_keys().collect();
+        ports.sort_unstable();
+        ports
+    }
+}
+
+/// (De)serializes a port map with string keys, as TOML requires
+pub(super) mod port_map {
+    use super::PortStrategyConfig;
+    use serde::de::{self, Deserializer, Visitor};
+    use serde::{Deserialize, Serializer};
+    use std::collections::{BTreeMap, HashMap};
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(
+        ports: &HashMap<u16, PortStrategyConfig>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let sorted: BTreeMap<String, &PortStrategyConfig> =
+            ports.iter().map(|(port, config)| (port.to_string(), config)).collect();
+        serializer.collect_map(sorted)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<u16, PortStrategyConfig>, D::Error> {
+        let ports: HashMap<Port, PortStrategyConfig> = HashMap::deserialize(deserializer)?;
+        Ok(ports.into_iter().map(|(Port(port), config)| (port, config)).collect())
+    }
+
+    /// A port written as a string key (TOML, JSON) or a number (YAML)
+    #[derive(PartialEq, Eq, Hash)]
+    struct Port(u16);
+
+    impl<'de> Deserialize<'de> for Port {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct PortVisitor;
+
+            impl Visitor<'_> for PortVisitor {
+                type Value = Port;
+
+                fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.write_str("a port number")
+                }
+
+                fn visit_u64<E: de::Error>(self, value: u64) -> Result<Port, E> {
+                    u16::try_from(value)
+                        .map(Port)
+                        .map_err(|_| E::custom(format!("port out of range: {value}")))
+                }
+
+                fn visit_i64<E: de::Error>(self, value: i64) -> Result<Port, E> {
+                    u16::try_from(value)
+                        .map(Port)
+                        .map_err(|_| E::custom(format!("port out of range: {value}")))
+                }
+
+                fn visit_str<E: de::Error>(self, value: &str) -> Result<Port, E> {
+                    value
+                        .parse()
+                        .map(Port)
+                        .map_err(|_| E::custom(format!("invalid port: {value}")))
+                }
+            }
+
+            deserializer.deserialize_any(PortVisitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_overrides_round_trip() {
+        let config = Config::from_toml(
+            r#"
+            [performance]
+            additional_ports = [8080, 8443]
+
+            [strategies.port_overrides.8443]
+            use_http_strategies = false
+            custom_fragment_size = 5
+            "#,
+        )
+        .unwrap();
+
+        let https_only = PortStrategyConfig {
+            use_http_strategies: false,
+            use_https_strategies: true,
+            custom_fragment_size: Some(5),
+        };
+        assert_eq!(config.strategies.port_overrides[&8443], https_only);
+
+        let ports = config.port_overrides();
+        assert_eq!(ports[&8443], https_only);
+        assert_eq!(ports[&8080], PortStrategyConfig::default());
+        assert_eq!(config.bypass_ports(), [8080, 8443]);
+
+        let toml = Config::from_toml(&config.to_toml().unwrap()).unwrap();
+        assert_eq!(toml.strategies.port_overrides, config.strategies.port_overrides);
+        let json = Config::from_json(&config.to_json().unwrap()).unwrap();
+        assert_eq!(json.strategies.port_overrides, config.strategies.port_overrides);
+        let yaml = Config::from_yaml("strategies:\n  port_overrides:\n    8443:\n      use_http_strategies: false\n")
+            .unwrap();
+        assert!(!yaml.strategies.port_overrides[&8443].use_http_strategies);
+    }
+}
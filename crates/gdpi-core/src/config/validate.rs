@@ -0,0 +1,347 @@
+//! Configuration validation
+//!
+//! Each check runs independently and reports every problem it finds, so a
+//! single pass can list all of them; [`Config::validate`] returns the first.
+
+use super::{decode_payload, Config};
+use crate::error::{Error, Result};
+use std::fmt;
+use std::net::IpAddr;
+
+/// A problem found in a configuration
+#[derive(Debug)]
+pub struct ValidationIssue {
+    /// Path of the offending key (e.g. "dns.ipv4_port")
+    pub key: String,
+    /// What is wrong
+    pub error: Error,
+    /// How to fix it
+    pub suggestion: &'static str,
+}
+
+impl ValidationIssue {
+    fn new(key: impl Into<String>, error: Error, suggestion: &'static str) -> Self {
+        Self {
+            key: key.into(),
+            error,
+            suggestion,
+        }
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.error)
+    }
+}
+
+/// Outcome of a single validation check
+#[derive(Debug)]
+pub struct ValidationCheck {
+    /// What was checked (e.g. "DNS port")
+    pub name: &'static str,
+    /// Problems found, empty if the check passed
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationCheck {
+    /// Whether the check passed
+    pub fn passed(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl Config {
+    /// Validate the configuration, failing with the first problem found
+    pub fn validate(&self) -> Result<()> {
+        match self.validation_issues().into_iter().next() {
+            Some(issue) => Err(issue.error),
+            None => Ok(()),
+        }
+    }
+
+    /// Every problem in the configuration, in check order
+    pub fn validation_issues(&self) -> Vec<ValidationIssue> {
+        self.validation_report()
+            .into_iter()
+            .flat_map(|check| check.issues)
+            .collect()
+    }
+
+    /// Run every validation check, collecting all failures
+    pub fn validation_report(&self) -> Vec<ValidationCheck> {
+        let checks: [(&'static str, fn(&Config) -> Vec<ValidationIssue>); 8] = [
+            ("DNS port", check_dns_ports),
+            ("DNS-over-HTTPS endpoint", check_doh_url),
+            ("Fragmentation sizes", check_fragment_sizes),
+            ("Fragment positions", check_fragment_positions),
+            ("Fake packet TTL", check_fake_ttl),
+            ("Fake packet SEQ offset", check_fake_seq_offset),
+            ("Custom fake payloads", check_fake_payloads),
+            ("Strategy overrides", check_overrides),
+        ];
+
+        checks
+            .into_iter()
+            .map(|(name, check)| ValidationCheck {
+                name,
+                issues: check(self),
+            })
+            .collect()
+    }
+}
+
+fn check_dns_ports(config: &Config) -> Vec<ValidationIssue> {
+    if !config.dns.enabled {
+        return Vec::new();
+    }
+
+    [
+        ("dns.ipv4_port", config.dns.ipv4_port),
+        ("dns.ipv6_port", config.dns.ipv6_port),
+    ]
+    .into_iter()
+    .filter(|(_, port)| *port == Some(0))
+    .map(|(key, _)| {
+        ValidationIssue::new(
+            key,
+            Error::InvalidPort { port: 0 },
+            "Use the resolver's port (53 for plain DNS) or remove the key for the default",
+        )
+    })
+    .collect()
+}
+
+fn check_doh_url(config: &Config) -> Vec<ValidationIssue> {
+    match config.dns.doh_url.as_deref() {
+        Some(url) if config.dns.enabled && doh_endpoint_ip(url).is_none() => vec![ValidationIssue::new(
+            "dns.doh_url",
+            Error::config_value("dns.doh_url", format!("Endpoint host must be an IP address: {}", url)),
+            "Use the resolver's address, e.g. https://1.1.1.1/dns-query",
+        )],
+        _ => Vec::new(),
+    }
+}
+
+/// IP address in the host part of a DNS-over-HTTPS endpoint URL
+///
+/// The endpoint can't be a hostname: looking it up would send a UDP DNS
+/// query that only the endpoint itself could answer.
+pub(crate) fn doh_endpoint_ip(url: &str) -> Option<IpAddr> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    if let Some(bracketed) = host.strip_prefix('[') {
+        let (ip, port) = bracketed.split_once(']')?;
+        return (port.is_empty() || port.starts_with(':')).then(|| ip.parse().ok())?;
+    }
+    host.split_once(':').map_or(host, |(host, _)| host).parse().ok()
+}
+
+fn check_fragment_sizes(config: &Config) -> Vec<ValidationIssue> {
+    let fragmentation = &config.strategies.fragmentation;
+    // http_size or https_size can be 0 to disable fragmentation for that protocol,
+    // but at least one must be non-zero if fragmentation is enabled
+    if fragmentation.enabled && fragmentation.http_size == 0 && fragmentation.https_size == 0 {
+        return vec![ValidationIssue::new(
+            "strategies.fragmentation",
+            Error::config_value(
+                "strategies.fragmentation",
+                "At least one of http_size or https_size must be non-zero when fragmentation is enabled",
+            ),
+            "Set https_size = 2 (and/or http_size = 2), or set enabled = false",
+        )];
+    }
+    Vec::new()
+}
+
+fn check_fragment_positions(config: &Config) -> Vec<ValidationIssue> {
+    let fragmentation = &config.strategies.fragmentation;
+    let positions = &fragmentation.fragment_positions;
+    if fragmentation.enabled
+        && (positions.first() == Some(&0) || positions.windows(2).any(|w| w[0] >= w[1]))
+    {
+        return vec![ValidationIssue::new(
+            "strategies.fragmentation.fragment_positions",
+            Error::config_value(
+                "strategies.fragmentation.fragment_positions",
+                "Positions must be non-zero and strictly increasing",
+            ),
+            "List split offsets in ascending order without duplicates, e.g. [1, 3]",
+        )];
+    }
+    Vec::new()
+}
+
+fn check_fake_ttl(config: &Config) -> Vec<ValidationIssue> {
+    if config.strategies.fake_packet.ttl == Some(0) {
+        return vec![ValidationIssue::new(
+            "strategies.fake_packet.ttl",
+            Error::InvalidTtl { ttl: 0 },
+            "Use a TTL that reaches the DPI box but not the server (e.g. 3-8), or enable auto_ttl",
+        )];
+    }
+    Vec::new()
+}
+
+fn check_fake_seq_offset(config: &Config) -> Vec<ValidationIssue> {
+    let fake_packet = &config.strategies.fake_packet;
+    // With no offset the fake is a valid copy of the real segment's sequence
+    // space and the server would accept its payload
+    if fake_packet.enabled && fake_packet.wrong_seq && fake_packet.seq_offset == 0 {
+        return vec![ValidationIssue::new(
+            "strategies.fake_packet.seq_offset",
+            Error::config_value(
+                "strategies.fake_packet.seq_offset",
+                "Must be non-zero when wrong_seq is enabled",
+            ),
+            "Use a negative offset (e.g. -10000) for a past SEQ or a positive one for a future SEQ",
+        )];
+    }
+    Vec::new()
+}
+
+fn check_fake_payloads(config: &Config) -> Vec<ValidationIssue> {
+    let fake_packet = &config.strategies.fake_packet;
+    if !fake_packet.enabled {
+        return Vec::new();
+    }
+
+    fake_packet
+        .custom_payloads
+        .iter()
+        .enumerate()
+        .filter_map(|(i, payload)| decode_payload(i, payload).err().map(|error| (i, error)))
+        .map(|(i, error)| {
+            ValidationIssue::new(
+                format!("strategies.fake_packet.custom_payloads[{i}]"),
+                error,
+                "Write the payload as hex bytes, e.g. \"16 03 01 00 2f\"",
+            )
+        })
+        .collect()
+}
+
+fn check_overrides(config: &Config) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+    for (i, entry) in config.overrides.iter().enumerate() {
+        let key = format!("overrides[{i}]");
+        if entry.domains.is_empty() {
+            issues.push(ValidationIssue::new(
+                format!("{key}.domains"),
+                Error::config_value(format!("{key}.domains"), "Must list at least one domain"),
+                "Add the domains the override is for, e.g. domains = [\"*.discord.com\"]",
+            ));
+        }
+        if let Err(error) = entry.merged_strategies(&config.strategies, &key) {
+            issues.push(ValidationIssue::new(
+                format!("{key}.strategies"),
+                error,
+                "Use the same tables and keys as [strategies], e.g. { fragmentation = { enabled = false } }",
+            ));
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_collects_all_failures() {
+        let mut config = Config::default();
+        config.strategies.fragmentation.enabled = true;
+        config.strategies.fragmentation.fragment_positions = vec![5, 5];
+        config.strategies.fake_packet.ttl = Some(0);
+
+        let report = config.validation_report();
+        let failed: Vec<_> = report.iter().filter(|c| !c.passed()).map(|c| c.name).collect();
+        assert_eq!(failed, ["Fragment positions", "Fake packet TTL"]);
+
+        // validate() reports the first failure
+        assert!(matches!(config.validate(), Err(Error::ConfigValue { .. })));
+    }
+
+    #[test]
+    fn test_doh_endpoint_ip() {
+        let ip = |url| doh_endpoint_ip(url).map(|ip| ip.to_string());
+        assert_eq!(ip("https://1.1.1.1/dns-query").as_deref(), Some("1.1.1.1"));
+        assert_eq!(ip("https://8.8.8.8:443/dns-query?ct=1").as_deref(), Some("8.8.8.8"));
+        assert_eq!(ip("https://[2606:4700::1111]/dns-query").as_deref(), Some("2606:4700::1111"));
+        assert_eq!(ip("https://[2606:4700::1111]:8443").as_deref(), Some("2606:4700::1111"));
+        assert_eq!(ip("https://cloudflare-dns.com/dns-query"), None);
+        assert_eq!(ip("https://[::1]x/dns-query"), None);
+        assert_eq!(ip("1.1.1.1/dns-query"), None);
+
+        let mut config = Config::default();
+        config.dns.enabled = true;
+        config.dns.doh_url = Some("https://cloudflare-dns.com/dns-query".into());
+        assert_eq!(config.validation_issues()[0].key, "dns.doh_url");
+        config.dns.doh_url = Some("https://1.1.1.1/dns-query".into());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_report_all_pass() {
+        assert!(Config::default().validation_report().iter().all(ValidationCheck::passed));
+        assert!(Config::default().validation_issues().is_empty());
+    }
+
+    #[test]
+    fn test_zero_seq_offset_rejected_with_wrong_seq() {
+        let mut config = Config::default();
+        config.strategies.fake_packet.wrong_seq = true;
+        config.strategies.fake_packet.seq_offset = 0;
+        assert!(matches!(
+            config.validate(),
+            Err(Error::ConfigValue { ref key, .. }) if key == "strategies.fake_packet.seq_offset"
+        ));
+
+        config.strategies.fake_packet.wrong_seq = false;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_overrides() {
+        let config = Config::from_toml(
+            r#"
+            [[overrides]]
+            domains = []
+            strategies = { fragmentation = { enabled = false } }
+
+            [[overrides]]
+            domains = ["discord.com"]
+            strategies = { fragmentation = { https_size = -1 } }
+            "#,
+        )
+        .unwrap();
+
+        let issues = config.validation_issues();
+        let keys: Vec<_> = issues.iter().map(|issue| issue.key.as_str()).collect();
+        assert_eq!(keys, ["overrides[0].domains", "overrides[1].strategies"]);
+    }
+
+    #[test]
+    fn test_issues_for_dns_port_and_fragment_sizes() {
+        let config = Config::from_toml(
+            r#"
+            [dns]
+            enabled = true
+            ipv4_port = 0
+
+            [strategies.fragmentation]
+            enabled = true
+            http_size = 0
+            https_size = 0
+            "#,
+        )
+        .unwrap();
+
+        let issues = config.validation_issues();
+        let keys: Vec<_> = issues.iter().map(|issue| issue.key.as_str()).collect();
+        assert_eq!(keys, ["dns.ipv4_port", "strategies.fragmentation"]);
+        assert!(issues.iter().all(|issue| !issue.suggestion.is_empty()));
+        assert!(matches!(config.validate(), Err(Error::InvalidPort { port: 0 })));
+    }
+}
@@ -0,0 +1,126 @@
+//! Configuration file watching
+//!
+//! Re-reads the configuration when the file changes on disk so a running
+//! instance can pick up new settings without a restart.
+
+use super::Config;
+use crate::error::{Error, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// Handle for an active configuration watch
+///
+/// Watching stops when the handle is dropped.
+pub struct ConfigWatcher {
+    watcher: RecommendedWatcher,
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl ConfigWatcher {
+    pub(super) fn new<F>(path: &Path, on_change: F) -> Result<Self>
+    where
+        F: Fn(Config) + Send + 'static,
+    {
+        let path = std::fs::canonicalize(path).map_err(|_| Error::ConfigNotFound {
+            path: path.display().to_string(),
+        })?;
+        // Editors often save by replacing the file, which drops a watch on
+        // the file itself, so watch the parent directory and filter by name.
+        let dir = path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        let target = path.clone();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+            match res {
+                Ok(event) if is_change_to(&event, &target) => reload(&target, &on_change),
+                Ok(_) => {}
+                Err(e) => warn!(error = %e, "Config watcher error"),
+            }
+        })
+        .map_err(|e| Error::Config(format!("failed to create file watcher: {}", e)))?;
+
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(|e| Error::Config(format!("failed to watch {}: {}", dir.display(), e)))?;
+
+        info!(path = %path.display(), "Watching configuration file");
+
+        Ok(Self { watcher, dir, path })
+    }
+
+    /// Path of the watched configuration file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        let _ = self.watcher.unwatch(&self.dir);
+        debug!(path = %self.path.display(), "Stopped watching configuration file");
+    }
+}
+
+fn is_change_to(event: &Event, target: &Path) -> bool {
+    matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+        && event.paths.iter().any(|p| p.file_name() == target.file_name())
+}
+
+fn reload<F: Fn(Config)>(path: &Path, on_change: &F) {
+    // Partially written files fail to parse; the next event retries
+    match Config::load_auto(path).and_then(|config| config.validate().map(|_| config)) {
+        Ok(config) => {
+            info!(path = %path.display(), "Configuration changed, reloading");
+            on_change(config);
+        }
+        Err(e) => warn!(error = %e, "Ignoring invalid configuration change"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_watch_reports_valid_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[general]\nname = \"before\"\n").unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let _watcher = Config::watch(&path, move |config| {
+            let _ = tx.send(config.general.name);
+        })
+        .unwrap();
+
+        // Invalid TOML is ignored
+        std::fs::write(&path, "[general\n").unwrap();
+        std::fs::write(&path, "[general]\nname = \"after\"\n").unwrap();
+
+        // A truncated intermediate write may be seen first; wait for the final one
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        let mut seen = None;
+        while let Some(remaining) = deadline.checked_duration_since(std::time::Instant::now()) {
+            match rx.recv_timeout(remaining) {
+                Ok(name) if name == "after" => {
+                    seen = Some(name);
+                    break;
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+        assert_eq!(seen.as_deref(), Some("after"));
+    }
+
+    #[test]
+    fn test_watch_missing_file() {
+        assert!(Config::watch("/nonexistent/config.toml", |_| {}).is_err());
+    }
+}
@@ -3,9 +3,16 @@
 //! Tracks DNS queries for response remapping.
 //! When we redirect a DNS query to an alternative DNS server,
 //! we need to remember where to send the response back.
+//! DNS-over-TCP connections are tracked per flow, like a small NAT table.
 
-use dashmap::DashMap;
+use crate::config::PerformanceConfig;
+use crate::packet::TcpFlags;
+use lru::LruCache;
+use parking_lot::Mutex;
 use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 
 /// DNS query information
@@ -15,8 +22,55 @@ struct QueryInfo {
     original_dst_ip: IpAddr,
     /// Original destination port
     original_dst_port: u16,
+    /// DNS transaction ID (None = match any response)
+    txid: Option<u16>,
     /// When the query was made
     created: Instant,
+    /// When the first matching response was seen
+    answered: Option<Instant>,
+}
+
+impl QueryInfo {
+    fn is_expired(&self, now: Instant, timeout: Duration) -> bool {
+        match self.answered {
+            Some(answered) => now.saturating_duration_since(answered) >= RESPONSE_LINGER,
+            None => now.saturating_duration_since(self.created) >= timeout,
+        }
+    }
+}
+
+/// How long a query is kept after its response, so retransmitted or
+/// duplicate responses are still rewritten
+const RESPONSE_LINGER: Duration = Duration::from_secs(2);
+
+/// How long a TCP flow is kept after both sides sent FIN, so the final
+/// ACKs are still rewritten
+const TCP_CLOSE_LINGER: Duration = Duration::from_secs(2);
+
+/// Redirected DNS-over-TCP flow
+#[derive(Debug, Clone)]
+struct TcpFlow {
+    /// Original destination IP
+    original_dst_ip: IpAddr,
+    /// Original destination port
+    original_dst_port: u16,
+    /// FIN seen from the client
+    fin_out: bool,
+    /// FIN seen from the server
+    fin_in: bool,
+    /// Last packet seen on this flow
+    last_seen: Instant,
+}
+
+impl TcpFlow {
+    fn is_expired(&self, now: Instant, idle_timeout: Duration) -> bool {
+        let limit = if self.fin_out && self.fin_in {
+            TCP_CLOSE_LINGER
+        } else {
+            idle_timeout
+        };
+        now.saturating_duration_since(self.last_seen) >= limit
+    }
 }
 
 /// DNS connection tracker
@@ -24,30 +78,56 @@ struct QueryInfo {
 /// Thread-safe tracker that maps DNS queries to their original destinations.
 /// This is needed because we redirect DNS queries to alternative servers,
 /// but the response needs to appear as if it came from the original DNS server.
+/// Queries and TCP flows are kept in LRU caches: once full, the least
+/// recently used entry is evicted.
 pub struct DnsConnTracker {
-    /// Query map: source_port -> original destination
-    queries: DashMap<u16, QueryInfo>,
+    /// Query cache: source_port -> original destination, most recently used first
+    queries: Mutex<LruCache<u16, QueryInfo>>,
     /// Query timeout (default 5 seconds for DNS)
     timeout: Duration,
+    /// TCP flows: client port -> original destination, most recently used first
+    tcp_flows: Mutex<LruCache<u16, TcpFlow>>,
+    /// Idle timeout for TCP flows
+    tcp_timeout: Duration,
+    /// Queries and flows evicted to stay within the capacity
+    evictions: AtomicU64,
 }
 
 impl DnsConnTracker {
     /// Create a new DNS connection tracker
     pub fn new() -> Self {
-        Self {
-            queries: DashMap::new(),
-            timeout: Duration::from_secs(5),
-        }
+        Self::with_timeout(Duration::from_secs(5))
     }
 
     /// Create with custom timeout
     pub fn with_timeout(timeout: Duration) -> Self {
         Self {
-            queries: DashMap::new(),
+            queries: Mutex::new(super::lru_cache(10000)),
             timeout,
+            tcp_flows: Mutex::new(super::lru_cache(10000)),
+            tcp_timeout: Duration::from_secs(60),
+            evictions: AtomicU64::new(0),
         }
     }
 
+    /// Create from performance configuration
+    ///
+    /// Holds at most `conntrack_max_entries` queries and as many TCP flows;
+    /// idle TCP flows expire after two `conntrack_cleanup_interval`s.
+    pub fn from_config(config: &PerformanceConfig) -> Self {
+        Self {
+            tcp_timeout: super::idle_timeout(config),
+            ..Self::new().with_max_entries(config.conntrack_max_entries)
+        }
+    }
+
+    /// Set the maximum number of tracked queries (0 = unbounded)
+    pub fn with_max_entries(self, max_entries: usize) -> Self {
+        *self.queries.lock() = super::lru_cache(max_entries);
+        *self.tcp_flows.lock() = super::lru_cache(max_entries);
+        self
+    }
+
     /// Track a DNS query
     ///
     /// # Arguments
@@ -55,12 +135,39 @@ impl DnsConnTracker {
     /// * `original_dst_ip` - Original DNS server IP
     /// * `original_dst_port` - Original DNS server port
     pub fn track_query(&self, src_port: u16, original_dst_ip: IpAddr, original_dst_port: u16) {
+        self.insert(src_port, None, original_dst_ip, original_dst_port);
+    }
+
+    /// Track a DNS query together with its transaction ID
+    ///
+    /// Responses are only matched by [`match_response`](Self::match_response)
+    /// if they carry the same ID.
+    pub fn track_query_id(
+        &self,
+        src_port: u16,
+        txid: u16,
+        original_dst_ip: IpAddr,
+        original_dst_port: u16,
+    ) {
+        self.insert(src_port, Some(txid), original_dst_ip, original_dst_port);
+    }
+
+    fn insert(&self, src_port: u16, txid: Option<u16>, original_dst_ip: IpAddr, original_dst_port: u16) {
         let info = QueryInfo {
             original_dst_ip,
             original_dst_port,
+            txid,
             created: Instant::now(),
+            answered: None,
         };
-        self.queries.insert(src_port, info);
+
+        // `push` hands back the evicted least recently used entry, or the
+        // replaced one for a reused port
+        if let Some((port, _)) = self.queries.lock().push(src_port, info) {
+            if port != src_port {
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
     }
 
     /// Get the original destination for a DNS response
@@ -72,44 +179,153 @@ impl DnsConnTracker {
     /// * `Some((ip, port))` - The original destination if found and not expired
     /// * `None` - If no record exists or it has expired
     pub fn get_original(&self, src_port: u16) -> Option<(IpAddr, u16)> {
-        if let Some(info) = self.queries.get(&src_port) {
-            if info.created.elapsed() < self.timeout {
-                return Some((info.original_dst_ip, info.original_dst_port));
-            } else {
-                // Expired, remove entry
-                drop(info);
-                self.queries.remove(&src_port);
-            }
+        let mut queries = self.queries.lock();
+        let info = queries.get(&src_port)?;
+        if info.is_expired(Instant::now(), self.timeout) {
+            queries.pop(&src_port);
+            return None;
         }
-        None
+        Some((info.original_dst_ip, info.original_dst_port))
+    }
+
+    /// Match a DNS response to its query
+    ///
+    /// The entry is kept for a short while after the first response so
+    /// duplicates are rewritten too, then expires.
+    ///
+    /// # Arguments
+    /// * `dst_port` - Destination port of the response (the query's source port)
+    /// * `txid` - Transaction ID of the response
+    ///
+    /// # Returns
+    /// * `Some((ip, port))` - The original destination of the matching query
+    /// * `None` - If no live query exists or the transaction ID differs;
+    ///   a mismatching response leaves the entry in place
+    pub fn match_response(&self, dst_port: u16, txid: u16) -> Option<(IpAddr, u16)> {
+        let now = Instant::now();
+        let mut queries = self.queries.lock();
+        let info = queries.get_mut(&dst_port)?;
+
+        if info.is_expired(now, self.timeout) {
+            queries.pop(&dst_port);
+            return None;
+        }
+        if info.txid.is_some_and(|id| id != txid) {
+            return None;
+        }
+
+        info.answered.get_or_insert(now);
+        Some((info.original_dst_ip, info.original_dst_port))
     }
 
     /// Remove a query entry (called after response is received)
     pub fn remove(&self, src_port: u16) {
-        self.queries.remove(&src_port);
+        self.queries.lock().pop(&src_port);
+    }
+
+    /// Start tracking a redirected TCP flow (called on the client's SYN)
+    pub fn track_tcp_flow(&self, client_port: u16, original_dst_ip: IpAddr, original_dst_port: u16) {
+        let flow = TcpFlow {
+            original_dst_ip,
+            original_dst_port,
+            fin_out: false,
+            fin_in: false,
+            last_seen: Instant::now(),
+        };
+
+        if let Some((port, _)) = self.tcp_flows.lock().push(client_port, flow) {
+            if port != client_port {
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Look up a TCP flow for a packet and advance its teardown state
+    ///
+    /// # Arguments
+    /// * `client_port` - Local port of the flow
+    /// * `outbound` - Whether the packet goes from client to server
+    /// * `flags` - TCP flags of the packet
+    ///
+    /// # Returns
+    /// * `Some((ip, port))` - The original destination; the packet should
+    ///   still be rewritten even if it closes the flow
+    /// * `None` - If the flow is unknown or has expired
+    pub fn tcp_flow(&self, client_port: u16, outbound: bool, flags: TcpFlags) -> Option<(IpAddr, u16)> {
+        let now = Instant::now();
+        let mut flows = self.tcp_flows.lock();
+        let flow = flows.get_mut(&client_port)?;
+
+        if flow.is_expired(now, self.tcp_timeout) {
+            flows.pop(&client_port);
+            return None;
+        }
+
+        let original = (flow.original_dst_ip, flow.original_dst_port);
+        if flags.rst {
+            flows.pop(&client_port);
+            return Some(original);
+        }
+
+        if flags.fin {
+            if outbound {
+                flow.fin_out = true;
+            } else {
+                flow.fin_in = true;
+            }
+        }
+        flow.last_seen = now;
+
+        Some(original)
+    }
+
+    /// Get the number of tracked TCP flows
+    pub fn tcp_flow_count(&self) -> usize {
+        self.tcp_flows.lock().len()
     }
 
     /// Clean up expired entries
     pub fn cleanup(&self) {
-        let now = Instant::now();
-        self.queries.retain(|_, info| {
-            now.duration_since(info.created) < self.timeout
-        });
+        self.sweep(Instant::now());
+    }
+
+    /// Drop expired queries and TCP flows as of `now`
+    ///
+    /// Queries expire after the timeout, or shortly after their response;
+    /// TCP flows after inactivity, or shortly after both sides closed.
+    /// Returns the number of removed entries.
+    pub fn sweep(&self, now: Instant) -> usize {
+        super::sweep_lru(&self.queries, |info| info.is_expired(now, self.timeout))
+            + super::sweep_lru(&self.tcp_flows, |flow| flow.is_expired(now, self.tcp_timeout))
+    }
+
+    /// Sweep expired entries from a background thread every `interval`
+    ///
+    /// The thread only holds a weak reference and exits once the tracker
+    /// is dropped.
+    pub fn spawn_cleanup(self: Arc<Self>, interval: Duration) -> std::io::Result<JoinHandle<()>> {
+        super::spawn_sweeper(self, "gdpi-dns-conntrack", interval, |tracker, now| tracker.sweep(now))
+    }
+
+    /// Number of queries and flows evicted because the tracker was full
+    pub fn eviction_count(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
     }
 
     /// Get the number of tracked queries
     pub fn len(&self) -> usize {
-        self.queries.len()
+        self.queries.lock().len()
     }
 
     /// Check if tracker is empty
     pub fn is_empty(&self) -> bool {
-        self.queries.is_empty()
+        self.queries.lock().is_empty()
     }
 
     /// Clear all entries
     pub fn clear(&self) {
-        self.queries.clear();
+        self.queries.lock().clear();
+        self.tcp_flows.lock().clear();
     }
 }
 
@@ -119,6 +335,20 @@ impl Default for DnsConnTracker {
     }
 }
 
+/// Copies the queries and flows as they are now; the copy is tracked
+/// separately
+impl Clone for DnsConnTracker {
+    fn clone(&self) -> Self {
+        Self {
+            queries: Mutex::new(self.queries.lock().clone()),
+            timeout: self.timeout,
+            tcp_flows: Mutex::new(self.tcp_flows.lock().clone()),
+            tcp_timeout: self.tcp_timeout,
+            evictions: AtomicU64::new(self.evictions.load(Ordering::Relaxed)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -159,6 +389,39 @@ mod tests {
         assert_eq!(result, None);
     }
 
+    #[test]
+    fn test_bounded_lru() {
+        let tracker = DnsConnTracker::new().with_max_entries(3);
+        let dns = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+
+        for port in 1000..1003 {
+            tracker.track_query(port, dns, 53);
+        }
+        // 1000 becomes the most recently used
+        assert!(tracker.get_original(1000).is_some());
+        // Re-tracking a port replaces its entry without evicting another
+        tracker.track_query(1000, dns, 53);
+        assert_eq!(tracker.eviction_count(), 0);
+
+        for port in 1003..1005 {
+            tracker.track_query(port, dns, 53);
+        }
+
+        assert_eq!(tracker.len(), 3);
+        assert_eq!(tracker.eviction_count(), 2);
+        assert!(tracker.get_original(1000).is_some());
+        assert!(tracker.get_original(1001).is_none());
+        assert!(tracker.get_original(1002).is_none());
+        assert!(tracker.get_original(1004).is_some());
+
+        // Flows share the limit and the counter
+        for port in 2000..2005 {
+            tracker.track_tcp_flow(port, dns, 53);
+        }
+        assert_eq!(tracker.tcp_flow_count(), 3);
+        assert_eq!(tracker.eviction_count(), 4);
+    }
+
     #[test]
     fn test_remove() {
         let tracker = DnsConnTracker::new();
@@ -184,6 +447,103 @@ mod tests {
         assert_eq!(tracker.get_original(22222), Some((dns2, 53)));
     }
 
+    #[test]
+    fn test_match_response() {
+        let tracker = DnsConnTracker::new();
+        let original_dns = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+
+        tracker.track_query_id(12345, 0xBEEF, original_dns, 53);
+
+        // Wrong transaction ID does not match or consume the entry
+        assert_eq!(tracker.match_response(12345, 0xDEAD), None);
+        assert_eq!(tracker.len(), 1);
+
+        assert_eq!(tracker.match_response(12345, 0xBEEF), Some((original_dns, 53)));
+        // Duplicate responses still match until the entry lingers out
+        assert_eq!(tracker.match_response(12345, 0xBEEF), Some((original_dns, 53)));
+    }
+
+    #[test]
+    fn test_sweep() {
+        let tracker = DnsConnTracker::new();
+        let original_dns = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+        let start = Instant::now();
+
+        tracker.track_query_id(1000, 1, original_dns, 53);
+        tracker.track_query_id(1001, 2, original_dns, 53);
+        tracker.track_tcp_flow(2000, original_dns, 53);
+        tracker.match_response(1000, 1);
+
+        // Answered query lingers briefly; unanswered one waits for the timeout
+        assert_eq!(tracker.sweep(start + Duration::from_secs(1)), 0);
+        assert_eq!(tracker.sweep(start + Duration::from_secs(3)), 1);
+        assert_eq!(tracker.get_original(1001), Some((original_dns, 53)));
+        assert_eq!(tracker.sweep(start + Duration::from_secs(6)), 1);
+        assert!(tracker.is_empty());
+
+        // Idle TCP flow expires after its own timeout
+        assert_eq!(tracker.tcp_flow_count(), 1);
+        assert_eq!(tracker.sweep(start + Duration::from_secs(61)), 1);
+        assert_eq!(tracker.tcp_flow_count(), 0);
+    }
+
+    #[test]
+    fn test_spawn_cleanup() {
+        let tracker = Arc::new(DnsConnTracker::with_timeout(Duration::from_millis(10)));
+        tracker.track_query(12345, IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)), 53);
+
+        let handle = Arc::clone(&tracker).spawn_cleanup(Duration::from_millis(5)).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !tracker.is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(tracker.is_empty());
+
+        // Dropping the tracker stops the thread
+        drop(tracker);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_tcp_flow_rst_teardown() {
+        let tracker = DnsConnTracker::new();
+        let original_dns = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+        let ack = TcpFlags { ack: true, ..Default::default() };
+        let rst = TcpFlags { rst: true, ..Default::default() };
+
+        assert_eq!(tracker.tcp_flow(40000, true, ack), None);
+
+        tracker.track_tcp_flow(40000, original_dns, 53);
+        assert_eq!(tracker.tcp_flow(40000, false, ack), Some((original_dns, 53)));
+
+        // The RST itself is still rewritten, then the flow is gone
+        assert_eq!(tracker.tcp_flow(40000, false, rst), Some((original_dns, 53)));
+        assert_eq!(tracker.tcp_flow_count(), 0);
+        assert_eq!(tracker.tcp_flow(40000, true, ack), None);
+    }
+
+    #[test]
+    fn test_tcp_flow_fin_teardown() {
+        let tracker = DnsConnTracker::new();
+        let original_dns = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+        let fin = TcpFlags { fin: true, ack: true, ..Default::default() };
+        let ack = TcpFlags { ack: true, ..Default::default() };
+
+        tracker.track_tcp_flow(40000, original_dns, 53);
+        assert_eq!(tracker.tcp_flow(40000, true, fin), Some((original_dns, 53)));
+        assert_eq!(tracker.tcp_flow(40000, false, fin), Some((original_dns, 53)));
+
+        // Final ACK after both FINs is still mapped during the linger period
+        assert_eq!(tracker.tcp_flow(40000, true, ack), Some((original_dns, 53)));
+        tracker.cleanup();
+        assert_eq!(tracker.tcp_flow_count(), 1);
+
+        // A half-closed flow is not subject to the linger period
+        tracker.track_tcp_flow(40001, original_dns, 53);
+        tracker.tcp_flow(40001, true, fin);
+        assert_eq!(tracker.tcp_flow(40001, true, ack), Some((original_dns, 53)));
+    }
+
     #[test]
     fn test_cleanup() {
         let tracker = DnsConnTracker::with_timeout(Duration::from_millis(10));
@@ -0,0 +1,290 @@
+//! ClientHello reassembly across TCP segments
+//!
+//! Large ClientHellos (post-quantum key shares, long ALPN lists) don't fit
+//! one segment, and the first one may end before the SNI. The start of
+//! such a record is buffered per flow until a later segment completes the
+//! hostname, so strategies can act on that segment instead.
+
+use super::ConnKey;
+use crate::packet::{ClientHelloInfo, Packet};
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
+
+/// Most bytes buffered for one flow
+pub const DEFAULT_MAX_BYTES: usize = 8 * 1024;
+
+/// Most flows buffered at once; a new one pushes out the least recent
+pub const DEFAULT_MAX_FLOWS: usize = 1024;
+
+/// How long a buffered start waits for the rest of its ClientHello
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start of a ClientHello waiting for more segments
+#[derive(Clone)]
+struct PartialHello {
+    /// Payload bytes received so far, in order
+    data: Vec<u8>,
+    /// SEQ the next segment has to start at
+    next_seq: u32,
+    /// Length of the TLS record including its header
+    record_len: usize,
+    /// When the first segment arrived
+    started: Instant,
+}
+
+/// ClientHello whose hostname was completed by a later segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReassembledHello {
+    /// Fields parsed from the reassembled record
+    pub info: ClientHelloInfo,
+    /// Record bytes carried by earlier segments
+    pub earlier_len: usize,
+}
+
+impl ReassembledHello {
+    /// Offset of the hostname in the completing segment's payload
+    ///
+    /// `None` if the hostname already starts in an earlier segment.
+    pub fn sni_offset(&self) -> Option<usize> {
+        self.info.offset_of_sni?.checked_sub(self.earlier_len)
+    }
+}
+
+/// Per-flow buffers for ClientHellos split before their SNI
+///
+/// Only in-order continuations are appended. A flow is dropped once its
+/// hostname is complete, its record ends without one, it outgrows the
+/// byte limit or it times out.
+pub struct HelloReassembler {
+    /// Buffered flows, most recently used first
+    flows: Mutex<LruCache<ConnKey, PartialHello>>,
+    /// Most bytes buffered for one flow
+    max_bytes: usize,
+    /// How long a flow may wait for its next segment
+    timeout: Duration,
+}
+
+impl HelloReassembler {
+    /// Create with the default limits
+    pub fn new() -> Self {
+        Self::with_limits(DEFAULT_MAX_FLOWS, DEFAULT_MAX_BYTES)
+    }
+
+    /// Buffer at most `max_flows` flows (at least one) of `max_bytes` each
+    pub fn with_limits(max_flows: usize, max_bytes: usize) -> Self {
+        Self {
+            flows: Mutex::new(LruCache::new(NonZeroUsize::new(max_flows).unwrap_or(NonZeroUsize::MIN))),
+            max_bytes,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Set how long a buffered flow waits for its next segment
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Feed an outbound TCP segment
+    ///
+    /// A segment starting a ClientHello record it doesn't hold in full,
+    /// and without the complete hostname, is buffered. Returns the
+    /// reassembled ClientHello when `packet` continues a buffered flow and
+    /// completes its hostname.
+    pub fn push(&self, packet: &Packet) -> Option<ReassembledHello> {
+        if !packet.is_outbound() || !packet.is_tcp() || packet.payload_len() == 0 {
+            return None;
+        }
+        let payload = packet.payload();
+        let seq = packet.tcp_seq()?;
+        let key = ConnKey::from_packet(packet);
+        let mut flows = self.flows.lock();
+
+        if packet.is_tls_client_hello() {
+            // A new record replaces whatever the flow had buffered
+            flows.pop(&key);
+            let info = ClientHelloInfo::parse(payload)?;
+            let record_len = 5 + usize::from(u16::from_be_bytes([payload[3], payload[4]]));
+            if info.sni.is_none() && payload.len() < record_len && payload.len() <= self.max_bytes {
+                flows.push(
+                    key,
+                    PartialHello {
+                        data: payload.to_vec(),
+                        next_seq: seq.wrapping_add(payload.len() as u32),
+                        record_len,
+                        started: Instant::now(),
+                    },
+                );
+            }
+            return None;
+        }
+
+        let partial = flows.get_mut(&key)?;
+        // Retransmissions and out-of-order segments leave the buffer as is
+        if seq != partial.next_seq {
+            return None;
+        }
+        if partial.started.elapsed() >= self.timeout || partial.data.len() + payload.len() > self.max_bytes {
+            flows.pop(&key);
+            return None;
+        }
+
+        let earlier_len = partial.data.len();
+        partial.data.extend_from_slice(payload);
+        partial.next_seq = seq.wrapping_add(payload.len() as u32);
+
+        let info = ClientHelloInfo::parse(&partial.data).filter(|info| info.sni.is_some());
+        if info.is_some() || partial.data.len() >= partial.record_len {
+            flows.pop(&key);
+        }
+        info.map(|info| ReassembledHello { info, earlier_len })
+    }
+
+    /// Drop the buffer of the flow with `key` (on FIN/RST)
+    pub fn forget(&self, key: &ConnKey) {
+        self.flows.lock().pop(key);
+    }
+
+    /// Drop flows that waited longer than the timeout as of `now`
+    ///
+    /// Returns the number of removed flows.
+    pub fn sweep(&self, now: Instant) -> usize {
+        let mut flows = self.flows.lock();
+        let stale: Vec<ConnKey> = flows
+            .iter()
+            .filter(|(_, partial)| now.saturating_duration_since(partial.started) >= self.timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &stale {
+            flows.pop(key);
+        }
+        stale.len()
+    }
+
+    /// Number of flows with a buffered ClientHello
+    pub fn len(&self) -> usize {
+        self.flows.lock().len()
+    }
+
+    /// Check if no flow is buffered
+    pub fn is_empty(&self) -> bool {
+        self.flows.lock().is_empty()
+    }
+}
+
+impl Default for HelloReassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Copies the buffered flows as they are now
+impl Clone for HelloReassembler {
+    fn clone(&self) -> Self {
+        Self {
+            flows: Mutex::new(self.flows.lock().clone()),
+            max_bytes: self.max_bytes,
+            timeout: self.timeout,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{ClientHelloBuilder, Direction, PacketBuilder, TcpFlags};
+
+    /// Outbound segment of the flow 192.168.1.10:50000 -> 1.2.3.4:443
+    fn segment(seq: u32, payload: &[u8]) -> Packet {
+        let data = PacketBuilder::tcp_v4()
+            .src_ip_v4([192, 168, 1, 10])
+            .dst_ip_v4([1, 2, 3, 4])
+            .ports(50000, 443)
+            .flags(TcpFlags { ack: true, psh: true, ..Default::default() })
+            .seq(seq)
+            .payload(payload)
+            .build_bytes();
+        Packet::from_bytes(&data, Direction::Outbound).unwrap()
+    }
+
+    fn hello() -> Vec<u8> {
+        ClientHelloBuilder::new("blocked.example").build()
+    }
+
+    #[test]
+    fn test_reassembles_split_hello() {
+        let reassembler = HelloReassembler::new();
+        let hello = hello();
+        let (first, second) = hello.split_at(100);
+
+        assert_eq!(reassembler.push(&segment(1000, first)), None);
+        assert_eq!(reassembler.len(), 1);
+
+        let reassembled = reassembler.push(&segment(1100, second)).unwrap();
+        assert_eq!(reassembled.info.sni.as_deref(), Some("blocked.example"));
+        assert_eq!(reassembled.earlier_len, 100);
+        let offset = reassembled.sni_offset().unwrap();
+        assert_eq!(&second[offset..offset + 15], b"blocked.example");
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn test_whole_hello_not_buffered() {
+        let reassembler = HelloReassembler::new();
+        assert_eq!(reassembler.push(&segment(1000, &hello())), None);
+        // The SNI is complete, even though the record continues
+        assert_eq!(reassembler.push(&segment(1000, &hello()[..400])), None);
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn test_out_of_order_segment_ignored() {
+        let reassembler = HelloReassembler::new();
+        let hello = hello();
+
+        reassembler.push(&segment(1000, &hello[..100]));
+        assert_eq!(reassembler.push(&segment(1200, &hello[200..])), None);
+        assert_eq!(reassembler.len(), 1);
+        assert!(reassembler.push(&segment(1100, &hello[100..])).is_some());
+    }
+
+    #[test]
+    fn test_byte_limit() {
+        let reassembler = HelloReassembler::with_limits(16, 200);
+        let hello = hello();
+
+        reassembler.push(&segment(1000, &hello[..100]));
+        assert_eq!(reassembler.push(&segment(1100, &hello[100..])), None);
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn test_flow_limit_and_forget() {
+        let reassembler = HelloReassembler::with_limits(1, DEFAULT_MAX_BYTES);
+        let hello = hello();
+        let first = segment(1000, &hello[..100]);
+
+        reassembler.push(&first);
+        let mut other = segment(1000, &hello[..100]);
+        other.set_src_port(50001);
+        reassembler.push(&other);
+        assert_eq!(reassembler.len(), 1);
+        // The older flow was pushed out
+        assert_eq!(reassembler.push(&segment(1100, &hello[100..])), None);
+
+        reassembler.forget(&ConnKey::from_packet(&other));
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn test_sweep() {
+        let reassembler = HelloReassembler::new().with_timeout(Duration::from_secs(5));
+        reassembler.push(&segment(1000, &hello()[..100]));
+
+        assert_eq!(reassembler.sweep(Instant::now()), 0);
+        assert_eq!(reassembler.sweep(Instant::now() + Duration::from_secs(6)), 1);
+        assert!(reassembler.is_empty());
+    }
+}
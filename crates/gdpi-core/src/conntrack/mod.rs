@@ -2,10 +2,88 @@
 //!
 //! Provides TCP and DNS connection tracking for:
 //! - Auto-TTL detection (tracking SYN-ACK TTL values)
+//! - Per-connection state, so a flow is only bypassed once
 //! - DNS query/response mapping
+//! - ClientHellos split across TCP segments
 
 mod tcp;
 mod dns;
+mod hello;
 
-pub use tcp::TcpConnTracker;
+pub use tcp::{ConnKey, FlowState, TcpConnTracker};
 pub use dns::DnsConnTracker;
+pub use hello::{HelloReassembler, ReassembledHello};
+
+use crate::config::PerformanceConfig;
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::hash::Hash;
+use std::io;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// How long an entry may sit idle: two cleanup intervals, so it survives
+/// at least one full sweep period
+fn idle_timeout(config: &PerformanceConfig) -> Duration {
+    Duration::from_secs(u64::from(config.conntrack_cleanup_interval.max(1)) * 2)
+}
+
+/// LRU cache holding at most `max_entries` entries (0 = unbounded)
+fn lru_cache<K: Hash + Eq, V>(max_entries: usize) -> LruCache<K, V> {
+    match NonZeroUsize::new(max_entries) {
+        Some(cap) => LruCache::new(cap),
+        None => LruCache::unbounded(),
+    }
+}
+
+/// Remove the entries of `cache` that are stale
+///
+/// Stale keys are collected first so packet processing only waits on
+/// the lock for the removals; entries refreshed in between are kept.
+/// Returns the number of removed entries.
+fn sweep_lru<K: Hash + Eq + Clone, V>(cache: &Mutex<LruCache<K, V>>, is_stale: impl Fn(&V) -> bool) -> usize {
+    let stale: Vec<K> = cache
+        .lock()
+        .iter()
+        .filter(|(_, value)| is_stale(value))
+        .map(|(key, _)| key.clone())
+        .collect();
+    if stale.is_empty() {
+        return 0;
+    }
+
+    let mut cache = cache.lock();
+    let mut removed = 0;
+    for key in &stale {
+        if cache.peek(key).is_some_and(&is_stale) {
+            cache.pop(key);
+            removed += 1;
+        }
+    }
+    removed
+}
+
+/// Run `sweep` on `tracker` every `interval` from a background thread
+///
+/// The thread only holds a weak reference and exits once the tracker is
+/// dropped.
+fn spawn_sweeper<T: Send + Sync + 'static>(
+    tracker: Arc<T>,
+    name: &str,
+    interval: Duration,
+    sweep: impl Fn(&T, Instant) -> usize + Send + 'static,
+) -> io::Result<JoinHandle<()>> {
+    let tracker = Arc::downgrade(&tracker);
+
+    thread::Builder::new().name(name.into()).spawn(move || loop {
+        thread::sleep(interval);
+        let Some(tracker) = tracker.upgrade() else {
+            break;
+        };
+        let removed = sweep(&tracker, Instant::now());
+        debug!(removed, "Swept expired conntrack entries");
+    })
+}
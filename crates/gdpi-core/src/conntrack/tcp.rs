@@ -4,62 +4,140 @@
 //! When a SYN-ACK is received, we record the TTL value.
 //! This TTL is then used for fake packets to ensure they
 //! reach the DPI but not the actual server.
-
-use dashmap::DashMap;
-use parking_lot::RwLock;
-use std::collections::HashMap;
+//!
+//! Each connection also follows a small state machine and remembers
+//! whether it was already bypassed, so a retransmitted ClientHello isn't
+//! handled twice.
+
+use crate::config::PerformanceConfig;
+use crate::packet::Packet;
+use lru::LruCache;
+use parking_lot::Mutex;
 use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 
 /// Connection key for tracking
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
-struct ConnKey {
+pub struct ConnKey {
     /// Server IP (remote)
-    server_ip: IpAddr,
+    pub server_ip: IpAddr,
     /// Server port (remote)
-    server_port: u16,
+    pub server_port: u16,
     /// Client IP (local)
-    client_ip: IpAddr,
+    pub client_ip: IpAddr,
     /// Client port (local)
-    client_port: u16,
+    pub client_port: u16,
+}
+
+impl ConnKey {
+    /// Key of the connection a packet belongs to
+    ///
+    /// Outbound packets go from client to server, inbound ones (such as
+    /// the SYN-ACK) the other way, so both map to the same key.
+    pub fn from_packet(packet: &Packet) -> Self {
+        let ((server_ip, server_port), (client_ip, client_port)) = if packet.is_outbound() {
+            ((packet.dst_addr, packet.dst_port), (packet.src_addr, packet.src_port))
+        } else {
+            ((packet.src_addr, packet.src_port), (packet.dst_addr, packet.dst_port))
+        };
+        Self {
+            server_ip,
+            server_port,
+            client_ip,
+            client_port,
+        }
+    }
 }
 
-/// Connection information
+/// Where a tracked connection is in its lifetime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowState {
+    /// Outbound SYN sent, no answer yet
+    SynSent,
+    /// SYN-ACK received
+    Established,
+    /// The client sent its ClientHello
+    ClientHelloSeen,
+    /// FIN or RST seen; the entry is dropped
+    Closed,
+}
+
+/// Connection entry
 #[derive(Debug, Clone)]
-struct ConnInfo {
+struct ConnEntry {
     /// TTL value from SYN-ACK
-    ttl: u8,
-    /// When this entry was created
-    created: Instant,
+    ttl: Option<u8>,
+    /// Connection state
+    state: FlowState,
+    /// Whether the bypass strategies already ran for this connection
+    bypassed: bool,
+    /// Whether a request on this connection was already fragmented
+    fragmented: bool,
+    /// When this entry was last recorded or looked up
+    last_seen: Instant,
 }
 
-/// TCP connection tracker for Auto-TTL
+impl ConnEntry {
+    fn new(state: FlowState) -> Self {
+        Self {
+            ttl: None,
+            state,
+            bypassed: false,
+            fragmented: false,
+            last_seen: Instant::now(),
+        }
+    }
+}
+
+/// TCP connection tracker for Auto-TTL and per-flow state
 ///
 /// Thread-safe tracker that stores TTL values from SYN-ACK packets.
+/// The table is an LRU cache bounded by `conntrack_max_entries`: once
+/// full, recording a new connection evicts the least recently used one.
 pub struct TcpConnTracker {
-    /// Connection map
-    connections: DashMap<ConnKey, ConnInfo>,
-    /// Entry timeout (default 60 seconds)
+    /// Connection cache, most recently used first
+    connections: Mutex<LruCache<ConnKey, ConnEntry>>,
+    /// Inactivity timeout (default 60 seconds)
     timeout: Duration,
+    /// Entries evicted to stay within the capacity
+    evictions: AtomicU64,
+    /// Entries removed by sweeps for being idle
+    cleaned_connections: AtomicU64,
 }
 
 impl TcpConnTracker {
     /// Create a new TCP connection tracker
     pub fn new() -> Self {
-        Self {
-            connections: DashMap::new(),
-            timeout: Duration::from_secs(60),
-        }
+        Self::with_timeout(Duration::from_secs(60))
     }
 
     /// Create with custom timeout
     pub fn with_timeout(timeout: Duration) -> Self {
         Self {
-            connections: DashMap::new(),
+            connections: Mutex::new(super::lru_cache(10000)),
             timeout,
+            evictions: AtomicU64::new(0),
+            cleaned_connections: AtomicU64::new(0),
         }
     }
 
+    /// Create from performance configuration
+    ///
+    /// Holds at most `conntrack_max_entries` connections; idle ones expire
+    /// after two `conntrack_cleanup_interval`s.
+    pub fn from_config(config: &PerformanceConfig) -> Self {
+        Self::with_timeout(super::idle_timeout(config)).with_max_entries(config.conntrack_max_entries)
+    }
+
+    /// Set the maximum number of tracked connections (0 = unbounded)
+    pub fn with_max_entries(self, max_entries: usize) -> Self {
+        *self.connections.lock() = super::lru_cache(max_entries);
+        self
+    }
+
     /// Record a connection's TTL (from SYN-ACK)
     ///
     /// # Arguments
@@ -82,13 +160,95 @@ impl TcpConnTracker {
             client_ip,
             client_port,
         };
+        self.record_syn_ack(&key, ttl);
+    }
 
-        let info = ConnInfo {
-            ttl,
-            created: Instant::now(),
-        };
+    /// Record the TTL of a connection's SYN-ACK
+    pub fn record_syn_ack(&self, key: &ConnKey, ttl: u8) {
+        self.update(key, FlowState::Established, |entry| {
+            entry.ttl = Some(ttl);
+            entry.state = FlowState::Established;
+        });
+    }
+
+    /// Advance the state of the connection `packet` belongs to
+    ///
+    /// An outbound SYN starts a fresh entry, a SYN-ACK records the TTL, an
+    /// outbound ClientHello moves it to [`FlowState::ClientHelloSeen`] and
+    /// FIN/RST drops it. Returns the new state, `None` for packets that
+    /// don't change it.
+    pub fn observe(&self, packet: &Packet) -> Option<FlowState> {
+        if !packet.is_tcp() {
+            return None;
+        }
+        let key = ConnKey::from_packet(packet);
+
+        if packet.is_fin() || packet.is_rst() {
+            self.forget(&key);
+            Some(FlowState::Closed)
+        } else if packet.is_syn_ack() {
+            if packet.is_outbound() {
+                return None;
+            }
+            self.record_syn_ack(&key, packet.ttl);
+            Some(FlowState::Established)
+        } else if packet.is_syn() && packet.is_outbound() {
+            // A reused 4-tuple is a new connection
+            self.update(&key, FlowState::SynSent, |entry| {
+                *entry = ConnEntry::new(FlowState::SynSent);
+            });
+            Some(FlowState::SynSent)
+        } else if packet.is_outbound() && packet.is_tls_client_hello() {
+            self.update(&key, FlowState::ClientHelloSeen, |entry| {
+                entry.state = FlowState::ClientHelloSeen;
+            });
+            Some(FlowState::ClientHelloSeen)
+        } else {
+            None
+        }
+    }
+
+    /// Current state of the connection with `key`
+    pub fn state(&self, key: &ConnKey) -> Option<FlowState> {
+        self.connections.lock().peek(key).map(|entry| entry.state)
+    }
+
+    /// Remember that the bypass strategies ran for the connection `key`
+    pub fn mark_bypassed(&self, key: &ConnKey) {
+        self.update(key, FlowState::ClientHelloSeen, |entry| entry.bypassed = true);
+    }
+
+    /// Whether [`mark_bypassed`](Self::mark_bypassed) was called for `key`
+    pub fn was_bypassed(&self, key: &ConnKey) -> bool {
+        self.connections.lock().peek(key).is_some_and(|entry| entry.bypassed)
+    }
+
+    /// Remember that a request on the connection `key` was fragmented
+    pub fn mark_fragmented(&self, key: &ConnKey) {
+        self.update(key, FlowState::Established, |entry| entry.fragmented = true);
+    }
 
-        self.connections.insert(key, info);
+    /// Whether [`mark_fragmented`](Self::mark_fragmented) was called for `key`
+    pub fn is_fragmented(&self, key: &ConnKey) -> bool {
+        self.connections.lock().peek(key).is_some_and(|entry| entry.fragmented)
+    }
+
+    /// Apply `change` to the entry for `key`, creating one in `state` first
+    /// if the connection isn't tracked
+    fn update(&self, key: &ConnKey, state: FlowState, change: impl FnOnce(&mut ConnEntry)) {
+        let mut connections = self.connections.lock();
+        if let Some(entry) = connections.get_mut(key) {
+            change(entry);
+            entry.last_seen = Instant::now();
+            return;
+        }
+
+        let mut entry = ConnEntry::new(state);
+        change(&mut entry);
+        // `push` hands back the evicted least recently used entry
+        if connections.push(key.clone(), entry).is_some() {
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
     }
 
     /// Get the TTL for a connection
@@ -109,47 +269,115 @@ impl TcpConnTracker {
         src_ip: IpAddr,
         src_port: u16,
     ) -> Option<u8> {
-        let key = ConnKey {
+        self.get_ttl_for(&ConnKey {
             server_ip: dst_ip,
             server_port: dst_port,
             client_ip: src_ip,
             client_port: src_port,
-        };
+        })
+    }
 
-        if let Some(info) = self.connections.get(&key) {
-            if info.created.elapsed() < self.timeout {
-                return Some(info.ttl);
-            } else {
-                // Entry expired, remove it
-                drop(info);
-                self.connections.remove(&key);
-            }
+    /// Get the TTL recorded for `key`, if not expired
+    pub fn get_ttl_for(&self, key: &ConnKey) -> Option<u8> {
+        let mut connections = self.connections.lock();
+        let entry = connections.get_mut(key)?;
+        if entry.last_seen.elapsed() < self.timeout {
+            entry.last_seen = Instant::now();
+            entry.ttl
+        } else {
+            // Entry expired, remove it
+            connections.pop(key);
+            None
         }
+    }
 
-        None
+    /// Forget the connection with `key`
+    pub fn forget(&self, key: &ConnKey) {
+        self.connections.lock().pop(key);
+    }
+
+    /// Forget a connection (called on FIN/RST)
+    pub fn remove(
+        &self,
+        server_ip: IpAddr,
+        server_port: u16,
+        client_ip: IpAddr,
+        client_port: u16,
+    ) {
+        self.forget(&ConnKey {
+            server_ip,
+            server_port,
+            client_ip,
+            client_port,
+        });
     }
 
     /// Clean up expired entries
     pub fn cleanup(&self) {
-        let now = Instant::now();
-        self.connections.retain(|_, info| {
-            now.duration_since(info.created) < self.timeout
+        self.sweep(Instant::now());
+    }
+
+    /// Drop entries inactive for longer than the timeout as of `now`
+    ///
+    /// Returns the number of removed entries.
+    pub fn sweep(&self, now: Instant) -> usize {
+        self.sweep_idle(now, self.timeout)
+    }
+
+    /// Drop entries not seen for `max_idle` as of `now`
+    ///
+    /// Stale keys are collected first so packet processing only waits on
+    /// the lock for the removals. Returns the number of removed entries.
+    pub fn sweep_idle(&self, now: Instant, max_idle: Duration) -> usize {
+        let removed = super::sweep_lru(&self.connections, |entry| {
+            now.saturating_duration_since(entry.last_seen) >= max_idle
         });
+        self.cleaned_connections.fetch_add(removed as u64, Ordering::Relaxed);
+        removed
+    }
+
+    /// Sweep idle entries from a background thread every `interval`
+    ///
+    /// The thread only holds a weak reference and exits once the tracker
+    /// is dropped.
+    pub fn spawn_cleanup(
+        self: Arc<Self>,
+        interval: Duration,
+        max_idle: Duration,
+    ) -> std::io::Result<JoinHandle<()>> {
+        super::spawn_sweeper(self, "gdpi-conntrack", interval, move |tracker, now| {
+            tracker.sweep_idle(now, max_idle)
+        })
+    }
+
+    /// Inactivity timeout used by [`sweep`](Self::sweep) and lookups
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Number of entries removed by sweeps for being idle
+    pub fn cleaned_count(&self) -> u64 {
+        self.cleaned_connections.load(Ordering::Relaxed)
+    }
+
+    /// Number of entries evicted because the tracker was full
+    pub fn eviction_count(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
     }
 
     /// Get the number of tracked connections
     pub fn len(&self) -> usize {
-        self.connections.len()
+        self.connections.lock().len()
     }
 
     /// Check if tracker is empty
     pub fn is_empty(&self) -> bool {
-        self.connections.is_empty()
+        self.connections.lock().is_empty()
     }
 
     /// Clear all entries
     pub fn clear(&self) {
-        self.connections.clear();
+        self.connections.lock().clear();
     }
 }
 
@@ -159,6 +387,18 @@ impl Default for TcpConnTracker {
     }
 }
 
+/// Copies the table as it is now; the copy is tracked separately
+impl Clone for TcpConnTracker {
+    fn clone(&self) -> Self {
+        Self {
+            connections: Mutex::new(self.connections.lock().clone()),
+            timeout: self.timeout,
+            evictions: AtomicU64::new(self.evictions.load(Ordering::Relaxed)),
+            cleaned_connections: AtomicU64::new(self.cleaned_connections.load(Ordering::Relaxed)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -215,6 +455,188 @@ mod tests {
         assert_eq!(ttl, Some(64));
     }
 
+    #[test]
+    fn test_sweep_inactive() {
+        let tracker = TcpConnTracker::new();
+        let server_ip = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+        let client_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let start = Instant::now();
+
+        tracker.record(server_ip, 443, client_ip, 1000, 50);
+        tracker.record(server_ip, 443, client_ip, 1001, 50);
+
+        assert_eq!(tracker.sweep(start + Duration::from_secs(30)), 0);
+        assert_eq!(tracker.sweep(start + Duration::from_secs(61)), 2);
+        assert!(tracker.is_empty());
+        assert_eq!(tracker.cleaned_count(), 2);
+    }
+
+    #[test]
+    fn test_spawn_cleanup() {
+        let tracker = Arc::new(TcpConnTracker::new());
+        let server_ip = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+        let client_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        tracker.record(server_ip, 443, client_ip, 1000, 50);
+
+        let handle = Arc::clone(&tracker)
+            .spawn_cleanup(Duration::from_millis(5), Duration::from_millis(10))
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !tracker.is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(tracker.is_empty());
+        assert_eq!(tracker.cleaned_count(), 1);
+
+        // Dropping the tracker stops the thread
+        drop(tracker);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_remove_on_teardown() {
+        let tracker = TcpConnTracker::new();
+        let server_ip = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+        let client_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        tracker.record(server_ip, 443, client_ip, 1000, 50);
+        tracker.remove(server_ip, 443, client_ip, 1000);
+        assert_eq!(tracker.get_ttl(server_ip, 443, client_ip, 1000), None);
+    }
+
+    #[test]
+    fn test_record_syn_ack_by_packet_key() {
+        use crate::packet::{Direction, PacketBuilder, TcpFlags};
+
+        let syn_ack = PacketBuilder::tcp_v4()
+            .src_ip_v4([93, 184, 216, 34])
+            .dst_ip_v4([192, 168, 1, 100])
+            .src_port(443)
+            .dst_port(50000)
+            .ttl(54)
+            .flags(TcpFlags { syn: true, ack: true, ..Default::default() })
+            .build_bytes();
+        let request = PacketBuilder::tcp_v4()
+            .src_ip_v4([192, 168, 1, 100])
+            .dst_ip_v4([93, 184, 216, 34])
+            .src_port(50000)
+            .dst_port(443)
+            .build_bytes();
+        let syn_ack = Packet::from_bytes(&syn_ack, Direction::Inbound).unwrap();
+        let request = Packet::from_bytes(&request, Direction::Outbound).unwrap();
+
+        let key = ConnKey::from_packet(&syn_ack);
+        assert_eq!(key, ConnKey::from_packet(&request));
+        assert_eq!(key.server_port, 443);
+
+        let tracker = TcpConnTracker::new();
+        tracker.record_syn_ack(&key, syn_ack.ttl);
+        assert_eq!(tracker.get_ttl_for(&ConnKey::from_packet(&request)), Some(54));
+
+        tracker.forget(&key);
+        assert_eq!(tracker.get_ttl_for(&key), None);
+    }
+
+    #[test]
+    fn test_flow_state_machine() {
+        use crate::packet::{ClientHelloBuilder, Direction, PacketBuilder, TcpFlags};
+
+        let packet = |flags: TcpFlags, payload: &[u8], direction: Direction| {
+            let (src, dst, sport, dport) = match direction {
+                Direction::Outbound => ([192, 168, 1, 100], [93, 184, 216, 34], 50000, 443),
+                Direction::Inbound => ([93, 184, 216, 34], [192, 168, 1, 100], 443, 50000),
+            };
+            let data = PacketBuilder::tcp_v4()
+                .src_ip_v4(src)
+                .dst_ip_v4(dst)
+                .src_port(sport)
+                .dst_port(dport)
+                .ttl(54)
+                .flags(flags)
+                .payload(payload)
+                .build_bytes();
+            Packet::from_bytes(&data, direction).unwrap()
+        };
+        let syn = TcpFlags { syn: true, ..Default::default() };
+        let syn_ack = TcpFlags { syn: true, ack: true, ..Default::default() };
+        let push = TcpFlags { psh: true, ack: true, ..Default::default() };
+        let hello = ClientHelloBuilder::new("example.com").build();
+
+        let tracker = TcpConnTracker::new();
+        let first = packet(syn, &[], Direction::Outbound);
+        let key = ConnKey::from_packet(&first);
+
+        assert_eq!(tracker.observe(&first), Some(FlowState::SynSent));
+        let answer = packet(syn_ack, &[], Direction::Inbound);
+        assert_eq!(tracker.observe(&answer), Some(FlowState::Established));
+        assert_eq!(tracker.get_ttl_for(&key), Some(54));
+        let request = packet(push, &hello, Direction::Outbound);
+        assert_eq!(tracker.observe(&request), Some(FlowState::ClientHelloSeen));
+        assert_eq!(tracker.state(&key), Some(FlowState::ClientHelloSeen));
+
+        tracker.mark_bypassed(&key);
+        assert!(tracker.was_bypassed(&key));
+        tracker.mark_fragmented(&key);
+        assert!(tracker.is_fragmented(&key));
+
+        // A new SYN on the same 4-tuple starts over
+        tracker.observe(&first);
+        assert_eq!(tracker.state(&key), Some(FlowState::SynSent));
+        assert!(!tracker.was_bypassed(&key));
+        assert!(!tracker.is_fragmented(&key));
+
+        let rst = TcpFlags { rst: true, ..Default::default() };
+        assert_eq!(tracker.observe(&packet(rst, &[], Direction::Inbound)), Some(FlowState::Closed));
+        assert_eq!(tracker.state(&key), None);
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let tracker = TcpConnTracker::new().with_max_entries(2);
+        let server_ip = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+        let client_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        tracker.record(server_ip, 443, client_ip, 1000, 50);
+        tracker.record(server_ip, 443, client_ip, 1001, 51);
+
+        // Touch the older entry so the second becomes least recently used
+        assert_eq!(tracker.get_ttl(server_ip, 443, client_ip, 1000), Some(50));
+        tracker.record(server_ip, 443, client_ip, 1002, 52);
+
+        assert_eq!(tracker.len(), 2);
+        assert_eq!(tracker.get_ttl(server_ip, 443, client_ip, 1000), Some(50));
+        assert_eq!(tracker.get_ttl(server_ip, 443, client_ip, 1001), None);
+        assert_eq!(tracker.get_ttl(server_ip, 443, client_ip, 1002), Some(52));
+        assert_eq!(tracker.eviction_count(), 1);
+
+        // Refreshing a tracked connection is not an eviction
+        tracker.record(server_ip, 443, client_ip, 1002, 53);
+        assert_eq!(tracker.eviction_count(), 1);
+    }
+
+    #[test]
+    fn test_from_config_bounds() {
+        let config = PerformanceConfig {
+            conntrack_max_entries: 100,
+            conntrack_cleanup_interval: 45,
+            ..PerformanceConfig::default()
+        };
+        let tracker = TcpConnTracker::from_config(&config);
+        assert_eq!(tracker.timeout(), Duration::from_secs(90));
+
+        let server_ip = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+        let client_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        for port in 0..250 {
+            tracker.record(server_ip, 443, client_ip, port, 50);
+        }
+        assert_eq!(tracker.len(), 100);
+        assert_eq!(tracker.eviction_count(), 150);
+        // The most recent connections are the ones kept
+        assert_eq!(tracker.get_ttl(server_ip, 443, client_ip, 249), Some(50));
+        assert_eq!(tracker.get_ttl(server_ip, 443, client_ip, 150), Some(50));
+        assert_eq!(tracker.get_ttl(server_ip, 443, client_ip, 149), None);
+    }
+
     #[test]
     fn test_cleanup() {
         let tracker = TcpConnTracker::with_timeout(Duration::from_millis(10));
@@ -82,6 +82,15 @@ pub enum Error {
         addr: String,
     },
 
+    /// Invalid regex in a domain filter
+    #[error("Invalid regex '{pattern}': {message}")]
+    InvalidRegex {
+        /// The pattern as written
+        pattern: String,
+        /// Why it failed to compile
+        message: String,
+    },
+
     /// Invalid port number
     #[error("Invalid port number: {port} (must be 1-65535)")]
     InvalidPort {
@@ -129,6 +138,14 @@ pub enum Error {
     #[error("TOML parsing error: {0}")]
     TomlParse(#[from] toml::de::Error),
 
+    /// JSON parsing error
+    #[error("JSON parsing error: {0}")]
+    JsonParse(#[from] serde_json::Error),
+
+    /// YAML parsing error
+    #[error("YAML parsing error: {0}")]
+    YamlParse(#[from] serde_yaml::Error),
+
     /// Hex decoding error
     #[error("Hex decoding error: {0}")]
     HexDecode(#[from] hex::FromHexError),
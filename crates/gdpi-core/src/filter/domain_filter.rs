@@ -2,9 +2,17 @@
 //!
 //! Provides whitelist and blacklist functionality for domain-based filtering.
 
-use dashmap::DashSet;
+use super::suffix_trie::SuffixTrie;
+use crate::config::BlacklistConfig;
+use crate::error::{Error, Result};
+use dashmap::DashMap;
+use ipnetwork::IpNetwork;
 use parking_lot::RwLock;
+use regex::{Regex, RegexBuilder};
+use std::collections::HashSet;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::SystemTime;
 use tracing::{debug, info, warn};
@@ -21,6 +29,10 @@ pub enum FilterMode {
     /// Blacklist mode - ONLY listed domains get bypass applied
     /// Use for: specific blocked sites only
     Blacklist,
+    /// Hybrid mode - a whitelist checked first, then the blacklist, with
+    /// a configurable result for domains on neither
+    /// Use for: bypass by default, but never for banks
+    Hybrid,
 }
 
 /// Result of domain filter check
@@ -39,14 +51,38 @@ pub enum FilterResult {
 pub struct DomainFilter {
     /// Current filter mode
     mode: RwLock<FilterMode>,
-    /// Exact domain matches
-    exact_domains: DashSet<String>,
+    /// Exact domain matches, with their hit counts
+    exact_domains: DashMap<String, AtomicU64>,
     /// Wildcard patterns (stored without *. prefix)
-    wildcard_domains: DashSet<String>,
-    /// Source file path for hot-reload
-    file_path: RwLock<Option<PathBuf>>,
-    /// Last modification time of the file
-    last_modified: RwLock<Option<SystemTime>>,
+    wildcard_domains: RwLock<SuffixTrie>,
+    /// Regex rules (`re:` entries), tried after the plain domains
+    patterns: RwLock<Vec<(Regex, AtomicU64)>>,
+    /// IP ranges, matched against the destination address
+    networks: RwLock<Vec<(IpNetwork, AtomicU64)>>,
+    /// Source files for hot-reload
+    files: RwLock<Vec<WatchedFile>>,
+    /// Entries not backed by a file, restored after a reload
+    inline: RwLock<Vec<String>>,
+    /// Whitelist checked before this filter's entries in hybrid mode
+    whitelist: RwLock<Option<Arc<DomainFilter>>>,
+    /// Hybrid mode result for hostnames on neither list
+    hybrid_default: RwLock<FilterResult>,
+}
+
+/// A filter file and its modification time when last read
+#[derive(Debug, Clone)]
+struct WatchedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl WatchedFile {
+    fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+        }
+    }
 }
 
 impl Default for DomainFilter {
@@ -60,10 +96,14 @@ impl DomainFilter {
     pub fn new() -> Self {
         Self {
             mode: RwLock::new(FilterMode::Disabled),
-            exact_domains: DashSet::new(),
-            wildcard_domains: DashSet::new(),
-            file_path: RwLock::new(None),
-            last_modified: RwLock::new(None),
+            exact_domains: DashMap::new(),
+            wildcard_domains: RwLock::new(SuffixTrie::new()),
+            patterns: RwLock::new(Vec::new()),
+            networks: RwLock::new(Vec::new()),
+            files: RwLock::new(Vec::new()),
+            inline: RwLock::new(Vec::new()),
+            whitelist: RwLock::new(None),
+            hybrid_default: RwLock::new(FilterResult::ApplyBypass),
         }
     }
 
@@ -72,9 +112,10 @@ impl DomainFilter {
         let filter = Self::new();
         *filter.mode.write() = mode;
         
-        for domain in domains {
-            filter.add_domain(&domain);
+        for domain in &domains {
+            filter.add_entry(domain);
         }
+        *filter.inline.write() = domains;
         
         filter
     }
@@ -97,96 +138,297 @@ impl DomainFilter {
         *self.mode.write() = mode;
     }
 
+    /// Set the whitelist consulted first in hybrid mode
+    ///
+    /// This filter's own entries act as the blacklist.
+    pub fn set_whitelist(&self, whitelist: Arc<DomainFilter>) {
+        *self.whitelist.write() = Some(whitelist);
+    }
+
+    /// Get the hybrid mode whitelist, if one is set
+    pub fn whitelist(&self) -> Option<Arc<DomainFilter>> {
+        self.whitelist.read().clone()
+    }
+
+    /// Set the hybrid mode result for hostnames on neither list
+    pub fn set_hybrid_default(&self, result: FilterResult) {
+        *self.hybrid_default.write() = result;
+    }
+
     /// Add a domain to the filter
     ///
     /// Supports:
     /// - Exact domains: "example.com"
     /// - Wildcard: "*.example.com" (matches any subdomain)
+    /// - Regex: `re:^ads?\.` (case-insensitive, matched against the hostname)
+    /// - IP ranges: "10.0.0.0/8", see [`add_cidr`](Self::add_cidr)
+    ///
+    /// Invalid regexes and IP ranges are logged and skipped.
     pub fn add_domain(&self, domain: &str) {
-        let domain = domain.trim().to_lowercase();
-        
-        if domain.is_empty() || domain.starts_with('#') {
-            return;
-        }
+        self.add_entry(domain);
+    }
 
-        if let Some(stripped) = domain.strip_prefix("*.") {
-            self.wildcard_domains.insert(stripped.to_string());
+    /// Remove a domain, regex or IP range from the filter
+    pub fn remove_domain(&self, domain: &str) {
+        let domain = domain.trim();
+        if let Some(pattern) = domain.strip_prefix(REGEX_PREFIX) {
+            self.patterns.write().retain(|(re, _)| re.as_str() != pattern);
+        } else if is_ip_entry(domain) {
+            self.remove_cidr(domain);
         } else {
-            self.exact_domains.insert(domain);
+            let domain = domain.to_lowercase();
+            if let Some(stripped) = domain.strip_prefix("*.") {
+                self.wildcard_domains.write().remove(stripped);
+            } else {
+                self.exact_domains.remove(&domain);
+            }
         }
     }
 
-    /// Remove a domain from the filter
-    pub fn remove_domain(&self, domain: &str) {
-        let domain = domain.trim().to_lowercase();
-        
-        if let Some(stripped) = domain.strip_prefix("*.") {
-            self.wildcard_domains.remove(stripped);
+    /// Add a regex rule, matched case-insensitively against hostnames
+    pub fn add_regex(&self, pattern: &str) -> Result<()> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| Error::InvalidRegex {
+                pattern: pattern.to_string(),
+                message: e.to_string(),
+            })?;
+
+        let mut patterns = self.patterns.write();
+        if !patterns.iter().any(|(re, _)| re.as_str() == pattern) {
+            patterns.push((regex, AtomicU64::new(0)));
+        }
+        Ok(())
+    }
+
+    /// Add an IP range to the filter
+    ///
+    /// Accepts CIDR notation ("192.168.0.0/16", "2a00:1450::/32") or a
+    /// single address, which is treated as a host route.
+    pub fn add_cidr(&self, cidr: &str) -> Result<()> {
+        let network: IpNetwork = cidr.trim().parse().map_err(|_| Error::InvalidIpAddr {
+            addr: cidr.to_string(),
+        })?;
+
+        let mut networks = self.networks.write();
+        if !networks.iter().any(|(n, _)| *n == network) {
+            networks.push((network, AtomicU64::new(0)));
+        }
+        Ok(())
+    }
+
+    /// Add a domain, regex or IP range, whichever the entry looks like
+    ///
+    /// Returns `false` if the entry was skipped.
+    fn add_entry(&self, entry: &str) -> bool {
+        let entry = entry.trim();
+        if entry.is_empty() || entry.starts_with('#') {
+            return false;
+        }
+
+        let result = if let Some(pattern) = entry.strip_prefix(REGEX_PREFIX) {
+            self.add_regex(pattern)
+        } else if is_ip_entry(entry) {
+            self.add_cidr(entry)
         } else {
-            self.exact_domains.remove(&domain);
+            let domain = entry.to_lowercase();
+            if let Some(stripped) = domain.strip_prefix("*.") {
+                self.wildcard_domains.write().insert(stripped);
+            } else {
+                self.exact_domains.entry(domain).or_default();
+            }
+            Ok(())
+        };
+
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Skipping filter entry: {}", e);
+                false
+            }
         }
     }
 
-    /// Clear all domains
+    /// Remove an IP range from the filter
+    pub fn remove_cidr(&self, cidr: &str) {
+        if let Ok(network) = cidr.trim().parse::<IpNetwork>() {
+            self.networks.write().retain(|(n, _)| *n != network);
+        }
+    }
+
+    /// Clear all domains and IP ranges
     pub fn clear(&self) {
         self.exact_domains.clear();
-        self.wildcard_domains.clear();
+        self.wildcard_domains.write().clear();
+        self.patterns.write().clear();
+        self.networks.write().clear();
     }
 
-    /// Load domains from a file
+    /// Load domains from a file, replacing the current entries
+    ///
+    /// Entries that are in both the filter and the file keep matching while
+    /// the rest are swapped.
     ///
     /// File format:
     /// - One domain per line
     /// - Lines starting with # are comments
     /// - Empty lines are ignored
     /// - Wildcard: *.example.com
+    /// - Regex: `re:^ads?\.`
+    /// - IP ranges: 10.0.0.0/8, 2001:db8::/32
     pub fn load_file<P: AsRef<Path>>(&self, path: P) -> std::io::Result<usize> {
         let path = path.as_ref();
         let content = std::fs::read_to_string(path)?;
-        
-        // Store file path for hot-reload
-        *self.file_path.write() = Some(path.to_path_buf());
-        
-        // Store modification time
-        if let Ok(metadata) = std::fs::metadata(path) {
-            if let Ok(modified) = metadata.modified() {
-                *self.last_modified.write() = Some(modified);
-            }
-        }
 
-        self.clear();
-        
-        let mut count = 0;
-        for line in content.lines() {
-            let line = line.trim();
-            if !line.is_empty() && !line.starts_with('#') {
-                self.add_domain(line);
-                count += 1;
-            }
-        }
+        // The file becomes the only source for hot-reload
+        *self.files.write() = vec![WatchedFile::new(path)];
+        self.inline.write().clear();
 
+        let wanted: HashSet<String> = list_entries(&content).map(normalize_entry).collect();
+        self.sync_entries(wanted);
+        let count = self.len();
         info!("Loaded {} domains from {}", count, path.display());
         Ok(count)
     }
 
-    /// Check if file has been modified and reload if necessary
+    /// Load domains from another file, keeping the current entries
+    ///
+    /// The file is watched by [`check_reload`](Self::check_reload) along
+    /// with any loaded before it.
+    pub fn add_file<P: AsRef<Path>>(&self, path: P) -> std::io::Result<usize> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        self.files.write().push(WatchedFile::new(path));
+
+        let count = self.add_lines(&content);
+        info!("Added {} domains from {}", count, path.display());
+        Ok(count)
+    }
+
+    /// Add each entry of a filter file, returning how many were valid
+    fn add_lines(&self, content: &str) -> usize {
+        list_entries(content).filter(|line| self.add_entry(line)).count()
+    }
+
+    /// Remove and add entries without clearing the filter
+    ///
+    /// Removals run first, then additions, each through
+    /// [`remove_domain`](Self::remove_domain) and
+    /// [`add_domain`](Self::add_domain). Entries not named stay in place, so
+    /// lookups during the update never see an empty filter.
+    pub fn apply_diff(&self, add: &[String], remove: &[String]) {
+        for entry in remove {
+            self.remove_domain(entry);
+        }
+        for entry in add {
+            self.add_domain(entry);
+        }
+    }
+
+    /// Exact domains and wildcard patterns (with their `*.` prefix)
+    ///
+    /// Regex rules and IP ranges are left out; see [`domains`](Self::domains)
+    /// and [`networks`](Self::networks).
+    pub fn snapshot(&self) -> (Vec<String>, Vec<String>) {
+        let exact = self.exact_domains.iter().map(|d| d.key().clone()).collect();
+        let wildcard = self
+            .wildcard_domains
+            .read()
+            .domains()
+            .into_iter()
+            .map(|d| format!("*.{d}"))
+            .collect();
+        (exact, wildcard)
+    }
+
+    /// Make the filter's entries match a new version of a list
+    ///
+    /// Entries missing from `content` are removed and new ones added with
+    /// [`apply_diff`](Self::apply_diff). Returns how many entries were added
+    /// and removed.
+    pub fn sync_with_list(&self, content: &str) -> (usize, usize) {
+        self.sync_entries(list_entries(content).map(normalize_entry).collect())
+    }
+
+    /// Apply the difference between the current entries and `wanted`
+    fn sync_entries(&self, wanted: HashSet<String>) -> (usize, usize) {
+        let (exact, wildcard) = self.snapshot();
+        let current: HashSet<String> = exact
+            .into_iter()
+            .chain(wildcard)
+            .chain(self.patterns.read().iter().map(|(re, _)| format!("{REGEX_PREFIX}{}", re.as_str())))
+            .chain(self.networks())
+            .collect();
+
+        let add: Vec<String> = wanted.difference(&current).cloned().collect();
+        let remove: Vec<String> = current.difference(&wanted).cloned().collect();
+        self.apply_diff(&add, &remove);
+
+        if !add.is_empty() || !remove.is_empty() {
+            info!(added = add.len(), removed = remove.len(), "Updated domain filter");
+        }
+        (add.len(), remove.len())
+    }
+
+    /// Check if any source file has been modified and reload if necessary
+    ///
+    /// A reload diffs the filter against every file plus the inline
+    /// entries it was created with, so unchanged entries keep matching
+    /// throughout. If a file can't be read or has an invalid regex or IP
+    /// range, the current entries are kept and an error returned; the
+    /// files are checked again on the next call.
     pub fn check_reload(&self) -> std::io::Result<bool> {
-        let file_path = self.file_path.read().clone();
-        let Some(path) = file_path else {
-            return Ok(false);
+        let whitelist_reloaded = match self.whitelist() {
+            Some(whitelist) => whitelist.check_reload()?,
+            None => false,
         };
 
-        let metadata = std::fs::metadata(&path)?;
-        let modified = metadata.modified()?;
+        let files = self.files.read().clone();
+        if files.is_empty() {
+            return Ok(whitelist_reloaded);
+        }
+
+        let mut changed = false;
+        for file in &files {
+            let modified = std::fs::metadata(&file.path)?.modified()?;
+            if file.modified.map_or(true, |last| modified > last) {
+                info!("Filter file changed, reloading: {}", file.path.display());
+                changed = true;
+            }
+        }
+        if !changed {
+            return Ok(whitelist_reloaded);
+        }
 
-        let last_modified = *self.last_modified.read();
-        if last_modified.map_or(true, |last| modified > last) {
-            info!("Filter file changed, reloading: {}", path.display());
-            self.load_file(&path)?;
-            return Ok(true);
+        // Read everything before touching the live entries
+        let contents = files
+            .iter()
+            .map(|file| std::fs::read_to_string(&file.path))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        for (file, content) in files.iter().zip(&contents) {
+            if let Some(e) = list_entries(content).find_map(|entry| check_entry(entry).err()) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{}: {}", file.path.display(), e),
+                ));
+            }
         }
 
-        Ok(false)
+        let wanted: HashSet<String> = self
+            .inline
+            .read()
+            .iter()
+            .map(String::as_str)
+            .chain(contents.iter().flat_map(|content| list_entries(content)))
+            .map(normalize_entry)
+            .collect();
+        let count = wanted.len();
+        self.sync_entries(wanted);
+        *self.files.write() = files.iter().map(|file| WatchedFile::new(&file.path)).collect();
+
+        info!("Reloaded {} domains from {} file(s)", count, files.len());
+        Ok(true)
     }
 
     /// Save current domains to file
@@ -198,6 +440,8 @@ impl DomainFilter {
         content.push_str("# \n");
         content.push_str("# One domain per line\n");
         content.push_str("# Use *.example.com for wildcard matching\n");
+        content.push_str("# Use re:<pattern> for a regex on the hostname\n");
+        content.push_str("# IP ranges in CIDR notation (10.0.0.0/8) are also accepted\n");
         content.push_str("# Lines starting with # are comments\n");
         content.push_str("#\n");
         content.push_str(&format!("# Mode: {:?}\n", self.mode()));
@@ -205,26 +449,35 @@ impl DomainFilter {
 
         // Write exact domains
         for domain in self.exact_domains.iter() {
-            content.push_str(&domain);
+            content.push_str(domain.key());
             content.push('\n');
         }
 
         // Write wildcard domains
-        for domain in self.wildcard_domains.iter() {
+        for domain in self.wildcard_domains.read().domains() {
             content.push_str("*.");
             content.push_str(&domain);
             content.push('\n');
         }
 
+        // Write regex rules
+        for (regex, _) in self.patterns.read().iter() {
+            content.push_str(REGEX_PREFIX);
+            content.push_str(regex.as_str());
+            content.push('\n');
+        }
+
+        // Write IP ranges
+        for (network, _) in self.networks.read().iter() {
+            content.push_str(&network.to_string());
+            content.push('\n');
+        }
+
         std::fs::write(path, content)?;
         
-        // Update file path and modification time
-        *self.file_path.write() = Some(path.to_path_buf());
-        if let Ok(metadata) = std::fs::metadata(path) {
-            if let Ok(modified) = metadata.modified() {
-                *self.last_modified.write() = Some(modified);
-            }
-        }
+        // The saved file now holds every entry; watch it alone
+        *self.files.write() = vec![WatchedFile::new(path)];
+        self.inline.write().clear();
 
         info!("Saved {} domains to {}", self.len(), path.display());
         Ok(())
@@ -254,62 +507,181 @@ impl DomainFilter {
                     FilterResult::SkipBypass
                 }
             }
+            FilterMode::Hybrid => {
+                // Hybrid: whitelist wins, then blacklist, then the default
+                if self.whitelist.read().as_ref().is_some_and(|w| w.matches(hostname)) {
+                    debug!("Domain {} is whitelisted, skipping bypass", hostname);
+                    FilterResult::SkipBypass
+                } else if self.matches(hostname) {
+                    FilterResult::ApplyBypass
+                } else {
+                    *self.hybrid_default.read()
+                }
+            }
         }
     }
 
+    /// Check if an IP address should have bypass applied
+    ///
+    /// Same semantics as [`check`](Self::check), but against the IP ranges.
+    /// Useful when no SNI/Host is available.
+    pub fn check_ip(&self, addr: IpAddr) -> FilterResult {
+        match *self.mode.read() {
+            FilterMode::Disabled => FilterResult::ApplyBypass,
+            FilterMode::Whitelist => {
+                if self.matches_ip(addr) {
+                    debug!("Address {} is whitelisted, skipping bypass", addr);
+                    FilterResult::SkipBypass
+                } else {
+                    FilterResult::ApplyBypass
+                }
+            }
+            FilterMode::Blacklist => {
+                if self.matches_ip(addr) {
+                    FilterResult::ApplyBypass
+                } else {
+                    FilterResult::SkipBypass
+                }
+            }
+            FilterMode::Hybrid => {
+                if self.whitelist.read().as_ref().is_some_and(|w| w.matches_ip(addr)) {
+                    debug!("Address {} is whitelisted, skipping bypass", addr);
+                    FilterResult::SkipBypass
+                } else if self.matches_ip(addr) {
+                    FilterResult::ApplyBypass
+                } else {
+                    *self.hybrid_default.read()
+                }
+            }
+        }
+    }
+
+    /// Check if an IP address falls into any filter range
+    pub fn matches_ip(&self, addr: IpAddr) -> bool {
+        let networks = self.networks.read();
+        match networks.iter().find(|(network, _)| network.contains(addr)) {
+            Some((_, hits)) => {
+                hits.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Check if an IP range of this filter or its hybrid whitelist covers
+    /// an address, i.e. whether [`check_ip`](Self::check_ip) has a rule for it
+    ///
+    /// Doesn't count as a hit; the `check_ip` that follows does.
+    pub fn lists_ip(&self, addr: IpAddr) -> bool {
+        self.has_network_for(addr)
+            || self.whitelist.read().as_ref().is_some_and(|w| w.has_network_for(addr))
+    }
+
+    fn has_network_for(&self, addr: IpAddr) -> bool {
+        self.networks.read().iter().any(|(network, _)| network.contains(addr))
+    }
+
+    /// Check if any IP ranges are configured
+    pub fn has_networks(&self) -> bool {
+        !self.networks.read().is_empty()
+    }
+
+    /// Get all IP ranges in CIDR notation
+    pub fn networks(&self) -> Vec<String> {
+        self.networks.read().iter().map(|(network, _)| network.to_string()).collect()
+    }
+
     /// Check if a hostname matches any filter entry
     pub fn matches(&self, hostname: &str) -> bool {
         let hostname = hostname.to_lowercase();
 
         // Check exact match
-        if self.exact_domains.contains(&hostname) {
+        if let Some(hits) = self.exact_domains.get(&hostname) {
+            hits.fetch_add(1, Ordering::Relaxed);
             return true;
         }
 
         // Check wildcard matches (suffix matching)
         // For example, if "example.com" is in wildcards,
-        // it matches "sub.example.com", "deep.sub.example.com"
-        let mut current = hostname.as_str();
-        loop {
-            if self.wildcard_domains.contains(current) {
-                return true;
-            }
-            
-            // Move to parent domain
-            match current.find('.') {
-                Some(pos) => current = &current[pos + 1..],
-                None => break,
-            }
+        // it matches "example.com", "sub.example.com", "deep.sub.example.com"
+        if self.wildcard_domains.read().record_hit(&hostname) {
+            return true;
         }
 
-        // Also check if the hostname itself is a wildcard target
-        // (e.g., hostname "example.com" matches wildcard "example.com")
-        if self.wildcard_domains.contains(&hostname) {
-            return true;
+        let patterns = self.patterns.read();
+        match patterns.iter().find(|(re, _)| re.is_match(&hostname)) {
+            Some((_, hits)) => {
+                hits.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            None => false,
         }
+    }
 
-        false
+    /// Every entry with the number of lookups it matched, most hits first
+    ///
+    /// Each [`matches`](Self::matches) or [`matches_ip`](Self::matches_ip)
+    /// call that finds an entry counts, so a connection checked by several
+    /// strategies counts once per check. Entries that never matched are
+    /// listed with 0.
+    pub fn hit_report(&self) -> Vec<(String, u64)> {
+        let count = |hits: &AtomicU64| hits.load(Ordering::Relaxed);
+        let mut report: Vec<(String, u64)> = self
+            .exact_domains
+            .iter()
+            .map(|entry| (entry.key().clone(), count(entry.value())))
+            .collect();
+        report.extend(
+            self.wildcard_domains
+                .read()
+                .hits()
+                .into_iter()
+                .map(|(suffix, hits)| (format!("*.{suffix}"), hits)),
+        );
+        report.extend(
+            self.patterns
+                .read()
+                .iter()
+                .map(|(re, hits)| (format!("{REGEX_PREFIX}{}", re.as_str()), count(hits))),
+        );
+        report.extend(
+            self.networks
+                .read()
+                .iter()
+                .map(|(network, hits)| (network.to_string(), count(hits))),
+        );
+        report.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        report
     }
 
-    /// Get total number of domains in filter
+    /// Get total number of entries (domains, regexes and IP ranges) in filter
     pub fn len(&self) -> usize {
-        self.exact_domains.len() + self.wildcard_domains.len()
+        self.exact_domains.len()
+            + self.wildcard_domains.read().len()
+            + self.patterns.read().len()
+            + self.networks.read().len()
     }
 
     /// Check if filter is empty
     pub fn is_empty(&self) -> bool {
-        self.exact_domains.is_empty() && self.wildcard_domains.is_empty()
+        self.exact_domains.is_empty()
+            && self.wildcard_domains.read().is_empty()
+            && self.patterns.read().is_empty()
+            && self.networks.read().is_empty()
     }
 
     /// Get all domains as a vector
     pub fn domains(&self) -> Vec<String> {
         let mut result: Vec<String> = self.exact_domains
             .iter()
-            .map(|d| d.clone())
+            .map(|d| d.key().clone())
             .collect();
         
-        for d in self.wildcard_domains.iter() {
-            result.push(format!("*.{}", d.as_str()));
+        for d in self.wildcard_domains.read().domains() {
+            result.push(format!("*.{d}"));
+        }
+        for (re, _) in self.patterns.read().iter() {
+            result.push(format!("{REGEX_PREFIX}{}", re.as_str()));
         }
         
         result.sort();
@@ -317,6 +689,52 @@ impl DomainFilter {
     }
 }
 
+/// Prefix marking a filter entry as a regex
+const REGEX_PREFIX: &str = "re:";
+
+/// Non-empty, non-comment lines of a filter list
+fn list_entries(content: &str) -> impl Iterator<Item = &str> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Check that a regex or IP range entry parses, without adding it
+fn check_entry(entry: &str) -> Result<()> {
+    if let Some(pattern) = entry.strip_prefix(REGEX_PREFIX) {
+        Regex::new(pattern).map_err(|e| Error::InvalidRegex {
+            pattern: pattern.to_string(),
+            message: e.to_string(),
+        })?;
+    } else if is_ip_entry(entry) {
+        entry.parse::<IpNetwork>().map_err(|_| Error::InvalidIpAddr {
+            addr: entry.to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+/// Whether an entry is an IP address or CIDR range rather than a domain
+fn is_ip_entry(entry: &str) -> bool {
+    let addr = entry.split('/').next().unwrap_or_default();
+    addr.parse::<IpAddr>().is_ok()
+}
+
+/// An entry as [`DomainFilter::domains`] and [`DomainFilter::networks`] list it
+fn normalize_entry(entry: &str) -> String {
+    if entry.starts_with(REGEX_PREFIX) {
+        // Patterns are case-sensitive as written (`\D` vs `\d`)
+        return entry.to_string();
+    }
+    if is_ip_entry(entry) {
+        if let Ok(network) = entry.parse::<IpNetwork>() {
+            return network.to_string();
+        }
+    }
+    entry.to_lowercase()
+}
+
 /// Create filter from configuration
 impl DomainFilter {
     /// Create from config with local file support
@@ -333,6 +751,7 @@ impl DomainFilter {
         let mode = match mode_str.to_lowercase().as_str() {
             "whitelist" | "white" => FilterMode::Whitelist,
             "blacklist" | "black" => FilterMode::Blacklist,
+            "hybrid" => FilterMode::Hybrid,
             _ => FilterMode::Disabled,
         };
 
@@ -348,9 +767,56 @@ impl DomainFilter {
             }
         }
 
-        // Add inline domains
+        // Add inline domains (IP ranges are accepted too)
         for domain in inline_domains {
-            filter.add_domain(domain);
+            filter.add_entry(domain);
+        }
+        *filter.inline.write() = inline_domains.to_vec();
+
+        Ok(filter)
+    }
+
+    /// Create from the `[blacklist]` section, including the legacy `files`
+    pub fn from_blacklist_config(config: &BlacklistConfig) -> std::io::Result<Self> {
+        let filter = Self::from_config(
+            config.enabled,
+            &config.mode,
+            config.file_path.as_deref(),
+            &config.domains,
+        )?;
+        if !config.enabled {
+            return Ok(filter);
+        }
+
+        let mut files: Vec<&String> = config.files.iter().collect();
+        if filter.mode() == FilterMode::Hybrid {
+            files.extend(&config.blacklist_file);
+        }
+        for path in files {
+            if Path::new(path).exists() {
+                filter.add_file(path)?;
+            } else {
+                warn!("Filter file not found: {}", path);
+            }
+        }
+
+        if filter.mode() == FilterMode::Hybrid {
+            let whitelist = match config.whitelist_file.as_deref() {
+                Some(path) if Path::new(path).exists() => {
+                    DomainFilter::from_file(path, FilterMode::Whitelist)?
+                }
+                Some(path) => {
+                    warn!("Whitelist file not found: {}", path);
+                    DomainFilter::new()
+                }
+                None => DomainFilter::new(),
+            };
+            filter.set_whitelist(Arc::new(whitelist));
+            filter.set_hybrid_default(if config.hybrid_default {
+                FilterResult::ApplyBypass
+            } else {
+                FilterResult::SkipBypass
+            });
         }
 
         Ok(filter)
@@ -418,4 +884,252 @@ mod tests {
         // Disabled = always apply bypass
         assert_eq!(filter.check("any.com"), FilterResult::ApplyBypass);
     }
+
+    #[test]
+    fn test_hybrid_mode() {
+        let filter = DomainFilter::with_domains(
+            FilterMode::Hybrid,
+            vec!["*.example.com".into(), "10.0.0.0/8".into()],
+        );
+        filter.set_whitelist(Arc::new(DomainFilter::with_domains(
+            FilterMode::Whitelist,
+            vec!["bank.example.com".into(), "10.1.0.0/16".into()],
+        )));
+
+        // Whitelist beats the blacklist
+        assert_eq!(filter.check("bank.example.com"), FilterResult::SkipBypass);
+        assert_eq!(filter.check("www.example.com"), FilterResult::ApplyBypass);
+        assert_eq!(filter.check_ip("10.1.2.3".parse().unwrap()), FilterResult::SkipBypass);
+        assert_eq!(filter.check_ip("10.2.0.1".parse().unwrap()), FilterResult::ApplyBypass);
+        assert!(filter.lists_ip("10.1.2.3".parse().unwrap()));
+
+        // Neither list: the hybrid default decides
+        assert_eq!(filter.check("other.org"), FilterResult::ApplyBypass);
+        filter.set_hybrid_default(FilterResult::SkipBypass);
+        assert_eq!(filter.check("other.org"), FilterResult::SkipBypass);
+    }
+
+    #[test]
+    fn test_hybrid_from_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let whitelist = dir.path().join("whitelist.txt");
+        let blacklist = dir.path().join("blacklist.txt");
+        std::fs::write(&whitelist, "*.gov.tr\n").unwrap();
+        std::fs::write(&blacklist, "discord.com\n").unwrap();
+
+        let config = BlacklistConfig {
+            enabled: true,
+            mode: "hybrid".into(),
+            whitelist_file: Some(whitelist.to_string_lossy().into_owned()),
+            blacklist_file: Some(blacklist.to_string_lossy().into_owned()),
+            hybrid_default: false,
+            ..BlacklistConfig::default()
+        };
+        let filter = DomainFilter::from_blacklist_config(&config).unwrap();
+
+        assert_eq!(filter.mode(), FilterMode::Hybrid);
+        assert_eq!(filter.check("www.turkiye.gov.tr"), FilterResult::SkipBypass);
+        assert_eq!(filter.check("discord.com"), FilterResult::ApplyBypass);
+        assert_eq!(filter.check("example.com"), FilterResult::SkipBypass);
+    }
+
+    #[test]
+    fn test_hit_report() {
+        let filter = DomainFilter::with_domains(
+            FilterMode::Whitelist,
+            vec![
+                "bank.com".into(),
+                "*.gov.tr".into(),
+                "re:^ads?\\.".into(),
+                "10.0.0.0/8".into(),
+                "unused.com".into(),
+            ],
+        );
+
+        for hostname in ["a.gov.tr", "b.gov.tr", "gov.tr", "Bank.com", "ad.example", "other.com"] {
+            filter.check(hostname);
+        }
+        filter.check_ip("10.1.2.3".parse().unwrap());
+
+        assert_eq!(
+            filter.hit_report(),
+            [
+                ("*.gov.tr".to_string(), 3),
+                ("10.0.0.0/8".to_string(), 1),
+                ("bank.com".to_string(), 1),
+                ("re:^ads?\\.".to_string(), 1),
+                ("unused.com".to_string(), 0),
+            ]
+        );
+
+        // Removing an entry drops its count
+        filter.remove_domain("*.gov.tr");
+        filter.add_domain("*.gov.tr");
+        assert!(filter.hit_report().contains(&("*.gov.tr".to_string(), 0)));
+    }
+
+    #[test]
+    fn test_cidr_check_ip() {
+        let filter = DomainFilter::new();
+        filter.set_mode(FilterMode::Blacklist);
+        filter.add_cidr("149.154.160.0/20").unwrap();
+        filter.add_cidr("2001:67c:4e8::/48").unwrap();
+        filter.add_cidr("1.1.1.1").unwrap();
+
+        assert_eq!(filter.check_ip("149.154.167.99".parse().unwrap()), FilterResult::ApplyBypass);
+        assert_eq!(filter.check_ip("2001:67c:4e8:f004::9".parse().unwrap()), FilterResult::ApplyBypass);
+        assert_eq!(filter.check_ip("1.1.1.1".parse().unwrap()), FilterResult::ApplyBypass);
+        assert_eq!(filter.check_ip("8.8.8.8".parse().unwrap()), FilterResult::SkipBypass);
+
+        filter.set_mode(FilterMode::Whitelist);
+        assert_eq!(filter.check_ip("149.154.167.99".parse().unwrap()), FilterResult::SkipBypass);
+        assert_eq!(filter.check_ip("8.8.8.8".parse().unwrap()), FilterResult::ApplyBypass);
+    }
+
+    #[test]
+    fn test_add_cidr_invalid() {
+        let filter = DomainFilter::new();
+        assert!(matches!(filter.add_cidr("10.0.0.0/33"), Err(Error::InvalidIpAddr { .. })));
+        assert!(filter.add_cidr("example.com").is_err());
+        assert!(!filter.has_networks());
+    }
+
+    #[test]
+    fn test_mixed_entries() {
+        let filter = DomainFilter::with_domains(
+            FilterMode::Blacklist,
+            vec!["discord.com".to_string(), "162.159.128.0/19".to_string()],
+        );
+
+        assert!(filter.matches("discord.com"));
+        assert!(filter.matches_ip("162.159.130.234".parse().unwrap()));
+        assert_eq!(filter.len(), 2);
+        assert_eq!(filter.networks(), vec!["162.159.128.0/19".to_string()]);
+    }
+
+    #[test]
+    fn test_regex_entries() {
+        let filter = DomainFilter::with_domains(
+            FilterMode::Blacklist,
+            vec!["re:^ads?\\.".to_string(), "re:(".to_string()],
+        );
+
+        assert!(filter.matches("ad.example"));
+        assert!(filter.matches("ADS.example"));
+        assert!(!filter.matches("bad.example"));
+        // The invalid pattern was skipped
+        assert_eq!(filter.len(), 1);
+        assert_eq!(filter.domains(), ["re:^ads?\\."]);
+
+        filter.remove_domain("re:^ads?\\.");
+        assert!(!filter.matches("ad.example"));
+    }
+
+    #[test]
+    fn test_add_domain_accepts_cidr() {
+        let filter = DomainFilter::new();
+        filter.add_domain("10.0.0.0/8");
+
+        assert!(filter.matches_ip("10.1.2.3".parse().unwrap()));
+        assert!(!filter.matches_ip("11.0.0.1".parse().unwrap()));
+        assert!(filter.domains().is_empty());
+
+        filter.remove_domain("10.0.0.0/8");
+        assert!(!filter.has_networks());
+    }
+
+    #[test]
+    fn test_reload_keeps_every_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.txt");
+        let second = dir.path().join("second.txt");
+        std::fs::write(&first, "# comment\nDiscord.com\n").unwrap();
+        std::fs::write(&second, "*.twitter.com\n").unwrap();
+
+        let filter = DomainFilter::with_domains(FilterMode::Blacklist, vec!["youtube.com".into()]);
+        assert_eq!(filter.add_file(&first).unwrap(), 1);
+        assert_eq!(filter.add_file(&second).unwrap(), 1);
+        assert!(!filter.check_reload().unwrap());
+
+        std::fs::write(&second, "*.x.com\n").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&second)
+            .unwrap()
+            .set_modified(SystemTime::now() + std::time::Duration::from_secs(5))
+            .unwrap();
+
+        assert!(filter.check_reload().unwrap());
+        assert!(filter.matches("youtube.com"));
+        assert!(filter.matches("discord.com"));
+        assert!(filter.matches("api.x.com"));
+        assert!(!filter.matches("api.twitter.com"));
+        assert!(!filter.check_reload().unwrap());
+    }
+
+    #[test]
+    fn test_malformed_reload_keeps_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("domains.txt");
+        std::fs::write(&path, "discord.com\n").unwrap();
+
+        let filter = DomainFilter::from_file(&path, FilterMode::Blacklist).unwrap();
+        let touch = |content: &str, secs: u64| {
+            std::fs::write(&path, content).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() + std::time::Duration::from_secs(secs))
+                .unwrap();
+        };
+
+        touch("youtube.com\nre:(\n", 5);
+        let err = filter.check_reload().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(filter.matches("discord.com"));
+        assert!(!filter.matches("youtube.com"));
+
+        touch("youtube.com\n", 10);
+        assert!(filter.check_reload().unwrap());
+        assert!(filter.matches("youtube.com"));
+        assert!(!filter.matches("discord.com"));
+    }
+
+    #[test]
+    fn test_apply_diff_and_snapshot() {
+        let filter = DomainFilter::with_domains(
+            FilterMode::Blacklist,
+            vec!["keep.com".into(), "gone.com".into(), "*.old.com".into()],
+        );
+
+        filter.apply_diff(
+            &["new.com".into(), "*.wild.com".into()],
+            &["gone.com".into(), "*.old.com".into()],
+        );
+
+        let (mut exact, wildcard) = filter.snapshot();
+        exact.sort();
+        assert_eq!(exact, ["keep.com", "new.com"]);
+        assert_eq!(wildcard, ["*.wild.com"]);
+        assert!(filter.matches("a.wild.com"));
+        assert!(!filter.matches("a.old.com"));
+    }
+
+    #[test]
+    fn test_sync_with_list() {
+        let filter = DomainFilter::with_domains(
+            FilterMode::Blacklist,
+            vec!["keep.com".into(), "*.gone.com".into(), "10.0.0.0/8".into()],
+        );
+
+        let (added, removed) = filter.sync_with_list("# v2\nKeep.com\n*.new.com\n192.0.2.1\n");
+        assert_eq!((added, removed), (2, 2));
+        assert_eq!(filter.domains(), ["*.new.com", "keep.com"]);
+        assert_eq!(filter.networks(), ["192.0.2.1/32"]);
+        assert!(!filter.matches("a.gone.com"));
+
+        // Same list again changes nothing
+        assert_eq!(filter.sync_with_list("keep.com\n*.new.com\n192.0.2.1/32\n"), (0, 0));
+    }
 }
@@ -8,8 +8,13 @@
 //! - Exact domain matching
 //! - Wildcard matching (*.example.com)
 //! - Suffix matching (example.com matches sub.example.com)
+//! - IP range matching (CIDR notation) on the destination address
 //! - Local file-based configuration with hot-reload
+//! - Lists downloaded over HTTPS (`remote-lists` feature)
 
 mod domain_filter;
+#[cfg(feature = "remote-lists")]
+mod remote;
+mod suffix_trie;
 
 pub use domain_filter::{DomainFilter, FilterMode, FilterResult};
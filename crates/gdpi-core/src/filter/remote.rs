@@ -0,0 +1,121 @@
+//! Domain lists downloaded over HTTPS
+//!
+//! Lets a filter follow a list published on the web instead of a local
+//! file. Only built with the `remote-lists` feature.
+
+use super::{DomainFilter, FilterMode};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+impl DomainFilter {
+    /// Download a domain list and build a filter from it
+    ///
+    /// The list uses the [`load_file`](Self::load_file) format. Responses
+    /// compressed with gzip or zstd are decoded.
+    pub async fn from_url(url: &str, mode: FilterMode) -> Result<Self, reqwest::Error> {
+        let body = fetch(&reqwest::Client::new(), url).await?;
+
+        let filter = Self::new();
+        filter.set_mode(mode);
+        let (count, _) = filter.sync_with_list(&body);
+        info!("Loaded {} domains from {}", count, url);
+        Ok(filter)
+    }
+
+    /// Re-download `url` every `interval` and apply the changes with
+    /// [`sync_with_list`](Self::sync_with_list)
+    ///
+    /// The downloaded list replaces the filter's entries. The task runs on
+    /// the current tokio runtime until the filter is dropped; a failed
+    /// download keeps the entries it has.
+    pub fn background_refresh(self: &Arc<Self>, url: String, interval: Duration) -> JoinHandle<()> {
+        let filter = Arc::downgrade(self);
+        let client = reqwest::Client::new();
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // The first tick is immediate and the list was just loaded
+            ticker.tick().await;
+
+            loop {
+                ticker.tick().await;
+                let body = fetch(&client, &url).await;
+                let Some(filter) = filter.upgrade() else {
+                    break;
+                };
+                match body {
+                    Ok(body) => {
+                        filter.sync_with_list(&body);
+                    }
+                    Err(e) => warn!(url = %url, error = %e, "Failed to refresh domain list"),
+                }
+            }
+        })
+    }
+}
+
+async fn fetch(client: &reqwest::Client, url: &str) -> Result<String, reqwest::Error> {
+    client.get(url).send().await?.error_for_status()?.text().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// HTTP server answering every request with the current `list`
+    fn serve(list: Arc<Mutex<String>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/list.txt", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request);
+
+                let body = list.lock().clone();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_from_url_and_refresh() {
+        let list = Arc::new(Mutex::new("# list\ndiscord.com\n*.twitter.com\n".to_string()));
+        let url = serve(Arc::clone(&list));
+
+        let filter = Arc::new(DomainFilter::from_url(&url, FilterMode::Blacklist).await.unwrap());
+        assert_eq!(filter.mode(), FilterMode::Blacklist);
+        assert_eq!(filter.len(), 2);
+        assert!(filter.matches("api.twitter.com"));
+
+        *list.lock() = "discord.com\nwikipedia.org\n".to_string();
+        let task = filter.background_refresh(url, Duration::from_millis(20));
+
+        for _ in 0..100 {
+            if filter.matches("wikipedia.org") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(filter.matches("wikipedia.org"));
+        assert!(!filter.matches("api.twitter.com"));
+
+        // The task ends once the filter is gone
+        drop(filter);
+        tokio::time::timeout(Duration::from_secs(2), task).await.unwrap().unwrap();
+    }
+}
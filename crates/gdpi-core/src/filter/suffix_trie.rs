@@ -0,0 +1,218 @@
+//! Label trie for wildcard domain matching
+//!
+//! Wildcard patterns are stored by their labels from right to left, so
+//! "example.com" becomes com -> example. A lookup walks the hostname's
+//! labels in the same order and stops at the first pattern it reaches,
+//! which is O(k) in the hostname length however many patterns are loaded.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: HashMap<Box<str>, TrieNode>,
+    /// A pattern ends at this node
+    terminal: bool,
+    /// Lookups that matched the pattern ending here
+    hits: AtomicU64,
+}
+
+impl TrieNode {
+    fn is_empty(&self) -> bool {
+        !self.terminal && self.children.is_empty()
+    }
+
+    fn remove<'a>(&mut self, mut labels: impl Iterator<Item = &'a str>) -> bool {
+        let Some(label) = labels.next() else {
+            self.hits = AtomicU64::new(0);
+            return std::mem::take(&mut self.terminal);
+        };
+        let Some(child) = self.children.get_mut(label) else {
+            return false;
+        };
+
+        let removed = child.remove(labels);
+        // Prune branches that no longer lead to a pattern
+        if child.is_empty() {
+            self.children.remove(label);
+        }
+        removed
+    }
+
+    fn collect(&self, suffix: &mut Vec<String>, out: &mut Vec<(String, u64)>) {
+        if self.terminal {
+            let mut labels = suffix.clone();
+            labels.reverse();
+            out.push((labels.join("."), self.hits.load(Ordering::Relaxed)));
+        }
+        for (label, child) in &self.children {
+            suffix.push(label.to_string());
+            child.collect(suffix, out);
+            suffix.pop();
+        }
+    }
+}
+
+/// Set of domain suffixes, matched against whole labels
+#[derive(Debug, Default)]
+pub(super) struct SuffixTrie {
+    root: TrieNode,
+    len: usize,
+}
+
+impl SuffixTrie {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a suffix (without the `*.` prefix), returning `false` if it was
+    /// already present
+    pub fn insert(&mut self, domain: &str) -> bool {
+        let mut node = &mut self.root;
+        for label in domain.rsplit('.') {
+            node = node.children.entry(label.into()).or_default();
+        }
+
+        let added = !node.terminal;
+        node.terminal = true;
+        if added {
+            self.len += 1;
+        }
+        added
+    }
+
+    /// Remove a suffix, returning `false` if it was not present
+    pub fn remove(&mut self, domain: &str) -> bool {
+        let removed = self.root.remove(domain.rsplit('.'));
+        if removed {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    /// Whether `hostname` is one of the suffixes or a subdomain of one,
+    /// counting a hit for that suffix if so
+    pub fn record_hit(&self, hostname: &str) -> bool {
+        match self.find_node(hostname) {
+            Some((_, node)) => {
+                node.hits.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The shortest stored suffix of `hostname`, as a slice of it
+    #[cfg(test)]
+    pub fn find<'a>(&self, hostname: &'a str) -> Option<&'a str> {
+        self.find_node(hostname).map(|(suffix, _)| suffix)
+    }
+
+    /// The shortest stored suffix of `hostname` and the node it ends at
+    fn find_node<'a>(&self, hostname: &'a str) -> Option<(&'a str, &TrieNode)> {
+        let mut node = &self.root;
+        let mut start = hostname.len();
+        for label in hostname.rsplit('.') {
+            start -= label.len();
+            match node.children.get(label) {
+                Some(child) if child.terminal => return Some((&hostname[start..], child)),
+                Some(child) => node = child,
+                None => return None,
+            }
+            // Step over the dot before this label
+            start = start.saturating_sub(1);
+        }
+        None
+    }
+
+    /// Every stored suffix, in no particular order
+    pub fn domains(&self) -> Vec<String> {
+        self.hits().into_iter().map(|(domain, _)| domain).collect()
+    }
+
+    /// Every stored suffix with its hit count, in no particular order
+    pub fn hits(&self) -> Vec<(String, u64)> {
+        let mut out = Vec::with_capacity(self.len);
+        self.root.collect(&mut Vec::new(), &mut out);
+        out
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.root = TrieNode::default();
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_whole_labels() {
+        let mut trie = SuffixTrie::new();
+        assert!(trie.insert("example.com"));
+        assert!(!trie.insert("example.com"));
+        trie.insert("co.uk");
+
+        assert_eq!(trie.find("example.com"), Some("example.com"));
+        assert_eq!(trie.find("notexample.com"), None);
+        assert_eq!(trie.find("com"), None);
+        assert_eq!(trie.find("a.b.example.com"), Some("example.com"));
+        assert_eq!(trie.find("bbc.co.uk"), Some("co.uk"));
+        assert_eq!(trie.len(), 2);
+    }
+
+    #[test]
+    fn test_remove_prunes() {
+        let mut trie = SuffixTrie::new();
+        trie.insert("example.com");
+        trie.insert("sub.example.com");
+
+        assert!(trie.remove("example.com"));
+        assert!(!trie.remove("example.com"));
+        assert_eq!(trie.find("other.example.com"), None);
+        assert_eq!(trie.find("x.sub.example.com"), Some("sub.example.com"));
+
+        assert!(trie.remove("sub.example.com"));
+        assert!(trie.is_empty());
+        assert!(trie.root.children.is_empty());
+    }
+
+    #[test]
+    fn test_domains_round_trip() {
+        let mut trie = SuffixTrie::new();
+        for domain in ["example.com", "sub.example.com", "gov.tr"] {
+            trie.insert(domain);
+        }
+        let mut domains = trie.domains();
+        domains.sort();
+        assert_eq!(domains, ["example.com", "gov.tr", "sub.example.com"]);
+    }
+
+    #[test]
+    fn test_record_hit() {
+        let mut trie = SuffixTrie::new();
+        trie.insert("example.com");
+        trie.insert("gov.tr");
+
+        assert!(trie.record_hit("a.example.com"));
+        assert!(trie.record_hit("example.com"));
+        assert!(!trie.record_hit("example.org"));
+        let mut hits = trie.hits();
+        hits.sort();
+        assert_eq!(hits, [("example.com".to_string(), 2), ("gov.tr".to_string(), 0)]);
+
+        // A removed and re-added suffix starts over
+        trie.remove("example.com");
+        trie.insert("example.com");
+        assert!(trie.hits().contains(&("example.com".to_string(), 0)));
+    }
+}
@@ -24,7 +24,7 @@
 //! pipeline.add_strategy(FakePacketStrategy::from_config(&config.strategies.fake_packet));
 //!
 //! // Process packets through the pipeline
-//! let mut context = Context::new(&config);
+//! let mut context = Context::from_config(&config).expect("Failed to load domain filter");
 //! let output_packets = pipeline.process(packet, &mut context).expect("Processing failed");
 //! ```
 
@@ -1,53 +1,133 @@
 //! Packet builder utilities
+//!
+//! Synthesizes TCP and UDP packets from scratch, e.g. for a standalone
+//! fake or a reset towards the server:
+//!
+//! ```
+//! use gdpi_core::packet::{PacketBuilder, TcpFlags};
+//!
+//! let rst = PacketBuilder::tcp([10, 0, 0, 1].into(), [93, 184, 216, 34].into())
+//!     .ports(50000, 443)
+//!     .seq(1000)
+//!     .flags(TcpFlags { rst: true, ..Default::default() })
+//!     .build()
+//!     .unwrap();
+//! assert!(rst.is_rst());
+//! ```
+//!
+//! [`PacketBuilder::tcp_ipv4`] and [`PacketBuilder::udp_ipv4`] build IPv4
+//! packets with valid checksums, ready to inject:
+//!
+//! ```
+//! use gdpi_core::packet::PacketBuilder;
+//! use std::net::Ipv4Addr;
+//!
+//! let query = PacketBuilder::udp_ipv4(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(8, 8, 8, 8))
+//!     .src_port(50000)
+//!     .dst_port(53)
+//!     .payload(&[0xAB, 0xCD])
+//!     .build()
+//!     .unwrap();
+//! assert!(query.is_udp());
+//! ```
 
-use super::{Direction, IpVersion, Protocol, TcpFlags};
+use super::{Direction, IpVersion, Packet, Protocol, TcpFlags};
+use crate::error::{Error, Result};
 use bytes::BytesMut;
+use std::net::{IpAddr, Ipv4Addr};
 
 /// Builder for constructing packets
+///
+/// Header lengths are filled in; checksums are left zeroed for the
+/// platform layer (or [`Packet::recalculate_checksums`]) to compute.
 pub struct PacketBuilder {
     ip_version: IpVersion,
     protocol: Protocol,
-    src_ip: [u8; 16],
-    dst_ip: [u8; 16],
+    direction: Direction,
+    src_ip: IpAddr,
+    dst_ip: IpAddr,
     src_port: u16,
     dst_port: u16,
     ttl: u8,
+    ip_id: u16,
     tcp_flags: TcpFlags,
     seq: u32,
     ack: u32,
+    window: u16,
     payload: Vec<u8>,
 }
 
 impl PacketBuilder {
-    /// Create new IPv4 TCP packet builder
-    pub fn tcp_v4() -> Self {
+    /// Create a TCP packet builder from `src` to `dst`
+    ///
+    /// The IP version follows the addresses, which must be the same family.
+    pub fn tcp(src: IpAddr, dst: IpAddr) -> Self {
+        Self::with_protocol(Protocol::Tcp, src, dst)
+    }
+
+    /// Create a UDP packet builder from `src` to `dst`
+    ///
+    /// The IP version follows the addresses, which must be the same family.
+    /// TCP-only settings (flags, sequence numbers, window) are ignored.
+    pub fn udp(src: IpAddr, dst: IpAddr) -> Self {
+        Self::with_protocol(Protocol::Udp, src, dst)
+    }
+
+    /// Create an IPv4 TCP builder whose packets get valid checksums
+    pub fn tcp_ipv4(src: Ipv4Addr, dst: Ipv4Addr) -> TcpIpv4Builder {
+        TcpIpv4Builder {
+            inner: Self::tcp(src.into(), dst.into()),
+        }
+    }
+
+    /// Create an IPv4 UDP builder whose packets get valid checksums
+    pub fn udp_ipv4(src: Ipv4Addr, dst: Ipv4Addr) -> UdpIpv4Builder {
+        UdpIpv4Builder {
+            inner: Self::udp(src.into(), dst.into()),
+        }
+    }
+
+    fn with_protocol(protocol: Protocol, src: IpAddr, dst: IpAddr) -> Self {
         Self {
-            ip_version: IpVersion::V4,
-            protocol: Protocol::Tcp,
-            src_ip: [0; 16],
-            dst_ip: [0; 16],
+            ip_version: if src.is_ipv6() { IpVersion::V6 } else { IpVersion::V4 },
+            protocol,
+            direction: Direction::Outbound,
+            src_ip: src,
+            dst_ip: dst,
             src_port: 0,
             dst_port: 0,
             ttl: 64,
+            ip_id: 0,
             tcp_flags: TcpFlags::default(),
             seq: 0,
             ack: 0,
+            window: 0xFFFF,
             payload: Vec::new(),
         }
     }
 
+    /// Create new IPv4 TCP packet builder
+    pub fn tcp_v4() -> Self {
+        Self::tcp(Ipv4Addr::UNSPECIFIED.into(), Ipv4Addr::UNSPECIFIED.into())
+    }
+
     /// Set source IP (IPv4)
     pub fn src_ip_v4(mut self, ip: [u8; 4]) -> Self {
-        self.src_ip[..4].copy_from_slice(&ip);
+        self.src_ip = ip.into();
         self
     }
 
     /// Set destination IP (IPv4)
     pub fn dst_ip_v4(mut self, ip: [u8; 4]) -> Self {
-        self.dst_ip[..4].copy_from_slice(&ip);
+        self.dst_ip = ip.into();
         self
     }
 
+    /// Set source and destination ports
+    pub fn ports(self, src: u16, dst: u16) -> Self {
+        self.src_port(src).dst_port(dst)
+    }
+
     /// Set source port
     pub fn src_port(mut self, port: u16) -> Self {
         self.src_port = port;
@@ -60,12 +140,24 @@ impl PacketBuilder {
         self
     }
 
-    /// Set TTL
+    /// Set the direction of the built [`Packet`] (default: outbound)
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Set TTL (hop limit for IPv6)
     pub fn ttl(mut self, ttl: u8) -> Self {
         self.ttl = ttl;
         self
     }
 
+    /// Set the IPv4 identification field
+    pub fn ip_id(mut self, id: u16) -> Self {
+        self.ip_id = id;
+        self
+    }
+
     /// Set TCP flags
     pub fn flags(mut self, flags: TcpFlags) -> Self {
         self.tcp_flags = flags;
@@ -84,6 +176,12 @@ impl PacketBuilder {
         self
     }
 
+    /// Set the TCP window size (default: 65535)
+    pub fn window(mut self, window: u16) -> Self {
+        self.window = window;
+        self
+    }
+
     /// Set payload
     pub fn payload(mut self, data: &[u8]) -> Self {
         self.payload = data.to_vec();
@@ -91,40 +189,85 @@ impl PacketBuilder {
     }
 
     /// Build the packet
-    pub fn build(self) -> Vec<u8> {
-        let ip_header_len = 20;
-        let tcp_header_len = 20;
-        let total_len = ip_header_len + tcp_header_len + self.payload.len();
+    pub fn build(self) -> Result<Packet> {
+        if self.src_ip.is_ipv6() != self.dst_ip.is_ipv6() {
+            return Err(Error::packet_parse(format!(
+                "Mixed address families: {} -> {}",
+                self.src_ip, self.dst_ip
+            )));
+        }
+        let direction = self.direction;
+        Packet::from_bytes(&self.build_bytes(), direction)
+    }
+
+    /// Build the raw packet bytes
+    ///
+    /// A destination of the other family than the source is written as
+    /// its IPv4-mapped form; [`build`](Self::build) rejects it instead.
+    pub fn build_bytes(self) -> Vec<u8> {
+        let ip_header_len = match self.ip_version {
+            IpVersion::V4 => 20,
+            IpVersion::V6 => 40,
+        };
+        let transport_header_len = match self.protocol {
+            Protocol::Udp => 8,
+            _ => 20,
+        };
+        let segment_len = transport_header_len + self.payload.len();
+        let total_len = ip_header_len + segment_len;
 
         let mut packet = BytesMut::with_capacity(total_len);
 
-        // IPv4 header
-        packet.extend_from_slice(&[
-            0x45,                                // Version (4) + IHL (5)
-            0x00,                                // DSCP + ECN
-            ((total_len >> 8) & 0xFF) as u8,     // Total Length (high)
-            (total_len & 0xFF) as u8,            // Total Length (low)
-            0x00, 0x00,                          // Identification
-            0x40, 0x00,                          // Flags (DF) + Fragment Offset
-            self.ttl,                            // TTL
-            0x06,                                // Protocol (TCP)
-            0x00, 0x00,                          // Header Checksum (placeholder)
-        ]);
-        packet.extend_from_slice(&self.src_ip[..4]); // Source IP
-        packet.extend_from_slice(&self.dst_ip[..4]); // Dest IP
-
-        // TCP header
+        match self.ip_version {
+            IpVersion::V4 => {
+                packet.extend_from_slice(&[
+                    0x45,                                // Version (4) + IHL (5)
+                    0x00,                                // DSCP + ECN
+                    ((total_len >> 8) & 0xFF) as u8,     // Total Length (high)
+                    (total_len & 0xFF) as u8,            // Total Length (low)
+                    (self.ip_id >> 8) as u8,             // Identification (high)
+                    (self.ip_id & 0xFF) as u8,           // Identification (low)
+                    0x40, 0x00,                          // Flags (DF) + Fragment Offset
+                    self.ttl,                            // TTL
+                    self.protocol.to_u8(),               // Protocol
+                    0x00, 0x00,                          // Header Checksum (placeholder)
+                ]);
+                packet.extend_from_slice(&v4_octets(self.src_ip)); // Source IP
+                packet.extend_from_slice(&v4_octets(self.dst_ip)); // Dest IP
+            }
+            IpVersion::V6 => {
+                packet.extend_from_slice(&[
+                    0x60, 0x00, 0x00, 0x00,              // Version (6) + Traffic Class + Flow Label
+                    ((segment_len >> 8) & 0xFF) as u8,   // Payload Length (high)
+                    (segment_len & 0xFF) as u8,          // Payload Length (low)
+                    self.protocol.to_u8(),               // Next Header
+                    self.ttl,                            // Hop Limit
+                ]);
+                packet.extend_from_slice(&v6_octets(self.src_ip)); // Source IP
+                packet.extend_from_slice(&v6_octets(self.dst_ip)); // Dest IP
+            }
+        }
+
         packet.extend_from_slice(&self.src_port.to_be_bytes());
         packet.extend_from_slice(&self.dst_port.to_be_bytes());
-        packet.extend_from_slice(&self.seq.to_be_bytes());
-        packet.extend_from_slice(&self.ack.to_be_bytes());
-        packet.extend_from_slice(&[
-            0x50,                           // Data Offset (5 * 4 = 20 bytes)
-            self.tcp_flags.to_byte(),       // Flags
-            0xFF, 0xFF,                     // Window Size
-            0x00, 0x00,                     // Checksum (placeholder)
-            0x00, 0x00,                     // Urgent Pointer
-        ]);
+        if self.protocol == Protocol::Udp {
+            // UDP header
+            packet.extend_from_slice(&(segment_len as u16).to_be_bytes()); // Length
+            packet.extend_from_slice(&[0x00, 0x00]); // Checksum (placeholder)
+        } else {
+            // TCP header
+            packet.extend_from_slice(&self.seq.to_be_bytes());
+            packet.extend_from_slice(&self.ack.to_be_bytes());
+            packet.extend_from_slice(&[
+                0x50,                           // Data Offset (5 * 4 = 20 bytes)
+                self.tcp_flags.to_byte(),       // Flags
+            ]);
+            packet.extend_from_slice(&self.window.to_be_bytes()); // Window Size
+            packet.extend_from_slice(&[
+                0x00, 0x00,                     // Checksum (placeholder)
+                0x00, 0x00,                     // Urgent Pointer
+            ]);
+        }
 
         // Payload
         packet.extend_from_slice(&self.payload);
@@ -133,6 +276,148 @@ impl PacketBuilder {
     }
 }
 
+/// IPv4 TCP packet builder, from [`PacketBuilder::tcp_ipv4`]
+///
+/// Unlike [`PacketBuilder`], the built packet has its checksums filled in.
+pub struct TcpIpv4Builder {
+    inner: PacketBuilder,
+}
+
+impl TcpIpv4Builder {
+    /// Set source port
+    pub fn src_port(mut self, port: u16) -> Self {
+        self.inner = self.inner.src_port(port);
+        self
+    }
+
+    /// Set destination port
+    pub fn dst_port(mut self, port: u16) -> Self {
+        self.inner = self.inner.dst_port(port);
+        self
+    }
+
+    /// Set sequence number
+    pub fn seq(mut self, seq: u32) -> Self {
+        self.inner = self.inner.seq(seq);
+        self
+    }
+
+    /// Set acknowledgment number
+    pub fn ack(mut self, ack: u32) -> Self {
+        self.inner = self.inner.ack(ack);
+        self
+    }
+
+    /// Set TCP flags
+    pub fn flags(mut self, flags: TcpFlags) -> Self {
+        self.inner = self.inner.flags(flags);
+        self
+    }
+
+    /// Set TTL
+    pub fn ttl(mut self, ttl: u8) -> Self {
+        self.inner = self.inner.ttl(ttl);
+        self
+    }
+
+    /// Set the IPv4 identification field
+    pub fn ip_id(mut self, id: u16) -> Self {
+        self.inner = self.inner.ip_id(id);
+        self
+    }
+
+    /// Set the TCP window size (default: 65535)
+    pub fn window(mut self, window: u16) -> Self {
+        self.inner = self.inner.window(window);
+        self
+    }
+
+    /// Set the direction of the built [`Packet`] (default: outbound)
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.inner = self.inner.direction(direction);
+        self
+    }
+
+    /// Set payload
+    pub fn payload(mut self, data: &[u8]) -> Self {
+        self.inner = self.inner.payload(data);
+        self
+    }
+
+    /// Build the packet with its IPv4 and TCP checksums
+    pub fn build(self) -> Result<Packet> {
+        let mut packet = self.inner.build()?;
+        packet.fix_checksums();
+        Ok(packet)
+    }
+}
+
+/// IPv4 UDP packet builder, from [`PacketBuilder::udp_ipv4`]
+///
+/// The built packet has its checksums filled in.
+pub struct UdpIpv4Builder {
+    inner: PacketBuilder,
+}
+
+impl UdpIpv4Builder {
+    /// Set source port
+    pub fn src_port(mut self, port: u16) -> Self {
+        self.inner = self.inner.src_port(port);
+        self
+    }
+
+    /// Set destination port
+    pub fn dst_port(mut self, port: u16) -> Self {
+        self.inner = self.inner.dst_port(port);
+        self
+    }
+
+    /// Set TTL
+    pub fn ttl(mut self, ttl: u8) -> Self {
+        self.inner = self.inner.ttl(ttl);
+        self
+    }
+
+    /// Set the IPv4 identification field
+    pub fn ip_id(mut self, id: u16) -> Self {
+        self.inner = self.inner.ip_id(id);
+        self
+    }
+
+    /// Set the direction of the built [`Packet`] (default: outbound)
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.inner = self.inner.direction(direction);
+        self
+    }
+
+    /// Set payload
+    pub fn payload(mut self, data: &[u8]) -> Self {
+        self.inner = self.inner.payload(data);
+        self
+    }
+
+    /// Build the packet with its IPv4 and UDP checksums
+    pub fn build(self) -> Result<Packet> {
+        let mut packet = self.inner.build()?;
+        packet.fix_checksums();
+        Ok(packet)
+    }
+}
+
+fn v4_octets(ip: IpAddr) -> [u8; 4] {
+    match ip {
+        IpAddr::V4(ip) => ip.octets(),
+        IpAddr::V6(ip) => ip.to_ipv4_mapped().unwrap_or(Ipv4Addr::UNSPECIFIED).octets(),
+    }
+}
+
+fn v6_octets(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -147,10 +432,137 @@ mod tests {
             .ttl(64)
             .flags(TcpFlags { ack: true, psh: true, ..Default::default() })
             .payload(b"GET / HTTP/1.1\r\n")
-            .build();
+            .build_bytes();
 
         assert_eq!(packet[0] >> 4, 4); // IPv4
         assert_eq!(packet[9], 6); // TCP
         assert_eq!(packet.len(), 20 + 20 + 16); // IP + TCP + payload
     }
+
+    #[test]
+    fn test_round_trip_v4() {
+        let packet = PacketBuilder::tcp([10, 0, 0, 1].into(), [1, 1, 1, 1].into())
+            .ports(50000, 443)
+            .seq(0x0102_0304)
+            .ack(0x0A0B_0C0D)
+            .flags(TcpFlags { ack: true, psh: true, ..Default::default() })
+            .ttl(5)
+            .ip_id(7)
+            .payload(b"hello")
+            .direction(Direction::Inbound)
+            .build()
+            .unwrap();
+
+        assert!(packet.is_ipv4() && packet.is_tcp() && packet.is_inbound());
+        assert_eq!(packet.src_addr, IpAddr::from([10, 0, 0, 1]));
+        assert_eq!(packet.dst_addr, IpAddr::from([1, 1, 1, 1]));
+        assert_eq!((packet.src_port, packet.dst_port), (50000, 443));
+        assert_eq!(packet.tcp_seq(), Some(0x0102_0304));
+        assert_eq!(packet.tcp_ack_num(), Some(0x0A0B_0C0D));
+        assert!(packet.is_ack() && !packet.is_syn());
+        assert_eq!((packet.ttl, packet.ip_id), (5, Some(7)));
+        assert_eq!(packet.payload(), b"hello");
+        assert_eq!(packet.len(), 20 + 20 + 5);
+        // Checksums are left to the caller
+        assert_eq!(&packet.as_bytes()[10..12], [0, 0]);
+        assert_eq!(&packet.as_bytes()[36..38], [0, 0]);
+    }
+
+    #[test]
+    fn test_round_trip_v6() {
+        let src: IpAddr = "2001:db8::1".parse().unwrap();
+        let dst: IpAddr = "2606:4700::1111".parse().unwrap();
+        let packet = PacketBuilder::tcp(src, dst)
+            .ports(40000, 443)
+            .seq(42)
+            .flags(TcpFlags { rst: true, ..Default::default() })
+            .ttl(3)
+            .payload(b"x")
+            .build()
+            .unwrap();
+
+        assert!(packet.is_ipv6() && packet.is_tcp() && packet.is_outbound());
+        assert_eq!((packet.src_addr, packet.dst_addr), (src, dst));
+        assert_eq!((packet.src_port, packet.dst_port), (40000, 443));
+        assert_eq!(packet.tcp_seq(), Some(42));
+        assert!(packet.is_rst());
+        assert_eq!(packet.ttl, 3);
+        assert_eq!(packet.payload(), b"x");
+        assert_eq!(packet.len(), 40 + 20 + 1);
+        // Payload Length excludes the fixed header
+        assert_eq!(&packet.as_bytes()[4..6], [0, 21]);
+    }
+
+    #[test]
+    fn test_tcp_ipv4_builder() {
+        let packet = PacketBuilder::tcp_ipv4(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(1, 1, 1, 1))
+            .src_port(50000)
+            .dst_port(443)
+            .seq(100)
+            .ack(200)
+            .flags(TcpFlags { ack: true, psh: true, ..Default::default() })
+            .ttl(8)
+            .ip_id(9)
+            .window(1024)
+            .payload(b"hello")
+            .build()
+            .unwrap();
+
+        assert!(packet.is_ipv4() && packet.is_tcp() && packet.is_outbound());
+        assert_eq!((packet.src_port, packet.dst_port), (50000, 443));
+        assert_eq!((packet.tcp_seq(), packet.tcp_ack_num()), (Some(100), Some(200)));
+        assert_eq!((packet.ttl, packet.ip_id), (8, Some(9)));
+        assert_eq!(&packet.as_bytes()[34..36], 1024u16.to_be_bytes());
+        assert_eq!(&packet.as_bytes()[2..4], [0, 45]);
+        assert_eq!(packet.payload(), b"hello");
+
+        // Recomputing leaves valid checksums unchanged
+        let mut fixed = packet.clone();
+        fixed.fix_checksums();
+        assert_eq!(fixed.as_bytes(), packet.as_bytes());
+        assert_ne!(&packet.as_bytes()[10..12], [0, 0]);
+        assert_ne!(&packet.as_bytes()[36..38], [0, 0]);
+    }
+
+    #[test]
+    fn test_udp_ipv4_builder() {
+        let packet = PacketBuilder::udp_ipv4(Ipv4Addr::new(8, 8, 8, 8), Ipv4Addr::new(10, 0, 0, 1))
+            .src_port(53)
+            .dst_port(50000)
+            .direction(Direction::Inbound)
+            .payload(b"answer")
+            .build()
+            .unwrap();
+
+        assert!(packet.is_ipv4() && packet.is_udp() && packet.is_inbound());
+        assert_eq!((packet.src_port, packet.dst_port), (53, 50000));
+        assert_eq!(packet.payload(), b"answer");
+        assert_eq!(packet.len(), 20 + 8 + 6);
+        assert_eq!(&packet.as_bytes()[2..4], [0, 34]);
+        // UDP length covers header and payload
+        assert_eq!(&packet.as_bytes()[24..26], [0, 14]);
+
+        let mut fixed = packet.clone();
+        fixed.fix_checksums();
+        assert_eq!(fixed.as_bytes(), packet.as_bytes());
+        assert_ne!(&packet.as_bytes()[26..28], [0, 0]);
+    }
+
+    #[test]
+    fn test_udp_v6() {
+        let src: IpAddr = "2001:db8::1".parse().unwrap();
+        let dst: IpAddr = "2001:4860:4860::8888".parse().unwrap();
+        let packet = PacketBuilder::udp(src, dst).ports(40000, 53).payload(b"q").build().unwrap();
+
+        assert!(packet.is_ipv6() && packet.is_udp());
+        assert_eq!((packet.src_port, packet.dst_port), (40000, 53));
+        assert_eq!(packet.payload(), b"q");
+        assert_eq!(&packet.as_bytes()[4..6], [0, 9]);
+    }
+
+    #[test]
+    fn test_mixed_families_rejected() {
+        let v6: IpAddr = "::1".parse().unwrap();
+        assert!(PacketBuilder::tcp([10, 0, 0, 1].into(), v6).build().is_err());
+    }
 }
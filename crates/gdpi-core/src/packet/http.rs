@@ -0,0 +1,139 @@
+//! HTTP/1.x request parsing
+//!
+//! Just enough of a plaintext request to match its hostname against the
+//! domain filter and to locate the parts header mangling touches. Only
+//! the request line and header block are read, never the body.
+
+use super::MAX_HOSTNAME_LEN;
+
+/// Lowercased Host header value of an HTTP request, without its port
+///
+/// The header name matches in any case, and any number of spaces or tabs
+/// may follow the colon. A last header without its CRLF (request cut at
+/// the segment end) still counts.
+pub fn host(payload: &[u8]) -> Option<String> {
+    let value = header_lines(payload).find_map(|line| {
+        let colon = line.iter().position(|&b| b == b':')?;
+        line[..colon]
+            .eq_ignore_ascii_case(b"host")
+            .then(|| trim(&line[colon + 1..]))
+    })?;
+
+    let host = strip_port(value);
+    let valid = host
+        .iter()
+        .all(|&b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'_' | b':' | b'[' | b']'));
+    if host.is_empty() || host.len() > MAX_HOSTNAME_LEN || !valid {
+        return None;
+    }
+    Some(String::from_utf8_lossy(host).to_ascii_lowercase())
+}
+
+/// Method and request target of an HTTP request line
+///
+/// `None` unless the first line reads `METHOD target HTTP/x.y`.
+pub fn request_line(payload: &[u8]) -> Option<(&str, &str)> {
+    let line = lines(payload).next()?;
+    let mut parts = std::str::from_utf8(line).ok()?.split(' ');
+    let (method, target, version) = (parts.next()?, parts.next()?, parts.next()?);
+
+    if parts.next().is_some()
+        || method.is_empty()
+        || !method.bytes().all(|b| b.is_ascii_uppercase())
+        || target.is_empty()
+        || !version.starts_with("HTTP/")
+    {
+        return None;
+    }
+    Some((method, target))
+}
+
+/// Lines of `payload` without their line endings
+fn lines(payload: &[u8]) -> impl Iterator<Item = &[u8]> {
+    payload
+        .split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+/// Header lines, from after the request line up to the empty line
+fn header_lines(payload: &[u8]) -> impl Iterator<Item = &[u8]> {
+    lines(payload).skip(1).take_while(|line| !line.is_empty())
+}
+
+fn trim(value: &[u8]) -> &[u8] {
+    let is_space = |b: &u8| *b == b' ' || *b == b'\t';
+    let start = value.iter().position(|b| !is_space(b)).unwrap_or(value.len());
+    let end = value.iter().rposition(|b| !is_space(b)).map_or(start, |end| end + 1);
+    &value[start..end]
+}
+
+/// `host` of `host:port`, keeping IPv6 literals such as `[::1]` whole
+fn strip_port(value: &[u8]) -> &[u8] {
+    if value.starts_with(b"[") {
+        return match value.iter().position(|&b| b == b']') {
+            Some(end) => &value[..=end],
+            None => value,
+        };
+    }
+    match value.iter().rposition(|&b| b == b':') {
+        Some(colon) if value[colon + 1..].iter().all(u8::is_ascii_digit) => &value[..colon],
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host() {
+        let long = format!("GET / HTTP/1.1\r\nHost: {}.com\r\n\r\n", "a".repeat(MAX_HOSTNAME_LEN));
+        let longest = format!("GET / HTTP/1.1\r\nHost: {}\r\n\r\n", "a".repeat(MAX_HOSTNAME_LEN));
+        let cases: &[(&str, &[u8], Option<&str>)] = &[
+            ("plain", b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n", Some("example.com")),
+            ("lowercase name", b"GET / HTTP/1.1\r\nhost: example.com\r\n\r\n", Some("example.com")),
+            ("uppercase name", b"GET / HTTP/1.1\r\nHOST: Example.COM\r\n\r\n", Some("example.com")),
+            ("no space", b"GET / HTTP/1.1\r\nHost:example.com\r\n\r\n", Some("example.com")),
+            ("spaces", b"GET / HTTP/1.1\r\nHost:   example.com  \r\n\r\n", Some("example.com")),
+            ("tab", b"GET / HTTP/1.1\r\nHost:\texample.com\r\n\r\n", Some("example.com")),
+            ("port", b"GET / HTTP/1.1\r\nHost: example.com:8080\r\n\r\n", Some("example.com")),
+            ("ipv6 with port", b"GET / HTTP/1.1\r\nHost: [::1]:8080\r\n\r\n", Some("[::1]")),
+            ("ipv6", b"GET / HTTP/1.1\r\nHost: [::1]\r\n\r\n", Some("[::1]")),
+            ("after other headers", b"GET / HTTP/1.1\r\nAccept: */*\r\nHost: a.co\r\n\r\n", Some("a.co")),
+            ("no trailing crlf", b"GET / HTTP/1.1\r\nAccept: */*\r\nHost: example.com", Some("example.com")),
+            ("bare lf", b"GET / HTTP/1.1\nHost: example.com\n\n", Some("example.com")),
+            ("missing", b"GET / HTTP/1.1\r\nAccept: */*\r\n\r\n", None),
+            ("in body", b"POST / HTTP/1.1\r\nAccept: */*\r\n\r\nHost: example.com\r\n", None),
+            ("in request line", b"GET /Host: HTTP/1.1\r\n\r\n", None),
+            ("lookalike name", b"GET / HTTP/1.1\r\nX-Host: example.com\r\n\r\n", None),
+            ("empty", b"GET / HTTP/1.1\r\nHost: \r\n\r\n", None),
+            ("inner space", b"GET / HTTP/1.1\r\nHost: exa mple.com\r\n\r\n", None),
+            ("too long", long.as_bytes(), None),
+            ("longest", longest.as_bytes(), Some(&longest[22..22 + MAX_HOSTNAME_LEN])),
+        ];
+
+        for (name, payload, expected) in cases {
+            assert_eq!(host(payload).as_deref(), *expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_request_line() {
+        type Case = (&'static [u8], Option<(&'static str, &'static str)>);
+        let cases: &[Case] = &[
+            (b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n", Some(("GET", "/"))),
+            (b"POST /api/v1?x=1 HTTP/1.0\r\n", Some(("POST", "/api/v1?x=1"))),
+            (b"CONNECT example.com:443 HTTP/1.1\r\n", Some(("CONNECT", "example.com:443"))),
+            (b"GET / HTTP/1.1", Some(("GET", "/"))),
+            (b"GET  / HTTP/1.1\r\n", None),
+            (b"get / HTTP/1.1\r\n", None),
+            (b"GET /\r\n", None),
+            (b"GET / SPDY/3\r\n", None),
+            (b"\x16\x03\x01\x02\x00", None),
+        ];
+
+        for (payload, expected) in cases {
+            assert_eq!(request_line(payload), *expected, "{}", String::from_utf8_lossy(payload));
+        }
+    }
+}
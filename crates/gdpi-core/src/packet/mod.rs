@@ -3,11 +3,16 @@
 //! Low-level packet handling for TCP/IP traffic.
 
 mod builder;
+pub mod http;
 mod parser;
+pub mod quic;
+pub mod tls;
 mod types;
 
-pub use builder::PacketBuilder;
+pub use builder::{PacketBuilder, TcpIpv4Builder, UdpIpv4Builder};
 pub use parser::PacketParser;
+pub use quic::{QuicInitial, QuicInitialParser};
+pub use tls::{ClientHelloBuilder, ClientHelloInfo};
 pub use types::*;
 
 use crate::error::{Error, Result};
@@ -20,6 +25,10 @@ pub const MAX_PACKET_SIZE: usize = 9016;
 /// Maximum hostname length (DNS standard)
 pub const MAX_HOSTNAME_LEN: usize = 253;
 
+/// Most IPv6 extension headers walked before giving up on the transport
+/// header
+const MAX_IPV6_EXTENSION_HEADERS: usize = 8;
+
 /// Represents a network packet with parsed headers
 #[derive(Debug, Clone)]
 pub struct Packet {
@@ -51,6 +60,10 @@ pub struct Packet {
     pub ip_id: Option<u16>,
     /// Flag indicating this is a fake/decoy packet (should not be fragmented)
     pub is_fake: bool,
+    /// Fragment offset in bytes (0 for unfragmented packets)
+    fragment_offset: u16,
+    /// More Fragments flag: the datagram continues in a later fragment
+    more_fragments: bool,
 }
 
 impl Packet {
@@ -78,6 +91,8 @@ impl Packet {
             ttl: 0,
             ip_id: None,
             is_fake: false,
+            fragment_offset: 0,
+            more_fragments: false,
         };
 
         packet.parse()?;
@@ -119,6 +134,11 @@ impl Packet {
         // Parse IP ID
         self.ip_id = Some(u16::from_be_bytes([self.data[4], self.data[5]]));
 
+        // Parse flags and fragment offset (8-octet units)
+        let flags_offset = u16::from_be_bytes([self.data[6], self.data[7]]);
+        self.more_fragments = flags_offset & 0x2000 != 0;
+        self.fragment_offset = (flags_offset & 0x1FFF) << 3;
+
         // Parse TTL
         self.ttl = self.data[8];
 
@@ -156,15 +176,10 @@ impl Packet {
         }
 
         self.ip_version = IpVersion::V6;
-        self.ip_header_len = 40; // Fixed for IPv6
 
         // Parse Hop Limit (TTL equivalent)
         self.ttl = self.data[7];
 
-        // Parse Next Header (protocol)
-        let proto = self.data[6];
-        self.protocol = Protocol::from_u8(proto);
-
         // Parse addresses
         let mut src_bytes = [0u8; 16];
         let mut dst_bytes = [0u8; 16];
@@ -174,14 +189,69 @@ impl Packet {
         self.src_addr = IpAddr::V6(Ipv6Addr::from(src_bytes));
         self.dst_addr = IpAddr::V6(Ipv6Addr::from(dst_bytes));
 
+        // Next Header (protocol), after any extension headers
+        let (proto, header_len) = self.walk_ipv6_extensions()?;
+        self.ip_header_len = header_len;
+        self.protocol = Protocol::from_u8(proto);
+
         // Parse transport layer
         self.parse_transport()?;
 
         Ok(())
     }
 
+    /// Follow the Next Header chain from the fixed IPv6 header
+    ///
+    /// Returns the first protocol that isn't an extension header and the
+    /// offset of its header. A chain longer than
+    /// [`MAX_IPV6_EXTENSION_HEADERS`] ends at its last extension type,
+    /// which parses as [`Protocol::Unknown`].
+    fn walk_ipv6_extensions(&mut self) -> Result<(u8, usize)> {
+        let mut next = self.data[6];
+        let mut offset = 40;
+
+        for _ in 0..MAX_IPV6_EXTENSION_HEADERS {
+            let len = match next {
+                // Hop-by-Hop, Routing, Destination Options: 8-octet units
+                // past the first
+                0 | 43 | 60 => self.data.get(offset + 1).map(|&n| (usize::from(n) + 1) * 8),
+                // Fragment: fixed size
+                44 => Some(8),
+                // Authentication Header: 4-octet units, minus two
+                51 => self.data.get(offset + 1).map(|&n| (usize::from(n) + 2) * 4),
+                _ => break,
+            };
+
+            let len = match len {
+                Some(len) if self.data.len() >= offset + len => len,
+                _ => {
+                    return Err(Error::PacketTooSmall {
+                        expected: offset + len.unwrap_or(8),
+                        actual: self.data.len(),
+                    })
+                }
+            };
+            if next == 44 {
+                // Offset in 8-octet units, two reserved bits, M flag
+                let offset_flags = u16::from_be_bytes([self.data[offset + 2], self.data[offset + 3]]);
+                self.more_fragments = offset_flags & 0x0001 != 0;
+                self.fragment_offset = offset_flags & !0x0007;
+            }
+
+            next = self.data[offset];
+            offset += len;
+        }
+
+        Ok((next, offset))
+    }
+
     /// Parse transport layer (TCP/UDP)
     fn parse_transport(&mut self) -> Result<()> {
+        // Later fragments carry the rest of the payload, not a header
+        if self.fragment_offset != 0 {
+            return Ok(());
+        }
+
         let offset = self.ip_header_len;
 
         match self.protocol {
@@ -270,6 +340,24 @@ impl Packet {
         matches!(self.ip_version, IpVersion::V6)
     }
 
+    /// Check if this is part of a fragmented datagram
+    ///
+    /// True for the first fragment too, although only the later ones
+    /// lack a transport header.
+    pub fn is_fragment(&self) -> bool {
+        self.fragment_offset != 0 || self.more_fragments
+    }
+
+    /// Get the offset of this fragment's data in the datagram, in bytes
+    pub fn fragment_offset(&self) -> u16 {
+        self.fragment_offset
+    }
+
+    /// Check if more fragments of the datagram follow this one
+    pub fn more_fragments(&self) -> bool {
+        self.more_fragments
+    }
+
     /// Check if TCP SYN flag is set
     pub fn is_syn(&self) -> bool {
         self.tcp_flags.map(|f| f.syn).unwrap_or(false)
@@ -280,6 +368,11 @@ impl Packet {
         self.tcp_flags.map(|f| f.ack).unwrap_or(false)
     }
 
+    /// Check if TCP FIN flag is set
+    pub fn is_fin(&self) -> bool {
+        self.tcp_flags.map(|f| f.fin).unwrap_or(false)
+    }
+
     /// Check if TCP RST flag is set
     pub fn is_rst(&self) -> bool {
         self.tcp_flags.map(|f| f.rst).unwrap_or(false)
@@ -326,74 +419,30 @@ impl Packet {
 
     /// Extract SNI from TLS ClientHello
     pub fn extract_sni(&self) -> Option<String> {
-        let payload = self.payload();
-        if payload.len() < 44 {
-            return None;
-        }
+        ClientHelloInfo::parse(self.payload())?.sni
+    }
 
-        // Look for SNI extension (type 0x00 0x00)
-        let mut ptr = 0;
-        while ptr + 10 < payload.len() {
-            // Look for SNI extension pattern:
-            // [0x00, 0x00] = extension type (SNI)
-            // [ext_len_hi, ext_len_lo] = extension length
-            // [list_len_hi, list_len_lo] = server name list length
-            // [0x00] = name type (hostname)
-            // [name_len_hi, name_len_lo] = name length
-            if payload[ptr] == 0x00 && payload[ptr + 1] == 0x00 {
-                // This might be the SNI extension
-                if ptr + 9 >= payload.len() {
-                    ptr += 1;
-                    continue;
-                }
-                
-                let ext_len = ((payload[ptr + 2] as usize) << 8) | (payload[ptr + 3] as usize);
-                let list_len = ((payload[ptr + 4] as usize) << 8) | (payload[ptr + 5] as usize);
-                let name_type = payload[ptr + 6];
-                let name_len = ((payload[ptr + 7] as usize) << 8) | (payload[ptr + 8] as usize);
-                
-                // Validate lengths: ext_len = list_len + 2, list_len = name_len + 3, name_type = 0
-                if ext_len == list_len + 2 && list_len == name_len + 3 && name_type == 0x00 {
-                    let sni_start = ptr + 9;
-                    let sni_end = sni_start + name_len;
-
-                    if sni_end <= payload.len() && name_len >= 3 && name_len <= MAX_HOSTNAME_LEN {
-                        let sni_bytes = &payload[sni_start..sni_end];
-                        
-                        // Validate hostname characters (allow lowercase, digits, dot, hyphen)
-                        if sni_bytes.iter().all(|&b| {
-                            (b >= b'0' && b <= b'9')
-                                || (b >= b'a' && b <= b'z')
-                                || b == b'.'
-                                || b == b'-'
-                        }) {
-                            return String::from_utf8(sni_bytes.to_vec()).ok();
-                        }
-                    }
-                }
-            }
-            ptr += 1;
+    /// Extract SNI from a QUIC Initial packet (UDP 443)
+    ///
+    /// Decrypts the Initial to reach the ClientHello; see [`QuicInitialParser`].
+    pub fn extract_quic_sni(&self) -> Option<String> {
+        if !self.is_udp() || self.dst_port != 443 {
+            return None;
         }
-
-        None
+        QuicInitialParser::parse(self.payload())?.sni
     }
 
-    /// Extract Host header from HTTP request
+    /// Extract the Host header from an HTTP request
+    ///
+    /// Lowercased and without a port; see [`http::host`].
     pub fn extract_http_host(&self) -> Option<String> {
-        let payload = self.payload();
-        let payload_str = std::str::from_utf8(payload).ok()?;
-
-        // Find "Host: " header
-        let host_marker = "\r\nHost: ";
-        let host_start = payload_str.find(host_marker)? + host_marker.len();
-        let host_end = payload_str[host_start..].find("\r\n")? + host_start;
+        http::host(self.payload())
+    }
 
-        let host = &payload_str[host_start..host_end];
-        if host.len() >= 3 && host.len() <= MAX_HOSTNAME_LEN {
-            Some(host.to_string())
-        } else {
-            None
-        }
+    /// Extract the method and request target from an HTTP request line
+    pub fn extract_http_method_and_path(&self) -> Option<(String, String)> {
+        let (method, path) = http::request_line(self.payload())?;
+        Some((method.to_string(), path.to_string()))
     }
 
     /// Get the raw packet data
@@ -421,6 +470,11 @@ impl Packet {
         Bytes::copy_from_slice(&self.data)
     }
 
+    /// Turn the packet into its raw data without copying
+    pub fn into_bytes(self) -> Bytes {
+        self.data.freeze()
+    }
+
     /// Get TCP sequence number
     pub fn tcp_seq(&self) -> Option<u32> {
         if !self.is_tcp() {
@@ -484,6 +538,56 @@ impl Packet {
         }
     }
 
+    /// Set TCP flags
+    pub fn set_tcp_flags(&mut self, flags: TcpFlags) {
+        if self.is_tcp() {
+            self.data[self.ip_header_len + 13] = flags.to_byte();
+            self.tcp_flags = Some(flags);
+        }
+    }
+
+    /// Set source address (must match the packet's IP version)
+    pub fn set_src_addr(&mut self, addr: IpAddr) -> Result<()> {
+        let range = match (self.ip_version, addr) {
+            (IpVersion::V4, IpAddr::V4(_)) => 12..16,
+            (IpVersion::V6, IpAddr::V6(_)) => 8..24,
+            _ => return Err(Error::InvalidIpAddr { addr: addr.to_string() }),
+        };
+        self.data[range].copy_from_slice(&ip_octets(addr));
+        self.src_addr = addr;
+        Ok(())
+    }
+
+    /// Set destination address (must match the packet's IP version)
+    pub fn set_dst_addr(&mut self, addr: IpAddr) -> Result<()> {
+        let range = match (self.ip_version, addr) {
+            (IpVersion::V4, IpAddr::V4(_)) => 16..20,
+            (IpVersion::V6, IpAddr::V6(_)) => 24..40,
+            _ => return Err(Error::InvalidIpAddr { addr: addr.to_string() }),
+        };
+        self.data[range].copy_from_slice(&ip_octets(addr));
+        self.dst_addr = addr;
+        Ok(())
+    }
+
+    /// Set TCP/UDP source port
+    pub fn set_src_port(&mut self, port: u16) {
+        if self.is_tcp() || self.is_udp() {
+            let offset = self.ip_header_len;
+            self.data[offset..offset + 2].copy_from_slice(&port.to_be_bytes());
+            self.src_port = port;
+        }
+    }
+
+    /// Set TCP/UDP destination port
+    pub fn set_dst_port(&mut self, port: u16) {
+        if self.is_tcp() || self.is_udp() {
+            let offset = self.ip_header_len + 2;
+            self.data[offset..offset + 2].copy_from_slice(&port.to_be_bytes());
+            self.dst_port = port;
+        }
+    }
+
     /// Get IP header length
     pub fn ip_header_len(&self) -> usize {
         self.ip_header_len
@@ -500,7 +604,9 @@ impl Packet {
     }
 
     /// Create a new packet with different payload
-    /// Copies headers from this packet and uses the provided payload
+    ///
+    /// Copies the headers from this packet, followed by `new_payload`, and
+    /// updates the IP/UDP lengths and the checksums to match.
     pub fn with_new_payload(&self, new_payload: &[u8]) -> Result<Self> {
         let header_len = self.ip_header_len + self.transport_header_len;
         
@@ -512,7 +618,8 @@ impl Packet {
         let mut packet = self.clone();
         packet.data = new_data;
         packet.update_lengths()?;
-        
+        packet.fix_checksums();
+
         Ok(packet)
     }
 
@@ -539,6 +646,58 @@ impl Packet {
         }
     }
 
+    /// Recalculate IPv4 header and TCP/UDP checksums in place
+    ///
+    /// WinDivert recomputes checksums on reinjection, but packets whose
+    /// addresses were rewritten should be valid on their own too.
+    pub fn recalculate_checksums(&mut self) {
+        self.fix_checksums();
+    }
+
+    /// Write correct IPv4 header and TCP/UDP checksums
+    ///
+    /// The transport checksum covers the IPv4 or IPv6 pseudo-header, so a
+    /// packet built or edited in process is valid without a driver to
+    /// fill them in.
+    pub fn fix_checksums(&mut self) {
+        self.zero_checksums();
+
+        let ip_len = self.ip_header_len;
+        if self.is_ipv4() && self.data.len() >= ip_len {
+            let checksum = compute_ipv4_checksum(&self.data[..ip_len]);
+            self.data[10..12].copy_from_slice(&checksum.to_be_bytes());
+        }
+
+        let offset = match self.protocol {
+            Protocol::Tcp => ip_len + 16,
+            Protocol::Udp => ip_len + 6,
+            _ => return,
+        };
+        if self.data.len() < offset + 2 {
+            return;
+        }
+
+        let segment = &self.data[ip_len..];
+        let checksum = match (self.src_addr, self.dst_addr, self.protocol) {
+            (IpAddr::V4(src), IpAddr::V4(dst), Protocol::Tcp) => {
+                PacketParser::tcp_checksum_ipv4(&src.octets(), &dst.octets(), segment)
+            }
+            (IpAddr::V4(src), IpAddr::V4(dst), _) => {
+                PacketParser::udp_checksum_ipv4(&src.octets(), &dst.octets(), segment)
+            }
+            (IpAddr::V6(src), IpAddr::V6(dst), protocol) => PacketParser::transport_checksum_ipv6(
+                &src.octets(),
+                &dst.octets(),
+                protocol.to_u8(),
+                segment,
+            ),
+            _ => return,
+        };
+        // A computed UDP checksum of zero is sent as all ones
+        let checksum = if checksum == 0 && self.is_udp() { 0xFFFF } else { checksum };
+        self.data[offset..offset + 2].copy_from_slice(&checksum.to_be_bytes());
+    }
+
     /// Update IP total length field
     pub fn update_ip_length(&mut self) {
         let total_len = self.data.len();
@@ -592,6 +751,48 @@ impl Packet {
         Ok((first, second))
     }
 
+    /// Split packet at several payload offsets, one fragment per interval
+    ///
+    /// Offsets must be strictly increasing and inside the payload
+    /// (`0 < offset < payload_len`). TCP sequence numbers advance with
+    /// each fragment, so `[1, 2, 3]` yields four fragments.
+    pub fn split_at_payloads(&self, offsets: &[usize]) -> Result<Vec<Self>> {
+        let header_len = self.ip_header_len + self.transport_header_len;
+        let payload = self.payload();
+
+        if offsets.first() == Some(&0) || offsets.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(Error::strategy("split", "Split offsets must be non-zero and strictly increasing"));
+        }
+        if offsets.last().is_some_and(|&last| last >= payload.len()) {
+            return Err(Error::strategy("split", "Split offset exceeds payload length"));
+        }
+
+        let seq = self.tcp_seq();
+        let bounds = std::iter::once(0)
+            .chain(offsets.iter().copied())
+            .chain(std::iter::once(payload.len()))
+            .collect::<Vec<_>>();
+
+        let mut fragments = Vec::with_capacity(bounds.len() - 1);
+        for range in bounds.windows(2) {
+            let (start, end) = (range[0], range[1]);
+
+            let mut data = BytesMut::with_capacity(header_len + end - start);
+            data.extend_from_slice(&self.data[..header_len]);
+            data.extend_from_slice(&payload[start..end]);
+
+            let mut fragment = self.clone();
+            fragment.data = data;
+            if let Some(seq) = seq {
+                fragment.set_tcp_seq(seq.wrapping_add(start as u32));
+            }
+            fragment.update_lengths()?;
+            fragments.push(fragment);
+        }
+
+        Ok(fragments)
+    }
+
     /// Update IP and TCP length fields after modification
     /// Also zeroes out checksums so WinDivert can recalculate them
     fn update_lengths(&mut self) -> Result<()> {
@@ -623,10 +824,41 @@ impl Packet {
             self.data[tcp_checksum_offset + 1] = 0;
         }
 
+        // UDP length covers the header and payload
+        if self.is_udp() && self.data.len() >= self.ip_header_len + 8 {
+            let udp_len = ((total_len - self.ip_header_len) as u16).to_be_bytes();
+            self.data[self.ip_header_len + 4..self.ip_header_len + 6].copy_from_slice(&udp_len);
+        }
+
         Ok(())
     }
 }
 
+/// Checksum of an IPv4 header
+///
+/// The one's complement of the one's-complement sum of the header's 16-bit
+/// words, with the checksum field (bytes 10-11) counted as zero.
+pub fn compute_ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .enumerate()
+        .filter(|&(i, _)| i != 5)
+        .map(|(_, word)| u32::from(u16::from_be_bytes([word[0], word.get(1).copied().unwrap_or(0)])))
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Raw address bytes (4 for IPv4, 16 for IPv6)
+fn ip_octets(addr: IpAddr) -> Vec<u8> {
+    match addr {
+        IpAddr::V4(addr) => addr.octets().to_vec(),
+        IpAddr::V6(addr) => addr.octets().to_vec(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -672,10 +904,238 @@ mod tests {
         assert!(!flags.syn);
     }
 
+    #[test]
+    fn test_compute_ipv4_checksum() {
+        // Header with the well-known checksum 0xB861
+        let mut header = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11,
+            0xB8, 0x61, 0xC0, 0xA8, 0x00, 0x01, 0xC0, 0xA8, 0x00, 0xC7,
+        ];
+        assert_eq!(compute_ipv4_checksum(&header), 0xB861);
+
+        // The stored checksum doesn't matter
+        header[10..12].fill(0);
+        assert_eq!(compute_ipv4_checksum(&header), 0xB861);
+        assert_eq!(PacketParser::ipv4_header_checksum(&header), 0xB861);
+    }
+
+    #[test]
+    fn test_fix_checksums() {
+        let mut packet = Packet::from_bytes(&create_test_tcp_packet(), Direction::Outbound).unwrap();
+        packet.set_dst_addr("10.0.0.1".parse().unwrap()).unwrap();
+        packet.set_ttl(3);
+        packet.fix_checksums();
+
+        let data = packet.as_bytes();
+        assert_eq!(PacketParser::internet_checksum(&data[..20]), 0);
+        let (src, dst) = (data[12..16].try_into().unwrap(), data[16..20].try_into().unwrap());
+        assert_eq!(PacketParser::tcp_checksum_ipv4(src, dst, &data[20..]), 0);
+    }
+
+    #[test]
+    fn test_with_new_payload() {
+        let packet = Packet::from_bytes(&create_test_tcp_packet(), Direction::Outbound).unwrap();
+        let replaced = packet.with_new_payload(b"longer payload").unwrap();
+
+        assert_eq!(replaced.payload(), b"longer payload");
+        assert_eq!((replaced.src_port, replaced.dst_port), (packet.src_port, packet.dst_port));
+        let data = replaced.as_bytes();
+        assert_eq!(usize::from(u16::from_be_bytes([data[2], data[3]])), data.len());
+        assert_eq!(PacketParser::internet_checksum(&data[..20]), 0);
+        let (src, dst) = (data[12..16].try_into().unwrap(), data[16..20].try_into().unwrap());
+        assert_eq!(PacketParser::tcp_checksum_ipv4(src, dst, &data[20..]), 0);
+    }
+
+    #[test]
+    fn test_ipv4_fragments() {
+        // Don't Fragment alone is not a fragment
+        let mut data = create_test_tcp_packet();
+        data[6] = 0x40;
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+        assert!(!packet.is_fragment());
+
+        // First fragment: More Fragments, offset 0; the TCP header follows
+        data[6] = 0x20;
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+        assert!(packet.is_fragment());
+        assert!(packet.more_fragments());
+        assert_eq!(packet.fragment_offset(), 0);
+        assert_eq!(packet.dst_port, 443);
+
+        // Last fragment at 1480 bytes: what follows is payload, not a header
+        data[6..8].copy_from_slice(&(1480u16 / 8).to_be_bytes());
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+        assert!(packet.is_fragment());
+        assert!(!packet.more_fragments());
+        assert_eq!(packet.fragment_offset(), 1480);
+        assert_eq!((packet.src_port, packet.dst_port), (0, 0));
+        assert_eq!(packet.tcp_flags, None);
+        assert_eq!(packet.payload().len(), 20);
+    }
+
     #[test]
     fn test_packet_too_small() {
         let data = vec![0x45, 0x00];
         let result = Packet::from_bytes(&data, Direction::Outbound);
         assert!(matches!(result, Err(Error::PacketTooSmall { .. })));
     }
+
+    fn create_payload_packet(payload: &[u8]) -> Packet {
+        let data = PacketBuilder::tcp_v4()
+            .src_ip_v4([192, 168, 1, 1])
+            .dst_ip_v4([192, 168, 1, 2])
+            .src_port(50000)
+            .dst_port(443)
+            .seq(1000)
+            .payload(payload)
+            .build_bytes();
+        Packet::from_bytes(&data, Direction::Outbound).unwrap()
+    }
+
+    #[test]
+    fn test_split_at_payloads() {
+        let packet = create_payload_packet(b"abcdefgh");
+        let fragments = packet.split_at_payloads(&[1, 2, 3]).unwrap();
+
+        assert_eq!(fragments.len(), 4);
+        let payloads: Vec<&[u8]> = fragments.iter().map(|f| f.payload()).collect();
+        assert_eq!(payloads, vec![&b"a"[..], b"b", b"c", b"defgh"]);
+        assert_eq!(payloads.concat(), b"abcdefgh");
+
+        let seqs: Vec<u32> = fragments.iter().map(|f| f.tcp_seq().unwrap()).collect();
+        assert_eq!(seqs, vec![1000, 1001, 1002, 1003]);
+
+        for fragment in &fragments {
+            let total_len = u16::from_be_bytes([fragment.as_bytes()[2], fragment.as_bytes()[3]]);
+            assert_eq!(total_len as usize, fragment.len());
+        }
+    }
+
+    #[test]
+    fn test_split_at_payloads_invalid_offsets() {
+        let packet = create_payload_packet(b"abcdefgh");
+
+        assert!(packet.split_at_payloads(&[2, 2]).is_err());
+        assert!(packet.split_at_payloads(&[3, 1]).is_err());
+        assert!(packet.split_at_payloads(&[0, 4]).is_err());
+        assert!(packet.split_at_payloads(&[4, 8]).is_err());
+        assert_eq!(packet.split_at_payloads(&[]).unwrap().len(), 1);
+    }
+
+    /// IPv6 header followed by `rest`, whose first header is `next`
+    fn ipv6_packet(next: u8, rest: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x60, 0x00, 0x00, 0x00];
+        data.extend_from_slice(&u16::try_from(rest.len()).unwrap().to_be_bytes());
+        data.extend_from_slice(&[next, 64]);
+        data.extend_from_slice(&Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1).octets());
+        data.extend_from_slice(&Ipv6Addr::new(0x2606, 0x4700, 0, 0, 0, 0, 0, 0x6811).octets());
+        data.extend_from_slice(rest);
+        data
+    }
+
+    /// TCP header from port 50000 to 443 (ACK+PSH) followed by `payload`
+    fn tcp_segment(payload: &[u8]) -> Vec<u8> {
+        let mut segment = vec![
+            0xC3, 0x50, 0x01, 0xBB, // Src Port (50000), Dst Port (443)
+            0x00, 0x00, 0x03, 0xE8, // Sequence Number
+            0x00, 0x00, 0x00, 0x01, // Ack Number
+            0x50, 0x18, 0xFF, 0xFF, // Data Offset, Flags (ACK+PSH), Window
+            0x00, 0x00, 0x00, 0x00, // Checksum, Urgent Pointer
+        ];
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    #[test]
+    fn test_ipv6_without_extensions() {
+        let data = ipv6_packet(6, &tcp_segment(b"hello"));
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+
+        assert!(packet.is_ipv6());
+        assert!(packet.is_tcp());
+        assert_eq!(packet.ip_header_len(), 40);
+        assert_eq!(packet.payload(), b"hello");
+    }
+
+    #[test]
+    fn test_ipv6_one_extension_header() {
+        // Hop-by-Hop Options, 8 bytes: Next Header TCP, PadN
+        let mut rest = vec![6, 0, 1, 4, 0, 0, 0, 0];
+        rest.extend(tcp_segment(b"hello"));
+        let packet = Packet::from_bytes(&ipv6_packet(0, &rest), Direction::Outbound).unwrap();
+
+        assert!(packet.is_tcp());
+        assert_eq!(packet.ip_header_len(), 48);
+        assert_eq!((packet.src_port, packet.dst_port), (50000, 443));
+        assert_eq!(packet.tcp_seq(), Some(1000));
+        assert_eq!(packet.payload(), b"hello");
+        assert!(!packet.is_fragment());
+    }
+
+    #[test]
+    fn test_ipv6_two_extension_headers() {
+        // Hop-by-Hop (8 bytes) -> Destination Options (16 bytes) -> UDP
+        let mut rest = vec![60, 0, 1, 4, 0, 0, 0, 0];
+        rest.extend_from_slice(&[17, 1, 1, 12]);
+        rest.extend_from_slice(&[0; 12]);
+        rest.extend_from_slice(&[0xC3, 0x50, 0x01, 0xBB, 0x00, 0x0B, 0x00, 0x00, b'q', b'u', b'i']);
+        let packet = Packet::from_bytes(&ipv6_packet(0, &rest), Direction::Outbound).unwrap();
+
+        assert!(packet.is_udp());
+        assert_eq!(packet.ip_header_len(), 64);
+        assert_eq!((packet.src_port, packet.dst_port), (50000, 443));
+        assert_eq!(packet.payload(), b"qui");
+    }
+
+    #[test]
+    fn test_ipv6_fragments() {
+        // First fragment: offset 0, more fragments; the TCP header follows
+        let mut rest = vec![6, 0, 0x00, 0x01, 0, 0, 0, 7];
+        rest.extend(tcp_segment(b"hello"));
+        let packet = Packet::from_bytes(&ipv6_packet(44, &rest), Direction::Outbound).unwrap();
+        assert!(packet.is_tcp());
+        assert!(packet.is_fragment());
+        assert!(packet.more_fragments());
+        assert_eq!(packet.fragment_offset(), 0);
+        assert_eq!(packet.dst_port, 443);
+
+        // Later fragment: offset 1480 bytes, only payload follows
+        let offset = (1480u16 / 8) << 3;
+        let mut rest = vec![6, 0];
+        rest.extend_from_slice(&offset.to_be_bytes());
+        rest.extend_from_slice(&[0, 0, 0, 7]);
+        rest.extend_from_slice(&[0xAB; 24]);
+        let packet = Packet::from_bytes(&ipv6_packet(44, &rest), Direction::Outbound).unwrap();
+        assert!(packet.is_fragment());
+        assert!(!packet.more_fragments());
+        assert_eq!(packet.fragment_offset(), 1480);
+        assert_eq!(packet.ip_header_len(), 48);
+        assert_eq!((packet.src_port, packet.dst_port), (0, 0));
+        assert_eq!(packet.tcp_flags, None);
+    }
+
+    #[test]
+    fn test_ipv6_extension_header_limits() {
+        // Claims 16 bytes of options, but the packet ends after 8
+        let data = ipv6_packet(0, &[6, 1, 1, 4, 0, 0, 0, 0]);
+        assert!(matches!(
+            Packet::from_bytes(&data, Direction::Outbound),
+            Err(Error::PacketTooSmall { expected: 56, .. })
+        ));
+
+        // Too long a chain stops at an extension type
+        let rest: Vec<u8> = std::iter::repeat([60, 0, 1, 4, 0, 0, 0, 0])
+            .take(MAX_IPV6_EXTENSION_HEADERS + 1)
+            .flatten()
+            .collect();
+        let packet = Packet::from_bytes(&ipv6_packet(60, &rest), Direction::Outbound).unwrap();
+        assert_eq!(packet.protocol, Protocol::Unknown);
+    }
+
+    #[test]
+    fn test_extract_quic_sni_ignores_tcp() {
+        let data = create_test_tcp_packet();
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+        assert_eq!(packet.extract_quic_sni(), None);
+    }
 }
@@ -1,5 +1,6 @@
 //! Packet parser utilities
 
+use super::tls::ClientHelloInfo;
 use crate::error::Result;
 
 /// Packet parser for detailed protocol analysis
@@ -79,21 +80,81 @@ impl PacketParser {
         Self::internet_checksum(&pseudo)
     }
 
+    /// Calculate TCP/UDP checksum with IPv6 pseudo-header
+    pub fn transport_checksum_ipv6(
+        src_ip: &[u8; 16],
+        dst_ip: &[u8; 16],
+        next_header: u8,
+        segment: &[u8],
+    ) -> u16 {
+        let mut pseudo = Vec::with_capacity(40 + segment.len() + 1);
+        pseudo.extend_from_slice(src_ip);
+        pseudo.extend_from_slice(dst_ip);
+        pseudo.extend_from_slice(&(segment.len() as u32).to_be_bytes());
+        pseudo.extend_from_slice(&[0, 0, 0, next_header]);
+        pseudo.extend_from_slice(segment);
+
+        // Pad if odd length
+        if pseudo.len() % 2 != 0 {
+            pseudo.push(0);
+        }
+
+        Self::internet_checksum(&pseudo)
+    }
+
     /// Calculate IPv4 header checksum
     pub fn ipv4_header_checksum(header: &[u8]) -> u16 {
-        // Zero out existing checksum field for calculation
-        let mut header_copy = header.to_vec();
-        if header_copy.len() >= 12 {
-            header_copy[10] = 0;
-            header_copy[11] = 0;
-        }
-        Self::internet_checksum(&header_copy)
+        super::compute_ipv4_checksum(header)
+    }
+
+    /// Extract the SNI hostname from a TLS ClientHello handshake message
+    ///
+    /// `handshake` starts at the handshake header (type `0x01`) without a
+    /// TLS record header, which is how QUIC carries it in CRYPTO frames.
+    /// A truncated message is parsed as far as it goes.
+    pub fn client_hello_sni(handshake: &[u8]) -> Option<String> {
+        ClientHelloInfo::parse_handshake(handshake)?.sni
+    }
+
+    /// Offset of the SNI hostname in a TLS record carrying a ClientHello
+    ///
+    /// Returns `None` when the hostname isn't in this record, e.g. when
+    /// the ClientHello continues in a further one.
+    pub fn tls_sni_offset(payload: &[u8]) -> Option<usize> {
+        ClientHelloInfo::parse(payload)?.offset_of_sni
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::packet::tls::tests::CLIENT_HELLO;
+
+    #[test]
+    fn test_tls_sni_offset() {
+        let offset = PacketParser::tls_sni_offset(&CLIENT_HELLO).unwrap();
+        assert_eq!(offset, 150);
+        assert_eq!(&CLIENT_HELLO[offset..offset + 11], b"discord.com");
+        assert_eq!(
+            PacketParser::client_hello_sni(&CLIENT_HELLO[5..]).as_deref(),
+            Some("discord.com")
+        );
+
+        // Truncated after the hostname is still fine
+        assert_eq!(PacketParser::tls_sni_offset(&CLIENT_HELLO[..170]), Some(150));
+        // Cut before the extension
+        assert_eq!(PacketParser::tls_sni_offset(&CLIENT_HELLO[..120]), None);
+    }
+
+    #[test]
+    fn test_tls_sni_offset_multiple_records() {
+        // Same ClientHello with its first record cut short at 100 bytes
+        let mut split = CLIENT_HELLO.to_vec();
+        split[3..5].copy_from_slice(&100u16.to_be_bytes());
+        assert_eq!(PacketParser::tls_sni_offset(&split), None);
+
+        assert_eq!(PacketParser::tls_sni_offset(b"GET / HTTP/1.1\r\n"), None);
+    }
 
     #[test]
     fn test_internet_checksum_rfc1071() {
@@ -0,0 +1,523 @@
+//! QUIC Initial packet parsing
+//!
+//! Client Initial packets are encrypted, but with keys derived only from
+//! the Destination Connection ID (RFC 9001 §5.2). Anyone on the path can
+//! remove the protection and read the TLS ClientHello inside, which is
+//! exactly what DPI boxes do and what we need for SNI-based blocking.
+
+use super::parser::PacketParser;
+use aes::cipher::{generic_array::GenericArray, BlockEncrypt};
+use aes::Aes128;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes128Gcm, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+/// QUIC version 1 (RFC 9000)
+pub const QUIC_V1: u32 = 0x0000_0001;
+
+/// QUIC version 2 (RFC 9369)
+pub const QUIC_V2: u32 = 0x6b33_43cf;
+
+/// Initial salt for QUIC v1 (RFC 9001 §5.2)
+const INITIAL_SALT_V1: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17,
+    0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad, 0xcc, 0xbb, 0x7f, 0x0a,
+];
+
+/// Initial salt for QUIC v2 (RFC 9369 §3.3.1)
+const INITIAL_SALT_V2: [u8; 20] = [
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93,
+    0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb, 0xf9, 0xbd, 0x2e, 0xd9,
+];
+
+/// Maximum connection ID length (RFC 9000 §17.2)
+const MAX_CID_LEN: usize = 20;
+
+/// AEAD authentication tag length for AES-128-GCM
+const AEAD_TAG_LEN: usize = 16;
+
+/// Header protection sample length
+const HP_SAMPLE_LEN: usize = 16;
+
+/// Upper bound on reassembled CRYPTO data we are willing to buffer
+const MAX_CRYPTO_LEN: usize = 16 * 1024;
+
+/// Parsed client QUIC Initial packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuicInitial {
+    /// QUIC version from the long header
+    pub version: u32,
+    /// Destination Connection ID (keys are derived from it)
+    pub dcid: Vec<u8>,
+    /// Source Connection ID
+    pub scid: Vec<u8>,
+    /// Contiguous CRYPTO data starting at offset 0
+    pub crypto: Vec<u8>,
+    /// Server name from the ClientHello, if present in this packet
+    pub sni: Option<String>,
+}
+
+/// Parser for client QUIC Initial packets
+pub struct QuicInitialParser;
+
+impl QuicInitialParser {
+    /// Parse a UDP payload as a client QUIC Initial packet
+    ///
+    /// Validates the long header, removes header protection, decrypts the
+    /// payload with the Initial keys derived from the DCID and hands the
+    /// CRYPTO data to the TLS ClientHello parser.
+    ///
+    /// Returns `None` if the payload is not a v1/v2 Initial, fails to decrypt,
+    /// or carries no CRYPTO data starting at offset 0 (e.g. the second packet
+    /// of a ClientHello split across Initials).
+    pub fn parse(payload: &[u8]) -> Option<QuicInitial> {
+        let header = LongHeader::parse(payload)?;
+        let keys = InitialKeys::client(header.version, header.dcid)?;
+        let plaintext = keys.decrypt(payload, &header)?;
+        let crypto = reassemble_crypto(&plaintext)?;
+
+        Some(QuicInitial {
+            version: header.version,
+            dcid: header.dcid.to_vec(),
+            scid: header.scid.to_vec(),
+            sni: PacketParser::client_hello_sni(&crypto),
+            crypto,
+        })
+    }
+}
+
+/// Parse a client QUIC Initial packet and extract its ClientHello
+///
+/// Shorthand for [`QuicInitialParser::parse`].
+pub fn parse_initial(payload: &[u8]) -> Option<QuicInitial> {
+    QuicInitialParser::parse(payload)
+}
+
+/// Read a QUIC variable-length integer (RFC 9000 §16)
+///
+/// Returns the value and the number of bytes consumed.
+fn read_varint(data: &[u8]) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    let len = 1usize << (first >> 6);
+    let bytes = data.get(..len)?;
+
+    let mut value = u64::from(first & 0x3f);
+    for &b in &bytes[1..] {
+        value = (value << 8) | u64::from(b);
+    }
+    Some((value, len))
+}
+
+/// Unprotected fields of an Initial long header
+struct LongHeader<'a> {
+    version: u32,
+    dcid: &'a [u8],
+    scid: &'a [u8],
+    /// Offset of the (protected) packet number
+    pn_offset: usize,
+    /// End of this packet within the datagram (Length field)
+    packet_end: usize,
+}
+
+impl<'a> LongHeader<'a> {
+    fn parse(payload: &'a [u8]) -> Option<Self> {
+        let first = *payload.first()?;
+
+        // Long header form with the fixed bit set
+        if first & 0xC0 != 0xC0 {
+            return None;
+        }
+
+        let version = u32::from_be_bytes(payload.get(1..5)?.try_into().ok()?);
+        let initial_type = match version {
+            QUIC_V1 => 0b00,
+            QUIC_V2 => 0b01,
+            _ => return None,
+        };
+        if (first >> 4) & 0x03 != initial_type {
+            return None;
+        }
+
+        let mut pos = 5;
+        let dcid_len = *payload.get(pos)? as usize;
+        if dcid_len > MAX_CID_LEN {
+            return None;
+        }
+        let dcid = payload.get(pos + 1..pos + 1 + dcid_len)?;
+        pos += 1 + dcid_len;
+
+        let scid_len = *payload.get(pos)? as usize;
+        if scid_len > MAX_CID_LEN {
+            return None;
+        }
+        let scid = payload.get(pos + 1..pos + 1 + scid_len)?;
+        pos += 1 + scid_len;
+
+        let (token_len, n) = read_varint(payload.get(pos..)?)?;
+        pos += n + usize::try_from(token_len).ok()?;
+
+        let (length, n) = read_varint(payload.get(pos..)?)?;
+        pos += n;
+
+        let packet_end = pos.checked_add(usize::try_from(length).ok()?)?;
+        if packet_end > payload.len() || pos + 4 + HP_SAMPLE_LEN > packet_end {
+            return None;
+        }
+
+        Some(Self {
+            version,
+            dcid,
+            scid,
+            pn_offset: pos,
+            packet_end,
+        })
+    }
+}
+
+/// Client Initial packet protection keys
+struct InitialKeys {
+    key: [u8; 16],
+    iv: [u8; 12],
+    hp: [u8; 16],
+}
+
+impl InitialKeys {
+    /// Derive the client Initial keys for `version` from `dcid`
+    fn client(version: u32, dcid: &[u8]) -> Option<Self> {
+        let (salt, prefix): (&[u8], &str) = match version {
+            QUIC_V1 => (&INITIAL_SALT_V1, "quic"),
+            QUIC_V2 => (&INITIAL_SALT_V2, "quicv2"),
+            _ => return None,
+        };
+
+        let (initial_secret, _) = Hkdf::<Sha256>::extract(Some(salt), dcid);
+        let mut client_secret = [0u8; 32];
+        expand_label(&initial_secret, "client in", &mut client_secret)?;
+
+        let mut keys = Self {
+            key: [0; 16],
+            iv: [0; 12],
+            hp: [0; 16],
+        };
+        expand_label(&client_secret, &format!("{prefix} key"), &mut keys.key)?;
+        expand_label(&client_secret, &format!("{prefix} iv"), &mut keys.iv)?;
+        expand_label(&client_secret, &format!("{prefix} hp"), &mut keys.hp)?;
+        Some(keys)
+    }
+
+    /// Header protection mask for the given ciphertext sample
+    fn hp_mask(&self, sample: &[u8]) -> Option<[u8; 16]> {
+        let cipher = Aes128::new_from_slice(&self.hp).ok()?;
+        let mut block = GenericArray::clone_from_slice(sample);
+        cipher.encrypt_block(&mut block);
+        Some(block.into())
+    }
+
+    /// AEAD nonce for a packet number
+    fn nonce(&self, packet_number: u64) -> [u8; 12] {
+        let mut nonce = self.iv;
+        for (n, p) in nonce[4..].iter_mut().zip(packet_number.to_be_bytes()) {
+            *n ^= p;
+        }
+        nonce
+    }
+
+    /// Remove header protection and decrypt the packet payload
+    fn decrypt(&self, payload: &[u8], header: &LongHeader<'_>) -> Option<Vec<u8>> {
+        let pn_offset = header.pn_offset;
+        let sample = &payload[pn_offset + 4..pn_offset + 4 + HP_SAMPLE_LEN];
+        let mask = self.hp_mask(sample)?;
+
+        // Unmasked header becomes the AAD
+        let first = payload[0] ^ (mask[0] & 0x0f);
+        let pn_len = usize::from(first & 0x03) + 1;
+        let mut aad = payload[..pn_offset + pn_len].to_vec();
+        aad[0] = first;
+
+        let mut packet_number = 0u64;
+        for i in 0..pn_len {
+            aad[pn_offset + i] ^= mask[1 + i];
+            packet_number = (packet_number << 8) | u64::from(aad[pn_offset + i]);
+        }
+
+        let ciphertext = &payload[pn_offset + pn_len..header.packet_end];
+        if ciphertext.len() < AEAD_TAG_LEN {
+            return None;
+        }
+
+        let cipher = Aes128Gcm::new_from_slice(&self.key).ok()?;
+        cipher
+            .decrypt(
+                Nonce::from_slice(&self.nonce(packet_number)),
+                Payload {
+                    msg: ciphertext,
+                    aad: &aad,
+                },
+            )
+            .ok()
+    }
+}
+
+/// HKDF-Expand-Label from TLS 1.3 (RFC 8446 §7.1) with an empty context
+fn expand_label(secret: &[u8], label: &str, out: &mut [u8]) -> Option<()> {
+    let hk = Hkdf::<Sha256>::from_prk(secret).ok()?;
+
+    let full_label = format!("tls13 {label}");
+    let mut info = Vec::with_capacity(4 + full_label.len());
+    info.extend_from_slice(&u16::try_from(out.len()).ok()?.to_be_bytes());
+    info.push(u8::try_from(full_label.len()).ok()?);
+    info.extend_from_slice(full_label.as_bytes());
+    info.push(0);
+
+    hk.expand(&info, out).ok()
+}
+
+/// Walk the decrypted frames and join CRYPTO data from offset 0
+///
+/// Browsers shuffle CRYPTO frames within an Initial, so frames are
+/// sorted by offset before joining. Returns `None` if nothing starts at 0.
+fn reassemble_crypto(frames: &[u8]) -> Option<Vec<u8>> {
+    let mut chunks: Vec<(usize, &[u8])> = Vec::new();
+    let mut pos = 0;
+
+    while pos < frames.len() {
+        let (frame_type, n) = read_varint(&frames[pos..])?;
+        pos += n;
+
+        match frame_type {
+            // PADDING, PING
+            0x00 | 0x01 => {}
+            // ACK, ACK with ECN counts
+            0x02 | 0x03 => {
+                // Largest Acknowledged, ACK Delay
+                for _ in 0..2 {
+                    pos += read_varint(frames.get(pos..)?)?.1;
+                }
+                let (range_count, n) = read_varint(frames.get(pos..)?)?;
+                pos += n;
+                // First ACK Range, then (Gap, ACK Range Length) pairs
+                pos += read_varint(frames.get(pos..)?)?.1;
+                for _ in 0..range_count {
+                    pos += read_varint(frames.get(pos..)?)?.1;
+                    pos += read_varint(frames.get(pos..)?)?.1;
+                }
+                if frame_type == 0x03 {
+                    // ECT0, ECT1, ECN-CE counts
+                    for _ in 0..3 {
+                        pos += read_varint(frames.get(pos..)?)?.1;
+                    }
+                }
+            }
+            // CRYPTO
+            0x06 => {
+                let (offset, n) = read_varint(frames.get(pos..)?)?;
+                pos += n;
+                let (length, n) = read_varint(frames.get(pos..)?)?;
+                pos += n;
+
+                let offset = usize::try_from(offset).ok()?;
+                let length = usize::try_from(length).ok()?;
+                let data = frames.get(pos..pos.checked_add(length)?)?;
+                pos += length;
+
+                if offset.saturating_add(length) <= MAX_CRYPTO_LEN {
+                    chunks.push((offset, data));
+                }
+            }
+            // CONNECTION_CLOSE or anything not allowed in Initial packets
+            _ => break,
+        }
+    }
+
+    chunks.sort_by_key(|&(offset, _)| offset);
+
+    let mut crypto = Vec::new();
+    for (offset, data) in chunks {
+        if offset > crypto.len() {
+            break;
+        }
+        let skip = crypto.len() - offset;
+        if skip < data.len() {
+            crypto.extend_from_slice(&data[skip..]);
+        }
+    }
+
+    if crypto.is_empty() {
+        None
+    } else {
+        Some(crypto)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Minimal TLS ClientHello handshake message carrying `sni`
+    pub(crate) fn client_hello(sni: &str) -> Vec<u8> {
+        let name = sni.as_bytes();
+
+        let mut sni_ext = Vec::new();
+        sni_ext.extend_from_slice(&[0x00, 0x00]);
+        sni_ext.extend_from_slice(&((name.len() + 5) as u16).to_be_bytes());
+        sni_ext.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+        sni_ext.push(0x00);
+        sni_ext.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        sni_ext.extend_from_slice(name);
+
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0x11; 32]); // random
+        body.push(0); // session id
+        body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]); // TLS_AES_128_GCM_SHA256
+        body.extend_from_slice(&[0x01, 0x00]); // null compression
+        body.extend_from_slice(&(sni_ext.len() as u16).to_be_bytes());
+        body.extend_from_slice(&sni_ext);
+
+        let mut hello = vec![0x01];
+        hello.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        hello.extend_from_slice(&body);
+        hello
+    }
+
+    /// Build a protected client Initial carrying `frames`, padded to 1200 bytes
+    pub(crate) fn protect_initial(version: u32, dcid: &[u8], frames: &[u8]) -> Vec<u8> {
+        let keys = InitialKeys::client(version, dcid).unwrap();
+        let initial_type: u8 = if version == QUIC_V2 { 0b01 } else { 0b00 };
+        let packet_number: u32 = 2;
+        let pn_len = 4;
+
+        let mut header = vec![0xC0 | (initial_type << 4) | (pn_len as u8 - 1)];
+        header.extend_from_slice(&version.to_be_bytes());
+        header.push(dcid.len() as u8);
+        header.extend_from_slice(dcid);
+        header.push(0); // empty SCID
+        header.push(0); // no token
+
+        // Pad the plaintext so the datagram reaches 1200 bytes
+        let fixed = header.len() + 2 + pn_len + AEAD_TAG_LEN;
+        let mut plaintext = frames.to_vec();
+        plaintext.resize(plaintext.len().max(1200 - fixed), 0x00);
+
+        let length = (pn_len + plaintext.len() + AEAD_TAG_LEN) as u16;
+        header.extend_from_slice(&(0x4000 | length).to_be_bytes());
+        let pn_offset = header.len();
+        header.extend_from_slice(&packet_number.to_be_bytes());
+
+        let cipher = Aes128Gcm::new_from_slice(&keys.key).unwrap();
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&keys.nonce(u64::from(packet_number))),
+                Payload {
+                    msg: &plaintext,
+                    aad: &header,
+                },
+            )
+            .unwrap();
+
+        let mut packet = header;
+        packet.extend_from_slice(&ciphertext);
+
+        let mask = keys
+            .hp_mask(&packet[pn_offset + 4..pn_offset + 4 + HP_SAMPLE_LEN])
+            .unwrap();
+        packet[0] ^= mask[0] & 0x0f;
+        for i in 0..pn_len {
+            packet[pn_offset + i] ^= mask[1 + i];
+        }
+        packet
+    }
+
+    /// CRYPTO frame with a 2-byte offset and length
+    pub(crate) fn crypto_frame(offset: usize, data: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x06];
+        frame.extend_from_slice(&(0x4000 | offset as u16).to_be_bytes());
+        frame.extend_from_slice(&(0x4000 | data.len() as u16).to_be_bytes());
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    #[test]
+    fn test_initial_keys_rfc9001() {
+        // RFC 9001 Appendix A.1
+        let dcid = hex::decode("8394c8f03e515708").unwrap();
+        let keys = InitialKeys::client(QUIC_V1, &dcid).unwrap();
+
+        assert_eq!(hex::encode(keys.key), "1f369613dd76d5467730efcbe3b1a22d");
+        assert_eq!(hex::encode(keys.iv), "fa044b2f42a3fd3b46fb255c");
+        assert_eq!(hex::encode(keys.hp), "9f50449e04a0e810283a1e9933adedd2");
+    }
+
+    #[test]
+    fn test_read_varint() {
+        assert_eq!(read_varint(&[0x25]), Some((37, 1)));
+        assert_eq!(read_varint(&[0x7b, 0xbd]), Some((15293, 2)));
+        assert_eq!(read_varint(&[0x9d, 0x7f, 0x3e, 0x7d]), Some((494_878_333, 4)));
+        assert_eq!(read_varint(&[0x7b]), None);
+    }
+
+    #[test]
+    fn test_parse_initial_extracts_sni() {
+        let dcid = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+        let frames = crypto_frame(0, &client_hello("discord.com"));
+        let packet = protect_initial(QUIC_V1, &dcid, &frames);
+
+        let hello = QuicInitialParser::parse(&packet).unwrap();
+        assert_eq!(hello.version, QUIC_V1);
+        assert_eq!(hello.dcid, dcid);
+        assert_eq!(hello.sni.as_deref(), Some("discord.com"));
+    }
+
+    #[test]
+    fn test_parse_initial_v2() {
+        let dcid = [0x01; 8];
+        let frames = crypto_frame(0, &client_hello("example.com"));
+        let packet = protect_initial(QUIC_V2, &dcid, &frames);
+
+        let hello = parse_initial(&packet).unwrap();
+        assert_eq!(hello.version, QUIC_V2);
+        assert_eq!(hello.sni.as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn test_parse_initial_shuffled_crypto_frames() {
+        let hello = client_hello("www.youtube.com");
+        let (head, tail) = hello.split_at(20);
+
+        let mut frames = crypto_frame(20, tail);
+        frames.push(0x01); // PING
+        frames.extend_from_slice(&crypto_frame(0, head));
+        let packet = protect_initial(QUIC_V1, &[0x42; 8], &frames);
+
+        let parsed = parse_initial(&packet).unwrap();
+        assert_eq!(parsed.crypto, hello);
+        assert_eq!(parsed.sni.as_deref(), Some("www.youtube.com"));
+    }
+
+    #[test]
+    fn test_parse_initial_rejects_tampering() {
+        let frames = crypto_frame(0, &client_hello("example.com"));
+        let mut packet = protect_initial(QUIC_V1, &[0x42; 8], &frames);
+        let last = packet.len() - 1;
+        packet[last] ^= 0xff;
+
+        assert!(parse_initial(&packet).is_none());
+    }
+
+    #[test]
+    fn test_parse_initial_rejects_non_initial() {
+        let mut payload = vec![0xC0, 0x00, 0x00, 0x00, 0x01];
+        payload.resize(1200, 0);
+        assert!(parse_initial(&payload).is_none());
+
+        // Short header
+        payload[0] = 0x40;
+        assert!(parse_initial(&payload).is_none());
+
+        // Handshake packet type
+        let frames = crypto_frame(0, &client_hello("example.com"));
+        let mut packet = protect_initial(QUIC_V1, &[0x42; 8], &frames);
+        packet[0] |= 0x20;
+        assert!(parse_initial(&packet).is_none());
+    }
+}
@@ -0,0 +1,603 @@
+//! TLS ClientHello building and parsing
+//!
+//! [`ClientHelloBuilder`] generates browser-like ClientHello messages for
+//! a chosen SNI, used for fake packets that should look like a handshake
+//! to another site. [`ClientHelloInfo`] walks a captured ClientHello
+//! field by field to find the hostname and what else DPI boxes look at.
+
+use rand::RngCore;
+
+/// TLS record content type of handshake messages
+const CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+
+/// Handshake message type of a ClientHello
+const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+
+/// `server_name` extension
+const EXT_SERVER_NAME: u16 = 0x0000;
+
+/// `application_layer_protocol_negotiation` extension
+const EXT_ALPN: u16 = 0x0010;
+
+/// `supported_versions` extension
+const EXT_SUPPORTED_VERSIONS: u16 = 0x002b;
+
+/// Cipher suites offered (TLS 1.3 first, then common TLS 1.2 suites)
+const CIPHER_SUITES: [u16; 15] = [
+    0x1301, 0x1303, 0x1302, 0xc02b, 0xc02f, 0xcca9, 0xcca8, 0xc02c,
+    0xc030, 0xc00a, 0xc009, 0xc013, 0xc014, 0x002f, 0x0035,
+];
+
+/// Builder for TLS ClientHello messages
+pub struct ClientHelloBuilder {
+    sni: String,
+    alpn: Vec<String>,
+    pad_to: usize,
+}
+
+impl ClientHelloBuilder {
+    /// Create a builder for a ClientHello with the given SNI
+    pub fn new(sni: impl Into<String>) -> Self {
+        Self {
+            sni: sni.into(),
+            alpn: vec!["h2".to_string(), "http/1.1".to_string()],
+            pad_to: 517,
+        }
+    }
+
+    /// Set ALPN protocols
+    pub fn alpn(mut self, protocols: &[&str]) -> Self {
+        self.alpn = protocols.iter().map(|p| (*p).to_string()).collect();
+        self
+    }
+
+    /// Pad the record to this many bytes with the padding extension (0 = off)
+    pub fn pad_to(mut self, len: usize) -> Self {
+        self.pad_to = len;
+        self
+    }
+
+    /// Build the handshake message (without TLS record header)
+    pub fn build_handshake(&self) -> Vec<u8> {
+        let mut rng = rand::thread_rng();
+
+        let mut body = vec![0x03, 0x03]; // legacy_version TLS 1.2
+        let mut random = [0u8; 32];
+        rng.fill_bytes(&mut random);
+        body.extend_from_slice(&random);
+
+        let mut session_id = [0u8; 32];
+        rng.fill_bytes(&mut session_id);
+        body.push(32);
+        body.extend_from_slice(&session_id);
+
+        body.extend_from_slice(&((CIPHER_SUITES.len() * 2) as u16).to_be_bytes());
+        for suite in CIPHER_SUITES {
+            body.extend_from_slice(&suite.to_be_bytes());
+        }
+        body.extend_from_slice(&[0x01, 0x00]); // null compression
+
+        let mut extensions = self.extensions(&mut rng);
+
+        // Padding extension brings the whole record up to `pad_to`
+        // (record header 5 + handshake header 4 + body + ext length 2)
+        let unpadded = 5 + 4 + body.len() + 2 + extensions.len();
+        if self.pad_to > unpadded + 4 {
+            let pad_len = self.pad_to - unpadded - 4;
+            push_extension(&mut extensions, 0x0015, &vec![0u8; pad_len]);
+        }
+
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut handshake = vec![0x01]; // ClientHello
+        handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&body);
+        handshake
+    }
+
+    /// Build a complete TLS record carrying the ClientHello
+    pub fn build(&self) -> Vec<u8> {
+        let handshake = self.build_handshake();
+
+        let mut record = vec![0x16, 0x03, 0x01]; // Handshake, TLS 1.0 record version
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    fn extensions(&self, rng: &mut impl RngCore) -> Vec<u8> {
+        let mut extensions = Vec::new();
+
+        // server_name
+        let name = self.sni.as_bytes();
+        let mut sni = Vec::with_capacity(name.len() + 5);
+        sni.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+        sni.push(0x00); // host_name
+        sni.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        sni.extend_from_slice(name);
+        push_extension(&mut extensions, 0x0000, &sni);
+
+        // extended_master_secret, renegotiation_info
+        push_extension(&mut extensions, 0x0017, &[]);
+        push_extension(&mut extensions, 0xff01, &[0x00]);
+
+        // supported_groups: x25519, secp256r1, secp384r1
+        push_extension(&mut extensions, 0x000a, &[0x00, 0x06, 0x00, 0x1d, 0x00, 0x17, 0x00, 0x18]);
+
+        // ec_point_formats: uncompressed
+        push_extension(&mut extensions, 0x000b, &[0x01, 0x00]);
+
+        // session_ticket
+        push_extension(&mut extensions, 0x0023, &[]);
+
+        // application_layer_protocol_negotiation
+        if !self.alpn.is_empty() {
+            let mut list = Vec::new();
+            for proto in &self.alpn {
+                list.push(proto.len() as u8);
+                list.extend_from_slice(proto.as_bytes());
+            }
+            let mut alpn = (list.len() as u16).to_be_bytes().to_vec();
+            alpn.extend_from_slice(&list);
+            push_extension(&mut extensions, 0x0010, &alpn);
+        }
+
+        // signature_algorithms
+        push_extension(
+            &mut extensions,
+            0x000d,
+            &[
+                0x00, 0x10, 0x04, 0x03, 0x08, 0x04, 0x04, 0x01, 0x05, 0x03,
+                0x08, 0x05, 0x05, 0x01, 0x08, 0x06, 0x06, 0x01,
+            ],
+        );
+
+        // key_share: random x25519 public key
+        let mut key = [0u8; 32];
+        rng.fill_bytes(&mut key);
+        let mut key_share = vec![0x00, 0x24, 0x00, 0x1d, 0x00, 0x20];
+        key_share.extend_from_slice(&key);
+        push_extension(&mut extensions, 0x0033, &key_share);
+
+        // supported_versions: TLS 1.3, TLS 1.2
+        push_extension(&mut extensions, 0x002b, &[0x04, 0x03, 0x04, 0x03, 0x03]);
+
+        // psk_key_exchange_modes: psk_dhe_ke
+        push_extension(&mut extensions, 0x002d, &[0x01, 0x01]);
+
+        extensions
+    }
+}
+
+/// What a TLS ClientHello offers
+///
+/// Parsing stops quietly where the data ends, so a ClientHello cut short
+/// by the end of a TCP segment still yields the fields before the cut.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientHelloInfo {
+    /// Hostname from the `server_name` extension, lowercased
+    pub sni: Option<String>,
+    /// Protocols from the ALPN extension, in order of preference
+    pub alpn: Vec<String>,
+    /// Versions from the `supported_versions` extension without GREASE
+    /// values, or just the legacy version if the extension is missing
+    pub versions: Vec<u16>,
+    /// Offset of the hostname in the parsed bytes
+    pub offset_of_sni: Option<usize>,
+}
+
+impl ClientHelloInfo {
+    /// Parse a TLS record carrying a ClientHello
+    ///
+    /// Returns `None` for anything but a handshake record starting a
+    /// ClientHello. Only this record is read; a ClientHello continuing in
+    /// the next one yields the fields this record holds.
+    pub fn parse(record: &[u8]) -> Option<Self> {
+        if record.len() < 5 || record[0] != CONTENT_TYPE_HANDSHAKE || record[1] != 0x03 {
+            return None;
+        }
+
+        let record_len = usize::from(u16::from_be_bytes([record[3], record[4]]));
+        let end = (5 + record_len).min(record.len());
+        let mut info = Self::parse_handshake(&record[5..end])?;
+        if let Some(offset) = &mut info.offset_of_sni {
+            *offset += 5;
+        }
+        Some(info)
+    }
+
+    /// Parse a ClientHello handshake message without a record header, the
+    /// way QUIC carries it in CRYPTO frames
+    pub fn parse_handshake(handshake: &[u8]) -> Option<Self> {
+        if handshake.first() != Some(&HANDSHAKE_CLIENT_HELLO) {
+            return None;
+        }
+
+        let mut info = Self::default();
+        if let Some(len) = handshake.get(1..4) {
+            let body_len = usize::from(len[0]) << 16 | usize::from(len[1]) << 8 | usize::from(len[2]);
+            let end = (4 + body_len).min(handshake.len());
+            // Truncation only ends the walk
+            let _ = info.read_body(&handshake[..end]);
+        }
+        Some(info)
+    }
+
+    /// Fill in the fields from a ClientHello body, stopping at the first
+    /// field cut short
+    fn read_body(&mut self, handshake: &[u8]) -> Option<()> {
+        let mut pos = 4;
+
+        self.versions = vec![read_u16(handshake, pos)?];
+        // legacy_version (2) + random (32)
+        pos += 34;
+
+        // Session ID
+        pos += 1 + usize::from(*handshake.get(pos)?);
+
+        // Cipher suites
+        pos += 2 + usize::from(read_u16(handshake, pos)?);
+
+        // Compression methods
+        pos += 1 + usize::from(*handshake.get(pos)?);
+
+        let extensions_len = usize::from(read_u16(handshake, pos)?);
+        pos += 2;
+        let extensions_end = (pos + extensions_len).min(handshake.len());
+
+        while pos + 4 <= extensions_end {
+            let ext_type = read_u16(handshake, pos)?;
+            let ext_len = usize::from(read_u16(handshake, pos + 2)?);
+            pos += 4;
+            let ext_end = (pos + ext_len).min(extensions_end);
+            let ext = &handshake[pos..ext_end];
+
+            match ext_type {
+                EXT_SERVER_NAME => {
+                    if let Some(name) = server_name(ext) {
+                        self.sni = Some(name);
+                        // After list length, name type and name length
+                        self.offset_of_sni = Some(pos + 5);
+                    }
+                }
+                EXT_ALPN => self.alpn = alpn_protocols(ext),
+                EXT_SUPPORTED_VERSIONS => {
+                    let versions = supported_versions(ext);
+                    if !versions.is_empty() {
+                        self.versions = versions;
+                    }
+                }
+                _ => {}
+            }
+
+            pos += ext_len;
+        }
+
+        Some(())
+    }
+}
+
+/// ClientHello record without the ALPN protocols `remove` matches
+///
+/// The ALPN, extensions, handshake and record lengths are rewritten, and an
+/// ALPN list left empty drops the extension (RFC 7301 forbids empty ones).
+/// Returns `None` unless `record` holds the whole ClientHello in one
+/// record and offers a protocol to remove, so a ClientHello split across
+/// segments is never touched.
+///
+/// Both ends hash the ClientHello into the handshake transcript, so a
+/// server receiving a rewritten one can't complete the handshake with the
+/// client that sent the original. Use it on ClientHellos built in process,
+/// such as decoys.
+pub fn strip_alpn(record: &[u8], remove: impl Fn(&str) -> bool) -> Option<Vec<u8>> {
+    if record.len() < 9 || record[0] != CONTENT_TYPE_HANDSHAKE || record[5] != HANDSHAKE_CLIENT_HELLO {
+        return None;
+    }
+    let record_len = 5 + usize::from(read_u16(record, 3)?);
+    let handshake_len = 4 + (usize::from(record[6]) << 16 | usize::from(read_u16(record, 7)?));
+    if record.len() != record_len || 5 + handshake_len != record_len {
+        return None;
+    }
+
+    // legacy_version (2) + random (32), then session ID, cipher suites and
+    // compression methods
+    let mut pos = 9 + 34;
+    pos += 1 + usize::from(*record.get(pos)?);
+    pos += 2 + usize::from(read_u16(record, pos)?);
+    pos += 1 + usize::from(*record.get(pos)?);
+    let extensions_len_at = pos;
+    if pos + 2 + usize::from(read_u16(record, pos)?) != record_len {
+        return None;
+    }
+    pos += 2;
+
+    while pos + 4 <= record_len {
+        let ext_type = read_u16(record, pos)?;
+        let ext_end = pos + 4 + usize::from(read_u16(record, pos + 2)?);
+        if ext_end > record_len {
+            return None;
+        }
+        if ext_type != EXT_ALPN {
+            pos = ext_end;
+            continue;
+        }
+
+        let mut kept = Vec::new();
+        let mut entry = pos + 6;
+        while entry < ext_end {
+            let end = entry + 1 + usize::from(record[entry]);
+            let name = record.get(entry + 1..end.min(ext_end))?;
+            if !std::str::from_utf8(name).is_ok_and(&remove) {
+                kept.extend_from_slice(record.get(entry..end)?);
+            }
+            entry = end;
+        }
+        if kept.len() == ext_end - pos - 6 {
+            return None;
+        }
+
+        let mut extension = Vec::new();
+        if !kept.is_empty() {
+            let mut list = (kept.len() as u16).to_be_bytes().to_vec();
+            list.extend_from_slice(&kept);
+            push_extension(&mut extension, EXT_ALPN, &list);
+        }
+        let removed = (ext_end - pos - extension.len()) as u16;
+
+        let mut out = Vec::with_capacity(record.len() - usize::from(removed));
+        out.extend_from_slice(&record[..pos]);
+        out.extend_from_slice(&extension);
+        out.extend_from_slice(&record[ext_end..]);
+        for at in [3, 7, extensions_len_at] {
+            let len = read_u16(&out, at)?.wrapping_sub(removed);
+            out[at..at + 2].copy_from_slice(&len.to_be_bytes());
+        }
+        // The handshake length has a third, high byte
+        if read_u16(record, 7)? < removed {
+            out[6] -= 1;
+        }
+        return Some(out);
+    }
+    None
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?]))
+}
+
+/// Lowercased hostname of a `server_name` extension's first entry
+///
+/// Letters of either case, digits, `.`, `-` and `_` are accepted; IDNs
+/// arrive in their `xn--` form. Anything else, such as spaces, control
+/// characters or raw UTF-8, means no hostname.
+fn server_name(ext: &[u8]) -> Option<String> {
+    // server_name_list length (2), name type (1), name length (2)
+    if *ext.get(2)? != 0x00 {
+        return None;
+    }
+    let name_len = usize::from(read_u16(ext, 3)?);
+    let name = ext.get(5..5 + name_len)?;
+
+    if name.is_empty() || name.len() > super::MAX_HOSTNAME_LEN {
+        return None;
+    }
+    if !name.iter().all(|&b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'_')) {
+        return None;
+    }
+    Some(String::from_utf8_lossy(name).to_ascii_lowercase())
+}
+
+/// Protocol names of an ALPN extension, skipping any that aren't UTF-8
+fn alpn_protocols(ext: &[u8]) -> Vec<String> {
+    let mut protocols = Vec::new();
+    let mut pos = 2;
+    while let Some(&len) = ext.get(pos) {
+        let Some(name) = ext.get(pos + 1..pos + 1 + usize::from(len)) else {
+            break;
+        };
+        if let Ok(name) = std::str::from_utf8(name) {
+            protocols.push(name.to_string());
+        }
+        pos += 1 + usize::from(len);
+    }
+    protocols
+}
+
+/// Versions of a ClientHello `supported_versions` extension without GREASE
+fn supported_versions(ext: &[u8]) -> Vec<u16> {
+    let len = ext.first().map_or(0, |&len| usize::from(len));
+    ext.get(1..)
+        .unwrap_or_default()
+        .chunks_exact(2)
+        .take(len / 2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .filter(|version| version & 0x0f0f != 0x0a0a)
+        .collect()
+}
+
+/// Append a TLS extension (type, length, data)
+fn push_extension(out: &mut Vec<u8>, ext_type: u16, data: &[u8]) {
+    out.extend_from_slice(&ext_type.to_be_bytes());
+    out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::packet::PacketParser;
+
+    /// Chrome-style ClientHello for discord.com, with GREASE values and a
+    /// random and session ID full of `00 00 00` runs
+    pub(crate) const CLIENT_HELLO: [u8; 190] = [
+        0x16, 0x03, 0x01, 0x00, 0xb9, 0x01, 0x00, 0x00, 0xb5, 0x03, 0x03, 0x5a, 0x00, 0x00, 0x00, 0x91,
+        0x3c, 0x7e, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x10, 0x00,
+        0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x10, 0x20, 0x00, 0x00, 0x00, 0x0b,
+        0x00, 0x00, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x0b,
+        0x00, 0x00, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x0b, 0x00, 0x20, 0x4a, 0x4a,
+        0x13, 0x01, 0x13, 0x02, 0x13, 0x03, 0xc0, 0x2b, 0xc0, 0x2f, 0xc0, 0x2c, 0xc0, 0x30, 0xcc, 0xa9,
+        0xcc, 0xa8, 0xc0, 0x13, 0xc0, 0x14, 0x00, 0x9c, 0x00, 0x9d, 0x00, 0x2f, 0x00, 0x35, 0x01, 0x00,
+        0x00, 0x4c, 0x4a, 0x4a, 0x00, 0x00, 0x00, 0x17, 0x00, 0x00, 0xff, 0x01, 0x00, 0x01, 0x00, 0x00,
+        0x0a, 0x00, 0x0a, 0x00, 0x08, 0x4a, 0x4a, 0x00, 0x1d, 0x00, 0x17, 0x00, 0x18, 0x00, 0x00, 0x00,
+        0x10, 0x00, 0x0e, 0x00, 0x00, 0x0b, 0x64, 0x69, 0x73, 0x63, 0x6f, 0x72, 0x64, 0x2e, 0x63, 0x6f,
+        0x6d, 0x00, 0x10, 0x00, 0x0e, 0x00, 0x0c, 0x02, 0x68, 0x32, 0x08, 0x68, 0x74, 0x74, 0x70, 0x2f,
+        0x31, 0x2e, 0x31, 0x00, 0x2b, 0x00, 0x07, 0x06, 0x4a, 0x4a, 0x03, 0x04, 0x03, 0x03,
+    ];
+
+    #[test]
+    fn test_parse_client_hello() {
+        let info = ClientHelloInfo::parse(&CLIENT_HELLO).unwrap();
+        assert_eq!(info.sni.as_deref(), Some("discord.com"));
+        assert_eq!(info.offset_of_sni, Some(150));
+        assert_eq!(info.alpn, ["h2", "http/1.1"]);
+        assert_eq!(info.versions, [0x0304, 0x0303]);
+
+        let handshake = ClientHelloInfo::parse_handshake(&CLIENT_HELLO[5..]).unwrap();
+        assert_eq!(handshake.sni, info.sni);
+        assert_eq!(handshake.offset_of_sni, Some(145));
+    }
+
+    #[test]
+    fn test_parse_truncated_at_every_offset() {
+        let full = ClientHelloInfo::parse(&CLIENT_HELLO).unwrap();
+
+        for cut in 0..=CLIENT_HELLO.len() {
+            let Some(info) = ClientHelloInfo::parse(&CLIENT_HELLO[..cut]) else {
+                assert!(cut <= 5, "rejected at {cut}");
+                continue;
+            };
+
+            // Fields are either complete or missing, never garbage
+            let has_sni = cut >= 150 + "discord.com".len();
+            assert_eq!(info.sni.is_some(), has_sni, "cut at {cut}");
+            assert_eq!(info.offset_of_sni.is_some(), has_sni, "cut at {cut}");
+            if has_sni {
+                assert_eq!(info.sni, full.sni);
+                assert_eq!(info.offset_of_sni, full.offset_of_sni);
+            }
+            assert!(full.alpn.starts_with(&info.alpn), "cut at {cut}: {:?}", info.alpn);
+            assert!(info.versions == [0x0303] || full.versions.starts_with(&info.versions), "cut at {cut}");
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_other_records() {
+        let mut alert = CLIENT_HELLO;
+        alert[0] = 0x15;
+        assert_eq!(ClientHelloInfo::parse(&alert), None);
+
+        let mut server_hello = CLIENT_HELLO;
+        server_hello[5] = 0x02;
+        assert_eq!(ClientHelloInfo::parse(&server_hello), None);
+
+        assert_eq!(ClientHelloInfo::parse(b"GET / HTTP/1.1\r\nHost: discord.com\r\n"), None);
+    }
+
+    #[test]
+    fn test_parse_lowercases_sni() {
+        let record = ClientHelloBuilder::new("Discord.COM").build();
+        let info = ClientHelloInfo::parse(&record).unwrap();
+        assert_eq!(info.sni.as_deref(), Some("discord.com"));
+
+        let offset = info.offset_of_sni.unwrap();
+        assert_eq!(&record[offset..offset + 11], b"Discord.COM");
+    }
+
+    #[test]
+    fn test_parse_sni_charset() {
+        let sni = |name: &str| ClientHelloInfo::parse(&ClientHelloBuilder::new(name).build()).unwrap().sni;
+
+        assert_eq!(sni("EXAMPLE.COM").as_deref(), Some("example.com"));
+        assert_eq!(sni("xn--80ak6aa92e.com").as_deref(), Some("xn--80ak6aa92e.com"));
+        assert_eq!(sni("_acme.Example.com").as_deref(), Some("_acme.example.com"));
+        assert_eq!(sni("a.co").as_deref(), Some("a.co"));
+
+        assert_eq!(sni("exa mple.com"), None);
+        assert_eq!(sni("example.com\n"), None);
+        assert_eq!(sni("пример.com"), None);
+    }
+
+    #[test]
+    fn test_parse_ignores_sni_lookalikes() {
+        // No extensions, but the random reads like a server_name extension
+        let mut hello = vec![0x16, 0x03, 0x01, 0x00, 0x2f, 0x01, 0x00, 0x00, 0x2b, 0x03, 0x03];
+        hello.extend_from_slice(&[0x00, 0x00, 0x00, 0x0c, 0x00, 0x0a, 0x00, 0x00, 0x07]);
+        hello.extend_from_slice(b"evil.co");
+        hello.extend_from_slice(&[0x42; 16]);
+        hello.extend_from_slice(&[0x00, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+
+        let info = ClientHelloInfo::parse(&hello).unwrap();
+        assert_eq!(info.sni, None);
+        assert_eq!(info.versions, [0x0303]);
+    }
+
+    #[test]
+    fn test_strip_alpn() {
+        let record = ClientHelloBuilder::new("discord.com").alpn(&["h3", "h2", "http/1.1"]).build();
+
+        let stripped = strip_alpn(&record, |proto| proto == "h3").unwrap();
+        assert_eq!(stripped.len(), record.len() - 3);
+        assert_eq!(usize::from(u16::from_be_bytes([stripped[3], stripped[4]])), stripped.len() - 5);
+        let handshake_len = u32::from_be_bytes([0, stripped[6], stripped[7], stripped[8]]) as usize;
+        assert_eq!(handshake_len, stripped.len() - 9);
+
+        let info = ClientHelloInfo::parse(&stripped).unwrap();
+        assert_eq!(info.alpn, ["h2", "http/1.1"]);
+        assert_eq!(info.sni.as_deref(), Some("discord.com"));
+        assert_eq!(info.versions, [0x0304, 0x0303]);
+    }
+
+    #[test]
+    fn test_strip_all_alpn() {
+        let stripped = strip_alpn(&CLIENT_HELLO, |_| true).unwrap();
+        // Type, length, list length and both entries are gone
+        assert_eq!(stripped.len(), CLIENT_HELLO.len() - 18);
+
+        let info = ClientHelloInfo::parse(&stripped).unwrap();
+        assert!(info.alpn.is_empty());
+        assert_eq!(info.sni.as_deref(), Some("discord.com"));
+        assert_eq!(info.versions, [0x0304, 0x0303]);
+    }
+
+    #[test]
+    fn test_strip_alpn_refuses() {
+        // Nothing to remove
+        assert_eq!(strip_alpn(&CLIENT_HELLO, |proto| proto == "h3"), None);
+        // Split across segments
+        assert_eq!(strip_alpn(&CLIENT_HELLO[..120], |_| true), None);
+        // Not a ClientHello
+        let mut server_hello = CLIENT_HELLO;
+        server_hello[5] = 0x02;
+        assert_eq!(strip_alpn(&server_hello, |_| true), None);
+    }
+
+    #[test]
+    fn test_client_hello_sni_round_trip() {
+        let handshake = ClientHelloBuilder::new("www.w3.org").build_handshake();
+        assert_eq!(
+            PacketParser::client_hello_sni(&handshake).as_deref(),
+            Some("www.w3.org")
+        );
+    }
+
+    #[test]
+    fn test_client_hello_record() {
+        let record = ClientHelloBuilder::new("example.com").build();
+
+        assert_eq!(&record[..3], &[0x16, 0x03, 0x01]);
+        assert_eq!(record.len(), 517);
+        let record_len = u16::from_be_bytes([record[3], record[4]]) as usize;
+        assert_eq!(record_len, record.len() - 5);
+    }
+
+    #[test]
+    fn test_client_hello_without_padding() {
+        let record = ClientHelloBuilder::new("example.com").pad_to(0).build();
+        assert!(record.len() < 517);
+        assert_eq!(
+            PacketParser::client_hello_sni(&record[5..]).as_deref(),
+            Some("example.com")
+        );
+    }
+}
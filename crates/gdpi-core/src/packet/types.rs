@@ -21,7 +21,7 @@ pub enum IpVersion {
 }
 
 /// Transport protocol
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Protocol {
     /// TCP (protocol number 6)
     Tcp,
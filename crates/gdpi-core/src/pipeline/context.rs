@@ -2,22 +2,32 @@
 //!
 //! Shared state and utilities for strategy execution.
 
-use crate::conntrack::{DnsConnTracker, TcpConnTracker};
+use crate::config::{Config, PerformanceConfig};
+use crate::conntrack::{ConnKey, DnsConnTracker, FlowState, HelloReassembler, ReassembledHello, TcpConnTracker};
+use crate::error::Result;
 use crate::filter::{DomainFilter, FilterMode, FilterResult};
-use crate::packet::Packet;
-use dashmap::DashSet;
-use parking_lot::RwLock;
-use std::collections::HashSet;
+use crate::packet::{ports, Packet, TcpFlags};
+use crate::strategies::StrategyAction;
+use dashmap::DashMap;
+use parking_lot::{Mutex, RwLock};
+use std::collections::{HashMap, HashSet};
 use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Most packets waiting in [`Context::queue_injection`]'s queue
+pub const MAX_QUEUED_INJECTIONS: usize = 1024;
 
 /// Statistics for pipeline execution
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct Stats {
     /// Total packets processed
     pub packets_processed: u64,
     /// Packets fragmented
     pub packets_fragmented: u64,
+    /// Fragments sent twice (`duplicate_first_fragment`)
+    pub fragments_duplicated: u64,
     /// Fake packets sent
     pub fake_packets_sent: u64,
     /// Headers modified
@@ -30,78 +40,395 @@ pub struct Stats {
     pub packets_dropped: u64,
     /// Domains filtered (skipped)
     pub domains_filtered: u64,
+    /// Outcomes per strategy, keyed by strategy name
+    pub per_strategy: HashMap<&'static str, StrategyStats>,
+    /// Times each strategy was applied, keyed by strategy name
+    pub strategy_hits: HashMap<&'static str, u64>,
+    /// Bytes of packets the strategies changed
+    pub bytes_modified: u64,
+    /// Bytes of packets sent on unchanged
+    pub bytes_passed_through: u64,
+    /// TCP connections currently tracked
+    pub tracked_connections: u64,
+    /// DNS queries and TCP flows currently tracked
+    pub tracked_dns: u64,
+    /// Conntrack entries evicted because a table was full
+    pub conntrack_evictions: u64,
+    /// When counting started
+    pub start_time: Instant,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            packets_processed: 0,
+            packets_fragmented: 0,
+            fragments_duplicated: 0,
+            fake_packets_sent: 0,
+            headers_modified: 0,
+            quic_blocked: 0,
+            dns_redirected: 0,
+            packets_dropped: 0,
+            domains_filtered: 0,
+            per_strategy: HashMap::new(),
+            strategy_hits: HashMap::new(),
+            bytes_modified: 0,
+            bytes_passed_through: 0,
+            tracked_connections: 0,
+            tracked_dns: 0,
+            conntrack_evictions: 0,
+            start_time: Instant::now(),
+        }
+    }
+}
+
+impl Stats {
+    /// Average packets processed per second since `start_time`
+    pub fn packets_per_second(&self) -> f64 {
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            self.packets_processed as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    /// Fraction of processed bytes the strategies changed (0.0 - 1.0)
+    pub fn modification_rate(&self) -> f64 {
+        let total = self.bytes_modified + self.bytes_passed_through;
+        if total > 0 {
+            self.bytes_modified as f64 / total as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Zero all counters, keeping `start_time`
+    pub fn reset(&mut self) {
+        *self = Self {
+            start_time: self.start_time,
+            ..Self::default()
+        };
+    }
+
+    /// Per-strategy counters, most applied first (ties by name)
+    pub fn report(&self) -> Vec<(&'static str, StrategyStats)> {
+        let mut report: Vec<_> = self.per_strategy.iter().map(|(&name, &stats)| (name, stats)).collect();
+        report.sort_by(|a, b| b.1.applied.cmp(&a.1.applied).then(a.0.cmp(b.0)));
+        report
+    }
+}
+
+/// How often a strategy fired and what it did
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StrategyStats {
+    /// Times `apply` was called
+    pub applied: u64,
+    /// Packet passed through
+    pub passed: u64,
+    /// Packet replaced
+    pub replaced: u64,
+    /// Packet dropped
+    pub dropped: u64,
+    /// Packets injected before or after the original
+    pub injected: u64,
+}
+
+impl StrategyStats {
+    fn record(&mut self, action: &StrategyAction) {
+        self.applied += 1;
+        match action {
+            StrategyAction::Pass(_) => self.passed += 1,
+            StrategyAction::Replace(_) => self.replaced += 1,
+            StrategyAction::Drop => self.dropped += 1,
+            StrategyAction::InjectBefore(..) | StrategyAction::InjectAfter(..) => self.injected += 1,
+        }
+    }
+}
+
+/// Shared statistics counters
+///
+/// Updated concurrently by every worker processing packets; use
+/// [`Context::get_stats`] for a consistent-enough snapshot.
+#[derive(Debug)]
+pub struct StatsCounters {
+    /// Total packets processed
+    pub packets_processed: AtomicU64,
+    /// Packets fragmented
+    pub packets_fragmented: AtomicU64,
+    /// Fragments sent twice (`duplicate_first_fragment`)
+    pub fragments_duplicated: AtomicU64,
+    /// Fake packets sent
+    pub fake_packets_sent: AtomicU64,
+    /// Headers modified
+    pub headers_modified: AtomicU64,
+    /// QUIC packets blocked
+    pub quic_blocked: AtomicU64,
+    /// DNS queries redirected
+    pub dns_redirected: AtomicU64,
+    /// Packets dropped
+    pub packets_dropped: AtomicU64,
+    /// Domains filtered (skipped)
+    pub domains_filtered: AtomicU64,
+    /// Bytes of packets the strategies changed
+    pub bytes_modified: AtomicU64,
+    /// Bytes of packets sent on unchanged
+    pub bytes_passed_through: AtomicU64,
+    /// Outcomes per strategy, keyed by strategy name
+    per_strategy: DashMap<&'static str, StrategyStats>,
+    /// When counting started (kept across resets)
+    start_time: Instant,
+}
+
+impl Default for StatsCounters {
+    fn default() -> Self {
+        Self {
+            packets_processed: AtomicU64::default(),
+            packets_fragmented: AtomicU64::default(),
+            fragments_duplicated: AtomicU64::default(),
+            fake_packets_sent: AtomicU64::default(),
+            headers_modified: AtomicU64::default(),
+            quic_blocked: AtomicU64::default(),
+            dns_redirected: AtomicU64::default(),
+            packets_dropped: AtomicU64::default(),
+            domains_filtered: AtomicU64::default(),
+            bytes_modified: AtomicU64::default(),
+            bytes_passed_through: AtomicU64::default(),
+            per_strategy: DashMap::new(),
+            start_time: Instant::now(),
+        }
+    }
+}
+
+impl StatsCounters {
+    /// Take a snapshot of all counters
+    pub fn snapshot(&self) -> Stats {
+        let per_strategy: HashMap<_, _> = self
+            .per_strategy
+            .iter()
+            .map(|entry| (*entry.key(), *entry.value()))
+            .collect();
+
+        Stats {
+            packets_processed: self.packets_processed.load(Ordering::Relaxed),
+            packets_fragmented: self.packets_fragmented.load(Ordering::Relaxed),
+            fragments_duplicated: self.fragments_duplicated.load(Ordering::Relaxed),
+            fake_packets_sent: self.fake_packets_sent.load(Ordering::Relaxed),
+            headers_modified: self.headers_modified.load(Ordering::Relaxed),
+            quic_blocked: self.quic_blocked.load(Ordering::Relaxed),
+            dns_redirected: self.dns_redirected.load(Ordering::Relaxed),
+            packets_dropped: self.packets_dropped.load(Ordering::Relaxed),
+            domains_filtered: self.domains_filtered.load(Ordering::Relaxed),
+            strategy_hits: per_strategy.iter().map(|(&name, stats)| (name, stats.applied)).collect(),
+            per_strategy,
+            bytes_modified: self.bytes_modified.load(Ordering::Relaxed),
+            bytes_passed_through: self.bytes_passed_through.load(Ordering::Relaxed),
+            start_time: self.start_time,
+            ..Stats::default()
+        }
+    }
+
+    /// Record the action a strategy returned from `apply`
+    pub fn record_strategy(&self, name: &'static str, action: &StrategyAction) {
+        self.per_strategy.entry(name).or_default().record(action);
+    }
+
+    /// Record a processed packet's size, by whether the strategies changed it
+    pub fn record_bytes(&self, len: usize, modified: bool) {
+        let counter = if modified { &self.bytes_modified } else { &self.bytes_passed_through };
+        counter.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    /// Reset all counters to zero (the start time is kept)
+    pub fn reset(&self) {
+        for counter in [
+            &self.packets_processed,
+            &self.packets_fragmented,
+            &self.fragments_duplicated,
+            &self.fake_packets_sent,
+            &self.headers_modified,
+            &self.quic_blocked,
+            &self.dns_redirected,
+            &self.packets_dropped,
+            &self.domains_filtered,
+            &self.bytes_modified,
+            &self.bytes_passed_through,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.per_strategy.clear();
+    }
 }
 
 /// Execution context for the pipeline
 ///
 /// Provides shared state between strategies including connection tracking,
-/// domain filtering, and statistics.
+/// domain filtering, and statistics. Clones share that state, so each
+/// worker thread can hold its own `Context`.
+#[derive(Clone)]
 pub struct Context {
     /// Processing statistics
-    pub stats: Stats,
+    pub stats: Arc<StatsCounters>,
     /// Domain filter (whitelist/blacklist)
     domain_filter: Arc<DomainFilter>,
     /// TCP connection tracker (for TTL)
     tcp_tracker: Arc<TcpConnTracker>,
     /// DNS connection tracker
     dns_tracker: Arc<DnsConnTracker>,
-    /// Allow connections without SNI
+    /// Starts of ClientHellos split before their SNI
+    hello_reassembler: Arc<HelloReassembler>,
+    /// ClientHello completed by the packet being processed, and its flow
+    continued_hello: Option<(ConnKey, ReassembledHello)>,
+    /// Bypass requests without a hostname while a domain filter is active
     pub allow_no_sni: bool,
-    
-    // Legacy compatibility
-    /// Whether blacklist filtering is enabled (legacy)
+    /// Look for HTTP on every captured port, not just 80
+    http_all_ports: bool,
+    /// Extra ports treated as both HTTP and HTTPS
+    additional_ports: Vec<u16>,
+    /// Whether the domain filter is active (its mode isn't Disabled)
     pub blacklist_enabled: bool,
-    /// Blacklisted domains (legacy)
-    blacklist: Arc<DashSet<String>>,
+    /// Packets produced outside [`Pipeline::process`](crate::Pipeline::process),
+    /// waiting for the capture loop to send them (at most
+    /// [`MAX_QUEUED_INJECTIONS`])
+    injections: Arc<Mutex<Vec<Packet>>>,
 }
 
 impl Context {
     /// Create a new context
     pub fn new() -> Self {
         Self {
-            stats: Stats::default(),
+            stats: Arc::new(StatsCounters::default()),
             domain_filter: Arc::new(DomainFilter::new()),
             tcp_tracker: Arc::new(TcpConnTracker::new()),
             dns_tracker: Arc::new(DnsConnTracker::new()),
+            hello_reassembler: Arc::new(HelloReassembler::new()),
+            continued_hello: None,
             allow_no_sni: false,
+            http_all_ports: false,
+            additional_ports: Vec::new(),
             blacklist_enabled: false,
-            blacklist: Arc::new(DashSet::new()),
+            injections: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
     /// Create context with domain filter
     pub fn with_filter(filter: DomainFilter) -> Self {
-        let filter_enabled = filter.mode() != FilterMode::Disabled;
-        Self {
-            stats: Stats::default(),
-            domain_filter: Arc::new(filter),
-            tcp_tracker: Arc::new(TcpConnTracker::new()),
-            dns_tracker: Arc::new(DnsConnTracker::new()),
-            allow_no_sni: false,
-            blacklist_enabled: filter_enabled,
-            blacklist: Arc::new(DashSet::new()),
-        }
+        Self::new().with_domain_filter(filter)
     }
 
-    /// Create context with blacklist (legacy)
+    /// Create context with a blacklist of domains
     pub fn with_blacklist(domains: Vec<String>) -> Self {
-        let blacklist = Arc::new(DashSet::new());
-        for domain in &domains {
-            blacklist.insert(domain.to_lowercase());
-        }
-        
-        // Also create new filter
-        let filter = DomainFilter::with_domains(FilterMode::Blacklist, domains);
-        
+        Self::with_filter(DomainFilter::with_domains(FilterMode::Blacklist, domains))
+    }
+
+    /// Create a context with the runtime settings from `config`
+    ///
+    /// Snapshots the port settings, connection tracking limits and
+    /// `blacklist.allow_no_sni`; the payload size limit is applied by the
+    /// [`Pipeline`](crate::Pipeline). The domain filter starts empty; use
+    /// [`from_config`](Self::from_config) to load it too.
+    pub fn with_config(config: &Config) -> Self {
+        let mut ctx = Self::new()
+            .with_tcp_tracker(TcpConnTracker::from_config(&config.performance))
+            .with_dns_tracker(DnsConnTracker::from_config(&config.performance))
+            .with_ports(&config.performance);
+        ctx.allow_no_sni = config.blacklist.allow_no_sni;
+        ctx
+    }
+
+    /// Create a context from `config`, loading its domain filter
+    ///
+    /// Like [`with_config`](Self::with_config), with the filter built from
+    /// the `[blacklist]` section and its domain files.
+    ///
+    /// # Errors
+    /// Returns error if a configured domain file can't be read.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let filter = DomainFilter::from_blacklist_config(&config.blacklist)?;
+        Ok(Self::with_config(config).with_domain_filter(filter))
+    }
+
+    /// Replace the domain filter, enabling filtering unless it is disabled
+    pub fn with_domain_filter(mut self, filter: DomainFilter) -> Self {
+        self.blacklist_enabled = filter.mode() != FilterMode::Disabled;
+        self.domain_filter = Arc::new(filter);
+        self
+    }
+
+    /// Replace the TCP connection tracker (e.g. one bounded by config)
+    pub fn with_tcp_tracker(mut self, tracker: TcpConnTracker) -> Self {
+        self.tcp_tracker = Arc::new(tracker);
+        self
+    }
+
+    /// Replace the DNS connection tracker (e.g. one bounded by config)
+    pub fn with_dns_tracker(mut self, tracker: DnsConnTracker) -> Self {
+        self.dns_tracker = Arc::new(tracker);
+        self
+    }
+
+    /// Replace the ClientHello reassembler (e.g. one with other limits)
+    pub fn with_hello_reassembler(mut self, reassembler: HelloReassembler) -> Self {
+        self.hello_reassembler = Arc::new(reassembler);
+        self
+    }
+
+    /// Apply the port settings from the performance config
+    pub fn with_ports(mut self, config: &PerformanceConfig) -> Self {
+        self.http_all_ports = config.http_all_ports;
+        self.additional_ports = config.additional_ports.clone();
+        self
+    }
+
+    /// Copy that shares no mutable state with this context
+    ///
+    /// Connection, DNS and ClientHello state is copied as it is now, while
+    /// the stats and the injection queue start empty, so nothing done
+    /// through the copy shows up here. Only the domain filter is shared.
+    pub fn detached(&self) -> Self {
         Self {
-            stats: Stats::default(),
-            domain_filter: Arc::new(filter),
-            blacklist_enabled: true,
-            blacklist,
-            tcp_tracker: Arc::new(TcpConnTracker::new()),
-            dns_tracker: Arc::new(DnsConnTracker::new()),
-            allow_no_sni: false,
+            stats: Arc::new(StatsCounters::default()),
+            domain_filter: Arc::clone(&self.domain_filter),
+            tcp_tracker: Arc::new(TcpConnTracker::clone(&self.tcp_tracker)),
+            dns_tracker: Arc::new(DnsConnTracker::clone(&self.dns_tracker)),
+            hello_reassembler: Arc::new(HelloReassembler::clone(&self.hello_reassembler)),
+            continued_hello: None,
+            allow_no_sni: self.allow_no_sni,
+            http_all_ports: self.http_all_ports,
+            additional_ports: self.additional_ports.clone(),
+            blacklist_enabled: self.blacklist_enabled,
+            injections: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Queue a packet for the capture loop to send on its own
+    ///
+    /// For replies that arrive after the packet they answer was dropped,
+    /// such as DNS-over-HTTPS answers.
+    ///
+    /// Returns `false`, dropping the packet, if the queue is full.
+    pub fn queue_injection(&self, packet: Packet) -> bool {
+        let mut injections = self.injections.lock();
+        if injections.len() >= MAX_QUEUED_INJECTIONS {
+            return false;
         }
+        injections.push(packet);
+        true
+    }
+
+    /// Take the packets queued with [`queue_injection`](Self::queue_injection)
+    pub fn take_injections(&self) -> Vec<Packet> {
+        std::mem::take(&mut *self.injections.lock())
+    }
+
+    /// Check if HTTP requests to this port should be processed
+    pub fn is_http_port(&self, port: u16) -> bool {
+        port == ports::HTTP || self.http_all_ports || self.additional_ports.contains(&port)
+    }
+
+    /// Check if TLS ClientHellos to this port should be processed
+    pub fn is_https_port(&self, port: u16) -> bool {
+        port == ports::HTTPS || self.additional_ports.contains(&port)
     }
 
     /// Get domain filter reference
@@ -117,6 +444,22 @@ impl Context {
         }
     }
 
+    /// Check if bypass should be applied to a packet
+    ///
+    /// A destination address inside a configured IP range decides on its
+    /// own, regardless of SNI. Otherwise the hostname is checked; packets
+    /// without a hostname get bypass applied only with `allow_no_sni`.
+    pub fn should_apply_bypass_to(&self, packet: &Packet, hostname: Option<&str>) -> bool {
+        if self.domain_filter.lists_ip(packet.dst_addr) {
+            return self.domain_filter.check_ip(packet.dst_addr) == FilterResult::ApplyBypass;
+        }
+
+        match hostname {
+            Some(hostname) => self.should_apply_bypass(hostname),
+            None => self.allow_no_sni,
+        }
+    }
+
     /// Check if a hostname is blacklisted (legacy - use should_apply_bypass instead)
     ///
     /// Also checks parent domains (e.g., "sub.example.com" matches "example.com")
@@ -125,15 +468,14 @@ impl Context {
         self.should_apply_bypass(hostname)
     }
 
-    /// Add a domain to the blacklist
+    /// Add a domain to the filter
     pub fn add_to_blacklist(&self, domain: &str) {
-        self.blacklist.insert(domain.to_lowercase());
         self.domain_filter.add_domain(domain);
     }
 
-    /// Load blacklist from a file
+    /// Load filter entries from a file, keeping the current ones
     pub fn load_blacklist_file(&self, path: &str) -> std::io::Result<usize> {
-        self.domain_filter.load_file(path)
+        self.domain_filter.add_file(path)
     }
 
     /// Check and reload filter file if changed
@@ -143,25 +485,117 @@ impl Context {
 
     /// Get the TTL for a connection (from SYN-ACK tracking)
     pub fn get_connection_ttl(&self, packet: &Packet) -> Option<u8> {
-        self.tcp_tracker.get_ttl(
-            packet.dst_addr,
-            packet.dst_port,
-            packet.src_addr,
-            packet.src_port,
-        )
+        self.tcp_tracker.get_ttl_for(&ConnKey::from_packet(packet))
+    }
+
+    /// Number of tracked TCP connections
+    pub fn connection_count(&self) -> usize {
+        self.tcp_tracker.len()
     }
 
     /// Record a TCP connection's TTL (called on SYN-ACK)
     pub fn record_connection_ttl(&self, packet: &Packet) {
         if packet.is_syn_ack() {
-            self.tcp_tracker.record(
-                packet.src_addr,
-                packet.src_port,
-                packet.dst_addr,
-                packet.dst_port,
-                packet.ttl,
-            );
+            self.tcp_tracker.record_syn_ack(&ConnKey::from_packet(packet), packet.ttl);
+        }
+    }
+
+    /// Forget a TCP connection's TTL (called on FIN/RST)
+    pub fn forget_connection(&self, packet: &Packet) {
+        self.tcp_tracker.forget(&ConnKey::from_packet(packet));
+    }
+
+    /// Advance the state of the TCP connection `packet` belongs to
+    ///
+    /// Records the SYN-ACK TTL and forgets the connection on FIN/RST; see
+    /// [`TcpConnTracker::observe`].
+    pub fn track_connection(&self, packet: &Packet) -> Option<FlowState> {
+        let state = self.tcp_tracker.observe(packet);
+        if state == Some(FlowState::Closed) {
+            self.hello_reassembler.forget(&ConnKey::from_packet(packet));
         }
+        state
+    }
+
+    /// Feed an outbound segment to the ClientHello reassembler
+    ///
+    /// Returns whether `packet` completes the hostname of a ClientHello
+    /// started in an earlier segment; until the next call,
+    /// [`continued_client_hello`](Self::continued_client_hello) then
+    /// returns it for packets of the flow.
+    pub fn reassemble_client_hello(&mut self, packet: &Packet) -> bool {
+        self.continued_hello = None;
+        if !self.is_https_port(packet.dst_port) {
+            return false;
+        }
+        self.continued_hello = self
+            .hello_reassembler
+            .push(packet)
+            .map(|hello| (ConnKey::from_packet(packet), hello));
+        self.continued_hello.is_some()
+    }
+
+    /// ClientHello the current segment of `packet`'s flow completed
+    pub fn continued_client_hello(&self, packet: &Packet) -> Option<&ReassembledHello> {
+        self.continued_hello
+            .as_ref()
+            .filter(|(flow, _)| *flow == ConnKey::from_packet(packet))
+            .map(|(_, hello)| hello)
+    }
+
+    /// Whether `packet` starts a ClientHello or completes a split one
+    pub fn is_client_hello(&self, packet: &Packet) -> bool {
+        packet.is_tls_client_hello() || self.continued_client_hello(packet).is_some()
+    }
+
+    /// SNI of the ClientHello `packet` starts or completes
+    pub fn client_hello_sni(&self, packet: &Packet) -> Option<String> {
+        if packet.is_tls_client_hello() {
+            return packet.extract_sni();
+        }
+        self.continued_client_hello(packet)?.info.sni.clone()
+    }
+
+    /// Remember that the bypass strategies already handled `flow`
+    pub fn mark_bypassed(&self, flow: &ConnKey) {
+        self.tcp_tracker.mark_bypassed(flow);
+    }
+
+    /// Whether `flow` was already handled, e.g. for a retransmitted ClientHello
+    pub fn was_bypassed(&self, flow: &ConnKey) -> bool {
+        self.tcp_tracker.was_bypassed(flow)
+    }
+
+    /// Remember that a request on `flow` was fragmented
+    pub fn mark_fragmented(&self, flow: &ConnKey) {
+        self.tcp_tracker.mark_fragmented(flow);
+    }
+
+    /// Whether a request on `flow` was already fragmented
+    pub fn is_fragmented(&self, flow: &ConnKey) -> bool {
+        self.tcp_tracker.is_fragmented(flow)
+    }
+
+    /// Expire stale ClientHello reassembly entries as of `now`
+    ///
+    /// The TCP and DNS trackers are swept by the threads from
+    /// [`spawn_conntrack_cleanup`](Self::spawn_conntrack_cleanup).
+    pub fn sweep_conntrack(&self, now: Instant) -> usize {
+        self.hello_reassembler.sweep(now)
+    }
+
+    /// Sweep the TCP and DNS trackers every `conntrack_cleanup_interval`
+    /// seconds (at least one) from background threads, which exit once
+    /// every context is dropped
+    pub fn spawn_conntrack_cleanup(
+        &self,
+        config: &PerformanceConfig,
+    ) -> std::io::Result<Vec<std::thread::JoinHandle<()>>> {
+        let interval = Duration::from_secs(config.conntrack_cleanup_interval.max(1).into());
+        Ok(vec![
+            Arc::clone(&self.tcp_tracker).spawn_cleanup(interval, self.tcp_tracker.timeout())?,
+            Arc::clone(&self.dns_tracker).spawn_cleanup(interval)?,
+        ])
     }
 
     /// Track a DNS query for response mapping
@@ -169,19 +603,48 @@ impl Context {
         self.dns_tracker.track_query(src_port, original_dst, original_port);
     }
 
+    /// Track a DNS query with its transaction ID for response rewriting
+    pub fn dns_track_query_id(&self, src_port: u16, txid: u16, original_dst: IpAddr, original_port: u16) {
+        self.dns_tracker.track_query_id(src_port, txid, original_dst, original_port);
+    }
+
+    /// Match a DNS response to its query
+    pub fn dns_match_response(&self, dst_port: u16, txid: u16) -> Option<(IpAddr, u16)> {
+        self.dns_tracker.match_response(dst_port, txid)
+    }
+
+    /// Track a redirected DNS-over-TCP flow
+    pub fn dns_track_tcp_flow(&self, client_port: u16, original_dst: IpAddr, original_port: u16) {
+        self.dns_tracker.track_tcp_flow(client_port, original_dst, original_port);
+    }
+
+    /// Look up a DNS-over-TCP flow, advancing its FIN/RST teardown state
+    pub fn dns_tcp_flow(&self, client_port: u16, outbound: bool, flags: TcpFlags) -> Option<(IpAddr, u16)> {
+        self.dns_tracker.tcp_flow(client_port, outbound, flags)
+    }
+
     /// Look up original DNS destination for a response
     pub fn dns_get_original(&self, src_port: u16) -> Option<(IpAddr, u16)> {
         self.dns_tracker.get_original(src_port)
     }
 
     /// Get current statistics
+    ///
+    /// Reads the shared atomic counters without locking, so it can be
+    /// called from a reporting thread while packets are processed.
+    /// Conntrack sizes and evictions are read from the trackers.
     pub fn get_stats(&self) -> Stats {
-        self.stats.clone()
+        Stats {
+            tracked_connections: self.tcp_tracker.len() as u64,
+            tracked_dns: (self.dns_tracker.len() + self.dns_tracker.tcp_flow_count()) as u64,
+            conntrack_evictions: self.tcp_tracker.eviction_count() + self.dns_tracker.eviction_count(),
+            ..self.stats.snapshot()
+        }
     }
 
     /// Reset statistics
-    pub fn reset_stats(&mut self) {
-        self.stats = Stats::default();
+    pub fn reset_stats(&self) {
+        self.stats.reset();
     }
 }
 
@@ -234,19 +697,198 @@ mod tests {
         assert!(ctx.should_apply_bypass("youtube.com"));
     }
 
+    #[test]
+    fn test_filter_reload_reaches_every_clone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("domains.txt");
+        std::fs::write(&path, "discord.com\n").unwrap();
+
+        let ctx = Context::with_filter(DomainFilter::from_file(&path, FilterMode::Blacklist).unwrap());
+        let worker = ctx.clone();
+        assert!(worker.is_blacklisted("discord.com"));
+        assert!(!ctx.check_filter_reload().unwrap());
+
+        std::fs::write(&path, "*.youtube.com\n").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(5))
+            .unwrap();
+
+        assert!(ctx.check_filter_reload().unwrap());
+        assert!(worker.is_blacklisted("www.youtube.com"));
+        assert!(!worker.is_blacklisted("discord.com"));
+    }
+
+    #[test]
+    fn test_cidr_overrides_hostname() {
+        use crate::packet::{Direction, PacketBuilder};
+
+        let ctx = Context::with_blacklist(vec![
+            "blocked.com".to_string(),
+            "10.20.0.0/16".to_string(),
+        ]);
+        let packet_to = |ip: [u8; 4]| {
+            let data = PacketBuilder::tcp_v4().dst_ip_v4(ip).dst_port(443).build_bytes();
+            Packet::from_bytes(&data, Direction::Outbound).unwrap()
+        };
+
+        // In range: bypass regardless of SNI
+        assert!(ctx.should_apply_bypass_to(&packet_to([10, 20, 1, 1]), Some("other.com")));
+        assert!(ctx.should_apply_bypass_to(&packet_to([10, 20, 1, 1]), None));
+
+        // Out of range: fall back to hostname check
+        assert!(ctx.should_apply_bypass_to(&packet_to([10, 30, 1, 1]), Some("blocked.com")));
+        assert!(!ctx.should_apply_bypass_to(&packet_to([10, 30, 1, 1]), Some("other.com")));
+        assert!(!ctx.should_apply_bypass_to(&packet_to([10, 30, 1, 1]), None));
+
+        // No hostname: only with allow_no_sni
+        let ctx = Context { allow_no_sni: true, ..ctx };
+        assert!(ctx.should_apply_bypass_to(&packet_to([10, 30, 1, 1]), None));
+    }
+
+    #[test]
+    fn test_from_config() {
+        use crate::config::Profile;
+
+        let mut config = Profile::Turkey.into_config();
+        config.blacklist.enabled = true;
+        config.blacklist.mode = "blacklist".to_string();
+        config.blacklist.domains = vec!["discord.com".to_string()];
+        config.blacklist.allow_no_sni = true;
+        config.performance.conntrack_max_entries = 1;
+
+        let ctx = Context::from_config(&config).unwrap();
+        assert!(ctx.blacklist_enabled);
+        assert_eq!(ctx.filter().mode(), FilterMode::Blacklist);
+        assert!(ctx.should_apply_bypass("discord.com"));
+        assert!(!ctx.should_apply_bypass("example.com"));
+        assert!(ctx.allow_no_sni);
+
+        // The DNS tracker is bounded by the config
+        let upstream: IpAddr = "8.8.8.8".parse().unwrap();
+        ctx.dns_track_query(50000, upstream, 53);
+        ctx.dns_track_query(50001, upstream, 53);
+        assert_eq!(ctx.dns_get_original(50000), None);
+        assert_eq!(ctx.dns_get_original(50001), Some((upstream, 53)));
+
+        // A directory exists but can't be read as a domain list
+        let dir = tempfile::tempdir().unwrap();
+        config.blacklist.file_path = Some(dir.path().to_string_lossy().into_owned());
+        assert!(Context::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_with_config() {
+        let mut config = Config::default();
+        config.blacklist.allow_no_sni = true;
+        config.performance.additional_ports = vec![8443];
+
+        let ctx = Context::with_config(&config);
+        assert!(ctx.allow_no_sni);
+        assert!(ctx.is_https_port(8443));
+        assert!(!ctx.blacklist_enabled);
+
+        let ctx = ctx.with_domain_filter(DomainFilter::with_domains(
+            FilterMode::Blacklist,
+            vec!["blocked.com".to_string()],
+        ));
+        assert!(ctx.blacklist_enabled);
+        assert!(!ctx.should_apply_bypass("other.com"));
+    }
+
+    #[test]
+    fn test_connection_ttl_from_syn_ack() {
+        use crate::packet::{Direction, PacketBuilder, TcpFlags};
+
+        let ctx = Context::new();
+        let packet = |src: [u8; 4], dst: [u8; 4], sport, dport, flags, direction| {
+            let data = PacketBuilder::tcp_v4()
+                .src_ip_v4(src)
+                .dst_ip_v4(dst)
+                .src_port(sport)
+                .dst_port(dport)
+                .ttl(54)
+                .flags(flags)
+                .build_bytes();
+            Packet::from_bytes(&data, direction).unwrap()
+        };
+        let syn_ack = TcpFlags { syn: true, ack: true, ..Default::default() };
+        let ack = TcpFlags { ack: true, ..Default::default() };
+
+        // Plain ACKs are not recorded
+        ctx.record_connection_ttl(&packet([1, 1, 1, 1], [10, 0, 0, 1], 443, 40000, ack, Direction::Inbound));
+        ctx.record_connection_ttl(&packet([1, 1, 1, 1], [10, 0, 0, 1], 443, 50000, syn_ack, Direction::Inbound));
+
+        let outbound = |sport| packet([10, 0, 0, 1], [1, 1, 1, 1], sport, 443, ack, Direction::Outbound);
+        assert_eq!(ctx.get_connection_ttl(&outbound(50000)), Some(54));
+        assert_eq!(ctx.get_connection_ttl(&outbound(40000)), None);
+        assert_eq!(ctx.get_connection_ttl(&outbound(50001)), None);
+    }
+
     #[test]
     fn test_stats() {
-        let mut ctx = Context::new();
+        let ctx = Context::new();
         
-        ctx.stats.packets_processed = 100;
-        ctx.stats.packets_fragmented = 50;
+        ctx.stats.packets_processed.store(100, Ordering::Relaxed);
+        ctx.stats.packets_fragmented.store(50, Ordering::Relaxed);
         
         let stats = ctx.get_stats();
         assert_eq!(stats.packets_processed, 100);
         assert_eq!(stats.packets_fragmented, 50);
 
+        // Clones share the counters
+        ctx.clone().stats.packets_processed.fetch_add(1, Ordering::Relaxed);
+        assert_eq!(ctx.get_stats().packets_processed, 101);
+
         ctx.reset_stats();
-        assert_eq!(ctx.stats.packets_processed, 0);
+        assert_eq!(ctx.get_stats().packets_processed, 0);
+    }
+
+    #[test]
+    fn test_injections_shared_by_clones() {
+        use crate::packet::{Direction, PacketBuilder};
+
+        let ctx = Context::new();
+        let packet = Packet::from_bytes(&PacketBuilder::tcp_v4().build_bytes(), Direction::Inbound).unwrap();
+        assert!(ctx.clone().queue_injection(packet.clone()));
+
+        assert_eq!(ctx.take_injections().len(), 1);
+        assert!(ctx.take_injections().is_empty());
+
+        for _ in 0..MAX_QUEUED_INJECTIONS {
+            assert!(ctx.queue_injection(packet.clone()));
+        }
+        assert!(!ctx.queue_injection(packet));
+        assert_eq!(ctx.take_injections().len(), MAX_QUEUED_INJECTIONS);
+    }
+
+    #[test]
+    fn test_conntrack_stats() {
+        use crate::packet::{Direction, PacketBuilder};
+
+        let ctx = Context::new()
+            .with_tcp_tracker(TcpConnTracker::new().with_max_entries(2))
+            .with_dns_tracker(DnsConnTracker::new().with_max_entries(2));
+        for port in 50000..50003 {
+            let data = PacketBuilder::tcp_v4()
+                .src_ip_v4([1, 1, 1, 1])
+                .dst_ip_v4([10, 0, 0, 1])
+                .src_port(443)
+                .dst_port(port)
+                .flags(TcpFlags { syn: true, ack: true, ..Default::default() })
+                .build_bytes();
+            ctx.record_connection_ttl(&Packet::from_bytes(&data, Direction::Inbound).unwrap());
+        }
+        for port in 1000..1004 {
+            ctx.dns_track_query(port, IpAddr::from([8, 8, 8, 8]), 53);
+        }
+
+        let stats = ctx.get_stats();
+        assert_eq!(stats.tracked_connections, 2);
+        assert_eq!(stats.tracked_dns, 2);
+        assert_eq!(stats.conntrack_evictions, 3);
     }
 }
 
@@ -0,0 +1,293 @@
+//! Prometheus metrics endpoint
+//!
+//! Pipeline counters exported in the Prometheus text format over HTTP,
+//! for scraping into dashboards. Only built with the `metrics` feature.
+
+use super::{Context, Stats};
+use crate::error::{Error, Result};
+use crate::packet::Direction;
+use std::fmt::Write;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// Default port for the metrics endpoint
+pub const DEFAULT_METRICS_PORT: u16 = 9090;
+
+/// Upper bounds of the latency histogram buckets, in microseconds
+const LATENCY_BUCKETS: [u64; 10] = [5, 10, 25, 50, 100, 250, 500, 1_000, 5_000, 10_000];
+
+/// Metrics recorded by a pipeline
+///
+/// Per-packet values are counted as packets go through
+/// [`Pipeline::process`](super::Pipeline::process); strategy counters and
+/// table sizes are read from the processing context when scraped.
+#[derive(Default)]
+pub struct Metrics {
+    packets_inbound: AtomicU64,
+    packets_outbound: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+    latency: Histogram,
+    /// Context of the first processed packet (clones share state)
+    ctx: OnceLock<Context>,
+}
+
+impl Metrics {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember the context whose counters are exported
+    pub(super) fn bind(&self, ctx: &Context) {
+        self.ctx.get_or_init(|| ctx.clone());
+    }
+
+    /// Count one processed packet
+    pub(super) fn record(&self, direction: Direction, bytes: usize, elapsed: Duration, failed: bool) {
+        match direction {
+            Direction::Inbound => &self.packets_inbound,
+            Direction::Outbound => &self.packets_outbound,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        if failed {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.latency.observe(elapsed.as_micros() as u64);
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        // The same snapshot the `stats` command shows
+        let (stats, domains) = match self.ctx.get() {
+            Some(ctx) => (ctx.get_stats(), ctx.filter().len() as u64),
+            None => (Stats::default(), 0),
+        };
+
+        header(&mut out, "gdpi_packets_total", "counter", "Packets processed by the pipeline");
+        for (direction, counter) in [("inbound", &self.packets_inbound), ("outbound", &self.packets_outbound)] {
+            let _ = writeln!(
+                out,
+                "gdpi_packets_total{{direction=\"{}\"}} {}",
+                direction,
+                counter.load(Ordering::Relaxed)
+            );
+        }
+
+        let counters = [
+            ("gdpi_packets_processed_total", "Packets run through the strategies", stats.packets_processed),
+            ("gdpi_fake_packets_sent_total", "Fake packets injected", stats.fake_packets_sent),
+            ("gdpi_packets_fragmented_total", "Packets split into fragments", stats.packets_fragmented),
+            // Earlier names of the two above, kept for existing dashboards
+            ("gdpi_fake_packets_total", "Fake packets injected", stats.fake_packets_sent),
+            ("gdpi_fragments_total", "Packets split into fragments", stats.packets_fragmented),
+            ("gdpi_conntrack_evictions_total", "Conntrack entries evicted from full tables", stats.conntrack_evictions),
+            ("gdpi_bytes_total", "Bytes processed by the pipeline", self.bytes.load(Ordering::Relaxed)),
+            ("gdpi_errors_total", "Packets the pipeline failed on", self.errors.load(Ordering::Relaxed)),
+        ];
+        for (name, help, value) in counters {
+            header(&mut out, name, "counter", help);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+
+        header(&mut out, "gdpi_strategy_applied_total", "counter", "Packets each strategy was applied to");
+        let mut strategies: Vec<_> = stats.per_strategy.iter().collect();
+        strategies.sort_unstable_by_key(|(name, _)| **name);
+        for (name, counts) in strategies {
+            let _ = writeln!(out, "gdpi_strategy_applied_total{{strategy=\"{}\"}} {}", name, counts.applied);
+        }
+
+        let gauges = [
+            ("gdpi_conntrack_entries", "Tracked TCP connections", stats.tracked_connections),
+            ("gdpi_filter_domains", "Domains in the domain filter", domains),
+        ];
+        for (name, help, value) in gauges {
+            header(&mut out, name, "gauge", help);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+
+        header(
+            &mut out,
+            "gdpi_pipeline_latency_microseconds",
+            "histogram",
+            "Time spent processing a packet",
+        );
+        self.latency.render(&mut out, "gdpi_pipeline_latency_microseconds");
+
+        out
+    }
+
+    /// Serve `/metrics` on `port` (loopback only) from a background thread
+    pub fn serve(self: &Arc<Self>, port: u16) -> Result<JoinHandle<()>> {
+        self.serve_on(SocketAddr::from((Ipv4Addr::LOCALHOST, port)))
+    }
+
+    /// Serve `/metrics` on `addr` from a background thread
+    pub fn serve_on(self: &Arc<Self>, addr: SocketAddr) -> Result<JoinHandle<()>> {
+        let server = tiny_http::Server::http(addr)
+            .map_err(|e| Error::Io(std::io::Error::other(e)))?;
+        info!(%addr, "Serving metrics on /metrics");
+
+        let metrics = Arc::clone(self);
+        let handle = thread::Builder::new()
+            .name("gdpi-metrics".into())
+            .spawn(move || {
+                for request in server.incoming_requests() {
+                    let response = if request.url() == "/metrics" {
+                        tiny_http::Response::from_string(metrics.render()).with_header(
+                            tiny_http::Header::from_bytes(
+                                &b"Content-Type"[..],
+                                &b"text/plain; version=0.0.4"[..],
+                            )
+                            .expect("static header is valid"),
+                        )
+                    } else {
+                        tiny_http::Response::from_string("Not found").with_status_code(404)
+                    };
+                    if let Err(e) = request.respond(response) {
+                        debug!(error = %e, "Failed to answer metrics request");
+                    }
+                }
+            })?;
+
+        Ok(handle)
+    }
+}
+
+/// Cumulative latency histogram
+#[derive(Default)]
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, value: u64) {
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&bound| value <= bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str) {
+        let mut cumulative = 0;
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum.load(Ordering::Relaxed));
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{Packet, PacketBuilder};
+    use crate::pipeline::Pipeline;
+    use std::io::{Read, Write as _};
+    use std::net::TcpStream;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        let ctx = Context::new();
+        ctx.stats.fake_packets_sent.fetch_add(3, Ordering::Relaxed);
+        metrics.bind(&ctx);
+
+        metrics.record(Direction::Outbound, 100, Duration::from_micros(7), false);
+        metrics.record(Direction::Outbound, 50, Duration::from_micros(700), true);
+        metrics.record(Direction::Inbound, 60, Duration::from_millis(20), false);
+
+        let text = metrics.render();
+        assert!(text.contains("gdpi_packets_total{direction=\"outbound\"} 2\n"));
+        assert!(text.contains("gdpi_packets_total{direction=\"inbound\"} 1\n"));
+        assert!(text.contains("gdpi_fake_packets_sent_total 3\n"));
+        assert!(text.contains("gdpi_fake_packets_total 3\n"));
+        assert!(text.contains("gdpi_bytes_total 210\n"));
+        assert!(text.contains("gdpi_errors_total 1\n"));
+        assert!(text.contains("# TYPE gdpi_conntrack_entries gauge\n"));
+        assert!(text.contains("gdpi_pipeline_latency_microseconds_bucket{le=\"5\"} 0\n"));
+        assert!(text.contains("gdpi_pipeline_latency_microseconds_bucket{le=\"10\"} 1\n"));
+        assert!(text.contains("gdpi_pipeline_latency_microseconds_bucket{le=\"1000\"} 2\n"));
+        assert!(text.contains("gdpi_pipeline_latency_microseconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("gdpi_pipeline_latency_microseconds_count 3\n"));
+    }
+
+    /// A free local port
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    /// Full HTTP response to `GET /metrics`
+    fn scrape(port: u16) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_pipeline_serves_metrics() {
+        let port = free_port();
+        let pipeline = Pipeline::new_with_metrics(port).unwrap();
+        let data = PacketBuilder::tcp_v4().dst_port(443).build_bytes();
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+        pipeline.process(packet, &mut Context::new()).unwrap();
+
+        let response = scrape(port);
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("text/plain; version=0.0.4"));
+        assert!(response.contains("gdpi_packets_total{direction=\"outbound\"} 1"));
+        assert!(response.contains(&format!("gdpi_bytes_total {}", data.len())));
+    }
+
+    #[test]
+    fn test_scrape_shows_strategy_counters() {
+        use crate::packet::ClientHelloBuilder;
+        use crate::strategies::FragmentationStrategy;
+
+        let port = free_port();
+        let mut pipeline = Pipeline::new_with_metrics_on(([127, 0, 0, 1], port).into()).unwrap();
+        pipeline.add_strategy(FragmentationStrategy::new());
+        let mut ctx = Context::new();
+
+        let data = PacketBuilder::tcp_v4()
+            .dst_port(443)
+            .payload(&ClientHelloBuilder::new("example.com").build())
+            .build_bytes();
+        pipeline.process(Packet::from_bytes(&data, Direction::Outbound).unwrap(), &mut ctx).unwrap();
+        assert!(scrape(port).contains("gdpi_packets_fragmented_total 1\n"));
+
+        let data = PacketBuilder::tcp_v4().dst_port(80).build_bytes();
+        pipeline.process(Packet::from_bytes(&data, Direction::Outbound).unwrap(), &mut ctx).unwrap();
+
+        let response = scrape(port);
+        assert!(response.contains("gdpi_packets_processed_total 2\n"));
+        assert!(response.contains("gdpi_packets_fragmented_total 1\n"));
+        assert!(response.contains("gdpi_fragments_total 1\n"));
+        assert!(response.contains("gdpi_strategy_applied_total{strategy=\"fragmentation\"} 1\n"));
+    }
+}
@@ -3,56 +3,175 @@
 //! Chain of responsibility pattern for processing packets through strategies.
 
 mod context;
-
-pub use context::{Context, Stats};
-
+#[cfg(feature = "metrics")]
+mod metrics;
+mod trace;
+mod workers;
+
+pub use context::{Context, Stats, StatsCounters, StrategyStats};
+#[cfg(feature = "metrics")]
+pub use metrics::{Metrics, DEFAULT_METRICS_PORT};
+pub use trace::{StrategyTrace, TraceAction};
+pub use workers::WorkerPool;
+
+use crate::config::Config;
+use crate::conntrack::{ConnKey, FlowState};
 use crate::error::Result;
-use crate::packet::Packet;
-use crate::strategies::{Strategy, StrategyAction};
-use tracing::instrument;
+use crate::packet::{ports, Packet};
+use crate::strategies::{DomainStrategies, Strategy, StrategyAction, StrategyBuilder};
+use parking_lot::RwLock;
+use std::path::Path;
+use std::sync::atomic::{AtomicU16, Ordering};
+#[cfg(feature = "metrics")]
+use std::sync::Arc;
+use tracing::{info, instrument};
 
 /// Packet processing pipeline
 ///
 /// Processes packets through a chain of strategies, collecting and
-/// applying transformations.
+/// applying transformations. Strategies sit behind a lock so they can be
+/// swapped on a config reload while packets are being processed.
 pub struct Pipeline {
-    strategies: Vec<Box<dyn Strategy>>,
+    strategies: RwLock<Vec<Box<dyn Strategy>>>,
+    /// Strategy sets used instead for packets to matching hostnames
+    overrides: RwLock<Vec<DomainStrategies>>,
+    /// TCP packets with a larger payload skip the strategies (0 = no limit)
+    max_payload_size: AtomicU16,
+    /// Prometheus metrics, if exported
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<Metrics>>,
 }
 
 impl Pipeline {
     /// Create a new empty pipeline
     pub fn new() -> Self {
         Self {
-            strategies: Vec::new(),
+            strategies: RwLock::new(Vec::new()),
+            overrides: RwLock::new(Vec::new()),
+            max_payload_size: AtomicU16::new(0),
+            #[cfg(feature = "metrics")]
+            metrics: None,
         }
     }
 
+    /// Create an empty pipeline exporting Prometheus metrics on `port`
+    ///
+    /// Starts an HTTP server answering `/metrics` on the loopback interface
+    /// (see [`DEFAULT_METRICS_PORT`]); use [`Pipeline::new_with_metrics_on`]
+    /// to expose it further. Fails if the port can't be bound.
+    #[cfg(feature = "metrics")]
+    pub fn new_with_metrics(port: u16) -> Result<Self> {
+        Self::new_with_metrics_on(std::net::SocketAddr::from((std::net::Ipv4Addr::LOCALHOST, port)))
+    }
+
+    /// Create an empty pipeline exporting Prometheus metrics on `addr`
+    #[cfg(feature = "metrics")]
+    pub fn new_with_metrics_on(addr: std::net::SocketAddr) -> Result<Self> {
+        let metrics = Arc::new(Metrics::new());
+        metrics.serve_on(addr)?;
+
+        Ok(Self {
+            metrics: Some(metrics),
+            ..Self::new()
+        })
+    }
+
+    /// Metrics registry, if created with [`Pipeline::new_with_metrics`]
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> Option<&Arc<Metrics>> {
+        self.metrics.as_ref()
+    }
+
+    /// Skip strategies for TCP packets whose payload exceeds `size`
+    ///
+    /// Only the first data packets of a connection (ClientHello, HTTP
+    /// request) need bypassing, so bulk transfers are passed straight
+    /// through. DNS over TCP is never skipped. 0 disables the limit.
+    pub fn set_max_payload_size(&self, size: u16) {
+        self.max_payload_size.store(size, Ordering::Relaxed);
+    }
+
+    /// Current payload size limit (0 = no limit)
+    pub fn max_payload_size(&self) -> u16 {
+        self.max_payload_size.load(Ordering::Relaxed)
+    }
+
     /// Add a strategy to the pipeline
     pub fn add_strategy<S: Strategy + 'static>(&mut self, strategy: S) {
-        self.strategies.push(Box::new(strategy));
+        let strategies = self.strategies.get_mut();
+        strategies.push(Box::new(strategy));
         // Re-sort by priority
-        self.strategies.sort_by_key(|s| s.priority());
+        strategies.sort_by_key(|s| s.priority());
     }
 
     /// Add multiple strategies from a vector
     pub fn add_strategies(&mut self, strategies: Vec<Box<dyn Strategy>>) {
-        self.strategies.extend(strategies);
-        self.strategies.sort_by_key(|s| s.priority());
+        let current = self.strategies.get_mut();
+        current.extend(strategies);
+        current.sort_by_key(|s| s.priority());
+    }
+
+    /// Swap in a new set of strategies
+    ///
+    /// Packets already inside `process` finish with the old set; the next
+    /// packet sees the new one. Connection state lives in the [`Context`]
+    /// and is not affected.
+    pub fn replace_strategies(&self, mut strategies: Vec<Box<dyn Strategy>>) {
+        strategies.sort_by_key(|s| s.priority());
+
+        let names: Vec<_> = strategies.iter().map(|s| s.name()).collect();
+        *self.strategies.write() = strategies;
+
+        info!(strategies = ?names, "Replaced pipeline strategies");
+    }
+
+    /// Swap in the per-domain strategy sets
+    ///
+    /// A packet whose HTTP Host or TLS/QUIC SNI matches one of the filters
+    /// runs through that set instead of the main one; the first match wins.
+    pub fn set_overrides(&self, mut overrides: Vec<DomainStrategies>) {
+        for (_, strategies) in &mut overrides {
+            strategies.sort_by_key(|s| s.priority());
+        }
+        *self.overrides.write() = overrides;
+    }
+
+    /// Number of per-domain strategy sets
+    pub fn override_count(&self) -> usize {
+        self.overrides.read().len()
+    }
+
+    /// Replace all strategies with those built from `config`
+    pub fn reload_config(&self, config: &Config) {
+        self.replace_strategies(StrategyBuilder::from_config(config));
+        self.set_overrides(StrategyBuilder::overrides_from_config(config));
+        self.set_max_payload_size(config.performance.max_payload_size);
+    }
+
+    /// Load, validate and apply the configuration file at `path`
+    ///
+    /// `GDPI_*` environment overrides apply as at startup. A file that
+    /// fails to load or validate leaves the current strategies in place.
+    pub fn reload_from_file(&self, path: impl AsRef<Path>) -> Result<Config> {
+        let config = Config::load_auto(path)?.with_env_overrides();
+        config.validate()?;
+        self.reload_config(&config);
+        Ok(config)
     }
 
     /// Get number of strategies in pipeline
     pub fn len(&self) -> usize {
-        self.strategies.len()
+        self.strategies.read().len()
     }
 
     /// Check if pipeline is empty
     pub fn is_empty(&self) -> bool {
-        self.strategies.is_empty()
+        self.strategies.read().is_empty()
     }
 
     /// Get strategy names for logging
     pub fn strategy_names(&self) -> Vec<&'static str> {
-        self.strategies.iter().map(|s| s.name()).collect()
+        self.strategies.read().iter().map(|s| s.name()).collect()
     }
 
     /// Process a packet through the pipeline
@@ -65,9 +184,74 @@ impl Pipeline {
         dst_port = packet.dst_port
     ))]
     pub fn process(&self, packet: Packet, ctx: &mut Context) -> Result<Vec<Packet>> {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.bind(ctx);
+            let (direction, len) = (packet.direction, packet.len());
+            let start = std::time::Instant::now();
+            let result = self.run(packet, ctx, None);
+            metrics.record(direction, len, start.elapsed(), result.is_err());
+            return result;
+        }
+
+        self.run(packet, ctx, None)
+    }
+
+    /// Process a packet and report what each strategy did
+    ///
+    /// Runs the same logic as [`Pipeline::process`] but returns a trace of
+    /// the strategies that fired instead of the packets to send. It runs
+    /// against a [`detached`](Context::detached) copy of `ctx`, so stats,
+    /// connection state and queued injections are left as they were.
+    pub fn process_dry(&self, packet: Packet, ctx: &Context) -> Result<Vec<StrategyTrace>> {
+        let mut trace = Vec::new();
+        self.run(packet, &mut ctx.detached(), Some(&mut trace))?;
+        Ok(trace)
+    }
+
+    fn run(
+        &self,
+        packet: Packet,
+        ctx: &mut Context,
+        mut trace: Option<&mut Vec<StrategyTrace>>,
+    ) -> Result<Vec<Packet>> {
+        // Later fragments have no header to match, and rewriting the first
+        // would leave the rest of the datagram behind
+        if packet.is_fragment() {
+            ctx.stats.record_bytes(packet.len(), false);
+            return Ok(vec![packet]);
+        }
+
+        // SYN-ACKs carry the server's TTL for auto-TTL; FIN/RST end the flow.
+        // An inbound RST may be forged and dropped by a strategy, so it only
+        // ends the flow once it made it through.
+        let reset = (packet.is_inbound() && packet.is_rst()).then(|| packet.clone());
+        let state = if reset.is_none() { ctx.track_connection(&packet) } else { None };
+        // Large ClientHellos may only show their SNI in a later segment
+        let continued = ctx.reassemble_client_hello(&packet);
+        if self.exceeds_max_payload(&packet) {
+            if let Some(reset) = &reset {
+                ctx.track_connection(reset);
+            }
+            ctx.stats.record_bytes(packet.len(), false);
+            return Ok(vec![packet]);
+        }
+
+        // A ClientHello is only bypassed once per connection
+        let flow = (state == Some(FlowState::ClientHelloSeen) || continued)
+            .then(|| ConnKey::from_packet(&packet));
+        let mut applied = false;
+
+        // Strategies may also edit a packet in place and pass it on, so the
+        // packet is copied for comparison once the first one acts on it
+        let original_len = packet.len();
+        let mut original: Option<Vec<u8>> = None;
+        let overrides = self.overrides.read();
+        let main = self.strategies.read();
+        let strategies = select_override(&overrides, &packet).unwrap_or(&main);
         let mut packets = vec![packet];
-        
-        for strategy in &self.strategies {
+
+        for strategy in strategies {
             if !strategy.is_enabled() {
                 continue;
             }
@@ -76,7 +260,17 @@ impl Pipeline {
 
             for pkt in packets {
                 if strategy.should_apply(&pkt, ctx) {
-                    match strategy.apply(pkt, ctx)? {
+                    applied = true;
+                    if original.is_none() {
+                        original = Some(pkt.as_bytes().to_vec());
+                    }
+                    let action = strategy.apply(pkt, ctx)?;
+                    ctx.stats.record_strategy(strategy.name(), &action);
+                    if let Some(trace) = trace.as_deref_mut() {
+                        trace.push(StrategyTrace::new(strategy.name(), &action));
+                    }
+
+                    match action {
                         StrategyAction::Pass(p) => {
                             new_packets.push(p);
                         }
@@ -108,12 +302,71 @@ impl Pipeline {
             }
         }
 
-        ctx.stats.packets_processed += 1;
+        if let (true, Some(flow)) = (applied, &flow) {
+            ctx.mark_bypassed(flow);
+        }
+        if let (Some(reset), false) = (&reset, packets.is_empty()) {
+            ctx.track_connection(reset);
+        }
+        let packets = decoys_first(packets);
+
+        ctx.stats.packets_processed.fetch_add(1, Ordering::Relaxed);
+        let modified = original.is_some_and(|original| {
+            !matches!(packets.as_slice(), [packet] if packet.as_bytes() == original.as_slice())
+        });
+        ctx.stats.record_bytes(original_len, modified);
 
         Ok(packets)
     }
 }
 
+/// Move decoy packets ahead of the real ones, keeping the order within each
+///
+/// A decoy injected by a later strategy, or next to a packet an earlier
+/// strategy split up, must still reach the DPI before the real payload.
+fn decoys_first(packets: Vec<Packet>) -> Vec<Packet> {
+    if !packets.iter().any(|p| p.is_fake) {
+        return packets;
+    }
+    let (mut decoys, real): (Vec<_>, Vec<_>) = packets.into_iter().partition(|p| p.is_fake);
+    decoys.extend(real);
+    decoys
+}
+
+impl Pipeline {
+    fn exceeds_max_payload(&self, packet: &Packet) -> bool {
+        let max = self.max_payload_size();
+        max != 0
+            && packet.is_tcp()
+            && packet.src_port != ports::DNS
+            && packet.dst_port != ports::DNS
+            && packet.payload_len() > usize::from(max)
+    }
+}
+
+/// Strategies of the first override whose filter matches the packet's hostname
+fn select_override<'a>(
+    overrides: &'a [DomainStrategies],
+    packet: &Packet,
+) -> Option<&'a [Box<dyn Strategy>]> {
+    if overrides.is_empty() {
+        return None;
+    }
+
+    let hostname = if packet.is_http_request() {
+        packet.extract_http_host()
+    } else if packet.is_tls_client_hello() {
+        packet.extract_sni()
+    } else {
+        packet.extract_quic_sni()
+    }?;
+
+    overrides
+        .iter()
+        .find(|(filter, _)| filter.matches(&hostname))
+        .map(|(_, strategies)| strategies.as_slice())
+}
+
 impl Default for Pipeline {
     fn default() -> Self {
         Self::new()
@@ -158,6 +411,25 @@ mod tests {
         }
     }
 
+    /// Sends a decoy after every real packet
+    struct MockDecoyAfterStrategy;
+
+    impl Strategy for MockDecoyAfterStrategy {
+        fn name(&self) -> &'static str {
+            "mock_decoy_after"
+        }
+
+        fn should_apply(&self, packet: &Packet, _ctx: &Context) -> bool {
+            !packet.is_fake
+        }
+
+        fn apply(&self, packet: Packet, _ctx: &mut Context) -> Result<StrategyAction> {
+            let mut decoy = packet.clone();
+            decoy.is_fake = true;
+            Ok(StrategyAction::InjectAfter(packet, vec![decoy]))
+        }
+    }
+
     fn create_test_packet(dst_port: u16) -> Packet {
         let data = vec![
             // IPv4 header
@@ -222,4 +494,206 @@ mod tests {
         // Order should be preserved for same priority
         assert_eq!(pipeline.len(), 2);
     }
+
+    #[test]
+    fn test_decoys_sent_before_real_packets() {
+        use crate::packet::{ClientHelloBuilder, PacketBuilder};
+        use crate::strategies::FragmentationStrategy;
+
+        let data = PacketBuilder::tcp_v4()
+            .dst_port(443)
+            .seq(1000)
+            .payload(&ClientHelloBuilder::new("example.com").build())
+            .build_bytes();
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+
+        // The decoys come from a strategy running after fragmentation
+        let mut pipeline = Pipeline::new();
+        pipeline.add_strategy(FragmentationStrategy::new());
+        pipeline.add_strategy(MockDecoyAfterStrategy);
+        let output = pipeline.process(packet, &mut Context::new()).unwrap();
+
+        let fakes: Vec<_> = output.iter().map(|p| p.is_fake).collect();
+        assert_eq!(fakes, vec![true, true, false, false]);
+        // Fragments keep their reverse order
+        assert!(output[2].tcp_seq().unwrap() > output[3].tcp_seq().unwrap());
+    }
+
+    #[test]
+    fn test_duplicate_first_fragment_after_fake() {
+        use crate::packet::{ClientHelloBuilder, PacketBuilder};
+
+        let mut config = Config::default();
+        config.dns.enabled = false;
+        config.strategies.header_mangle.enabled = false;
+        config.strategies.disorder.enabled = false;
+        config.strategies.quic_block.enabled = false;
+        config.strategies.passive_dpi.enabled = false;
+        config.strategies.fake_packet.ttl = Some(3);
+        config.strategies.fragmentation.reverse_order = false;
+        config.strategies.fragmentation.fragment_positions = vec![2];
+        config.strategies.fragmentation.duplicate_first_fragment = true;
+
+        let mut pipeline = Pipeline::new();
+        pipeline.add_strategies(StrategyBuilder::from_config(&config));
+        assert_eq!(pipeline.strategy_names(), ["fake_packet", "fragmentation"]);
+
+        let data = PacketBuilder::tcp_v4()
+            .dst_port(443)
+            .seq(1000)
+            .payload(&ClientHelloBuilder::new("example.com").build())
+            .build_bytes();
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+
+        // The same fakes fake_packet sends on its own
+        let mut fakes_only = Pipeline::new();
+        config.strategies.fragmentation.enabled = false;
+        fakes_only.add_strategies(StrategyBuilder::from_config(&config));
+        let fakes = fakes_only.process(packet.clone(), &mut Context::new()).unwrap();
+        assert_eq!(fakes.len(), 4);
+
+        let mut ctx = Context::new();
+        let output = pipeline.process(packet, &mut ctx).unwrap();
+        // Three fakes, then two fragments and the SNI fragment again
+        assert_eq!(output.len(), 6);
+        assert!(output[..3].iter().all(|p| p.is_fake));
+        assert!(output[3..].iter().all(|p| !p.is_fake));
+        for (sent, fake) in output[..3].iter().zip(&fakes[..3]) {
+            assert_eq!(sent.as_bytes(), fake.as_bytes());
+        }
+
+        let duplicates: Vec<_> = (0..output.len())
+            .flat_map(|i| (i + 1..output.len()).map(move |j| (i, j)))
+            .filter(|&(i, j)| output[i].as_bytes() == output[j].as_bytes())
+            .collect();
+        assert_eq!(duplicates, [(4, 5)]);
+        assert_eq!(output[4].tcp_seq(), Some(1002));
+        assert_eq!(output[4].tcp_seq(), output[5].tcp_seq());
+        assert_eq!(output[3].tcp_seq(), Some(1000));
+        assert_eq!(ctx.get_stats().fragments_duplicated, 1);
+    }
+
+    #[test]
+    fn test_ipv6_fragments_pass_untouched() {
+        let mut pipeline = Pipeline::new();
+        pipeline.add_strategy(MockDecoyAfterStrategy);
+
+        let mut data = vec![0u8; 48];
+        data[0] = 0x60;
+        data[4..6].copy_from_slice(&8u16.to_be_bytes());
+        data[6] = 44; // Fragment header
+        data[7] = 64;
+        data[40] = 6; // TCP
+        data[42..44].copy_from_slice(&(185u16 << 3).to_be_bytes());
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+
+        let mut ctx = Context::new();
+        let result = pipeline.process(packet, &mut ctx).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].as_bytes(), data.as_slice());
+    }
+
+    #[test]
+    fn test_per_strategy_stats() {
+        let mut pipeline = Pipeline::new();
+        pipeline.add_strategy(MockDropStrategy);
+        pipeline.add_strategy(MockPassStrategy);
+        let mut ctx = Context::new();
+
+        pipeline.process(create_test_packet(80), &mut ctx).unwrap();
+        let stats = ctx.get_stats();
+        assert!(!stats.per_strategy.contains_key("mock_drop"));
+        assert_eq!(stats.per_strategy["mock_pass"].passed, 1);
+
+        pipeline.process(create_test_packet(12345), &mut ctx).unwrap();
+        // Dropped packet never reaches the pass strategy, so both applied once
+        let report = ctx.get_stats().report();
+        assert_eq!(
+            report,
+            vec![
+                ("mock_drop", StrategyStats { applied: 1, dropped: 1, ..Default::default() }),
+                ("mock_pass", StrategyStats { applied: 1, passed: 1, ..Default::default() }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stats_hits_and_bytes() {
+        let mut pipeline = Pipeline::new();
+        pipeline.add_strategy(MockDropStrategy);
+        pipeline.add_strategy(MockPassStrategy);
+        let mut ctx = Context::new();
+
+        let passed = create_test_packet(80);
+        let dropped = create_test_packet(12345);
+        let (passed_len, dropped_len) = (passed.len() as u64, dropped.len() as u64);
+        pipeline.process(passed, &mut ctx).unwrap();
+        pipeline.process(dropped, &mut ctx).unwrap();
+
+        let mut stats = ctx.get_stats();
+        assert_eq!(stats.strategy_hits["mock_drop"], 1);
+        assert_eq!(stats.strategy_hits["mock_pass"], 1);
+        assert_eq!(stats.bytes_passed_through, passed_len);
+        assert_eq!(stats.bytes_modified, dropped_len);
+        assert_eq!(stats.modification_rate(), dropped_len as f64 / (passed_len + dropped_len) as f64);
+        assert!(stats.packets_per_second() > 0.0);
+
+        let start_time = stats.start_time;
+        stats.reset();
+        assert_eq!(stats.packets_processed, 0);
+        assert!(stats.strategy_hits.is_empty());
+        assert_eq!(stats.modification_rate(), 0.0);
+        assert_eq!(stats.start_time, start_time);
+
+        ctx.reset_stats();
+        assert_eq!(ctx.get_stats().start_time, start_time);
+    }
+
+    #[test]
+    fn test_reload_config() {
+        let mut pipeline = Pipeline::new();
+        pipeline.add_strategy(MockDropStrategy);
+
+        let config = Config::default();
+        pipeline.reload_config(&config);
+
+        let expected: Vec<_> = StrategyBuilder::from_config(&config)
+            .iter()
+            .map(|s| s.name())
+            .collect();
+        assert_eq!(pipeline.len(), expected.len());
+        assert!(!pipeline.strategy_names().contains(&"mock_drop"));
+
+        // Reloaded pipeline no longer drops the mock port
+        let mut ctx = Context::new();
+        let result = pipeline.process(create_test_packet(12345), &mut ctx).unwrap();
+        assert_eq!(result.len(), 1);
+    }
+
+    #[test]
+    fn test_reload_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let pipeline = Pipeline::new();
+
+        // A valid file swaps the strategies in
+        std::fs::write(&path, "[strategies.fragmentation]\nenabled = true\nhttps_size = 2\n").unwrap();
+        let config = pipeline.reload_from_file(&path).unwrap();
+        let names = pipeline.strategy_names();
+        assert!(!names.is_empty());
+        assert_eq!(names.len(), StrategyBuilder::from_config(&config).len());
+
+        // An invalid one is rejected and the previous strategies stay
+        std::fs::write(
+            &path,
+            "[strategies.fragmentation]\nenabled = true\nhttp_size = 0\nhttps_size = 0\n",
+        )
+        .unwrap();
+        assert!(pipeline.reload_from_file(&path).is_err());
+        assert_eq!(pipeline.strategy_names(), names);
+
+        std::fs::write(&path, "[strategies\n").unwrap();
+        assert!(pipeline.reload_from_file(&path).is_err());
+        assert_eq!(pipeline.strategy_names(), names);
+    }
 }
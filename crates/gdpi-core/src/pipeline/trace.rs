@@ -0,0 +1,71 @@
+//! Dry-run tracing
+//!
+//! Structured record of what each strategy did to a packet, for showing
+//! the effect of a configuration without sending anything.
+
+use crate::strategies::StrategyAction;
+use std::fmt;
+
+/// Kind of action a strategy took
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceAction {
+    /// Packet passed through unchanged
+    Pass,
+    /// Packet replaced (e.g. split into fragments)
+    Replace,
+    /// Packet dropped
+    Drop,
+    /// Packets injected before the original
+    InjectBefore(usize),
+    /// Packets injected after the original
+    InjectAfter(usize),
+}
+
+/// What one strategy did to one packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StrategyTrace {
+    /// Strategy name
+    pub strategy: &'static str,
+    /// Action taken
+    pub action: TraceAction,
+    /// Sizes of the resulting packets, in send order (empty if dropped)
+    pub sizes: Vec<usize>,
+}
+
+impl StrategyTrace {
+    pub(super) fn new(strategy: &'static str, action: &StrategyAction) -> Self {
+        let (action, sizes) = match action {
+            StrategyAction::Pass(p) => (TraceAction::Pass, vec![p.len()]),
+            StrategyAction::Replace(ps) => (TraceAction::Replace, ps.iter().map(|p| p.len()).collect()),
+            StrategyAction::Drop => (TraceAction::Drop, Vec::new()),
+            StrategyAction::InjectBefore(inject, original) => (
+                TraceAction::InjectBefore(inject.len()),
+                inject.iter().chain(Some(original)).map(|p| p.len()).collect(),
+            ),
+            StrategyAction::InjectAfter(original, inject) => (
+                TraceAction::InjectAfter(inject.len()),
+                Some(original).into_iter().chain(inject).map(|p| p.len()).collect(),
+            ),
+        };
+
+        Self { strategy, action, sizes }
+    }
+
+    /// Number of packets the strategy produced
+    pub fn packet_count(&self) -> usize {
+        self.sizes.len()
+    }
+}
+
+impl fmt::Display for StrategyTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.action {
+            TraceAction::Pass => write!(f, "{} would pass", self.strategy)?,
+            TraceAction::Replace => write!(f, "{} would split into {}", self.strategy, self.packet_count())?,
+            TraceAction::Drop => return write!(f, "{} would drop", self.strategy),
+            TraceAction::InjectBefore(n) => write!(f, "{} would inject {} before", self.strategy, n)?,
+            TraceAction::InjectAfter(n) => write!(f, "{} would inject {} after", self.strategy, n)?,
+        }
+        write!(f, " (sizes {:?})", self.sizes)
+    }
+}
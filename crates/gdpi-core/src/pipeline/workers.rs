@@ -0,0 +1,243 @@
+//! Multi-threaded packet processing
+//!
+//! Packets are sharded across workers by flow, so packets of one
+//! connection are always processed (and re-injected) in capture order
+//! while different connections run in parallel. With a single worker
+//! the pipeline runs inline on the dispatching thread instead.
+
+use super::{Context, Pipeline};
+use crate::error::Result;
+use crate::packet::Packet;
+use parking_lot::Mutex;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use tracing::debug;
+
+/// Packets queued per worker before `dispatch` blocks
+const QUEUE_DEPTH: usize = 1024;
+
+/// Pool of threads running packets through a shared pipeline
+///
+/// Each packet carries a caller-defined tag (e.g. the capture address
+/// needed to re-inject it) that is handed back with the result.
+pub struct WorkerPool<T> {
+    mode: Mode<T>,
+}
+
+type Output<T> = Arc<dyn Fn(T, Result<Vec<Packet>>) + Send + Sync>;
+
+enum Mode<T> {
+    /// Process on the caller's thread
+    Inline {
+        pipeline: Arc<Pipeline>,
+        ctx: Mutex<Context>,
+        output: Output<T>,
+    },
+    /// Shard across worker threads
+    Threads {
+        queues: Vec<SyncSender<(Packet, T)>>,
+        workers: Vec<JoinHandle<()>>,
+    },
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    /// Spawn `threads` workers (0 = one per CPU)
+    ///
+    /// `output` is called on the worker thread with each packet's tag and
+    /// pipeline result, in order within a flow. With `threads == 1` no
+    /// thread is spawned and `output` runs inside `dispatch`.
+    pub fn new<F>(threads: usize, pipeline: Arc<Pipeline>, ctx: Context, output: F) -> Result<Self>
+    where
+        F: Fn(T, Result<Vec<Packet>>) + Send + Sync + 'static,
+    {
+        let threads = if threads == 0 {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            threads
+        };
+        let output: Output<T> = Arc::new(output);
+
+        if threads == 1 {
+            debug!("Processing packets inline");
+            return Ok(Self {
+                mode: Mode::Inline {
+                    pipeline,
+                    ctx: Mutex::new(ctx),
+                    output,
+                },
+            });
+        }
+
+        let mut queues = Vec::with_capacity(threads);
+        let mut workers = Vec::with_capacity(threads);
+
+        for id in 0..threads {
+            let (tx, rx) = mpsc::sync_channel::<(Packet, T)>(QUEUE_DEPTH);
+            let pipeline = Arc::clone(&pipeline);
+            let output = Arc::clone(&output);
+            let mut ctx = ctx.clone();
+
+            let worker = thread::Builder::new()
+                .name(format!("gdpi-worker-{}", id))
+                .spawn(move || {
+                    for (packet, tag) in rx {
+                        output(tag, pipeline.process(packet, &mut ctx));
+                    }
+                    debug!(worker = id, "Packet worker stopped");
+                })?;
+
+            queues.push(tx);
+            workers.push(worker);
+        }
+
+        debug!(threads, "Started packet workers");
+        Ok(Self {
+            mode: Mode::Threads { queues, workers },
+        })
+    }
+
+    /// Number of worker threads
+    pub fn threads(&self) -> usize {
+        match &self.mode {
+            Mode::Inline { .. } => 1,
+            Mode::Threads { workers, .. } => workers.len(),
+        }
+    }
+
+    /// Queue a packet on its flow's worker
+    ///
+    /// Blocks while that worker's queue is full. Returns the tag if the
+    /// worker has stopped.
+    pub fn dispatch(&self, packet: Packet, tag: T) -> std::result::Result<(), T> {
+        match &self.mode {
+            Mode::Inline { pipeline, ctx, output } => {
+                let result = pipeline.process(packet, &mut ctx.lock());
+                output(tag, result);
+                Ok(())
+            }
+            Mode::Threads { queues, .. } => {
+                let worker = flow_hash(&packet) as usize % queues.len();
+                queues[worker]
+                    .send((packet, tag))
+                    .map_err(|mpsc::SendError((_, tag))| tag)
+            }
+        }
+    }
+
+    /// Finish queued packets and stop the workers
+    pub fn join(self) {
+        if let Mode::Threads { queues, workers } = self.mode {
+            drop(queues);
+            for worker in workers {
+                let _ = worker.join();
+            }
+        }
+    }
+}
+
+/// Hash of the connection 5-tuple, identical for both directions
+fn flow_hash(packet: &Packet) -> u64 {
+    let src = (packet.src_addr, packet.src_port);
+    let dst = (packet.dst_addr, packet.dst_port);
+    let (low, high) = if src <= dst { (src, dst) } else { (dst, src) };
+
+    let mut hasher = DefaultHasher::new();
+    packet.protocol.hash(&mut hasher);
+    low.hash(&mut hasher);
+    high.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{Direction, PacketBuilder};
+    use std::collections::HashMap;
+
+    fn packet(sport: u16, seq: u32, direction: Direction) -> Packet {
+        let (src, dst, sport, dport) = match direction {
+            Direction::Outbound => ([10, 0, 0, 1], [1, 1, 1, 1], sport, 443),
+            Direction::Inbound => ([1, 1, 1, 1], [10, 0, 0, 1], 443, sport),
+        };
+        let data = PacketBuilder::tcp_v4()
+            .src_ip_v4(src)
+            .dst_ip_v4(dst)
+            .src_port(sport)
+            .dst_port(dport)
+            .seq(seq)
+            .build_bytes();
+        Packet::from_bytes(&data, direction).unwrap()
+    }
+
+    #[test]
+    fn test_flow_hash_symmetric() {
+        assert_eq!(
+            flow_hash(&packet(50000, 0, Direction::Outbound)),
+            flow_hash(&packet(50000, 0, Direction::Inbound))
+        );
+    }
+
+    fn assert_flow_order_kept(threads: usize) {
+        const FLOWS: u16 = 64;
+        const PER_FLOW: u32 = 500;
+
+        let seen: Arc<Mutex<HashMap<u16, Vec<u32>>>> = Arc::default();
+        let ctx = Context::new();
+        let pool = {
+            let seen = Arc::clone(&seen);
+            WorkerPool::new(threads, Arc::new(Pipeline::new()), ctx.clone(), move |flow, result| {
+                for packet in result.unwrap() {
+                    seen.lock().entry(flow).or_default().push(packet.tcp_seq().unwrap());
+                }
+            })
+            .unwrap()
+        };
+        assert_eq!(pool.threads(), threads);
+
+        let (tx, rx) = mpsc::channel();
+        for seq in 0..PER_FLOW {
+            for flow in 0..FLOWS {
+                tx.send((flow, packet(40000 + flow, seq, Direction::Outbound))).unwrap();
+            }
+        }
+        drop(tx);
+
+        for (flow, packet) in rx {
+            pool.dispatch(packet, flow).unwrap();
+        }
+        pool.join();
+
+        let seen = seen.lock();
+        assert_eq!(seen.len(), FLOWS as usize);
+        for seqs in seen.values() {
+            assert_eq!(*seqs, (0..PER_FLOW).collect::<Vec<_>>());
+        }
+        assert_eq!(ctx.get_stats().packets_processed, u64::from(FLOWS) * u64::from(PER_FLOW));
+    }
+
+    #[test]
+    fn test_no_packets_lost_and_flow_order_kept() {
+        assert_flow_order_kept(4);
+    }
+
+    #[test]
+    fn test_single_thread_runs_inline() {
+        assert_flow_order_kept(1);
+
+        let caller = thread::current().id();
+        let ran_on = Arc::new(Mutex::new(None));
+        let pool = {
+            let ran_on = Arc::clone(&ran_on);
+            WorkerPool::new(1, Arc::new(Pipeline::new()), Context::new(), move |(), _| {
+                *ran_on.lock() = Some(thread::current().id());
+            })
+            .unwrap()
+        };
+        pool.dispatch(packet(50000, 0, Direction::Outbound), ()).unwrap();
+        assert_eq!(*ran_on.lock(), Some(caller));
+        pool.join();
+    }
+}
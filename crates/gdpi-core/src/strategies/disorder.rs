@@ -0,0 +1,225 @@
+//! Disorder strategy
+//!
+//! Splits the TLS ClientHello in two and sends the second segment first.
+//! A DPI that doesn't reassemble out-of-order segments never sees the SNI
+//! in one piece, while the server's TCP stack puts them back together.
+
+use super::fake_packet::{damage_checksum, fake_ttl};
+use super::fragment::move_push_to_last;
+use super::{Strategy, StrategyAction};
+use crate::config::{AutoTtlConfig, DisorderConfig};
+use crate::conntrack::ConnKey;
+use crate::error::Result;
+use crate::packet::Packet;
+use crate::pipeline::Context;
+use std::sync::atomic::Ordering;
+use tracing::instrument;
+
+/// Disorder strategy for sending ClientHello segments out of order
+pub struct DisorderStrategy {
+    /// ClientHello offset to split at
+    split_position: usize,
+    /// Send a fooling copy of the first segment ahead of the real ones
+    fooling: bool,
+    /// Fixed TTL of the fooling copy (None = use auto)
+    ttl: Option<u8>,
+    /// Auto TTL configuration
+    auto_ttl: Option<AutoTtlConfig>,
+}
+
+impl DisorderStrategy {
+    /// Create a new disorder strategy with default settings
+    pub fn new() -> Self {
+        Self::from_config(&DisorderConfig::default())
+    }
+
+    /// Create from configuration
+    pub fn from_config(config: &DisorderConfig) -> Self {
+        Self {
+            split_position: config.split_position as usize,
+            fooling: config.fooling,
+            ttl: config.ttl,
+            auto_ttl: config.auto_ttl.clone(),
+        }
+    }
+}
+
+impl Default for DisorderStrategy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Strategy for DisorderStrategy {
+    fn name(&self) -> &'static str {
+        "disorder"
+    }
+
+    fn priority(&self) -> u8 {
+        // Split before fragmentation gets to the ClientHello
+        75
+    }
+
+    fn should_apply(&self, packet: &Packet, ctx: &Context) -> bool {
+        if packet.is_fake || !packet.is_outbound() || !packet.is_tcp() {
+            return false;
+        }
+        if packet.payload_len() == 0 {
+            return false;
+        }
+        if ctx.was_bypassed(&ConnKey::from_packet(packet)) {
+            tracing::trace!("Disorder: connection already bypassed");
+            return false;
+        }
+
+        if !ctx.is_https_port(packet.dst_port) || !packet.is_tls_client_hello() {
+            return false;
+        }
+
+        // Check blacklist if enabled
+        if ctx.blacklist_enabled {
+            let hostname = packet.extract_sni();
+            if !ctx.should_apply_bypass_to(packet, hostname.as_deref()) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    #[instrument(skip(self, ctx), fields(strategy = self.name()))]
+    fn apply(&self, packet: Packet, ctx: &mut Context) -> Result<StrategyAction> {
+        if self.split_position == 0 || self.split_position >= packet.payload_len() {
+            return Ok(StrategyAction::Pass(packet));
+        }
+
+        let flow = ConnKey::from_packet(&packet);
+        let fooling_ttl = if self.fooling {
+            fake_ttl(ctx, &packet, self.ttl, self.auto_ttl.as_ref(), None)
+        } else {
+            None
+        };
+        let (first, second) = packet.split_at_payload(self.split_position)?;
+
+        let mut packets = vec![second, first];
+        move_push_to_last(&mut packets);
+        if let Some(ttl) = fooling_ttl {
+            packets.insert(0, fooling_copy(&packets[1], ttl)?);
+            ctx.stats.fake_packets_sent.fetch_add(1, Ordering::Relaxed);
+        }
+
+        ctx.stats.packets_fragmented.fetch_add(1, Ordering::Relaxed);
+        ctx.mark_fragmented(&flow);
+
+        Ok(StrategyAction::Replace(packets))
+    }
+}
+
+/// Copy of `first` with a zeroed payload, a low TTL and a bad checksum
+///
+/// The server drops it, but a DPI that takes the first segment it sees
+/// for a sequence range keeps the garbage instead of the real bytes.
+fn fooling_copy(first: &Packet, ttl: u8) -> Result<Packet> {
+    let mut fooling = first.with_new_payload(&vec![0; first.payload_len()])?;
+    fooling.is_fake = true;
+    fooling.set_ttl(ttl);
+    fooling.recalculate_checksums();
+    damage_checksum(&mut fooling);
+    Ok(fooling)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{ClientHelloBuilder, Direction, PacketBuilder, TcpFlags};
+
+    /// Outbound TLS ClientHello to port 443
+    fn client_hello_packet() -> Packet {
+        let data = PacketBuilder::tcp_v4()
+            .src_ip_v4([192, 168, 1, 10])
+            .dst_ip_v4([93, 184, 216, 34])
+            .src_port(50000)
+            .dst_port(443)
+            .seq(1000)
+            .flags(TcpFlags { ack: true, psh: true, ..TcpFlags::default() })
+            .payload(&ClientHelloBuilder::new("blocked.example").build())
+            .build_bytes();
+        Packet::from_bytes(&data, Direction::Outbound).unwrap()
+    }
+
+    fn replaced(action: StrategyAction) -> Vec<Packet> {
+        match action {
+            StrategyAction::Replace(packets) => packets,
+            other => panic!("unexpected action: {other:?}"),
+        }
+    }
+
+    /// TCP checksum as carried in the packet
+    fn tcp_checksum(packet: &Packet) -> u16 {
+        let offset = packet.ip_header_len() + 16;
+        let data = packet.as_bytes();
+        u16::from_be_bytes([data[offset], data[offset + 1]])
+    }
+
+    #[test]
+    fn test_segments_reversed() {
+        let strategy = DisorderStrategy::from_config(&DisorderConfig {
+            enabled: true,
+            split_position: 5,
+            fooling: false,
+            ..DisorderConfig::default()
+        });
+        let mut ctx = Context::new();
+        let original = client_hello_packet();
+        assert!(strategy.should_apply(&original, &ctx));
+
+        let packets = replaced(strategy.apply(original.clone(), &mut ctx).unwrap());
+
+        let seqs: Vec<_> = packets.iter().map(|p| p.tcp_seq().unwrap()).collect();
+        assert_eq!(seqs, vec![1005, 1000]);
+        assert_eq!(packets[0].payload(), &original.payload()[5..]);
+        assert_eq!(packets[1].payload(), &original.payload()[..5]);
+        // Only the segment sent last pushes
+        assert!(!packets[0].tcp_flags.unwrap().psh);
+        assert!(packets[1].tcp_flags.unwrap().psh);
+    }
+
+    #[test]
+    fn test_fooling_copy_first() {
+        let strategy = DisorderStrategy::from_config(&DisorderConfig {
+            enabled: true,
+            split_position: 5,
+            ttl: Some(3),
+            ..DisorderConfig::default()
+        });
+        let mut ctx = Context::new();
+
+        let packets = replaced(strategy.apply(client_hello_packet(), &mut ctx).unwrap());
+
+        let seqs: Vec<_> = packets.iter().map(|p| p.tcp_seq().unwrap()).collect();
+        assert_eq!(seqs, vec![1000, 1005, 1000]);
+
+        let fooling = &packets[0];
+        assert!(fooling.is_fake);
+        assert_eq!(fooling.as_bytes()[8], 3);
+        assert_eq!(fooling.payload(), &[0; 5]);
+
+        let mut valid = fooling.clone();
+        valid.recalculate_checksums();
+        assert_ne!(tcp_checksum(fooling), tcp_checksum(&valid));
+        assert_eq!(ctx.stats.fake_packets_sent.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_split_past_payload_passes() {
+        let strategy = DisorderStrategy::from_config(&DisorderConfig {
+            enabled: true,
+            split_position: u16::MAX,
+            ..DisorderConfig::default()
+        });
+        let mut ctx = Context::new();
+
+        let action = strategy.apply(client_hello_packet(), &mut ctx).unwrap();
+        assert!(matches!(action, StrategyAction::Pass(_)));
+    }
+}
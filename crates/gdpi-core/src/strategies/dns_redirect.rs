@@ -1,31 +1,58 @@
 //! DNS redirection strategy
 //!
 //! Redirects DNS queries to alternative DNS servers to bypass DNS-based blocking.
+//! Responses from the upstream are rewritten back to the resolver the query
+//! was originally sent to, otherwise the OS stack discards them. DNS over
+//! TCP is redirected per connection, starting from the client's SYN. With
+//! the `doh` feature, UDP queries can go to a DNS-over-HTTPS endpoint instead.
 
+#[cfg(feature = "doh")]
+use super::DohResolver;
 use super::{Strategy, StrategyAction};
 use crate::error::Result;
 use crate::packet::Packet;
 use crate::pipeline::Context;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::Ordering;
+#[cfg(feature = "doh")]
+use std::sync::Arc;
 use tracing::{debug, instrument};
 
 /// DNS redirection strategy
+///
+/// Each instance handles one address family: an IPv4 upstream only
+/// redirects IPv4 queries and an IPv6 upstream only IPv6 queries.
 pub struct DnsRedirectStrategy {
-    /// Upstream DNS server IPv4 address
-    upstream_addr: Ipv4Addr,
+    /// Upstream DNS server address
+    upstream_addr: IpAddr,
     /// Upstream DNS port
     upstream_port: u16,
+    /// Resolves UDP queries over HTTPS instead of redirecting them
+    #[cfg(feature = "doh")]
+    doh: Option<Arc<DohResolver>>,
 }
 
 impl DnsRedirectStrategy {
     /// Create a new DNS redirection strategy
-    pub fn new(upstream_addr: Ipv4Addr, upstream_port: u16) -> Self {
+    pub fn new(upstream_addr: impl Into<IpAddr>, upstream_port: u16) -> Self {
         Self {
-            upstream_addr,
+            upstream_addr: upstream_addr.into(),
             upstream_port,
+            #[cfg(feature = "doh")]
+            doh: None,
         }
     }
 
+    /// Resolve UDP queries with `resolver` instead of the upstream server
+    ///
+    /// The query is dropped and the answer injected later as if the
+    /// original resolver had sent it. DNS over TCP is still redirected.
+    #[cfg(feature = "doh")]
+    pub fn with_doh(mut self, resolver: Arc<DohResolver>) -> Self {
+        self.doh = Some(resolver);
+        self
+    }
+
     /// Create with Yandex DNS (default for Turkey)
     pub fn yandex() -> Self {
         Self::new(Ipv4Addr::new(77, 88, 8, 8), 53)
@@ -72,23 +99,69 @@ impl DnsRedirectStrategy {
         true
     }
 
+    /// Check if payload looks like a DNS response
+    fn is_dns_response(&self, payload: &[u8]) -> bool {
+        payload.len() >= 12 && payload[2] & 0x80 != 0
+    }
+
+    /// Check if packet is an inbound datagram from the upstream server
+    fn is_from_upstream(&self, packet: &Packet) -> bool {
+        packet.is_inbound()
+            && (packet.is_udp() || packet.is_tcp())
+            && packet.src_addr == self.upstream_addr
+            && packet.src_port == self.upstream_port
+    }
+
     /// Modify packet to redirect to upstream DNS
-    fn redirect_packet(&self, packet: &mut Packet) {
-        let data = packet.as_bytes_mut();
-
-        // Modify destination IP address (IPv4 at offset 16-19)
-        let octets = self.upstream_addr.octets();
-        data[16] = octets[0];
-        data[17] = octets[1];
-        data[18] = octets[2];
-        data[19] = octets[3];
-
-        // Modify destination port in UDP header
-        // UDP header starts after IP header (typically at offset 20)
-        let ip_header_len = ((data[0] & 0x0F) * 4) as usize;
-        let port_bytes = self.upstream_port.to_be_bytes();
-        data[ip_header_len + 2] = port_bytes[0];
-        data[ip_header_len + 3] = port_bytes[1];
+    fn redirect_packet(&self, packet: &mut Packet) -> Result<()> {
+        packet.set_dst_addr(self.upstream_addr)?;
+        packet.set_dst_port(self.upstream_port);
+        packet.recalculate_checksums();
+        Ok(())
+    }
+
+    /// Rewrite an upstream response to come from the original resolver
+    fn restore_response(&self, mut packet: Packet, ctx: &mut Context) -> Result<StrategyAction> {
+        let payload = packet.payload();
+        if !self.is_dns_response(payload) {
+            return Ok(StrategyAction::Pass(packet));
+        }
+        let txid = u16::from_be_bytes([payload[0], payload[1]]);
+
+        let Some((original_ip, original_port)) = ctx.dns_match_response(packet.dst_port, txid) else {
+            debug!(txid, port = packet.dst_port, "No matching DNS query for response");
+            return Ok(StrategyAction::Pass(packet));
+        };
+
+        packet.set_src_addr(original_ip)?;
+        packet.set_src_port(original_port);
+        packet.recalculate_checksums();
+
+        debug!(original = %original_ip, txid, "Restored DNS response source");
+
+        Ok(StrategyAction::Pass(packet))
+    }
+
+    /// Redirect a DNS-over-TCP segment in either direction
+    fn apply_tcp(&self, mut packet: Packet, ctx: &mut Context) -> Result<StrategyAction> {
+        let flags = packet.tcp_flags.unwrap_or_default();
+
+        if packet.is_outbound() {
+            if flags.syn && !flags.ack {
+                ctx.dns_track_tcp_flow(packet.src_port, packet.dst_addr, packet.dst_port);
+                ctx.stats.dns_redirected.fetch_add(1, Ordering::Relaxed);
+                debug!(upstream = %self.upstream_addr, "Redirecting DNS-over-TCP connection");
+            }
+            if ctx.dns_tcp_flow(packet.src_port, true, flags).is_some() {
+                self.redirect_packet(&mut packet)?;
+            }
+        } else if let Some((original_ip, original_port)) = ctx.dns_tcp_flow(packet.dst_port, false, flags) {
+            packet.set_src_addr(original_ip)?;
+            packet.set_src_port(original_port);
+            packet.recalculate_checksums();
+        }
+
+        Ok(StrategyAction::Pass(packet))
     }
 }
 
@@ -103,30 +176,50 @@ impl Strategy for DnsRedirectStrategy {
     }
 
     fn should_apply(&self, packet: &Packet, _ctx: &Context) -> bool {
-        // Apply to outbound UDP port 53 (DNS)
-        packet.is_outbound() 
-            && packet.is_udp() 
+        // Apply to outbound port 53 (DNS) and responses from upstream
+        let is_query = packet.is_outbound()
+            && (packet.is_udp() || packet.is_tcp())
             && packet.dst_port == 53
-            && packet.is_ipv4()
+            && packet.is_ipv4() == self.upstream_addr.is_ipv4();
+
+        is_query || self.is_from_upstream(packet)
     }
 
     #[instrument(skip(self, ctx), fields(strategy = self.name()))]
     fn apply(&self, mut packet: Packet, ctx: &mut Context) -> Result<StrategyAction> {
-        if !self.is_dns_query(packet.payload()) {
+        if packet.is_tcp() {
+            return self.apply_tcp(packet, ctx);
+        }
+        if packet.is_inbound() {
+            return self.restore_response(packet, ctx);
+        }
+
+        let payload = packet.payload();
+        if !self.is_dns_query(payload) {
             return Ok(StrategyAction::Pass(packet));
         }
+        let txid = u16::from_be_bytes([payload[0], payload[1]]);
+
+        #[cfg(feature = "doh")]
+        if let Some(doh) = &self.doh {
+            debug!(url = doh.url(), txid, "Resolving DNS query over HTTPS");
+            doh.resolve(packet, ctx);
+            ctx.stats.dns_redirected.fetch_add(1, Ordering::Relaxed);
+            return Ok(StrategyAction::Drop);
+        }
 
         // Store original destination for response mapping
-        ctx.dns_track_query(
+        ctx.dns_track_query_id(
             packet.src_port,
+            txid,
             packet.dst_addr,
             packet.dst_port,
         );
 
         // Redirect to upstream DNS
-        self.redirect_packet(&mut packet);
+        self.redirect_packet(&mut packet)?;
 
-        ctx.stats.dns_redirected += 1;
+        ctx.stats.dns_redirected.fetch_add(1, Ordering::Relaxed);
         debug!(
             upstream = %self.upstream_addr,
             port = self.upstream_port,
@@ -140,6 +233,8 @@ impl Strategy for DnsRedirectStrategy {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::packet::{Direction, TcpFlags};
+    use std::net::Ipv6Addr;
 
     #[test]
     fn test_dns_query_detection() {
@@ -175,6 +270,247 @@ mod tests {
         assert!(!strategy.is_dns_query(&response));
     }
 
+    /// Outbound IPv6/UDP DNS query to 2001:db8::53 port 53
+    fn ipv6_dns_query() -> Vec<u8> {
+        let query = [
+            0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        let udp_len = (8 + query.len()) as u16;
+
+        let mut data = vec![0x60, 0x00, 0x00, 0x00];
+        data.extend_from_slice(&udp_len.to_be_bytes());
+        data.extend_from_slice(&[17, 64]); // Next header UDP, hop limit
+        data.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        data.extend_from_slice(&"2001:db8::53".parse::<Ipv6Addr>().unwrap().octets());
+        data.extend_from_slice(&[0xC3, 0x50, 0x00, 0x35]);
+        data.extend_from_slice(&udp_len.to_be_bytes());
+        data.extend_from_slice(&[0xAB, 0xCD]);
+        data.extend_from_slice(&query);
+        data
+    }
+
+    #[test]
+    fn test_ipv6_redirect_round_trip() {
+        let upstream: Ipv6Addr = "2a02:6b8::feed:0ff".parse().unwrap();
+        let strategy = DnsRedirectStrategy::new(upstream, 5353);
+        let mut ctx = Context::new();
+
+        let packet = Packet::from_bytes(&ipv6_dns_query(), Direction::Outbound).unwrap();
+        assert!(strategy.should_apply(&packet, &ctx));
+
+        let redirected = match strategy.apply(packet, &mut ctx).unwrap() {
+            StrategyAction::Pass(p) => p,
+            other => panic!("unexpected action: {other:?}"),
+        };
+        let reparsed = Packet::from_bytes(redirected.as_bytes(), Direction::Outbound).unwrap();
+
+        assert_eq!(reparsed.dst_addr, IpAddr::V6(upstream));
+        assert_eq!(reparsed.dst_port, 5353);
+        assert_eq!(reparsed.src_addr, "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(reparsed.payload(), &ipv6_dns_query()[48..]);
+
+        // UDP checksum covers the rewritten destination
+        let IpAddr::V6(src) = reparsed.src_addr else { unreachable!() };
+        let segment = &reparsed.as_bytes()[40..];
+        assert_eq!(
+            crate::packet::PacketParser::transport_checksum_ipv6(&src.octets(), &upstream.octets(), 17, segment),
+            0
+        );
+        assert_eq!(ctx.get_stats().dns_redirected, 1);
+    }
+
+    /// IPv4/UDP DNS datagram between `src` and `dst`
+    fn ipv4_dns(src: ([u8; 4], u16), dst: ([u8; 4], u16), txid: u16, response: bool) -> Vec<u8> {
+        use crate::packet::PacketBuilder;
+
+        let flags: u16 = if response { 0x8180 } else { 0x0100 };
+        let mut dns = txid.to_be_bytes().to_vec();
+        dns.extend_from_slice(&flags.to_be_bytes());
+        dns.extend_from_slice(&[0x00, 0x01, 0x00, response as u8, 0x00, 0x00, 0x00, 0x00]);
+
+        PacketBuilder::udp_ipv4(src.0.into(), dst.0.into())
+            .src_port(src.1)
+            .dst_port(dst.1)
+            .ip_id(1)
+            .payload(&dns)
+            .build()
+            .unwrap()
+            .as_bytes()
+            .to_vec()
+    }
+
+    fn pass(action: StrategyAction) -> Packet {
+        match action {
+            StrategyAction::Pass(p) => p,
+            other => panic!("unexpected action: {other:?}"),
+        }
+    }
+
+    fn checksums_valid(packet: &Packet) -> bool {
+        use crate::packet::PacketParser;
+
+        let data = packet.as_bytes();
+        let (IpAddr::V4(src), IpAddr::V4(dst)) = (packet.src_addr, packet.dst_addr) else {
+            return false;
+        };
+        PacketParser::internet_checksum(&data[..20]) == 0
+            && PacketParser::udp_checksum_ipv4(&src.octets(), &dst.octets(), &data[20..]) == 0
+    }
+
+    #[test]
+    fn test_response_restored_to_original_resolver() {
+        let strategy = DnsRedirectStrategy::yandex();
+        let mut ctx = Context::new();
+        let client = ([192, 168, 1, 10], 50000);
+        let isp_dns = ([10, 0, 0, 1], 53);
+
+        let query = Packet::from_bytes(&ipv4_dns(client, isp_dns, 0x1234, false), Direction::Outbound).unwrap();
+        let query = pass(strategy.apply(query, &mut ctx).unwrap());
+        assert_eq!(query.dst_addr, IpAddr::V4(Ipv4Addr::new(77, 88, 8, 8)));
+        assert!(checksums_valid(&query));
+
+        let response = ipv4_dns(([77, 88, 8, 8], 53), client, 0x1234, true);
+        let response = Packet::from_bytes(&response, Direction::Inbound).unwrap();
+        assert!(strategy.should_apply(&response, &ctx));
+
+        let restored = pass(strategy.apply(response, &mut ctx).unwrap());
+        let reparsed = Packet::from_bytes(restored.as_bytes(), Direction::Inbound).unwrap();
+        assert_eq!(reparsed.src_addr, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(reparsed.src_port, 53);
+        assert!(checksums_valid(&reparsed));
+    }
+
+    /// DoH endpoint answering every request with `answer`
+    #[cfg(feature = "doh")]
+    fn serve_doh(answer: Vec<u8>) -> String {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/dns-query", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request);
+                let header = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/dns-message\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    answer.len()
+                );
+                let _ = stream.write_all(header.as_bytes());
+                let _ = stream.write_all(&answer);
+            }
+        });
+        url
+    }
+
+    #[cfg(feature = "doh")]
+    #[test]
+    fn test_doh_answer_injected() {
+        use std::time::{Duration, Instant};
+
+        let client = ([192, 168, 1, 10], 50000);
+        let isp_dns = ([10, 0, 0, 1], 53);
+        let answer = ipv4_dns(([0; 4], 0), ([0; 4], 0), 0, true)[28..].to_vec();
+        let resolver = DohResolver::new(serve_doh(answer)).unwrap();
+        let strategy = DnsRedirectStrategy::yandex().with_doh(Arc::new(resolver));
+        let mut ctx = Context::new();
+
+        let query = Packet::from_bytes(&ipv4_dns(client, isp_dns, 0x1234, false), Direction::Outbound).unwrap();
+        assert!(matches!(strategy.apply(query, &mut ctx).unwrap(), StrategyAction::Drop));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let injected = loop {
+            let mut injected = ctx.take_injections();
+            if let Some(packet) = injected.pop() {
+                break packet;
+            }
+            assert!(Instant::now() < deadline, "no DoH answer injected");
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert!(injected.is_inbound());
+        assert_eq!(injected.src_addr, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!((injected.src_port, injected.dst_port), (53, 50000));
+        assert_eq!(&injected.payload()[..2], &[0x12, 0x34]);
+        assert!(checksums_valid(&injected));
+        assert_eq!(ctx.get_stats().dns_redirected, 1);
+    }
+
+    #[test]
+    fn test_response_with_wrong_txid_untouched() {
+        let strategy = DnsRedirectStrategy::yandex();
+        let mut ctx = Context::new();
+        let client = ([192, 168, 1, 10], 50000);
+
+        let query = ipv4_dns(client, ([10, 0, 0, 1], 53), 0x1234, false);
+        strategy.apply(Packet::from_bytes(&query, Direction::Outbound).unwrap(), &mut ctx).unwrap();
+
+        let spoofed = ipv4_dns(([77, 88, 8, 8], 53), client, 0x9999, true);
+        let spoofed = pass(strategy.apply(Packet::from_bytes(&spoofed, Direction::Inbound).unwrap(), &mut ctx).unwrap());
+        assert_eq!(spoofed.src_addr, IpAddr::V4(Ipv4Addr::new(77, 88, 8, 8)));
+
+        // The real response still matches afterwards
+        assert_eq!(ctx.dns_match_response(50000, 0x1234), Some(("10.0.0.1".parse().unwrap(), 53)));
+    }
+
+    fn tcp_dns(src: ([u8; 4], u16), dst: ([u8; 4], u16), flags: TcpFlags, direction: Direction) -> Packet {
+        use crate::packet::PacketBuilder;
+
+        let data = PacketBuilder::tcp_v4()
+            .src_ip_v4(src.0)
+            .dst_ip_v4(dst.0)
+            .src_port(src.1)
+            .dst_port(dst.1)
+            .flags(flags)
+            .build_bytes();
+        Packet::from_bytes(&data, direction).unwrap()
+    }
+
+    #[test]
+    fn test_tcp_flow_redirect_lifecycle() {
+        let strategy = DnsRedirectStrategy::yandex();
+        let mut ctx = Context::new();
+        let client = ([192, 168, 1, 10], 40000);
+        let isp_dns = ([10, 0, 0, 1], 53);
+        let upstream = ([77, 88, 8, 8], 53);
+        let syn = TcpFlags { syn: true, ..Default::default() };
+        let syn_ack = TcpFlags { syn: true, ack: true, ..Default::default() };
+        let ack = TcpFlags { ack: true, ..Default::default() };
+        let rst = TcpFlags { rst: true, ..Default::default() };
+
+        // Mid-stream packets of an unknown flow are left alone
+        let stray = tcp_dns(client, isp_dns, ack, Direction::Outbound);
+        assert_eq!(pass(strategy.apply(stray, &mut ctx).unwrap()).dst_addr, IpAddr::from(isp_dns.0));
+
+        let out = pass(strategy.apply(tcp_dns(client, isp_dns, syn, Direction::Outbound), &mut ctx).unwrap());
+        assert_eq!(out.dst_addr, IpAddr::from(upstream.0));
+
+        let reply = tcp_dns(upstream, client, syn_ack, Direction::Inbound);
+        assert!(strategy.should_apply(&reply, &ctx));
+        let reply = pass(strategy.apply(reply, &mut ctx).unwrap());
+        assert_eq!(reply.src_addr, IpAddr::from(isp_dns.0));
+        assert_eq!(reply.src_port, 53);
+
+        let out = pass(strategy.apply(tcp_dns(client, isp_dns, ack, Direction::Outbound), &mut ctx).unwrap());
+        assert_eq!(out.dst_addr, IpAddr::from(upstream.0));
+
+        // RST from the server is rewritten and tears the mapping down
+        let reset = pass(strategy.apply(tcp_dns(upstream, client, rst, Direction::Inbound), &mut ctx).unwrap());
+        assert_eq!(reset.src_addr, IpAddr::from(isp_dns.0));
+        let after = pass(strategy.apply(tcp_dns(client, isp_dns, ack, Direction::Outbound), &mut ctx).unwrap());
+        assert_eq!(after.dst_addr, IpAddr::from(isp_dns.0));
+    }
+
+    #[test]
+    fn test_redirect_matches_address_family() {
+        let ctx = Context::new();
+        let packet = Packet::from_bytes(&ipv6_dns_query(), Direction::Outbound).unwrap();
+
+        assert!(!DnsRedirectStrategy::yandex().should_apply(&packet, &ctx));
+        assert!(DnsRedirectStrategy::new(Ipv6Addr::LOCALHOST, 53).should_apply(&packet, &ctx));
+    }
+
     #[test]
     fn test_predefined_servers() {
         let yandex = DnsRedirectStrategy::yandex();
@@ -0,0 +1,284 @@
+//! DNS-over-HTTPS upstream for [`DnsRedirectStrategy`](super::DnsRedirectStrategy)
+//!
+//! A UDP query is dropped and sent as an RFC 8484 GET request instead; the
+//! answer comes back as a UDP datagram from the resolver the client asked.
+//! Only built with the `doh` feature.
+
+use crate::config::doh_endpoint_ip;
+use crate::error::{Error, Result};
+use crate::packet::{Direction, Packet};
+use crate::pipeline::Context;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tracing::debug;
+
+/// Media type of DNS messages over HTTPS
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// Longest a DoH request may take; the client retries on its own after that
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most requests running at once; queries beyond that are dropped
+pub const MAX_IN_FLIGHT: usize = 64;
+
+/// Sends DNS queries to a DoH endpoint and queues the answers for injection
+///
+/// Requests run on a runtime owned by the resolver, so it works from the
+/// synchronous packet loop.
+pub struct DohResolver {
+    /// Endpoint URL, e.g. `https://1.1.1.1/dns-query`
+    url: String,
+    client: reqwest::Client,
+    /// Requests running right now
+    in_flight: Arc<AtomicUsize>,
+    /// Taken on drop to shut down without waiting for pending requests
+    runtime: Option<Runtime>,
+}
+
+impl DohResolver {
+    /// Create a resolver for the endpoint at `url`
+    ///
+    /// The host of `url` has to be an IP address, since resolving a name
+    /// would need the resolver itself.
+    ///
+    /// # Errors
+    /// Returns error if the host isn't an IP address, or the HTTP client or
+    /// its runtime can't be created.
+    pub fn new(url: impl Into<String>) -> Result<Self> {
+        let url = url.into();
+        if doh_endpoint_ip(&url).is_none() {
+            return Err(Error::strategy(
+                "dns_redirect",
+                format!("DoH endpoint host must be an IP address: {}", url),
+            ));
+        }
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| Error::strategy("dns_redirect", format!("DoH client: {}", e)))?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("gdpi-doh")
+            .enable_all()
+            .build()?;
+
+        Ok(Self {
+            url,
+            client,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            runtime: Some(runtime),
+        })
+    }
+
+    /// Endpoint URL
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Resolve the UDP query in `query` in the background
+    ///
+    /// The answer is queued on `ctx` with [`Context::queue_injection`]; a
+    /// failed request queues nothing. With [`MAX_IN_FLIGHT`] requests
+    /// already running the query is dropped, and the client retries.
+    pub fn resolve(&self, query: Packet, ctx: &Context) {
+        let Some(runtime) = &self.runtime else {
+            return;
+        };
+        let Some(slot) = InFlight::acquire(&self.in_flight) else {
+            debug!("Too many DoH requests in flight, dropping query");
+            return;
+        };
+        let url = request_url(&self.url, query.payload());
+        let client = self.client.clone();
+        let ctx = ctx.clone();
+
+        runtime.spawn(async move {
+            let _slot = slot;
+            let answer = match fetch(&client, &url).await {
+                Ok(answer) => answer,
+                Err(e) => {
+                    debug!(error = %e, "DoH request failed");
+                    return;
+                }
+            };
+            match response_packet(&query, &answer) {
+                Ok(packet) => {
+                    if !ctx.queue_injection(packet) {
+                        debug!("Injection queue full, discarding DoH answer");
+                    }
+                }
+                Err(e) => debug!(error = %e, "Discarding DoH answer"),
+            }
+        });
+    }
+}
+
+/// One of the [`MAX_IN_FLIGHT`] request slots, released on drop
+struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    fn acquire(count: &Arc<AtomicUsize>) -> Option<Self> {
+        count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < MAX_IN_FLIGHT).then_some(n + 1))
+            .ok()
+            .map(|_| Self(Arc::clone(count)))
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl Drop for DohResolver {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+async fn fetch(client: &reqwest::Client, url: &str) -> reqwest::Result<Vec<u8>> {
+    let response = client
+        .get(url)
+        .header(reqwest::header::ACCEPT, DNS_MESSAGE)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// GET URL asking `endpoint` the DNS question in `query`
+///
+/// The transaction ID is zeroed so identical questions share an HTTP cache
+/// entry (RFC 8484, section 4.1); [`response_packet`] puts it back.
+pub fn request_url(endpoint: &str, query: &[u8]) -> String {
+    let mut message = query.to_vec();
+    if message.len() >= 2 {
+        message[..2].fill(0);
+    }
+    let separator = if endpoint.contains('?') { '&' } else { '?' };
+    format!("{}{}dns={}", endpoint, separator, URL_SAFE_NO_PAD.encode(message))
+}
+
+/// Inbound UDP datagram carrying `answer` to the sender of `query`
+///
+/// The datagram comes from the address and port the query went to, with
+/// the query's transaction ID.
+///
+/// # Errors
+/// Returns error if `answer` isn't a DNS response.
+pub fn response_packet(query: &Packet, answer: &[u8]) -> Result<Packet> {
+    let payload = query.payload();
+    if payload.len() < 2 || answer.len() < 12 || answer[2] & 0x80 == 0 {
+        return Err(Error::strategy("dns_redirect", "DoH answer is not a DNS response"));
+    }
+
+    let mut answer = answer.to_vec();
+    answer[..2].copy_from_slice(&payload[..2]);
+    let mut packet = query.with_new_payload(&answer)?;
+    packet.set_src_addr(query.dst_addr)?;
+    packet.set_dst_addr(query.src_addr)?;
+    packet.set_src_port(query.dst_port);
+    packet.set_dst_port(query.src_port);
+    packet.direction = Direction::Inbound;
+    packet.recalculate_checksums();
+    Ok(packet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Query for example.com A with transaction ID 0xABCD
+    const QUERY: [u8; 29] = [
+        0xAB, 0xCD, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00,
+        0x00, 0x01, 0x00, 0x01,
+    ];
+
+    /// Outbound IPv4/UDP datagram carrying `payload` to 8.8.8.8:53
+    fn udp_packet(payload: &[u8]) -> Packet {
+        use crate::packet::PacketBuilder;
+        use std::net::Ipv4Addr;
+
+        PacketBuilder::udp_ipv4(Ipv4Addr::new(192, 168, 1, 10), Ipv4Addr::new(8, 8, 8, 8))
+            .src_port(50000)
+            .dst_port(53)
+            .payload(payload)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_request_url() {
+        assert_eq!(
+            request_url("https://dns.example/dns-query", &QUERY),
+            "https://dns.example/dns-query?dns=AAABAAABAAAAAAAAB2V4YW1wbGUDY29tAAABAAE"
+        );
+        assert_eq!(
+            request_url("https://dns.example/resolve?ct=1", &QUERY),
+            "https://dns.example/resolve?ct=1&dns=AAABAAABAAAAAAAAB2V4YW1wbGUDY29tAAABAAE"
+        );
+    }
+
+    #[test]
+    fn test_request_url_shared_across_ids() {
+        let mut other = QUERY;
+        other[..2].copy_from_slice(&[0x12, 0x34]);
+        assert_eq!(request_url("https://dns.example/dns-query", &QUERY), request_url("https://dns.example/dns-query", &other));
+    }
+
+    #[test]
+    fn test_response_packet() {
+        let query = udp_packet(&QUERY);
+        let mut answer = QUERY.to_vec();
+        answer[..2].fill(0);
+        answer[2] = 0x81;
+        answer[3] = 0x80;
+
+        let response = response_packet(&query, &answer).unwrap();
+        assert!(response.is_inbound());
+        assert!(response.is_udp());
+        assert_eq!(response.src_addr, query.dst_addr);
+        assert_eq!(response.dst_addr, query.src_addr);
+        assert_eq!((response.src_port, response.dst_port), (53, 50000));
+        assert_eq!(&response.payload()[..2], &[0xAB, 0xCD]);
+        assert_eq!(&response.payload()[2..], &answer[2..]);
+
+        let reparsed = Packet::from_bytes(response.as_bytes(), Direction::Inbound).unwrap();
+        assert_eq!(reparsed.payload(), response.payload());
+        let udp_len = u16::from_be_bytes([response.as_bytes()[24], response.as_bytes()[25]]);
+        assert_eq!(usize::from(udp_len), 8 + answer.len());
+    }
+
+    #[test]
+    fn test_hostname_endpoint_rejected() {
+        assert!(DohResolver::new("https://cloudflare-dns.com/dns-query").is_err());
+        assert!(DohResolver::new("https://1.1.1.1/dns-query").is_ok());
+    }
+
+    #[test]
+    fn test_in_flight_limit() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let slots: Vec<_> = (0..MAX_IN_FLIGHT).map_while(|_| InFlight::acquire(&count)).collect();
+        assert_eq!(slots.len(), MAX_IN_FLIGHT);
+        assert!(InFlight::acquire(&count).is_none());
+
+        drop(slots);
+        assert_eq!(count.load(Ordering::Acquire), 0);
+        assert!(InFlight::acquire(&count).is_some());
+    }
+
+    #[test]
+    fn test_response_packet_rejects_queries() {
+        let query = udp_packet(&QUERY);
+        assert!(response_packet(&query, &QUERY).is_err());
+        assert!(response_packet(&query, &[0x00, 0x00, 0x81]).is_err());
+    }
+}
@@ -4,9 +4,12 @@
 
 use super::{Strategy, StrategyAction};
 use crate::config::{AutoTtlConfig, FakePacketConfig};
+use crate::conntrack::ConnKey;
 use crate::error::Result;
-use crate::packet::{Packet, PacketBuilder, TcpFlags, Direction};
+use crate::packet::{ClientHelloBuilder, Packet, PacketBuilder, TcpFlags, Direction};
 use crate::pipeline::Context;
+use bytes::Bytes;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tracing::instrument;
 
 /// Fake packet injection strategy
@@ -15,6 +18,10 @@ pub struct FakePacketStrategy {
     wrong_checksum: bool,
     /// Use wrong SEQ/ACK numbers
     wrong_seq: bool,
+    /// SEQ offset for `wrong_seq` fakes
+    seq_offset: i64,
+    /// ACK offset for `wrong_seq` fakes
+    ack_offset: i64,
     /// Fixed TTL value (None = use auto)
     ttl: Option<u8>,
     /// Auto TTL configuration
@@ -23,6 +30,12 @@ pub struct FakePacketStrategy {
     min_ttl_hops: Option<u8>,
     /// Number of times to resend
     resend_count: u8,
+    /// Custom fake payloads (replace the built-in HTTP/TLS fakes)
+    custom_payloads: Vec<Bytes>,
+    /// Fake TLS ClientHellos generated from `fake_sni_domains`
+    sni_payloads: Vec<Bytes>,
+    /// Rotation counter for custom/SNI payloads
+    next_payload: AtomicUsize,
 }
 
 impl FakePacketStrategy {
@@ -31,81 +44,79 @@ impl FakePacketStrategy {
         Self {
             wrong_checksum: true,
             wrong_seq: true,
+            seq_offset: -10000,
+            ack_offset: -66000,
             ttl: None,
             auto_ttl: None,
             min_ttl_hops: Some(3),
             resend_count: 1,
+            custom_payloads: Vec::new(),
+            sni_payloads: Vec::new(),
+            next_payload: AtomicUsize::new(0),
         }
     }
 
     /// Create from configuration
+    ///
+    /// Invalid `custom_payloads` entries are skipped with a warning;
+    /// [`Config::validate`](crate::Config::validate) rejects them up front.
     pub fn from_config(config: &FakePacketConfig) -> Self {
+        let custom_payloads = match config.decode_custom_payloads() {
+            Ok(payloads) => payloads,
+            Err(e) => {
+                tracing::warn!("Ignoring custom fake payloads: {}", e);
+                Vec::new()
+            }
+        };
+
+        let sni_payloads = config
+            .fake_sni_domains
+            .iter()
+            .map(|domain| Bytes::from(ClientHelloBuilder::new(domain.as_str()).build()))
+            .collect();
+
         Self {
             wrong_checksum: config.wrong_checksum,
             wrong_seq: config.wrong_seq,
+            seq_offset: config.seq_offset,
+            ack_offset: config.ack_offset,
             ttl: config.ttl,
             auto_ttl: config.auto_ttl.clone(),
             min_ttl_hops: config.min_ttl_hops,
             resend_count: config.resend_count,
+            custom_payloads,
+            sni_payloads,
+            next_payload: AtomicUsize::new(0),
         }
     }
 
-    /// Calculate TTL for fake packet
-    fn calculate_ttl(&self, ctx: &Context, packet: &Packet) -> Option<u8> {
-        // If fixed TTL is set, use it
-        if let Some(ttl) = self.ttl {
-            return Some(ttl);
-        }
-
-        // If auto TTL is enabled, calculate based on connection TTL
-        if let Some(auto_config) = &self.auto_ttl {
-            // Look up the connection's measured TTL
-            if let Some(conn_ttl) = ctx.get_connection_ttl(packet) {
-                return self.auto_ttl_calculate(conn_ttl, auto_config);
-            }
-        }
-
-        // Default: use a low TTL that won't reach the server
-        Some(8)
+    /// Pick the next payload from a rotation list
+    fn next_from(&self, payloads: &[Bytes]) -> Bytes {
+        let index = self.next_payload.fetch_add(1, Ordering::Relaxed);
+        payloads[index % payloads.len()].clone()
     }
 
-    /// Calculate auto TTL based on measured connection TTL
-    fn auto_ttl_calculate(&self, conn_ttl: u8, config: &AutoTtlConfig) -> Option<u8> {
-        // Calculate number of hops to destination
-        let nhops = if conn_ttl > 98 && conn_ttl < 128 {
-            128 - conn_ttl
-        } else if conn_ttl > 34 && conn_ttl < 64 {
-            64 - conn_ttl
+    /// Create a fake packet with the configured payload for this connection
+    ///
+    /// Custom payloads take precedence, then fake SNI ClientHellos (HTTPS
+    /// only), then the built-in HTTP/TLS fakes.
+    fn create_fake(&self, original: &Packet, is_https: bool, ttl: u8, wrong_seq: bool) -> Packet {
+        if !self.custom_payloads.is_empty() {
+            let payload = self.next_from(&self.custom_payloads);
+            self.create_fake_packet(original, &payload, ttl, wrong_seq)
+        } else if is_https && !self.sni_payloads.is_empty() {
+            let payload = self.next_from(&self.sni_payloads);
+            self.create_fake_packet(original, &payload, ttl, wrong_seq)
+        } else if is_https {
+            self.create_fake_https(original, ttl, wrong_seq)
         } else {
-            return None;
-        };
-
-        // Check minimum hops requirement
-        if let Some(min_hops) = self.min_ttl_hops {
-            if nhops < min_hops {
-                return None;
-            }
-        }
-
-        // Calculate fake packet TTL
-        let mut fake_ttl = nhops.saturating_sub(config.a2);
-
-        // Adjust for short distances
-        if fake_ttl < config.a2 && nhops <= 9 {
-            let scale = (config.a2 - config.a1) as f32 * (nhops as f32 / 10.0);
-            fake_ttl = nhops.saturating_sub(config.a1).saturating_sub(scale as u8);
-        }
-
-        // Apply maximum limit
-        if fake_ttl > config.max {
-            fake_ttl = config.max;
+            self.create_fake_http(original, ttl, wrong_seq)
         }
+    }
 
-        if fake_ttl > 0 {
-            Some(fake_ttl)
-        } else {
-            None
-        }
+    /// Calculate TTL for fake packet
+    fn calculate_ttl(&self, ctx: &Context, packet: &Packet) -> Option<u8> {
+        fake_ttl(ctx, packet, self.ttl, self.auto_ttl.as_ref(), self.min_ttl_hops)
     }
 
     /// Create fake HTTP request packet
@@ -177,13 +188,13 @@ impl FakePacketStrategy {
         // Set TTL
         fake.set_ttl(ttl);
 
-        // If wrong_seq, modify SEQ/ACK to be in the past
+        // If wrong_seq, move SEQ/ACK out of the receiver's window
         if wrong_seq {
             if let Some(seq) = fake.tcp_seq() {
-                fake.set_tcp_seq(seq.wrapping_sub(10000));
+                fake.set_tcp_seq(offset_seq(seq, self.seq_offset));
             }
             if let Some(ack) = fake.tcp_ack_num() {
-                fake.set_tcp_ack(ack.wrapping_sub(66000));
+                fake.set_tcp_ack(offset_seq(ack, self.ack_offset));
             }
         }
 
@@ -192,24 +203,6 @@ impl FakePacketStrategy {
 
         fake
     }
-
-    /// Damage checksum to make packet invalid
-    fn damage_checksum(&self, packet: &mut Packet) {
-        // TCP checksum is at offset IP_header_len + 16
-        // Subtract 1 from checksum to make it invalid
-        let ip_header_len = packet.ip_header_len();
-        let tcp_checksum_offset = ip_header_len + 16;
-        
-        let data = packet.as_bytes_mut();
-        if data.len() > tcp_checksum_offset + 1 {
-            // Read current checksum, subtract 1, write back
-            let current = u16::from_be_bytes([data[tcp_checksum_offset], data[tcp_checksum_offset + 1]]);
-            let damaged = current.wrapping_sub(1);
-            let bytes = damaged.to_be_bytes();
-            data[tcp_checksum_offset] = bytes[0];
-            data[tcp_checksum_offset + 1] = bytes[1];
-        }
-    }
 }
 
 impl Default for FakePacketStrategy {
@@ -244,10 +237,14 @@ impl Strategy for FakePacketStrategy {
             tracing::trace!("FakePacket: no payload");
             return false;
         }
+        if ctx.was_bypassed(&ConnKey::from_packet(packet)) {
+            tracing::trace!("FakePacket: connection already bypassed");
+            return false;
+        }
 
         // Only for HTTP/HTTPS initial requests
-        let is_http = packet.dst_port == 80 && packet.is_http_request();
-        let is_https = packet.dst_port == 443 && packet.is_tls_client_hello();
+        let is_http = ctx.is_http_port(packet.dst_port) && packet.is_http_request();
+        let is_https = ctx.is_https_port(packet.dst_port) && ctx.is_client_hello(packet);
 
         if !is_http && !is_https {
             return false;
@@ -258,13 +255,11 @@ impl Strategy for FakePacketStrategy {
             let hostname = if is_http {
                 packet.extract_http_host()
             } else {
-                packet.extract_sni()
+                ctx.client_hello_sni(packet)
             };
 
-            if let Some(host) = hostname {
-                if !ctx.is_blacklisted(&host) {
-                    return false;
-                }
+            if !ctx.should_apply_bypass_to(packet, hostname.as_deref()) {
+                return false;
             }
         }
 
@@ -280,48 +275,126 @@ impl Strategy for FakePacketStrategy {
             }
         };
 
-        let is_https = packet.dst_port == 443;
+        let is_https = ctx.is_client_hello(&packet);
         let mut fake_packets = Vec::new();
 
         for _ in 0..self.resend_count {
             // Create fake with wrong TTL
             if self.ttl.is_some() || self.auto_ttl.is_some() {
-                let fake = if is_https {
-                    self.create_fake_https(&packet, ttl, false)
-                } else {
-                    self.create_fake_http(&packet, ttl, false)
-                };
-                fake_packets.push(fake);
+                fake_packets.push(self.create_fake(&packet, is_https, ttl, false));
             }
 
             // Create fake with wrong checksum
             if self.wrong_checksum {
-                let mut fake = if is_https {
-                    self.create_fake_https(&packet, 64, false)
-                } else {
-                    self.create_fake_http(&packet, 64, false)
-                };
-                self.damage_checksum(&mut fake);
+                let mut fake = self.create_fake(&packet, is_https, 64, false);
+                damage_checksum(&mut fake);
                 fake_packets.push(fake);
             }
 
             // Create fake with wrong SEQ/ACK
             if self.wrong_seq {
-                let fake = if is_https {
-                    self.create_fake_https(&packet, 64, true)
-                } else {
-                    self.create_fake_http(&packet, 64, true)
-                };
-                fake_packets.push(fake);
+                fake_packets.push(self.create_fake(&packet, is_https, 64, true));
             }
         }
 
-        ctx.stats.fake_packets_sent += fake_packets.len() as u64;
+        ctx.stats.fake_packets_sent.fetch_add(fake_packets.len() as u64, Ordering::Relaxed);
 
         Ok(StrategyAction::InjectBefore(fake_packets, packet))
     }
 }
 
+/// TTL for a packet that must not reach the server
+///
+/// A fixed `ttl` wins; otherwise it is derived from the connection's
+/// measured TTL when `auto` is set, falling back to 8. `None` when the
+/// server is too close for a TTL that stops short of it.
+pub(super) fn fake_ttl(
+    ctx: &Context,
+    packet: &Packet,
+    ttl: Option<u8>,
+    auto: Option<&AutoTtlConfig>,
+    min_hops: Option<u8>,
+) -> Option<u8> {
+    // If fixed TTL is set, use it
+    if let Some(ttl) = ttl {
+        return Some(ttl);
+    }
+
+    // If auto TTL is enabled, calculate based on connection TTL
+    if let Some(auto_config) = auto {
+        // Look up the connection's measured TTL
+        if let Some(conn_ttl) = ctx.get_connection_ttl(packet) {
+            return auto_ttl(conn_ttl, auto_config, min_hops);
+        }
+    }
+
+    // Default: use a low TTL that won't reach the server
+    Some(8)
+}
+
+/// Calculate auto TTL based on measured connection TTL
+fn auto_ttl(conn_ttl: u8, config: &AutoTtlConfig, min_hops: Option<u8>) -> Option<u8> {
+    // Calculate number of hops to destination
+    let nhops = if conn_ttl > 98 && conn_ttl < 128 {
+        128 - conn_ttl
+    } else if conn_ttl > 34 && conn_ttl < 64 {
+        64 - conn_ttl
+    } else {
+        return None;
+    };
+
+    // Check minimum hops requirement
+    if let Some(min_hops) = min_hops {
+        if nhops < min_hops {
+            return None;
+        }
+    }
+
+    // Calculate fake packet TTL
+    let mut fake_ttl = nhops.saturating_sub(config.a2);
+
+    // Adjust for short distances
+    if fake_ttl < config.a2 && nhops <= 9 {
+        let scale = (config.a2 - config.a1) as f32 * (nhops as f32 / 10.0);
+        fake_ttl = nhops.saturating_sub(config.a1).saturating_sub(scale as u8);
+    }
+
+    // Apply maximum limit
+    if fake_ttl > config.max {
+        fake_ttl = config.max;
+    }
+
+    if fake_ttl > 0 {
+        Some(fake_ttl)
+    } else {
+        None
+    }
+}
+
+/// Damage checksum to make packet invalid
+pub(super) fn damage_checksum(packet: &mut Packet) {
+    // TCP checksum is at offset IP_header_len + 16
+    // Subtract 1 from checksum to make it invalid
+    let ip_header_len = packet.ip_header_len();
+    let tcp_checksum_offset = ip_header_len + 16;
+
+    let data = packet.as_bytes_mut();
+    if data.len() > tcp_checksum_offset + 1 {
+        // Read current checksum, subtract 1, write back
+        let current = u16::from_be_bytes([data[tcp_checksum_offset], data[tcp_checksum_offset + 1]]);
+        let damaged = current.wrapping_sub(1);
+        let bytes = damaged.to_be_bytes();
+        data[tcp_checksum_offset] = bytes[0];
+        data[tcp_checksum_offset + 1] = bytes[1];
+    }
+}
+
+/// Add a signed offset to a TCP sequence number, modulo 2^32
+fn offset_seq(value: u32, offset: i64) -> u32 {
+    // Truncating keeps the offset's value modulo 2^32
+    value.wrapping_add(offset as u32)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -339,11 +412,12 @@ mod tests {
             }),
             min_ttl_hops: Some(3),
             resend_count: 1,
+            ..FakePacketStrategy::new()
         };
 
         // Test with TTL indicating ~10 hops (128 - 118 = 10)
         let config = strategy.auto_ttl.as_ref().unwrap();
-        let result = strategy.auto_ttl_calculate(118, config);
+        let result = auto_ttl(118, config, strategy.min_ttl_hops);
         assert!(result.is_some());
         let ttl = result.unwrap();
         assert!(ttl > 0 && ttl <= 10);
@@ -358,11 +432,119 @@ mod tests {
             auto_ttl: Some(AutoTtlConfig::default()),
             min_ttl_hops: Some(5),
             resend_count: 1,
+            ..FakePacketStrategy::new()
         };
 
         // TTL 126 means only 2 hops, should return None (below min_hops)
         let config = strategy.auto_ttl.as_ref().unwrap();
-        let result = strategy.auto_ttl_calculate(126, config);
+        let result = auto_ttl(126, config, strategy.min_ttl_hops);
         assert!(result.is_none());
     }
+
+    /// Outbound TLS ClientHello to port 443
+    fn client_hello_packet() -> Packet {
+        let data = PacketBuilder::tcp_v4()
+            .src_ip_v4([192, 168, 1, 10])
+            .dst_ip_v4([93, 184, 216, 34])
+            .src_port(50000)
+            .dst_port(443)
+            .flags(TcpFlags { ack: true, psh: true, ..TcpFlags::default() })
+            .payload(&ClientHelloBuilder::new("blocked.example").build())
+            .build_bytes();
+        Packet::from_bytes(&data, Direction::Outbound).unwrap()
+    }
+
+    fn fakes(action: StrategyAction) -> Vec<Packet> {
+        match action {
+            StrategyAction::InjectBefore(fakes, _) => fakes,
+            other => panic!("unexpected action: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_custom_payloads_alternate() {
+        let config = FakePacketConfig {
+            wrong_checksum: false,
+            wrong_seq: true,
+            custom_payloads: vec!["deadbeef".to_string(), "ca fe ba be".to_string()],
+            ..FakePacketConfig::default()
+        };
+        let strategy = FakePacketStrategy::from_config(&config);
+        let mut ctx = Context::new();
+
+        let payloads: Vec<Vec<u8>> = (0..4)
+            .flat_map(|_| fakes(strategy.apply(client_hello_packet(), &mut ctx).unwrap()))
+            .map(|p| p.payload().to_vec())
+            .collect();
+
+        assert_eq!(
+            payloads,
+            vec![
+                vec![0xde, 0xad, 0xbe, 0xef],
+                vec![0xca, 0xfe, 0xba, 0xbe],
+                vec![0xde, 0xad, 0xbe, 0xef],
+                vec![0xca, 0xfe, 0xba, 0xbe],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_custom_payload_skipped() {
+        let config = FakePacketConfig {
+            custom_payloads: vec!["zz".to_string()],
+            ..FakePacketConfig::default()
+        };
+        let strategy = FakePacketStrategy::from_config(&config);
+        assert!(strategy.custom_payloads.is_empty());
+    }
+
+    #[test]
+    fn test_seq_offset_past_and_future() {
+        let original = client_hello_packet();
+        let (seq, ack) = (original.tcp_seq().unwrap(), original.tcp_ack_num().unwrap());
+
+        let cases = [
+            (-10000, -66000, seq.wrapping_sub(10000), ack.wrapping_sub(66000)),
+            (1 << 20, 5, seq.wrapping_add(1 << 20), ack.wrapping_add(5)),
+        ];
+        for (seq_offset, ack_offset, want_seq, want_ack) in cases {
+            let config = FakePacketConfig {
+                wrong_checksum: false,
+                wrong_seq: true,
+                seq_offset,
+                ack_offset,
+                ..FakePacketConfig::default()
+            };
+            let strategy = FakePacketStrategy::from_config(&config);
+            let fake = strategy.create_fake_packet(&original, b"fake", 8, true);
+
+            assert_eq!(fake.tcp_seq(), Some(want_seq));
+            assert_eq!(fake.tcp_ack_num(), Some(want_ack));
+        }
+    }
+
+    #[test]
+    fn test_seq_offset_wraps() {
+        assert_eq!(offset_seq(5, -10), u32::MAX - 4);
+        assert_eq!(offset_seq(u32::MAX - 1, 3), 1);
+        assert_eq!(offset_seq(0, -(1 << 32)), 0);
+        assert_eq!(offset_seq(1000, -10000), 1000u32.wrapping_sub(10000));
+    }
+
+    #[test]
+    fn test_fake_sni_domains() {
+        let config = FakePacketConfig {
+            wrong_checksum: false,
+            wrong_seq: true,
+            fake_sni_domains: vec!["www.w3.org".to_string()],
+            ..FakePacketConfig::default()
+        };
+        let strategy = FakePacketStrategy::from_config(&config);
+        let mut ctx = Context::new();
+
+        let fakes = fakes(strategy.apply(client_hello_packet(), &mut ctx).unwrap());
+        assert_eq!(fakes.len(), 1);
+        assert!(fakes[0].is_fake);
+        assert_eq!(fakes[0].extract_sni().as_deref(), Some("www.w3.org"));
+    }
 }
@@ -3,10 +3,13 @@
 //! Splits TCP packets into smaller fragments to evade DPI inspection.
 
 use super::{Strategy, StrategyAction};
-use crate::config::FragmentationConfig;
+use crate::config::{FragmentationConfig, PortStrategyConfig};
+use crate::conntrack::ConnKey;
 use crate::error::Result;
-use crate::packet::{Packet, Direction};
+use crate::packet::{ClientHelloInfo, Direction, Packet};
 use crate::pipeline::Context;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
 use tracing::instrument;
 
 /// Fragmentation strategy for splitting packets
@@ -23,6 +26,12 @@ pub struct FragmentationStrategy {
     by_sni: bool,
     /// Enable for persistent HTTP connections
     http_persistent: bool,
+    /// Explicit split offsets (multi-way fragmentation)
+    fragment_positions: Vec<usize>,
+    /// Send the fragment holding the hostname twice
+    duplicate_first_fragment: bool,
+    /// Settings for destination ports other than 80/443
+    port_overrides: HashMap<u16, PortStrategyConfig>,
 }
 
 impl FragmentationStrategy {
@@ -35,6 +44,9 @@ impl FragmentationStrategy {
             reverse_order: true,
             by_sni: false,
             http_persistent: true,
+            fragment_positions: Vec::new(),
+            duplicate_first_fragment: false,
+            port_overrides: HashMap::new(),
         }
     }
 
@@ -47,43 +59,41 @@ impl FragmentationStrategy {
             reverse_order: config.reverse_order,
             by_sni: config.by_sni,
             http_persistent: config.http_persistent,
+            fragment_positions: config
+                .fragment_positions
+                .iter()
+                .map(|&pos| pos as usize)
+                .collect(),
+            duplicate_first_fragment: config.duplicate_first_fragment,
+            port_overrides: HashMap::new(),
         }
     }
 
+    /// Route destination ports by these settings instead of the context's
+    /// HTTP/HTTPS ports (see [`Config::port_overrides`](crate::config::Config::port_overrides))
+    pub fn with_port_overrides(mut self, port_overrides: HashMap<u16, PortStrategyConfig>) -> Self {
+        self.port_overrides = port_overrides;
+        self
+    }
+
     /// Get fragment size for this packet
     fn get_fragment_size(&self, packet: &Packet) -> u16 {
-        if packet.dst_port == 80 || packet.src_port == 80 {
+        let custom = self.port_overrides.get(&packet.dst_port).and_then(|port| port.custom_fragment_size);
+        if let Some(size) = custom {
+            size
+        } else if packet.dst_port == 80 || packet.src_port == 80 || packet.is_http_request() {
             self.http_size
         } else {
             self.https_size
         }
     }
 
-    /// Find optimal fragment position for TLS (before SNI)
+    /// Split position one byte before the TLS SNI hostname
     fn find_sni_fragment_position(&self, packet: &Packet) -> Option<usize> {
         if !self.by_sni {
             return None;
         }
-
-        let payload = packet.payload();
-        if payload.len() < 44 {
-            return None;
-        }
-
-        // Look for SNI extension in TLS ClientHello
-        // SNI extension starts with 00 00 (extension type)
-        for i in 0..payload.len().saturating_sub(10) {
-            // SNI extension pattern check
-            if payload[i] == 0x00 
-                && payload[i + 1] == 0x00 
-                && payload[i + 2] == 0x00 
-            {
-                // Found potential SNI, fragment just before it
-                return Some(i);
-            }
-        }
-
-        None
+        ClientHelloInfo::parse(packet.payload())?.offset_of_sni.map(|offset| offset - 1)
     }
 }
 
@@ -125,33 +135,42 @@ impl Strategy for FragmentationStrategy {
             tracing::trace!("Fragment: no payload");
             return false;
         }
+        let flow = ConnKey::from_packet(packet);
+        if ctx.was_bypassed(&flow) {
+            tracing::trace!("Fragment: connection already bypassed");
+            return false;
+        }
+        // Some servers reject a keep-alive connection split more than once
+        if !self.http_persistent && ctx.is_fragmented(&flow) {
+            tracing::trace!("Fragment: connection already fragmented");
+            return false;
+        }
 
         // Check if it's HTTP or HTTPS traffic
-        let is_http = packet.dst_port == 80;
-        let is_https = packet.dst_port == 443;
+        let (is_http_port, is_https_port) = match self.port_overrides.get(&packet.dst_port) {
+            Some(port) => (port.use_http_strategies, port.use_https_strategies),
+            None => (ctx.is_http_port(packet.dst_port), ctx.is_https_port(packet.dst_port)),
+        };
 
-        if !is_http && !is_https {
+        if !is_http_port && !is_https_port {
             tracing::trace!(dst_port = packet.dst_port, "Fragment: not HTTP/HTTPS port");
             return false;
         }
 
-        // For HTTP, check if it looks like an HTTP request
-        if is_http && !packet.is_http_request() {
-            tracing::trace!("Fragment: port 80 but not HTTP request");
-            return false;
-        }
+        // Must look like an HTTP request or a TLS ClientHello
+        let is_http = is_http_port && packet.is_http_request();
+        let is_https = is_https_port && ctx.is_client_hello(packet);
 
-        // For HTTPS, check if it looks like TLS ClientHello
-        if is_https && !packet.is_tls_client_hello() {
+        if !is_http && !is_https {
+            tracing::trace!("Fragment: not HTTP request or ClientHello");
             return false;
         }
 
         // Check blacklist if enabled
         if ctx.blacklist_enabled {
-            if let Some(hostname) = self.extract_hostname(packet) {
-                if !ctx.is_blacklisted(&hostname) {
-                    return false;
-                }
+            let hostname = self.extract_hostname(packet, ctx);
+            if !ctx.should_apply_bypass_to(packet, hostname.as_deref()) {
+                return false;
             }
         }
 
@@ -160,7 +179,35 @@ impl Strategy for FragmentationStrategy {
 
     #[instrument(skip(self, ctx), fields(strategy = self.name()))]
     fn apply(&self, packet: Packet, ctx: &mut Context) -> Result<StrategyAction> {
-        let fragment_size = if self.by_sni {
+        let hostname_seq = self.hostname_seq(&packet, ctx);
+        let flow = ConnKey::from_packet(&packet);
+        let mut action = if self.fragment_positions.is_empty() {
+            self.apply_size(packet, ctx)?
+        } else {
+            self.apply_positions(packet, ctx)?
+        };
+
+        if let (StrategyAction::Replace(fragments), Some(seq)) = (&mut action, hostname_seq) {
+            if duplicate_fragment_at(fragments, seq) {
+                ctx.stats.fragments_duplicated.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if matches!(action, StrategyAction::Replace(_)) {
+            ctx.mark_fragmented(&flow);
+        }
+        Ok(action)
+    }
+}
+
+impl FragmentationStrategy {
+    /// Split into two fragments at the HTTP/HTTPS fragment size
+    fn apply_size(&self, packet: Packet, ctx: &mut Context) -> Result<StrategyAction> {
+        // Fixed sizes count from the record start, which an earlier segment
+        // carried, so a continuation is split before the hostname instead
+        let continued_sni = ctx.continued_client_hello(&packet).and_then(|hello| hello.sni_offset());
+        let fragment_size = if let Some(offset) = continued_sni {
+            offset.saturating_sub(1).max(1) as u16
+        } else if self.by_sni {
             self.find_sni_fragment_position(&packet)
                 .map(|pos| pos as u16)
                 .unwrap_or_else(|| self.get_fragment_size(&packet))
@@ -176,28 +223,103 @@ impl Strategy for FragmentationStrategy {
         // Split the packet
         let (first, second) = packet.split_at_payload(fragment_size as usize)?;
 
-        ctx.stats.packets_fragmented += 1;
+        ctx.stats.packets_fragmented.fetch_add(1, Ordering::Relaxed);
 
         // Return fragments in order (or reversed)
-        let fragments = if self.reverse_order {
+        let mut fragments = if self.reverse_order {
             vec![second, first]
         } else {
             vec![first, second]
         };
+        move_push_to_last(&mut fragments);
 
         Ok(StrategyAction::Replace(fragments))
     }
-}
 
-impl FragmentationStrategy {
+    /// Split at the configured positions that fall inside the payload
+    fn apply_positions(&self, packet: Packet, ctx: &mut Context) -> Result<StrategyAction> {
+        let payload_len = packet.payload_len();
+        let offsets: Vec<usize> = self
+            .fragment_positions
+            .iter()
+            .copied()
+            .filter(|&pos| pos > 0 && pos < payload_len)
+            .collect();
+
+        if offsets.is_empty() {
+            return Ok(StrategyAction::Pass(packet));
+        }
+
+        let mut fragments = packet.split_at_payloads(&offsets)?;
+        ctx.stats.packets_fragmented.fetch_add(1, Ordering::Relaxed);
+
+        if self.reverse_order {
+            fragments.reverse();
+        }
+        move_push_to_last(&mut fragments);
+
+        Ok(StrategyAction::Replace(fragments))
+    }
+
+    /// SEQ of the first hostname byte, if it is to be duplicated
+    fn hostname_seq(&self, packet: &Packet, ctx: &Context) -> Option<u32> {
+        if !self.duplicate_first_fragment {
+            return None;
+        }
+        let hostname = self.extract_hostname(packet, ctx)?;
+        let offset = packet
+            .payload()
+            .windows(hostname.len())
+            .position(|window| window.eq_ignore_ascii_case(hostname.as_bytes()))?;
+        Some(packet.tcp_seq()?.wrapping_add(offset as u32))
+    }
+
     /// Extract hostname from packet (HTTP Host header or TLS SNI)
-    fn extract_hostname(&self, packet: &Packet) -> Option<String> {
+    fn extract_hostname(&self, packet: &Packet, ctx: &Context) -> Option<String> {
         if packet.is_http_request() {
             packet.extract_http_host()
-        } else if packet.is_tls_client_hello() {
-            packet.extract_sni()
         } else {
-            None
+            ctx.client_hello_sni(packet)
+        }
+    }
+}
+
+/// Send the fragment covering `seq` once more, right after itself
+///
+/// Only one copy is added however the fragments were split. Returns
+/// whether a fragment covered `seq`.
+fn duplicate_fragment_at(fragments: &mut Vec<Packet>, seq: u32) -> bool {
+    let position = fragments.iter().position(|fragment| {
+        fragment
+            .tcp_seq()
+            .is_some_and(|start| (seq.wrapping_sub(start) as usize) < fragment.payload_len())
+    });
+    let Some(index) = position else {
+        return false;
+    };
+
+    let copy = fragments[index].clone();
+    fragments.insert(index + 1, copy);
+    true
+}
+
+/// Keep PSH only on the last fragment sent
+///
+/// Every fragment inherits the original header, so each would otherwise
+/// carry PSH. With out-of-order sending, a PSH on an early segment makes
+/// some stacks deliver before the hole is filled; the receiver should only
+/// be pushed once the final fragment arrives and the stream is complete.
+pub(super) fn move_push_to_last(fragments: &mut [Packet]) {
+    let Some((last, rest)) = fragments.split_last_mut() else {
+        return;
+    };
+    if !last.tcp_flags.is_some_and(|f| f.psh) {
+        return;
+    }
+    for fragment in rest {
+        if let Some(mut flags) = fragment.tcp_flags {
+            flags.psh = false;
+            fragment.set_tcp_flags(flags);
         }
     }
 }
@@ -218,6 +340,8 @@ mod tests {
             by_sni: false,
             http_persistent: true,
             persistent_nowait: true,
+            fragment_positions: Vec::new(),
+            duplicate_first_fragment: false,
         };
 
         let strategy = FragmentationStrategy::from_config(&config);
@@ -240,7 +364,181 @@ mod tests {
         assert_eq!(strategy.get_fragment_size(&https_packet), 2);
     }
 
-    fn create_mock_packet(dst_port: u16) -> Packet {
+    #[test]
+    fn test_multi_way_fragmentation() {
+        let strategy = FragmentationStrategy::from_config(&FragmentationConfig {
+            reverse_order: false,
+            fragment_positions: vec![1, 2, 3, 64],
+            ..FragmentationConfig::default()
+        });
+        let mut ctx = Context::new();
+        let packet = create_mock_packet(80);
+        let original = packet.payload().to_vec();
+
+        let fragments = match strategy.apply(packet, &mut ctx).unwrap() {
+            StrategyAction::Replace(fragments) => fragments,
+            other => panic!("unexpected action: {other:?}"),
+        };
+
+        // Position 64 is past the payload and ignored
+        assert_eq!(fragments.len(), 4);
+        let reassembled: Vec<u8> = fragments.iter().flat_map(|f| f.payload().to_vec()).collect();
+        assert_eq!(reassembled, original);
+        assert_eq!(ctx.get_stats().packets_fragmented, 1);
+    }
+
+    #[test]
+    fn test_by_sni_splits_before_hostname() {
+        use crate::packet::{ClientHelloBuilder, PacketBuilder};
+
+        let strategy = FragmentationStrategy::from_config(&FragmentationConfig {
+            by_sni: true,
+            reverse_order: false,
+            ..FragmentationConfig::default()
+        });
+        let data = PacketBuilder::tcp_v4()
+            .dst_port(443)
+            .payload(&ClientHelloBuilder::new("discord.com").build())
+            .build_bytes();
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+        let offset = ClientHelloInfo::parse(packet.payload()).unwrap().offset_of_sni.unwrap();
+        let mut ctx = Context::new();
+
+        let fragments = match strategy.apply(packet, &mut ctx).unwrap() {
+            StrategyAction::Replace(fragments) => fragments,
+            other => panic!("unexpected action: {other:?}"),
+        };
+        assert_eq!(fragments[0].payload_len(), offset - 1);
+        assert!(fragments[1].payload()[1..].starts_with(b"discord.com"));
+    }
+
+    #[test]
+    fn test_duplicate_only_one_fragment() {
+        use crate::packet::{PacketBuilder, TcpFlags};
+
+        let strategy = FragmentationStrategy::from_config(&FragmentationConfig {
+            reverse_order: true,
+            // The second split falls inside "example.com"
+            fragment_positions: vec![1, 24],
+            duplicate_first_fragment: true,
+            ..FragmentationConfig::default()
+        });
+        let data = PacketBuilder::tcp_v4()
+            .dst_port(80)
+            .seq(500)
+            .flags(TcpFlags { psh: true, ack: true, ..TcpFlags::default() })
+            .payload(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n")
+            .build_bytes();
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+        let mut ctx = Context::new();
+
+        let fragments = match strategy.apply(packet, &mut ctx).unwrap() {
+            StrategyAction::Replace(fragments) => fragments,
+            other => panic!("unexpected action: {other:?}"),
+        };
+
+        let seqs: Vec<u32> = fragments.iter().map(|f| f.tcp_seq().unwrap()).collect();
+        assert_eq!(seqs, [524, 501, 501, 500]);
+        assert_eq!(ctx.get_stats().fragments_duplicated, 1);
+    }
+
+    #[test]
+    fn test_push_flag_on_last_sent_fragment() {
+        for reverse_order in [false, true] {
+            let strategy = FragmentationStrategy::from_config(&FragmentationConfig {
+                http_size: 4,
+                reverse_order,
+                ..FragmentationConfig::default()
+            });
+            let mut ctx = Context::new();
+
+            let fragments = match strategy.apply(create_mock_packet(80), &mut ctx).unwrap() {
+                StrategyAction::Replace(fragments) => fragments,
+                other => panic!("unexpected action: {other:?}"),
+            };
+            assert_eq!(fragments.len(), 2);
+
+            let psh: Vec<bool> = fragments
+                .iter()
+                .map(|f| Packet::from_bytes(f.as_bytes(), Direction::Outbound).unwrap())
+                .map(|f| f.tcp_flags.unwrap().psh)
+                .collect();
+            assert_eq!(psh, vec![false, true], "reverse_order = {reverse_order}");
+
+            // First-by-seq fragment is sent last when reversed
+            let seqs: Vec<u32> = fragments.iter().map(|f| f.tcp_seq().unwrap()).collect();
+            if reverse_order {
+                assert_eq!(seqs, vec![5, 1]);
+            } else {
+                assert_eq!(seqs, vec![1, 5]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_apply_additional_port() {
+        use crate::config::PerformanceConfig;
+
+        let strategy = FragmentationStrategy::new();
+        let packet = create_mock_packet(8080);
+
+        assert!(!strategy.should_apply(&packet, &Context::new()));
+
+        let ctx = Context::new().with_ports(&PerformanceConfig {
+            additional_ports: vec![8080],
+            ..PerformanceConfig::default()
+        });
+        assert!(strategy.should_apply(&packet, &ctx));
+        assert!(!strategy.should_apply(&create_mock_packet(8081), &ctx));
+    }
+
+    #[test]
+    fn test_port_overrides() {
+        let http_only = PortStrategyConfig {
+            use_http_strategies: true,
+            use_https_strategies: false,
+            custom_fragment_size: Some(3),
+        };
+        let https_only = PortStrategyConfig {
+            use_http_strategies: false,
+            ..PortStrategyConfig::default()
+        };
+        let strategy = FragmentationStrategy::new()
+            .with_port_overrides(HashMap::from([(8080, http_only), (8443, https_only)]));
+        let mut ctx = Context::new();
+
+        // An HTTP request only counts on a port routed to the HTTP strategies
+        assert!(!strategy.should_apply(&create_mock_packet(8443), &ctx));
+        let packet = create_mock_packet(8080);
+        assert!(strategy.should_apply(&packet, &ctx));
+
+        let fragments = match strategy.apply(packet, &mut ctx).unwrap() {
+            StrategyAction::Replace(fragments) => fragments,
+            other => panic!("unexpected action: {other:?}"),
+        };
+        // Reversed: the second fragment starts after the custom size
+        assert_eq!(fragments[1].payload(), b"GET");
+    }
+
+    #[test]
+    fn test_http_persistent() {
+        let mut ctx = Context::new();
+        let first_only = FragmentationStrategy::from_config(&FragmentationConfig {
+            http_persistent: false,
+            ..FragmentationConfig::default()
+        });
+
+        let request = create_mock_packet(80);
+        assert!(first_only.should_apply(&request, &ctx));
+        let action = first_only.apply(request, &mut ctx).unwrap();
+        assert!(matches!(action, StrategyAction::Replace(_)));
+
+        // The next request on the keep-alive connection goes out whole
+        assert!(!first_only.should_apply(&create_mock_packet(80), &ctx));
+        assert!(FragmentationStrategy::new().should_apply(&create_mock_packet(80), &ctx));
+    }
+
+        fn create_mock_packet(dst_port: u16) -> Packet {
         // Minimal TCP packet for testing
         let mut data = vec![
             // IPv4 header (20 bytes)
@@ -7,6 +7,7 @@ use crate::config::HeaderMangleConfig;
 use crate::error::Result;
 use crate::packet::Packet;
 use crate::pipeline::Context;
+use std::sync::atomic::Ordering;
 use tracing::{debug, instrument};
 
 /// Header manipulation strategy
@@ -112,32 +113,19 @@ impl Strategy for HeaderMangleStrategy {
         50
     }
 
-    fn should_apply(&self, packet: &Packet, _ctx: &Context) -> bool {
+    fn should_apply(&self, packet: &Packet, ctx: &Context) -> bool {
         // Only apply to outbound HTTP requests
         packet.is_outbound() 
             && packet.is_tcp() 
-            && packet.dst_port == 80
+            && ctx.is_http_port(packet.dst_port)
             && packet.is_http_request()
     }
 
     #[instrument(skip(self, ctx), fields(strategy = self.name()))]
-    fn apply(&self, mut packet: Packet, ctx: &mut Context) -> Result<StrategyAction> {
+    fn apply(&self, packet: Packet, ctx: &mut Context) -> Result<StrategyAction> {
         let mut modified = false;
-
-        // Get mutable access to packet payload
-        // Note: In real implementation, we need proper packet reconstruction
-        let data = packet.as_bytes_mut();
-        
-        // Calculate payload offset
-        let ip_header_len = ((data[0] & 0x0F) * 4) as usize;
-        let tcp_header_len = ((data[ip_header_len + 12] >> 4) * 4) as usize;
-        let payload_start = ip_header_len + tcp_header_len;
-
-        if payload_start >= data.len() {
-            return Ok(StrategyAction::Pass(packet));
-        }
-
-        let payload = &mut data[payload_start..];
+        let mut payload = packet.payload().to_vec();
+        let payload = payload.as_mut_slice();
 
         // Replace "Host:" with "hoSt:"
         if self.host_replace {
@@ -168,11 +156,12 @@ impl Strategy for HeaderMangleStrategy {
             }
         }
 
-        if modified {
-            ctx.stats.headers_modified += 1;
+        if !modified {
+            return Ok(StrategyAction::Pass(packet));
         }
 
-        Ok(StrategyAction::Pass(packet))
+        ctx.stats.headers_modified.fetch_add(1, Ordering::Relaxed);
+        Ok(StrategyAction::Pass(packet.with_new_payload(payload)?))
     }
 }
 
@@ -205,6 +194,31 @@ mod tests {
         assert_eq!(&hostname, b"eXaMpLe.cOm");
     }
 
+    #[test]
+    fn test_apply_rebuilds_packet() {
+        use crate::packet::{Direction, PacketBuilder};
+
+        let data = PacketBuilder::tcp_v4()
+            .src_ip_v4([192, 168, 1, 10])
+            .dst_ip_v4([93, 184, 216, 34])
+            .ports(50000, 80)
+            .payload(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n")
+            .build_bytes();
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+        let mut ctx = Context::new();
+
+        let StrategyAction::Pass(mangled) = HeaderMangleStrategy::new().apply(packet, &mut ctx).unwrap() else {
+            panic!("expected the packet to pass");
+        };
+        assert_eq!(mangled.payload(), b"GET / HTTP/1.1\r\nhoSt: example.com\r\n\r\n");
+        assert_eq!(mangled.len(), data.len());
+        // Checksums match the new payload
+        let mut fixed = mangled.clone();
+        fixed.fix_checksums();
+        assert_eq!(fixed.as_bytes(), mangled.as_bytes());
+        assert_eq!(ctx.stats.headers_modified.load(Ordering::Relaxed), 1);
+    }
+
     #[test]
     fn test_find_method_end() {
         let strategy = HeaderMangleStrategy::new();
@@ -4,23 +4,34 @@
 //! Each strategy implements the [`Strategy`] trait and can be composed
 //! into a processing pipeline.
 
+mod disorder;
 mod fake_packet;
 mod fragment;
 mod header_mangle;
+mod passive_dpi;
 mod quic_block;
 mod dns_redirect;
+#[cfg(feature = "doh")]
+pub mod doh;
 
+pub use disorder::DisorderStrategy;
 pub use fake_packet::FakePacketStrategy;
 pub use fragment::FragmentationStrategy;
 pub use header_mangle::HeaderMangleStrategy;
+pub use passive_dpi::PassiveDpiStrategy;
 pub use quic_block::QuicBlockStrategy;
 pub use dns_redirect::DnsRedirectStrategy;
+#[cfg(feature = "doh")]
+pub use doh::DohResolver;
 
 use crate::config::Config;
 use crate::error::Result;
+use crate::filter::{DomainFilter, FilterMode};
 use crate::packet::Packet;
 use crate::pipeline::Context;
+use std::net::IpAddr;
 use std::sync::Arc;
+use tracing::warn;
 
 /// Action to take after strategy processing
 #[derive(Debug, Clone)]
@@ -34,6 +45,8 @@ pub enum StrategyAction {
     /// Inject additional packets before the original
     InjectBefore(Vec<Packet>, Packet),
     /// Inject additional packets after the original
+    ///
+    /// The pipeline still sends decoys (`is_fake`) ahead of real packets.
     InjectAfter(Packet, Vec<Packet>),
 }
 
@@ -66,10 +79,35 @@ pub trait Strategy: Send + Sync {
     }
 }
 
+/// A strategy set and the filter choosing the hostnames it is used for
+pub type DomainStrategies = (DomainFilter, Vec<Box<dyn Strategy>>);
+
 /// Builder for creating strategies from configuration
 pub struct StrategyBuilder;
 
 impl StrategyBuilder {
+    /// Build the strategies of each `[[overrides]]` entry with a filter for its domains
+    ///
+    /// Entries that fail to merge are logged and left out;
+    /// [`Config::validate`] reports them.
+    pub fn overrides_from_config(config: &Config) -> Vec<DomainStrategies> {
+        config
+            .overrides
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| match entry.apply_to(config, &format!("overrides[{i}]")) {
+                Ok(merged) => {
+                    let filter = DomainFilter::with_domains(FilterMode::Blacklist, entry.domains.clone());
+                    Some((filter, Self::from_config(&merged)))
+                }
+                Err(e) => {
+                    warn!(error = %e, "Skipping strategy override");
+                    None
+                }
+            })
+            .collect()
+    }
+
     /// Create all enabled strategies from configuration
     pub fn from_config(config: &Config) -> Vec<Box<dyn Strategy>> {
         let mut strategies: Vec<Box<dyn Strategy>> = Vec::new();
@@ -90,27 +128,55 @@ impl StrategyBuilder {
             ));
         }
 
+        // Out-of-order ClientHello segments (before fragmentation)
+        if config.strategies.disorder.enabled {
+            strategies.push(Box::new(
+                DisorderStrategy::from_config(&config.strategies.disorder)
+            ));
+        }
+
         // Fragmentation (runs after header modification)
         if config.strategies.fragmentation.enabled {
             strategies.push(Box::new(
                 FragmentationStrategy::from_config(&config.strategies.fragmentation)
+                    .with_port_overrides(config.port_overrides())
+            ));
+        }
+
+        // Forged resets from a passive DPI
+        if config.strategies.passive_dpi.enabled {
+            strategies.push(Box::new(
+                PassiveDpiStrategy::from_config(&config.strategies.passive_dpi)
             ));
         }
 
         // QUIC blocking
         if config.strategies.quic_block.enabled {
-            strategies.push(Box::new(QuicBlockStrategy::new()));
+            strategies.push(Box::new(
+                QuicBlockStrategy::from_config(&config.strategies.quic_block)
+            ));
         }
 
-        // DNS redirection
+        // DNS redirection (one strategy per address family)
         if config.dns.enabled {
-            if let Some(upstream) = config.dns.ipv4_upstream {
-                strategies.push(Box::new(
-                    DnsRedirectStrategy::new(
-                        upstream,
-                        config.dns.ipv4_port.unwrap_or(53),
-                    )
-                ));
+            let upstreams = [
+                config.dns.ipv4_upstream.map(|ip| (IpAddr::V4(ip), config.dns.ipv4_port)),
+                config.dns.ipv6_upstream.map(|ip| (IpAddr::V6(ip), config.dns.ipv6_port)),
+            ];
+            #[cfg(feature = "doh")]
+            let doh = Self::doh_resolver(config);
+            #[cfg(not(feature = "doh"))]
+            if config.dns.doh_url.is_some() {
+                warn!("Ignoring dns.doh_url, built without the doh feature");
+            }
+            for (upstream, port) in upstreams.into_iter().flatten() {
+                let strategy = DnsRedirectStrategy::new(upstream, port.unwrap_or(53));
+                #[cfg(feature = "doh")]
+                let strategy = match &doh {
+                    Some(resolver) => strategy.with_doh(Arc::clone(resolver)),
+                    None => strategy,
+                };
+                strategies.push(Box::new(strategy));
             }
         }
 
@@ -119,6 +185,19 @@ impl StrategyBuilder {
 
         strategies
     }
+
+    /// Resolver for `dns.doh_url`, if it is set and the client starts
+    #[cfg(feature = "doh")]
+    fn doh_resolver(config: &Config) -> Option<Arc<DohResolver>> {
+        let url = config.dns.doh_url.as_deref()?;
+        match DohResolver::new(url) {
+            Ok(resolver) => Some(Arc::new(resolver)),
+            Err(e) => {
+                warn!(url, "DNS over HTTPS unavailable, redirecting queries instead: {}", e);
+                None
+            }
+        }
+    }
 }
 
 #[cfg(test)]
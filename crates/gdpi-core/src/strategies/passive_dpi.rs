@@ -0,0 +1,121 @@
+//! Passive DPI counter-measure
+//!
+//! Passive DPI boxes can't drop packets, so they tear connections down by
+//! injecting TCP resets. Their forged RSTs carry a recognizable IP ID;
+//! dropping inbound RSTs with that ID keeps the connection alive.
+
+use super::{Strategy, StrategyAction};
+use crate::config::PassiveDpiConfig;
+use crate::error::Result;
+use crate::packet::Packet;
+use crate::pipeline::Context;
+use std::ops::RangeInclusive;
+use tracing::{debug, instrument};
+
+/// IP IDs matched when none are configured, as in the original GoodbyeDPI
+const DEFAULT_IP_IDS: RangeInclusive<u16> = 0x0000..=0x000F;
+
+/// Drops inbound TCP resets forged by a passive DPI
+pub struct PassiveDpiStrategy {
+    /// IP IDs of forged resets (empty = [`DEFAULT_IP_IDS`])
+    ip_ids: Vec<u16>,
+}
+
+impl PassiveDpiStrategy {
+    /// Drop resets whose IP ID is one of `ip_ids`
+    pub fn new(ip_ids: Vec<u16>) -> Self {
+        Self { ip_ids }
+    }
+
+    /// Create from configuration
+    pub fn from_config(config: &PassiveDpiConfig) -> Self {
+        Self::new(config.ip_ids.clone())
+    }
+
+    /// Whether `id` is a DPI fingerprint
+    fn is_forged_id(&self, id: u16) -> bool {
+        if self.ip_ids.is_empty() {
+            DEFAULT_IP_IDS.contains(&id)
+        } else {
+            self.ip_ids.contains(&id)
+        }
+    }
+}
+
+impl Default for PassiveDpiStrategy {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl Strategy for PassiveDpiStrategy {
+    fn name(&self) -> &'static str {
+        "passive_dpi"
+    }
+
+    fn priority(&self) -> u8 {
+        // Drop the reset before anything else looks at it
+        1
+    }
+
+    fn should_apply(&self, packet: &Packet, _ctx: &Context) -> bool {
+        packet.is_inbound()
+            && packet.is_tcp()
+            && packet.is_rst()
+            && packet.ip_id.is_some_and(|id| self.is_forged_id(id))
+    }
+
+    #[instrument(skip(self, _ctx), fields(strategy = self.name()))]
+    fn apply(&self, packet: Packet, _ctx: &mut Context) -> Result<StrategyAction> {
+        debug!(
+            src = %packet.src_addr,
+            ip_id = packet.ip_id,
+            "Dropping forged TCP reset"
+        );
+        Ok(StrategyAction::Drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{Direction, PacketBuilder, TcpFlags};
+
+    fn reset(ip_id: u16, direction: Direction) -> Packet {
+        let data = PacketBuilder::tcp_v4()
+            .src_ip_v4([93, 184, 216, 34])
+            .dst_ip_v4([192, 168, 1, 100])
+            .src_port(443)
+            .dst_port(50000)
+            .ip_id(ip_id)
+            .flags(TcpFlags { rst: true, ack: true, ..Default::default() })
+            .build_bytes();
+        Packet::from_bytes(&data, direction).unwrap()
+    }
+
+    #[test]
+    fn test_drops_reset_with_matching_ip_id() {
+        let strategy = PassiveDpiStrategy::from_config(&PassiveDpiConfig {
+            enabled: true,
+            ip_ids: vec![0x1234],
+        });
+        let mut ctx = Context::new();
+
+        let forged = reset(0x1234, Direction::Inbound);
+        assert!(strategy.should_apply(&forged, &ctx));
+        assert!(matches!(strategy.apply(forged, &mut ctx).unwrap(), StrategyAction::Drop));
+
+        // A real reset from the server, and our own outbound resets
+        assert!(!strategy.should_apply(&reset(0x4321, Direction::Inbound), &ctx));
+        assert!(!strategy.should_apply(&reset(0x1234, Direction::Outbound), &ctx));
+    }
+
+    #[test]
+    fn test_default_ip_ids() {
+        let strategy = PassiveDpiStrategy::default();
+        let ctx = Context::new();
+
+        assert!(strategy.should_apply(&reset(0x0001, Direction::Inbound), &ctx));
+        assert!(!strategy.should_apply(&reset(0x0010, Direction::Inbound), &ctx));
+    }
+}
@@ -4,9 +4,12 @@
 //! which can then be processed by other DPI bypass strategies.
 
 use super::{Strategy, StrategyAction};
+use crate::config::QuicBlockConfig;
 use crate::error::Result;
+use crate::packet::quic::{QUIC_V1, QUIC_V2};
 use crate::packet::Packet;
 use crate::pipeline::Context;
+use std::sync::atomic::Ordering;
 use tracing::{debug, instrument};
 
 /// QUIC blocking strategy
@@ -14,9 +17,18 @@ use tracing::{debug, instrument};
 /// QUIC uses UDP on port 443 and is fully encrypted, making it impossible
 /// to manipulate. By blocking QUIC, we force browsers to fall back to
 /// HTTP/2 over TCP, which we can then process.
+///
+/// Only QUIC Initial packets are dropped; other UDP 443 traffic (DTLS,
+/// WireGuard on 443, ...) passes. In selective mode the Initial packet is
+/// decrypted to read the SNI, and only QUIC to blacklisted domains is
+/// blocked. `drop_all` restores the old behavior of dropping all UDP 443.
 pub struct QuicBlockStrategy {
     /// Minimum payload size for QUIC detection
     min_payload_size: usize,
+    /// Only block QUIC to blacklisted domains
+    selective: bool,
+    /// Drop all outbound UDP 443 without inspecting it
+    drop_all: bool,
 }
 
 impl QuicBlockStrategy {
@@ -24,6 +36,17 @@ impl QuicBlockStrategy {
     pub fn new() -> Self {
         Self {
             min_payload_size: 1200,
+            selective: false,
+            drop_all: false,
+        }
+    }
+
+    /// Create from configuration
+    pub fn from_config(config: &QuicBlockConfig) -> Self {
+        Self {
+            selective: config.selective,
+            drop_all: config.drop_all,
+            ..Self::new()
         }
     }
 
@@ -37,23 +60,21 @@ impl QuicBlockStrategy {
         }
 
         // Check QUIC header format
-        // First byte: form bit (1) + fixed bit (1) + packet type
-        // For Initial packets: 0b11xxxxxx (0xC0 or higher)
-        if payload[0] < 0xC0 {
+        // First byte: form bit (1) + fixed bit (1) + packet type (2)
+        if payload[0] & 0xC0 != 0xC0 {
             return false;
         }
+        let packet_type = (payload[0] >> 4) & 0x03;
 
-        // Check version field at bytes 1-4
-        // QUIC version 1 (RFC 9000): 0x00000001
-        if payload.len() >= 5 {
-            let version = u32::from_be_bytes([payload[1], payload[2], payload[3], payload[4]]);
-            // Version 1 or version negotiation (0)
-            if version == 1 || version == 0 {
-                return true;
-            }
+        // Check version field at bytes 1-4; the Initial type depends on it
+        let version = u32::from_be_bytes([payload[1], payload[2], payload[3], payload[4]]);
+        match version {
+            // QUIC v1 (RFC 9000) and drafts (0xff0000xx)
+            QUIC_V1 | 0xff00_0000..=0xff00_00ff => packet_type == 0b00,
+            // QUIC v2 (RFC 9369)
+            QUIC_V2 => packet_type == 0b01,
+            _ => false,
         }
-
-        false
     }
 }
 
@@ -73,18 +94,39 @@ impl Strategy for QuicBlockStrategy {
         5
     }
 
-    fn should_apply(&self, packet: &Packet, _ctx: &Context) -> bool {
-        // Only apply to outbound UDP on port 443
-        packet.is_outbound() 
-            && packet.is_udp() 
-            && packet.dst_port == 443
-            && packet.payload_len() >= self.min_payload_size
+    fn should_apply(&self, packet: &Packet, ctx: &Context) -> bool {
+        // Only apply to outbound UDP on HTTPS ports
+        if !(packet.is_outbound() && packet.is_udp() && ctx.is_https_port(packet.dst_port)) {
+            return false;
+        }
+
+        if self.drop_all {
+            return true;
+        }
+
+        if !self.is_quic_initial(packet) {
+            return false;
+        }
+
+        // Selective mode: only block QUIC to blacklisted domains
+        if self.selective && ctx.blacklist_enabled {
+            if ctx.filter().lists_ip(packet.dst_addr) {
+                return ctx.should_apply_bypass_to(packet, None);
+            }
+            return match packet.extract_quic_sni() {
+                Some(sni) => ctx.should_apply_bypass(&sni),
+                // Can't tell where it's going, let it through
+                None => false,
+            };
+        }
+
+        true
     }
 
     #[instrument(skip(self, ctx), fields(strategy = self.name()))]
     fn apply(&self, packet: Packet, ctx: &mut Context) -> Result<StrategyAction> {
-        if self.is_quic_initial(&packet) {
-            ctx.stats.quic_blocked += 1;
+        if self.drop_all || self.is_quic_initial(&packet) {
+            ctx.stats.quic_blocked.fetch_add(1, Ordering::Relaxed);
             debug!(
                 dst = %packet.dst_addr,
                 payload_len = packet.payload_len(),
@@ -101,7 +143,9 @@ impl Strategy for QuicBlockStrategy {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::packet::Direction;
+    use crate::packet::quic::tests as quic_tests;
+    use crate::packet::PacketBuilder;
+    use std::net::Ipv4Addr;
 
     #[test]
     fn test_quic_detection() {
@@ -130,4 +174,112 @@ mod tests {
         // This test validates the detection logic
         assert!(quic_payload[0] >= 0xC0); // QUIC long header
     }
+
+    /// Wrap a QUIC payload in an outbound IPv4/UDP packet to port 443
+    fn udp_443(payload: &[u8]) -> Packet {
+        PacketBuilder::udp_ipv4(Ipv4Addr::new(192, 168, 1, 1), Ipv4Addr::new(142, 250, 1, 1))
+            .src_port(50000)
+            .dst_port(443)
+            .ip_id(1)
+            .payload(payload)
+            .build()
+            .unwrap()
+    }
+
+    fn initial_for(sni: &str) -> Packet {
+        let frames = quic_tests::crypto_frame(0, &quic_tests::client_hello(sni));
+        udp_443(&quic_tests::protect_initial(crate::packet::quic::QUIC_V1, &[0x5a; 8], &frames))
+    }
+
+    #[test]
+    fn test_blunt_mode_ignores_blacklist() {
+        let strategy = QuicBlockStrategy::new();
+        let ctx = Context::with_blacklist(vec!["youtube.com".to_string()]);
+
+        assert!(strategy.should_apply(&initial_for("example.com"), &ctx));
+        assert!(strategy.should_apply(&initial_for("youtube.com"), &ctx));
+    }
+
+    #[test]
+    fn test_selective_mode_blocks_only_blacklisted() {
+        let strategy = QuicBlockStrategy::from_config(&QuicBlockConfig {
+            selective: true,
+            ..QuicBlockConfig::default()
+        });
+        let ctx = Context::with_blacklist(vec!["youtube.com".to_string()]);
+
+        assert!(strategy.should_apply(&initial_for("youtube.com"), &ctx));
+        assert!(!strategy.should_apply(&initial_for("example.com"), &ctx));
+
+        // Undecodable Initials are let through
+        let mut garbage = vec![0xC0, 0x00, 0x00, 0x00, 0x01];
+        garbage.resize(1200, 0);
+        assert!(!strategy.should_apply(&udp_443(&garbage), &ctx));
+    }
+
+    #[test]
+    fn test_selective_mode_without_blacklist_blocks_all() {
+        let strategy = QuicBlockStrategy::from_config(&QuicBlockConfig {
+            selective: true,
+            ..QuicBlockConfig::default()
+        });
+        let ctx = Context::new();
+
+        assert!(strategy.should_apply(&initial_for("example.com"), &ctx));
+    }
+
+    /// DTLS 1.2 ClientHello record (as used by WebRTC/VPNs on UDP 443)
+    fn dtls_datagram() -> Packet {
+        let mut payload = vec![0x16, 0xFE, 0xFD, 0x00, 0x00];
+        payload.resize(1200, 0x00);
+        udp_443(&payload)
+    }
+
+    #[test]
+    fn test_drops_quic_initial_passes_dtls() {
+        let strategy = QuicBlockStrategy::new();
+        let mut ctx = Context::new();
+
+        let initial = initial_for("example.com");
+        assert!(strategy.should_apply(&initial, &ctx));
+        assert!(matches!(
+            strategy.apply(initial, &mut ctx).unwrap(),
+            StrategyAction::Drop
+        ));
+
+        let dtls = dtls_datagram();
+        assert!(!strategy.should_apply(&dtls, &ctx));
+        assert!(matches!(
+            strategy.apply(dtls, &mut ctx).unwrap(),
+            StrategyAction::Pass(_)
+        ));
+        assert_eq!(ctx.get_stats().quic_blocked, 1);
+    }
+
+    #[test]
+    fn test_ignores_non_initial_long_header() {
+        let strategy = QuicBlockStrategy::new();
+        let ctx = Context::new();
+
+        // Handshake packet (type 0b10) for QUIC v1
+        let mut payload = vec![0xE0, 0x00, 0x00, 0x00, 0x01];
+        payload.resize(1200, 0x00);
+        assert!(!strategy.should_apply(&udp_443(&payload), &ctx));
+    }
+
+    #[test]
+    fn test_drop_all_drops_any_udp_443() {
+        let strategy = QuicBlockStrategy::from_config(&QuicBlockConfig {
+            drop_all: true,
+            ..QuicBlockConfig::default()
+        });
+        let mut ctx = Context::new();
+
+        let dtls = dtls_datagram();
+        assert!(strategy.should_apply(&dtls, &ctx));
+        assert!(matches!(
+            strategy.apply(dtls, &mut ctx).unwrap(),
+            StrategyAction::Drop
+        ));
+    }
 }
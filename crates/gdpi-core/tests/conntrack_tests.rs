@@ -1,6 +1,10 @@
 //! Integration tests for connection tracking
 
+use gdpi_core::config::{AutoTtlConfig, FakePacketConfig};
 use gdpi_core::conntrack::{DnsConnTracker, TcpConnTracker};
+use gdpi_core::packet::{ClientHelloBuilder, Direction, Packet, PacketBuilder, TcpFlags};
+use gdpi_core::pipeline::{Context, Pipeline};
+use gdpi_core::strategies::FakePacketStrategy;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::time::Duration;
 
@@ -93,6 +97,122 @@ fn test_tcp_tracker_cleanup() {
     assert_eq!(tracker.len(), 0);
 }
 
+#[test]
+fn test_pipeline_auto_ttl_from_syn_ack() {
+    let mut pipeline = Pipeline::new();
+    pipeline.add_strategy(FakePacketStrategy::from_config(&FakePacketConfig {
+        enabled: true,
+        auto_ttl: Some(AutoTtlConfig::default()),
+        ..FakePacketConfig::default()
+    }));
+    let mut ctx = Context::new();
+
+    let server = [93, 184, 216, 34];
+    let client = [192, 168, 1, 100];
+
+    // SYN-ACK from the server, 10 hops away from an initial TTL of 64
+    let syn_ack = PacketBuilder::tcp_v4()
+        .src_ip_v4(server)
+        .dst_ip_v4(client)
+        .src_port(443)
+        .dst_port(50000)
+        .ttl(54)
+        .flags(TcpFlags { syn: true, ack: true, ..Default::default() })
+        .build_bytes();
+    let syn_ack = Packet::from_bytes(&syn_ack, Direction::Inbound).unwrap();
+    assert_eq!(pipeline.process(syn_ack, &mut ctx).unwrap().len(), 1);
+
+    let hello = PacketBuilder::tcp_v4()
+        .src_ip_v4(client)
+        .dst_ip_v4(server)
+        .src_port(50000)
+        .dst_port(443)
+        .flags(TcpFlags { psh: true, ack: true, ..Default::default() })
+        .payload(&ClientHelloBuilder::new("example.com").build())
+        .build_bytes();
+    let hello = Packet::from_bytes(&hello, Direction::Outbound).unwrap();
+    assert_eq!(ctx.get_connection_ttl(&hello), Some(54));
+
+    let output = pipeline.process(hello, &mut ctx).unwrap();
+    let fake = output.iter().find(|p| p.is_fake).expect("fake packet injected");
+
+    // 10 hops minus the default a2 of 4, instead of the fallback TTL 8
+    assert_eq!(fake.ttl, 6);
+}
+
+#[test]
+fn test_pipeline_forgets_closed_connection() {
+    let pipeline = Pipeline::new();
+    let mut ctx = Context::new();
+
+    let server = [93, 184, 216, 34];
+    let client = [192, 168, 1, 100];
+
+    let syn_ack = PacketBuilder::tcp_v4()
+        .src_ip_v4(server)
+        .dst_ip_v4(client)
+        .src_port(443)
+        .dst_port(50000)
+        .ttl(54)
+        .flags(TcpFlags { syn: true, ack: true, ..Default::default() })
+        .build_bytes();
+    let syn_ack = Packet::from_bytes(&syn_ack, Direction::Inbound).unwrap();
+    pipeline.process(syn_ack, &mut ctx).unwrap();
+
+    let fin = PacketBuilder::tcp_v4()
+        .src_ip_v4(client)
+        .dst_ip_v4(server)
+        .src_port(50000)
+        .dst_port(443)
+        .flags(TcpFlags { fin: true, ack: true, ..Default::default() })
+        .build_bytes();
+    let fin = Packet::from_bytes(&fin, Direction::Outbound).unwrap();
+    assert_eq!(ctx.get_connection_ttl(&fin), Some(54));
+
+    pipeline.process(fin.clone(), &mut ctx).unwrap();
+    assert_eq!(ctx.get_connection_ttl(&fin), None);
+}
+
+#[test]
+fn test_tcp_tracker_from_config() {
+    use gdpi_core::config::PerformanceConfig;
+
+    let tracker = TcpConnTracker::from_config(&PerformanceConfig {
+        conntrack_max_entries: 3,
+        ..PerformanceConfig::default()
+    });
+
+    let server = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+    let client = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    for port in 10000..10010 {
+        tracker.record(server, 443, client, port, 64);
+    }
+
+    assert_eq!(tracker.len(), 3);
+}
+
+#[test]
+fn test_tcp_tracker_lru_cap() {
+    let max = 100;
+    let tracker = TcpConnTracker::new().with_max_entries(max);
+
+    let server = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+    let client = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+    for port in 0..(max + 100) as u16 {
+        tracker.record(server, 443, client, 10000 + port, 64);
+        // Keep the first connection hot
+        assert_eq!(tracker.get_ttl(server, 443, client, 10000), Some(64));
+        assert!(tracker.len() <= max);
+    }
+
+    assert_eq!(tracker.len(), max);
+    assert_eq!(tracker.get_ttl(server, 443, client, 10000), Some(64));
+    assert_eq!(tracker.get_ttl(server, 443, client, 10001), None);
+    assert_eq!(tracker.get_ttl(server, 443, client, 10000 + max as u16 + 99), Some(64));
+    assert_eq!(tracker.eviction_count(), 100);
+}
+
 // ============ DNS Connection Tracker Tests ============
 
 #[test]
@@ -171,3 +291,22 @@ fn test_dns_tracker_high_volume() {
     tracker.clear();
     assert!(tracker.is_empty());
 }
+
+#[test]
+fn test_dns_tracker_lru_cap() {
+    let max = 100;
+    let tracker = DnsConnTracker::new().with_max_entries(max);
+    let dns = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+
+    for port in 0..(max + 100) as u16 {
+        tracker.track_query(20000 + port, dns, 53);
+        // Keep the first query hot
+        assert_eq!(tracker.get_original(20000), Some((dns, 53)));
+        assert!(tracker.len() <= max);
+    }
+
+    assert_eq!(tracker.len(), max);
+    assert_eq!(tracker.get_original(20000), Some((dns, 53)));
+    assert_eq!(tracker.get_original(20001), None);
+    assert_eq!(tracker.get_original(20000 + max as u16 + 99), Some((dns, 53)));
+}
@@ -147,6 +147,16 @@ fn test_extract_http_host() {
     assert_eq!(host.unwrap(), "example.com");
 }
 
+#[test]
+fn test_extract_http_method_and_path() {
+    let data = create_http_get_packet();
+    let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+
+    let (method, path) = packet.extract_http_method_and_path().unwrap();
+    assert_eq!(method, "GET");
+    assert_eq!(path, "/");
+}
+
 #[test]
 fn test_extract_sni() {
     let data = create_tls_client_hello_packet();
@@ -166,7 +176,7 @@ fn test_packet_builder() {
         .dst_port(443)
         .ttl(128)
         .flags(TcpFlags { syn: true, ..Default::default() })
-        .build();
+        .build_bytes();
 
     // Verify IPv4
     assert_eq!(packet[0] >> 4, 4);
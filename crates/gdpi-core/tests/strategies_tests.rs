@@ -6,7 +6,35 @@ use gdpi_core::config::*;
 use gdpi_core::strategies::*;
 
 mod test_helpers {
-    use gdpi_core::packet::{Direction, Packet};
+    use gdpi_core::packet::{Direction, PacketBuilder, TcpFlags};
+
+    /// Address of the local client in built segments
+    const CLIENT_IP: [u8; 4] = [192, 168, 1, 10];
+    /// Port of the local client in built segments
+    const CLIENT_PORT: u16 = 50000;
+
+    /// Builder for an outbound PSH/ACK segment from the client to
+    /// `dst`:`port` carrying `payload`
+    pub fn client_segment(dst: [u8; 4], port: u16, payload: &[u8]) -> PacketBuilder {
+        PacketBuilder::tcp_v4()
+            .src_ip_v4(CLIENT_IP)
+            .dst_ip_v4(dst)
+            .src_port(CLIENT_PORT)
+            .dst_port(port)
+            .flags(TcpFlags { psh: true, ack: true, ..Default::default() })
+            .payload(payload)
+    }
+
+    /// Builder for an inbound segment from `src`:`port` to the client,
+    /// without flags or payload
+    pub fn server_segment(src: [u8; 4], port: u16) -> PacketBuilder {
+        PacketBuilder::tcp_v4()
+            .src_ip_v4(src)
+            .dst_ip_v4(CLIENT_IP)
+            .src_port(port)
+            .dst_port(CLIENT_PORT)
+            .direction(Direction::Inbound)
+    }
 
     /// Create a mock HTTP GET request packet
     pub fn create_http_get(host: &str) -> Vec<u8> {
@@ -124,6 +152,8 @@ fn test_fragmentation_config() {
         by_sni: false,
         http_persistent: true,
         persistent_nowait: true,
+        fragment_positions: Vec::new(),
+        duplicate_first_fragment: false,
     };
 
     assert!(config.enabled);
@@ -138,6 +168,8 @@ fn test_fake_packet_config() {
         enabled: true,
         wrong_checksum: true,
         wrong_seq: true,
+        seq_offset: 10000,
+        ack_offset: 0,
         ttl: Some(8),
         auto_ttl: None,
         min_ttl_hops: Some(3),
@@ -187,6 +219,8 @@ fn test_header_mangle_config() {
 fn test_quic_block_config() {
     let config = QuicBlockConfig {
         enabled: true,
+        selective: false,
+        drop_all: false,
     };
 
     assert!(config.enabled);
@@ -203,6 +237,57 @@ fn test_passive_dpi_config() {
     assert_eq!(config.ip_ids.len(), 2);
 }
 
+#[test]
+fn test_disorder_runs_before_fragmentation() {
+    let mut config = Config::default();
+    config.strategies.disorder.enabled = true;
+    config.strategies.fragmentation.enabled = true;
+
+    let names: Vec<_> = StrategyBuilder::from_config(&config)
+        .iter()
+        .map(|s| s.name())
+        .collect();
+    let disorder = names.iter().position(|&n| n == "disorder").unwrap();
+    let fragmentation = names.iter().position(|&n| n == "fragmentation").unwrap();
+    assert!(disorder < fragmentation);
+}
+
+#[test]
+fn test_pipeline_drops_forged_reset() {
+    use gdpi_core::packet::TcpFlags;
+    use gdpi_core::pipeline::{Context, Pipeline};
+
+    let mut config = Config::default();
+    config.strategies.passive_dpi.enabled = true;
+    config.strategies.passive_dpi.ip_ids = vec![0x0100];
+
+    let pipeline = Pipeline::new();
+    pipeline.reload_config(&config);
+    assert!(pipeline.strategy_names().contains(&"passive_dpi"));
+    let mut ctx = Context::new();
+
+    let inbound = |ip_id: u16, flags: TcpFlags| {
+        test_helpers::server_segment([93, 184, 216, 34], 443)
+            .ip_id(ip_id)
+            .flags(flags)
+            .build()
+            .unwrap()
+    };
+    let reset = |ip_id: u16| inbound(ip_id, TcpFlags { rst: true, ..Default::default() });
+
+    pipeline
+        .process(inbound(0x7a30, TcpFlags { syn: true, ack: true, ..Default::default() }), &mut ctx)
+        .unwrap();
+    assert_eq!(ctx.connection_count(), 1);
+
+    // The dropped reset leaves the flow tracked
+    assert!(pipeline.process(reset(0x0100), &mut ctx).unwrap().is_empty());
+    assert_eq!(ctx.connection_count(), 1);
+
+    assert_eq!(pipeline.process(reset(0x7a31), &mut ctx).unwrap().len(), 1);
+    assert_eq!(ctx.connection_count(), 0);
+}
+
 #[test]
 fn test_strategies_config_default() {
     let config = StrategiesConfig::default();
@@ -226,3 +311,431 @@ fn test_mode_specific_strategies() {
     assert!(mode9.strategies.fake_packet.enabled);
     assert!(mode9.strategies.quic_block.enabled);
 }
+
+#[test]
+fn test_additional_port_fragmented() {
+    use gdpi_core::packet::ClientHelloBuilder;
+    use gdpi_core::pipeline::{Context, Pipeline};
+
+    let packet = |dst_port: u16, payload: &[u8]| {
+        test_helpers::client_segment([93, 184, 216, 34], dst_port, payload).build().unwrap()
+    };
+    let hello = ClientHelloBuilder::new("example.com").build();
+    let request = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+
+    let mut config = Config::default();
+    config.strategies.fake_packet.enabled = false;
+    config.strategies.fragmentation.enabled = true;
+    let pipeline = Pipeline::new();
+    pipeline.reload_config(&config);
+
+    let mut ctx = Context::with_config(&config);
+    assert_eq!(pipeline.process(packet(8443, &hello), &mut ctx).unwrap().len(), 1);
+
+    config.performance.additional_ports = vec![8443];
+    pipeline.reload_config(&config);
+    let mut ctx = Context::with_config(&config);
+    assert_eq!(pipeline.process(packet(8443, &hello), &mut ctx).unwrap().len(), 2);
+
+    // Any port carries HTTP, but only the HTTPS ports carry TLS
+    config.performance.http_all_ports = true;
+    let mut ctx = Context::with_config(&config);
+    assert_eq!(pipeline.process(packet(8888, request), &mut ctx).unwrap().len(), 2);
+    assert_eq!(pipeline.process(packet(8888, &hello), &mut ctx).unwrap().len(), 1);
+}
+
+#[test]
+fn test_fakes_precede_fragments() {
+    use gdpi_core::packet::ClientHelloBuilder;
+    use gdpi_core::pipeline::{Context, Pipeline};
+
+    let hello = ClientHelloBuilder::new("example.com").build();
+    let packet = test_helpers::client_segment([93, 184, 216, 34], 443, &hello)
+        .seq(1000)
+        .build()
+        .unwrap();
+
+    let mut config = Config::default();
+    config.strategies.fake_packet.enabled = true;
+    config.strategies.fake_packet.ttl = Some(3);
+    config.strategies.fragmentation.enabled = true;
+    config.strategies.fragmentation.https_size = 2;
+    config.strategies.fragmentation.reverse_order = true;
+    let pipeline = Pipeline::new();
+    pipeline.reload_config(&config);
+
+    let output = pipeline.process(packet, &mut Context::with_config(&config)).unwrap();
+
+    let fakes = output.iter().take_while(|p| p.is_fake).count();
+    assert!(fakes > 0);
+    let real = &output[fakes..];
+    assert_eq!(real.len(), 2);
+    assert!(real.iter().all(|p| !p.is_fake));
+    // [fakes..., frag2, frag1]
+    assert_eq!(real[0].tcp_seq(), Some(1002));
+    assert_eq!(real[1].tcp_seq(), Some(1000));
+}
+
+#[test]
+fn test_max_payload_size_skips_large_packets() {
+    use gdpi_core::packet::ClientHelloBuilder;
+    use gdpi_core::pipeline::{Context, Pipeline};
+
+    let hello = |len: usize| {
+        let payload = ClientHelloBuilder::new("example.com").pad_to(len).build();
+        test_helpers::client_segment([93, 184, 216, 34], 443, &payload).build().unwrap()
+    };
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_strategy(FragmentationStrategy::new());
+    pipeline.set_max_payload_size(1200);
+    let mut ctx = Context::new();
+
+    let large = hello(1400);
+    assert_eq!(large.payload_len(), 1400);
+    let output = pipeline.process(large.clone(), &mut ctx).unwrap();
+    assert_eq!(output.len(), 1);
+    assert_eq!(output[0].as_bytes(), large.as_bytes());
+
+    let small = hello(500);
+    assert!(pipeline.process(small, &mut ctx).unwrap().len() > 1);
+}
+
+#[test]
+fn test_process_dry_traces_client_hello() {
+    use gdpi_core::packet::ClientHelloBuilder;
+    use gdpi_core::pipeline::{Context, Pipeline, TraceAction};
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_strategy(FakePacketStrategy::from_config(&FakePacketConfig {
+        enabled: true,
+        ttl: Some(3),
+        ..FakePacketConfig::default()
+    }));
+    pipeline.add_strategy(FragmentationStrategy::new());
+    let ctx = Context::new();
+
+    let payload = ClientHelloBuilder::new("example.com").build();
+    let hello = test_helpers::client_segment([93, 184, 216, 34], 443, &payload).build().unwrap();
+
+    let trace = pipeline.process_dry(hello, &ctx).unwrap();
+    let names: Vec<_> = trace.iter().map(|t| t.strategy).collect();
+    assert_eq!(names, ["fake_packet", "fragmentation"]);
+    assert!(matches!(trace[0].action, TraceAction::InjectBefore(n) if n > 0));
+    assert_eq!(trace[1].action, TraceAction::Replace);
+    assert_eq!(trace[1].packet_count(), 2);
+    assert!(trace[1].to_string().starts_with("fragmentation would split into 2"));
+}
+
+#[test]
+fn test_process_dry_leaves_context_unchanged() {
+    use gdpi_core::conntrack::ConnKey;
+    use gdpi_core::packet::ClientHelloBuilder;
+    use gdpi_core::pipeline::{Context, Pipeline};
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_strategy(FakePacketStrategy::from_config(&FakePacketConfig {
+        enabled: true,
+        ttl: Some(3),
+        ..FakePacketConfig::default()
+    }));
+    pipeline.add_strategy(FragmentationStrategy::new());
+    let mut ctx = Context::new();
+
+    let payload = ClientHelloBuilder::new("example.com").build();
+    let hello = test_helpers::client_segment([93, 184, 216, 34], 443, &payload).build().unwrap();
+    let flow = ConnKey::from_packet(&hello);
+
+    assert_eq!(pipeline.process_dry(hello.clone(), &ctx).unwrap().len(), 2);
+    let stats = ctx.get_stats();
+    assert_eq!(stats.packets_processed, 0);
+    assert_eq!(stats.packets_fragmented, 0);
+    assert_eq!(stats.bytes_modified, 0);
+    assert!(stats.per_strategy.is_empty());
+    assert_eq!(stats.tracked_connections, 0);
+    assert!(!ctx.was_bypassed(&flow));
+
+    // The real run still bypasses the ClientHello the dry run traced
+    assert!(pipeline.process(hello, &mut ctx).unwrap().len() > 1);
+    assert_eq!(ctx.get_stats().packets_processed, 1);
+    assert!(ctx.was_bypassed(&flow));
+}
+
+#[test]
+fn test_allow_no_sni_from_config() {
+    use gdpi_core::filter::DomainFilter;
+    use gdpi_core::pipeline::{Context, Pipeline};
+
+    // ClientHello without any extensions, so without SNI
+    let mut hello = vec![0x16, 0x03, 0x01, 0x00, 0x2f, 0x01, 0x00, 0x00, 0x2b, 0x03, 0x03];
+    hello.extend_from_slice(&[0x42; 32]);
+    hello.extend_from_slice(&[0x00, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+    let packet = test_helpers::client_segment([93, 184, 216, 34], 443, &hello).build().unwrap();
+    assert!(packet.is_tls_client_hello());
+    assert_eq!(packet.extract_sni(), None);
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_strategy(FragmentationStrategy::new());
+
+    for allow_no_sni in [false, true] {
+        let config = Config::from_toml(&format!(
+            "[blacklist]\nenabled = true\nmode = \"blacklist\"\ndomains = [\"blocked.com\"]\nallow_no_sni = {}\n",
+            allow_no_sni
+        ))
+        .unwrap();
+        let filter = DomainFilter::from_config(
+            config.blacklist.enabled,
+            &config.blacklist.mode,
+            config.blacklist.file_path.as_deref(),
+            &config.blacklist.domains,
+        )
+        .unwrap();
+        let mut ctx = Context::with_config(&config).with_domain_filter(filter);
+
+        let output = pipeline.process(packet.clone(), &mut ctx).unwrap();
+        assert_eq!(output.len() > 1, allow_no_sni, "allow_no_sni = {}", allow_no_sni);
+    }
+}
+
+#[test]
+fn test_whitelist_skips_listed_domains() {
+    use gdpi_core::filter::DomainFilter;
+    use gdpi_core::packet::{Direction, Packet};
+    use gdpi_core::pipeline::{Context, Pipeline};
+
+    let dir = tempfile::tempdir().unwrap();
+    let domains = dir.path().join("domains.txt");
+    std::fs::write(&domains, "# Government sites\n*.gov.tr\n").unwrap();
+
+    let config = Config::from_toml(&format!(
+        "[blacklist]\nenabled = true\nmode = \"whitelist\"\nfiles = [{:?}]\n",
+        domains.to_str().unwrap()
+    ))
+    .unwrap();
+    let filter = DomainFilter::from_blacklist_config(&config.blacklist).unwrap();
+    let mut ctx = Context::with_config(&config).with_domain_filter(filter);
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_strategy(FragmentationStrategy::new());
+
+    let process = |sni: &str, ctx: &mut Context| {
+        let data = test_helpers::create_tls_client_hello(sni);
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+        pipeline.process(packet, ctx).unwrap().len()
+    };
+
+    assert_eq!(process("www.turkiye.gov.tr", &mut ctx), 1);
+    assert!(process("twitter.com", &mut ctx) > 1);
+}
+
+#[test]
+fn test_replace_strategies_mid_stream() {
+    use gdpi_core::packet::{ClientHelloBuilder, TcpFlags};
+    use gdpi_core::pipeline::{Context, Pipeline};
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+
+    let packet = |src_port: u16, dst_port: u16, flags: TcpFlags, payload: &[u8]| {
+        test_helpers::client_segment([8, 8, 8, 8], dst_port, payload)
+            .src_port(src_port)
+            .flags(flags)
+            .build()
+            .unwrap()
+    };
+    let hello = ClientHelloBuilder::new("example.com").build();
+    let push = TcpFlags { psh: true, ack: true, ..Default::default() };
+    let syn = TcpFlags { syn: true, ..Default::default() };
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_strategy(FragmentationStrategy::new());
+    let pipeline = Arc::new(pipeline);
+    let mut ctx = Context::new();
+
+    assert!(pipeline.process(packet(50000, 443, push, &hello), &mut ctx).unwrap().len() > 1);
+
+    // Swapped through a shared handle, as the config watcher does
+    let shared = Arc::clone(&pipeline);
+    shared.replace_strategies(vec![Box::new(DnsRedirectStrategy::new(Ipv4Addr::new(1, 1, 1, 1), 53))]);
+    assert_eq!(pipeline.strategy_names(), ["dns_redirect"]);
+
+    // Later packets only see the new set
+    let output = pipeline.process(packet(50000, 443, push, &hello), &mut ctx).unwrap();
+    assert_eq!(output.len(), 1);
+    let output = pipeline.process(packet(50001, 53, syn, &[]), &mut ctx).unwrap();
+    assert_eq!(output[0].dst_addr, IpAddr::from([1, 1, 1, 1]));
+
+    // A new DNS target applies to the next flow
+    let mut config = Config::default();
+    config.dns.enabled = true;
+    config.dns.ipv4_upstream = Some(Ipv4Addr::new(9, 9, 9, 9));
+    pipeline.reload_config(&config);
+    let output = pipeline.process(packet(50002, 53, syn, &[]), &mut ctx).unwrap();
+    assert_eq!(output[0].dst_addr, IpAddr::from([9, 9, 9, 9]));
+}
+
+#[test]
+fn test_duplicate_hostname_fragment() {
+    use gdpi_core::packet::ClientHelloBuilder;
+    use gdpi_core::pipeline::{Context, Pipeline};
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_strategy(FakePacketStrategy::from_config(&FakePacketConfig {
+        ttl: Some(3),
+        ..FakePacketConfig::default()
+    }));
+    pipeline.add_strategy(FragmentationStrategy::from_config(&FragmentationConfig {
+        https_size: 2,
+        reverse_order: false,
+        duplicate_first_fragment: true,
+        ..FragmentationConfig::default()
+    }));
+    let mut ctx = Context::new();
+
+    let payload = ClientHelloBuilder::new("example.com").build();
+    let hello = test_helpers::client_segment([93, 184, 216, 34], 443, &payload)
+        .seq(1000)
+        .build()
+        .unwrap();
+
+    let output = pipeline.process(hello, &mut ctx).unwrap();
+    let fakes = output.iter().take_while(|p| p.is_fake).count();
+    assert!(fakes > 0);
+
+    // 2-byte fragment, then the fragment with the SNI twice
+    let real = &output[fakes..];
+    assert_eq!(real.len(), 3);
+    assert!(real.iter().all(|p| !p.is_fake));
+    assert_eq!(real[0].tcp_seq(), Some(1000));
+    assert_eq!(real[1].tcp_seq(), Some(1002));
+    assert_eq!(real[1].tcp_seq(), real[2].tcp_seq());
+    assert_eq!(real[1].as_bytes(), real[2].as_bytes());
+    assert_eq!(ctx.get_stats().fragments_duplicated, 1);
+}
+
+#[test]
+fn test_domain_override_skips_fragmentation() {
+    use gdpi_core::packet::{Direction, Packet};
+    use gdpi_core::pipeline::{Context, Pipeline};
+
+    let config = Config::from_toml(
+        r#"
+        [strategies.fragmentation]
+        enabled = true
+        https_size = 2
+
+        [[overrides]]
+        domains = ["*.discord.com"]
+        strategies = { fragmentation = { enabled = false } }
+        "#,
+    )
+    .unwrap();
+    config.validate().unwrap();
+
+    let pipeline = Pipeline::new();
+    pipeline.reload_config(&config);
+    assert_eq!(pipeline.override_count(), 1);
+
+    // Real segments sent, leaving out the fakes injected for both. The
+    // helper reuses one 4-tuple, so each hello gets a fresh context.
+    let process = |sni: &str| {
+        let data = test_helpers::create_tls_client_hello(sni);
+        let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+        let output = pipeline.process(packet, &mut Context::new()).unwrap();
+        output.iter().filter(|p| !p.is_fake).count()
+    };
+
+    assert_eq!(process("discord.com"), 1);
+    assert_eq!(process("cdn.discord.com"), 1);
+    assert!(process("youtube.com") > 1);
+}
+
+#[test]
+fn test_retransmitted_client_hello_bypassed_once() {
+    use gdpi_core::packet::ClientHelloBuilder;
+    use gdpi_core::pipeline::{Context, Pipeline};
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_strategy(FragmentationStrategy::new());
+    let mut ctx = Context::new();
+
+    let hello = ClientHelloBuilder::new("discord.com").build();
+    let packet = |src_port: u16| {
+        test_helpers::client_segment([162, 159, 128, 233], 443, &hello)
+            .src_port(src_port)
+            .seq(1000)
+            .build()
+            .unwrap()
+    };
+
+    assert!(pipeline.process(packet(50000), &mut ctx).unwrap().len() > 1);
+
+    // The retransmission (same SEQ) goes out as is
+    let output = pipeline.process(packet(50000), &mut ctx).unwrap();
+    assert_eq!(output.len(), 1);
+    assert_eq!(output[0].tcp_seq(), Some(1000));
+
+    // A new connection to the same host is bypassed again
+    assert!(pipeline.process(packet(50001), &mut ctx).unwrap().len() > 1);
+    assert_eq!(ctx.get_stats().packets_fragmented, 2);
+}
+
+#[test]
+fn test_later_ipv4_fragment_passes_untouched() {
+    use gdpi_core::packet::{ClientHelloBuilder, Direction, Packet};
+    use gdpi_core::pipeline::{Context, Pipeline};
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_strategy(FragmentationStrategy::new());
+    let mut ctx = Context::new();
+
+    // Data of a second fragment that happens to read as a TCP header and
+    // a ClientHello
+    let hello = ClientHelloBuilder::new("discord.com").build();
+    let mut data = test_helpers::client_segment([162, 159, 128, 233], 443, &hello).build_bytes();
+    data[6..8].copy_from_slice(&(1480u16 / 8).to_be_bytes());
+
+    let packet = Packet::from_bytes(&data, Direction::Outbound).unwrap();
+    assert!(!packet.is_tls_client_hello());
+
+    let output = pipeline.process(packet, &mut ctx).unwrap();
+    assert_eq!(output.len(), 1);
+    assert_eq!(output[0].as_bytes(), data.as_slice());
+    assert_eq!(ctx.get_stats().packets_fragmented, 0);
+}
+
+#[test]
+fn test_client_hello_split_before_sni() {
+    use gdpi_core::packet::ClientHelloBuilder;
+    use gdpi_core::pipeline::{Context, Pipeline};
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_strategy(FragmentationStrategy::from_config(&FragmentationConfig {
+        https_size: 2,
+        reverse_order: false,
+        ..FragmentationConfig::default()
+    }));
+    let mut ctx = Context::with_blacklist(vec!["discord.com".to_string()]);
+
+    let hello = ClientHelloBuilder::new("discord.com").build();
+    let (first, second) = hello.split_at(100);
+    let segment = |seq: u32, payload: &[u8]| {
+        test_helpers::client_segment([162, 159, 128, 233], 443, payload)
+            .seq(seq)
+            .build()
+            .unwrap()
+    };
+
+    // No hostname yet, so the blacklist can't match the first segment
+    let output = pipeline.process(segment(1000, first), &mut ctx).unwrap();
+    assert_eq!(output.len(), 1);
+    assert_eq!(output[0].payload(), first);
+
+    // The second segment completes the SNI and is split right before it
+    let output = pipeline.process(segment(1100, second), &mut ctx).unwrap();
+    assert_eq!(output.len(), 2);
+    let sni_offset = second.windows(11).position(|w| w == b"discord.com").unwrap();
+    assert_eq!(output[0].tcp_seq(), Some(1100));
+    assert_eq!(output[0].payload(), &second[..sni_offset - 1]);
+    assert_eq!(output[1].tcp_seq(), Some(1100 + sni_offset as u32 - 1));
+    assert_eq!(ctx.get_stats().packets_fragmented, 1);
+}
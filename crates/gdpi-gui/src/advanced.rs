@@ -0,0 +1,188 @@
+//! Advanced settings - strategy options saved as a bypass config file
+
+use eframe::egui;
+use gdpi_core::config::{AutoTtlConfig, Profile};
+use gdpi_core::Config;
+use std::net::IpAddr;
+use std::path::Path;
+use tracing::warn;
+
+const ERROR_COLOR: egui::Color32 = egui::Color32::from_rgb(244, 67, 54);
+
+/// Editor behind the Advanced settings tab
+pub struct AdvancedSettings {
+    config: Config,
+    /// DNS upstream as typed; empty turns DNS redirection off
+    dns_upstream: String,
+    /// DNS upstream port
+    dns_port: u16,
+    /// Problems found by the last save, as (config key, message)
+    issues: Vec<(String, String)>,
+}
+
+impl AdvancedSettings {
+    /// Edit the settings saved at `path`, or start from `profile`
+    pub fn load(path: &Path, profile: &str) -> Self {
+        let from_profile = || Config::from_profile(Profile::from_name(profile).unwrap_or(Profile::Turkey));
+        let config = if path.exists() {
+            Config::load_auto(path).unwrap_or_else(|e| {
+                warn!("Ignoring {}: {}", path.display(), e);
+                from_profile()
+            })
+        } else {
+            from_profile()
+        };
+
+        let dns = &config.dns;
+        let (dns_upstream, dns_port) = match (dns.ipv4_upstream, dns.ipv6_upstream) {
+            (Some(ip), _) if dns.enabled => (ip.to_string(), dns.ipv4_port),
+            (None, Some(ip)) if dns.enabled => (ip.to_string(), dns.ipv6_port),
+            _ => (String::new(), None),
+        };
+
+        Self {
+            config,
+            dns_upstream,
+            dns_port: dns_port.unwrap_or(53),
+            issues: Vec::new(),
+        }
+    }
+
+    /// Render the settings
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let strategies = &mut self.config.strategies;
+
+        ui.label(egui::RichText::new("Fragmentation").strong());
+        ui.checkbox(&mut strategies.fragmentation.enabled, "Split requests");
+        ui.add_enabled_ui(strategies.fragmentation.enabled, |ui| {
+            egui::Grid::new("fragment_sizes").num_columns(2).show(ui, |ui| {
+                ui.label("HTTP fragment size");
+                ui.add(egui::DragValue::new(&mut strategies.fragmentation.http_size).range(0..=1500));
+                ui.end_row();
+                ui.label("HTTPS fragment size");
+                ui.add(egui::DragValue::new(&mut strategies.fragmentation.https_size).range(0..=1500));
+                ui.end_row();
+            });
+        });
+        show_issues(ui, &self.issues, "strategies.fragmentation");
+
+        ui.add_space(8.0);
+        ui.label(egui::RichText::new("Fake packets").strong());
+        let fake = &mut strategies.fake_packet;
+        ui.checkbox(&mut fake.enabled, "Send fake packets");
+        ui.add_enabled_ui(fake.enabled, |ui| {
+            ui.checkbox(&mut fake.wrong_checksum, "Wrong checksum");
+            ui.checkbox(&mut fake.wrong_seq, "Wrong SEQ/ACK");
+            let mut auto_ttl = fake.auto_ttl.is_some();
+            if ui.checkbox(&mut auto_ttl, "Auto TTL").changed() {
+                fake.auto_ttl = auto_ttl.then(AutoTtlConfig::default);
+            }
+        });
+        show_issues(ui, &self.issues, "strategies.fake_packet");
+
+        ui.add_space(8.0);
+        ui.checkbox(&mut strategies.quic_block.enabled, "Block QUIC (forces browsers onto TCP)");
+
+        ui.add_space(8.0);
+        ui.label(egui::RichText::new("DNS").strong());
+        ui.horizontal(|ui| {
+            ui.label("Upstream");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.dns_upstream)
+                    .hint_text("e.g. 77.88.8.8, empty = off")
+                    .desired_width(140.0),
+            );
+            ui.label("Port");
+            ui.add(egui::DragValue::new(&mut self.dns_port));
+        });
+        if let Err(msg) = parse_upstream(&self.dns_upstream) {
+            ui.colored_label(ERROR_COLOR, msg);
+        }
+        show_issues(ui, &self.issues, "dns");
+        show_issues(ui, &self.issues, "");
+    }
+
+    /// Validate and write the settings to `path`
+    ///
+    /// On failure nothing is written and the problems are shown next to
+    /// their settings.
+    pub fn save(&mut self, path: &Path) -> bool {
+        self.issues.clear();
+
+        match parse_upstream(&self.dns_upstream) {
+            Ok(upstream) => self.apply_dns(upstream),
+            Err(msg) => {
+                self.issues.push(("dns".into(), msg));
+                return false;
+            }
+        }
+        // The capture filter follows the shortcut, the strategy the table
+        self.config.strategies.block_quic = self.config.strategies.quic_block.enabled;
+
+        self.issues = self
+            .config
+            .validation_issues()
+            .into_iter()
+            .map(|issue| (issue.key, format!("{} ({})", issue.error, issue.suggestion)))
+            .collect();
+        if !self.issues.is_empty() {
+            return false;
+        }
+
+        let written = self
+            .config
+            .to_toml()
+            .map_err(|e| e.to_string())
+            .and_then(|toml| std::fs::write(path, toml).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            self.issues.push((String::new(), format!("Failed to write {}: {}", path.display(), e)));
+            return false;
+        }
+        true
+    }
+
+    fn apply_dns(&mut self, upstream: Option<IpAddr>) {
+        let dns = &mut self.config.dns;
+        dns.enabled = upstream.is_some();
+        dns.ipv4_upstream = None;
+        dns.ipv6_upstream = None;
+        match upstream {
+            Some(IpAddr::V4(ip)) => {
+                dns.ipv4_upstream = Some(ip);
+                dns.ipv4_port = Some(self.dns_port);
+            }
+            Some(IpAddr::V6(ip)) => {
+                dns.ipv6_upstream = Some(ip);
+                dns.ipv6_port = Some(self.dns_port);
+            }
+            None => {}
+        }
+    }
+}
+
+/// The DNS upstream typed in, `None` if left empty
+fn parse_upstream(text: &str) -> Result<Option<IpAddr>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    text.parse()
+        .map(Some)
+        .map_err(|_| format!("\"{}\" is not an IP address", text))
+}
+
+/// Show the issues for config keys under `prefix` ("" for the rest)
+fn show_issues(ui: &mut egui::Ui, issues: &[(String, String)], prefix: &str) {
+    for (key, msg) in issues {
+        let matches = if prefix.is_empty() {
+            !["strategies.fragmentation", "strategies.fake_packet", "dns"]
+                .iter()
+                .any(|known| key.starts_with(known))
+        } else {
+            key.starts_with(prefix)
+        };
+        if matches {
+            ui.colored_label(ERROR_COLOR, msg);
+        }
+    }
+}
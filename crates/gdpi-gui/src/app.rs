@@ -1,9 +1,12 @@
 //! Main application and GUI window
 
+use crate::advanced::AdvancedSettings;
 use crate::config::GuiConfig;
-use crate::service::{ServiceController, ServiceStatus};
+use crate::notify::{Notice, Notifier};
+use crate::service::{BypassSettings, ServiceController, ServiceEvent, ServiceStatus};
 use crate::tray::{TrayEvent, TrayManager};
 use eframe::egui;
+use gdpi_platform::StatsSnapshot;
 use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
 use std::time::{Duration, Instant};
 use tracing::{info, error};
@@ -18,6 +21,13 @@ static SHOW_WINDOW_REQUESTED: AtomicBool = AtomicBool::new(false);
 /// Saved window position for restore
 static mut SAVED_WINDOW_POS: Option<(i32, i32)> = None;
 
+/// Tabs of the settings window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SettingsTab {
+    General,
+    Advanced,
+}
+
 /// Application state
 pub struct GoodbyeDpiApp {
     /// Configuration
@@ -28,10 +38,16 @@ pub struct GoodbyeDpiApp {
     profiles: Vec<String>,
     /// Show settings panel
     show_settings: bool,
+    /// Selected settings tab
+    settings_tab: SettingsTab,
+    /// Advanced settings being edited, loaded when the tab is first shown
+    advanced: Option<AdvancedSettings>,
     /// Status message
     status_message: Option<(String, Instant)>,
     /// Tray manager (optional - created after window)
     tray: Option<TrayManager>,
+    /// Desktop notifications
+    notifier: Notifier,
     /// Pending show from tray request
     pending_show: bool,
     /// Should quit
@@ -53,8 +69,11 @@ impl GoodbyeDpiApp {
             service: Arc::new(Mutex::new(ServiceController::new())),
             profiles,
             show_settings: false,
+            settings_tab: SettingsTab::General,
+            advanced: None,
             status_message: None,
             tray: None,
+            notifier: Notifier::new(),
             pending_show: false,
             should_quit: false,
             window_visible: true,
@@ -68,9 +87,9 @@ impl GoodbyeDpiApp {
             return;
         }
 
-        let is_running = self.service.lock().unwrap().status().is_running();
+        let status = self.service.lock().unwrap().status();
         
-        match TrayManager::new(&self.profiles, &self.config.profile, is_running) {
+        match TrayManager::new(&self.profiles, &self.config.profile, status) {
             Ok(tray) => {
                 self.tray = Some(tray);
                 info!("System tray initialized");
@@ -195,6 +214,7 @@ impl GoodbyeDpiApp {
                 }
                 TrayEvent::SelectProfile(profile) => {
                     self.config.profile = profile;
+                    self.config.custom_settings = false;
                     let _ = self.config.save();
                 }
                 TrayEvent::OpenSettings => {
@@ -217,7 +237,7 @@ impl GoodbyeDpiApp {
     fn toggle_service(&mut self) {
         let result = {
             let mut service = self.service.lock().unwrap();
-            let res = service.toggle(&self.config.profile);
+            let res = service.toggle(&self.bypass_settings(), self.config.auto_start);
             let is_running = service.status().is_running();
             (res, is_running)
         };
@@ -242,7 +262,7 @@ impl GoodbyeDpiApp {
         let result = {
             let mut service = self.service.lock().unwrap();
             if !service.status().is_running() {
-                Some(service.start(&self.config.profile))
+                Some(service.start(&self.bypass_settings(), self.config.auto_start))
             } else {
                 None
             }
@@ -276,6 +296,17 @@ impl GoodbyeDpiApp {
         }
     }
 
+    /// What to start the bypass with: the Advanced settings file if in
+    /// use, else the selected profile
+    fn bypass_settings(&self) -> BypassSettings {
+        let path = GuiConfig::custom_settings_path();
+        if self.config.custom_settings && path.exists() {
+            BypassSettings::ConfigFile(path)
+        } else {
+            BypassSettings::Profile(self.config.profile.clone())
+        }
+    }
+
     /// Set status message
     fn set_status(&mut self, msg: &str) {
         self.status_message = Some((msg.to_string(), Instant::now()));
@@ -286,18 +317,44 @@ impl GoodbyeDpiApp {
         self.service.lock().unwrap().status()
     }
 
+    /// Show a notification if they are enabled
+    fn notify(&mut self, notice: Notice) {
+        if self.config.show_notifications {
+            self.notifier.show(notice);
+        }
+    }
+
     /// Update service status and sync tray
     fn check_service(&mut self) {
-        let status = {
+        let (event, status) = {
             let mut service = self.service.lock().unwrap();
-            service.check_status();
-            service.status()
+            let event = service.check_status();
+            (event, service.status())
         };
-        
+
+        match event {
+            Some(ServiceEvent::Started) => {
+                let settings = match self.bypass_settings() {
+                    BypassSettings::Profile(profile) => format!("the {} profile", profile),
+                    BypassSettings::ConfigFile(_) => "custom settings".to_string(),
+                };
+                self.notify(Notice::Started(settings));
+            }
+            Some(ServiceEvent::Stopped) => self.notify(Notice::Stopped),
+            Some(ServiceEvent::Exited) => {
+                self.set_status("DPI bypass stopped unexpectedly");
+                self.notify(Notice::Exited);
+            }
+            Some(ServiceEvent::Failed(msg)) => {
+                self.set_status(&format!("Error: {}", msg));
+                self.notify(Notice::Failed(msg));
+            }
+            None => {}
+        }
+
         // Update tray icon/menu based on service status
         if let Some(ref mut tray) = self.tray {
-            let is_running = status == ServiceStatus::Running;
-            tray.update_status(is_running);
+            tray.update_status(status);
         }
     }
 
@@ -422,11 +479,19 @@ impl GoodbyeDpiApp {
                 ui.add_enabled_ui(!is_loading, |ui| {
                     ui.horizontal(|ui| {
                         ui.label("Profile:");
+                        let selected = if self.config.custom_settings {
+                            "custom"
+                        } else {
+                            self.config.profile.as_str()
+                        };
                         egui::ComboBox::from_id_salt("profile_selector")
-                            .selected_text(&self.config.profile)
+                            .selected_text(selected)
                             .show_ui(ui, |ui| {
                                 for profile in &self.profiles {
-                                    if ui.selectable_value(&mut self.config.profile, profile.clone(), profile).changed() {
+                                    let current = !self.config.custom_settings && self.config.profile == *profile;
+                                    if ui.selectable_label(current, profile).clicked() {
+                                        self.config.profile = profile.clone();
+                                        self.config.custom_settings = false;
                                         let _ = self.config.save();
                                     }
                                 }
@@ -443,6 +508,13 @@ impl GoodbyeDpiApp {
                     }
                 }
 
+                // Live stats, when the running bypass serves them
+                let stats = self.service.lock().unwrap().stats().cloned();
+                if let Some(stats) = stats {
+                    ui.add_space(10.0);
+                    render_stats(ui, &stats);
+                }
+
                 // Settings button at bottom
                 ui.add_space(20.0);
                 if ui.button("⚙  Settings").clicked() {
@@ -459,10 +531,33 @@ impl GoodbyeDpiApp {
             .resizable(false)
             .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
             .show(ctx, |ui| {
-                ui.checkbox(&mut self.config.start_minimized, "Start minimized to tray");
-                ui.checkbox(&mut self.config.auto_start, "Start with Windows");
-                ui.checkbox(&mut self.config.auto_connect, "Auto-connect on startup");
-                ui.checkbox(&mut self.config.show_notifications, "Show notifications");
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.settings_tab, SettingsTab::General, "General");
+                    ui.selectable_value(&mut self.settings_tab, SettingsTab::Advanced, "Advanced");
+                });
+                ui.separator();
+
+                match self.settings_tab {
+                    SettingsTab::General => {
+                        ui.checkbox(&mut self.config.start_minimized, "Start minimized to tray");
+                        ui.checkbox(&mut self.config.auto_start, "Start with Windows");
+                        ui.checkbox(&mut self.config.auto_connect, "Auto-connect on startup");
+                        ui.checkbox(&mut self.config.show_notifications, "Show notifications");
+                    }
+                    SettingsTab::Advanced => {
+                        ui.checkbox(&mut self.config.custom_settings, "Use these settings instead of the profile");
+                        let profile = &self.config.profile;
+                        let advanced = self.advanced.get_or_insert_with(|| {
+                            AdvancedSettings::load(&GuiConfig::custom_settings_path(), profile)
+                        });
+                        ui.add_enabled_ui(self.config.custom_settings, |ui| advanced.ui(ui));
+                        ui.label(
+                            egui::RichText::new("Changes apply the next time the bypass starts.")
+                                .small()
+                                .color(egui::Color32::GRAY),
+                        );
+                    }
+                }
 
                 ui.add_space(10.0);
                 ui.separator();
@@ -470,19 +565,39 @@ impl GoodbyeDpiApp {
 
                 ui.horizontal(|ui| {
                     if ui.button("Save").clicked() {
-                        if let Err(e) = self.config.save() {
-                            self.set_status(&format!("Failed to save: {}", e));
-                        } else {
-                            self.set_status("Settings saved");
-                            self.show_settings = false;
-                        }
+                        self.save_settings();
                     }
                     if ui.button("Cancel").clicked() {
-                        self.show_settings = false;
+                        self.close_settings();
                     }
                 });
             });
     }
+
+    /// Save the settings window, keeping it open if anything is invalid
+    fn save_settings(&mut self) {
+        if self.config.custom_settings {
+            if let Some(advanced) = &mut self.advanced {
+                if !advanced.save(&GuiConfig::custom_settings_path()) {
+                    self.settings_tab = SettingsTab::Advanced;
+                    return;
+                }
+            }
+        }
+
+        if let Err(e) = self.config.save() {
+            self.set_status(&format!("Failed to save: {}", e));
+        } else {
+            self.set_status("Settings saved");
+            self.close_settings();
+        }
+    }
+
+    /// Close the settings window, dropping unsaved Advanced settings
+    fn close_settings(&mut self) {
+        self.show_settings = false;
+        self.advanced = None;
+    }
 }
 
 impl eframe::App for GoodbyeDpiApp {
@@ -536,11 +651,48 @@ impl eframe::App for GoodbyeDpiApp {
     }
 }
 
+/// Counters and recently bypassed domains of the running bypass
+fn render_stats(ui: &mut egui::Ui, stats: &StatsSnapshot) {
+    egui::CollapsingHeader::new("Statistics")
+        .default_open(true)
+        .show(ui, |ui| {
+            egui::Grid::new("stats_grid").num_columns(2).striped(true).show(ui, |ui| {
+                let uptime = stats.uptime_secs;
+                let rows = [
+                    ("Uptime", format!("{:02}:{:02}:{:02}", uptime / 3600, uptime / 60 % 60, uptime % 60)),
+                    ("Packets", stats.packets_captured.to_string()),
+                    ("Bypassed", stats.packets_modified.to_string()),
+                    ("Fragmented", stats.packets_fragmented.to_string()),
+                    ("Fake packets", stats.fake_packets_sent.to_string()),
+                    ("QUIC blocked", stats.quic_blocked.to_string()),
+                    ("DNS redirected", stats.dns_redirected.to_string()),
+                    ("Connections", stats.tracked_connections.to_string()),
+                    ("Full batches", stats.full_batches.to_string()),
+                ];
+                for (label, value) in rows {
+                    ui.label(label);
+                    ui.label(egui::RichText::new(value).monospace());
+                    ui.end_row();
+                }
+            });
+
+            if !stats.recent_domains.is_empty() {
+                ui.add_space(5.0);
+                ui.label("Recently bypassed:");
+                egui::ScrollArea::vertical().max_height(80.0).show(ui, |ui| {
+                    for domain in &stats.recent_domains {
+                        ui.label(egui::RichText::new(domain).small().color(egui::Color32::GRAY));
+                    }
+                });
+            }
+        });
+}
+
 /// Run the application
 pub fn run() -> anyhow::Result<()> {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([350.0, 400.0])
+            .with_inner_size([350.0, 560.0])
             .with_min_inner_size([300.0, 350.0])
             .with_icon(load_app_icon())
             .with_title("GoodbyeDPI Turkey"),
@@ -16,6 +16,9 @@ pub struct GuiConfig {
     pub auto_connect: bool,
     /// Show notifications
     pub show_notifications: bool,
+    /// Start with the Advanced settings file instead of the profile
+    #[serde(default)]
+    pub custom_settings: bool,
     /// Last window position
     pub window_pos: Option<(f32, f32)>,
     /// Last window size
@@ -30,6 +33,7 @@ impl Default for GuiConfig {
             auto_start: false,
             auto_connect: false,
             show_notifications: true,
+            custom_settings: false,
             window_pos: None,
             window_size: None,
         }
@@ -37,14 +41,22 @@ impl Default for GuiConfig {
 }
 
 impl GuiConfig {
-    /// Get config file path
-    pub fn config_path() -> PathBuf {
-        let exe_dir = std::env::current_exe()
+    /// Directory of the GUI executable, where its files are kept
+    fn exe_dir() -> PathBuf {
+        std::env::current_exe()
             .ok()
             .and_then(|p| p.parent().map(|p| p.to_path_buf()))
-            .unwrap_or_else(|| PathBuf::from("."));
-        
-        exe_dir.join("gui_config.json")
+            .unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    /// Get config file path
+    pub fn config_path() -> PathBuf {
+        Self::exe_dir().join("gui_config.json")
+    }
+
+    /// Path of the bypass configuration written by the Advanced settings
+    pub fn custom_settings_path() -> PathBuf {
+        Self::exe_dir().join("goodbyedpi-custom.toml")
     }
 
     /// Load configuration from file
@@ -4,10 +4,12 @@
 
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod advanced;
 mod app;
 mod tray;
 mod service;
 mod config;
+mod notify;
 
 use anyhow::Result;
 use tracing::info;
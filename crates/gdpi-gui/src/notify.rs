@@ -0,0 +1,76 @@
+//! Desktop notifications for bypass state changes
+
+use std::collections::HashMap;
+use std::mem::{discriminant, Discriminant};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// The same kind of notice is shown at most once per this interval
+const DEBOUNCE: Duration = Duration::from_secs(10);
+
+/// Something the user should hear about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notice {
+    /// The bypass started with these settings (e.g. "the turkey profile")
+    Started(String),
+    /// The bypass was stopped from the GUI
+    Stopped,
+    /// The bypass stopped without being asked to
+    Exited,
+    /// Starting or stopping failed
+    Failed(String),
+}
+
+impl Notice {
+    fn summary(&self) -> &'static str {
+        match self {
+            Notice::Started(_) => "DPI bypass started",
+            Notice::Stopped => "DPI bypass stopped",
+            Notice::Exited => "DPI bypass stopped unexpectedly",
+            Notice::Failed(_) => "DPI bypass error",
+        }
+    }
+
+    fn body(&self) -> String {
+        match self {
+            Notice::Started(settings) => format!("Running with {}.", settings),
+            Notice::Stopped => "Your traffic is no longer protected.".to_string(),
+            Notice::Exited => "Your traffic is no longer protected. Start it again from the tray.".to_string(),
+            Notice::Failed(msg) => msg.clone(),
+        }
+    }
+}
+
+/// Shows notifications, dropping repeats of a kind within [`DEBOUNCE`]
+#[derive(Default)]
+pub struct Notifier {
+    last_shown: HashMap<Discriminant<Notice>, Instant>,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show `notice` unless one of its kind was shown recently
+    pub fn show(&mut self, notice: Notice) {
+        let now = Instant::now();
+        let kind = discriminant(&notice);
+        if self.last_shown.get(&kind).is_some_and(|last| now - *last < DEBOUNCE) {
+            return;
+        }
+        self.last_shown.insert(kind, now);
+
+        let mut notification = notify_rust::Notification::new();
+        notification
+            .appname("GoodbyeDPI Turkey")
+            .summary(notice.summary())
+            .body(&notice.body());
+        // Showing may block on the notification service
+        std::thread::spawn(move || {
+            if let Err(e) = notification.show() {
+                warn!("Failed to show notification: {}", e);
+            }
+        });
+    }
+}
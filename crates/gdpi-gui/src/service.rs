@@ -1,23 +1,17 @@
 //! Service management - controls the DPI bypass process
-
-use std::process::{Child, Command, Stdio};
-use std::path::PathBuf;
+//!
+//! On Windows the bypass runs as the `GoodbyeDPI` service, controlled
+//! through the service control manager. Without administrator rights the
+//! CLI's `service start`/`service stop` is run elevated instead.
+
+use gdpi_platform::ipc::{self, StatsSnapshot};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Child;
 use std::sync::mpsc;
 use std::thread;
-use std::time::Duration;
-use tracing::{info, error, warn};
-
-#[cfg(windows)]
-use std::os::windows::ffi::OsStrExt;
-#[cfg(windows)]
-use std::ffi::OsStr;
-#[cfg(windows)]
-use std::iter::once;
-#[cfg(windows)]
-use std::os::windows::process::CommandExt;
-
-#[cfg(windows)]
-const CREATE_NO_WINDOW: u32 = 0x08000000;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, error, warn};
 
 /// Service status
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -45,40 +39,85 @@ impl ServiceStatus {
     }
 }
 
+/// What the bypass is started with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BypassSettings {
+    /// A built-in profile
+    Profile(String),
+    /// A configuration file
+    ConfigFile(PathBuf),
+}
+
+impl BypassSettings {
+    /// Command-line arguments selecting these settings
+    fn args(&self) -> Vec<OsString> {
+        match self {
+            BypassSettings::Profile(profile) => vec!["--profile".into(), profile.into()],
+            BypassSettings::ConfigFile(path) => vec!["--config".into(), path.into()],
+        }
+    }
+}
+
+/// Status change seen by [`ServiceController::check_status`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceEvent {
+    /// A start requested from the GUI finished
+    Started,
+    /// A stop requested from the GUI finished
+    Stopped,
+    /// The bypass stopped without the GUI asking
+    Exited,
+    /// A start or stop failed
+    Failed(String),
+}
+
 /// Service controller
 pub struct ServiceController {
+    /// Bypass process started directly (non-Windows)
     process: Option<Child>,
-    process_id: Option<u32>,
     status: ServiceStatus,
     exe_path: PathBuf,
     /// Channel for async operation results
     result_rx: Option<mpsc::Receiver<ServiceResult>>,
+    /// Latest stats from the running bypass, `None` if it serves none
+    stats: Option<StatsSnapshot>,
+    /// When stats were last fetched
+    last_stats_poll: Option<Instant>,
+    /// Pending stats fetch, run off the UI thread
+    stats_rx: Option<mpsc::Receiver<Option<StatsSnapshot>>>,
 }
 
 /// Result from async operations
 enum ServiceResult {
-    Started(Option<u32>),  // Optional PID
+    Started(Option<Child>),
     StartFailed(String),
     Stopped,
     StopFailed(String),
 }
 
+/// How often to fetch stats while running
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait for the elevated CLI to start or stop the service
+#[cfg(windows)]
+const ELEVATED_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Check if current process is running as administrator
 #[cfg(windows)]
 fn is_elevated() -> bool {
     use winapi::um::processthreadsapi::{GetCurrentProcess, OpenProcessToken};
     use winapi::um::securitybaseapi::GetTokenInformation;
     use winapi::um::winnt::{TokenElevation, HANDLE, TOKEN_ELEVATION, TOKEN_QUERY};
-    
+
     unsafe {
         let mut token: HANDLE = std::ptr::null_mut();
         if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
             return false;
         }
-        
+
         let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
         let mut size = std::mem::size_of::<TOKEN_ELEVATION>() as u32;
-        
+
         let result = GetTokenInformation(
             token,
             TokenElevation,
@@ -86,17 +125,81 @@ fn is_elevated() -> bool {
             size,
             &mut size,
         );
-        
+
         winapi::um::handleapi::CloseHandle(token);
-        
+
         result != 0 && elevation.TokenIsElevated != 0
     }
 }
 
-#[cfg(not(windows))]
-fn is_elevated() -> bool {
-    // On non-Windows, check if running as root
-    unsafe { libc::geteuid() == 0 }
+/// Run `exe_path` with `args` as administrator (UAC prompt), without
+/// waiting for it
+#[cfg(windows)]
+fn run_elevated(exe_path: &Path, args: &str) -> Result<(), String> {
+    use std::ffi::OsStr;
+    use std::iter::once;
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::shellapi::ShellExecuteW;
+    use winapi::um::winuser::SW_HIDE;
+
+    let wide = |s: &OsStr| -> Vec<u16> { s.encode_wide().chain(once(0)).collect() };
+    let operation = wide(OsStr::new("runas"));
+    let file = wide(exe_path.as_os_str());
+    let parameters = wide(OsStr::new(args));
+
+    let result = unsafe {
+        ShellExecuteW(
+            std::ptr::null_mut(),
+            operation.as_ptr(),
+            file.as_ptr(),
+            parameters.as_ptr(),
+            std::ptr::null(),
+            SW_HIDE,
+        )
+    };
+
+    let code = result as isize;
+    if code > 32 {
+        return Ok(());
+    }
+    let error_msg = match code {
+        0 => "Out of memory",
+        2 => "File not found",
+        3 => "Path not found",
+        5 => "Access denied (UAC cancelled?)",
+        _ => "Unknown error",
+    };
+    Err(format!("{} (code: {})", error_msg, code))
+}
+
+/// Current state of the service, treating "not installed" as stopped
+#[cfg(windows)]
+fn query_status() -> ServiceStatus {
+    use gdpi_service::State;
+
+    match gdpi_service::service_state() {
+        Ok(Some(State::Running)) => ServiceStatus::Running,
+        Ok(Some(State::StartPending)) => ServiceStatus::Starting,
+        Ok(Some(State::StopPending)) => ServiceStatus::Stopping,
+        Ok(_) => ServiceStatus::Stopped,
+        Err(e) => {
+            warn!("Failed to query service: {:#}", e);
+            ServiceStatus::Error
+        }
+    }
+}
+
+/// Wait until the service reaches `target`, or give up
+#[cfg(windows)]
+fn wait_for_status(target: ServiceStatus) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < ELEVATED_TIMEOUT {
+        if query_status() == target {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+    false
 }
 
 impl ServiceController {
@@ -104,13 +207,24 @@ impl ServiceController {
     pub fn new() -> Self {
         // Find the CLI executable
         let exe_path = Self::find_exe();
-        
+
+        // The service may already be running, e.g. started with Windows
+        #[cfg(windows)]
+        let status = match query_status() {
+            ServiceStatus::Error => ServiceStatus::Stopped,
+            status => status,
+        };
+        #[cfg(not(windows))]
+        let status = ServiceStatus::Stopped;
+
         Self {
             process: None,
-            process_id: None,
-            status: ServiceStatus::Stopped,
+            status,
             exe_path,
             result_rx: None,
+            stats: None,
+            last_stats_poll: None,
+            stats_rx: None,
         }
     }
 
@@ -120,7 +234,7 @@ impl ServiceController {
             .ok()
             .and_then(|p| p.parent().map(|p| p.to_path_buf()))
             .unwrap_or_else(|| PathBuf::from("."));
-        
+
         // Try different possible locations
         let candidates = [
             exe_dir.join("goodbyedpi.exe"),
@@ -143,228 +257,189 @@ impl ServiceController {
         self.status
     }
 
-    /// Start the DPI bypass service with administrator privileges (non-blocking)
-    pub fn start(&mut self, profile: &str) -> anyhow::Result<()> {
-        if self.process.is_some() || self.process_id.is_some() {
+    /// Latest stats of the running bypass
+    ///
+    /// `None` while stopped, or when the bypass doesn't serve stats (a CLI
+    /// from before the stats channel).
+    pub fn stats(&self) -> Option<&StatsSnapshot> {
+        self.stats.as_ref()
+    }
+
+    /// Pick up a finished stats fetch, and start a new one (non-blocking)
+    /// if the last is older than [`STATS_INTERVAL`]
+    fn poll_stats(&mut self) {
+        if let Some(ref rx) = self.stats_rx {
+            match rx.try_recv() {
+                Ok(stats) => self.stats = stats,
+                Err(mpsc::TryRecvError::Empty) => return,
+                Err(mpsc::TryRecvError::Disconnected) => self.stats = None,
+            }
+            self.stats_rx = None;
+        }
+        if self.last_stats_poll.is_some_and(|last| last.elapsed() < STATS_INTERVAL) {
+            return;
+        }
+        self.last_stats_poll = Some(Instant::now());
+
+        let (tx, rx) = mpsc::channel();
+        self.stats_rx = Some(rx);
+        thread::spawn(move || {
+            let stats = match ipc::fetch_stats() {
+                Ok(stats) => Some(stats),
+                Err(e) => {
+                    debug!("No stats from the bypass: {}", e);
+                    None
+                }
+            };
+            let _ = tx.send(stats);
+        });
+    }
+
+    /// Start the DPI bypass with `settings` (non-blocking)
+    ///
+    /// On Windows this installs the service if needed, switches it to
+    /// `settings` and sets whether it starts with Windows.
+    pub fn start(&mut self, settings: &BypassSettings, auto_start: bool) -> anyhow::Result<()> {
+        if self.status.is_running() || self.result_rx.is_some() {
             warn!("Service already running");
             return Ok(());
         }
 
-        info!("Starting DPI bypass with profile: {}", profile);
+        info!("Starting DPI bypass with {:?}", settings);
         self.status = ServiceStatus::Starting;
 
         // Start async operation
         let exe_path = self.exe_path.clone();
-        let profile = profile.to_string();
+        let settings = settings.clone();
         let (tx, rx) = mpsc::channel();
         self.result_rx = Some(rx);
 
         thread::spawn(move || {
-            let result = Self::start_elevated_async(&exe_path, &profile);
+            let result = Self::start_async(&exe_path, &settings, auto_start);
             let _ = tx.send(result);
         });
 
         Ok(())
     }
 
-    /// Async start with elevation
+    /// Async start through the service manager
     #[cfg(windows)]
-    fn start_elevated_async(exe_path: &PathBuf, profile: &str) -> ServiceResult {
-        use winapi::um::shellapi::ShellExecuteW;
-        use winapi::um::winuser::SW_HIDE;
-        
-        let exe_path_str = exe_path.to_string_lossy().to_string();
-        let args = format!("run --profile {}", profile);
-        
-        // Convert strings to wide strings for Windows API
-        let operation: Vec<u16> = OsStr::new("runas").encode_wide().chain(once(0)).collect();
-        let file: Vec<u16> = OsStr::new(&exe_path_str).encode_wide().chain(once(0)).collect();
-        let parameters: Vec<u16> = OsStr::new(&args).encode_wide().chain(once(0)).collect();
-        
-        let result = unsafe {
-            ShellExecuteW(
-                std::ptr::null_mut(),
-                operation.as_ptr(),
-                file.as_ptr(),
-                parameters.as_ptr(),
-                std::ptr::null(),
-                SW_HIDE,
-            )
-        };
-
-        if (result as isize) > 32 {
-            info!("DPI bypass started with elevation");
-            
-            // Wait a bit and find the process
-            thread::sleep(Duration::from_millis(500));
-            
-            if let Some(pid) = Self::find_process_pid() {
-                ServiceResult::Started(Some(pid))
-            } else {
-                thread::sleep(Duration::from_millis(1000));
-                if let Some(pid) = Self::find_process_pid() {
-                    ServiceResult::Started(Some(pid))
-                } else {
+    fn start_async(exe_path: &Path, settings: &BypassSettings, auto_start: bool) -> ServiceResult {
+        if is_elevated() {
+            let mut args: Vec<OsString> = vec![
+                "--log-file".into(),
+                exe_path.with_file_name("goodbyedpi-service.log").into_os_string(),
+                "service".into(),
+                "run".into(),
+            ];
+            args.extend(settings.args());
+            let configured = match gdpi_service::service_state() {
+                Ok(Some(_)) => gdpi_service::update_service(exe_path, &args, auto_start),
+                Ok(None) => gdpi_service::install_service(exe_path, &args, auto_start),
+                Err(e) => Err(e),
+            };
+            return match configured.and_then(|()| gdpi_service::start_service()) {
+                Ok(()) => {
+                    info!("DPI bypass service started");
                     ServiceResult::Started(None)
                 }
-            }
-        } else {
-            let error_code = result as isize;
-            let error_msg = match error_code {
-                0 => "Out of memory",
-                2 => "File not found",
-                3 => "Path not found", 
-                5 => "Access denied (UAC cancelled?)",
-                _ => "Unknown error",
+                Err(e) => {
+                    error!("Failed to start service: {:#}", e);
+                    ServiceResult::StartFailed(format!("{:#}", e))
+                }
             };
-            error!("Failed to start with elevation: {} (code: {})", error_msg, error_code);
-            ServiceResult::StartFailed(format!("{} (code: {})", error_msg, error_code))
+        }
+
+        let mut args = match settings {
+            BypassSettings::Profile(profile) => format!("service start --profile {}", profile),
+            BypassSettings::ConfigFile(path) => format!("service start --config \"{}\"", path.display()),
+        };
+        if auto_start {
+            args.push_str(" --auto-start");
+        }
+        if let Err(msg) = run_elevated(exe_path, &args) {
+            error!("Failed to start with elevation: {}", msg);
+            return ServiceResult::StartFailed(msg);
+        }
+
+        if wait_for_status(ServiceStatus::Running) {
+            info!("DPI bypass service started with elevation");
+            ServiceResult::Started(None)
+        } else {
+            ServiceResult::StartFailed(
+                "Service did not start, see goodbyedpi-service.log".to_string(),
+            )
         }
     }
 
     #[cfg(not(windows))]
-    fn start_elevated_async(exe_path: &PathBuf, profile: &str) -> ServiceResult {
+    fn start_async(exe_path: &Path, settings: &BypassSettings, _auto_start: bool) -> ServiceResult {
+        use std::process::{Command, Stdio};
+
         let mut cmd = Command::new(exe_path);
         cmd.arg("run")
-            .arg("--profile")
-            .arg(profile)
+            .args(settings.args())
             .stdout(Stdio::null())
             .stderr(Stdio::null());
 
         match cmd.spawn() {
-            Ok(child) => {
-                ServiceResult::Started(Some(child.id()))
-            }
-            Err(e) => {
-                ServiceResult::StartFailed(e.to_string())
-            }
-        }
-    }
-
-    /// Find running goodbyedpi process PID (without creating console window)
-    #[cfg(windows)]
-    fn find_process_pid() -> Option<u32> {
-        let mut cmd = Command::new("tasklist");
-        cmd.args(["/FI", "IMAGENAME eq goodbyedpi.exe", "/FO", "CSV", "/NH"])
-            .creation_flags(CREATE_NO_WINDOW)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null());
-        
-        if let Ok(output) = cmd.output() {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            if stdout.contains("goodbyedpi.exe") {
-                if let Some(pid_str) = stdout.split(',').nth(1) {
-                    let pid_str = pid_str.trim().trim_matches('"');
-                    if let Ok(pid) = pid_str.parse::<u32>() {
-                        info!("Found running process with PID: {}", pid);
-                        return Some(pid);
-                    }
-                }
-            }
+            Ok(child) => ServiceResult::Started(Some(child)),
+            Err(e) => ServiceResult::StartFailed(e.to_string()),
         }
-        None
-    }
-
-    #[cfg(not(windows))]
-    fn find_process_pid() -> Option<u32> {
-        None
     }
 
     /// Stop the DPI bypass service (non-blocking)
     pub fn stop(&mut self) -> anyhow::Result<()> {
-        if self.process.is_none() && self.process_id.is_none() {
+        if !self.status.is_running() || self.result_rx.is_some() {
             return Ok(());
         }
 
         info!("Stopping DPI bypass");
         self.status = ServiceStatus::Stopping;
 
-        let pid = self.process_id.take();
+        let exe_path = self.exe_path.clone();
         let process = self.process.take();
-        
+
         let (tx, rx) = mpsc::channel();
         self.result_rx = Some(rx);
 
         thread::spawn(move || {
-            let result = Self::stop_async(pid, process);
+            let result = Self::stop_async(&exe_path, process);
             let _ = tx.send(result);
         });
 
         Ok(())
     }
 
-    /// Async stop process
+    /// Async stop through the service manager
     #[cfg(windows)]
-    fn stop_async(pid: Option<u32>, mut process: Option<Child>) -> ServiceResult {
-        use std::ffi::OsStr;
-        use std::os::windows::ffi::OsStrExt;
-        use std::iter::once;
-        use winapi::um::shellapi::ShellExecuteW;
-        use winapi::um::winuser::SW_HIDE;
-        
-        // Kill by PID if we have it
-        if let Some(pid) = pid {
-            info!("Killing process with PID: {}", pid);
-            let mut cmd = Command::new("taskkill");
-            cmd.args(["/PID", &pid.to_string(), "/T", "/F"])
-                .creation_flags(CREATE_NO_WINDOW)
-                .stdout(Stdio::null())
-                .stderr(Stdio::null());
-            let _ = cmd.output();
-        }
-
-        // Kill child process if we have it
-        if let Some(ref mut child) = process {
-            let pid = child.id();
-            let mut cmd = Command::new("taskkill");
-            cmd.args(["/PID", &pid.to_string(), "/T", "/F"])
-                .creation_flags(CREATE_NO_WINDOW)
-                .stdout(Stdio::null())
-                .stderr(Stdio::null());
-            let _ = cmd.output();
-            let _ = child.kill();
-            let _ = child.wait();
-        }
+    fn stop_async(exe_path: &Path, _process: Option<Child>) -> ServiceResult {
+        let stopped = if is_elevated() {
+            gdpi_service::stop_service().map_err(|e| format!("{:#}", e))
+        } else {
+            run_elevated(exe_path, "service stop").and_then(|()| {
+                if wait_for_status(ServiceStatus::Stopped) {
+                    Ok(())
+                } else {
+                    Err("Service did not stop".to_string())
+                }
+            })
+        };
 
-        // Try to kill by name as fallback (non-elevated first)
-        let mut cmd = Command::new("taskkill");
-        cmd.args(["/IM", "goodbyedpi.exe", "/F"])
-            .creation_flags(CREATE_NO_WINDOW)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null());
-        let _ = cmd.output();
-        
-        // Wait a bit and check if still running
-        std::thread::sleep(std::time::Duration::from_millis(300));
-        
-        // If still running, use elevated taskkill
-        if Self::find_process_pid().is_some() {
-            info!("Process still running, trying elevated taskkill");
-            let operation: Vec<u16> = OsStr::new("runas").encode_wide().chain(once(0)).collect();
-            let file: Vec<u16> = OsStr::new("taskkill").encode_wide().chain(once(0)).collect();
-            let parameters: Vec<u16> = OsStr::new("/IM goodbyedpi.exe /F").encode_wide().chain(once(0)).collect();
-            
-            unsafe {
-                ShellExecuteW(
-                    std::ptr::null_mut(),
-                    operation.as_ptr(),
-                    file.as_ptr(),
-                    parameters.as_ptr(),
-                    std::ptr::null(),
-                    SW_HIDE,
-                );
+        match stopped {
+            Ok(()) => {
+                info!("DPI bypass stopped");
+                ServiceResult::Stopped
+            }
+            Err(msg) => {
+                error!("Failed to stop service: {}", msg);
+                ServiceResult::StopFailed(msg)
             }
-            
-            // Wait for process to be killed
-            std::thread::sleep(std::time::Duration::from_millis(500));
         }
-
-        info!("DPI bypass stopped");
-        ServiceResult::Stopped
     }
 
     #[cfg(not(windows))]
-    fn stop_async(pid: Option<u32>, mut process: Option<Child>) -> ServiceResult {
+    fn stop_async(_exe_path: &Path, mut process: Option<Child>) -> ServiceResult {
         if let Some(ref mut child) = process {
             let _ = child.kill();
             let _ = child.wait();
@@ -373,117 +448,106 @@ impl ServiceController {
     }
 
     /// Toggle service state
-    pub fn toggle(&mut self, profile: &str) -> anyhow::Result<()> {
+    pub fn toggle(&mut self, settings: &BypassSettings, auto_start: bool) -> anyhow::Result<()> {
         if self.status().is_running() {
             self.stop()
         } else {
-            self.start(profile)
+            self.start(settings, auto_start)
         }
     }
 
-    /// Check if process is still running and poll async results
-    pub fn check_status(&mut self) {
+    /// Check if the bypass is still running and poll async results
+    ///
+    /// Returns what changed since the last call, if anything.
+    pub fn check_status(&mut self) -> Option<ServiceEvent> {
         // Check for async operation results (non-blocking)
         if let Some(ref rx) = self.result_rx {
-            if let Ok(result) = rx.try_recv() {
-                match result {
-                    ServiceResult::Started(pid) => {
-                        self.process_id = pid;
-                        self.status = ServiceStatus::Running;
-                        info!("Service started, PID: {:?}", pid);
-                    }
-                    ServiceResult::StartFailed(msg) => {
-                        self.status = ServiceStatus::Error;
-                        error!("Service start failed: {}", msg);
-                    }
-                    ServiceResult::Stopped => {
-                        self.status = ServiceStatus::Stopped;
-                        info!("Service stopped");
-                    }
-                    ServiceResult::StopFailed(msg) => {
-                        self.status = ServiceStatus::Error;
-                        error!("Service stop failed: {}", msg);
-                    }
+            let result = rx.try_recv().ok()?;
+            self.result_rx = None;
+            let event = match result {
+                ServiceResult::Started(process) => {
+                    self.process = process;
+                    self.status = ServiceStatus::Running;
+                    info!("Service started");
+                    ServiceEvent::Started
+                }
+                ServiceResult::StartFailed(msg) => {
+                    self.status = ServiceStatus::Error;
+                    error!("Service start failed: {}", msg);
+                    ServiceEvent::Failed(msg)
                 }
-                self.result_rx = None;
+                ServiceResult::Stopped => {
+                    self.status = ServiceStatus::Stopped;
+                    info!("Service stopped");
+                    ServiceEvent::Stopped
+                }
+                ServiceResult::StopFailed(msg) => {
+                    self.status = ServiceStatus::Error;
+                    error!("Service stop failed: {}", msg);
+                    ServiceEvent::Failed(msg)
+                }
+            };
+            return Some(event);
+        }
+
+        if self.status != ServiceStatus::Running {
+            self.stats = None;
+            self.last_stats_poll = None;
+            self.stats_rx = None;
+            return None;
+        }
+
+        // Notice the bypass stopping behind our back; the user is
+        // unprotected until they start it again, so show it as an error
+        #[cfg(windows)]
+        {
+            if query_status() == ServiceStatus::Stopped {
+                self.status = ServiceStatus::Error;
+                warn!("Service stopped outside the GUI");
             }
         }
 
-        // Check if running process is still alive
-        if self.status == ServiceStatus::Running {
-            if let Some(ref mut child) = self.process {
-                match child.try_wait() {
-                    Ok(Some(_)) => {
-                        self.process = None;
-                        self.process_id = None;
-                        self.status = ServiceStatus::Stopped;
-                        info!("Process exited");
-                    }
-                    Ok(None) => {} // Still running
-                    Err(e) => {
-                        error!("Failed to check process: {}", e);
-                    }
+        if let Some(ref mut child) = self.process {
+            match child.try_wait() {
+                Ok(Some(exit)) => {
+                    self.process = None;
+                    self.status = ServiceStatus::Error;
+                    warn!("Process exited: {}", exit);
                 }
-            } else if self.process_id.is_some() {
-                // Periodically check if elevated process is still running
-                #[cfg(windows)]
-                {
-                    if Self::find_process_pid().is_none() {
-                        self.process_id = None;
-                        self.status = ServiceStatus::Stopped;
-                        info!("Elevated process exited");
-                    }
+                Ok(None) => {} // Still running
+                Err(e) => {
+                    error!("Failed to check process: {}", e);
                 }
             }
         }
+
+        if self.status == ServiceStatus::Running {
+            self.poll_stats();
+            None
+        } else {
+            Some(ServiceEvent::Exited)
+        }
     }
 
-    /// Force kill any running process (for cleanup on exit)
+    /// Stop the bypass right away (for cleanup on exit)
     pub fn force_stop(&mut self) {
         if let Some(mut child) = self.process.take() {
             let _ = child.kill();
         }
-        
+
         #[cfg(windows)]
         {
-            use std::ffi::OsStr;
-            use std::os::windows::ffi::OsStrExt;
-            use std::iter::once;
-            use winapi::um::shellapi::ShellExecuteW;
-            use winapi::um::winuser::SW_HIDE;
-            
-            // First try normal taskkill
-            let mut cmd = Command::new("taskkill");
-            cmd.args(["/IM", "goodbyedpi.exe", "/F"])
-                .creation_flags(CREATE_NO_WINDOW)
-                .stdout(Stdio::null())
-                .stderr(Stdio::null());
-            let _ = cmd.output();
-            
-            // If process still running, try with elevation
-            if Self::find_process_pid().is_some() {
-                // Use elevated taskkill via cmd /c
-                let operation: Vec<u16> = OsStr::new("runas").encode_wide().chain(once(0)).collect();
-                let file: Vec<u16> = OsStr::new("taskkill").encode_wide().chain(once(0)).collect();
-                let parameters: Vec<u16> = OsStr::new("/IM goodbyedpi.exe /F").encode_wide().chain(once(0)).collect();
-                
-                unsafe {
-                    ShellExecuteW(
-                        std::ptr::null_mut(),
-                        operation.as_ptr(),
-                        file.as_ptr(),
-                        parameters.as_ptr(),
-                        std::ptr::null(),
-                        SW_HIDE,
-                    );
+            if query_status().is_running() {
+                if is_elevated() {
+                    if let Err(e) = gdpi_service::stop_service() {
+                        error!("Failed to stop service: {:#}", e);
+                    }
+                } else if let Err(msg) = run_elevated(&self.exe_path, "service stop") {
+                    error!("Failed to stop service: {}", msg);
                 }
-                
-                // Wait a bit for the process to be killed
-                std::thread::sleep(std::time::Duration::from_millis(500));
             }
         }
-        
-        self.process_id = None;
+
         self.status = ServiceStatus::Stopped;
     }
 }
@@ -1,5 +1,6 @@
 //! System tray icon management
 
+use crate::service::ServiceStatus;
 use tray_icon::{
     menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem, CheckMenuItem, MenuId},
     TrayIcon, TrayIconBuilder, Icon,
@@ -32,28 +33,27 @@ pub struct TrayManager {
     tray: TrayIcon,
     event_rx: mpsc::Receiver<TrayEvent>,
     toggle_item: MenuItem,
-    is_running: bool,
+    status: ServiceStatus,
 }
 
 impl TrayManager {
     /// Create a new tray manager
-    pub fn new(profiles: &[String], current_profile: &str, is_running: bool) -> anyhow::Result<Self> {
+    pub fn new(profiles: &[String], current_profile: &str, status: ServiceStatus) -> anyhow::Result<Self> {
         let (event_tx, event_rx) = mpsc::channel();
 
         // Create toggle menu item (we keep a reference to update it later)
-        let toggle_text = if is_running { "⏹ Stop" } else { "▶ Start" };
-        let toggle_item = MenuItem::with_id(menu_ids::TOGGLE, toggle_text, true, None);
+        let toggle_item = MenuItem::with_id(menu_ids::TOGGLE, Self::toggle_text(status), true, None);
 
         // Create menu
         let menu = Self::create_menu(profiles, current_profile, &toggle_item)?;
 
         // Create icon
-        let icon = Self::create_icon(is_running)?;
+        let icon = Self::create_icon(status)?;
 
         // Build tray icon
         let tray = TrayIconBuilder::new()
             .with_menu(Box::new(menu))
-            .with_tooltip(Self::tooltip_text(is_running))
+            .with_tooltip(Self::tooltip_text(status))
             .with_icon(icon)
             .build()?;
 
@@ -114,28 +114,32 @@ impl TrayManager {
             tray,
             event_rx,
             toggle_item,
-            is_running,
+            status,
         })
     }
 
     /// Update tray status (icon, tooltip, menu text)
-    pub fn update_status(&mut self, is_running: bool) {
-        if self.is_running == is_running {
+    pub fn update_status(&mut self, status: ServiceStatus) {
+        if self.status == status {
             return; // No change
         }
-        self.is_running = is_running;
+        self.status = status;
 
         // Update icon
-        if let Ok(icon) = Self::create_icon(is_running) {
+        if let Ok(icon) = Self::create_icon(status) {
             let _ = self.tray.set_icon(Some(icon));
         }
 
         // Update tooltip
-        let _ = self.tray.set_tooltip(Some(Self::tooltip_text(is_running)));
+        let _ = self.tray.set_tooltip(Some(Self::tooltip_text(status)));
 
         // Update toggle menu item text
-        let toggle_text = if is_running { "⏹ Stop" } else { "▶ Start" };
-        self.toggle_item.set_text(toggle_text);
+        self.toggle_item.set_text(Self::toggle_text(status));
+    }
+
+    /// Toggle menu item text
+    fn toggle_text(status: ServiceStatus) -> &'static str {
+        if status.is_running() { "⏹ Stop" } else { "▶ Start" }
     }
 
     /// Create the tray menu
@@ -182,15 +186,15 @@ impl TrayManager {
     }
 
     /// Create tray icon based on status
-    fn create_icon(is_running: bool) -> anyhow::Result<Icon> {
+    fn create_icon(status: ServiceStatus) -> anyhow::Result<Icon> {
         // Create a simple colored icon (16x16)
         let size = 16u32;
         let mut rgba = Vec::with_capacity((size * size * 4) as usize);
         
-        let (r, g, b) = if is_running {
-            (0x4C, 0xAF, 0x50) // Green when running
-        } else {
-            (0x9E, 0x9E, 0x9E) // Gray when stopped
+        let (r, g, b) = match status {
+            ServiceStatus::Running => (0x4C, 0xAF, 0x50), // Green when running
+            ServiceStatus::Error => (0xF4, 0x43, 0x36),   // Red on error
+            _ => (0x9E, 0x9E, 0x9E),                      // Gray otherwise
         };
 
         for y in 0..size {
@@ -218,11 +222,11 @@ impl TrayManager {
     }
 
     /// Get tooltip text
-    fn tooltip_text(is_running: bool) -> &'static str {
-        if is_running {
-            "GoodbyeDPI Turkey - Running"
-        } else {
-            "GoodbyeDPI Turkey - Stopped"
+    fn tooltip_text(status: ServiceStatus) -> &'static str {
+        match status {
+            ServiceStatus::Running => "GoodbyeDPI Turkey - Running",
+            ServiceStatus::Error => "GoodbyeDPI Turkey - Not running (error)",
+            _ => "GoodbyeDPI Turkey - Stopped",
         }
     }
 
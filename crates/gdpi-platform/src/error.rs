@@ -17,10 +17,37 @@ pub enum PlatformError {
     #[error("Invalid filter syntax: {0}")]
     InvalidFilter(String),
 
+    /// Installed driver is older than required
+    #[error(
+        "WinDivert driver {}.{} is too old, {}.{} or newer is required",
+        found.0, found.1, required.0, required.1
+    )]
+    DriverVersionMismatch {
+        /// Minimum (major, minor) version
+        required: (u16, u16),
+        /// Loaded (major, minor) version
+        found: (u16, u16),
+    },
+
+    /// An installed driver file doesn't match the embedded one
+    #[error("{file} is corrupted: SHA-256 is {found}, expected {expected}")]
+    InstallCorrupted {
+        /// File name
+        file: String,
+        /// SHA-256 of the embedded file (hex)
+        expected: String,
+        /// SHA-256 of the installed file (hex)
+        found: String,
+    },
+
     /// Packet capture error
     #[error("Capture error: {0}")]
     CaptureError(String),
 
+    /// No packets left in a capture file
+    #[error("End of capture")]
+    EndOfCapture,
+
     /// Packet injection error
     #[error("Injection error: {0}")]
     InjectionError(String),
@@ -9,14 +9,20 @@ use std::path::PathBuf;
 use std::process::Command;
 
 use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
 use tracing::{debug, error, info, warn};
 
+use crate::PlatformError;
+
 /// Embedded WinDivert files for x64
 #[cfg(target_arch = "x86_64")]
 mod embedded {
     pub const WINDIVERT_DLL: &[u8] = include_bytes!("../../../resources/windivert/x64/WinDivert.dll");
     pub const WINDIVERT_SYS: &[u8] = include_bytes!("../../../resources/windivert/x64/WinDivert64.sys");
     pub const SYS_NAME: &str = "WinDivert64.sys";
+    pub const SYS_STEP: &str = "Writing WinDivert64.sys";
+    pub const EXPECTED_DLL_SHA256: &str = "c1e060ee19444a259b2162f8af0f3fe8c4428a1c6f694dce20de194ac8d7d9a2";
+    pub const EXPECTED_SYS_SHA256: &str = "8da085332782708d8767bcace5327a6ec7283c17cfb85e40b03cd2323a90ddc2";
 }
 
 /// Embedded WinDivert files for x86
@@ -25,6 +31,20 @@ mod embedded {
     pub const WINDIVERT_DLL: &[u8] = include_bytes!("../../../resources/windivert/x86/WinDivert.dll");
     pub const WINDIVERT_SYS: &[u8] = include_bytes!("../../../resources/windivert/x86/WinDivert32.sys");
     pub const SYS_NAME: &str = "WinDivert32.sys";
+    pub const SYS_STEP: &str = "Writing WinDivert32.sys";
+    pub const EXPECTED_DLL_SHA256: &str = "a321649090c21aaa7529ce5d019d242b1d5f2a2aff04bc3224db409641604a83";
+    pub const EXPECTED_SYS_SHA256: &str = "2f43f4251be4d72dd56c91bf6cce475d379eb9ba6c4dda2be3022ea633d5e807";
+}
+
+/// A step of [`WinDivertInstaller::install_with_progress`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstallProgress {
+    /// What is being done, e.g. "Writing WinDivert.dll"
+    pub step: &'static str,
+    /// Progress once the step is done, out of `total`
+    pub current: u64,
+    /// Progress of the finished installation
+    pub total: u64,
 }
 
 /// WinDivert installer
@@ -80,22 +100,45 @@ impl WinDivertInstaller {
 
     /// Install WinDivert files
     pub fn install(&self) -> Result<()> {
+        self.install_with_progress(|_| {})
+    }
+
+    /// Install WinDivert files, reporting each step to `callback`
+    ///
+    /// Each step is reported as it starts, with the percentage reached
+    /// when it is done. Used for the GUI's progress bar.
+    pub fn install_with_progress<F: Fn(InstallProgress) + Send>(&self, callback: F) -> Result<()> {
+        let progress = |step, current| callback(InstallProgress { step, current, total: 100 });
         info!("Installing WinDivert to {:?}", self.install_dir);
 
         // Create directory if needed
+        progress("Creating directory", 10);
         fs::create_dir_all(&self.install_dir)
             .context("Failed to create installation directory")?;
 
         // Write DLL
+        progress("Writing WinDivert.dll", 50);
         let dll_path = self.install_dir.join("WinDivert.dll");
         Self::write_file(&dll_path, embedded::WINDIVERT_DLL)?;
         info!("Installed WinDivert.dll");
 
         // Write SYS
+        progress(embedded::SYS_STEP, 90);
         let sys_path = self.install_dir.join(embedded::SYS_NAME);
         Self::write_file(&sys_path, embedded::WINDIVERT_SYS)?;
         info!("Installed {}", embedded::SYS_NAME);
 
+        // A short write would only show when the driver fails to load
+        progress("Verifying files", 100);
+        for (path, data) in [(&dll_path, embedded::WINDIVERT_DLL), (&sys_path, embedded::WINDIVERT_SYS)] {
+            let written = fs::metadata(path)
+                .with_context(|| format!("Failed to read back {:?}", path))?
+                .len();
+            if written != data.len() as u64 {
+                bail!("{:?} has {} bytes, expected {}", path, written, data.len());
+            }
+        }
+
         Ok(())
     }
 
@@ -163,6 +206,35 @@ impl WinDivertInstaller {
         Ok(())
     }
 
+    /// Compare the installed files' SHA-256 against the embedded ones
+    ///
+    /// # Errors
+    /// Returns [`PlatformError::InstallCorrupted`] for the first file that
+    /// differs, or an I/O error if one can't be read.
+    pub fn verify_checksums(&self) -> Result<()> {
+        let files = [
+            ("WinDivert.dll", embedded::EXPECTED_DLL_SHA256),
+            (embedded::SYS_NAME, embedded::EXPECTED_SYS_SHA256),
+        ];
+
+        for (name, expected) in files {
+            let path = self.install_dir.join(name);
+            let data = fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+            let found = sha256_hex(&data);
+            if found != expected {
+                return Err(PlatformError::InstallCorrupted {
+                    file: name.to_string(),
+                    expected: expected.to_string(),
+                    found,
+                }
+                .into());
+            }
+            debug!(file = name, "Checksum verified");
+        }
+
+        Ok(())
+    }
+
     /// Write file with proper error handling
     fn write_file(path: &PathBuf, data: &[u8]) -> Result<()> {
         let mut file = fs::File::create(path)
@@ -251,6 +323,11 @@ impl WinDivertInstaller {
     }
 }
 
+/// SHA-256 of `data` as lowercase hex
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
 impl Default for WinDivertInstaller {
     fn default() -> Self {
         Self::new()
@@ -340,9 +417,55 @@ mod tests {
         assert!(!embedded::WINDIVERT_SYS.is_empty());
     }
 
+    #[test]
+    fn test_embedded_checksums() {
+        assert_eq!(sha256_hex(embedded::WINDIVERT_DLL), embedded::EXPECTED_DLL_SHA256);
+        assert_eq!(sha256_hex(embedded::WINDIVERT_SYS), embedded::EXPECTED_SYS_SHA256);
+    }
+
+    #[test]
+    fn test_verify_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let installer = WinDivertInstaller::with_dir(dir.path().to_path_buf());
+        installer.install().unwrap();
+        installer.verify_checksums().unwrap();
+
+        fs::write(dir.path().join(embedded::SYS_NAME), b"not a driver").unwrap();
+        let err = installer.verify_checksums().unwrap_err();
+        match err.downcast_ref::<PlatformError>() {
+            Some(PlatformError::InstallCorrupted { file, .. }) => assert_eq!(file, embedded::SYS_NAME),
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+
     #[test]
     fn test_default_install_dir() {
         let installer = WinDivertInstaller::new();
         assert!(!installer.install_dir().as_os_str().is_empty());
     }
+
+    #[test]
+    fn test_install_with_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        let installer = WinDivertInstaller::with_dir(dir.path().join("windivert"));
+
+        let steps = std::sync::Mutex::new(Vec::new());
+        installer
+            .install_with_progress(|progress| steps.lock().unwrap().push(progress))
+            .unwrap();
+
+        let steps = steps.into_inner().unwrap();
+        let reached: Vec<_> = steps.iter().map(|p| (p.step, p.current)).collect();
+        assert_eq!(
+            reached,
+            vec![
+                ("Creating directory", 10),
+                ("Writing WinDivert.dll", 50),
+                (embedded::SYS_STEP, 90),
+                ("Verifying files", 100),
+            ]
+        );
+        assert!(steps.iter().all(|p| p.total == 100));
+        assert!(installer.is_installed());
+    }
 }
@@ -0,0 +1,377 @@
+//! Live statistics for other local processes (the GUI)
+//!
+//! A running bypass serves a [`StatsSnapshot`] as one line of JSON to
+//! every client that connects: over the named pipe [`PIPE_NAME`] on
+//! Windows, over a unix socket at [`socket_path`] elsewhere. Both only
+//! admit the account the bypass runs as, since the snapshot lists the
+//! domains it was recently applied to.
+
+use crate::error::Result;
+use gdpi_core::Stats;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tracing::debug;
+
+/// Named pipe the stats are served on (Windows)
+pub const PIPE_NAME: &str = r"\\.\pipe\gdpi-stats";
+
+/// Recently bypassed domains kept in a snapshot
+pub const RECENT_DOMAINS: usize = 20;
+
+/// Longest a client waits for the snapshot once connected
+pub const FETCH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Counters of a running bypass at one point in time
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatsSnapshot {
+    /// Seconds since the bypass started
+    pub uptime_secs: u64,
+    /// Packets captured
+    pub packets_captured: u64,
+    /// Bytes captured
+    pub bytes_captured: u64,
+    /// Packets the pipeline changed
+    pub packets_modified: u64,
+    /// Pipeline errors
+    pub errors: u64,
+    /// Packets fragmented
+    pub packets_fragmented: u64,
+    /// Fake packets sent
+    pub fake_packets_sent: u64,
+    /// QUIC packets blocked
+    pub quic_blocked: u64,
+    /// DNS queries redirected
+    pub dns_redirected: u64,
+    /// Packets dropped
+    pub packets_dropped: u64,
+    /// Domains the filter skipped
+    pub domains_filtered: u64,
+    /// TCP connections currently tracked
+    pub tracked_connections: u64,
+    /// Most packets the capture queue holds (0 if unknown)
+    pub queue_max_len: u64,
+    /// Receives that filled a whole batch, so more packets were waiting
+    /// in the capture queue
+    pub full_batches: u64,
+    /// Hostnames the bypass was applied to, newest first
+    pub recent_domains: Vec<String>,
+}
+
+impl StatsSnapshot {
+    /// Snapshot of the pipeline counters; capture counters and recent
+    /// domains are left for the caller
+    pub fn from_stats(stats: &Stats) -> Self {
+        Self {
+            uptime_secs: stats.start_time.elapsed().as_secs(),
+            packets_fragmented: stats.packets_fragmented,
+            fake_packets_sent: stats.fake_packets_sent,
+            quic_blocked: stats.quic_blocked,
+            dns_redirected: stats.dns_redirected,
+            packets_dropped: stats.packets_dropped,
+            domains_filtered: stats.domains_filtered,
+            tracked_connections: stats.tracked_connections,
+            ..Self::default()
+        }
+    }
+}
+
+/// Path of the stats socket (non-Windows)
+#[cfg(not(windows))]
+pub fn socket_path() -> PathBuf {
+    std::env::temp_dir().join("gdpi-stats.sock")
+}
+
+/// Where the stats are served by default: [`PIPE_NAME`] on Windows,
+/// [`socket_path`] elsewhere
+pub fn default_endpoint() -> PathBuf {
+    #[cfg(windows)]
+    return PathBuf::from(PIPE_NAME);
+    #[cfg(not(windows))]
+    return socket_path();
+}
+
+/// Read a snapshot from the running bypass
+///
+/// Fails with [`std::io::ErrorKind::NotFound`] (or `ConnectionRefused`
+/// for a stale socket) when nothing is serving stats.
+pub fn fetch_stats() -> Result<StatsSnapshot> {
+    fetch_stats_at(&default_endpoint())
+}
+
+/// Read a snapshot from a bypass serving at `endpoint`
+pub fn fetch_stats_at(endpoint: &Path) -> Result<StatsSnapshot> {
+    let mut line = String::new();
+    BufReader::new(connect(endpoint)?).read_line(&mut line)?;
+    serde_json::from_str(&line).map_err(|e| std::io::Error::from(e).into())
+}
+
+#[cfg(windows)]
+fn connect(endpoint: &Path) -> std::io::Result<std::fs::File> {
+    // Opening fails right away with no free instance, and the server
+    // writes as soon as it accepts, so reads don't stall
+    std::fs::File::open(endpoint)
+}
+
+#[cfg(not(windows))]
+fn connect(endpoint: &Path) -> std::io::Result<std::os::unix::net::UnixStream> {
+    let stream = std::os::unix::net::UnixStream::connect(endpoint)?;
+    stream.set_read_timeout(Some(FETCH_TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Serves stats until dropped
+pub struct StatsServer {
+    running: Arc<AtomicBool>,
+    endpoint: PathBuf,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl StatsServer {
+    /// Start serving on a background thread, taking a fresh snapshot from
+    /// `snapshot` for each client
+    pub fn start<F>(snapshot: F) -> Result<Self>
+    where
+        F: Fn() -> StatsSnapshot + Send + 'static,
+    {
+        Self::start_at(default_endpoint(), snapshot)
+    }
+
+    /// Like [`StatsServer::start`], serving at `endpoint` instead of the
+    /// default one
+    pub fn start_at<F>(endpoint: impl Into<PathBuf>, snapshot: F) -> Result<Self>
+    where
+        F: Fn() -> StatsSnapshot + Send + 'static,
+    {
+        let endpoint = endpoint.into();
+        let running = Arc::new(AtomicBool::new(true));
+        let listener = transport::Listener::bind(endpoint.clone())?;
+
+        let worker = {
+            let running = Arc::clone(&running);
+            std::thread::Builder::new()
+                .name("gdpi-stats".into())
+                .spawn(move || {
+                    while running.load(Ordering::SeqCst) {
+                        // Snapshot only once a client is connected, so it
+                        // gets the counters of that moment
+                        let line = || {
+                            let mut json = serde_json::to_vec(&snapshot())?;
+                            json.push(b'\n');
+                            Ok(json)
+                        };
+                        if let Err(e) = listener.serve_one(line) {
+                            debug!("Stats client failed: {}", e);
+                        }
+                    }
+                })?
+        };
+
+        Ok(Self {
+            running,
+            endpoint,
+            worker: Some(worker),
+        })
+    }
+}
+
+impl Drop for StatsServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        // The server blocks waiting for a client; be the last one
+        let _ = connect(&self.endpoint);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod transport {
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+
+    pub struct Listener {
+        listener: UnixListener,
+        path: PathBuf,
+    }
+
+    impl Listener {
+        pub fn bind(path: PathBuf) -> std::io::Result<Self> {
+            // Left behind by a bypass that didn't shut down cleanly
+            if path.exists() && std::os::unix::net::UnixStream::connect(&path).is_err() {
+                std::fs::remove_file(&path)?;
+            }
+            let listener = UnixListener::bind(&path)?;
+            // Snapshots name visited domains; keep other users out
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+            Ok(Self { listener, path })
+        }
+
+        /// Wait for a client and send it what `data` returns
+        pub fn serve_one<F>(&self, data: F) -> std::io::Result<()>
+        where
+            F: FnOnce() -> std::io::Result<Vec<u8>>,
+        {
+            let (mut stream, _) = self.listener.accept()?;
+            stream.write_all(&data()?)
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod transport {
+    use std::fs::File;
+    use std::io::Write;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::FromRawHandle;
+    use std::path::PathBuf;
+    use std::ptr;
+    use winapi::shared::minwindef::FALSE;
+    use winapi::shared::sddl::{
+        ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+    };
+    use winapi::shared::winerror::ERROR_PIPE_CONNECTED;
+    use winapi::um::errhandlingapi::GetLastError;
+    use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+    use winapi::um::minwinbase::SECURITY_ATTRIBUTES;
+    use winapi::um::namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW};
+    use winapi::um::winbase::{
+        LocalFree, PIPE_ACCESS_OUTBOUND, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE,
+        PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    /// Output buffer for one snapshot
+    const BUFFER_SIZE: u32 = 64 * 1024;
+
+    /// Protected DACL granting full access to the pipe owner only, since
+    /// snapshots name visited domains
+    const OWNER_ONLY_SDDL: &str = "D:P(A;;GA;;;OW)";
+
+    pub struct Listener {
+        name: Vec<u16>,
+    }
+
+    impl Listener {
+        pub fn bind(path: PathBuf) -> std::io::Result<Self> {
+            let name = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+            Ok(Self { name })
+        }
+
+        /// Create a pipe instance, wait for a client and send it what
+        /// `data` returns
+        pub fn serve_one<F>(&self, data: F) -> std::io::Result<()>
+        where
+            F: FnOnce() -> std::io::Result<Vec<u8>>,
+        {
+            let sddl: Vec<u16> = OWNER_ONLY_SDDL.encode_utf16().chain(std::iter::once(0)).collect();
+            let mut descriptor = ptr::null_mut();
+            let converted = unsafe {
+                ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                    sddl.as_ptr(),
+                    SDDL_REVISION_1 as u32,
+                    &mut descriptor,
+                    ptr::null_mut(),
+                )
+            };
+            if converted == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let mut attributes = SECURITY_ATTRIBUTES {
+                nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+                lpSecurityDescriptor: descriptor,
+                bInheritHandle: FALSE,
+            };
+
+            let handle = unsafe {
+                CreateNamedPipeW(
+                    self.name.as_ptr(),
+                    PIPE_ACCESS_OUTBOUND,
+                    PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                    PIPE_UNLIMITED_INSTANCES,
+                    BUFFER_SIZE,
+                    0,
+                    0,
+                    &mut attributes,
+                )
+            };
+            let created = std::io::Error::last_os_error();
+            unsafe { LocalFree(descriptor) };
+            if handle == INVALID_HANDLE_VALUE {
+                return Err(created);
+            }
+            // Closes the instance when done
+            let mut pipe = unsafe { File::from_raw_handle(handle.cast()) };
+
+            let connected = unsafe { ConnectNamedPipe(handle, ptr::null_mut()) } != 0
+                || unsafe { GetLastError() } == ERROR_PIPE_CONNECTED;
+            if !connected {
+                return Err(std::io::Error::last_os_error());
+            }
+            pipe.write_all(&data()?)?;
+            // Let the client read everything before the instance closes
+            pipe.sync_all()
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serve_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.sock");
+        let snapshot = StatsSnapshot {
+            packets_captured: 42,
+            recent_domains: vec!["discord.com".into()],
+            ..StatsSnapshot::default()
+        };
+
+        let server = {
+            let snapshot = snapshot.clone();
+            StatsServer::start_at(&path, move || snapshot.clone()).unwrap()
+        };
+        assert_eq!(fetch_stats_at(&path).unwrap(), snapshot);
+        assert_eq!(fetch_stats_at(&path).unwrap(), snapshot);
+
+        drop(server);
+        assert!(!path.exists());
+        assert!(fetch_stats_at(&path).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_taken_per_client() {
+        use std::sync::atomic::AtomicU64;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.sock");
+        let taken = Arc::new(AtomicU64::new(0));
+
+        let _server = {
+            let taken = Arc::clone(&taken);
+            StatsServer::start_at(&path, move || StatsSnapshot {
+                packets_captured: taken.fetch_add(1, Ordering::SeqCst) + 1,
+                ..StatsSnapshot::default()
+            })
+            .unwrap()
+        };
+        assert_eq!(taken.load(Ordering::SeqCst), 0);
+        assert_eq!(fetch_stats_at(&path).unwrap().packets_captured, 1);
+        assert_eq!(fetch_stats_at(&path).unwrap().packets_captured, 2);
+    }
+}
@@ -5,8 +5,11 @@
 //! ## Supported Platforms
 //!
 //! - **Windows**: WinDivert driver
-//! - **Linux**: (Future) NFQUEUE
+//! - **Linux**: NFQUEUE (`nfqueue` feature)
 //! - **macOS**: (Future) Network Extension API
+//!
+//! [`PcapCapture`] and [`PcapReplayCapture`] replay capture files on any
+//! platform.
 
 #![warn(missing_docs)]
 #![warn(clippy::all)]
@@ -20,9 +23,23 @@ pub mod windows;
 #[cfg(windows)]
 pub use windows::WinDivertDriver;
 
+#[cfg(target_os = "linux")]
+pub mod linux;
+
+#[cfg(all(target_os = "linux", feature = "nfqueue"))]
+pub use linux::NfqueueCapture;
+
+pub mod pcap;
+pub use pcap::{PcapCapture, PcapReplayCapture};
+
+pub mod ipc;
+pub use ipc::{StatsServer, StatsSnapshot};
+
+mod reopen;
+
 // Platform-agnostic traits
 mod traits;
-pub use traits::{PacketCapture, PacketFilter};
+pub use traits::{CapturedPacket, PacketAddress, PacketCapture, PacketFilter};
 
 // Driver installer
 #[cfg(windows)]
@@ -0,0 +1,106 @@
+//! Raw socket packet injection
+
+use crate::error::{PlatformError, Result};
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tracing::warn;
+
+/// `IPPROTO_RAW`: the packet carries its own IP header
+const IPPROTO_RAW: i32 = 255;
+
+/// Sends complete IP packets through raw sockets
+///
+/// Needs `CAP_NET_RAW`. Every packet is tagged with a firewall mark so
+/// queue rules can recognise it.
+pub struct RawInjector {
+    v4: Socket,
+    v6: Option<Socket>,
+}
+
+impl RawInjector {
+    /// Open raw sockets that mark their packets with `mark`
+    ///
+    /// IPv6 injection is disabled (with a warning) if the IPv6 socket
+    /// can't be opened.
+    pub fn new(mark: u32) -> Result<Self> {
+        let v4 = open(Domain::IPV4, mark)?;
+        let v6 = match open(Domain::IPV6, mark) {
+            Ok(socket) => Some(socket),
+            Err(e) => {
+                warn!(error = %e, "IPv6 raw socket unavailable, IPv6 injection disabled");
+                None
+            }
+        };
+
+        Ok(Self { v4, v6 })
+    }
+
+    /// Send a packet to the destination in its IP header
+    pub fn send(&self, packet: &[u8]) -> Result<()> {
+        let dst = destination(packet)
+            .ok_or_else(|| PlatformError::InjectionError("Not an IP packet".into()))?;
+
+        let socket = match dst {
+            IpAddr::V4(_) => &self.v4,
+            IpAddr::V6(_) => self
+                .v6
+                .as_ref()
+                .ok_or_else(|| PlatformError::InjectionError("IPv6 injection unavailable".into()))?,
+        };
+
+        socket.send_to(packet, &SockAddr::from(SocketAddr::new(dst, 0)))?;
+        Ok(())
+    }
+}
+
+fn open(domain: Domain, mark: u32) -> Result<Socket> {
+    // IPPROTO_RAW implies the IP header is included
+    let socket = Socket::new(domain, Type::RAW, Some(Protocol::from(IPPROTO_RAW))).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::PermissionDenied {
+            PlatformError::PermissionDenied("Raw sockets need CAP_NET_RAW".into())
+        } else {
+            e.into()
+        }
+    })?;
+    socket.set_mark(mark)?;
+    Ok(socket)
+}
+
+/// Destination address from an IPv4/IPv6 header
+pub(crate) fn destination(packet: &[u8]) -> Option<IpAddr> {
+    match packet.first()? >> 4 {
+        4 => {
+            let addr: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+            Some(Ipv4Addr::from(addr).into())
+        }
+        6 => {
+            let addr: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+            Some(Ipv6Addr::from(addr).into())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gdpi_core::packet::PacketBuilder;
+
+    #[test]
+    fn test_destination() {
+        let v4 = PacketBuilder::tcp_v4()
+            .src_ip_v4([10, 0, 0, 1])
+            .dst_ip_v4([93, 184, 216, 34])
+            .build_bytes();
+        assert_eq!(destination(&v4), Some("93.184.216.34".parse().unwrap()));
+
+        let mut v6 = vec![0u8; 40];
+        v6[0] = 0x60;
+        v6[39] = 1;
+        assert_eq!(destination(&v6), Some("::1".parse().unwrap()));
+
+        assert_eq!(destination(&[]), None);
+        assert_eq!(destination(&[0x45, 0, 0]), None);
+        assert_eq!(destination(&[0x20; 40]), None);
+    }
+}
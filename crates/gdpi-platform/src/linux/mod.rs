@@ -0,0 +1,261 @@
+//! Linux platform implementation using NFQUEUE
+//!
+//! iptables rules hand matching packets to a netfilter queue; the capture
+//! reads them from there and answers each one with a verdict. Packets the
+//! pipeline adds (fake packets, leading fragments) go out through a raw
+//! socket carrying [`INJECT_MARK`], which the rules skip so they aren't
+//! queued again.
+
+mod inject;
+#[cfg(feature = "nfqueue")]
+mod nfqueue;
+
+pub use inject::RawInjector;
+#[cfg(feature = "nfqueue")]
+pub use nfqueue::NfqueueCapture;
+
+use gdpi_core::packet::Direction;
+use gdpi_core::Config;
+
+/// Firewall mark set on injected packets
+pub const INJECT_MARK: u32 = 0x4744;
+
+/// iptables/ip6tables rules that queue the traffic `config` handles on
+/// `queue_num`
+///
+/// Outbound TCP to the bypass ports (80 and 443 are always included) is
+/// queued for the strategies, and inbound SYN-ACKs from them for TTL
+/// tracking. With passive DPI blocking on, inbound resets from them are
+/// queued too, and with `http_all_ports` outbound TCP to any port is.
+/// DNS redirection adds queries to port 53 and the upstreams' responses,
+/// QUIC blocking outbound UDP to 443. `--queue-bypass` lets traffic
+/// through if nothing is bound to the queue.
+pub fn iptables_rules(queue_num: u16, config: &Config) -> Vec<String> {
+    let mut all_ports = vec![80, 443];
+    for port in config.bypass_ports() {
+        if !all_ports.contains(&port) {
+            all_ports.push(port);
+        }
+    }
+    let ports = all_ports
+        .iter()
+        .map(u16::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    let target = format!("-j NFQUEUE --queue-num {} --queue-bypass", queue_num);
+    let passive_dpi = config.strategies.passive_dpi.enabled;
+    let quic_block = config.strategies.quic_block.enabled;
+    let dns_ports = dns_upstream_ports(config);
+    // HTTP is looked for on every port, so every port is queued
+    let dst_ports = if config.performance.http_all_ports {
+        String::new()
+    } else {
+        format!(" -m multiport --dports {}", ports)
+    };
+
+    ["iptables", "ip6tables"]
+        .iter()
+        .flat_map(|cmd| {
+            let mut rules = vec![
+                format!(
+                    "{} -t mangle -A POSTROUTING -p tcp{} -m mark ! --mark {:#x} {}",
+                    cmd, dst_ports, INJECT_MARK, target
+                ),
+                format!(
+                    "{} -t mangle -A PREROUTING -p tcp -m multiport --sports {} --tcp-flags SYN,ACK SYN,ACK {}",
+                    cmd, ports, target
+                ),
+            ];
+            if passive_dpi {
+                rules.push(format!(
+                    "{} -t mangle -A PREROUTING -p tcp -m multiport --sports {} --tcp-flags RST RST {}",
+                    cmd, ports, target
+                ));
+            }
+            if !dns_ports.is_empty() {
+                for proto in ["udp", "tcp"] {
+                    rules.push(format!(
+                        "{} -t mangle -A POSTROUTING -p {} --dport 53 -m mark ! --mark {:#x} {}",
+                        cmd, proto, INJECT_MARK, target
+                    ));
+                    // Answers injected for DNS over HTTPS loop back in marked
+                    rules.push(format!(
+                        "{} -t mangle -A PREROUTING -p {} -m multiport --sports {} -m mark ! --mark {:#x} {}",
+                        cmd, proto, dns_ports, INJECT_MARK, target
+                    ));
+                }
+            }
+            if quic_block {
+                rules.push(format!(
+                    "{} -t mangle -A POSTROUTING -p udp --dport 443 -m mark ! --mark {:#x} {}",
+                    cmd, INJECT_MARK, target
+                ));
+            }
+            rules
+        })
+        .collect()
+}
+
+/// Ports DNS responses come back from, comma separated, or empty with DNS
+/// redirection off
+fn dns_upstream_ports(config: &Config) -> String {
+    let dns = &config.dns;
+    if !dns.enabled {
+        return String::new();
+    }
+    let mut ports: Vec<u16> = Vec::new();
+    let upstreams = [
+        dns.ipv4_upstream.map(|_| dns.ipv4_port.unwrap_or(53)),
+        dns.ipv6_upstream.map(|_| dns.ipv6_port.unwrap_or(53)),
+    ];
+    for port in upstreams.into_iter().flatten() {
+        if !ports.contains(&port) {
+            ports.push(port);
+        }
+    }
+    ports.iter().map(u16::to_string).collect::<Vec<_>>().join(",")
+}
+
+/// Verdict for a queued packet
+#[cfg_attr(not(feature = "nfqueue"), allow(dead_code))]
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Verdict<'a> {
+    /// Let the original through unchanged
+    Accept,
+    /// Let it through with this content instead
+    Replace(&'a [u8]),
+    /// Drop it
+    Drop,
+}
+
+/// Split pipeline output into packets to inject first and the verdict for
+/// the queued original
+///
+/// The last output packet takes the original's place in the queue, so
+/// send order is kept: `[fake, original]` injects the fake and accepts,
+/// `[frag1, frag2]` injects `frag1` and replaces the original with `frag2`.
+#[cfg_attr(not(feature = "nfqueue"), allow(dead_code))]
+pub(crate) fn plan<'a>(original: &[u8], output: &'a [Vec<u8>]) -> (&'a [Vec<u8>], Verdict<'a>) {
+    match output.split_last() {
+        None => (&[], Verdict::Drop),
+        Some((last, inject)) if last.as_slice() == original => (inject, Verdict::Accept),
+        Some((last, inject)) => (inject, Verdict::Replace(last)),
+    }
+}
+
+/// Direction of a queued packet from its netfilter output device
+///
+/// Only locally delivered packets (no output device) count as inbound;
+/// forwarded traffic is treated as outbound, so on a router replies from
+/// servers are passed over by the outbound-only strategies.
+#[cfg_attr(not(feature = "nfqueue"), allow(dead_code))]
+pub(crate) fn direction(outdev: u32) -> Direction {
+    if outdev == 0 {
+        Direction::Inbound
+    } else {
+        Direction::Outbound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        let original = vec![1, 2, 3];
+
+        assert_eq!(plan(&original, &[]), (&[][..], Verdict::Drop));
+        assert_eq!(plan(&original, std::slice::from_ref(&original)), (&[][..], Verdict::Accept));
+
+        // Fake packet before the original
+        let fake = vec![9];
+        let output = [fake.clone(), original.clone()];
+        assert_eq!(plan(&original, &output), (&[fake][..], Verdict::Accept));
+
+        // Fragments replace the original
+        let output = [vec![1], vec![2, 3]];
+        assert_eq!(plan(&original, &output), (&[vec![1]][..], Verdict::Replace(&[2, 3])));
+    }
+
+    #[test]
+    fn test_direction() {
+        assert_eq!(direction(0), Direction::Inbound);
+        assert_eq!(direction(2), Direction::Outbound);
+    }
+
+    #[test]
+    fn test_iptables_rules() {
+        let mut config = Config::default();
+        config.strategies.passive_dpi.enabled = false;
+        config.strategies.quic_block.enabled = false;
+        config.dns.enabled = false;
+        config.performance.additional_ports = vec![443, 8443];
+        let rules = iptables_rules(3, &config);
+        assert_eq!(rules.len(), 4);
+        assert!(rules[0].starts_with("iptables -t mangle -A POSTROUTING"));
+        assert!(rules[0].contains("--dports 80,443,8443"));
+        assert!(rules[0].contains("! --mark 0x4744"));
+        assert!(rules[1].contains("--sports 80,443,8443"));
+        assert!(rules.iter().all(|rule| rule.ends_with("--queue-num 3 --queue-bypass")));
+        assert!(rules[2].starts_with("ip6tables"));
+
+        // Forged resets only reach the pipeline with passive DPI blocking on
+        config.strategies.passive_dpi.enabled = true;
+        let rules = iptables_rules(3, &config);
+        assert_eq!(rules.len(), 6);
+        assert!(rules[2].contains("--sports 80,443,8443 --tcp-flags RST RST"));
+
+        config.performance.http_all_ports = true;
+        let rules = iptables_rules(3, &config);
+        assert_eq!(
+            rules[0],
+            "iptables -t mangle -A POSTROUTING -p tcp -m mark ! --mark 0x4744 \
+             -j NFQUEUE --queue-num 3 --queue-bypass"
+        );
+        assert!(rules[1].contains("--sports 80,443,8443"));
+    }
+
+    #[test]
+    fn test_iptables_rules_dns_and_quic() {
+        let mut config = Config::default();
+        config.strategies.passive_dpi.enabled = false;
+        config.strategies.quic_block.enabled = false;
+        config.dns.enabled = false;
+        assert_eq!(iptables_rules(0, &config).len(), 4);
+
+        config.dns.enabled = true;
+        config.dns.ipv4_upstream = Some("77.88.8.8".parse().unwrap());
+        config.dns.ipv4_port = Some(1253);
+        config.dns.ipv6_upstream = None;
+        let rules = iptables_rules(0, &config);
+        assert_eq!(rules.len(), 12);
+        assert_eq!(
+            rules[2],
+            "iptables -t mangle -A POSTROUTING -p udp --dport 53 -m mark ! --mark 0x4744 \
+             -j NFQUEUE --queue-num 0 --queue-bypass"
+        );
+        assert_eq!(
+            rules[3],
+            "iptables -t mangle -A PREROUTING -p udp -m multiport --sports 1253 -m mark ! --mark 0x4744 \
+             -j NFQUEUE --queue-num 0 --queue-bypass"
+        );
+        assert!(rules[4].contains("-p tcp --dport 53"));
+        assert!(rules[5].contains("-p tcp -m multiport --sports 1253"));
+
+        // A redirect without upstreams has nothing to queue
+        config.dns.ipv4_upstream = None;
+        assert_eq!(iptables_rules(0, &config).len(), 4);
+
+        config.dns.enabled = false;
+        config.strategies.quic_block.enabled = true;
+        let rules = iptables_rules(0, &config);
+        assert_eq!(rules.len(), 6);
+        assert_eq!(
+            rules[2],
+            "iptables -t mangle -A POSTROUTING -p udp --dport 443 -m mark ! --mark 0x4744 \
+             -j NFQUEUE --queue-num 0 --queue-bypass"
+        );
+        assert!(rules[5].starts_with("ip6tables -t mangle -A POSTROUTING -p udp --dport 443"));
+    }
+}
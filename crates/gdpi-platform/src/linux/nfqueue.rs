@@ -0,0 +1,205 @@
+//! NFQUEUE packet capture via libnetfilter_queue
+
+use super::{direction, plan, RawInjector, Verdict, INJECT_MARK};
+use crate::error::{PlatformError, Result};
+use crate::traits::{CapturedPacket, PacketAddress, PacketCapture, PacketFilter};
+use bytes::Bytes;
+use gdpi_core::packet::Direction;
+use nfq::{Message, Queue};
+use std::collections::HashMap;
+use tracing::{debug, info, warn};
+
+/// Packet capture from a netfilter queue
+///
+/// Every packet returned by [`recv`](PacketCapture::recv) is held by the
+/// kernel until it gets a verdict through [`verdict`](Self::verdict),
+/// [`send`](PacketCapture::send) or [`send_batch`](PacketCapture::send_batch).
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use gdpi_platform::linux::NfqueueCapture;
+/// use gdpi_platform::PacketCapture;
+///
+/// // iptables ... -j NFQUEUE --queue-num 0
+/// let mut capture = NfqueueCapture::open(0)?;
+///
+/// loop {
+///     let captured = capture.recv()?;
+///     // Process packet...
+///     capture.verdict(&captured.address, &[captured.data.clone()])?;
+/// }
+/// ```
+pub struct NfqueueCapture {
+    /// Netlink queue handle (None once closed)
+    queue: Option<Queue>,
+    /// Bound queue number
+    queue_num: u16,
+    /// Packets waiting for a verdict, by packet id
+    pending: HashMap<u32, Message>,
+    /// Injector for packets added by the pipeline
+    injector: RawInjector,
+    /// Description returned by `get_filter`
+    filter: String,
+}
+
+impl NfqueueCapture {
+    /// Bind to netfilter queue `queue_num` (the rule's `--queue-num`)
+    ///
+    /// # Errors
+    /// Returns error without `CAP_NET_ADMIN`/`CAP_NET_RAW`, or if another
+    /// process already has the queue bound.
+    pub fn open(queue_num: u16) -> Result<Self> {
+        let mut queue = Queue::open()
+            .map_err(|e| PlatformError::DriverInitFailed(format!("NFQUEUE open failed: {}", e)))?;
+        queue.bind(queue_num).map_err(|e| {
+            PlatformError::DriverInitFailed(format!("Binding queue {} failed: {}", queue_num, e))
+        })?;
+        // Accept packets in the kernel rather than drop them if we fall behind
+        queue.set_fail_open(queue_num, true)?;
+
+        info!(queue = queue_num, "Bound NFQUEUE");
+
+        Ok(Self {
+            queue: Some(queue),
+            queue_num,
+            pending: HashMap::new(),
+            injector: RawInjector::new(INJECT_MARK)?,
+            filter: format!("nfqueue {}", queue_num),
+        })
+    }
+
+    /// Bound queue number
+    pub fn queue_num(&self) -> u16 {
+        self.queue_num
+    }
+
+    /// Answer a queued packet with the pipeline's output
+    ///
+    /// Empty output drops the packet. Otherwise every packet but the last
+    /// is injected, then the original is accepted - with the last packet's
+    /// content if that differs.
+    pub fn verdict(&mut self, addr: &PacketAddress, output: &[Vec<u8>]) -> Result<()> {
+        let mut msg = self.pending.remove(&addr.packet_id).ok_or_else(|| {
+            PlatformError::InjectionError(format!("No queued packet with id {}", addr.packet_id))
+        })?;
+
+        let (inject, verdict) = plan(msg.get_payload(), output);
+        for packet in inject {
+            // Still answer the original if an extra packet can't be sent
+            if let Err(e) = self.injector.send(packet) {
+                warn!(error = %e, "Failed to inject packet");
+            }
+        }
+
+        match verdict {
+            Verdict::Accept => msg.set_verdict(nfq::Verdict::Accept),
+            Verdict::Replace(payload) => {
+                msg.set_payload(payload.to_vec());
+                msg.set_verdict(nfq::Verdict::Accept);
+            }
+            Verdict::Drop => msg.set_verdict(nfq::Verdict::Drop),
+        }
+
+        self.queue_mut()?
+            .verdict(msg)
+            .map_err(|e| PlatformError::InjectionError(format!("Verdict failed: {}", e)))
+    }
+
+    fn queue_mut(&mut self) -> Result<&mut Queue> {
+        self.queue
+            .as_mut()
+            .ok_or_else(|| PlatformError::HandleError("Queue closed".into()))
+    }
+}
+
+impl PacketCapture for NfqueueCapture {
+    fn recv(&mut self) -> Result<CapturedPacket> {
+        let msg = self
+            .queue_mut()?
+            .recv()
+            .map_err(|e| PlatformError::CaptureError(format!("Recv failed: {}", e)))?;
+
+        let data = msg.get_payload().to_vec();
+        let direction = direction(msg.get_outdev());
+        let outbound = direction == Direction::Outbound;
+        let address = PacketAddress {
+            interface_index: if outbound { msg.get_outdev() } else { msg.get_indev() },
+            outbound,
+            ipv6: data.first().is_some_and(|b| b >> 4 == 6),
+            packet_id: msg.get_packet_id(),
+            ..Default::default()
+        };
+
+        self.pending.insert(address.packet_id, msg);
+
+        Ok(CapturedPacket {
+            data,
+            direction,
+            interface_index: address.interface_index,
+            subinterface_index: 0,
+            address,
+        })
+    }
+
+    /// Accept the queued packet `addr` refers to with `packet` as its
+    /// content, or inject `packet` if nothing is queued under that id
+    fn send(&mut self, packet: &[u8], addr: &PacketAddress) -> Result<()> {
+        if self.pending.contains_key(&addr.packet_id) {
+            self.verdict(addr, &[packet.to_vec()])
+        } else {
+            self.injector.send(packet)
+        }
+    }
+
+    /// Treats `packets` as the pipeline output for the queued packet the
+    /// last address refers to (see [`NfqueueCapture::verdict`])
+    fn send_batch(&mut self, packets: &[(Bytes, PacketAddress)]) -> Result<u32> {
+        let Some((_, addr)) = packets.last() else {
+            return Ok(0);
+        };
+        let output: Vec<Vec<u8>> = packets.iter().map(|(data, _)| data.to_vec()).collect();
+        self.verdict(addr, &output)?;
+        Ok(packets.len() as u32)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let Some(mut queue) = self.queue.take() {
+            // Don't leave packets stuck in the kernel
+            for (_, mut msg) in self.pending.drain() {
+                msg.set_verdict(nfq::Verdict::Accept);
+                let _ = queue.verdict(msg);
+            }
+            if let Err(e) = queue.unbind(self.queue_num) {
+                debug!(error = %e, "Failed to unbind queue");
+            }
+            info!("Closed NFQUEUE");
+        }
+        Ok(())
+    }
+}
+
+impl PacketFilter for NfqueueCapture {
+    fn set_filter(&mut self, _filter: &str) -> Result<()> {
+        Err(PlatformError::InvalidFilter(
+            "NFQUEUE traffic is selected by iptables rules - see linux::iptables_rules".into(),
+        ))
+    }
+
+    fn get_filter(&self) -> &str {
+        &self.filter
+    }
+
+    fn validate_filter(filter: &str) -> Result<()> {
+        if filter.is_empty() {
+            return Err(PlatformError::InvalidFilter("Empty filter".into()));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for NfqueueCapture {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
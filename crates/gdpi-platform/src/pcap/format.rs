@@ -0,0 +1,416 @@
+//! Minimal pcap/pcapng reader and pcap writer
+//!
+//! Only what replaying captures needs: frames and their link type.
+//! Timestamps, options and non-packet blocks are skipped when reading;
+//! written files are classic pcap with raw IP frames.
+
+use crate::error::{PlatformError, Result};
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Classic pcap magic, microsecond timestamps
+const PCAP_MAGIC_US: u32 = 0xa1b2_c3d4;
+/// Classic pcap magic, nanosecond timestamps
+const PCAP_MAGIC_NS: u32 = 0xa1b2_3c4d;
+/// pcapng Section Header Block type (same in either byte order)
+const PCAPNG_SHB: u32 = 0x0a0d_0d0a;
+/// pcapng byte-order magic
+const PCAPNG_BYTE_ORDER: u32 = 0x1a2b_3c4d;
+
+/// pcapng Interface Description Block
+const PCAPNG_IDB: u32 = 1;
+/// pcapng Simple Packet Block
+const PCAPNG_SPB: u32 = 3;
+/// pcapng Enhanced Packet Block
+const PCAPNG_EPB: u32 = 6;
+
+/// Link types (see tcpdump.org/linktypes.html)
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LOOP: u32 = 108;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+/// One captured frame, still carrying its link-layer header
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Frame<'a> {
+    pub linktype: u32,
+    pub data: &'a [u8],
+}
+
+#[derive(Debug)]
+enum Format {
+    Pcap { linktype: u32 },
+    PcapNg { linktypes: Vec<u32> },
+}
+
+/// Iterates over the frames of an in-memory pcap or pcapng file
+#[derive(Debug)]
+pub(crate) struct PcapReader {
+    data: Vec<u8>,
+    pos: usize,
+    big_endian: bool,
+    format: Format,
+}
+
+impl PcapReader {
+    /// Detect the format from the file header
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        let magic = data
+            .get(..4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .ok_or_else(|| invalid("file too short"))?;
+
+        if magic == PCAPNG_SHB {
+            let mut reader = Self {
+                data,
+                pos: 0,
+                big_endian: false,
+                format: Format::PcapNg { linktypes: Vec::new() },
+            };
+            reader.section_byte_order(0)?;
+            return Ok(reader);
+        }
+
+        let big_endian = match magic {
+            PCAP_MAGIC_US | PCAP_MAGIC_NS => false,
+            m if m.swap_bytes() == PCAP_MAGIC_US || m.swap_bytes() == PCAP_MAGIC_NS => true,
+            m => return Err(invalid(&format!("unknown magic {:#010x}", m))),
+        };
+        let mut reader = Self {
+            data,
+            pos: 24,
+            big_endian,
+            format: Format::Pcap { linktype: 0 },
+        };
+        let linktype = reader.u32_at(20).ok_or_else(|| invalid("truncated file header"))?;
+        reader.format = Format::Pcap { linktype };
+        Ok(reader)
+    }
+
+    /// Next frame, `None` at the end of the file
+    pub fn next_frame(&mut self) -> Result<Option<Frame<'_>>> {
+        if self.pos >= self.data.len() {
+            return Ok(None);
+        }
+        match self.format {
+            Format::Pcap { linktype } => self.next_pcap(linktype),
+            Format::PcapNg { .. } => self.next_pcapng(),
+        }
+    }
+
+    fn next_pcap(&mut self, linktype: u32) -> Result<Option<Frame<'_>>> {
+        let caplen = self.u32_at(self.pos + 8).ok_or_else(|| invalid("truncated record header"))? as usize;
+        let start = self.pos + 16;
+        let end = start + caplen;
+        if end > self.data.len() {
+            return Err(invalid("truncated packet record"));
+        }
+        self.pos = end;
+        Ok(Some(Frame {
+            linktype,
+            data: &self.data[start..end],
+        }))
+    }
+
+    fn next_pcapng(&mut self) -> Result<Option<Frame<'_>>> {
+        loop {
+            if self.pos >= self.data.len() {
+                return Ok(None);
+            }
+            let start = self.pos;
+            let block_type = self.u32_at(start).ok_or_else(|| invalid("truncated block"))?;
+            if block_type == PCAPNG_SHB {
+                // A new section may switch byte order and resets interfaces
+                self.section_byte_order(start)?;
+            }
+            let total_len = self.u32_at(start + 4).ok_or_else(|| invalid("truncated block"))? as usize;
+            if total_len < 12 || !total_len.is_multiple_of(4) || start + total_len > self.data.len() {
+                return Err(invalid(&format!("bad block length {} at offset {}", total_len, start)));
+            }
+            self.pos = start + total_len;
+            let body = start + 8..start + total_len - 4;
+
+            let (interface, data) = match block_type {
+                PCAPNG_IDB => {
+                    let linktype = self.u16_at(body.start).ok_or_else(|| invalid("truncated interface"))?;
+                    if let Format::PcapNg { linktypes } = &mut self.format {
+                        linktypes.push(u32::from(linktype));
+                    }
+                    continue;
+                }
+                PCAPNG_EPB => {
+                    let interface = self.u32_at(body.start).ok_or_else(|| invalid("truncated packet"))?;
+                    let caplen = self.u32_at(body.start + 12).ok_or_else(|| invalid("truncated packet"))? as usize;
+                    let data = body.start + 20..body.start + 20 + caplen;
+                    if data.end > body.end {
+                        return Err(invalid("packet overruns its block"));
+                    }
+                    (interface as usize, data)
+                }
+                PCAPNG_SPB => {
+                    let orig_len = self.u32_at(body.start).ok_or_else(|| invalid("truncated packet"))? as usize;
+                    // Padded to 32 bits, and possibly cut at the snap length
+                    let end = (body.start + 4 + orig_len).min(body.end);
+                    (0, body.start + 4..end)
+                }
+                _ => continue,
+            };
+
+            let Format::PcapNg { linktypes } = &self.format else {
+                unreachable!("pcapng blocks in a classic pcap")
+            };
+            let linktype = *linktypes
+                .get(interface)
+                .ok_or_else(|| invalid(&format!("packet for undeclared interface {}", interface)))?;
+            return Ok(Some(Frame {
+                linktype,
+                data: &self.data[data],
+            }));
+        }
+    }
+
+    /// Read the byte order of the section header at `start`
+    fn section_byte_order(&mut self, start: usize) -> Result<()> {
+        let magic = self
+            .data
+            .get(start + 8..start + 12)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .ok_or_else(|| invalid("truncated section header"))?;
+        self.big_endian = match magic {
+            PCAPNG_BYTE_ORDER => false,
+            m if m.swap_bytes() == PCAPNG_BYTE_ORDER => true,
+            m => return Err(invalid(&format!("bad byte-order magic {:#010x}", m))),
+        };
+        self.format = Format::PcapNg { linktypes: Vec::new() };
+        Ok(())
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+}
+
+/// Writes IP packets to a classic pcap stream
+pub(crate) struct PcapWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Write the file header
+    pub fn new(mut out: W) -> Result<Self> {
+        out.write_all(&PCAP_MAGIC_US.to_le_bytes())?;
+        out.write_all(&2u16.to_le_bytes())?; // version 2.4
+        out.write_all(&4u16.to_le_bytes())?;
+        out.write_all(&[0; 8])?; // timezone, accuracy
+        out.write_all(&65535u32.to_le_bytes())?; // snap length
+        out.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+        Ok(Self { out })
+    }
+
+    /// Append one packet, stamped with the current time
+    pub fn write_packet(&mut self, packet: &[u8]) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let len = u32::try_from(packet.len())
+            .map_err(|_| PlatformError::InjectionError("Packet too large for pcap".into()))?;
+        self.out.write_all(&(now.as_secs() as u32).to_le_bytes())?;
+        self.out.write_all(&now.subsec_micros().to_le_bytes())?;
+        self.out.write_all(&len.to_le_bytes())?;
+        self.out.write_all(&len.to_le_bytes())?;
+        self.out.write_all(packet)?;
+        Ok(())
+    }
+
+    /// Flush buffered output
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Strip the link-layer header, returning the IP packet
+///
+/// `None` for non-IP frames and unsupported link types. Trailing padding
+/// (e.g. Ethernet minimum frame size) is cut at the IP length.
+pub(crate) fn ip_packet<'a>(frame: &Frame<'a>) -> Option<&'a [u8]> {
+    let data = frame.data;
+    let ip = match frame.linktype {
+        LINKTYPE_NULL | LINKTYPE_LOOP => data.get(4..)?,
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ethertype = u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?);
+            // 802.1Q / 802.1ad tags
+            while ethertype == 0x8100 || ethertype == 0x88a8 {
+                offset += 4;
+                ethertype = u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?);
+            }
+            if ethertype != 0x0800 && ethertype != 0x86dd {
+                return None;
+            }
+            data.get(offset + 2..)?
+        }
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => data,
+        LINKTYPE_LINUX_SLL => data.get(16..)?,
+        LINKTYPE_LINUX_SLL2 => data.get(20..)?,
+        _ => return None,
+    };
+
+    let len = match ip.first()? >> 4 {
+        4 => usize::from(u16::from_be_bytes(ip.get(2..4)?.try_into().ok()?)),
+        6 => 40 + usize::from(u16::from_be_bytes(ip.get(4..6)?.try_into().ok()?)),
+        _ => return None,
+    };
+    ip.get(..len.min(ip.len()))
+}
+
+fn invalid(reason: &str) -> PlatformError {
+    PlatformError::CaptureError(format!("Invalid capture file: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ipv4(payload_len: u16) -> Vec<u8> {
+        let mut ip = vec![0u8; 20 + payload_len as usize];
+        ip[0] = 0x45;
+        ip[2..4].copy_from_slice(&(20 + payload_len).to_be_bytes());
+        ip
+    }
+
+    #[test]
+    fn test_classic_big_endian() {
+        let packet = ipv4(4);
+        let mut file = Vec::new();
+        file.extend_from_slice(&PCAP_MAGIC_NS.to_be_bytes());
+        file.extend_from_slice(&[0, 2, 0, 4]);
+        file.extend_from_slice(&[0; 12]);
+        file.extend_from_slice(&LINKTYPE_RAW.to_be_bytes());
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+        file.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+        file.extend_from_slice(&packet);
+
+        let mut reader = PcapReader::new(file).unwrap();
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!(frame.linktype, LINKTYPE_RAW);
+        assert_eq!(ip_packet(&frame), Some(&packet[..]));
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_pcapng() {
+        fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+            let mut body = body.to_vec();
+            body.resize(body.len().div_ceil(4) * 4, 0);
+            let len = (body.len() + 12) as u32;
+            let mut out = Vec::new();
+            out.extend_from_slice(&block_type.to_le_bytes());
+            out.extend_from_slice(&len.to_le_bytes());
+            out.extend_from_slice(&body);
+            out.extend_from_slice(&len.to_le_bytes());
+            out
+        }
+
+        // SLL frame carrying IPv4
+        let packet = ipv4(3);
+        let mut sll = vec![0u8; 16];
+        sll[14..16].copy_from_slice(&0x0800u16.to_be_bytes());
+        sll.extend_from_slice(&packet);
+
+        let mut epb = Vec::new();
+        epb.extend_from_slice(&1u32.to_le_bytes()); // second interface
+        epb.extend_from_slice(&[0; 8]);
+        epb.extend_from_slice(&(sll.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&(sll.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&sll);
+
+        let mut spb = Vec::new();
+        spb.extend_from_slice(&(packet.len() as u32 + 4).to_le_bytes());
+        spb.extend_from_slice(&[2, 0, 0, 0]);
+        spb.extend_from_slice(&packet);
+
+        let mut file = block(PCAPNG_SHB, &[0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        file.extend(block(PCAPNG_IDB, &[0, 0, 0, 0, 0, 0, 0, 0])); // NULL
+        file.extend(block(PCAPNG_IDB, &[113, 0, 0, 0, 0, 0, 0, 0])); // SLL
+        file.extend(block(5, &[0; 8])); // statistics, skipped
+        file.extend(block(PCAPNG_EPB, &epb));
+        file.extend(block(PCAPNG_SPB, &spb));
+
+        let mut reader = PcapReader::new(file).unwrap();
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!(frame.linktype, LINKTYPE_LINUX_SLL);
+        assert_eq!(ip_packet(&frame), Some(&packet[..]));
+
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!(frame.linktype, LINKTYPE_NULL);
+        assert_eq!(ip_packet(&frame), Some(&packet[..]));
+
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_ip_packet_ethernet() {
+        let packet = ipv4(0);
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&[0x81, 0x00, 0, 7, 0x08, 0x00]);
+        frame.extend_from_slice(&packet);
+        frame.resize(60, 0); // minimum frame padding
+        let frame = Frame { linktype: LINKTYPE_ETHERNET, data: &frame };
+        assert_eq!(ip_packet(&frame), Some(&packet[..]));
+
+        // ARP
+        let mut arp = vec![0u8; 42];
+        arp[12..14].copy_from_slice(&[0x08, 0x06]);
+        assert_eq!(ip_packet(&Frame { linktype: LINKTYPE_ETHERNET, data: &arp }), None);
+    }
+
+    #[test]
+    fn test_writer_roundtrip() {
+        let packets = [ipv4(0), ipv4(5)];
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        for packet in &packets {
+            writer.write_packet(packet).unwrap();
+        }
+
+        let mut reader = PcapReader::new(writer.out).unwrap();
+        for packet in &packets {
+            let frame = reader.next_frame().unwrap().unwrap();
+            assert_eq!(frame.linktype, LINKTYPE_RAW);
+            assert_eq!(frame.data, &packet[..]);
+        }
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert!(PcapReader::new(vec![1, 2]).is_err());
+        assert!(PcapReader::new(vec![0; 24]).is_err());
+
+        let mut file = PCAP_MAGIC_US.to_le_bytes().to_vec();
+        file.resize(24, 0);
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&100u32.to_le_bytes());
+        file.extend_from_slice(&100u32.to_le_bytes());
+        file.extend_from_slice(&[0; 10]);
+        let mut reader = PcapReader::new(file).unwrap();
+        assert!(reader.next_frame().is_err());
+    }
+}
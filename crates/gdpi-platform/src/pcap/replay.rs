@@ -0,0 +1,76 @@
+//! Capture file replay with recorded output
+
+use super::format::PcapWriter;
+use super::PcapCapture;
+use crate::error::Result;
+use crate::traits::{CapturedPacket, PacketAddress, PacketCapture};
+use ipnetwork::IpNetwork;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use tracing::info;
+
+/// Packet capture that reads a capture file and records what is sent
+///
+/// Packets come from a [`PcapCapture`]; everything passed to
+/// [`send`](PacketCapture::send) is appended, in order, to an output pcap
+/// of raw IP frames. Feeding the whole pipeline output back through it
+/// shows exactly what would have gone on the wire.
+pub struct PcapReplayCapture {
+    source: PcapCapture,
+    output: PcapWriter<Box<dyn Write + Send>>,
+    /// Packets written so far
+    sent: u64,
+}
+
+impl PcapReplayCapture {
+    /// Replay `input`, writing sent packets to a new pcap at `output`
+    pub fn open(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<Self> {
+        let output = output.as_ref();
+        let file = BufWriter::new(File::create(output)?);
+        info!(path = %output.display(), "Writing replay output");
+        Self::new(PcapCapture::open(input)?, file)
+    }
+
+    /// Replay `source`, writing sent packets as pcap to `output`
+    pub fn new(source: PcapCapture, output: impl Write + Send + 'static) -> Result<Self> {
+        Ok(Self {
+            source,
+            output: PcapWriter::new(Box::new(output) as Box<dyn Write + Send>)?,
+            sent: 0,
+        })
+    }
+
+    /// Networks whose hosts count as local (see [`PcapCapture::with_local_networks`])
+    pub fn with_local_networks(mut self, networks: Vec<IpNetwork>) -> Self {
+        self.source.local_networks = networks;
+        self
+    }
+
+    /// Packets written to the output so far
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+}
+
+impl PacketCapture for PcapReplayCapture {
+    fn recv(&mut self) -> Result<CapturedPacket> {
+        self.source.recv()
+    }
+
+    fn send(&mut self, packet: &[u8], _addr: &PacketAddress) -> Result<()> {
+        self.output.write_packet(packet)?;
+        self.sent += 1;
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.output.flush()
+    }
+}
+
+impl Drop for PcapReplayCapture {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
@@ -0,0 +1,93 @@
+//! Swapping a capture handle for one with another filter
+//!
+//! WinDivert can't change the filter of an open handle, so a new filter
+//! means a new handle. The order of the swap lives here, apart from the
+//! driver, so it is tested on every platform.
+
+use crate::error::Result;
+
+/// Put a handle opened with `filter` in place of `handle`
+///
+/// `filter` is validated and the new handle opened before the old one is
+/// dropped, so on error `handle` and `current_filter` are left as they
+/// were. `open` gets the old handle to carry its settings over.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn swap_handle<H>(
+    handle: &mut Option<H>,
+    current_filter: &mut String,
+    filter: &str,
+    validate: impl FnOnce(&str) -> Result<()>,
+    open: impl FnOnce(&str, Option<&H>) -> Result<H>,
+) -> Result<()> {
+    validate(filter)?;
+    let reopened = open(filter, handle.as_ref())?;
+    // Dropping the old handle closes it
+    *handle = Some(reopened);
+    *current_filter = filter.to_string();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::PlatformError;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Handle that logs when it is closed
+    struct Handle {
+        filter: String,
+        log: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            self.log.borrow_mut().push(format!("close {}", self.filter));
+        }
+    }
+
+    fn validate(filter: &str) -> Result<()> {
+        if filter.contains("DstPrt") {
+            return Err(PlatformError::InvalidFilter(filter.into()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_swap_handle() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let open = |filter: &str, old: Option<&Handle>| {
+            log.borrow_mut().push(format!("open {filter} after {}", old.unwrap().filter));
+            Ok(Handle {
+                filter: filter.into(),
+                log: Rc::clone(&log),
+            })
+        };
+        let mut current = String::from("tcp");
+        let mut handle = Some(Handle {
+            filter: current.clone(),
+            log: Rc::clone(&log),
+        });
+
+        // Rejected before anything is opened
+        let result = swap_handle(&mut handle, &mut current, "tcp.DstPrt == 443", validate, open);
+        assert!(matches!(result, Err(PlatformError::InvalidFilter(_))));
+        assert_eq!(current, "tcp");
+        assert!(log.borrow().is_empty());
+
+        // Failing to open keeps the old handle
+        let result = swap_handle(&mut handle, &mut current, "udp", validate, |_, _| {
+            Err(PlatformError::DriverInitFailed("busy".into()))
+        });
+        assert!(result.is_err());
+        assert_eq!(current, "tcp");
+        assert_eq!(handle.as_ref().unwrap().filter, "tcp");
+        assert!(log.borrow().is_empty());
+
+        // The old handle closes only once the new one is open
+        swap_handle(&mut handle, &mut current, "udp", validate, open).unwrap();
+        assert_eq!(current, "udp");
+        assert_eq!(handle.as_ref().unwrap().filter, "udp");
+        assert_eq!(*log.borrow(), ["open udp after tcp", "close tcp"]);
+    }
+}
@@ -2,8 +2,9 @@
 //!
 //! These traits define the interface that platform-specific implementations must follow.
 
+use bytes::Bytes;
 use gdpi_core::packet::{Direction, Packet};
-use crate::Result;
+use crate::{PlatformError, Result};
 
 /// Packet capture and injection interface
 ///
@@ -16,14 +17,42 @@ pub trait PacketCapture: Send {
 
     /// Receive a batch of packets
     ///
-    /// More efficient for high-throughput scenarios.
-    fn recv_batch(&mut self, max_count: usize) -> Result<Vec<CapturedPacket>>;
+    /// More efficient for high-throughput scenarios. The default
+    /// implementation calls [`recv`](Self::recv) up to `max_count` times,
+    /// stopping early on a capture error or the end of a capture file;
+    /// drivers with a native batch API should override it.
+    fn recv_batch(&mut self, max_count: usize) -> Result<Vec<CapturedPacket>> {
+        let mut packets = Vec::with_capacity(max_count);
+
+        for _ in 0..max_count {
+            match self.recv() {
+                Ok(pkt) => packets.push(pkt),
+                Err(PlatformError::CaptureError(_) | PlatformError::EndOfCapture)
+                    if !packets.is_empty() =>
+                {
+                    break
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(packets)
+    }
 
     /// Send/inject a packet
     fn send(&mut self, packet: &[u8], addr: &PacketAddress) -> Result<()>;
 
     /// Send multiple packets
-    fn send_batch(&mut self, packets: &[(Vec<u8>, PacketAddress)]) -> Result<()>;
+    ///
+    /// Returns the number of packets queued, which drivers with a native
+    /// batch API may report short of `packets.len()`. The default
+    /// implementation sends them one at a time.
+    fn send_batch(&mut self, packets: &[(Bytes, PacketAddress)]) -> Result<u32> {
+        for (data, addr) in packets {
+            self.send(data, addr)?;
+        }
+        Ok(packets.len() as u32)
+    }
 
     /// Close the capture handle
     fn close(&mut self) -> Result<()>;
@@ -90,6 +119,8 @@ pub struct PacketAddress {
     pub tcp_checksum: bool,
     /// UDP checksum valid
     pub udp_checksum: bool,
+    /// Driver packet id (NFQUEUE needs it to issue the verdict)
+    pub packet_id: u32,
 }
 
 impl PacketAddress {
@@ -127,6 +158,73 @@ impl PacketAddress {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::VecDeque;
+
+    /// Capture source that replays queued packets, then fails
+    #[derive(Default)]
+    struct MockCapture {
+        queue: VecDeque<CapturedPacket>,
+        sent: Vec<Vec<u8>>,
+    }
+
+    impl MockCapture {
+        fn with_packets(count: u8) -> Self {
+            let queue = (0..count)
+                .map(|i| CapturedPacket {
+                    data: vec![i],
+                    direction: Direction::Outbound,
+                    interface_index: 0,
+                    subinterface_index: 0,
+                    address: PacketAddress::outbound(),
+                })
+                .collect();
+            Self { queue, ..Default::default() }
+        }
+    }
+
+    impl PacketCapture for MockCapture {
+        fn recv(&mut self) -> Result<CapturedPacket> {
+            self.queue
+                .pop_front()
+                .ok_or_else(|| PlatformError::CaptureError("queue empty".into()))
+        }
+
+        fn send(&mut self, packet: &[u8], _addr: &PacketAddress) -> Result<()> {
+            self.sent.push(packet.to_vec());
+            Ok(())
+        }
+
+        fn close(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_recv_batch_fallback() {
+        let mut capture = MockCapture::with_packets(5);
+
+        let batch = capture.recv_batch(3).unwrap();
+        assert_eq!(batch.iter().map(|p| p.data[0]).collect::<Vec<_>>(), [0, 1, 2]);
+
+        // Fewer packets queued than requested: return what arrived
+        let batch = capture.recv_batch(8).unwrap();
+        assert_eq!(batch.iter().map(|p| p.data[0]).collect::<Vec<_>>(), [3, 4]);
+
+        // Nothing queued: the error surfaces
+        assert!(capture.recv_batch(8).is_err());
+    }
+
+    #[test]
+    fn test_send_batch_fallback() {
+        let mut capture = MockCapture::default();
+        let packets = vec![
+            (Bytes::from_static(&[1]), PacketAddress::outbound()),
+            (Bytes::from_static(&[2]), PacketAddress::inbound()),
+        ];
+
+        assert_eq!(capture.send_batch(&packets).unwrap(), 2);
+        assert_eq!(capture.sent, [vec![1], vec![2]]);
+    }
 
     #[test]
     fn test_packet_address_outbound() {
@@ -3,7 +3,11 @@
 //! Safe Rust wrapper around WinDivert using the `windivert` crate.
 
 use crate::error::{PlatformError, Result};
+use crate::reopen::swap_handle;
+use super::FilterExpr;
 use crate::traits::{CapturedPacket, PacketAddress, PacketCapture, PacketFilter};
+use bytes::Bytes;
+use std::time::Duration;
 use tracing::{debug, info, warn};
 
 #[cfg(windows)]
@@ -68,6 +72,34 @@ impl Flags {
     }
 }
 
+/// Driver tuning options
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriverOptions {
+    /// Packets received/sent per WinDivert call (1 disables batching)
+    pub batch_size: usize,
+}
+
+impl Default for DriverOptions {
+    fn default() -> Self {
+        Self { batch_size: 32 }
+    }
+}
+
+/// Packet queue limits of a WinDivert handle
+///
+/// WinDivert drops packets that wait longer than `max_time_ms` or don't
+/// fit in the queue. The driver keeps no count of queued or dropped
+/// packets, so these limits are all it can report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Most packets the queue holds
+    pub max_len: u64,
+    /// Most bytes the queue holds
+    pub max_size: u64,
+    /// Longest a packet may wait in the queue, in milliseconds
+    pub max_time_ms: u64,
+}
+
 /// WinDivert driver wrapper
 ///
 /// Provides safe access to WinDivert packet capture and injection.
@@ -99,8 +131,16 @@ pub struct WinDivertDriver {
     filter: String,
     /// Layer (stored for reference)
     _layer: Layer,
+    /// Priority the handle was opened with
+    #[cfg_attr(not(windows), allow(dead_code))]
+    priority: i16,
+    /// Flags the handle was opened with
+    #[cfg_attr(not(windows), allow(dead_code))]
+    flags: Flags,
     /// Buffer for receiving packets
     recv_buffer: Vec<u8>,
+    /// Tuning options
+    options: DriverOptions,
     /// Is handle valid
     is_open: bool,
 }
@@ -108,6 +148,10 @@ pub struct WinDivertDriver {
 // Safety: WinDivert handle can be sent between threads
 unsafe impl Send for WinDivertDriver {}
 
+// Safety: WinDivertRecv/WinDivertSend may be called concurrently on one
+// handle; only `recv_shared`/`send_shared` are reachable through `&self`
+unsafe impl Sync for WinDivertDriver {}
+
 impl WinDivertDriver {
     /// Maximum packet size
     pub const MAX_PACKET_SIZE: usize = 65535;
@@ -118,6 +162,12 @@ impl WinDivertDriver {
     /// Default queue time (ms)
     pub const DEFAULT_QUEUE_TIME: u32 = 1000;
 
+    /// Most packets WinDivert moves in one call (`WINDIVERT_BATCH_MAX`)
+    pub const MAX_BATCH_SIZE: usize = 0xFF;
+
+    /// Oldest driver version (major, minor) known to work
+    pub const MIN_DRIVER_VERSION: (u16, u16) = (2, 2);
+
     /// Open WinDivert with a filter
     ///
     /// # Arguments
@@ -147,88 +197,296 @@ impl WinDivertDriver {
 
         info!("WinDivert handle opened successfully");
 
-        Ok(Self {
+        let mut driver = Self {
             handle: Some(handle),
             filter: filter.to_string(),
             _layer: layer,
+            priority,
+            flags,
             recv_buffer: vec![0u8; Self::MAX_PACKET_SIZE],
+            options: DriverOptions::default(),
             is_open: true,
-        })
+        };
+        if let Err(e) = driver.driver_version().and_then(Self::check_driver_version) {
+            warn!("{}", e);
+        }
+        Ok(driver)
+    }
+
+    /// Version (major, minor) of the loaded WinDivert driver
+    #[cfg(windows)]
+    pub fn driver_version(&mut self) -> Result<(u16, u16)> {
+        let handle = self.handle.as_ref()
+            .ok_or_else(|| PlatformError::HandleError("No handle".into()))?;
+        let param = |param| {
+            handle.get_param(param)
+                .map(|v| v as u16)
+                .map_err(|e| PlatformError::HandleError(format!("WinDivertGetParam failed: {:?}", e)))
+        };
+
+        let version = (param(WinDivertParam::VersionMajor)?, param(WinDivertParam::VersionMinor)?);
+        debug!(major = version.0, minor = version.1, "WinDivert driver version");
+        Ok(version)
     }
 
     /// Stub implementation for non-Windows
     #[cfg(not(windows))]
-    pub fn open(filter: &str, _flags: Flags) -> Result<Self> {
-        warn!("WinDivert is only available on Windows");
-        Ok(Self {
-            _handle: None,
-            filter: filter.to_string(),
-            _layer: Layer::Network,
-            recv_buffer: vec![0u8; Self::MAX_PACKET_SIZE],
-            is_open: false,
-        })
+    pub fn driver_version(&mut self) -> Result<(u16, u16)> {
+        Err(PlatformError::HandleError("Not implemented on this platform".into()))
+    }
+
+    /// Check a driver version against [`Self::MIN_DRIVER_VERSION`]
+    pub fn check_driver_version(found: (u16, u16)) -> Result<()> {
+        if found < Self::MIN_DRIVER_VERSION {
+            return Err(PlatformError::DriverVersionMismatch {
+                required: Self::MIN_DRIVER_VERSION,
+                found,
+            });
+        }
+        Ok(())
+    }
+
+    /// Stub implementation for non-Windows
+    #[cfg(not(windows))]
+    pub fn open(filter: &str, flags: Flags) -> Result<Self> {
+        Self::open_ex(filter, Layer::Network, 0, flags)
     }
 
     /// Stub implementation for non-Windows
     #[cfg(not(windows))]
-    pub fn open_ex(filter: &str, layer: Layer, _priority: i16, _flags: Flags) -> Result<Self> {
+    pub fn open_ex(filter: &str, layer: Layer, priority: i16, flags: Flags) -> Result<Self> {
         warn!("WinDivert is only available on Windows");
         Ok(Self {
             _handle: None,
             filter: filter.to_string(),
             _layer: layer,
+            priority,
+            flags,
             recv_buffer: vec![0u8; Self::MAX_PACKET_SIZE],
+            options: DriverOptions::default(),
             is_open: false,
         })
     }
 
-    /// Set queue length
-    #[allow(unused_variables)]
+    /// Swap the filter by opening a new handle with the same priority and
+    /// flags
+    ///
+    /// WinDivert can't change the filter of an open handle. The new filter
+    /// is validated and its handle opened before the old one is closed, so
+    /// on error the driver keeps capturing with the old filter. Packets
+    /// still queued on the old handle are lost.
+    #[cfg(windows)]
+    pub fn reopen_with_filter(&mut self, filter: &str) -> Result<()> {
+        let (priority, flags) = (self.priority, self.flags);
+        swap_handle(
+            &mut self.handle,
+            &mut self.filter,
+            filter,
+            Self::validate_filter_internal,
+            |filter, old| {
+                let handle = WinDivert::network(filter, priority, flags.to_windivert_flags()).map_err(|e| {
+                    PlatformError::DriverInitFailed(format!("WinDivertOpen failed: {:?}", e))
+                })?;
+
+                // Keep any queue limits set on the old handle
+                if let Some(old) = old {
+                    for param in [WinDivertParam::QueueLength, WinDivertParam::QueueTime, WinDivertParam::QueueSize] {
+                        if let Err(e) = old.get_param(param).and_then(|value| handle.set_param(param, value)) {
+                            warn!("Failed to carry {:?} over to the new handle: {:?}", param, e);
+                        }
+                    }
+                }
+                Ok(handle)
+            },
+        )?;
+
+        info!(filter = filter, "Reopened WinDivert handle with a new filter");
+        self.is_open = true;
+        Ok(())
+    }
+
+    /// Stub implementation for non-Windows: validates and records the filter
+    #[cfg(not(windows))]
+    pub fn reopen_with_filter(&mut self, filter: &str) -> Result<()> {
+        swap_handle(
+            &mut self._handle,
+            &mut self.filter,
+            filter,
+            Self::validate_filter_internal,
+            |_, _| Ok(()),
+        )
+    }
+
+    /// Apply tuning options
+    ///
+    /// `batch_size` is clamped to `1..=MAX_BATCH_SIZE`.
+    pub fn with_options(mut self, options: DriverOptions) -> Self {
+        self.options = DriverOptions {
+            batch_size: options.batch_size.clamp(1, Self::MAX_BATCH_SIZE),
+        };
+        self
+    }
+
+    /// Packets received/sent per WinDivert call
+    pub fn batch_size(&self) -> usize {
+        self.options.batch_size
+    }
+
+    /// Receive buffer size needed for a full batch
+    pub fn batch_buffer_len(&self) -> usize {
+        self.options.batch_size * Self::MAX_PACKET_SIZE
+    }
+
+    /// Set queue length (packets)
+    #[cfg(windows)]
     pub fn set_queue_len(&mut self, queue_len: u32) -> Result<()> {
+        self.set_param(WinDivertParam::QueueLength, queue_len.into())?;
         debug!(queue_len, "Set queue length");
         Ok(())
     }
 
-    /// Set queue time
-    #[allow(unused_variables)]
+    /// Set queue time (ms)
+    #[cfg(windows)]
     pub fn set_queue_time(&mut self, queue_time: u32) -> Result<()> {
+        self.set_param(WinDivertParam::QueueTime, queue_time.into())?;
         debug!(queue_time, "Set queue time");
         Ok(())
     }
 
+    #[cfg(windows)]
+    fn set_param(&self, param: WinDivertParam, value: u64) -> Result<()> {
+        let handle = self.handle.as_ref()
+            .ok_or_else(|| PlatformError::HandleError("No handle".into()))?;
+        handle.set_param(param, value)
+            .map_err(|e| PlatformError::HandleError(format!("WinDivertSetParam failed: {:?}", e)))
+    }
+
+    /// Stub implementation for non-Windows
+    #[cfg(not(windows))]
+    pub fn set_queue_len(&mut self, queue_len: u32) -> Result<()> {
+        debug!(queue_len, "Set queue length");
+        Ok(())
+    }
+
+    /// Stub implementation for non-Windows
+    #[cfg(not(windows))]
+    pub fn set_queue_time(&mut self, queue_time: u32) -> Result<()> {
+        debug!(queue_time, "Set queue time");
+        Ok(())
+    }
+
+    /// Current queue limits of the handle
+    #[cfg(windows)]
+    pub fn queue_stats(&self) -> Result<QueueStats> {
+        let handle = self.handle.as_ref()
+            .ok_or_else(|| PlatformError::HandleError("No handle".into()))?;
+        let param = |param| {
+            handle.get_param(param)
+                .map_err(|e| PlatformError::HandleError(format!("WinDivertGetParam failed: {:?}", e)))
+        };
+
+        Ok(QueueStats {
+            max_len: param(WinDivertParam::QueueLength)?,
+            max_size: param(WinDivertParam::QueueSize)?,
+            max_time_ms: param(WinDivertParam::QueueTime)?,
+        })
+    }
+
+    /// Stub implementation for non-Windows
+    #[cfg(not(windows))]
+    pub fn queue_stats(&self) -> Result<QueueStats> {
+        Err(PlatformError::HandleError("Not implemented on this platform".into()))
+    }
+
     /// Internal filter validation
+    ///
+    /// Parses the filter for errors naming the offending token, then
+    /// compiles it with WinDivert's own parser to catch anything else
+    /// before the driver is opened.
+    #[cfg(windows)]
     fn validate_filter_internal(filter: &str) -> Result<()> {
-        // Basic validation
-        if filter.is_empty() {
-            return Err(PlatformError::InvalidFilter("Empty filter".into()));
+        use std::ffi::{CStr, CString};
+        use windivert_sys::{WinDivertHelperCompileFilter, WinDivertLayer};
+
+        FilterExpr::parse(filter)?;
+        let c_filter = CString::new(filter)
+            .map_err(|_| PlatformError::InvalidFilter("Filter contains a NUL byte".into()))?;
+
+        let mut error_str: *const std::ffi::c_char = std::ptr::null();
+        let mut error_pos: u32 = 0;
+        // Without an output buffer the filter is only checked
+        let compiled = unsafe {
+            WinDivertHelperCompileFilter(
+                c_filter.as_ptr(),
+                WinDivertLayer::Network,
+                std::ptr::null_mut(),
+                0,
+                &mut error_str,
+                &mut error_pos,
+            )
+        };
+        if compiled != 0 {
+            return Ok(());
         }
 
-        // Check for basic syntax
-        let keywords = [
-            "inbound", "outbound", "ip", "ipv6", "icmp", "icmpv6",
-            "tcp", "udp", "loopback", "impostor", "fragment",
-            "true", "false", "and", "or", "not",
-        ];
+        // WinDivert's error strings are static
+        let message = if error_str.is_null() {
+            "invalid filter".into()
+        } else {
+            unsafe { CStr::from_ptr(error_str) }.to_string_lossy()
+        };
+        Err(PlatformError::InvalidFilter(format!(
+            "Syntax error at column {}: {message}",
+            error_pos + 1
+        )))
+    }
 
-        let lower = filter.to_lowercase();
-        let has_valid_keyword = keywords.iter().any(|k| lower.contains(k)) 
-            || lower.contains("==") 
-            || lower.contains("!=")
-            || lower == "true";
+    /// Internal filter validation (parser only, without WinDivert)
+    #[cfg(not(windows))]
+    fn validate_filter_internal(filter: &str) -> Result<()> {
+        FilterExpr::parse(filter).map(|_| ())
+    }
+}
 
-        if !has_valid_keyword {
-            warn!(filter, "Filter may be invalid");
+impl WinDivertDriver {
+    /// Receive a packet into a caller-provided buffer
+    ///
+    /// Unlike [`PacketCapture::recv`] this only needs `&self`, so one
+    /// handle can be shared by several threads.
+    #[cfg(windows)]
+    pub fn recv_shared(&self, buffer: &mut [u8]) -> Result<CapturedPacket> {
+        if !self.is_open {
+            return Err(PlatformError::HandleError("Handle not open".into()));
         }
 
-        Ok(())
+        let handle = self.handle.as_ref()
+            .ok_or_else(|| PlatformError::HandleError("No handle".into()))?;
+
+        // Receive packet using the new API
+        let packet = handle.recv(buffer)
+            .map_err(|e| PlatformError::CaptureError(format!("Recv failed: {:?}", e)))?;
+
+        Ok(Self::captured(&packet))
     }
-}
 
-impl PacketCapture for WinDivertDriver {
+    #[cfg(not(windows))]
+    pub fn recv_shared(&self, _buffer: &mut [u8]) -> Result<CapturedPacket> {
+        Err(PlatformError::CaptureError("Not implemented on this platform".into()))
+    }
+
+    /// Receive up to `max_count` packets with a single `WinDivertRecvEx`
+    ///
+    /// `buffer` should hold [`Self::batch_buffer_len`] bytes; WinDivert
+    /// returns fewer packets when it fills up. Blocks until at least one
+    /// packet is available. Falls back to [`Self::recv_shared`] when
+    /// batching is disabled.
     #[cfg(windows)]
-    fn recv(&mut self) -> Result<CapturedPacket> {
-        use gdpi_core::packet::Direction;
-        
+    pub fn recv_batch_shared(&self, buffer: &mut [u8], max_count: usize) -> Result<Vec<CapturedPacket>> {
+        let count = max_count.min(self.options.batch_size);
+        if count <= 1 {
+            return self.recv_shared(buffer).map(|packet| vec![packet]);
+        }
+
         if !self.is_open {
             return Err(PlatformError::HandleError("Handle not open".into()));
         }
@@ -236,10 +494,94 @@ impl PacketCapture for WinDivertDriver {
         let handle = self.handle.as_ref()
             .ok_or_else(|| PlatformError::HandleError("No handle".into()))?;
 
-        // Receive packet using the new API
-        let packet = handle.recv(&mut self.recv_buffer)
+        let packets = handle.recv_ex(buffer, count as u8)
+            .map_err(|e| PlatformError::CaptureError(format!("RecvEx failed: {:?}", e)))?;
+
+        Ok(packets.iter().map(Self::captured).collect())
+    }
+
+    #[cfg(not(windows))]
+    pub fn recv_batch_shared(&self, _buffer: &mut [u8], _max_count: usize) -> Result<Vec<CapturedPacket>> {
+        Err(PlatformError::CaptureError("Not implemented on this platform".into()))
+    }
+
+    /// Like [`Self::recv_shared`], but gives up after `timeout`
+    ///
+    /// The read is overlapped and cancelled once the deadline passes, so a
+    /// loop polling this can notice shutdown while no traffic arrives.
+    /// Returns `Ok(None)` on timeout.
+    #[cfg(windows)]
+    pub fn recv_timeout_shared(&self, buffer: &mut [u8], timeout: Duration) -> Result<Option<CapturedPacket>> {
+        if !self.is_open {
+            return Err(PlatformError::HandleError("Handle not open".into()));
+        }
+
+        let handle = self.handle.as_ref()
+            .ok_or_else(|| PlatformError::HandleError("No handle".into()))?;
+
+        let packet = handle.recv_wait(buffer, wait_millis(timeout))
             .map_err(|e| PlatformError::CaptureError(format!("Recv failed: {:?}", e)))?;
 
+        Ok(packet.as_ref().map(Self::captured))
+    }
+
+    #[cfg(not(windows))]
+    pub fn recv_timeout_shared(&self, _buffer: &mut [u8], _timeout: Duration) -> Result<Option<CapturedPacket>> {
+        Err(PlatformError::CaptureError("Not implemented on this platform".into()))
+    }
+
+    /// Like [`Self::recv_batch_shared`], but returns an empty batch once
+    /// `timeout` passes without traffic
+    #[cfg(windows)]
+    pub fn recv_batch_timeout_shared(
+        &self,
+        buffer: &mut [u8],
+        max_count: usize,
+        timeout: Duration,
+    ) -> Result<Vec<CapturedPacket>> {
+        let count = max_count.min(self.options.batch_size);
+        if count <= 1 {
+            return self
+                .recv_timeout_shared(buffer, timeout)
+                .map(|packet| packet.into_iter().collect());
+        }
+
+        if !self.is_open {
+            return Err(PlatformError::HandleError("Handle not open".into()));
+        }
+
+        let handle = self.handle.as_ref()
+            .ok_or_else(|| PlatformError::HandleError("No handle".into()))?;
+
+        let packets = handle.recv_wait_ex(buffer, count as u8, wait_millis(timeout))
+            .map_err(|e| PlatformError::CaptureError(format!("RecvEx failed: {:?}", e)))?;
+
+        Ok(packets.iter().map(Self::captured).collect())
+    }
+
+    #[cfg(not(windows))]
+    pub fn recv_batch_timeout_shared(
+        &self,
+        _buffer: &mut [u8],
+        _max_count: usize,
+        _timeout: Duration,
+    ) -> Result<Vec<CapturedPacket>> {
+        Err(PlatformError::CaptureError("Not implemented on this platform".into()))
+    }
+
+    /// Receive a packet, or `Ok(None)` if none arrives within `timeout`
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<CapturedPacket>> {
+        let mut buffer = std::mem::take(&mut self.recv_buffer);
+        let result = self.recv_timeout_shared(&mut buffer, timeout);
+        self.recv_buffer = buffer;
+        result
+    }
+
+    /// Convert a received WinDivert packet
+    #[cfg(windows)]
+    fn captured(packet: &WinDivertPacket<'_, windivert::layer::NetworkLayer>) -> CapturedPacket {
+        use gdpi_core::packet::Direction;
+
         // Extract address info from the packet
         let wd_addr = &packet.address;
         
@@ -253,6 +595,7 @@ impl PacketCapture for WinDivertDriver {
             ip_checksum: wd_addr.ip_checksum(),
             tcp_checksum: wd_addr.tcp_checksum(),
             udp_checksum: wd_addr.udp_checksum(),
+            ..Default::default()
         };
         
         let direction = if wd_addr.outbound() { 
@@ -261,39 +604,52 @@ impl PacketCapture for WinDivertDriver {
             Direction::Inbound 
         };
 
-        Ok(CapturedPacket {
+        CapturedPacket {
             data: packet.data.to_vec(),
             direction,
             interface_index: wd_addr.interface_index(),
             subinterface_index: wd_addr.subinterface_index(),
             address: addr,
-        })
+        }
+    }
+
+    /// Inject a packet; like [`Self::recv_shared`] this only needs `&self`
+    #[cfg(windows)]
+    pub fn send_shared(&self, packet: &[u8], addr: &PacketAddress) -> Result<()> {
+        if !self.is_open {
+            return Err(PlatformError::HandleError("Handle not open".into()));
+        }
+
+        let handle = self.handle.as_ref()
+            .ok_or_else(|| PlatformError::HandleError("No handle".into()))?;
+
+        handle.send(&Self::outgoing(packet, addr))
+            .map_err(|e| PlatformError::InjectionError(format!("Send failed: {:?}", e)))?;
+
+        Ok(())
     }
 
     #[cfg(not(windows))]
-    fn recv(&mut self) -> Result<CapturedPacket> {
-        Err(PlatformError::CaptureError("Not implemented on this platform".into()))
+    pub fn send_shared(&self, packet: &[u8], _addr: &PacketAddress) -> Result<()> {
+        debug!(len = packet.len(), "Would send packet (not Windows)");
+        Ok(())
     }
 
-    fn recv_batch(&mut self, max_count: usize) -> Result<Vec<CapturedPacket>> {
-        let mut packets = Vec::with_capacity(max_count);
-        
-        for _ in 0..max_count {
-            match self.recv() {
-                Ok(pkt) => packets.push(pkt),
-                Err(PlatformError::CaptureError(_)) => break,
-                Err(e) => return Err(e),
+    /// Inject several packets, batching runs of the same direction
+    ///
+    /// Consecutive packets heading the same way go out in one
+    /// `WinDivertSendEx` call (at most `batch_size` per call); send order
+    /// is preserved. Returns the number of packets queued, which is short
+    /// of `packets.len()` only if the driver took part of a batch.
+    #[cfg(windows)]
+    pub fn send_batch_shared(&self, packets: &[(Bytes, PacketAddress)]) -> Result<u32> {
+        if self.options.batch_size <= 1 {
+            for (data, addr) in packets {
+                self.send_shared(data, addr)?;
             }
+            return Ok(packets.len() as u32);
         }
 
-        Ok(packets)
-    }
-
-    #[cfg(windows)]
-    fn send(&mut self, packet: &[u8], addr: &PacketAddress) -> Result<()> {
-        use windivert::layer::NetworkLayer;
-        use windivert_sys::ChecksumFlags;
-        
         if !self.is_open {
             return Err(PlatformError::HandleError("Handle not open".into()));
         }
@@ -301,6 +657,46 @@ impl PacketCapture for WinDivertDriver {
         let handle = self.handle.as_ref()
             .ok_or_else(|| PlatformError::HandleError("No handle".into()))?;
 
+        let mut queued = 0;
+        for run in direction_runs(packets) {
+            for chunk in run.chunks(self.options.batch_size) {
+                if let [(data, addr)] = chunk {
+                    self.send_shared(data, addr)?;
+                    queued += 1;
+                    continue;
+                }
+
+                let batch: Vec<_> = chunk.iter()
+                    .map(|(data, addr)| Self::outgoing(data, addr))
+                    .collect();
+                let sent = handle.send_ex(&batch)
+                    .map_err(|e| PlatformError::InjectionError(format!("SendEx failed: {:?}", e)))?;
+
+                let lens = chunk.iter().map(|(data, _)| data.len());
+                let whole = packets_within(lens, sent as usize);
+                queued += whole;
+                if whole < chunk.len() as u32 {
+                    // Later packets would overtake the ones dropped here
+                    return Ok(queued);
+                }
+            }
+        }
+
+        Ok(queued)
+    }
+
+    #[cfg(not(windows))]
+    pub fn send_batch_shared(&self, packets: &[(Bytes, PacketAddress)]) -> Result<u32> {
+        debug!(count = packets.len(), "Would send packets (not Windows)");
+        Ok(packets.len() as u32)
+    }
+
+    /// Build a WinDivert packet for injection
+    #[cfg(windows)]
+    fn outgoing(packet: &[u8], addr: &PacketAddress) -> WinDivertPacket<'static, windivert::layer::NetworkLayer> {
+        use windivert::layer::NetworkLayer;
+        use windivert_sys::ChecksumFlags;
+
         // Create WinDivert address
         // SAFETY: We're filling in all the fields before sending
         let mut wd_addr = unsafe { WinDivertAddress::<NetworkLayer>::new() };
@@ -328,23 +724,68 @@ impl PacketCapture for WinDivertDriver {
             // Continue anyway - might still work
         }
 
-        handle.send(&wd_packet)
-            .map_err(|e| PlatformError::InjectionError(format!("Send failed: {:?}", e)))?;
+        wd_packet
+    }
+}
 
-        Ok(())
+/// Runs of consecutive packets heading the same way
+///
+/// `slice::chunk_by` would do, but needs a newer Rust than the MSRV.
+fn direction_runs<T>(packets: &[(T, PacketAddress)]) -> impl Iterator<Item = &[(T, PacketAddress)]> {
+    let mut rest = packets;
+    std::iter::from_fn(move || {
+        let ((_, first), _) = rest.split_first()?;
+        let len = rest
+            .iter()
+            .take_while(|(_, addr)| addr.outbound == first.outbound)
+            .count();
+        let (run, tail) = rest.split_at(len);
+        rest = tail;
+        Some(run)
+    })
+}
+
+/// How many of the packets with these lengths fit whole in `bytes`
+fn packets_within(lens: impl IntoIterator<Item = usize>, bytes: usize) -> u32 {
+    let mut total = 0;
+    let mut count = 0;
+    for len in lens {
+        total += len;
+        if total > bytes {
+            break;
+        }
+        count += 1;
     }
+    count
+}
 
-    #[cfg(not(windows))]
-    fn send(&mut self, packet: &[u8], _addr: &PacketAddress) -> Result<()> {
-        debug!(len = packet.len(), "Would send packet (not Windows)");
-        Ok(())
+/// `timeout` in whole milliseconds for a wait, short of `INFINITE`
+fn wait_millis(timeout: Duration) -> u32 {
+    u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX).min(u32::MAX - 1)
+}
+
+impl PacketCapture for WinDivertDriver {
+    fn recv(&mut self) -> Result<CapturedPacket> {
+        let mut buffer = std::mem::take(&mut self.recv_buffer);
+        let result = self.recv_shared(&mut buffer);
+        self.recv_buffer = buffer;
+        result
     }
 
-    fn send_batch(&mut self, packets: &[(Vec<u8>, PacketAddress)]) -> Result<()> {
-        for (data, addr) in packets {
-            self.send(data, addr)?;
-        }
-        Ok(())
+    fn recv_batch(&mut self, max_count: usize) -> Result<Vec<CapturedPacket>> {
+        let mut buffer = std::mem::take(&mut self.recv_buffer);
+        buffer.resize(buffer.len().max(self.batch_buffer_len()), 0);
+        let result = self.recv_batch_shared(&mut buffer, max_count);
+        self.recv_buffer = buffer;
+        result
+    }
+
+    fn send(&mut self, packet: &[u8], addr: &PacketAddress) -> Result<()> {
+        self.send_shared(packet, addr)
+    }
+
+    fn send_batch(&mut self, packets: &[(Bytes, PacketAddress)]) -> Result<u32> {
+        self.send_batch_shared(packets)
     }
 
     fn close(&mut self) -> Result<()> {
@@ -361,12 +802,8 @@ impl PacketCapture for WinDivertDriver {
 }
 
 impl PacketFilter for WinDivertDriver {
-    #[allow(unused_variables)]
     fn set_filter(&mut self, filter: &str) -> Result<()> {
-        // WinDivert doesn't support changing filter after open
-        Err(PlatformError::InvalidFilter(
-            "Cannot change filter after open - close and reopen".into()
-        ))
+        self.reopen_with_filter(filter)
     }
 
     fn get_filter(&self) -> &str {
@@ -405,6 +842,51 @@ mod tests {
         assert_eq!(value, 0x0001 | 0x0020);
     }
 
+    #[test]
+    fn test_direction_runs() {
+        let packets: Vec<_> = [true, true, false, true, false, false]
+            .into_iter()
+            .enumerate()
+            .map(|(i, outbound)| (i, PacketAddress { outbound, ..PacketAddress::default() }))
+            .collect();
+        let runs: Vec<Vec<usize>> = direction_runs(&packets)
+            .map(|run| run.iter().map(|(i, _)| *i).collect())
+            .collect();
+        assert_eq!(runs, [vec![0, 1], vec![2], vec![3], vec![4, 5]]);
+        assert_eq!(direction_runs::<usize>(&[]).count(), 0);
+    }
+
+    #[test]
+    fn test_packets_within() {
+        assert_eq!(packets_within([40, 60, 100], 200), 3);
+        assert_eq!(packets_within([40, 60, 100], 199), 2);
+        assert_eq!(packets_within([40, 60, 100], 100), 2);
+        assert_eq!(packets_within([40, 60, 100], 39), 0);
+    }
+
+    #[test]
+    fn test_wait_millis() {
+        assert_eq!(wait_millis(Duration::ZERO), 0);
+        assert_eq!(wait_millis(Duration::from_micros(1500)), 1);
+        assert_eq!(wait_millis(Duration::from_secs(2)), 2000);
+        // u32::MAX would wait forever
+        assert_eq!(wait_millis(Duration::MAX), u32::MAX - 1);
+    }
+
+    #[test]
+    fn test_check_driver_version() {
+        assert!(WinDivertDriver::check_driver_version((2, 2)).is_ok());
+        assert!(WinDivertDriver::check_driver_version((3, 0)).is_ok());
+
+        match WinDivertDriver::check_driver_version((2, 1)) {
+            Err(PlatformError::DriverVersionMismatch { required, found }) => {
+                assert_eq!(required, (2, 2));
+                assert_eq!(found, (2, 1));
+            }
+            other => panic!("expected a version mismatch, got {other:?}"),
+        }
+    }
+
     #[test]
     fn test_validate_filter() {
         // Valid filters
@@ -416,4 +898,29 @@ mod tests {
         // Invalid filters
         assert!(WinDivertDriver::validate_filter("").is_err());
     }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_validate_filter_syntax_errors() {
+        let bad = [
+            "tcp.DstPort ==",
+            "outbound and and tcp",
+            "(tcp or udp",
+            "tcp.NoSuchField == 1",
+            "tcp.DstPort == 443 xor udp",
+        ];
+        for filter in bad {
+            match WinDivertDriver::validate_filter(filter) {
+                Err(PlatformError::InvalidFilter(msg)) => {
+                    assert!(msg.starts_with("Syntax error at column"), "{filter}: {msg}");
+                }
+                other => panic!("{filter}: expected a syntax error, got {other:?}"),
+            }
+        }
+
+        // The presets compile
+        assert!(WinDivertDriver::validate_filter(
+            &crate::windows::FilterPresets::goodbyedpi(true, &[8443], 1200)
+        ).is_ok());
+    }
 }
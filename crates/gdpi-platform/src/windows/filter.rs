@@ -2,6 +2,13 @@
 //!
 //! Type-safe builder for WinDivert filter expressions.
 
+mod expr;
+
+pub use expr::{CompareOp, Field, FilterExpr, Value};
+
+use crate::error::Result;
+use ipnetwork::Ipv4Network;
+use std::net::Ipv4Addr;
 
 /// Filter builder for WinDivert
 ///
@@ -126,12 +133,42 @@ impl FilterBuilder {
         self
     }
 
+    /// Add destination IP range condition (IPv4, inclusive)
+    ///
+    /// Adds two conditions joined by "and"; group them before an "or".
+    pub fn dst_addr_range(mut self, min: Ipv4Addr, max: Ipv4Addr) -> Self {
+        self.parts.push(FilterPart::Condition(format!("ip.DstAddr >= {}", min)));
+        self.parts.push(FilterPart::Condition(format!("ip.DstAddr <= {}", max)));
+        self
+    }
+
+    /// Add destination subnet condition (IPv4)
+    pub fn dst_subnet(self, subnet: Ipv4Network) -> Self {
+        let (min, max) = subnet_range(subnet);
+        self.dst_addr_range(min, max)
+    }
+
     /// Add source IP condition (IPv4)
     pub fn src_addr(mut self, ip: &str) -> Self {
         self.parts.push(FilterPart::Condition(format!("ip.SrcAddr == {}", ip)));
         self
     }
 
+    /// Add source IP range condition (IPv4, inclusive)
+    ///
+    /// Adds two conditions joined by "and"; group them before an "or".
+    pub fn src_addr_range(mut self, min: Ipv4Addr, max: Ipv4Addr) -> Self {
+        self.parts.push(FilterPart::Condition(format!("ip.SrcAddr >= {}", min)));
+        self.parts.push(FilterPart::Condition(format!("ip.SrcAddr <= {}", max)));
+        self
+    }
+
+    /// Add source subnet condition (IPv4)
+    pub fn src_subnet(self, subnet: Ipv4Network) -> Self {
+        let (min, max) = subnet_range(subnet);
+        self.src_addr_range(min, max)
+    }
+
     /// Add TCP flags condition (SYN)
     pub fn tcp_syn(mut self) -> Self {
         self.parts.push(FilterPart::Condition("tcp.Syn".into()));
@@ -188,6 +225,15 @@ impl FilterBuilder {
         self
     }
 
+    /// Add the negation of another filter, as "not (...)"
+    pub fn not_group(mut self, inner: FilterBuilder) -> Self {
+        self.parts.push(FilterPart::Not);
+        self.parts.push(FilterPart::GroupStart);
+        self.parts.extend(inner.parts);
+        self.parts.push(FilterPart::GroupEnd);
+        self
+    }
+
     /// Start a group (open parenthesis)
     pub fn group_start(mut self) -> Self {
         self.parts.push(FilterPart::GroupStart);
@@ -206,6 +252,11 @@ impl FilterBuilder {
         self
     }
 
+    /// Build and parse the filter, checking it is well formed
+    pub fn build_expr(self) -> Result<FilterExpr> {
+        FilterExpr::parse(&self.build())
+    }
+
     /// Build the filter string
     pub fn build(self) -> String {
         let mut result = String::new();
@@ -266,6 +317,11 @@ impl Default for FilterBuilder {
     }
 }
 
+/// First and last address of an IPv4 subnet
+pub fn subnet_range(subnet: Ipv4Network) -> (Ipv4Addr, Ipv4Addr) {
+    (subnet.network(), subnet.broadcast())
+}
+
 /// Common filter presets for GoodbyeDPI
 pub struct FilterPresets;
 
@@ -302,6 +358,50 @@ impl FilterPresets {
             .build()
     }
 
+    /// Filter for outbound TCP to 80, 443 and each of `additional_ports`
+    ///
+    /// A non-zero `max_payload` leaves larger segments in the kernel.
+    pub fn tcp_outbound(additional_ports: &[u16], max_payload: u16) -> String {
+        let mut filter = FilterBuilder::new()
+            .outbound()
+            .tcp()
+            .group_start()
+            .dst_port(80)
+            .or()
+            .dst_port(443);
+
+        let mut seen = vec![80, 443];
+        for &port in additional_ports {
+            if !seen.contains(&port) {
+                seen.push(port);
+                filter = filter.or().dst_port(port);
+            }
+        }
+        filter = filter.group_end();
+
+        if max_payload > 0 {
+            filter = filter.tcp_payload_size("<=", max_payload.into());
+        }
+        filter.build()
+    }
+
+    /// Filter for outbound TCP segments carrying data to any port, for
+    /// finding HTTP requests off port 80
+    ///
+    /// A non-zero `max_payload` leaves larger segments in the kernel.
+    pub fn tcp_data_outbound(max_payload: u16) -> String {
+        let mut filter = FilterBuilder::new()
+            .group_start()
+            .outbound()
+            .tcp()
+            .tcp_payload_size(">", 0);
+
+        if max_payload > 0 {
+            filter = filter.tcp_payload_size("<=", max_payload.into());
+        }
+        filter.group_end().build()
+    }
+
     /// Filter for DNS (port 53) UDP packets
     pub fn dns_outbound() -> String {
         FilterBuilder::new()
@@ -311,6 +411,21 @@ impl FilterPresets {
             .build()
     }
 
+    /// Filter for redirected DNS: outbound queries and upstream responses
+    /// over UDP and TCP
+    pub fn dns_redirect(upstream_ports: &[u16]) -> String {
+        let responses = upstream_ports
+            .iter()
+            .map(|port| format!("udp.SrcPort == {0} or tcp.SrcPort == {0}", port))
+            .collect::<Vec<_>>()
+            .join(" or ");
+
+        format!(
+            "(outbound and (udp.DstPort == 53 or tcp.DstPort == 53)) or (inbound and ({}))",
+            responses
+        )
+    }
+
     /// Filter for QUIC (UDP 443) packets
     pub fn quic_outbound() -> String {
         FilterBuilder::new()
@@ -320,6 +435,18 @@ impl FilterPresets {
             .build()
     }
 
+    /// Filter for inbound TCP resets, for dropping those forged by a
+    /// passive DPI
+    pub fn rst_inbound() -> String {
+        FilterBuilder::new()
+            .group_start()
+            .inbound()
+            .tcp()
+            .tcp_rst()
+            .group_end()
+            .build()
+    }
+
     /// Filter for incoming SYN-ACK packets
     pub fn syn_ack_inbound() -> String {
         FilterBuilder::new()
@@ -330,22 +457,89 @@ impl FilterPresets {
             .build()
     }
 
+    /// `filter` minus traffic to or from any of `subnets` (e.g. a VPN's)
+    ///
+    /// Both addresses are checked so inbound handles skip the subnet too.
+    pub fn exclude_subnets(filter: &str, subnets: &[Ipv4Network]) -> String {
+        if subnets.is_empty() {
+            return filter.to_string();
+        }
+
+        let mut builder = FilterBuilder::new().group_start().raw(filter).group_end();
+        for &subnet in subnets {
+            builder = builder
+                .not_group(FilterBuilder::new().dst_subnet(subnet))
+                .not_group(FilterBuilder::new().src_subnet(subnet));
+        }
+        builder.build()
+    }
+
     /// Combined filter for GoodbyeDPI (HTTP + HTTPS)
     pub fn goodbyedpi_basic() -> String {
         "outbound and tcp and (tcp.DstPort == 80 or tcp.DstPort == 443)".into()
     }
 
-    /// Full filter for GoodbyeDPI (HTTP + HTTPS + SYN-ACK) - DNS excluded for stability
+    /// Full filter for GoodbyeDPI (HTTP + HTTPS + DNS over TCP + SYN-ACK) - UDP DNS excluded for stability
     pub fn goodbyedpi_full() -> String {
-        "(outbound and tcp and (tcp.DstPort == 80 or tcp.DstPort == 443)) or \
-         (inbound and tcp and tcp.Syn and tcp.Ack)".into()
+        Self::goodbyedpi(false, &[], 0)
     }
 
-    /// Turkey-optimized filter (includes QUIC blocking, DNS excluded for stability)
+    /// Turkey-optimized filter (includes QUIC blocking and DNS over TCP, UDP DNS excluded for stability)
     pub fn turkey_optimized() -> String {
-        "(outbound and tcp and (tcp.DstPort == 80 or tcp.DstPort == 443)) or \
-         (outbound and udp and udp.DstPort == 443) or \
-         (inbound and tcp and tcp.Syn and tcp.Ack)".into()
+        Self::goodbyedpi(true, &[], 0)
+    }
+
+    /// GoodbyeDPI filter with extra outbound TCP ports (e.g. an HTTP proxy)
+    ///
+    /// Captures TCP to 80, 443, each of `additional_ports` and 53, inbound
+    /// SYN-ACKs, and QUIC when `block_quic` is set. A non-zero
+    /// `max_payload` leaves larger HTTP(S) segments in the kernel.
+    pub fn goodbyedpi(block_quic: bool, additional_ports: &[u16], max_payload: u16) -> String {
+        let mut filter = FilterBuilder::new()
+            .group_start()
+            .outbound()
+            .tcp()
+            .group_start();
+
+        if max_payload > 0 {
+            filter = filter.group_start().group_start();
+        }
+        filter = filter.dst_port(80).or().dst_port(443);
+
+        let mut seen = vec![80, 443, 53];
+        for &port in additional_ports {
+            if !seen.contains(&port) {
+                seen.push(port);
+                filter = filter.or().dst_port(port);
+            }
+        }
+        if max_payload > 0 {
+            filter = filter
+                .group_end()
+                .tcp_payload_size("<=", max_payload.into())
+                .group_end();
+        }
+        filter = filter.or().dst_port(53).group_end().group_end();
+
+        if block_quic {
+            filter = filter
+                .or()
+                .group_start()
+                .outbound()
+                .udp()
+                .udp_dst_port(443)
+                .group_end();
+        }
+
+        filter
+            .or()
+            .group_start()
+            .inbound()
+            .tcp()
+            .tcp_syn()
+            .tcp_ack()
+            .group_end()
+            .build()
     }
 }
 
@@ -400,4 +594,143 @@ mod tests {
         let dns = FilterPresets::dns_outbound();
         assert!(dns.contains("udp.DstPort == 53"));
     }
+
+    #[test]
+    fn test_tcp_outbound_preset() {
+        assert_eq!(
+            FilterPresets::tcp_outbound(&[], 0),
+            "outbound and tcp and (tcp.DstPort == 80 or tcp.DstPort == 443)"
+        );
+        assert_eq!(
+            FilterPresets::tcp_outbound(&[8080, 443, 8080], 1200),
+            "outbound and tcp and (tcp.DstPort == 80 or tcp.DstPort == 443 or tcp.DstPort == 8080) and \
+             tcp.PayloadLength <= 1200"
+        );
+    }
+
+    #[test]
+    fn test_tcp_data_outbound_preset() {
+        assert_eq!(
+            FilterPresets::tcp_data_outbound(0),
+            "(outbound and tcp and tcp.PayloadLength > 0)"
+        );
+        assert_eq!(
+            FilterPresets::tcp_data_outbound(1200),
+            "(outbound and tcp and tcp.PayloadLength > 0 and tcp.PayloadLength <= 1200)"
+        );
+    }
+
+    #[test]
+    fn test_dns_redirect_preset() {
+        let filter = FilterPresets::dns_redirect(&[53, 1253]);
+        assert_eq!(
+            filter,
+            "(outbound and (udp.DstPort == 53 or tcp.DstPort == 53)) or \
+             (inbound and (udp.SrcPort == 53 or tcp.SrcPort == 53 or \
+             udp.SrcPort == 1253 or tcp.SrcPort == 1253))"
+        );
+    }
+
+    #[test]
+    fn test_goodbyedpi_filter() {
+        assert_eq!(
+            FilterPresets::turkey_optimized(),
+            "(outbound and tcp and (tcp.DstPort == 80 or tcp.DstPort == 443 or tcp.DstPort == 53)) or \
+             (outbound and udp and udp.DstPort == 443) or \
+             (inbound and tcp and tcp.Syn and tcp.Ack)"
+        );
+        assert_eq!(
+            FilterPresets::goodbyedpi(false, &[8080, 443, 8443, 8080], 0),
+            "(outbound and tcp and (tcp.DstPort == 80 or tcp.DstPort == 443 or \
+             tcp.DstPort == 8080 or tcp.DstPort == 8443 or tcp.DstPort == 53)) or \
+             (inbound and tcp and tcp.Syn and tcp.Ack)"
+        );
+        assert_eq!(
+            FilterPresets::goodbyedpi(true, &[], 1200),
+            "(outbound and tcp and (((tcp.DstPort == 80 or tcp.DstPort == 443) and \
+             tcp.PayloadLength <= 1200) or tcp.DstPort == 53)) or \
+             (outbound and udp and udp.DstPort == 443) or \
+             (inbound and tcp and tcp.Syn and tcp.Ack)"
+        );
+    }
+
+    #[test]
+    fn test_subnet_range() {
+        let range = |cidr: &str| subnet_range(cidr.parse().unwrap());
+
+        assert_eq!(range("10.8.0.0/24"), (Ipv4Addr::new(10, 8, 0, 0), Ipv4Addr::new(10, 8, 0, 255)));
+        assert_eq!(range("172.16.5.9/12"), (Ipv4Addr::new(172, 16, 0, 0), Ipv4Addr::new(172, 31, 255, 255)));
+        assert_eq!(range("1.2.3.4/32"), (Ipv4Addr::new(1, 2, 3, 4), Ipv4Addr::new(1, 2, 3, 4)));
+        assert_eq!(range("0.0.0.0/0"), (Ipv4Addr::UNSPECIFIED, Ipv4Addr::BROADCAST));
+    }
+
+    #[test]
+    fn test_not_group() {
+        let filter = FilterBuilder::new()
+            .outbound()
+            .tcp()
+            .not_group(FilterBuilder::new().dst_addr_range(
+                Ipv4Addr::new(10, 0, 0, 0),
+                Ipv4Addr::new(10, 255, 255, 255),
+            ))
+            .build();
+
+        assert_eq!(
+            filter,
+            "outbound and tcp and not (ip.DstAddr >= 10.0.0.0 and ip.DstAddr <= 10.255.255.255)"
+        );
+    }
+
+    #[test]
+    fn test_exclude_subnets() {
+        let base = "outbound and tcp";
+        assert_eq!(FilterPresets::exclude_subnets(base, &[]), base);
+
+        let subnets = ["10.8.0.0/24".parse().unwrap(), "192.168.100.0/22".parse().unwrap()];
+        assert_eq!(
+            FilterPresets::exclude_subnets(base, &subnets),
+            "(outbound and tcp) and \
+             not (ip.DstAddr >= 10.8.0.0 and ip.DstAddr <= 10.8.0.255) and \
+             not (ip.SrcAddr >= 10.8.0.0 and ip.SrcAddr <= 10.8.0.255) and \
+             not (ip.DstAddr >= 192.168.100.0 and ip.DstAddr <= 192.168.103.255) and \
+             not (ip.SrcAddr >= 192.168.100.0 and ip.SrcAddr <= 192.168.103.255)"
+        );
+    }
+
+    #[test]
+    fn test_presets_parse() {
+        let presets = [
+            FilterPresets::http_outbound(),
+            FilterPresets::https_client_hello(),
+            FilterPresets::dns_redirect(&[53, 1253]),
+            FilterPresets::rst_inbound(),
+            FilterPresets::syn_ack_inbound(),
+            FilterPresets::tcp_outbound(&[8080], 1200),
+            FilterPresets::goodbyedpi(true, &[8443], 1200),
+            FilterPresets::exclude_subnets(&FilterPresets::turkey_optimized(), &["10.8.0.0/24".parse().unwrap()]),
+        ];
+        for preset in presets {
+            let expr = FilterExpr::parse(&preset).unwrap_or_else(|e| panic!("{preset}: {e}"));
+            assert_eq!(FilterExpr::parse(&expr.to_string()).unwrap(), expr);
+        }
+    }
+
+    #[test]
+    fn test_build_expr() {
+        let expr = FilterBuilder::new().outbound().tcp().dst_port(443).build_expr().unwrap();
+        assert_eq!(expr.to_string(), "outbound and tcp and tcp.DstPort == 443");
+
+        assert!(FilterBuilder::new().raw("tcp.DstPrt == 443").build_expr().is_err());
+    }
+
+    #[test]
+    fn test_rst_inbound_preset() {
+        assert_eq!(FilterPresets::rst_inbound(), "(inbound and tcp and tcp.Rst)");
+    }
+
+    #[test]
+    fn test_full_presets_capture_dns_over_tcp() {
+        assert!(FilterPresets::goodbyedpi_full().contains("tcp.DstPort == 53"));
+        assert!(FilterPresets::turkey_optimized().contains("tcp.DstPort == 53"));
+    }
 }
@@ -0,0 +1,661 @@
+//! WinDivert filter language parser
+//!
+//! Parses network-layer filters into a [`FilterExpr`] tree, so mistakes
+//! such as `tcp.DstPrt == 443` are reported with their column before the
+//! driver sees them. Field names are matched case-insensitively, as
+//! WinDivert does, and rendered in their documented spelling.
+
+use crate::error::{PlatformError, Result};
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Parsed filter expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterExpr {
+    /// `true` or `false`
+    Bool(bool),
+    /// A field on its own, true when non-zero (`tcp`, `tcp.Syn`)
+    Test(Field),
+    /// `field op value`
+    Compare(Field, CompareOp, Value),
+    /// `not expr`
+    Not(Box<FilterExpr>),
+    /// `lhs and rhs`
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    /// `lhs or rhs`
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+    /// `(test ? then : otherwise)`
+    If(Box<FilterExpr>, Box<FilterExpr>, Box<FilterExpr>),
+}
+
+/// Packet field, optionally indexed (`packet[0]`, `tcp.Payload16[-2]`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// Documented spelling, e.g. "tcp.DstPort"
+    pub name: &'static str,
+    /// Byte offset for indexed fields
+    pub index: Option<i32>,
+}
+
+/// Comparison operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    /// `==`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `<`
+    Lt,
+    /// `<=`
+    Le,
+    /// `>`
+    Gt,
+    /// `>=`
+    Ge,
+}
+
+/// Literal compared against a field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// Decimal or hex number (`true`/`false` are 1/0)
+    Number(u64),
+    /// IPv4 address
+    Ipv4(Ipv4Addr),
+    /// IPv6 address
+    Ipv6(Ipv6Addr),
+}
+
+/// What a field holds, and so what it can be compared with
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    /// Number up to the given maximum
+    Number(u64),
+    /// IPv4 address
+    Ipv4,
+    /// IPv6 address
+    Ipv6,
+    /// Byte-indexed number up to the given maximum
+    Indexed(u64),
+}
+
+const FLAG: Kind = Kind::Number(1);
+const U8: Kind = Kind::Number(0xFF);
+const U16: Kind = Kind::Number(0xFFFF);
+const U32: Kind = Kind::Number(0xFFFF_FFFF);
+
+/// Network layer fields
+const FIELDS: &[(&str, Kind)] = &[
+    ("zero", Kind::Number(0)),
+    ("timestamp", Kind::Number(u64::MAX)),
+    ("outbound", FLAG),
+    ("inbound", FLAG),
+    ("fragment", FLAG),
+    ("ifIdx", U32),
+    ("subIfIdx", U32),
+    ("loopback", FLAG),
+    ("impostor", FLAG),
+    ("random8", U8),
+    ("random16", U16),
+    ("random32", U32),
+    ("length", U16),
+    ("ip", FLAG),
+    ("ipv6", FLAG),
+    ("icmp", FLAG),
+    ("icmpv6", FLAG),
+    ("tcp", FLAG),
+    ("udp", FLAG),
+    ("ip.HdrLength", Kind::Number(0xF)),
+    ("ip.TOS", U8),
+    ("ip.Length", U16),
+    ("ip.Id", U16),
+    ("ip.DF", FLAG),
+    ("ip.MF", FLAG),
+    ("ip.FragOff", Kind::Number(0x1FFF)),
+    ("ip.TTL", U8),
+    ("ip.Protocol", U8),
+    ("ip.Checksum", U16),
+    ("ip.SrcAddr", Kind::Ipv4),
+    ("ip.DstAddr", Kind::Ipv4),
+    ("ipv6.TrafficClass", U8),
+    ("ipv6.FlowLabel", Kind::Number(0xF_FFFF)),
+    ("ipv6.Length", U16),
+    ("ipv6.NextHdr", U8),
+    ("ipv6.HopLimit", U8),
+    ("ipv6.SrcAddr", Kind::Ipv6),
+    ("ipv6.DstAddr", Kind::Ipv6),
+    ("icmp.Type", U8),
+    ("icmp.Code", U8),
+    ("icmp.Checksum", U16),
+    ("icmp.Body", U32),
+    ("icmpv6.Type", U8),
+    ("icmpv6.Code", U8),
+    ("icmpv6.Checksum", U16),
+    ("icmpv6.Body", U32),
+    ("tcp.SrcPort", U16),
+    ("tcp.DstPort", U16),
+    ("tcp.SeqNum", U32),
+    ("tcp.AckNum", U32),
+    ("tcp.HdrLength", Kind::Number(0xF)),
+    ("tcp.Urg", FLAG),
+    ("tcp.Ack", FLAG),
+    ("tcp.Psh", FLAG),
+    ("tcp.Rst", FLAG),
+    ("tcp.Syn", FLAG),
+    ("tcp.Fin", FLAG),
+    ("tcp.Window", U16),
+    ("tcp.Checksum", U16),
+    ("tcp.UrgPtr", U16),
+    ("tcp.PayloadLength", U16),
+    ("udp.SrcPort", U16),
+    ("udp.DstPort", U16),
+    ("udp.Length", U16),
+    ("udp.Checksum", U16),
+    ("udp.PayloadLength", U16),
+    ("packet", Kind::Indexed(0xFF)),
+    ("packet16", Kind::Indexed(0xFFFF)),
+    ("packet32", Kind::Indexed(0xFFFF_FFFF)),
+    ("tcp.Payload", Kind::Indexed(0xFF)),
+    ("tcp.Payload16", Kind::Indexed(0xFFFF)),
+    ("tcp.Payload32", Kind::Indexed(0xFFFF_FFFF)),
+    ("udp.Payload", Kind::Indexed(0xFF)),
+    ("udp.Payload16", Kind::Indexed(0xFFFF)),
+    ("udp.Payload32", Kind::Indexed(0xFFFF_FFFF)),
+];
+
+impl FilterExpr {
+    /// Parse a filter, reporting the first error with its column
+    pub fn parse(filter: &str) -> Result<Self> {
+        let tokens = tokenize(filter)?;
+        if tokens.is_empty() {
+            return Err(PlatformError::InvalidFilter("Empty filter".into()));
+        }
+
+        let mut parser = Parser { tokens, pos: 0, end: filter.chars().count() + 1 };
+        let expr = parser.conditional()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) if token.tok == Tok::Close => Err(error(token.column, "Unmatched ')'")),
+            Some(token) => Err(error(token.column, format!("Unexpected '{}'", token.text))),
+        }
+    }
+
+    /// Binding strength, for deciding where parentheses are needed
+    ///
+    /// Conditionals are always rendered in parentheses.
+    fn precedence(&self) -> u8 {
+        match self {
+            FilterExpr::Or(..) => 1,
+            FilterExpr::And(..) => 2,
+            _ => 3,
+        }
+    }
+
+    fn fmt_within(&self, f: &mut fmt::Formatter<'_>, min: u8) -> fmt::Result {
+        if self.precedence() < min {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+impl fmt::Display for FilterExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterExpr::Bool(value) => write!(f, "{}", value),
+            FilterExpr::Test(field) => write!(f, "{}", field),
+            FilterExpr::Compare(field, op, value) => write!(f, "{} {} {}", field, op, value),
+            FilterExpr::Not(inner) => {
+                f.write_str("not ")?;
+                inner.fmt_within(f, 3)
+            }
+            FilterExpr::And(lhs, rhs) => {
+                lhs.fmt_within(f, 2)?;
+                f.write_str(" and ")?;
+                rhs.fmt_within(f, 3)
+            }
+            FilterExpr::Or(lhs, rhs) => {
+                lhs.fmt_within(f, 1)?;
+                f.write_str(" or ")?;
+                rhs.fmt_within(f, 2)
+            }
+            FilterExpr::If(test, then, otherwise) => {
+                f.write_str("(")?;
+                test.fmt_within(f, 1)?;
+                f.write_str(" ? ")?;
+                then.fmt_within(f, 1)?;
+                f.write_str(" : ")?;
+                otherwise.fmt_within(f, 1)?;
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "{}[{}]", self.name, index),
+            None => f.write_str(self.name),
+        }
+    }
+}
+
+impl fmt::Display for CompareOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        })
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Ipv4(ip) => write!(f, "{}", ip),
+            Value::Ipv6(ip) => write!(f, "{}", ip),
+        }
+    }
+}
+
+fn error(column: usize, message: impl fmt::Display) -> PlatformError {
+    PlatformError::InvalidFilter(format!("Syntax error at column {}: {}", column, message))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    /// Field name, keyword or literal
+    Word,
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+    Minus,
+    Question,
+    Colon,
+    Not,
+    And,
+    Or,
+    Compare(CompareOp),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    text: String,
+    /// 1-based column of the first character
+    column: usize,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '.' || c == '_'
+}
+
+fn tokenize(filter: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = filter.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        let next = chars.get(i + 1).copied();
+        let tok = match (c, next) {
+            ('=', Some('=')) | ('!', Some('=')) | ('<', Some('=')) | ('>', Some('=')) => {
+                i += 2;
+                Tok::Compare(match c {
+                    '=' => CompareOp::Eq,
+                    '!' => CompareOp::Ne,
+                    '<' => CompareOp::Le,
+                    _ => CompareOp::Ge,
+                })
+            }
+            ('&', Some('&')) => {
+                i += 2;
+                Tok::And
+            }
+            ('|', Some('|')) => {
+                i += 2;
+                Tok::Or
+            }
+            _ if is_word_char(c) || c == ':' => {
+                i = word_end(&chars, i);
+                Tok::Word
+            }
+            _ => {
+                i += 1;
+                match c {
+                    '=' => Tok::Compare(CompareOp::Eq),
+                    '<' => Tok::Compare(CompareOp::Lt),
+                    '>' => Tok::Compare(CompareOp::Gt),
+                    '!' => Tok::Not,
+                    '(' => Tok::Open,
+                    ')' => Tok::Close,
+                    '[' => Tok::OpenBracket,
+                    ']' => Tok::CloseBracket,
+                    '-' => Tok::Minus,
+                    '?' => Tok::Question,
+                    _ => return Err(error(start + 1, format!("Unexpected character '{}'", c))),
+                }
+            }
+        };
+
+        let text: String = chars[start..i].iter().collect();
+        let tok = match (tok, text.to_ascii_lowercase().as_str()) {
+            (Tok::Word, "and") => Tok::And,
+            (Tok::Word, "or") => Tok::Or,
+            (Tok::Word, "not") => Tok::Not,
+            (Tok::Word, ":") => Tok::Colon,
+            (tok, _) => tok,
+        };
+        tokens.push(Token { tok, text, column: start + 1 });
+    }
+
+    Ok(tokens)
+}
+
+/// End of the word starting at `start`
+///
+/// Colons belong to IPv6 addresses but also separate the branches of
+/// `a ? b : c`, so a run containing one is only kept whole when it is an
+/// address.
+fn word_end(chars: &[char], start: usize) -> usize {
+    let run = start + chars[start..]
+        .iter()
+        .take_while(|&&c| is_word_char(c) || c == ':')
+        .count();
+    let text: String = chars[start..run].iter().collect();
+    if !text.contains(':') || text.parse::<Ipv6Addr>().is_ok() {
+        return run;
+    }
+    match chars[start..run].iter().position(|&c| c == ':') {
+        // A lone colon
+        Some(0) => start + 1,
+        Some(colon) => start + colon,
+        None => run,
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Column just past the end of the filter
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consume the next token if it is `tok`
+    fn eat(&mut self, tok: &Tok) -> bool {
+        if self.peek().is_some_and(|t| t.tok == *tok) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Consume `tok` or fail with what was expected
+    fn expect(&mut self, tok: &Tok, expected: &str) -> Result<()> {
+        match self.advance() {
+            Some(token) if token.tok == *tok => Ok(()),
+            Some(token) => Err(error(token.column, format!("Expected {}, found '{}'", expected, token.text))),
+            None => Err(error(self.end, format!("Expected {} at end of filter", expected))),
+        }
+    }
+
+    fn conditional(&mut self) -> Result<FilterExpr> {
+        let test = self.or()?;
+        if !self.eat(&Tok::Question) {
+            return Ok(test);
+        }
+        let then = self.conditional()?;
+        self.expect(&Tok::Colon, "':'")?;
+        let otherwise = self.conditional()?;
+        Ok(FilterExpr::If(Box::new(test), Box::new(then), Box::new(otherwise)))
+    }
+
+    fn or(&mut self) -> Result<FilterExpr> {
+        let mut expr = self.and()?;
+        while self.eat(&Tok::Or) {
+            expr = FilterExpr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<FilterExpr> {
+        let mut expr = self.unary()?;
+        while self.eat(&Tok::And) {
+            expr = FilterExpr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<FilterExpr> {
+        if self.eat(&Tok::Not) {
+            return Ok(FilterExpr::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<FilterExpr> {
+        let Some(token) = self.advance() else {
+            return Err(error(self.end, "Expected a condition at end of filter"));
+        };
+
+        match token.tok {
+            Tok::Open => {
+                let expr = self.conditional()?;
+                match self.advance() {
+                    Some(t) if t.tok == Tok::Close => Ok(expr),
+                    Some(t) => Err(error(t.column, format!("Expected ')', found '{}'", t.text))),
+                    None => Err(error(token.column, "Unclosed '('")),
+                }
+            }
+            Tok::Word => match token.text.to_ascii_lowercase().as_str() {
+                "true" => Ok(FilterExpr::Bool(true)),
+                "false" => Ok(FilterExpr::Bool(false)),
+                _ => self.field_test(&token),
+            },
+            _ => Err(error(token.column, format!("Expected a condition, found '{}'", token.text))),
+        }
+    }
+
+    /// A field, alone or compared with a value
+    fn field_test(&mut self, token: &Token) -> Result<FilterExpr> {
+        let Some(&(name, kind)) = FIELDS.iter().find(|(name, _)| name.eq_ignore_ascii_case(&token.text)) else {
+            return Err(error(token.column, format!("Unknown field '{}'", token.text)));
+        };
+
+        let index = if self.eat(&Tok::OpenBracket) {
+            let Kind::Indexed(_) = kind else {
+                return Err(error(token.column, format!("{} does not take an index", name)));
+            };
+            Some(self.index()?)
+        } else {
+            None
+        };
+        if matches!(kind, Kind::Indexed(_)) && index.is_none() {
+            return Err(error(token.column, format!("{} needs an index, e.g. {}[0]", name, name)));
+        }
+        let field = Field { name, index };
+
+        let op = match self.peek() {
+            Some(Token { tok: Tok::Compare(op), .. }) => *op,
+            _ => return Ok(FilterExpr::Test(field)),
+        };
+        self.pos += 1;
+
+        let Some(value_token) = self.advance() else {
+            return Err(error(self.end, format!("Expected a value after '{}'", op)));
+        };
+        let value = parse_value(&value_token)?;
+        check_value(name, kind, &value, value_token.column)?;
+        Ok(FilterExpr::Compare(field, op, value))
+    }
+
+    /// `[n]` or `[-n]`, after the opening bracket
+    fn index(&mut self) -> Result<i32> {
+        let negative = self.eat(&Tok::Minus);
+        let token = match self.advance() {
+            Some(token) if token.tok == Tok::Word => token,
+            Some(token) => return Err(error(token.column, format!("Expected an index, found '{}'", token.text))),
+            None => return Err(error(self.end, "Expected an index at end of filter")),
+        };
+        let index = match parse_number(&token.text).map(i32::try_from) {
+            Some(Ok(index)) => index,
+            _ => return Err(error(token.column, format!("Invalid index '{}'", token.text))),
+        };
+        self.expect(&Tok::CloseBracket, "']'")?;
+        Ok(if negative { -index } else { index })
+    }
+}
+
+fn parse_number(text: &str) -> Option<u64> {
+    match text.get(..2) {
+        Some("0x" | "0X") => u64::from_str_radix(&text[2..], 16).ok(),
+        _ => text.parse().ok(),
+    }
+}
+
+fn parse_value(token: &Token) -> Result<Value> {
+    if token.tok != Tok::Word {
+        return Err(error(token.column, format!("Expected a value, found '{}'", token.text)));
+    }
+    let text = token.text.as_str();
+    if text.eq_ignore_ascii_case("true") {
+        return Ok(Value::Number(1));
+    }
+    if text.eq_ignore_ascii_case("false") {
+        return Ok(Value::Number(0));
+    }
+    if let Some(n) = parse_number(text) {
+        return Ok(Value::Number(n));
+    }
+    if let Ok(ip) = text.parse() {
+        return Ok(Value::Ipv4(ip));
+    }
+    if let Ok(ip) = text.parse() {
+        return Ok(Value::Ipv6(ip));
+    }
+    Err(error(token.column, format!("Invalid value '{}'", text)))
+}
+
+/// Check that `value` fits the field it is compared with
+fn check_value(name: &str, kind: Kind, value: &Value, column: usize) -> Result<()> {
+    match (kind, value) {
+        (Kind::Number(max) | Kind::Indexed(max), Value::Number(n)) if *n > max => Err(error(
+            column,
+            format!("{} is out of range for {} (0-{})", n, name, max),
+        )),
+        (Kind::Number(_) | Kind::Indexed(_), Value::Number(_))
+        | (Kind::Ipv4, Value::Ipv4(_))
+        | (Kind::Ipv6, Value::Ipv6(_)) => Ok(()),
+        (Kind::Ipv4, _) => Err(error(column, format!("{} needs an IPv4 address", name))),
+        (Kind::Ipv6, _) => Err(error(column, format!("{} needs an IPv6 address", name))),
+        (_, _) => Err(error(column, format!("{} needs a number, not an address", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_filters() {
+        // (filter, rendering)
+        let cases = [
+            ("true", "true"),
+            ("outbound", "outbound"),
+            ("tcp.DstPort == 443", "tcp.DstPort == 443"),
+            ("outbound and tcp", "outbound and tcp"),
+            ("outbound && !loopback", "outbound and not loopback"),
+            ("TCP.DSTPORT = 0x1BB", "tcp.DstPort == 443"),
+            ("tcp.Syn and tcp.Ack", "tcp.Syn and tcp.Ack"),
+            ("tcp.PayloadLength <= 1200", "tcp.PayloadLength <= 1200"),
+            ("ip.DstAddr >= 10.0.0.0 and ip.DstAddr <= 10.255.255.255",
+             "ip.DstAddr >= 10.0.0.0 and ip.DstAddr <= 10.255.255.255"),
+            ("ipv6.DstAddr == 2a02:6b8::feed:ff", "ipv6.DstAddr == 2a02:6b8::feed:ff"),
+            ("ipv6.SrcAddr != ::1", "ipv6.SrcAddr != ::1"),
+            ("(tcp or udp) and outbound", "(tcp or udp) and outbound"),
+            ("tcp or udp and outbound", "tcp or udp and outbound"),
+            ("not (tcp.DstPort == 80 or tcp.DstPort == 443)",
+             "not (tcp.DstPort == 80 or tcp.DstPort == 443)"),
+            ("packet[0] == 0x45", "packet[0] == 69"),
+            ("tcp.Payload16[-2] != 0", "tcp.Payload16[-2] != 0"),
+            ("(tcp? tcp.Syn: udp)", "(tcp ? tcp.Syn : udp)"),
+            ("tcp.Syn == true", "tcp.Syn == 1"),
+            ("((outbound))", "outbound"),
+        ];
+        for (filter, rendered) in cases {
+            match FilterExpr::parse(filter) {
+                Ok(expr) => assert_eq!(expr.to_string(), rendered, "{filter}"),
+                Err(e) => panic!("{filter}: {e}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_filters() {
+        // (filter, expected error)
+        let cases = [
+            ("", "Empty filter"),
+            ("   ", "Empty filter"),
+            ("tcp.DstPrt == 443", "column 1: Unknown field 'tcp.DstPrt'"),
+            ("outbound and tcp.NoSuchField", "column 14: Unknown field 'tcp.NoSuchField'"),
+            ("tcp.DstPort ==", "column 15: Expected a value after '=='"),
+            ("tcp.DstPort == 70000", "column 16: 70000 is out of range for tcp.DstPort (0-65535)"),
+            ("tcp.Syn == 2", "column 12: 2 is out of range for tcp.Syn (0-1)"),
+            ("ip.DstAddr == 443", "column 15: ip.DstAddr needs an IPv4 address"),
+            ("ip.DstAddr == ::1", "column 15: ip.DstAddr needs an IPv4 address"),
+            ("tcp.DstPort == 1.2.3.4", "column 16: tcp.DstPort needs a number, not an address"),
+            ("tcp.DstPort == 443 xor udp", "column 20: Unexpected 'xor'"),
+            ("outbound and and tcp", "column 14: Expected a condition, found 'and'"),
+            ("outbound and", "column 13: Expected a condition at end of filter"),
+            ("(tcp or udp", "column 1: Unclosed '('"),
+            ("tcp or udp)", "column 11: Unmatched ')'"),
+            ("tcp.DstPort[0] == 1", "column 1: tcp.DstPort does not take an index"),
+            ("packet == 1", "column 1: packet needs an index, e.g. packet[0]"),
+            ("packet[x] == 1", "column 8: Invalid index 'x'"),
+            ("packet[0 == 1", "column 10: Expected ']', found '=='"),
+            ("tcp ? udp", "column 10: Expected ':' at end of filter"),
+            ("tcp.DstPort == 10.0.0", "column 16: Invalid value '10.0.0'"),
+            ("tcp.DstPort # 1", "column 13: Unexpected character '#'"),
+        ];
+        for (filter, expected) in cases {
+            match FilterExpr::parse(filter) {
+                Err(PlatformError::InvalidFilter(msg)) => {
+                    assert!(msg.ends_with(expected), "{filter}: {msg}");
+                }
+                other => panic!("{filter}: expected an error, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_rendering_parses_back() {
+        let filter = "(tcp ? (tcp.DstPort == 80 or tcp.DstPort == 443) : udp.DstPort == 53) \
+                      and not (ip.DstAddr >= 10.0.0.0 and ip.DstAddr <= 10.0.0.255)";
+        let expr = FilterExpr::parse(filter).unwrap();
+        assert_eq!(FilterExpr::parse(&expr.to_string()).unwrap(), expr);
+    }
+}
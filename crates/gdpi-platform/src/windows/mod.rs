@@ -5,6 +5,10 @@
 
 mod driver;
 mod filter;
+mod multi;
+mod reload;
 
-pub use driver::{WinDivertDriver, Flags, Layer};
-pub use filter::{FilterBuilder, FilterPresets};
+pub use driver::{DriverOptions, QueueStats, WinDivertDriver, Flags, Layer};
+pub use filter::{subnet_range, CompareOp, Field, FilterBuilder, FilterExpr, FilterPresets, Value};
+pub use multi::{HandleKind, MultiHandleDriver};
+pub use reload::{ReloadSignal, RELOAD_EVENT_NAME};
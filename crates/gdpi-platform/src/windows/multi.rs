@@ -0,0 +1,418 @@
+//! Several WinDivert handles, one per kind of traffic
+//!
+//! One catch-all filter brings every matching packet to user space, even
+//! those only one strategy cares about. Narrow filters on separate handles
+//! leave the rest in the kernel. Each handle gets a reader thread feeding
+//! its own channel, and [`MultiHandleDriver`] selects over the channels.
+
+use super::driver::{Flags, Layer, WinDivertDriver};
+use super::filter::FilterPresets;
+use crate::error::{PlatformError, Result};
+use crate::traits::{CapturedPacket, PacketAddress};
+use bytes::Bytes;
+use crossbeam_channel::{bounded, never, select, Receiver, Sender};
+use gdpi_core::Config;
+use ipnetwork::Ipv4Network;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Longest a reader thread blocks before checking for shutdown
+const READ_POLL: Duration = Duration::from_millis(250);
+
+/// Packets a reader may queue before it stops reading its handle
+const LANE_CAPACITY: usize = 1024;
+
+/// Longest a reader waits before reading its handle again after an error
+const MAX_ERROR_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Traffic captured by one handle of a [`MultiHandleDriver`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HandleKind {
+    /// Outbound TCP to HTTP(S) and the configured extra ports
+    TcpOutbound,
+    /// Inbound SYN-ACKs, plus resets when passive DPI blocking is on
+    TcpInbound,
+    /// DNS queries and upstream responses, when DNS redirection is on
+    Dns,
+    /// Outbound QUIC, when it is blocked
+    Quic,
+}
+
+impl HandleKind {
+    /// Every kind, in the order the handles are opened
+    pub const ALL: [HandleKind; 4] = [
+        HandleKind::TcpOutbound,
+        HandleKind::TcpInbound,
+        HandleKind::Dns,
+        HandleKind::Quic,
+    ];
+
+    /// WinDivert priority of the handle
+    ///
+    /// The filters barely overlap (a SYN-ACK from a DNS upstream matches
+    /// two), and distinct priorities make such a packet pass the handles
+    /// one after another instead of in an undefined order.
+    fn priority(self) -> i16 {
+        match self {
+            HandleKind::TcpOutbound => 3,
+            HandleKind::TcpInbound => 2,
+            HandleKind::Dns => 1,
+            HandleKind::Quic => 0,
+        }
+    }
+
+    /// Filter for this kind, or `None` if `config` needs no such handle
+    pub fn filter(self, config: &Config) -> Option<String> {
+        match self {
+            HandleKind::TcpOutbound => {
+                let max_payload = config.performance.max_payload_size;
+                let ports = FilterPresets::tcp_outbound(&config.bypass_ports(), max_payload);
+                Some(if config.performance.http_all_ports {
+                    format!("({}) or {}", ports, FilterPresets::tcp_data_outbound(max_payload))
+                } else {
+                    ports
+                })
+            }
+            HandleKind::TcpInbound => {
+                let syn_ack = FilterPresets::syn_ack_inbound();
+                Some(if config.strategies.passive_dpi.enabled {
+                    format!("({}) or {}", syn_ack, FilterPresets::rst_inbound())
+                } else {
+                    syn_ack
+                })
+            }
+            HandleKind::Dns => {
+                let upstream_ports = dns_upstream_ports(config);
+                (config.dns.enabled && !upstream_ports.is_empty())
+                    .then(|| FilterPresets::dns_redirect(&upstream_ports))
+            }
+            HandleKind::Quic => config.strategies.block_quic.then(FilterPresets::quic_outbound),
+        }
+    }
+
+    /// Filter of every handle `config` needs, minus traffic to any of
+    /// `exclude`
+    pub fn filters(config: &Config, exclude: &[Ipv4Network]) -> Vec<(HandleKind, String)> {
+        HandleKind::ALL
+            .into_iter()
+            .filter_map(|kind| {
+                let filter = kind.filter(config)?;
+                Some((kind, FilterPresets::exclude_subnets(&filter, exclude)))
+            })
+            .collect()
+    }
+}
+
+/// Ports DNS responses come back from
+fn dns_upstream_ports(config: &Config) -> Vec<u16> {
+    let dns = &config.dns;
+    [
+        dns.ipv4_upstream.map(|_| dns.ipv4_port.unwrap_or(53)),
+        dns.ipv6_upstream.map(|_| dns.ipv6_port.unwrap_or(53)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// One handle and the thread reading it
+struct Lane {
+    driver: Arc<WinDivertDriver>,
+    packets: Receiver<Result<CapturedPacket>>,
+    reader: JoinHandle<()>,
+}
+
+impl Lane {
+    fn open(kind: HandleKind, filter: &str, running: &Arc<AtomicBool>) -> Result<Self> {
+        let driver = Arc::new(WinDivertDriver::open_ex(
+            filter,
+            Layer::Network,
+            kind.priority(),
+            Flags::default(),
+        )?);
+        let (sender, packets) = bounded(LANE_CAPACITY);
+
+        let reader = {
+            let driver = Arc::clone(&driver);
+            let running = Arc::clone(running);
+            std::thread::Builder::new()
+                .name(format!("gdpi-recv-{:?}", kind))
+                .spawn(move || read_loop(&driver, &sender, &running))?
+        };
+
+        Ok(Self {
+            driver,
+            packets,
+            reader,
+        })
+    }
+
+    fn close(self) {
+        // A reader blocked on a full channel fails once it is gone
+        drop(self.packets);
+        if self.reader.join().is_err() {
+            warn!("WinDivert reader thread panicked");
+        }
+    }
+}
+
+fn read_loop(driver: &WinDivertDriver, packets: &Sender<Result<CapturedPacket>>, running: &AtomicBool) {
+    let mut buffer = vec![0u8; WinDivertDriver::MAX_PACKET_SIZE];
+    let mut backoff = Duration::ZERO;
+    while running.load(Ordering::SeqCst) {
+        match driver.recv_timeout_shared(&mut buffer, READ_POLL) {
+            Ok(None) => backoff = Duration::ZERO,
+            Ok(Some(packet)) => {
+                backoff = Duration::ZERO;
+                if packets.send(Ok(packet)).is_err() {
+                    break;
+                }
+            }
+            Err(e) => {
+                if packets.send(Err(e)).is_err() {
+                    break;
+                }
+                // A broken handle fails right away; don't spin on it
+                backoff = (backoff * 2).clamp(READ_POLL, MAX_ERROR_BACKOFF);
+                std::thread::sleep(backoff);
+            }
+        }
+    }
+}
+
+/// WinDivert capture split over one handle per [`HandleKind`]
+///
+/// Packets must be re-injected through the handle they came from, so
+/// they are received and sent together with their kind.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use gdpi_platform::windows::MultiHandleDriver;
+///
+/// let driver = MultiHandleDriver::open_from_config(&config)?;
+/// while let Some((kind, captured)) = driver.recv_timeout(Duration::from_millis(250))? {
+///     driver.send(kind, &captured.data, &captured.address)?;
+/// }
+/// ```
+pub struct MultiHandleDriver {
+    /// Indexed by `HandleKind as usize`
+    lanes: [Option<Lane>; 4],
+    /// Filter of each open handle, in [`HandleKind::ALL`] order
+    filters: Vec<(HandleKind, String)>,
+    /// Cleared to stop the reader threads
+    running: Arc<AtomicBool>,
+}
+
+impl MultiHandleDriver {
+    /// Open the handles `config` needs
+    ///
+    /// # Errors
+    /// Returns error if any handle fails to open; those already open are
+    /// closed again.
+    pub fn open_from_config(config: &Config) -> Result<Self> {
+        Self::open(HandleKind::filters(config, &[]))
+    }
+
+    /// Open one handle per entry of `filters`, e.g. from
+    /// [`HandleKind::filters`]
+    ///
+    /// # Errors
+    /// Returns error if any handle fails to open; those already open are
+    /// closed again.
+    pub fn open(filters: Vec<(HandleKind, String)>) -> Result<Self> {
+        let mut driver = Self {
+            lanes: Default::default(),
+            filters: Vec::new(),
+            running: Arc::new(AtomicBool::new(true)),
+        };
+
+        for (kind, filter) in filters {
+            info!(?kind, filter = filter, "Opening WinDivert handle");
+            driver.lanes[kind as usize] = Some(Lane::open(kind, &filter, &driver.running)?);
+            driver.filters.push((kind, filter));
+        }
+
+        Ok(driver)
+    }
+
+    /// Filter of each open handle
+    pub fn filters(&self) -> &[(HandleKind, String)] {
+        &self.filters
+    }
+
+    /// Kinds with an open handle
+    pub fn kinds(&self) -> Vec<HandleKind> {
+        HandleKind::ALL
+            .into_iter()
+            .filter(|&kind| self.lanes[kind as usize].is_some())
+            .collect()
+    }
+
+    /// Next packet from any handle
+    pub fn recv(&self) -> Result<(HandleKind, CapturedPacket)> {
+        loop {
+            if let Some(received) = self.recv_timeout(Duration::MAX)? {
+                return Ok(received);
+            }
+        }
+    }
+
+    /// Next packet from any handle, or `Ok(None)` if none arrives within
+    /// `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<(HandleKind, CapturedPacket)>> {
+        let closed = never();
+        let packets = |kind: HandleKind| {
+            self.lanes[kind as usize]
+                .as_ref()
+                .map_or(&closed, |lane| &lane.packets)
+        };
+
+        let (kind, received) = select! {
+            recv(packets(HandleKind::TcpOutbound)) -> msg => (HandleKind::TcpOutbound, msg),
+            recv(packets(HandleKind::TcpInbound)) -> msg => (HandleKind::TcpInbound, msg),
+            recv(packets(HandleKind::Dns)) -> msg => (HandleKind::Dns, msg),
+            recv(packets(HandleKind::Quic)) -> msg => (HandleKind::Quic, msg),
+            default(timeout) => return Ok(None),
+        };
+
+        let packet = received
+            .map_err(|_| PlatformError::HandleError(format!("{:?} reader stopped", kind)))??;
+        Ok(Some((kind, packet)))
+    }
+
+    /// Up to `max_count` packets from any handle, waiting at most `timeout`
+    /// for the first; empty if none arrives
+    pub fn recv_batch_timeout(
+        &self,
+        max_count: usize,
+        timeout: Duration,
+    ) -> Result<Vec<(HandleKind, CapturedPacket)>> {
+        let Some(first) = self.recv_timeout(timeout)? else {
+            return Ok(Vec::new());
+        };
+        let mut batch = vec![first];
+        while batch.len() < max_count {
+            match self.recv_timeout(Duration::ZERO) {
+                Ok(Some(received)) => batch.push(received),
+                Ok(None) => break,
+                Err(e) => {
+                    // Deliver what was received; the reader reports again
+                    debug!("Ending batch early: {}", e);
+                    break;
+                }
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Inject a packet through the handle of `kind`
+    pub fn send(&self, kind: HandleKind, packet: &[u8], addr: &PacketAddress) -> Result<()> {
+        self.driver(kind)?.send_shared(packet, addr)
+    }
+
+    /// Inject packets through the handle of `kind`, returning how many
+    /// were queued
+    pub fn send_batch(&self, kind: HandleKind, packets: &[(Bytes, PacketAddress)]) -> Result<u32> {
+        self.driver(kind)?.send_batch_shared(packets)
+    }
+
+    fn driver(&self, kind: HandleKind) -> Result<&WinDivertDriver> {
+        self.lanes[kind as usize]
+            .as_ref()
+            .map(|lane| lane.driver.as_ref())
+            .ok_or_else(|| PlatformError::HandleError(format!("No {:?} handle", kind)))
+    }
+
+    /// Stop the reader threads and close every handle
+    pub fn close(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        for lane in self.lanes.iter_mut().filter_map(Option::take) {
+            lane.close();
+        }
+    }
+}
+
+impl Drop for MultiHandleDriver {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::windows::FilterExpr;
+
+    #[test]
+    fn test_filters_follow_config() {
+        let mut config = Config::default();
+        config.dns.enabled = false;
+        config.strategies.block_quic = false;
+        config.strategies.passive_dpi.enabled = false;
+
+        assert_eq!(
+            HandleKind::TcpOutbound.filter(&config).unwrap(),
+            FilterPresets::tcp_outbound(&config.bypass_ports(), config.performance.max_payload_size)
+        );
+        assert_eq!(HandleKind::TcpInbound.filter(&config).unwrap(), FilterPresets::syn_ack_inbound());
+        assert_eq!(HandleKind::Dns.filter(&config), None);
+        assert_eq!(HandleKind::Quic.filter(&config), None);
+
+        config.dns.enabled = true;
+        config.dns.ipv4_upstream = Some("77.88.8.8".parse().unwrap());
+        config.dns.ipv4_port = Some(1253);
+        config.strategies.block_quic = true;
+        config.strategies.passive_dpi.enabled = true;
+
+        assert_eq!(HandleKind::Dns.filter(&config).unwrap(), FilterPresets::dns_redirect(&[1253]));
+        assert_eq!(HandleKind::Quic.filter(&config).unwrap(), FilterPresets::quic_outbound());
+        assert!(HandleKind::TcpInbound.filter(&config).unwrap().contains("tcp.Rst"));
+
+        config.performance.http_all_ports = true;
+        assert!(HandleKind::TcpOutbound
+            .filter(&config)
+            .unwrap()
+            .ends_with(&FilterPresets::tcp_data_outbound(config.performance.max_payload_size)));
+    }
+
+    #[test]
+    fn test_filters_parse() {
+        let mut config = Config::default();
+        config.dns.enabled = true;
+        config.dns.ipv4_upstream = Some("77.88.8.8".parse().unwrap());
+        config.strategies.block_quic = true;
+        config.strategies.passive_dpi.enabled = true;
+
+        for kind in HandleKind::ALL {
+            let filter = kind.filter(&config).unwrap();
+            FilterExpr::parse(&filter).unwrap_or_else(|e| panic!("{filter}: {e}"));
+        }
+    }
+
+    #[test]
+    fn test_filters_exclude_subnets() {
+        let mut config = Config::default();
+        config.dns.enabled = false;
+        config.strategies.block_quic = true;
+        let vpn: Ipv4Network = "10.8.0.0/24".parse().unwrap();
+
+        let filters = HandleKind::filters(&config, &[vpn]);
+        let kinds: Vec<_> = filters.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, [HandleKind::TcpOutbound, HandleKind::TcpInbound, HandleKind::Quic]);
+        for (kind, filter) in &filters {
+            assert_eq!(*filter, FilterPresets::exclude_subnets(&kind.filter(&config).unwrap(), &[vpn]));
+        }
+    }
+
+    #[test]
+    fn test_priorities_distinct() {
+        let mut priorities: Vec<_> = HandleKind::ALL.iter().map(|kind| kind.priority()).collect();
+        priorities.sort_unstable();
+        priorities.dedup();
+        assert_eq!(priorities.len(), HandleKind::ALL.len());
+    }
+}
@@ -0,0 +1,112 @@
+//! Manual config reload trigger
+//!
+//! Ctrl+Break in the console, or signalling the named event from another
+//! process, asks a running instance to reload its configuration file.
+
+use crate::error::{PlatformError, Result};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use tracing::debug;
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE};
+use winapi::um::consoleapi::SetConsoleCtrlHandler;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::synchapi::{CreateEventW, OpenEventW, SetEvent, WaitForSingleObject};
+use winapi::um::winbase::WAIT_OBJECT_0;
+use winapi::um::wincon::CTRL_BREAK_EVENT;
+use winapi::um::winnt::{EVENT_MODIFY_STATE, HANDLE};
+
+/// Name of the event that triggers a reload
+pub const RELOAD_EVENT_NAME: &str = "Local\\GoodbyeDPI-ReloadConfig";
+
+/// Event set by the console handler; the handler has no context pointer
+static BREAK_EVENT: AtomicPtr<winapi::ctypes::c_void> = AtomicPtr::new(ptr::null_mut());
+
+/// Pending reload requests from Ctrl+Break or [`RELOAD_EVENT_NAME`]
+///
+/// Ctrl+Break no longer terminates the process while this is alive;
+/// Ctrl+C is left to other handlers. Only one signal should exist at a
+/// time.
+pub struct ReloadSignal {
+    event: HANDLE,
+}
+
+// The event handle may be waited on from any thread
+unsafe impl Send for ReloadSignal {}
+unsafe impl Sync for ReloadSignal {}
+
+impl ReloadSignal {
+    /// Create the named event and take over Ctrl+Break
+    pub fn new() -> Result<Self> {
+        let name = wide(RELOAD_EVENT_NAME);
+        // Auto-reset, so each request is consumed by one `triggered` call
+        let event = unsafe { CreateEventW(ptr::null_mut(), FALSE, FALSE, name.as_ptr()) };
+        if event.is_null() {
+            return Err(last_error("CreateEventW"));
+        }
+
+        BREAK_EVENT.store(event, Ordering::SeqCst);
+        if unsafe { SetConsoleCtrlHandler(Some(on_console_ctrl), TRUE) } == 0 {
+            // No console (e.g. a service); the named event still works
+            debug!("Ctrl+Break reload unavailable: {}", last_error("SetConsoleCtrlHandler"));
+        }
+
+        Ok(Self { event })
+    }
+
+    /// Whether a reload was requested since the last call
+    pub fn triggered(&self) -> bool {
+        unsafe { WaitForSingleObject(self.event, 0) == WAIT_OBJECT_0 }
+    }
+
+    /// Ask the running instance to reload, from another process
+    pub fn notify() -> Result<()> {
+        let name = wide(RELOAD_EVENT_NAME);
+        let event = unsafe { OpenEventW(EVENT_MODIFY_STATE, FALSE, name.as_ptr()) };
+        if event.is_null() {
+            return Err(last_error("OpenEventW"));
+        }
+
+        let set = unsafe { SetEvent(event) };
+        unsafe { CloseHandle(event) };
+        if set == 0 {
+            return Err(last_error("SetEvent"));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ReloadSignal {
+    fn drop(&mut self) {
+        unsafe {
+            SetConsoleCtrlHandler(Some(on_console_ctrl), FALSE);
+        }
+        BREAK_EVENT.store(ptr::null_mut(), Ordering::SeqCst);
+        unsafe {
+            CloseHandle(self.event);
+        }
+    }
+}
+
+/// Handles Ctrl+Break; every other event falls through to the next handler
+unsafe extern "system" fn on_console_ctrl(ctrl_type: DWORD) -> BOOL {
+    let event = BREAK_EVENT.load(Ordering::SeqCst);
+    if ctrl_type == CTRL_BREAK_EVENT && !event.is_null() {
+        SetEvent(event);
+        return TRUE;
+    }
+    FALSE
+}
+
+/// NUL-terminated UTF-16 string for the wide Win32 APIs
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+fn last_error(call: &str) -> PlatformError {
+    let code = unsafe { GetLastError() };
+    PlatformError::SystemError {
+        code,
+        message: format!("{} failed", call),
+    }
+}
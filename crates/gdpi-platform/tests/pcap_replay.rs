@@ -0,0 +1,94 @@
+//! Replaying the fixture capture through `PcapCapture`
+
+use gdpi_core::config::{Config, Profile};
+use gdpi_core::packet::Direction;
+use gdpi_core::pipeline::{Context, Pipeline};
+use gdpi_core::strategies::StrategyBuilder;
+use gdpi_platform::{PacketCapture, PcapCapture, PcapReplayCapture, PlatformError};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/client_hello.pcap");
+
+fn read_all(capture: &mut impl PacketCapture) -> Vec<gdpi_platform::CapturedPacket> {
+    let mut packets = Vec::new();
+    loop {
+        match capture.recv() {
+            Ok(packet) => packets.push(packet),
+            Err(PlatformError::EndOfCapture) => return packets,
+            Err(e) => panic!("replay failed: {}", e),
+        }
+    }
+}
+
+#[test]
+fn test_fixture_sni() {
+    // SYN, SYN-ACK, ARP (skipped), ACK, ClientHello over Ethernet
+    let mut capture = PcapCapture::open(FIXTURE).unwrap();
+    let packets = read_all(&mut capture);
+    assert_eq!(packets.len(), 4);
+
+    let directions: Vec<_> = packets.iter().map(|p| p.direction).collect();
+    assert_eq!(
+        directions,
+        [Direction::Outbound, Direction::Inbound, Direction::Outbound, Direction::Outbound]
+    );
+
+    let parsed: Vec<_> = packets.iter().map(|p| p.parse().unwrap()).collect();
+    assert!(parsed[1].is_syn_ack());
+    assert_eq!(parsed[1].ttl, 54);
+
+    let snis: Vec<_> = parsed.iter().map(|p| p.extract_sni()).collect();
+    assert_eq!(snis, [None, None, None, Some("discord.com".to_string())]);
+}
+
+
+#[test]
+fn test_replay_writes_pipeline_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("out.pcap");
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_strategies(StrategyBuilder::from_config(&Config::from_profile(Profile::Turkey)));
+    let mut ctx = Context::new();
+
+    let mut replay = PcapReplayCapture::open(FIXTURE, &output).unwrap();
+    for captured in read_all(&mut replay) {
+        let packets = pipeline.process(captured.parse().unwrap(), &mut ctx).unwrap();
+        let batch: Vec<_> = packets
+            .into_iter()
+            .map(|packet| (packet.into_bytes(), captured.address.clone()))
+            .collect();
+        assert_eq!(replay.send_batch(&batch).unwrap() as usize, batch.len());
+    }
+    assert_eq!(replay.sent(), 7);
+    replay.close().unwrap();
+
+    let written = read_all(&mut PcapCapture::open(&output).unwrap());
+    let packets: Vec<_> = written.iter().map(|p| p.parse().unwrap()).collect();
+    assert_eq!(packets.len(), 7);
+
+    // Handshake passes through untouched
+    assert!(packets[0].is_syn());
+    assert!(packets[1].is_syn_ack());
+    assert_eq!(packets[2].payload_len(), 0);
+
+    // Fake ClientHellos for another site go first: one that expires on
+    // the way (TTL from the SYN-ACK), one with a wrong sequence number
+    let fakes = &packets[3..5];
+    assert!(fakes.iter().all(|p| p.extract_sni().as_deref() == Some("www.w3.org")));
+    assert!(fakes[0].ttl < 54);
+    assert_ne!(fakes[1].tcp_seq(), Some(1001));
+
+    // Then the real ClientHello, split and sent in reverse order
+    let (second, first) = (&packets[5], &packets[6]);
+    assert_eq!(first.tcp_seq(), Some(1001));
+    assert_eq!(second.tcp_seq(), Some(1001 + first.payload_len() as u32));
+    let mut hello = first.payload().to_vec();
+    hello.extend_from_slice(second.payload());
+    assert_eq!(hello, fixture_hello());
+}
+
+/// Payload of the ClientHello in the fixture
+fn fixture_hello() -> Vec<u8> {
+    let packets = read_all(&mut PcapCapture::open(FIXTURE).unwrap());
+    packets[3].parse().unwrap().payload().to_vec()
+}
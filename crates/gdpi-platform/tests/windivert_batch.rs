@@ -0,0 +1,114 @@
+//! WinDivert batching micro-benchmark
+//!
+//! Compares unbatched (`batch_size = 1`) against batched (32) receive
+//! and injection on loopback traffic. Needs Windows, the WinDivert
+//! driver and an elevated prompt, so it is ignored by default:
+//!
+//! ```text
+//! cargo test -p gdpi-platform --release --test windivert_batch -- --ignored --nocapture
+//! ```
+//!
+//! Each benchmark prints packets/s for both settings. Rates depend on the
+//! machine and on how fast the sender can generate loopback traffic, so
+//! compare the two rows of a single run rather than absolute figures.
+#![cfg(windows)]
+
+use gdpi_core::packet::PacketBuilder;
+use gdpi_platform::windows::{DriverOptions, Flags, WinDivertDriver};
+use gdpi_platform::PacketAddress;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const BENCH_PORT: u16 = 50999;
+const DURATION: Duration = Duration::from_secs(3);
+const INJECT_COUNT: usize = 50_000;
+
+fn driver(flags: Flags, batch_size: usize) -> WinDivertDriver {
+    WinDivertDriver::open(&format!("loopback and udp.DstPort == {}", BENCH_PORT), flags)
+        .expect("WinDivert open failed - run elevated with the driver installed")
+        .with_options(DriverOptions { batch_size })
+}
+
+/// Packets/s sniffed while another thread floods loopback UDP
+fn recv_rate(batch_size: usize) -> f64 {
+    let driver = driver(
+        Flags {
+            sniff: true,
+            recv_only: true,
+            ..Flags::default()
+        },
+        batch_size,
+    );
+
+    let running = Arc::new(AtomicBool::new(true));
+    let sender = {
+        let running = Arc::clone(&running);
+        thread::spawn(move || {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            while running.load(Ordering::Relaxed) {
+                let _ = socket.send_to(&[0u8; 64], ("127.0.0.1", BENCH_PORT));
+            }
+        })
+    };
+
+    let mut buffer = vec![0u8; driver.batch_buffer_len()];
+    let mut received = 0usize;
+    let start = Instant::now();
+    while start.elapsed() < DURATION {
+        received += driver.recv_batch_shared(&mut buffer, batch_size).unwrap().len();
+    }
+    let rate = received as f64 / start.elapsed().as_secs_f64();
+
+    running.store(false, Ordering::Relaxed);
+    sender.join().unwrap();
+    rate
+}
+
+/// Packets/s injected, handing the driver `batch_size` packets per call
+fn send_rate(batch_size: usize) -> f64 {
+    let driver = driver(
+        Flags {
+            send_only: true,
+            ..Flags::default()
+        },
+        batch_size,
+    );
+
+    let packet = PacketBuilder::tcp_v4()
+        .src_ip_v4([127, 0, 0, 1])
+        .dst_ip_v4([127, 0, 0, 1])
+        .src_port(BENCH_PORT + 1)
+        .dst_port(BENCH_PORT)
+        .payload(&[0u8; 64])
+        .build_bytes();
+    let addr = PacketAddress {
+        loopback: true,
+        ..PacketAddress::outbound()
+    };
+    let batch: Vec<_> = (0..batch_size).map(|_| (packet.clone(), addr.clone())).collect();
+
+    let start = Instant::now();
+    for _ in 0..INJECT_COUNT / batch_size {
+        driver.send_batch_shared(&batch).unwrap();
+    }
+    INJECT_COUNT as f64 / start.elapsed().as_secs_f64()
+}
+
+#[test]
+#[ignore = "needs WinDivert and administrator rights"]
+fn bench_recv_batch() {
+    for batch_size in [1, 32] {
+        println!("recv batch_size={:<2} {:>12.0} packets/s", batch_size, recv_rate(batch_size));
+    }
+}
+
+#[test]
+#[ignore = "needs WinDivert and administrator rights"]
+fn bench_send_batch() {
+    for batch_size in [1, 32] {
+        println!("send batch_size={:<2} {:>12.0} packets/s", batch_size, send_rate(batch_size));
+    }
+}
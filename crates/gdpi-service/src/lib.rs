@@ -6,4 +6,7 @@
 
 pub mod service;
 
-pub use service::run_service;
+pub use service::{
+    install_service, run_service, service_state, start_service, stop_service, uninstall_service,
+    update_service, State,
+};
@@ -1,56 +1,270 @@
 //! Windows Service implementation
 //!
-//! Provides Windows service lifecycle management.
+//! Installs and controls the service through the service control manager
+//! (SCM), and runs the service body once the SCM starts the process.
 
 #![cfg(windows)]
 
-use std::time::Duration;
-use tracing::info;
+use anyhow::{anyhow, bail, Result};
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+use windows_service::service::{
+    Service, ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl,
+    ServiceExitCode, ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+pub use windows_service::service::ServiceState as State;
 
 /// Service name
 pub const SERVICE_NAME: &str = "GoodbyeDPI";
+/// Name shown in the services console
+pub const SERVICE_DISPLAY_NAME: &str = "GoodbyeDPI Turkey";
+/// Description shown in the services console
+pub const SERVICE_DESCRIPTION: &str = "Deep Packet Inspection bypass service for Turkey";
 
-/// Run as Windows service
-pub fn run_service() -> anyhow::Result<()> {
-    // This would use windows-service crate
-    // For now, just a placeholder
-    info!("Starting {} service...", SERVICE_NAME);
-    
-    // Service main loop would go here
-    loop {
-        std::thread::sleep(Duration::from_secs(1));
-    }
-}
+const ERROR_ACCESS_DENIED: i32 = 5;
+const ERROR_SERVICE_ALREADY_RUNNING: i32 = 1056;
+const ERROR_SERVICE_DOES_NOT_EXIST: i32 = 1060;
+const ERROR_SERVICE_NOT_ACTIVE: i32 = 1062;
+const ERROR_FAILED_SERVICE_CONTROLLER_CONNECT: i32 = 1063;
+const ERROR_SERVICE_EXISTS: i32 = 1073;
+
+/// How long start and stop wait for the service to get there
+const STATE_TIMEOUT: Duration = Duration::from_secs(20);
 
-/// Install the service
-pub fn install_service(
-    _exe_path: &str,
-    _args: &[&str],
-    _auto_start: bool,
-) -> anyhow::Result<()> {
+/// Install the service, starting `exe_path` with `args`
+///
+/// It runs as LocalSystem, which the packet driver needs, and starts
+/// with Windows when `auto_start` is set.
+pub fn install_service(exe_path: &Path, args: &[OsString], auto_start: bool) -> Result<()> {
     info!("Installing service: {}", SERVICE_NAME);
-    // TODO: Implement service installation
-    // sc create GoodbyeDPI binPath= "..."
-    Ok(())
+    let manager = manager(ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE)?;
+    let service = manager
+        .create_service(&service_info(exe_path, args, auto_start), ServiceAccess::CHANGE_CONFIG)
+        .map_err(|e| explain(e, "install the service"))?;
+    service
+        .set_description(SERVICE_DESCRIPTION)
+        .map_err(|e| explain(e, "set the service description"))
 }
 
-/// Uninstall the service
-pub fn uninstall_service() -> anyhow::Result<()> {
+/// Change the command line and start type of the installed service
+///
+/// Takes effect the next time the service starts.
+pub fn update_service(exe_path: &Path, args: &[OsString], auto_start: bool) -> Result<()> {
+    info!("Updating service: {}", SERVICE_NAME);
+    open(ServiceAccess::CHANGE_CONFIG)?
+        .change_config(&service_info(exe_path, args, auto_start))
+        .map_err(|e| explain(e, "update the service"))
+}
+
+/// Uninstall the service, stopping it first
+pub fn uninstall_service() -> Result<()> {
     info!("Uninstalling service: {}", SERVICE_NAME);
-    // sc delete GoodbyeDPI
-    Ok(())
+    let service = open(ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE)?;
+    stop(&service)?;
+    service.delete().map_err(|e| explain(e, "uninstall the service"))
 }
 
-/// Start the service
-pub fn start_service() -> anyhow::Result<()> {
+/// Start the service and wait until it runs
+pub fn start_service() -> Result<()> {
     info!("Starting service: {}", SERVICE_NAME);
-    // net start GoodbyeDPI
-    Ok(())
+    let service = open(ServiceAccess::QUERY_STATUS | ServiceAccess::START)?;
+    match service.start::<&str>(&[]) {
+        Ok(()) => {}
+        Err(e) if os_error(&e) == Some(ERROR_SERVICE_ALREADY_RUNNING) => return Ok(()),
+        Err(e) => return Err(explain(e, "start the service")),
+    }
+    wait_for(&service, ServiceState::Running)
 }
 
-/// Stop the service
-pub fn stop_service() -> anyhow::Result<()> {
+/// Stop the service and wait until the packet driver is closed
+pub fn stop_service() -> Result<()> {
     info!("Stopping service: {}", SERVICE_NAME);
-    // net stop GoodbyeDPI
-    Ok(())
+    stop(&open(ServiceAccess::QUERY_STATUS | ServiceAccess::STOP)?)
+}
+
+/// State of the service, `None` if it isn't installed
+///
+/// Needs no administrator rights.
+pub fn service_state() -> Result<Option<ServiceState>> {
+    let manager = manager(ServiceManagerAccess::CONNECT)?;
+    let service = match manager.open_service(SERVICE_NAME, ServiceAccess::QUERY_STATUS) {
+        Ok(service) => service,
+        Err(e) if os_error(&e) == Some(ERROR_SERVICE_DOES_NOT_EXIST) => return Ok(None),
+        Err(e) => return Err(explain(e, "open the service")),
+    };
+    let status = service
+        .query_status()
+        .map_err(|e| explain(e, "query the service"))?;
+    Ok(Some(status.current_state))
+}
+
+/// Run as the service: hand the process to the SCM and call `body` from
+/// its service main
+///
+/// `body` runs until the flag it gets is cleared by a stop request, so it
+/// must check the flag regularly. Only works when the SCM started this
+/// process.
+pub fn run_service<F>(body: F) -> Result<()>
+where
+    F: FnOnce(Arc<AtomicBool>) -> Result<()> + Send + 'static,
+{
+    *BODY.lock().unwrap_or_else(PoisonError::into_inner) = Some(Box::new(body));
+
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main).map_err(|e| {
+        if os_error(&e) == Some(ERROR_FAILED_SERVICE_CONTROLLER_CONNECT) {
+            anyhow!("Not started by the service manager; use `goodbyedpi service start` instead")
+        } else {
+            anyhow::Error::new(e).context("Failed to start the service dispatcher")
+        }
+    })
+}
+
+/// What [`run_service`] hands over to the service main
+type Body = Box<dyn FnOnce(Arc<AtomicBool>) -> Result<()> + Send>;
+
+static BODY: Mutex<Option<Body>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+fn service_main(_arguments: Vec<OsString>) {
+    let Some(body) = BODY.lock().unwrap_or_else(PoisonError::into_inner).take() else {
+        return;
+    };
+    if let Err(e) = serve(body) {
+        error!("Service failed: {:#}", e);
+    }
+}
+
+fn serve(body: Body) -> Result<()> {
+    let running = Arc::new(AtomicBool::new(true));
+
+    let handler = {
+        let running = Arc::clone(&running);
+        move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                info!("Stop requested by the service manager");
+                running.store(false, Ordering::SeqCst);
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        }
+    };
+    let status = service_control_handler::register(SERVICE_NAME, handler)?;
+
+    let report = |state, controls_accepted, exit_code| {
+        status.set_service_status(ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted,
+            exit_code,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        })
+    };
+    report(
+        ServiceState::Running,
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        ServiceExitCode::NO_ERROR,
+    )?;
+
+    let result = body(running);
+    let exit_code = match result {
+        Ok(()) => ServiceExitCode::NO_ERROR,
+        Err(_) => ServiceExitCode::ServiceSpecific(1),
+    };
+    report(ServiceState::Stopped, ServiceControlAccept::empty(), exit_code)?;
+    result
+}
+
+fn service_info(exe_path: &Path, args: &[OsString], auto_start: bool) -> ServiceInfo {
+    ServiceInfo {
+        name: SERVICE_NAME.into(),
+        display_name: SERVICE_DISPLAY_NAME.into(),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: if auto_start {
+            ServiceStartType::AutoStart
+        } else {
+            ServiceStartType::OnDemand
+        },
+        error_control: ServiceErrorControl::Normal,
+        executable_path: exe_path.to_path_buf(),
+        launch_arguments: args.to_vec(),
+        dependencies: Vec::new(),
+        account_name: None,
+        account_password: None,
+    }
+}
+
+fn manager(access: ServiceManagerAccess) -> Result<ServiceManager> {
+    ServiceManager::local_computer(None::<&str>, access)
+        .map_err(|e| explain(e, "connect to the service manager"))
+}
+
+fn open(access: ServiceAccess) -> Result<Service> {
+    manager(ServiceManagerAccess::CONNECT)?
+        .open_service(SERVICE_NAME, access)
+        .map_err(|e| explain(e, "open the service"))
+}
+
+fn stop(service: &Service) -> Result<()> {
+    match service.stop() {
+        Ok(_) => {}
+        Err(e) if os_error(&e) == Some(ERROR_SERVICE_NOT_ACTIVE) => return Ok(()),
+        Err(e) => return Err(explain(e, "stop the service")),
+    }
+    wait_for(service, ServiceState::Stopped)
+}
+
+fn wait_for(service: &Service, state: ServiceState) -> Result<()> {
+    let started = Instant::now();
+    loop {
+        let status = service
+            .query_status()
+            .map_err(|e| explain(e, "query the service"))?;
+        if status.current_state == state {
+            return Ok(());
+        }
+        if status.current_state == ServiceState::Stopped {
+            bail!(
+                "The service stopped while starting (exit code {:?}); see the service log for why",
+                status.exit_code
+            );
+        }
+        if started.elapsed() > STATE_TIMEOUT {
+            bail!("Timed out waiting for the service to become {:?}", state);
+        }
+        std::thread::sleep(Duration::from_millis(250));
+    }
+}
+
+fn os_error(err: &windows_service::Error) -> Option<i32> {
+    match err {
+        windows_service::Error::Winapi(e) => e.raw_os_error(),
+        _ => None,
+    }
+}
+
+/// An SCM error with what to do about it, for the errors users run into
+fn explain(err: windows_service::Error, action: &str) -> anyhow::Error {
+    let hint = match os_error(&err) {
+        Some(ERROR_ACCESS_DENIED) => "access denied, run it from an Administrator prompt",
+        Some(ERROR_SERVICE_DOES_NOT_EXIST) => {
+            "the service is not installed, run `goodbyedpi service install` first"
+        }
+        Some(ERROR_SERVICE_EXISTS) => {
+            "the service is already installed, run `goodbyedpi service uninstall` to replace it"
+        }
+        _ => return anyhow::Error::new(err).context(format!("Failed to {}", action)),
+    };
+    anyhow!("Failed to {}: {}", action, hint)
 }